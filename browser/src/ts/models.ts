@@ -9,9 +9,18 @@ export type Age = {
   mtime: number;
 };
 
+export type OwnerContact = {
+  prefix: string;
+  team?: string;
+  email?: string;
+  name?: string;
+};
+
 export type RawFolder = {
   path: string;
   users: Record<string, Record<string, Age>>;
+  owner?: OwnerContact;
+  tier?: string;
 };
 
 export type UserStatsJson = {
@@ -33,6 +42,8 @@ export type FolderItem = {
   accessed: number;
   modified: number;
   users: Record<string, UserStatsJson>;
+  owner?: OwnerContact;
+  tier?: string;
 };
 
 export type ScannedFile = {
@@ -67,6 +67,8 @@ export function transformFolders(raw: RawFolder[], filter: AgeFilterType): Folde
         accessed: max_atime,
         modified: max_mtime,
         users: usersAgg,
+        owner: rf.owner,
+        tier: rf.tier,
       } as FolderItem;
     })
     .filter((rf) => rf.path);
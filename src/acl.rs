@@ -0,0 +1,140 @@
+// rs/src/acl.rs
+//
+// Folders only some callers may see, by path prefix, loaded from a CSV with
+// a header row. `prefix` and `allow` are required; `allow` lists the users
+// and groups (the OIDC `groups` claim) that may see the tree, separated by
+// `;`, matched case-insensitively:
+//
+//   prefix,allow
+//   /hr,hr-staff;jdoe
+//   /legal,legal
+//
+// Anyone else does not see the folder at all: `db::list_children_with`
+// drops it from its parent's listing and lists nothing below it, and duapi
+// answers 403 to any route given a path inside it. Admin
+// rights do not lift the restriction, so an admin of other areas is kept
+// out too. Prefixes match whole path components, and a folder under several
+// prefixes must be allowed by each of them.
+use anyhow::{bail, Context, Result};
+use csv::{ReaderBuilder, Trim};
+use std::path::Path;
+
+use crate::query::canonical_key;
+use crate::util::replace_path_prefix;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclEntry {
+    pub prefix: String,
+    /// Lowercased user and group names.
+    pub allow: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct AccessList {
+    entries: Vec<AclEntry>,
+}
+
+impl AccessList {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading ACL file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("in {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(true)
+            .flexible(true)
+            .trim(Trim::All)
+            .comment(Some(b'#'))
+            .from_reader(text.as_bytes());
+        let headers = rdr.headers()?.clone();
+        let col = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+        let (Some(prefix_col), Some(allow_col)) = (col("prefix"), col("allow")) else {
+            bail!("ACL file needs 'prefix' and 'allow' columns");
+        };
+
+        let mut entries = Vec::new();
+        for rec in rdr.records() {
+            let rec = rec?;
+            let Some(prefix) = rec.get(prefix_col).filter(|p| !p.is_empty()) else {
+                continue;
+            };
+            let allow = rec
+                .get(allow_col)
+                .unwrap_or_default()
+                .split(';')
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect();
+            entries.push(AclEntry {
+                prefix: prefix.to_string(),
+                allow,
+            });
+        }
+        Ok(Self { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The prefixes `user`, a member of `groups`, may not see; empty when
+    /// every entry allows them.
+    pub fn hidden_for(&self, user: &str, groups: &[String]) -> Vec<String> {
+        let mut names = vec![user.to_lowercase()];
+        names.extend(groups.iter().map(|g| g.to_lowercase()));
+        self.entries
+            .iter()
+            .filter(|e| !e.allow.iter().any(|a| names.contains(a)))
+            .map(|e| e.prefix.clone())
+            .collect()
+    }
+}
+
+/// Whether `path` is at or below one of the `hidden` prefixes.
+pub fn is_hidden(path: &str, hidden: &[String], case_insensitive: bool) -> bool {
+    if hidden.is_empty() {
+        return false;
+    }
+    let path = canonical_key(path, case_insensitive);
+    hidden.iter().any(|h| {
+        let prefix = canonical_key(h, case_insensitive);
+        replace_path_prefix(path.as_bytes(), prefix.as_bytes(), b"").is_some()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hidden_prefixes_follow_users_and_groups() {
+        let acl = AccessList::parse(
+            "Prefix,Allow\n\
+             # comment\n\
+             /hr,HR-Staff; jdoe\n\
+             /legal,legal\n\
+             /locked,\n",
+        )
+        .unwrap();
+        assert_eq!(acl.len(), 3);
+
+        assert_eq!(acl.hidden_for("JDoe", &[]), ["/legal", "/locked"]);
+        assert_eq!(acl.hidden_for("bob", &["hr-staff".into(), "Legal".into()]), ["/locked"]);
+        let root = acl.hidden_for("root", &[]);
+        assert_eq!(root, ["/hr", "/legal", "/locked"]);
+
+        assert!(is_hidden("/hr", &root, false));
+        assert!(is_hidden("/hr/payroll", &root, false));
+        assert!(!is_hidden("/hrx", &root, false));
+        assert!(!is_hidden("/", &root, false));
+        assert!(!is_hidden("/HR/payroll", &root, false));
+        assert!(is_hidden("/HR/payroll", &root, true));
+        assert!(AccessList::parse("prefix\n/hr\n").is_err());
+    }
+}
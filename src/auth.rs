@@ -10,8 +10,9 @@ use axum_extra::{
     headers::{authorization::Bearer, Authorization},
     TypedHeader,
 };
-use jsonwebtoken::{decode, DecodingKey, EncodingKey, Validation};
+use jsonwebtoken::{decode, decode_header, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::fmt::Display;
 use std::future::Future;
 use std::pin::Pin;
@@ -30,30 +31,120 @@ pub fn set_extra_verifier(f: BearerVerifier) {
 }
 
 // ---- Keys (JWT) ----
+//
+// Tokens are signed with JWT_SECRET and carry its `kid` (a hash prefix of
+// the secret, so no key ids need configuring). Secrets listed in
+// JWT_SECRET_PREVIOUS still verify tokens naming their kid: rotate by moving
+// the old secret there, and drop it once the longest token TTL has passed.
 pub struct Keys {
     pub encoding: EncodingKey,
     pub decoding: DecodingKey,
+    /// Key id of `encoding`/`decoding`, sent in the token header.
+    pub kid: String,
+    previous: Vec<(String, DecodingKey)>,
 }
 
 impl Keys {
-    fn new(secret: &[u8]) -> Self {
+    fn with_previous(secret: &[u8], previous: &[&[u8]]) -> Self {
         Self {
             encoding: EncodingKey::from_secret(secret),
             decoding: DecodingKey::from_secret(secret),
+            kid: key_id(secret),
+            previous: previous
+                .iter()
+                .map(|s| (key_id(s), DecodingKey::from_secret(s)))
+                .collect(),
+        }
+    }
+
+    /// Header for tokens signed with the current key.
+    pub fn header(&self) -> Header {
+        Header {
+            kid: Some(self.kid.clone()),
+            ..Header::default()
+        }
+    }
+
+    /// Verification key for a token header's `kid`. Tokens without one
+    /// predate key ids and can only match the current key.
+    pub fn decoding_for(&self, kid: Option<&str>) -> Option<&DecodingKey> {
+        match kid {
+            None => Some(&self.decoding),
+            Some(k) if k == self.kid => Some(&self.decoding),
+            Some(k) => self.previous.iter().find(|(id, _)| id == k).map(|(_, d)| d),
         }
     }
 }
 
+/// Stable, non-reversible id of an HMAC secret.
+pub fn key_id(secret: &[u8]) -> String {
+    Sha256::digest(secret)[..6]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
 static KEYS: OnceLock<Keys> = OnceLock::new();
 
 #[inline]
 pub fn keys() -> &'static Keys {
     KEYS.get_or_init(|| {
         let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-        Keys::new(secret.as_bytes())
+        let previous = std::env::var("JWT_SECRET_PREVIOUS").unwrap_or_default();
+        let previous: Vec<&[u8]> = previous
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::as_bytes)
+            .collect();
+        Keys::with_previous(secret.as_bytes(), &previous)
     })
 }
 
+/// `iss` of issued tokens and the only issuer accepted (JWT_ISSUER).
+pub fn issuer() -> &'static str {
+    static ISS: OnceLock<String> = OnceLock::new();
+    ISS.get_or_init(|| env_or("JWT_ISSUER", "dutopia"))
+}
+
+/// `aud` of issued tokens and the only audience accepted (JWT_AUDIENCE).
+pub fn audience() -> &'static str {
+    static AUD: OnceLock<String> = OnceLock::new();
+    AUD.get_or_init(|| env_or("JWT_AUDIENCE", "dutopia"))
+}
+
+fn env_or(key: &str, default: &str) -> String {
+    std::env::var(key)
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| default.to_string())
+}
+
+/// Sign `claims` with the current key.
+pub fn issue_token(claims: &Claims) -> Result<String, AuthError> {
+    let k = keys();
+    encode(&k.header(), claims, &k.encoding).map_err(|_| AuthError::TokenCreation)
+}
+
+/// Claims of an internal HS256 token: signature by the key its `kid`
+/// names, expiry, issuer and audience.
+pub fn verify_token(token: &str) -> Option<Claims> {
+    verify_with(keys(), token, issuer(), audience())
+}
+
+fn verify_with(keys: &Keys, token: &str, iss: &str, aud: &str) -> Option<Claims> {
+    let header = decode_header(token).ok()?;
+    let key = keys.decoding_for(header.kid.as_deref())?;
+    let mut validation = Validation::default();
+    validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+    validation.set_issuer(&[iss]);
+    validation.set_audience(&[aud]);
+    decode::<Claims>(token, key, &validation)
+        .ok()
+        .map(|td| td.claims)
+}
+
 #[derive(Debug, Deserialize)]
 pub struct AuthPayload {
     pub username: String,
@@ -111,6 +202,28 @@ pub struct Claims {
     pub sub: String,
     pub is_admin: bool,
     pub exp: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    /// Groups from the identity provider (OIDC `groups` claim); matched
+    /// against `--acl-file` entries. Empty for local logins.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+}
+
+impl Claims {
+    /// Claims for a token issued by this server (`iss`/`aud` set).
+    pub fn new(sub: String, is_admin: bool, exp: usize) -> Self {
+        Self {
+            sub,
+            is_admin,
+            exp,
+            iss: Some(issuer().to_string()),
+            aud: Some(audience().to_string()),
+            groups: Vec::new(),
+        }
+    }
 }
 
 // allow us to print the claim details for the private route
@@ -136,16 +249,16 @@ where
         let token = bearer.token();
 
         // Internal HS256 token (issued by /api/login or /api/auth/callback).
-        if let Ok(td) = decode::<Claims>(token, &keys().decoding, &Validation::default()) {
-            return Ok(td.claims);
+        if let Some(claims) = verify_token(token) {
+            return Ok(claims);
         }
 
         // Fallback: Keycloak/OIDC RS256 access_token. Used when callers go
         // through the neos proxy's token-exchange path (`aud=dutopia-mcp`).
-        if let Some(verifier) = EXTRA_VERIFIER.get() {
-            if let Some(claims) = verifier(token.to_string()).await {
-                return Ok(claims);
-            }
+        if let Some(verifier) = EXTRA_VERIFIER.get()
+            && let Some(claims) = verifier(token.to_string()).await
+        {
+            return Ok(claims);
         }
 
         Err(AuthError::InvalidToken)
@@ -173,14 +286,14 @@ pub struct VerifyResult {
 pub fn verify_credentials(username: &str, password: &str) -> VerifyResult {
     #[cfg(any(debug_assertions, test))]
     {
-        if let Ok(expected) = std::env::var("ADMIN_PASSWORD") {
-            if !expected.is_empty() && password == expected {
-                tracing::warn!(user = %username, "verify_credentials: ADMIN_PASSWORD override matched");
-                return VerifyResult {
-                    authenticated: true,
-                    admin_override: true,
-                };
-            }
+        if let Ok(expected) = std::env::var("ADMIN_PASSWORD")
+            && !expected.is_empty() && password == expected
+        {
+            tracing::warn!(user = %username, "verify_credentials: ADMIN_PASSWORD override matched");
+            return VerifyResult {
+                authenticated: true,
+                admin_override: true,
+            };
         }
     }
     #[cfg(not(any(debug_assertions, test)))]
@@ -261,11 +374,11 @@ pub mod platform {
             }
         };
 
-        if let Some(mut stdin) = child.stdin.take() {
-            if let Err(e) = writeln!(stdin, "{}", password) {
-                tracing::warn!(user = %username, error = %e, "verify_user: failed to write password to su stdin");
-                return false;
-            }
+        if let Some(mut stdin) = child.stdin.take()
+            && let Err(e) = writeln!(stdin, "{}", password)
+        {
+            tracing::warn!(user = %username, error = %e, "verify_user: failed to write password to su stdin");
+            return false;
         }
 
         match child.wait() {
@@ -321,6 +434,52 @@ mod tests {
     }
 }
 
+#[cfg(test)]
+mod key_rotation_tests {
+    use super::*;
+
+    fn sign(keys: &Keys, claims: &Claims) -> String {
+        encode(&keys.header(), claims, &keys.encoding).unwrap()
+    }
+
+    fn claims(iss: &str, aud: &str) -> Claims {
+        let mut c = Claims::new("alice".into(), false, 9_999_999_999);
+        c.iss = Some(iss.into());
+        c.aud = Some(aud.into());
+        c
+    }
+
+    #[test]
+    fn previous_secret_still_verifies_its_tokens() {
+        let old = Keys::with_previous(b"old-secret", &[]);
+        let token = sign(&old, &claims("dutopia", "dutopia"));
+
+        let rotated = Keys::with_previous(b"new-secret", &[b"old-secret"]);
+        assert_ne!(rotated.kid, old.kid);
+        let c = verify_with(&rotated, &token, "dutopia", "dutopia").unwrap();
+        assert_eq!(c.sub, "alice");
+
+        // Once the old secret is dropped its tokens stop verifying.
+        let dropped = Keys::with_previous(b"new-secret", &[]);
+        assert!(verify_with(&dropped, &token, "dutopia", "dutopia").is_none());
+    }
+
+    #[test]
+    fn issuer_and_audience_must_match() {
+        let keys = Keys::with_previous(b"secret", &[]);
+        let token = sign(&keys, &claims("dutopia", "dutopia"));
+        assert!(verify_with(&keys, &token, "dutopia", "dutopia").is_some());
+        assert!(verify_with(&keys, &token, "other", "dutopia").is_none());
+        assert!(verify_with(&keys, &token, "dutopia", "other").is_none());
+
+        let mut legacy = claims("", "");
+        legacy.iss = None;
+        legacy.aud = None;
+        let token = encode(&Header::default(), &legacy, &keys.encoding).unwrap();
+        assert!(verify_with(&keys, &token, "dutopia", "dutopia").is_none());
+    }
+}
+
 #[cfg(test)]
 mod admin_override_tests {
     use super::verify_credentials;
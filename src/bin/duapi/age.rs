@@ -0,0 +1,155 @@
+// rs/src/bin/duapi/age.rs
+//
+// `GET /api/age-profile`: how old the data in one folder is, for the age
+// histogram in the UI. The totals of the folder itself (its whole subtree,
+// as dusum rolled it up) come per age bucket, every bucket listed even when
+// empty so the chart keeps its three bars. Buckets are dusum's (`--age`,
+// default 60 and 600 days by mtime); the DB holds no finer bins, so there
+// is no per-year split yet.
+use anyhow::Result;
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use dutopia::auth::{AuthError, Claims};
+use dutopia::db::{self, Age, DbPool};
+
+use crate::handler::impersonate;
+use crate::query::AgeProfileQuery;
+use crate::{get_db, is_case_insensitive};
+
+/// dusum age buckets: 0 recent, 1 not too old, 2 old.
+const BUCKETS: u8 = 3;
+
+#[derive(Serialize, Debug)]
+pub struct Bucket {
+    pub age: u8,
+    #[serde(flatten)]
+    pub totals: Age,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AgeProfile {
+    pub path: String,
+    pub user: Option<String>,
+    pub count: u64,
+    pub size: u64,
+    pub disk: u64,
+    pub ages: Vec<Bucket>,
+}
+
+/// `None` when the folder has no rows for `user`.
+fn age_profile(pool: &DbPool, path: &str, user: Option<&str>, case_insensitive: bool) -> Result<Option<AgeProfile>> {
+    let Some(mut found) = db::folder_totals(pool, path, user, case_insensitive)? else {
+        return Ok(None);
+    };
+    let ages: Vec<Bucket> = (0..BUCKETS)
+        .map(|age| Bucket {
+            age,
+            totals: found.remove(&age).unwrap_or_default(),
+        })
+        .collect();
+    Ok(Some(AgeProfile {
+        path: path.to_string(),
+        user: user.map(str::to_string),
+        count: ages.iter().map(|b| b.totals.count).sum(),
+        size: ages.iter().map(|b| b.totals.size).sum(),
+        disk: ages.iter().map(|b| b.totals.disk).sum(),
+        ages,
+    }))
+}
+
+/// GET /api/age-profile?path=/x&user=alice
+///
+/// Files, bytes and latest times per age bucket of the folder `path`.
+/// Users see their own files (`user` may be left out); admins may ask for
+/// any user, or all of them without `user`.
+pub async fn handler(claims: Claims, Query(q): Query<AgeProfileQuery>) -> Response {
+    let claims = match impersonate(claims, q.as_user.as_deref(), "/api/age-profile") {
+        Ok(c) => c,
+        Err(e) => return e.into_response(),
+    };
+    let raw_path = q.path.unwrap_or_default();
+    let case_insensitive = is_case_insensitive();
+    let path = match crate::query::normalize_path(&raw_path) {
+        Some(p) if case_insensitive => dutopia::query::nfc(&p).into_owned(),
+        Some(p) => p,
+        None => {
+            tracing::warn!(input = %raw_path, "400 Bad Request /api/age-profile rejected path");
+            return (StatusCode::BAD_REQUEST, "invalid path").into_response();
+        }
+    };
+    let user = q.user.map(|u| u.trim().to_string()).filter(|u| !u.is_empty());
+    let user = match user {
+        None if !claims.is_admin => Some(claims.sub.clone()),
+        Some(u) if !claims.is_admin && u != claims.sub => {
+            tracing::warn!(path = %path, user = %u, "403 Forbidden /api/age-profile");
+            return AuthError::Forbidden.into_response();
+        }
+        user => user,
+    };
+    if let Err(e) = crate::ensure_visible(&path, &claims) {
+        tracing::warn!(path = %path, user = %claims.sub, "403 Forbidden /api/age-profile hidden");
+        return e.into_response();
+    }
+
+    let pool = get_db();
+    let task_path = path.clone();
+    let fut = tokio::task::spawn_blocking(move || {
+        age_profile(&pool, &task_path, user.as_deref(), case_insensitive)
+    });
+    match fut.await {
+        Ok(Ok(Some(profile))) => {
+            tracing::info!(path = %path, count = profile.count, "200 OK /api/age-profile");
+            Json(profile).into_response()
+        }
+        Ok(Ok(None)) => {
+            tracing::info!(path = %path, "404 Not Found /api/age-profile");
+            (StatusCode::NOT_FOUND, "no data for this folder").into_response()
+        }
+        Ok(Err(e)) => {
+            tracing::error!(err = %format!("{e:#}"), "500 DB ERROR /api/age-profile");
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("db error: {e:#}")).into_response()
+        }
+        Err(join_err) => {
+            tracing::error!(err = %join_err, "500 Task Join Error /api/age-profile");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("task error: {join_err}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_every_bucket_of_the_folder() {
+        let db = dutopia::db::test_support::build_test_db();
+        let pool = db::open_pool(&db.path).unwrap();
+
+        let all = age_profile(&pool, "/", None, false).unwrap().unwrap();
+        assert_eq!((all.count, all.size, all.disk), (3, 250, 150));
+        let counts: Vec<(u8, u64)> = all.ages.iter().map(|b| (b.age, b.totals.count)).collect();
+        assert_eq!(counts, vec![(0, 2), (1, 1), (2, 0)]);
+
+        let alice = age_profile(&pool, "/docs", Some("alice"), false).unwrap().unwrap();
+        let v = serde_json::json!(alice);
+        assert_eq!(v["user"], "alice");
+        assert_eq!(v["ages"][2], serde_json::json!({
+            "age": 2, "count": 3, "size": 600, "disk": 300, "linked": 300,
+            "atime": 1500000000, "mtime": 1500000050, "oldest_atime": 0, "oldest_mtime": 0
+        }));
+        assert_eq!(v["ages"][0]["count"], 0);
+
+        assert!(age_profile(&pool, "/docs", Some("bob"), false).unwrap().is_none());
+        assert!(age_profile(&pool, "/nope", None, false).unwrap().is_none());
+    }
+}
@@ -0,0 +1,913 @@
+// rs/src/bin/duapi/agents.rs
+//
+// Remote scans on duscand agents, started and watched from duapi, so the
+// whole pipeline (scan on the storage node, sum, index, serve) runs without
+// a shell on either side. Agents are listed in a TOML file (`--agents FILE`):
+//
+//   [defaults]
+//   work_dir = "/var/lib/dutopia/remote"  # downloaded scans and sums (.)
+//   bin_dir = "/opt/dutopia/bin"          # dusum and dudb (next to duapi)
+//   poll = "10s"                          # status poll interval (5s)
+//   sum = ["--age", "30,365"]             # extra dusum args
+//   dudb = ["--case-insensitive"]         # extra dudb args
+//
+//   [[agent]]
+//   name = "nas1"
+//   url = "http://nas1:8010"
+//   token_env = "NAS1_TOKEN"              # or token = "..."
+//
+// All routes are admin-only:
+//
+//   GET  /api/admin/agents               agents and their /health
+//   POST /api/admin/scans                {"agent", "folders", "args", "load"}
+//                                        (`args` as duscand takes them)
+//   GET  /api/admin/scans[/{id}]         state and progress, polled from the
+//                                        agent every `poll`
+//   POST /api/admin/scans/{id}/cancel    cancel on the agent
+//
+// When the agent reports the scan done, its CSV and manifest are downloaded
+// to `work_dir/<agent>-<remote id>.csv`. With `load` (the default) the scan
+// is then summed with dusum, indexed with dudb next to the served DB and
+// swapped in like POST /api/admin/reload; the raw CSV is deleted once
+// summed. Loads run one at a time, and each replaces the served dataset.
+// Remote scans are kept in memory: a restart of duapi stops following them,
+// though the agent finishes them anyway.
+use anyhow::{bail, Context, Result};
+use axum::{
+    extract::Path as UrlPath,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use dutopia::auth::{AuthError, Claims};
+use dutopia::util::parse_duration;
+
+use crate::dataset;
+
+const DEFAULT_POLL: Duration = Duration::from_secs(5);
+/// Timeout of status and control calls; downloads have none.
+const API_TIMEOUT: Duration = Duration::from_secs(30);
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Failed polls in a row after which the agent is given up on.
+const MAX_POLL_ERRORS: u32 = 12;
+/// Finished remote scans stay listed this long.
+const KEEP_SECS: i64 = 7 * 86_400;
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct FileDefaults {
+    work_dir: Option<PathBuf>,
+    bin_dir: Option<PathBuf>,
+    poll: Option<String>,
+    #[serde(default)]
+    sum: Vec<String>,
+    #[serde(default)]
+    dudb: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileAgent {
+    name: String,
+    url: String,
+    token: Option<String>,
+    token_env: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    defaults: FileDefaults,
+    #[serde(default)]
+    agent: Vec<FileAgent>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Agent {
+    pub name: String,
+    /// Base URL, without a trailing slash
+    pub url: String,
+    token: String,
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub work_dir: PathBuf,
+    /// Directory holding dusum and dudb
+    pub bin_dir: PathBuf,
+    pub poll: Duration,
+    pub sum: Vec<String>,
+    pub dudb: Vec<String>,
+    pub agents: Vec<Agent>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading agents file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("in {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let file: File = toml::from_str(text)?;
+        let d = file.defaults;
+        let poll = match &d.poll {
+            Some(p) => parse_duration(p).map_err(|e| anyhow::anyhow!("poll: {e}"))?,
+            None => DEFAULT_POLL,
+        };
+        let bin_dir = match d.bin_dir {
+            Some(b) => b,
+            None => std::env::current_exe()
+                .ok()
+                .and_then(|e| e.parent().map(Path::to_path_buf))
+                .unwrap_or_else(|| PathBuf::from(".")),
+        };
+        let mut names = HashSet::new();
+        let mut agents = Vec::new();
+        for a in file.agent {
+            let name = a.name.trim().to_string();
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+                bail!("agent name '{}' must be letters, digits, '-', '_' or '.'", a.name);
+            }
+            if !names.insert(name.clone()) {
+                bail!("agent '{name}' is listed twice");
+            }
+            let url = a.url.trim().trim_end_matches('/').to_string();
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                bail!("agent '{name}': url must start with http:// or https://");
+            }
+            let token = match (a.token, a.token_env) {
+                (Some(t), None) => t,
+                (None, Some(var)) => std::env::var(&var)
+                    .with_context(|| format!("agent '{name}': {var} is not set"))?,
+                _ => bail!("agent '{name}' needs one of token or token_env"),
+            };
+            agents.push(Agent { name, url, token });
+        }
+        if agents.is_empty() {
+            bail!("no [[agent]] listed");
+        }
+        Ok(Self {
+            work_dir: d.work_dir.unwrap_or_else(|| PathBuf::from(".")),
+            bin_dir,
+            poll,
+            sum: d.sum,
+            dudb: d.dudb,
+            agents,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Queued,
+    Running,
+    /// Downloading, summing and indexing the result
+    Loading,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScanReq {
+    pub agent: String,
+    pub folders: Vec<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Sum, index and serve the result once done (default true)
+    #[serde(default = "yes")]
+    pub load: bool,
+}
+
+fn yes() -> bool {
+    true
+}
+
+/// The fields duapi reads from a duscand scan.
+#[derive(Debug, Deserialize)]
+struct AgentScan {
+    id: u64,
+    state: String,
+    #[serde(default)]
+    done: u64,
+    total: Option<u64>,
+    percent: Option<f64>,
+    #[serde(default)]
+    per_sec: f64,
+    eta_secs: Option<f64>,
+    error: Option<String>,
+}
+
+struct Status {
+    state: State,
+    finished_at: Option<i64>,
+    done: u64,
+    total: Option<u64>,
+    percent: Option<f64>,
+    per_sec: f64,
+    eta_secs: Option<f64>,
+    error: Option<String>,
+    /// The downloaded scan, or its summary once loaded
+    output: Option<PathBuf>,
+    /// `built_at` of the dataset the scan was loaded into
+    built_at: Option<String>,
+}
+
+struct RemoteScan {
+    id: u64,
+    actor: String,
+    agent: Agent,
+    remote_id: u64,
+    folders: Vec<String>,
+    args: Vec<String>,
+    load: bool,
+    created_at: i64,
+    status: Mutex<Status>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScanOut {
+    pub id: u64,
+    pub actor: String,
+    pub agent: String,
+    pub remote_id: u64,
+    pub folders: Vec<String>,
+    pub args: Vec<String>,
+    pub load: bool,
+    pub state: State,
+    pub created_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<i64>,
+    pub done: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percent: Option<f64>,
+    pub per_sec: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta_secs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub built_at: Option<String>,
+}
+
+impl RemoteScan {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Status> {
+        self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn out(&self) -> ScanOut {
+        let st = self.lock();
+        ScanOut {
+            id: self.id,
+            actor: self.actor.clone(),
+            agent: self.agent.name.clone(),
+            remote_id: self.remote_id,
+            folders: self.folders.clone(),
+            args: self.args.clone(),
+            load: self.load,
+            state: st.state,
+            created_at: self.created_at,
+            finished_at: st.finished_at,
+            done: st.done,
+            total: st.total,
+            percent: st.percent,
+            per_sec: st.per_sec,
+            eta_secs: st.eta_secs.filter(|_| st.state == State::Running),
+            error: st.error.clone(),
+            output: st.output.as_ref().map(|p| p.display().to_string()),
+            built_at: st.built_at.clone(),
+        }
+    }
+
+    fn finish(&self, state: State, error: Option<String>) {
+        let mut st = self.lock();
+        st.state = state;
+        st.error = error;
+        st.finished_at = Some(Utc::now().timestamp());
+    }
+
+    fn remote_url(&self, tail: &str) -> String {
+        format!("{}/scans/{}{tail}", self.agent.url, self.remote_id)
+    }
+}
+
+pub struct Registry {
+    cfg: Config,
+    client: reqwest::Client,
+    scans: Mutex<HashMap<u64, Arc<RemoteScan>>>,
+    next_id: AtomicU64,
+    /// One load at a time: each swaps the served dataset.
+    loading: tokio::sync::Mutex<()>,
+}
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+/// Enable the remote scan routes.
+pub fn configure(cfg: Config) -> Result<()> {
+    let _ = REGISTRY.set(Registry::new(cfg)?);
+    Ok(())
+}
+
+fn registry() -> Option<&'static Registry> {
+    REGISTRY.get()
+}
+
+/// Why a submission failed: the agent refused it, or could not be reached.
+enum SubmitError {
+    Refused(String),
+    Unreachable(anyhow::Error),
+}
+
+impl Registry {
+    pub fn new(cfg: Config) -> Result<Self> {
+        std::fs::create_dir_all(&cfg.work_dir)
+            .with_context(|| format!("creating {}", cfg.work_dir.display()))?;
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .build()?;
+        Ok(Self {
+            cfg,
+            client,
+            scans: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            loading: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    fn scans(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Arc<RemoteScan>>> {
+        self.scans.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get(&self, id: u64) -> Option<Arc<RemoteScan>> {
+        self.scans().get(&id).cloned()
+    }
+
+    fn prune(&self, now: i64) {
+        self.scans()
+            .retain(|_, s| s.lock().finished_at.is_none_or(|t| now - t < KEEP_SECS));
+    }
+
+    fn request(&self, agent: &Agent, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        self.client.request(method, url).bearer_auth(&agent.token)
+    }
+
+    /// Start a scan on `req.agent`.
+    async fn submit(&self, actor: &str, agent: &Agent, req: &ScanReq) -> Result<Arc<RemoteScan>, SubmitError> {
+        let body = serde_json::json!({ "folders": req.folders, "args": req.args });
+        let resp = self
+            .request(agent, reqwest::Method::POST, &format!("{}/scans", agent.url))
+            .timeout(API_TIMEOUT)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| SubmitError::Unreachable(e.into()))?;
+        let status = resp.status();
+        if status.is_client_error() {
+            let text = resp.text().await.unwrap_or_default();
+            return Err(SubmitError::Refused(format!("agent {}: {text}", agent.name)));
+        }
+        let remote: AgentScan = resp
+            .error_for_status()
+            .map_err(|e| SubmitError::Unreachable(e.into()))?
+            .json()
+            .await
+            .map_err(|e| SubmitError::Unreachable(e.into()))?;
+        let scan = Arc::new(RemoteScan {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            actor: actor.to_string(),
+            agent: agent.clone(),
+            remote_id: remote.id,
+            folders: req.folders.clone(),
+            args: req.args.clone(),
+            load: req.load,
+            created_at: Utc::now().timestamp(),
+            status: Mutex::new(Status {
+                state: State::Queued,
+                finished_at: None,
+                done: 0,
+                total: None,
+                percent: None,
+                per_sec: 0.0,
+                eta_secs: None,
+                error: None,
+                output: None,
+                built_at: None,
+            }),
+        });
+        self.scans().insert(scan.id, scan.clone());
+        Ok(scan)
+    }
+
+    /// Follow `scan` to its end, then fetch and load its result.
+    async fn track(&self, scan: Arc<RemoteScan>) {
+        match self.follow(&scan).await {
+            Ok(State::Done) => {}
+            Ok(state) => return scan.finish(state, None),
+            Err(e) => {
+                tracing::warn!(id = scan.id, agent = %scan.agent.name, err = %format!("{e:#}"), "remote scan failed");
+                return scan.finish(State::Failed, Some(format!("{e:#}")));
+            }
+        }
+        scan.lock().state = State::Loading;
+        match self.fetch(&scan).await {
+            Ok(()) => {
+                tracing::info!(id = scan.id, agent = %scan.agent.name, "remote scan done");
+                scan.finish(State::Done, None);
+            }
+            Err(e) => {
+                tracing::warn!(id = scan.id, agent = %scan.agent.name, err = %format!("{e:#}"), "remote scan load failed");
+                scan.finish(State::Failed, Some(format!("{e:#}")));
+            }
+        }
+    }
+
+    /// Poll the agent until the scan ends; Done, Cancelled or an error.
+    async fn follow(&self, scan: &RemoteScan) -> Result<State> {
+        let mut errors = 0;
+        loop {
+            tokio::time::sleep(self.cfg.poll).await;
+            let resp = self
+                .request(&scan.agent, reqwest::Method::GET, &scan.remote_url(""))
+                .timeout(API_TIMEOUT)
+                .send()
+                .await;
+            let remote = match resp {
+                Ok(r) if r.status() == reqwest::StatusCode::NOT_FOUND => {
+                    bail!("agent {} no longer knows scan {} (restarted?)", scan.agent.name, scan.remote_id)
+                }
+                Ok(r) => match r.error_for_status() {
+                    Ok(r) => r.json::<AgentScan>().await.map_err(anyhow::Error::from),
+                    Err(e) => Err(e.into()),
+                },
+                Err(e) => Err(e.into()),
+            };
+            let remote = match remote {
+                Ok(r) => r,
+                Err(e) => {
+                    errors += 1;
+                    if errors >= MAX_POLL_ERRORS {
+                        return Err(e.context(format!("agent {} unreachable", scan.agent.name)));
+                    }
+                    continue;
+                }
+            };
+            errors = 0;
+            {
+                let mut st = scan.lock();
+                st.done = remote.done;
+                st.total = remote.total;
+                st.percent = remote.percent;
+                st.per_sec = remote.per_sec;
+                st.eta_secs = remote.eta_secs;
+                if remote.state == "running" {
+                    st.state = State::Running;
+                }
+            }
+            match remote.state.as_str() {
+                "done" => return Ok(State::Done),
+                "cancelled" => return Ok(State::Cancelled),
+                "failed" => bail!(
+                    "scan failed on {}: {}",
+                    scan.agent.name,
+                    remote.error.unwrap_or_default()
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    /// Download the result and, with `load`, build and serve a dataset
+    /// from it.
+    async fn fetch(&self, scan: &RemoteScan) -> Result<()> {
+        let csv = self
+            .cfg
+            .work_dir
+            .join(format!("{}-{}.csv", scan.agent.name, scan.remote_id));
+        self.download(scan, "/result", &csv, true).await?;
+        let meta = PathBuf::from(format!("{}.meta.json", csv.display()));
+        self.download(scan, "/manifest", &meta, false).await?;
+        scan.lock().output = Some(csv.clone());
+        if !scan.load {
+            return Ok(());
+        }
+
+        let db = dataset::source_path().context("no dataset path configured")?;
+        let _one = self.loading.lock().await;
+        let sum = build_db(&self.cfg, &csv, &db).await?;
+        for p in [&csv, &meta] {
+            let _ = std::fs::remove_file(p);
+        }
+        scan.lock().output = Some(sum);
+        let ds = tokio::task::spawn_blocking(dataset::reload).await??;
+        tracing::info!(id = scan.id, path = %ds.path.display(), built_at = %ds.built_at, "remote scan loaded");
+        scan.lock().built_at = Some(ds.built_at.clone());
+        Ok(())
+    }
+
+    /// Stream `<scan>/<tail>` into `dest`. A missing optional file is not
+    /// an error.
+    async fn download(&self, scan: &RemoteScan, tail: &str, dest: &Path, required: bool) -> Result<()> {
+        let mut resp = self
+            .request(&scan.agent, reqwest::Method::GET, &scan.remote_url(tail))
+            .send()
+            .await
+            .with_context(|| format!("downloading {tail} from {}", scan.agent.name))?;
+        if !required && resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        if !resp.status().is_success() {
+            bail!("downloading {tail} from {}: {}", scan.agent.name, resp.status());
+        }
+        let part = PathBuf::from(format!("{}.part", dest.display()));
+        let mut f = tokio::fs::File::create(&part)
+            .await
+            .with_context(|| format!("creating {}", part.display()))?;
+        while let Some(chunk) = resp
+            .chunk()
+            .await
+            .with_context(|| format!("downloading {tail} from {}", scan.agent.name))?
+        {
+            f.write_all(&chunk).await?;
+        }
+        f.flush().await?;
+        drop(f);
+        std::fs::rename(&part, dest).with_context(|| format!("writing {}", dest.display()))?;
+        Ok(())
+    }
+}
+
+pub fn exe(name: &str) -> String {
+    format!("{name}{}", std::env::consts::EXE_SUFFIX)
+}
+
+/// Run a step to completion; the error carries its last line on stderr.
+pub async fn run_step(mut cmd: Command, what: &str) -> Result<()> {
+    let out = cmd
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .with_context(|| format!("starting {what}"))?;
+    if !out.status.success() {
+        let err = String::from_utf8_lossy(&out.stderr);
+        let last = err.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("");
+        bail!("{what} exited with {}: {last}", out.status);
+    }
+    Ok(())
+}
+
+/// Sum `scan` next to it and index the summary into `db`. The DB is built
+/// beside `db` and renamed over it, so the served file is never half
+/// written. Returns the summary path.
+async fn build_db(cfg: &Config, scan: &Path, db: &Path) -> Result<PathBuf> {
+    let sum = scan.with_extension("sum.csv");
+    let mut dusum = Command::new(cfg.bin_dir.join(exe("dusum")));
+    dusum.arg(scan).arg("-o").arg(&sum).args(&cfg.sum);
+    run_step(dusum, "dusum").await?;
+
+    let building = PathBuf::from(format!("{}.loading", db.display()));
+    let mut dudb = Command::new(cfg.bin_dir.join(exe("dudb")));
+    dudb.arg(&sum).arg("-o").arg(&building).arg("--rebuild").args(&cfg.dudb);
+    run_step(dudb, "dudb").await?;
+    std::fs::rename(&building, db)
+        .with_context(|| format!("replacing {} with {}", db.display(), building.display()))?;
+    Ok(sum)
+}
+
+fn forbidden(claims: &Claims, route: &str) -> Option<Response> {
+    if claims.is_admin {
+        return None;
+    }
+    tracing::warn!(actor = %claims.sub, "403 Forbidden {route} (not admin)");
+    Some(AuthError::Forbidden.into_response())
+}
+
+fn not_configured(route: &str) -> Response {
+    tracing::warn!("404 Not Found {route} without --agents");
+    (StatusCode::NOT_FOUND, "no scan agents configured").into_response()
+}
+
+fn not_found(route: &str) -> Response {
+    tracing::warn!("404 Not Found {route}");
+    (StatusCode::NOT_FOUND, "scan not found").into_response()
+}
+
+#[derive(Debug, Serialize)]
+pub struct AgentOut {
+    pub name: String,
+    pub url: String,
+    pub reachable: bool,
+    /// The agent's /health answer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// GET /api/admin/agents
+pub async fn agents_handler(claims: Claims) -> Response {
+    if let Some(r) = forbidden(&claims, "GET /api/admin/agents") {
+        return r;
+    }
+    let Some(reg) = registry() else {
+        return not_configured("GET /api/admin/agents");
+    };
+    let mut checks = tokio::task::JoinSet::new();
+    for (i, agent) in reg.cfg.agents.iter().enumerate() {
+        let req = reg.client.get(format!("{}/health", agent.url)).timeout(HEALTH_TIMEOUT);
+        checks.spawn(async move {
+            let res = match req.send().await.and_then(reqwest::Response::error_for_status) {
+                Ok(r) => r.json::<serde_json::Value>().await,
+                Err(e) => Err(e),
+            };
+            (i, res)
+        });
+    }
+    let mut out: Vec<AgentOut> = reg
+        .cfg
+        .agents
+        .iter()
+        .map(|a| AgentOut {
+            name: a.name.clone(),
+            url: a.url.clone(),
+            reachable: false,
+            health: None,
+            error: None,
+        })
+        .collect();
+    while let Some(Ok((i, res))) = checks.join_next().await {
+        match res {
+            Ok(h) => {
+                out[i].reachable = true;
+                out[i].health = Some(h);
+            }
+            Err(e) => out[i].error = Some(e.to_string()),
+        }
+    }
+    tracing::info!(actor = %claims.sub, agents = out.len(), "200 OK GET /api/admin/agents");
+    Json(out).into_response()
+}
+
+/// POST /api/admin/scans
+pub async fn create_handler(claims: Claims, Json(req): Json<ScanReq>) -> Response {
+    if let Some(r) = forbidden(&claims, "POST /api/admin/scans") {
+        return r;
+    }
+    if let Err(msg) = dutopia::scanargs::check(&req.folders, &req.args) {
+        tracing::warn!(msg = %msg, "400 Bad Request POST /api/admin/scans");
+        return (StatusCode::BAD_REQUEST, msg).into_response();
+    }
+    let Some(reg) = registry() else {
+        return not_configured("POST /api/admin/scans");
+    };
+    let Some(agent) = reg.cfg.agents.iter().find(|a| a.name == req.agent) else {
+        tracing::warn!(agent = %req.agent, "400 Bad Request POST /api/admin/scans unknown agent");
+        return (StatusCode::BAD_REQUEST, format!("unknown agent '{}'", req.agent)).into_response();
+    };
+    reg.prune(Utc::now().timestamp());
+    match reg.submit(&claims.sub, agent, &req).await {
+        Ok(scan) => {
+            tracing::info!(
+                actor = %claims.sub,
+                id = scan.id,
+                agent = %agent.name,
+                remote_id = scan.remote_id,
+                "202 Accepted POST /api/admin/scans"
+            );
+            tokio::spawn(reg.track(scan.clone()));
+            (StatusCode::ACCEPTED, Json(scan.out())).into_response()
+        }
+        Err(SubmitError::Refused(msg)) => {
+            tracing::warn!(agent = %agent.name, msg = %msg, "400 Bad Request POST /api/admin/scans");
+            (StatusCode::BAD_REQUEST, msg).into_response()
+        }
+        Err(SubmitError::Unreachable(e)) => {
+            tracing::error!(agent = %agent.name, err = %format!("{e:#}"), "502 Bad Gateway POST /api/admin/scans");
+            (StatusCode::BAD_GATEWAY, format!("agent {}: {e:#}", agent.name)).into_response()
+        }
+    }
+}
+
+/// GET /api/admin/scans
+pub async fn list_handler(claims: Claims) -> Response {
+    if let Some(r) = forbidden(&claims, "GET /api/admin/scans") {
+        return r;
+    }
+    let Some(reg) = registry() else {
+        return not_configured("GET /api/admin/scans");
+    };
+    reg.prune(Utc::now().timestamp());
+    let mut out: Vec<ScanOut> = reg.scans().values().map(|s| s.out()).collect();
+    out.sort_by_key(|s| s.id);
+    Json(out).into_response()
+}
+
+/// GET /api/admin/scans/{id}
+pub async fn get_handler(claims: Claims, UrlPath(id): UrlPath<u64>) -> Response {
+    if let Some(r) = forbidden(&claims, "GET /api/admin/scans/{id}") {
+        return r;
+    }
+    let Some(reg) = registry() else {
+        return not_configured("GET /api/admin/scans/{id}");
+    };
+    match reg.get(id) {
+        Some(scan) => Json(scan.out()).into_response(),
+        None => not_found("GET /api/admin/scans/{id}"),
+    }
+}
+
+/// POST /api/admin/scans/{id}/cancel
+///
+/// Asks the agent to cancel; the state turns `cancelled` at the next poll.
+pub async fn cancel_handler(claims: Claims, UrlPath(id): UrlPath<u64>) -> Response {
+    if let Some(r) = forbidden(&claims, "POST /api/admin/scans/{id}/cancel") {
+        return r;
+    }
+    let Some(reg) = registry() else {
+        return not_configured("POST /api/admin/scans/{id}/cancel");
+    };
+    let Some(scan) = reg.get(id) else {
+        return not_found("POST /api/admin/scans/{id}/cancel");
+    };
+    if !matches!(scan.lock().state, State::Queued | State::Running) {
+        tracing::warn!(id, "409 Conflict POST /api/admin/scans/{{id}}/cancel");
+        return (StatusCode::CONFLICT, "scan already ended").into_response();
+    }
+    let resp = reg
+        .request(&scan.agent, reqwest::Method::POST, &scan.remote_url("/cancel"))
+        .timeout(API_TIMEOUT)
+        .send()
+        .await;
+    match resp {
+        Ok(r) if r.status().is_success() => {
+            tracing::info!(actor = %claims.sub, id, "202 Accepted POST /api/admin/scans/{{id}}/cancel");
+            (StatusCode::ACCEPTED, Json(scan.out())).into_response()
+        }
+        Ok(r) if r.status() == reqwest::StatusCode::CONFLICT => {
+            tracing::warn!(id, "409 Conflict POST /api/admin/scans/{{id}}/cancel");
+            (StatusCode::CONFLICT, "scan already ended").into_response()
+        }
+        Ok(r) => {
+            let status = r.status();
+            tracing::error!(id, %status, "502 Bad Gateway POST /api/admin/scans/{{id}}/cancel");
+            (StatusCode::BAD_GATEWAY, format!("agent {}: {status}", scan.agent.name)).into_response()
+        }
+        Err(e) => {
+            tracing::error!(id, err = %e, "502 Bad Gateway POST /api/admin/scans/{{id}}/cancel");
+            (StatusCode::BAD_GATEWAY, format!("agent {}: {e}", scan.agent.name)).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_claims as claims;
+    use axum::routing::{get, post};
+    use axum::Router;
+
+    #[test]
+    fn parses_the_agents_file() {
+        let cfg = Config::parse(
+            "[defaults]\nwork_dir = \"/w\"\nbin_dir = \"/b\"\npoll = \"10s\"\nsum = [\"--age\", \"30,365\"]\n\
+             [[agent]]\nname = \"nas1\"\nurl = \"http://nas1:8010/\"\ntoken = \"t\"\n",
+        )
+        .unwrap();
+        assert_eq!(cfg.poll, Duration::from_secs(10));
+        assert_eq!(cfg.sum, ["--age", "30,365"]);
+        assert_eq!(cfg.agents[0].url, "http://nas1:8010");
+
+        let agent = "[[agent]]\nname = \"a\"\nurl = \"http://a\"\ntoken = \"t\"\n";
+        assert!(Config::parse("").is_err());
+        assert!(Config::parse(&format!("{agent}{agent}")).is_err());
+        assert!(Config::parse("[[agent]]\nname = \"a\"\nurl = \"http://a\"\n").is_err());
+        assert!(Config::parse("[[agent]]\nname = \"a b\"\nurl = \"http://a\"\ntoken = \"t\"\n").is_err());
+        assert!(Config::parse("[[agent]]\nname = \"a\"\nurl = \"a:80\"\ntoken = \"t\"\n").is_err());
+        assert!(Config::parse(&format!("[defaults]\npoll = \"soon\"\n{agent}")).is_err());
+    }
+
+    /// A duscand that knows one scan, done at the first poll.
+    async fn fake_agent() -> String {
+        let auth = |h: &axum::http::HeaderMap| h.get("authorization").is_some_and(|v| v == "Bearer t");
+        let app = Router::new()
+            .route(
+                "/scans",
+                post(move |h: axum::http::HeaderMap| async move {
+                    if !auth(&h) {
+                        return (StatusCode::UNAUTHORIZED, String::new());
+                    }
+                    (StatusCode::ACCEPTED, r#"{"id":41,"state":"queued","done":0,"per_sec":0.0}"#.into())
+                }),
+            )
+            .route(
+                "/scans/41",
+                get(|| async { r#"{"id":41,"state":"done","done":3,"total":3,"percent":100.0,"per_sec":3.0}"# }),
+            )
+            .route("/scans/41/result", get(|| async { "INODE,ATIME\n1-2,0\n" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn follows_a_remote_scan_and_downloads_it() {
+        let tmp = tempfile::tempdir().unwrap();
+        let url = fake_agent().await;
+        let cfg = Config::parse(&format!(
+            "[defaults]\nwork_dir = {:?}\npoll = \"0s\"\n[[agent]]\nname = \"nas1\"\nurl = \"{url}\"\ntoken = \"t\"\n",
+            tmp.path()
+        ))
+        .unwrap();
+        let reg = Registry::new(cfg).unwrap();
+        let agent = reg.cfg.agents[0].clone();
+        let req = ScanReq { agent: "nas1".into(), folders: vec!["/data".into()], args: vec![], load: false };
+
+        let scan = match reg.submit("root", &agent, &req).await {
+            Ok(s) => s,
+            Err(_) => panic!("submit failed"),
+        };
+        assert_eq!(scan.remote_id, 41);
+        assert_eq!(scan.out().state, State::Queued);
+        reg.track(scan.clone()).await;
+        let out = scan.out();
+        assert_eq!(out.state, State::Done, "{:?}", out.error);
+        assert_eq!(out.done, 3);
+        let csv = tmp.path().join("nas1-41.csv");
+        assert_eq!(out.output.as_deref(), Some(csv.to_str().unwrap()));
+        assert_eq!(std::fs::read_to_string(&csv).unwrap(), "INODE,ATIME\n1-2,0\n");
+        // No manifest on this agent: nothing written for it.
+        assert!(!tmp.path().join("nas1-41.csv.meta.json").exists());
+
+        let bad = Agent { token: "wrong".into(), ..agent };
+        assert!(matches!(reg.submit("root", &bad, &req).await, Err(SubmitError::Refused(_))));
+    }
+
+    #[tokio::test]
+    async fn admin_only_and_off_without_agents() {
+        let resp = list_handler(claims("alice", false)).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        if registry().is_none() {
+            let resp = list_handler(claims("root", true)).await;
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        }
+        let args = vec!["--enrich".into(), "lib:/tmp/x.so".into()];
+        let req = ScanReq { agent: "nas1".into(), folders: vec!["/data".into()], args, load: false };
+        let resp = create_handler(claims("root", true), Json(req)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn builds_the_db_beside_the_served_one() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp = tempfile::tempdir().unwrap();
+        let bin = tmp.path().join("bin");
+        std::fs::create_dir(&bin).unwrap();
+        // Both tools copy their input to the path after -o.
+        for (name, body) in [
+            ("dusum", "cp \"$1\" \"$3\"; echo \"$@\" > \"$3.args\""),
+            ("dudb", "[ \"$5\" = --case-insensitive ] || { echo 'Error: no flag' >&2; exit 2; }; cp \"$1\" \"$3\""),
+        ] {
+            let p = bin.join(name);
+            std::fs::write(&p, format!("#!/bin/sh\n{body}\n")).unwrap();
+            std::fs::set_permissions(&p, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let cfg = Config::parse(&format!(
+            "[defaults]\nbin_dir = {bin:?}\nsum = [\"--age\", \"1,2\"]\ndudb = [\"--case-insensitive\"]\n\
+             [[agent]]\nname = \"a\"\nurl = \"http://a\"\ntoken = \"t\"\n"
+        ))
+        .unwrap();
+        let scan = tmp.path().join("a-1.csv");
+        std::fs::write(&scan, "rows").unwrap();
+        let db = tmp.path().join("served.db");
+        std::fs::write(&db, "old").unwrap();
+
+        let sum = build_db(&cfg, &scan, &db).await.unwrap();
+        assert_eq!(sum, tmp.path().join("a-1.sum.csv"));
+        assert_eq!(std::fs::read_to_string(&db).unwrap(), "rows");
+        let args = std::fs::read_to_string(tmp.path().join("a-1.sum.csv.args")).unwrap();
+        assert!(args.ends_with(".sum.csv --age 1,2\n"), "{args}");
+
+        let cfg = Config { dudb: vec![], ..cfg };
+        let err = build_db(&cfg, &scan, &db).await.unwrap_err().to_string();
+        assert!(err.starts_with("dudb exited with exit status: 2: Error: no flag"), "{err}");
+    }
+}
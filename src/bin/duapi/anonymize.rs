@@ -0,0 +1,181 @@
+// rs/src/bin/duapi/anonymize.rs
+//
+// Demo mode (`--anonymize`): every dataset is served from a private copy of
+// the DB in which usernames and the path components below
+// `--anonymize-depth` are replaced by salted SHA-256 tags, so the product
+// can be shown and screenshots shared without real names. The tags are
+// stable for a given salt, so the same folder keeps its name across reloads
+// and restarts. Sign-ins are mapped the same way, which lets a non-admin
+// still see their own (renamed) files. File listings would show real names
+// and are switched off; so are `--users-file` display names.
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::OnceLock;
+use tempfile::TempPath;
+
+use dutopia::auth::Claims;
+
+/// Hex digits kept from the digest.
+const TAG_LEN: usize = 10;
+
+pub struct Anonymizer {
+    salt: String,
+    /// Path components down to this depth are kept (`/` is depth 0)
+    depth: usize,
+}
+
+static ACTIVE: OnceLock<Anonymizer> = OnceLock::new();
+
+pub fn configure(salt: &str, depth: usize) {
+    let _ = ACTIVE.set(Anonymizer::new(salt, depth));
+}
+
+pub fn active() -> Option<&'static Anonymizer> {
+    ACTIVE.get()
+}
+
+/// Claims as the anonymized dataset knows the user.
+pub fn claims(mut claims: Claims) -> Claims {
+    if let Some(a) = active() {
+        claims.sub = a.user(&claims.sub);
+    }
+    claims
+}
+
+impl Anonymizer {
+    pub fn new(salt: &str, depth: usize) -> Self {
+        Self {
+            salt: salt.to_string(),
+            depth,
+        }
+    }
+
+    fn tag(&self, kind: &str, value: &str) -> String {
+        let mut h = Sha256::new();
+        h.update(self.salt.as_bytes());
+        h.update([0]);
+        h.update(kind.as_bytes());
+        h.update([0]);
+        h.update(value.as_bytes());
+        h.finalize()
+            .iter()
+            .flat_map(|b| [b >> 4, b & 0xf])
+            .take(TAG_LEN)
+            .map(|n| char::from_digit(n as u32, 16).unwrap_or('0'))
+            .collect()
+    }
+
+    pub fn user(&self, name: &str) -> String {
+        format!("user-{}", self.tag("user", name))
+    }
+
+    /// `full_path` as stored by dudb (`/a/b`, `C:\a\b`, `\\srv\share`) with
+    /// the components deeper than `depth` replaced. The root and the
+    /// separators stay, so the tree keeps its shape.
+    pub fn path(&self, full_path: &str) -> String {
+        let (root, rest, sep) = if let Some(r) = full_path.strip_prefix(r"\\") {
+            (r"\\", r, '\\')
+        } else if let Some(i) = full_path.find('\\') {
+            // A drive root (`C:\`) counts as depth 0, like `/`.
+            (&full_path[..=i], &full_path[i + 1..], '\\')
+        } else if let Some(r) = full_path.strip_prefix('/') {
+            ("/", r, '/')
+        } else {
+            ("", full_path, '/')
+        };
+        let mut out = String::with_capacity(full_path.len());
+        out.push_str(root);
+        for (i, comp) in rest.split(sep).enumerate() {
+            if i > 0 {
+                out.push(sep);
+            }
+            if comp.is_empty() || i < self.depth {
+                out.push_str(comp);
+            } else {
+                out.push_str(&self.tag("path", comp));
+            }
+        }
+        out
+    }
+
+    /// Copy `db` to a temp file and rewrite names and paths in the copy.
+    /// The copy is deleted when the returned path is dropped.
+    pub fn copy_db(&self, db: &Path) -> Result<TempPath> {
+        let copy = tempfile::Builder::new()
+            .prefix("duapi-anon-")
+            .suffix(".db")
+            .tempfile()?
+            .into_temp_path();
+        std::fs::remove_file(&copy)?;
+        let src = Connection::open_with_flags(db, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("opening {}", db.display()))?;
+        src.execute("VACUUM INTO ?1", [copy.to_string_lossy()])
+            .context("copying the DB for --anonymize")?;
+        drop(src);
+
+        let mut conn = Connection::open(&copy)?;
+        let tx = conn.transaction()?;
+        {
+            let users: Vec<(i64, String)> = tx
+                .prepare("SELECT id, name FROM users")?
+                .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+                .collect::<rusqlite::Result<_>>()?;
+            let mut upd = tx.prepare("UPDATE users SET name = ?1 WHERE id = ?2")?;
+            for (id, name) in users {
+                upd.execute(params![self.user(&name), id])?;
+            }
+            let paths: Vec<(i64, String)> = tx
+                .prepare("SELECT id, full_path FROM paths")?
+                .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+                .collect::<rusqlite::Result<_>>()?;
+            let mut upd = tx.prepare("UPDATE paths SET full_path = ?1 WHERE id = ?2")?;
+            for (id, path) in paths {
+                upd.execute(params![self.path(&path), id])?;
+            }
+            tx.execute("DELETE FROM metadata WHERE key = 'source_csv'", [])?;
+        }
+        tx.commit()?;
+        Ok(copy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dutopia::db;
+
+    #[test]
+    fn paths_keep_shape_and_shallow_components() {
+        let a = Anonymizer::new("salt", 1);
+        assert_eq!(a.path(""), "");
+        assert_eq!(a.path("/"), "/");
+        assert_eq!(a.path("/home"), "/home");
+        let alice = a.path("/home/alice");
+        assert!(alice.starts_with("/home/") && !alice.contains("alice"));
+        assert_eq!(a.path("/home/alice/x"), format!("{alice}/{}", a.tag("path", "x")));
+        assert_eq!(a.path(r"C:\"), r"C:\");
+        assert!(a.path(r"C:\Users\bob").starts_with(r"C:\Users\"));
+        assert!(a.path(r"\\srv\share\x").starts_with(r"\\srv\"));
+        assert_ne!(Anonymizer::new("other", 1).path("/home/alice"), alice);
+        assert_eq!(a.user("alice").len(), 5 + TAG_LEN);
+    }
+
+    #[test]
+    fn copy_rewrites_users_and_paths() {
+        let fixture = db::test_support::build_test_db();
+        let a = Anonymizer::new("salt", 0);
+        let copy = a.copy_db(&fixture.path).unwrap();
+        let pool = db::open_pool(&copy).unwrap();
+        let users = db::list_users(&pool).unwrap();
+        assert!(users.contains(&a.user("alice")));
+        let docs = a.path("/docs");
+        let children = db::list_children(&pool, "/", &[], None).unwrap();
+        assert_eq!(children[0].path, docs);
+        assert!(children[0].users.contains_key(&a.user("alice")));
+        // The original is untouched.
+        let orig = db::open_pool(&fixture.path).unwrap();
+        assert_eq!(db::list_users(&orig).unwrap(), vec!["alice", "bob"]);
+    }
+}
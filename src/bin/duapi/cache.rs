@@ -0,0 +1,206 @@
+// rs/src/bin/duapi/cache.rs
+//
+// In-process LRU cache for /api/folders responses.
+//
+// Popular top-level folders are requested with identical parameters many
+// times a day; each request is a multi-join SQLite query. Entries are keyed
+// by (dataset, path, users, age, by_device, hidden prefixes) where `dataset`
+// identifies the loaded DB (its path plus dudb's `built_at` stamp), so a
+// reload can never serve rows from the previous dataset. `invalidate` drops
+// everything and switches the dataset tag.
+//
+// Size comes from `--cache-size` / FOLDERS_CACHE_SIZE; 0 (the default)
+// disables caching and every lookup misses.
+
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use dutopia::db::FolderOut;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    dataset: String,
+    path: String,
+    users: Vec<String>,
+    age: Option<u8>,
+    by_device: bool,
+    /// The `--acl-file` prefixes hidden from the caller.
+    hidden: Vec<String>,
+}
+
+pub struct FolderCache {
+    dataset: String,
+    lru: LruCache<CacheKey, Arc<Vec<FolderOut>>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl FolderCache {
+    pub fn new(capacity: NonZeroUsize, dataset: String) -> Self {
+        Self {
+            dataset,
+            lru: LruCache::new(capacity),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Key for a request against the current dataset. User order and
+    /// duplicates do not change the result, so they are normalized.
+    pub fn key(
+        &self,
+        path: &str,
+        users: &[String],
+        age: Option<u8>,
+        by_device: bool,
+        hidden: &[String],
+    ) -> CacheKey {
+        let mut users = users.to_vec();
+        users.sort();
+        users.dedup();
+        CacheKey {
+            dataset: self.dataset.clone(),
+            path: path.to_string(),
+            users,
+            age,
+            by_device,
+            hidden: hidden.to_vec(),
+        }
+    }
+
+    pub fn get(&mut self, key: &CacheKey) -> Option<Arc<Vec<FolderOut>>> {
+        let hit = self.lru.get(key).cloned();
+        if hit.is_some() {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        hit
+    }
+
+    /// Keys built before an `invalidate` carry the old dataset tag and are
+    /// dropped instead of stored.
+    pub fn put(&mut self, key: CacheKey, items: Arc<Vec<FolderOut>>) {
+        if key.dataset == self.dataset {
+            self.lru.put(key, items);
+        }
+    }
+
+    pub fn invalidate(&mut self, dataset: String) {
+        self.lru.clear();
+        self.dataset = dataset;
+    }
+
+    pub fn hit_ratio(&self) -> (u64, u64) {
+        (self.hits, self.misses)
+    }
+}
+
+static CACHE: OnceLock<Mutex<FolderCache>> = OnceLock::new();
+
+/// Enable the global cache with room for `capacity` responses. No-op when
+/// `capacity` is 0.
+pub fn init(capacity: usize, dataset: String) {
+    if let Some(cap) = NonZeroUsize::new(capacity) {
+        let _ = CACHE.set(Mutex::new(FolderCache::new(cap, dataset)));
+    }
+}
+
+fn with_cache<T>(f: impl FnOnce(&mut FolderCache) -> T) -> Option<T> {
+    let mut guard = CACHE.get()?.lock().ok()?;
+    Some(f(&mut guard))
+}
+
+/// `None` when caching is off, and for requests on a named dataset.
+pub fn key(
+    path: &str,
+    users: &[String],
+    age: Option<u8>,
+    by_device: bool,
+    hidden: &[String],
+) -> Option<CacheKey> {
+    if crate::dataset::is_named() {
+        return None;
+    }
+    with_cache(|c| c.key(path, users, age, by_device, hidden))
+}
+
+pub fn get(key: &CacheKey) -> Option<Arc<Vec<FolderOut>>> {
+    with_cache(|c| {
+        let hit = c.get(key);
+        if hit.is_some() {
+            let (hits, misses) = c.hit_ratio();
+            tracing::debug!(hits, misses, entries = c.lru.len(), "folders cache hit");
+        }
+        hit
+    })
+    .flatten()
+}
+
+pub fn put(key: CacheKey, items: Arc<Vec<FolderOut>>) {
+    with_cache(|c| c.put(key, items));
+}
+
+/// Forget every entry and start keying on `dataset`. Called when the served
+/// dataset changes.
+pub fn invalidate(dataset: String) {
+    with_cache(|c| c.invalidate(dataset));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(cap: usize) -> FolderCache {
+        FolderCache::new(NonZeroUsize::new(cap).unwrap(), "ds1".to_string())
+    }
+
+    #[test]
+    fn key_normalizes_users() {
+        let c = cache(2);
+        let a = c.key("/docs", &["bob".into(), "alice".into(), "bob".into()], Some(1), false, &[]);
+        let b = c.key("/docs", &["alice".into(), "bob".into()], Some(1), false, &[]);
+        assert_eq!(a, b);
+        assert_ne!(a, c.key("/docs", &["alice".into()], Some(1), false, &[]));
+        assert_ne!(a, c.key("/docs", &["alice".into(), "bob".into()], None, false, &[]));
+        assert_ne!(a, c.key("/docs", &["alice".into(), "bob".into()], Some(1), true, &[]));
+        assert_ne!(a, c.key("/docs", &["alice".into(), "bob".into()], Some(1), false, &["/hr".into()]));
+    }
+
+    #[test]
+    fn get_put_and_lru_eviction() {
+        let mut c = cache(2);
+        let k1 = c.key("/a", &[], None, false, &[]);
+        let k2 = c.key("/b", &[], None, false, &[]);
+        let k3 = c.key("/c", &[], None, false, &[]);
+        assert!(c.get(&k1).is_none());
+        c.put(k1.clone(), Arc::new(Vec::new()));
+        c.put(k2.clone(), Arc::new(Vec::new()));
+        assert!(c.get(&k1).is_some());
+        c.put(k3.clone(), Arc::new(Vec::new()));
+        // k2 was least recently used.
+        assert!(c.get(&k2).is_none());
+        assert!(c.get(&k1).is_some());
+        assert!(c.get(&k3).is_some());
+        assert_eq!(c.hit_ratio(), (3, 2));
+    }
+
+    #[test]
+    fn invalidate_drops_entries_and_stale_keys() {
+        let mut c = cache(4);
+        let old = c.key("/a", &[], None, false, &[]);
+        c.put(old.clone(), Arc::new(Vec::new()));
+        c.invalidate("ds2".to_string());
+        assert!(c.get(&old).is_none());
+
+        // A key computed before the reload must not repopulate the cache.
+        c.put(old.clone(), Arc::new(Vec::new()));
+        assert_eq!(c.lru.len(), 0);
+
+        let fresh = c.key("/a", &[], None, false, &[]);
+        assert_ne!(fresh, old);
+        c.put(fresh.clone(), Arc::new(Vec::new()));
+        assert!(c.get(&fresh).is_some());
+    }
+}
@@ -74,6 +74,13 @@ pub async fn script_handler(
         tracing::warn!(actor = %claims.sub, %msg, "{code} /api/cleanup/script invalid");
         return (code, msg).into_response();
     }
+    if let Some(p) = req.paths.iter().find(|p| crate::ensure_visible(&p.path, &claims).is_err()) {
+        tracing::warn!(
+            actor = %claims.sub, path = %p.path,
+            "403 Forbidden /api/cleanup/script (hidden path)"
+        );
+        return AuthError::Forbidden.into_response();
+    }
 
     let script = render_script(&req.username, &req.paths);
     let filename = format!(
@@ -120,6 +127,13 @@ pub async fn notify_handler(
         tracing::warn!(actor = %claims.sub, %msg, "{code} /api/cleanup/notify invalid");
         return (code, msg).into_response();
     }
+    if let Some(p) = req.paths.iter().find(|p| crate::ensure_visible(&p.path, &claims).is_err()) {
+        tracing::warn!(
+            actor = %claims.sub, path = %p.path,
+            "403 Forbidden /api/cleanup/notify (hidden path)"
+        );
+        return AuthError::Forbidden.into_response();
+    }
 
     if !email::is_configured() {
         tracing::warn!("501 Not Implemented /api/cleanup/notify (SMTP not configured)");
@@ -210,7 +224,7 @@ fn is_absolute(p: &str) -> bool {
 
 fn has_traversal(p: &str) -> bool {
     // Reject ".." as a standalone path segment on either / or \ separators.
-    p.split(|c| c == '/' || c == '\\').any(|seg| seg == "..")
+    p.split(['/', '\\']).any(|seg| seg == "..")
 }
 
 fn sanitize_filename(s: &str) -> String {
@@ -0,0 +1,163 @@
+// rs/src/bin/duapi/compact.rs
+//
+// `GET /api/folders?compact=true`: the same data as the default response,
+// laid out as arrays with a schema instead of nested objects, for the
+// mobile view on slow links. Field names are sent once, and users once as a
+// list that the rows point into:
+//
+//   { "schema": { "folder": ["path", "usage", "devices"],
+//                 "usage": ["user", "age", "count", ...],
+//                 "device": ["device", "count", ...] },
+//     "users": ["alice", "bob"],
+//     "folders": [["/docs", [[0, 2, 3, 600, 600, 0, 0, 0, 0, 0]], null]] }
+//
+// `user` in a usage row is an index into `users`; `devices` is null unless
+// the query asked for `by_device`. `user_info`, when configured, is a map
+// keyed by username as in the default response; `owners` maps folder paths
+// to their contact the same way, and `tiers` to their storage tier.
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+use dutopia::db::{Age, FolderOut};
+
+const FOLDER: [&str; 3] = ["path", "usage", "devices"];
+const USAGE: [&str; 10] = [
+    "user", "age", "count", "size", "disk", "linked", "atime", "mtime", "oldest_atime",
+    "oldest_mtime",
+];
+const DEVICE: [&str; 9] = [
+    "device", "count", "size", "disk", "linked", "atime", "mtime", "oldest_atime", "oldest_mtime",
+];
+
+fn totals(a: &Age) -> [Value; 8] {
+    [
+        a.count.into(),
+        a.size.into(),
+        a.disk.into(),
+        a.linked.into(),
+        a.atime.into(),
+        a.mtime.into(),
+        a.oldest_atime.into(),
+        a.oldest_mtime.into(),
+    ]
+}
+
+pub fn folders(items: &[FolderOut]) -> Value {
+    let mut users: Vec<&str> = items
+        .iter()
+        .flat_map(|f| f.users.keys().map(String::as_str))
+        .collect();
+    users.sort_unstable();
+    users.dedup();
+    let index: HashMap<&str, usize> = users.iter().enumerate().map(|(i, u)| (*u, i)).collect();
+
+    let mut info = BTreeMap::new();
+    let mut owners = BTreeMap::new();
+    let mut tiers = BTreeMap::new();
+    let folders: Vec<Value> = items
+        .iter()
+        .map(|f| {
+            let mut usage: Vec<(usize, u8, &Age)> = f
+                .users
+                .iter()
+                .flat_map(|(user, ages)| {
+                    let i = index[user.as_str()];
+                    ages.iter().filter_map(move |(age, a)| Some((i, age.parse().ok()?, a)))
+                })
+                .collect();
+            usage.sort_by_key(|(i, age, _)| (*i, *age));
+            let usage: Vec<Value> = usage
+                .into_iter()
+                .map(|(i, age, a)| {
+                    let mut row = vec![i.into(), age.into()];
+                    row.extend(totals(a));
+                    Value::Array(row)
+                })
+                .collect();
+            let devices = f.devices.as_ref().map(|d| {
+                d.iter()
+                    .map(|(dev, a)| {
+                        let mut row = vec![Value::from(dev.as_str())];
+                        row.extend(totals(a));
+                        Value::Array(row)
+                    })
+                    .collect::<Vec<_>>()
+            });
+            if let Some(ui) = &f.user_info {
+                info.extend(ui.iter().map(|(k, v)| (k.clone(), v.clone())));
+            }
+            if let Some(o) = &f.owner {
+                owners.insert(f.path.clone(), o.clone());
+            }
+            if let Some(t) = &f.tier {
+                tiers.insert(f.path.clone(), t.clone());
+            }
+            json!([f.path, usage, devices])
+        })
+        .collect();
+
+    let mut out = json!({
+        "schema": { "folder": FOLDER, "usage": USAGE, "device": DEVICE },
+        "users": users,
+        "folders": folders,
+    });
+    if !info.is_empty() {
+        out["user_info"] = json!(info);
+    }
+    if !owners.is_empty() {
+        out["owners"] = json!(owners);
+    }
+    if !tiers.is_empty() {
+        out["tiers"] = json!(tiers);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn age(count: u64, size: u64) -> Age {
+        Age {
+            count,
+            size,
+            disk: size,
+            linked: 0,
+            atime: 1,
+            mtime: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn folders_become_rows_with_a_user_dictionary() {
+        let f = FolderOut {
+            path: "/docs".into(),
+            users: HashMap::from([
+                ("bob".into(), HashMap::from([("1".into(), age(1, 50))])),
+                (
+                    "alice".into(),
+                    HashMap::from([("2".into(), age(3, 600)), ("0".into(), age(2, 200))]),
+                ),
+            ]),
+            devices: None,
+            user_info: None,
+            owner: None,
+            tier: None,
+        };
+        let v = folders(&[f]);
+        assert_eq!(v["users"], json!(["alice", "bob"]));
+        assert_eq!(v["schema"]["usage"][2], "count");
+        let row = &v["folders"][0];
+        assert_eq!(row[0], "/docs");
+        assert_eq!(row[1], json!([
+                [0, 0, 2, 200, 200, 0, 1, 2, 0, 0],
+                [0, 2, 3, 600, 600, 0, 1, 2, 0, 0],
+                [1, 1, 1, 50, 50, 0, 1, 2, 0, 0]
+            ]));
+        assert!(row[2].is_null());
+        assert!(v.get("user_info").is_none());
+        assert!(v.get("owners").is_none());
+        assert!(v.get("tiers").is_none());
+    }
+}
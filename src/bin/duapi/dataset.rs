@@ -0,0 +1,408 @@
+// rs/src/bin/duapi/dataset.rs
+//
+// The served dataset: the dudb file plus everything derived from it at load
+// time (user list, path case, project roots). duapi may start before the
+// nightly pipeline has produced the file; until a dataset is loaded the data
+// routes answer 503 "no dataset loaded" and /api/health reports
+// `dataset_loaded: false`. `POST /api/admin/reload` (re)opens the configured
+// file and swaps it in; requests already running keep the pool they started
+// with, and the folders cache is re-keyed so no response from the old file
+// is served again. Each install also gives subscription digests a chance to
+// go out. With `--anonymize` the pool is opened on a renamed private copy of
+// the file (see `anonymize`), which lives as long as the dataset.
+//
+// Besides that default dataset, named ones (from `--watch-dir`) can be
+// served side by side: a data route given `?dataset=NAME` answers from that
+// dataset instead, or 404 for an unknown name. The selection is scoped to
+// the request, so `current()` returns it for the whole handler. Named
+// datasets are not cached and send no digests; `GET /api/datasets` lists
+// them all, and admins can drop (`DELETE /api/datasets/{name}`) or reopen
+// (`POST /api/datasets/{name}/reload`) one. The default dataset a reload
+// replaced stays open until the next reload, as the baseline `/api/me`
+// compares with.
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Path as UrlPath, Request},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use tempfile::TempPath;
+
+use dutopia::auth::{AuthError, Claims};
+use dutopia::db::{self, DbPool};
+use dutopia::project::{project_roots, ProjectRoot, ProjectRules};
+
+use crate::{anonymize, cache, subscriptions};
+
+pub struct Dataset {
+    pub path: PathBuf,
+    /// dudb's `built_at` stamp (empty for DBs that predate it)
+    pub built_at: String,
+    pub pool: DbPool,
+    /// The anonymized copy `pool` reads, deleted with the dataset
+    _copy: Option<TempPath>,
+    pub users: Vec<String>,
+    /// The DB was built with `dudb --case-insensitive`
+    pub case_insensitive: bool,
+    /// Roots resolved from `--project-rules`; `None` without rules
+    pub projects: Option<Arc<[ProjectRoot]>>,
+}
+
+/// Where datasets are loaded from; set once at startup.
+struct Source {
+    path: PathBuf,
+    rules: Option<ProjectRules>,
+}
+
+static SOURCE: OnceLock<Source> = OnceLock::new();
+static CURRENT: RwLock<Option<Arc<Dataset>>> = RwLock::new(None);
+static NAMED: RwLock<BTreeMap<String, Arc<Dataset>>> = RwLock::new(BTreeMap::new());
+/// The default dataset the last install replaced, for `previous`
+static PREVIOUS: RwLock<Option<Arc<Dataset>>> = RwLock::new(None);
+
+tokio::task_local! {
+    /// The dataset a request picked with `?dataset=`.
+    static SELECTED: Arc<Dataset>;
+}
+/// Serializes reloads so two admins cannot open the file twice at once.
+static LOADING: Mutex<()> = Mutex::new(());
+
+pub fn configure(path: PathBuf, rules: Option<ProjectRules>) {
+    let _ = SOURCE.set(Source { path, rules });
+}
+
+/// The configured DB file, loaded or not.
+pub fn source_path() -> Option<PathBuf> {
+    SOURCE.get().map(|s| s.path.clone())
+}
+
+/// The dataset the running request reads: its `?dataset=` pick, else the
+/// default one.
+pub fn current() -> Option<Arc<Dataset>> {
+    if let Ok(ds) = SELECTED.try_with(Arc::clone) {
+        return Some(ds);
+    }
+    default()
+}
+
+/// The dataset loaded from the configured file.
+pub fn default() -> Option<Arc<Dataset>> {
+    CURRENT.read().ok()?.clone()
+}
+
+/// True inside a request that picked a named dataset.
+pub fn is_named() -> bool {
+    SELECTED.try_with(|_| ()).is_ok()
+}
+
+pub fn named(name: &str) -> Option<Arc<Dataset>> {
+    NAMED.read().ok()?.get(name).cloned()
+}
+
+/// Named datasets, by name.
+pub fn named_list() -> Vec<(String, Arc<Dataset>)> {
+    NAMED
+        .read()
+        .map(|m| m.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+        .unwrap_or_default()
+}
+
+/// Open `path` with the configured project rules and serve it as `name`,
+/// replacing any dataset of that name.
+pub fn load_named(name: &str, path: &Path) -> Result<Arc<Dataset>> {
+    let rules = SOURCE.get().and_then(|s| s.rules.as_ref());
+    let ds = Arc::new(Dataset::open(path, rules)?);
+    if let Ok(mut named) = NAMED.write() {
+        named.insert(name.to_string(), ds.clone());
+    }
+    Ok(ds)
+}
+
+/// Stop serving `name`; requests already running keep it until they end.
+pub fn unload_named(name: &str) -> Option<Arc<Dataset>> {
+    NAMED.write().ok()?.remove(name)
+}
+
+impl Dataset {
+    pub fn open(path: &Path, rules: Option<&ProjectRules>) -> Result<Self> {
+        let copy = anonymize::active().map(|a| a.copy_db(path)).transpose()?;
+        let pool = db::open_pool(copy.as_deref().unwrap_or(path)).with_context(|| {
+            format!(
+                "opening DB at {}. Build it first with `dudb --input <csv> --output {}`",
+                path.display(),
+                path.display()
+            )
+        })?;
+        let users = db::list_users(&pool).context("loading user list")?;
+        let built_at = db::read_metadata(&pool, "built_at")
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        let case_insensitive = db::read_metadata(&pool, "path_case")
+            .ok()
+            .flatten()
+            .is_some_and(|v| v == "insensitive");
+        let projects = match rules {
+            Some(rules) => Some(
+                project_roots(&pool, rules)
+                    .context("resolving project roots")?
+                    .into(),
+            ),
+            None => None,
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            built_at,
+            pool,
+            _copy: copy,
+            users,
+            case_insensitive,
+            projects,
+        })
+    }
+
+    /// `built_at` as epoch seconds.
+    pub fn built_secs(&self) -> Option<i64> {
+        self.built_at.parse().ok()
+    }
+
+    /// Folders cache tag: a different file or a rebuild of the same one
+    /// never shares entries.
+    fn tag(&self) -> String {
+        format!("{}@{}", self.path.display(), self.built_at)
+    }
+}
+
+/// Serve `ds` from now on.
+pub fn install(ds: Dataset) -> Arc<Dataset> {
+    let ds = Arc::new(ds);
+    cache::invalidate(ds.tag());
+    let replaced = CURRENT.write().ok().and_then(|mut c| c.replace(ds.clone()));
+    if let (Some(old), Ok(mut previous)) = (replaced, PREVIOUS.write()) {
+        *previous = Some(old);
+    }
+    subscriptions::on_install(ds.clone());
+    ds
+}
+
+/// The newest other dataset built before `ds`: the default one replaced at
+/// the last install, or any dataset still served. None when `ds` has no
+/// `built_at`.
+pub fn previous(ds: &Dataset) -> Option<Arc<Dataset>> {
+    let at = ds.built_secs()?;
+    let replaced = PREVIOUS.read().ok().and_then(|p| p.clone());
+    named_list()
+        .into_iter()
+        .map(|(_, d)| d)
+        .chain(default())
+        .chain(replaced)
+        .filter_map(|d| Some((d.built_secs()?, d)))
+        .filter(|(t, _)| *t < at)
+        .max_by_key(|(t, _)| *t)
+        .map(|(_, d)| d)
+}
+
+/// Open the configured file and serve it. On error the previous dataset,
+/// if any, stays in place.
+pub fn reload() -> Result<Arc<Dataset>> {
+    let source = SOURCE.get().context("dataset source not configured")?;
+    let _guard = LOADING.lock().unwrap_or_else(|e| e.into_inner());
+    let ds = Dataset::open(&source.path, source.rules.as_ref())?;
+    Ok(install(ds))
+}
+
+/// Middleware for routes that read the dataset: picks the `?dataset=` one
+/// for the rest of the request, or checks that the default one is loaded.
+pub async fn require_dataset(req: Request, next: Next) -> Response {
+    let name = req.uri().query().and_then(|q| {
+        url::form_urlencoded::parse(q.as_bytes())
+            .find(|(k, _)| k == "dataset")
+            .map(|(_, v)| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    });
+    if let Some(name) = name {
+        let Some(ds) = named(&name) else {
+            tracing::warn!(path = %req.uri().path(), dataset = %name, "404 Not Found: unknown dataset");
+            return unknown_dataset(&name);
+        };
+        return SELECTED.scope(ds, next.run(req)).await;
+    }
+    if current().is_none() {
+        tracing::warn!(path = %req.uri().path(), "503 Service Unavailable: no dataset loaded");
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "error": "no dataset loaded" })),
+        )
+            .into_response();
+    }
+    next.run(req).await
+}
+
+#[derive(Serialize)]
+pub struct DatasetOut {
+    /// Empty for the default dataset
+    name: String,
+    path: String,
+    built_at: String,
+    users: usize,
+}
+
+impl DatasetOut {
+    fn new(name: &str, ds: &Dataset) -> Self {
+        Self {
+            name: name.to_string(),
+            path: ds.path.display().to_string(),
+            built_at: ds.built_at.clone(),
+            users: ds.users.len(),
+        }
+    }
+}
+
+/// GET /api/datasets
+///
+/// The default dataset, if loaded, then the named ones by name. Paths are
+/// for admins only.
+pub async fn list_handler(claims: Claims) -> Response {
+    let mut out: Vec<DatasetOut> = default()
+        .map(|ds| DatasetOut::new("", &ds))
+        .into_iter()
+        .chain(named_list().iter().map(|(n, ds)| DatasetOut::new(n, ds)))
+        .collect();
+    if !claims.is_admin {
+        out.iter_mut().for_each(|d| d.path.clear());
+    }
+    tracing::info!(datasets = out.len(), "200 OK /api/datasets");
+    Json(out).into_response()
+}
+
+fn unknown_dataset(name: &str) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(serde_json::json!({ "error": format!("unknown dataset '{name}'") })),
+    )
+        .into_response()
+}
+
+/// DELETE /api/datasets/{name}
+///
+/// Admin-only. Stops serving a named dataset; its pool and indexes are
+/// freed once the requests still reading it end. Files are left alone, and
+/// a `--watch-dir` dataset stays unloaded until its summary changes.
+pub async fn delete_handler(claims: Claims, UrlPath(name): UrlPath<String>) -> Response {
+    if !claims.is_admin {
+        tracing::warn!(actor = %claims.sub, "403 Forbidden DELETE /api/datasets/{name} (not admin)");
+        return AuthError::Forbidden.into_response();
+    }
+    match unload_named(&name) {
+        Some(ds) => {
+            tracing::info!(actor = %claims.sub, dataset = %name, path = %ds.path.display(), "200 OK DELETE /api/datasets/{name}");
+            Json(DatasetOut::new(&name, &ds)).into_response()
+        }
+        None => {
+            tracing::warn!(dataset = %name, "404 Not Found DELETE /api/datasets/{name}");
+            unknown_dataset(&name)
+        }
+    }
+}
+
+/// POST /api/datasets/{name}/reload
+///
+/// Admin-only. Opens a named dataset's DB file again, e.g. after it was
+/// rebuilt in place, and swaps it in. 500 leaves the served one in place.
+pub async fn reload_named_handler(claims: Claims, UrlPath(name): UrlPath<String>) -> Response {
+    if !claims.is_admin {
+        tracing::warn!(actor = %claims.sub, "403 Forbidden /api/datasets/{name}/reload (not admin)");
+        return AuthError::Forbidden.into_response();
+    }
+    let Some(old) = named(&name) else {
+        tracing::warn!(dataset = %name, "404 Not Found /api/datasets/{name}/reload");
+        return unknown_dataset(&name);
+    };
+    let task_name = name.clone();
+    match tokio::task::spawn_blocking(move || load_named(&task_name, &old.path)).await {
+        Ok(Ok(ds)) => {
+            tracing::info!(
+                actor = %claims.sub,
+                dataset = %name,
+                built_at = %ds.built_at,
+                "200 OK /api/datasets/{name}/reload"
+            );
+            Json(DatasetOut::new(&name, &ds)).into_response()
+        }
+        Ok(Err(e)) => {
+            tracing::error!(dataset = %name, err = %format!("{e:#}"), "500 reload ERROR /api/datasets/{name}/reload");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("reload error: {e:#}"),
+            )
+                .into_response()
+        }
+        Err(join_err) => {
+            tracing::error!(err = %join_err, "500 Task Join Error /api/datasets/{name}/reload");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("task error: {join_err}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReloadOut {
+    path: String,
+    built_at: String,
+    users: usize,
+}
+
+/// POST /api/admin/reload
+///
+/// Admin-only. Opens the configured DB file again and serves it, so a
+/// server started before the file existed, or serving last night's build,
+/// picks up the new one without a restart. 500 leaves the current dataset
+/// in place.
+pub async fn reload_handler(claims: Claims) -> Response {
+    if !claims.is_admin {
+        tracing::warn!(actor = %claims.sub, "403 Forbidden /api/admin/reload (not admin)");
+        return AuthError::Forbidden.into_response();
+    }
+    match tokio::task::spawn_blocking(reload).await {
+        Ok(Ok(ds)) => {
+            tracing::info!(
+                actor = %claims.sub,
+                path = %ds.path.display(),
+                built_at = %ds.built_at,
+                users = ds.users.len(),
+                "200 OK /api/admin/reload"
+            );
+            Json(ReloadOut {
+                path: ds.path.display().to_string(),
+                built_at: ds.built_at.clone(),
+                users: ds.users.len(),
+            })
+            .into_response()
+        }
+        Ok(Err(e)) => {
+            tracing::error!(err = %format!("{e:#}"), "500 reload ERROR /api/admin/reload");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("reload error: {e:#}"),
+            )
+                .into_response()
+        }
+        Err(join_err) => {
+            tracing::error!(err = %join_err, "500 Task Join Error /api/admin/reload");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("task error: {join_err}"),
+            )
+                .into_response()
+        }
+    }
+}
@@ -0,0 +1,527 @@
+// rs/src/bin/duapi/federate.rs
+//
+// Federation: one duapi answering /api/folders and /api/summary for several
+// sites, each indexed and served by its own duapi, so users see every site
+// from one place. Sites are listed in a TOML file (`--sites FILE`):
+//
+//   [defaults]
+//   timeout = "30s"                # per call to a site (30s)
+//
+//   [[site]]
+//   name = "site-a"
+//   url = "https://duapi-a:8080"
+//   token_env = "SITE_A_TOKEN"     # or token = "..."
+//
+// The site is the first component of every path: `/` lists one folder per
+// site (`/site-a`, `/site-b`) holding that site's totals, and
+// `/site-a/proj/x` is `/proj/x` on site-a, its children's paths prefixed
+// back with `/site-a`. `/api/summary` adds the sites' summaries up and
+// lists each site's status under `sites`; a site that cannot be reached is
+// left out of the totals (and of the `/` listing) instead of failing the
+// call.
+//
+// Each site is called with its token, a JWT issued by that site's duapi to
+// an admin account. The caller's own rights are applied there: non-admins
+// are forwarded as `as_user`, so a site answers what it would answer them
+// directly, its own `--acl-file` included. Sites must serve POSIX paths.
+use anyhow::{bail, Context, Result};
+use axum::{
+    extract::Query,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+use dutopia::analytic::{FolderTotal, UserTotal};
+use dutopia::auth::{AuthError, Claims};
+use dutopia::dashboard::{AgeTotal, Dashboard};
+use dutopia::db::{Age, FolderOut};
+use dutopia::tiers::TierTotal;
+use dutopia::util::parse_duration;
+
+use crate::get_user_info;
+use crate::handler::{folders_json, impersonate};
+use crate::locale::{with_formatted, Locale};
+use crate::query::{parse_users_csv, FolderQuery, SummaryQuery};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct FileDefaults {
+    timeout: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileSite {
+    name: String,
+    url: String,
+    token: Option<String>,
+    token_env: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    defaults: FileDefaults,
+    #[serde(default)]
+    site: Vec<FileSite>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Site {
+    pub name: String,
+    /// Base URL of the site's duapi, without a trailing slash
+    pub url: String,
+    token: String,
+}
+
+pub struct Federation {
+    pub sites: Vec<Site>,
+    timeout: Duration,
+    client: reqwest::Client,
+}
+
+static FEDERATION: OnceLock<Federation> = OnceLock::new();
+
+/// Serve /api/folders and /api/summary from `fed`'s sites.
+pub fn configure(fed: Federation) {
+    let _ = FEDERATION.set(fed);
+}
+
+/// Query parameters forwarded to the sites.
+type Params = Vec<(&'static str, String)>;
+
+/// Why a federated listing failed.
+pub enum ListError {
+    UnknownSite(String),
+    Unreachable(anyhow::Error),
+}
+
+impl Federation {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading sites file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("in {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let file: File = toml::from_str(text)?;
+        let timeout = match &file.defaults.timeout {
+            Some(t) => parse_duration(t).map_err(|e| anyhow::anyhow!("timeout: {e}"))?,
+            None => DEFAULT_TIMEOUT,
+        };
+        let mut names = HashSet::new();
+        let mut sites = Vec::new();
+        for s in file.site {
+            let name = s.name.trim().to_string();
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+                bail!("site name '{}' must be letters, digits, '-', '_' or '.'", s.name);
+            }
+            if !names.insert(name.clone()) {
+                bail!("site '{name}' is listed twice");
+            }
+            let url = s.url.trim().trim_end_matches('/').to_string();
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                bail!("site '{name}': url must start with http:// or https://");
+            }
+            let token = match (s.token, s.token_env) {
+                (Some(t), None) => t,
+                (None, Some(var)) => std::env::var(&var)
+                    .with_context(|| format!("site '{name}': {var} is not set"))?,
+                _ => bail!("site '{name}' needs one of token or token_env"),
+            };
+            sites.push(Site { name, url, token });
+        }
+        if sites.is_empty() {
+            bail!("no [[site]] listed");
+        }
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .build()?;
+        Ok(Self { sites, timeout, client })
+    }
+
+    /// GET `/api/{route}` on `site`.
+    async fn get<T: DeserializeOwned>(&self, site: &Site, route: &str, params: &Params) -> Result<T> {
+        let resp = self
+            .client
+            .get(format!("{}/api/{route}", site.url))
+            .bearer_auth(&site.token)
+            .query(params)
+            .timeout(self.timeout)
+            .send()
+            .await
+            .with_context(|| format!("site {}", site.name))?;
+        let status = resp.status();
+        if !status.is_success() {
+            let text = resp.text().await.unwrap_or_default();
+            bail!("site {}: {status} {}", site.name, text.trim());
+        }
+        resp.json()
+            .await
+            .with_context(|| format!("site {}: reading /api/{route}", site.name))
+    }
+
+    /// `route` on every site at once, in the order the sites are listed.
+    async fn all<T>(&'static self, route: &'static str, params: &Params) -> Vec<(&'static Site, Result<T>)>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let mut set = JoinSet::new();
+        for (i, site) in self.sites.iter().enumerate() {
+            let params = params.clone();
+            set.spawn(async move { (i, self.get::<T>(site, route, &params).await) });
+        }
+        let mut out = set.join_all().await;
+        out.sort_by_key(|(i, _)| *i);
+        out.into_iter().map(|(i, r)| (&self.sites[i], r)).collect()
+    }
+
+    /// Children of the federated `path`: the sites for `/`, else the
+    /// children on the site it names.
+    pub async fn list(&'static self, path: &str, params: &Params) -> Result<Vec<FolderOut>, ListError> {
+        let Some((name, remote)) = split(path) else {
+            let mut params = params.clone();
+            params.push(("path", String::new()));
+            let mut out = Vec::new();
+            for (site, res) in self.all::<Vec<FolderOut>>("folders", &params).await {
+                match res {
+                    Ok(roots) => out.push(site_folder(&site.name, roots)),
+                    Err(e) => tracing::warn!(site = %site.name, err = %format!("{e:#}"), "site left out of /api/folders"),
+                }
+            }
+            return Ok(out);
+        };
+        let Some(site) = self.sites.iter().find(|s| s.name == name) else {
+            return Err(ListError::UnknownSite(name.to_string()));
+        };
+        let mut params = params.clone();
+        params.push(("path", remote));
+        let mut items: Vec<FolderOut> = self
+            .get(site, "folders", &params)
+            .await
+            .map_err(ListError::Unreachable)?;
+        for f in items.iter_mut() {
+            f.path = format!("/{name}{}", f.path);
+        }
+        Ok(items)
+    }
+}
+
+/// `/site-a/proj` -> `("site-a", "/proj")`; `None` for the root.
+fn split(path: &str) -> Option<(&str, String)> {
+    let rest = path.trim_start_matches('/');
+    if rest.is_empty() {
+        return None;
+    }
+    match rest.split_once('/') {
+        Some((name, tail)) => Some((name, format!("/{tail}"))),
+        None => Some((rest, "/".to_string())),
+    }
+}
+
+/// One folder `/name` with the totals of a site's platform roots.
+fn site_folder(name: &str, roots: Vec<FolderOut>) -> FolderOut {
+    let mut users: HashMap<String, HashMap<String, Age>> = HashMap::new();
+    let mut devices: Option<BTreeMap<String, Age>> = None;
+    for root in roots {
+        for (user, ages) in root.users {
+            let into = users.entry(user).or_default();
+            for (age, a) in ages {
+                into.entry(age).or_default().add(&a);
+            }
+        }
+        for (dev, a) in root.devices.into_iter().flatten() {
+            devices.get_or_insert_default().entry(dev).or_default().add(&a);
+        }
+    }
+    FolderOut {
+        path: format!("/{name}"),
+        users,
+        devices,
+        user_info: None,
+        owner: None,
+        tier: None,
+    }
+}
+
+/// The sites' summaries as one; `top` caps the merged lists. A user's
+/// total only counts the sites where they made the top list.
+fn merge_summaries(parts: Vec<(&str, Dashboard)>, top: u32) -> Dashboard {
+    let mut out = Dashboard::default();
+    let mut ages: BTreeMap<u8, AgeTotal> = BTreeMap::new();
+    let mut users: HashMap<String, UserTotal> = HashMap::new();
+    let mut tiers: HashMap<String, TierTotal> = HashMap::new();
+    for (name, d) in parts {
+        out.built_at = match (out.built_at, d.built_at) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        out.count += d.count;
+        out.size += d.size;
+        out.disk += d.disk;
+        out.linked += d.linked;
+        for a in d.ages {
+            let into = ages.entry(a.age).or_insert_with(|| AgeTotal { age: a.age, ..AgeTotal::default() });
+            into.count += a.count;
+            into.size += a.size;
+            into.disk += a.disk;
+        }
+        for u in d.top_users {
+            let into = users.entry(u.user.clone()).or_insert(UserTotal { disk: 0, size: 0, count: 0, ..u.clone() });
+            into.disk += u.disk;
+            into.size += u.size;
+            into.count += u.count;
+        }
+        out.top_folders.extend(d.top_folders.into_iter().map(|f| FolderTotal {
+            path: format!("/{name}{}", f.path),
+            ..f
+        }));
+        for t in d.tiers {
+            let into = tiers.entry(t.tier.clone()).or_insert(TierTotal { count: 0, size: 0, disk: 0, ..t.clone() });
+            into.count += t.count;
+            into.size += t.size;
+            into.disk += t.disk;
+        }
+    }
+    out.ages = ages.into_values().collect();
+    out.top_users = users.into_values().collect();
+    out.top_users.sort_by(|a, b| b.disk.cmp(&a.disk).then_with(|| a.user.cmp(&b.user)));
+    out.top_users.truncate(top as usize);
+    out.top_folders.sort_by(|a, b| b.disk.cmp(&a.disk).then_with(|| a.path.cmp(&b.path)));
+    out.top_folders.truncate(top as usize);
+    out.tiers = tiers.into_values().collect();
+    out.tiers.sort_by(|a, b| b.disk.cmp(&a.disk).then_with(|| a.tier.cmp(&b.tier)));
+    out
+}
+
+#[derive(Serialize)]
+struct SiteStatus {
+    name: String,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    built_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct FederatedSummary {
+    #[serde(flatten)]
+    summary: Dashboard,
+    sites: Vec<SiteStatus>,
+}
+
+/// GET /api/folders with `--sites`
+pub async fn folders_handler(claims: Claims, Query(q): Query<FolderQuery>) -> Response {
+    let Some(fed) = FEDERATION.get() else {
+        return (StatusCode::NOT_FOUND, "federation is off").into_response();
+    };
+    let claims = match impersonate(claims, q.as_user.as_deref(), "/api/folders") {
+        Ok(c) => c,
+        Err(e) => return e.into_response(),
+    };
+    let raw_path = q.path.unwrap_or_default();
+    let Some(path) = crate::query::normalize_path(&raw_path).filter(|p| p.is_empty() || p.starts_with('/')) else {
+        tracing::warn!(input = %raw_path, "400 Bad Request /api/folders rejected path");
+        return (StatusCode::BAD_REQUEST, "invalid path").into_response();
+    };
+    let requested: Vec<String> = match q.users.as_deref() {
+        Some(s) if !s.trim().is_empty() => parse_users_csv(s),
+        _ => Vec::new(),
+    };
+    if !claims.is_admin && (requested.len() != 1 || requested[0] != claims.sub) {
+        tracing::warn!(path = %path, requested_users = ?requested, "403 Forbidden /api/folders");
+        return AuthError::Forbidden.into_response();
+    }
+    if q.group_by.as_deref().map(str::trim).is_some_and(|g| !g.is_empty() && g != "folder") {
+        tracing::warn!("400 Bad Request /api/folders group_by on federation");
+        return (StatusCode::BAD_REQUEST, "group_by is not available across sites").into_response();
+    }
+
+    let mut params: Params = Vec::new();
+    if !requested.is_empty() {
+        params.push(("users", requested.join(",")));
+    }
+    if let Some(age) = q.age {
+        params.push(("age", age.to_string()));
+    }
+    if q.by_device.unwrap_or(false) {
+        params.push(("by_device", "true".into()));
+    }
+    if !claims.is_admin {
+        params.push(("as_user", claims.sub.clone()));
+    }
+    match fed.list(&path, &params).await {
+        Ok(mut items) => {
+            if let Some(dir) = get_user_info() {
+                for f in items.iter_mut() {
+                    f.user_info = dir.subset(f.users.keys());
+                }
+            }
+            tracing::info!(path = %path, items = items.len(), "200 OK /api/folders (federated)");
+            folders_json(&items, q.compact.unwrap_or(false))
+        }
+        Err(ListError::UnknownSite(name)) => {
+            tracing::warn!(path = %path, "404 Not Found /api/folders unknown site");
+            (StatusCode::NOT_FOUND, format!("no site '{name}'")).into_response()
+        }
+        Err(ListError::Unreachable(e)) => {
+            tracing::warn!(path = %path, err = %format!("{e:#}"), "502 Bad Gateway /api/folders");
+            (StatusCode::BAD_GATEWAY, format!("{e:#}")).into_response()
+        }
+    }
+}
+
+/// GET /api/summary with `--sites`
+pub async fn summary_handler(claims: Claims, headers: HeaderMap, Query(q): Query<SummaryQuery>) -> Response {
+    let Some(fed) = FEDERATION.get() else {
+        return (StatusCode::NOT_FOUND, "federation is off").into_response();
+    };
+    let locale = Locale::resolve(q.locale.as_deref(), &headers);
+    let claims = match impersonate(claims, q.as_user.as_deref(), "/api/summary") {
+        Ok(c) => c,
+        Err(e) => return e.into_response(),
+    };
+    let top = q.top.unwrap_or(10).clamp(1, 100);
+    let mut params: Params = vec![("top", top.to_string())];
+    if !claims.is_admin {
+        params.push(("as_user", claims.sub.clone()));
+    }
+
+    let mut parts = Vec::new();
+    let mut sites = Vec::new();
+    for (site, res) in fed.all::<Dashboard>("summary", &params).await {
+        match res {
+            Ok(d) => {
+                sites.push(SiteStatus { name: site.name.clone(), ok: true, built_at: d.built_at, error: None });
+                parts.push((site.name.as_str(), d));
+            }
+            Err(e) => {
+                tracing::warn!(site = %site.name, err = %format!("{e:#}"), "site left out of /api/summary");
+                sites.push(SiteStatus { name: site.name.clone(), ok: false, built_at: None, error: Some(format!("{e:#}")) });
+            }
+        }
+    }
+    let summary = merge_summaries(parts, top);
+    tracing::info!(count = summary.count, sites = sites.len(), "200 OK /api/summary (federated)");
+    let formatted = locale.map(|l| l.dashboard(&summary));
+    let out = FederatedSummary { summary, sites };
+    Json(with_formatted(serde_json::json!(out), formatted)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+
+    #[test]
+    fn sites_file_and_paths() {
+        let fed = Federation::parse(
+            "[defaults]\ntimeout = \"5s\"\n[[site]]\nname = \"site-a\"\nurl = \"http://a:8080/\"\ntoken = \"t\"\n",
+        )
+        .unwrap();
+        assert_eq!(fed.sites[0].url, "http://a:8080");
+        assert_eq!(fed.timeout, Duration::from_secs(5));
+        assert!(Federation::parse("").is_err());
+        assert!(Federation::parse("[[site]]\nname = \"a/b\"\nurl = \"http://a\"\ntoken = \"t\"\n").is_err());
+        assert!(Federation::parse("[[site]]\nname = \"a\"\nurl = \"http://a\"\n").is_err());
+
+        assert_eq!(split("/"), None);
+        assert_eq!(split(""), None);
+        assert_eq!(split("/site-a"), Some(("site-a", "/".into())));
+        assert_eq!(split("/site-a/proj/x"), Some(("site-a", "/proj/x".into())));
+    }
+
+    /// A site's duapi with one user under `/proj`.
+    async fn fake_site(disk: u64, built_at: i64) -> String {
+        let auth = |h: &HeaderMap| h.get("authorization").is_some_and(|v| v == "Bearer t");
+        let app = Router::new()
+            .route(
+                "/api/folders",
+                get(move |h: HeaderMap, Query(q): Query<HashMap<String, String>>| async move {
+                    if !auth(&h) {
+                        return (StatusCode::UNAUTHORIZED, String::new());
+                    }
+                    let path = if q.get("path").is_some_and(|p| p.is_empty()) { "/" } else { "/proj" };
+                    let user = q.get("as_user").map_or("alice", String::as_str);
+                    let body = format!(
+                        r#"[{{"path":"{path}","users":{{"{user}":{{"0":{{"count":1,"size":{disk},"disk":{disk},"linked":0,"atime":{built_at},"mtime":5}}}}}}}}]"#
+                    );
+                    (StatusCode::OK, body)
+                }),
+            )
+            .route(
+                "/api/summary",
+                get(move |h: HeaderMap| async move {
+                    if !auth(&h) {
+                        return StatusCode::UNAUTHORIZED.into_response();
+                    }
+                    Json(Dashboard {
+                        built_at: Some(built_at),
+                        count: 1,
+                        size: disk,
+                        disk,
+                        ages: vec![AgeTotal { age: 0, count: 1, size: disk, disk }],
+                        top_users: vec![UserTotal { user: "alice".into(), disk, size: disk, count: 1 }],
+                        top_folders: vec![FolderTotal { path: "/proj".into(), disk, size: disk, count: 1 }],
+                        ..Dashboard::default()
+                    })
+                    .into_response()
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn lists_and_sums_across_sites() {
+        let (a, b) = (fake_site(100, 10).await, fake_site(300, 20).await);
+        let fed: &'static Federation = Box::leak(Box::new(
+            Federation::parse(&format!(
+                "[[site]]\nname = \"a\"\nurl = \"{a}\"\ntoken = \"t\"\n\
+                 [[site]]\nname = \"b\"\nurl = \"{b}\"\ntoken = \"t\"\n\
+                 [[site]]\nname = \"down\"\nurl = \"{a}\"\ntoken = \"wrong\"\n"
+            ))
+            .unwrap(),
+        ));
+
+        let Ok(root) = fed.list("/", &vec![]).await else { panic!("root listing failed") };
+        let paths: Vec<_> = root.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["/a", "/b"]);
+        assert_eq!(root[1].users["alice"]["0"].disk, 300);
+
+        let as_bob = vec![("as_user", "bob".to_string())];
+        let Ok(proj) = fed.list("/b/proj", &as_bob).await else { panic!("site listing failed") };
+        assert_eq!(proj[0].path, "/b/proj");
+        assert!(proj[0].users.contains_key("bob"));
+        assert!(matches!(fed.list("/nowhere", &vec![]).await, Err(ListError::UnknownSite(_))));
+        assert!(matches!(fed.list("/down/x", &vec![]).await, Err(ListError::Unreachable(_))));
+
+        let parts: Vec<_> = fed
+            .all::<Dashboard>("summary", &vec![])
+            .await
+            .into_iter()
+            .filter_map(|(s, r)| r.ok().map(|d| (s.name.as_str(), d)))
+            .collect();
+        assert_eq!(parts.len(), 2);
+        let sum = merge_summaries(parts, 1);
+        assert_eq!((sum.disk, sum.count, sum.built_at), (400, 2, Some(10)));
+        assert_eq!(sum.ages[0].disk, 400);
+        assert_eq!(sum.top_users[0].disk, 400);
+        assert_eq!(sum.top_folders.len(), 1);
+        assert_eq!(sum.top_folders[0].path, "/b/proj");
+    }
+}
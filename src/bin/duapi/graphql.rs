@@ -0,0 +1,272 @@
+// rs/src/bin/duapi/graphql.rs
+//
+// Optional GraphQL endpoint (`--graphql`) at `POST /api/graphql`, over the
+// same index as /api/folders. Clients select only the fields they need and
+// may ask for several folders in one round trip:
+//
+//   { a: folders(path: "/data") { path total { size } }
+//     b: folders(path: "/home", age: 2) { path users { user total { size } } } }
+//
+// Auth: the JWT `Claims` are handed to the resolvers as context data; as in
+// /api/folders, non-admins only ever see their own rows.
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Error, Object, Request, Schema, SimpleObject,
+};
+use axum::{response::IntoResponse, Json};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use dutopia::auth::Claims;
+use dutopia::db::{self, Age, FolderOut};
+
+use crate::query::normalize_path;
+use crate::{get_db, get_user_info, get_users, is_case_insensitive};
+
+pub type DuSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+const MAX_DEPTH: usize = 8;
+const MAX_COMPLEXITY: usize = 2000;
+
+static SCHEMA: OnceLock<DuSchema> = OnceLock::new();
+
+pub fn schema() -> &'static DuSchema {
+    SCHEMA.get_or_init(|| {
+        Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+            .limit_depth(MAX_DEPTH)
+            .limit_complexity(MAX_COMPLEXITY)
+            .finish()
+    })
+}
+
+/// POST /api/graphql
+pub async fn handler(claims: Claims, Json(req): Json<Request>) -> impl IntoResponse {
+    let user = claims.sub.clone();
+    let resp = schema().execute(req.data(claims)).await;
+    if resp.is_ok() {
+        tracing::info!(%user, "200 OK /api/graphql");
+    } else {
+        tracing::warn!(%user, errors = ?resp.errors, "200 /api/graphql with errors");
+    }
+    Json(resp)
+}
+
+/// Totals of a set of rows.
+#[derive(SimpleObject, Default, Clone)]
+pub struct Usage {
+    /// Files
+    count: u64,
+    /// Apparent size in bytes
+    size: u64,
+    /// Allocated bytes
+    disk: u64,
+    /// Bytes of hard links counted elsewhere
+    linked: u64,
+    /// Latest access time (epoch seconds)
+    atime: i64,
+    /// Latest modification time (epoch seconds)
+    mtime: i64,
+    /// Earliest access time (epoch seconds), 0 when unknown
+    oldest_atime: i64,
+    /// Earliest modification time (epoch seconds), 0 when unknown
+    oldest_mtime: i64,
+}
+
+impl Usage {
+    fn add(&mut self, a: &Age) {
+        self.count += a.count;
+        self.size += a.size;
+        self.disk += a.disk;
+        self.linked += a.linked;
+        self.atime = self.atime.max(a.atime);
+        self.mtime = self.mtime.max(a.mtime);
+        self.oldest_atime = db::earliest(self.oldest_atime, a.oldest_atime);
+        self.oldest_mtime = db::earliest(self.oldest_mtime, a.oldest_mtime);
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct AgeUsage {
+    /// Age bucket: 0 recent, 1 not too old, 2 old
+    age: u8,
+    usage: Usage,
+}
+
+pub struct UserUsage {
+    user: String,
+    ages: HashMap<String, Age>,
+}
+
+#[Object]
+impl UserUsage {
+    async fn user(&self) -> &str {
+        &self.user
+    }
+
+    /// "Full Name (Department)" from --users-file
+    async fn display(&self) -> Option<String> {
+        get_user_info()?.get(&self.user).map(|i| i.display.clone())
+    }
+
+    /// One entry per age bucket present, oldest bucket last.
+    async fn ages(&self) -> Vec<AgeUsage> {
+        let mut out: Vec<AgeUsage> = self
+            .ages
+            .iter()
+            .filter_map(|(k, a)| {
+                let mut usage = Usage::default();
+                usage.add(a);
+                Some(AgeUsage { age: k.parse().ok()?, usage })
+            })
+            .collect();
+        out.sort_by_key(|a| a.age);
+        out
+    }
+
+    /// All age buckets summed.
+    async fn total(&self) -> Usage {
+        let mut u = Usage::default();
+        self.ages.values().for_each(|a| u.add(a));
+        u
+    }
+}
+
+pub struct Folder(FolderOut);
+
+#[Object]
+impl Folder {
+    async fn path(&self) -> &str {
+        &self.0.path
+    }
+
+    /// Per-user breakdown, sorted by user.
+    async fn users(&self) -> Vec<UserUsage> {
+        let mut out: Vec<UserUsage> = self
+            .0
+            .users
+            .iter()
+            .map(|(user, ages)| UserUsage {
+                user: user.clone(),
+                ages: ages.clone(),
+            })
+            .collect();
+        out.sort_by(|a, b| a.user.cmp(&b.user));
+        out
+    }
+
+    /// All users and age buckets summed.
+    async fn total(&self) -> Usage {
+        let mut u = Usage::default();
+        self.0.users.values().flat_map(|a| a.values()).for_each(|a| u.add(a));
+        u
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// Users the caller may query: everyone for admins, else themselves.
+    async fn users(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<String>> {
+        let claims = ctx.data::<Claims>()?;
+        Ok(if claims.is_admin {
+            get_users()
+        } else {
+            vec![claims.sub.clone()]
+        })
+    }
+
+    /// Children of `path` ("" or omitted: the top-level roots), like
+    /// /api/folders. `users` restricts to those owners (non-admins: only
+    /// themselves); `age` to one bucket.
+    async fn folders(
+        &self,
+        ctx: &Context<'_>,
+        path: Option<String>,
+        users: Option<Vec<String>>,
+        age: Option<u8>,
+    ) -> async_graphql::Result<Vec<Folder>> {
+        let claims = ctx.data::<Claims>()?;
+        let users = users.unwrap_or_default();
+        let users = if claims.is_admin {
+            users
+        } else if users.is_empty() || users == [claims.sub.as_str()] {
+            vec![claims.sub.clone()]
+        } else {
+            tracing::warn!(user = %claims.sub, requested = ?users, "403 Forbidden /api/graphql folders");
+            return Err(Error::new("forbidden: non-admins may only query their own user"));
+        };
+        let case_insensitive = is_case_insensitive();
+        let raw = path.unwrap_or_default();
+        let path = match normalize_path(&raw) {
+            Some(p) if case_insensitive => dutopia::query::nfc(&p).into_owned(),
+            Some(p) => p,
+            None => return Err(Error::new(format!("invalid path {raw:?}"))),
+        };
+        let pool = get_db();
+        let opts = db::ListOptions {
+            case_insensitive,
+            by_device: false,
+            hidden: crate::hidden_for(claims),
+        };
+        let mut v = tokio::task::spawn_blocking(move || {
+            db::list_children_with(&pool, &path, &users, age, &opts)
+        })
+        .await
+        .map_err(|e| Error::new(format!("task error: {e}")))?
+        .map_err(|e| Error::new(format!("list_children error: {e}")))?;
+        v.truncate(crate::query::max_page_size());
+        Ok(v.into_iter().map(Folder).collect())
+    }
+
+    /// `built_at` stamp of the served DB.
+    async fn built_at(&self) -> Option<String> {
+        crate::dataset::current().map(|d| d.built_at.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::{self, Dataset};
+    use crate::test_claims as claims;
+    use crate::TEST_DB;
+    use serial_test::serial;
+
+    fn init_db_once() {
+        if dataset::current().is_some() {
+            return;
+        }
+        let temp_db = dutopia::db::test_support::build_test_db();
+        let ds = Dataset::open(&temp_db.path, None).expect("open dataset");
+        dataset::configure(temp_db.path.clone(), None);
+        let _ = TEST_DB.set(temp_db);
+        dataset::install(ds);
+    }
+
+    async fn run(query: &str, c: Claims) -> serde_json::Value {
+        let resp = schema().execute(Request::new(query).data(c)).await;
+        serde_json::to_value(resp).unwrap()
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn folders_select_fields_and_respect_ownership() {
+        init_db_once();
+        let q = r#"{ folders(path: "") { path total { count size } users { user ages { age } } } }"#;
+        let v = run(q, claims("root", true)).await;
+        let root = &v["data"]["folders"][0];
+        assert_eq!(root["path"], "/");
+        assert_eq!(root["total"]["size"], 250);
+        assert_eq!(root["users"][1]["user"], "bob");
+        assert_eq!(root["users"][1]["ages"][0]["age"], 1);
+
+        let v = run(q, claims("alice", false)).await;
+        assert_eq!(v["data"]["folders"][0]["total"]["size"], 200);
+
+        let v = run(r#"{ folders(path: "/", users: ["bob"]) { path } }"#, claims("alice", false)).await;
+        assert!(v["errors"][0]["message"].as_str().unwrap().starts_with("forbidden"));
+
+        let v = run("{ users }", claims("alice", false)).await;
+        assert_eq!(v["data"]["users"], serde_json::json!(["alice"]));
+    }
+}
@@ -1,34 +1,149 @@
 // rs/src/bin/duapi/handler.rs
 use axum::{
     extract::Query,
-    http::StatusCode,
+    http::{HeaderMap, HeaderName, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use jsonwebtoken::{encode, Header};
 use std::collections::HashSet;
+use std::sync::Arc;
 use std::time::SystemTime;
 
-use dutopia::auth::{keys, AuthBody, AuthError, AuthPayload, Claims};
+use dutopia::auth::{issue_token, AuthBody, AuthError, AuthPayload, Claims};
 
+use dutopia::dashboard::dashboard;
 use dutopia::db;
-use dutopia::item::get_items;
+use dutopia::fileindex::FilesPage;
+use dutopia::item::{get_items, page_items, FsItemOut, SortKey};
 use crate::email;
-use crate::query::{parse_users_csv, FilesQuery, FolderQuery};
-use crate::{get_db, get_users};
+use crate::locale::{with_formatted, Locale};
+use crate::query::{parse_users_csv, FilesQuery, FolderQuery, StatsQuery, SummaryQuery, UsersQuery};
+use crate::{
+    get_db, get_file_index, get_owners, get_projects, get_tiers, get_user_info, get_users,
+    is_case_insensitive,
+};
+
+/// Files matching `/api/files` before paging.
+const TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
 
 /// GET /api/health
 ///
 /// `smtp_configured` tells the frontend whether the Notify button in the
 /// cleanup panel should be enabled — without this probe the button would
-/// always render and then fail on click with a 501.
+/// always render and then fail on click with a 501. `dataset_loaded` is
+/// false while duapi waits for its DB file; data routes answer 503 until
+/// then.
 pub async fn health_handler() -> impl IntoResponse {
+    let dataset = crate::dataset::current();
     Json(serde_json::json!({
         "status": "ok",
         "smtp_configured": email::is_configured(),
+        "subscriptions_enabled": crate::subscriptions::is_configured(),
+        "dataset_loaded": dataset.is_some(),
+        "built_at": dataset.as_ref().map(|d| d.built_at.as_str()),
     }))
 }
 
+/// GET /api/stats
+///
+/// Load-quality counters dudb recorded for the served DB (malformed,
+/// incomplete and duplicate rows, paths, users, max depth) plus its size, so
+/// a load that silently dropped rows is visible without opening the DB.
+/// `source_csv`, a server-side path, is only shown to admins.
+pub async fn stats_handler(
+    claims: Claims,
+    headers: HeaderMap,
+    Query(q): Query<StatsQuery>,
+) -> impl IntoResponse {
+    let locale = Locale::resolve(q.locale.as_deref(), &headers);
+    let pool = get_db();
+    match tokio::task::spawn_blocking(move || db::index_stats(&pool)).await {
+        Ok(Ok(mut stats)) => {
+            tracing::info!("200 OK /api/stats");
+            if !claims.is_admin {
+                stats.source_csv = None;
+            }
+            let formatted = locale.map(|l| l.stats(&stats));
+            Json(with_formatted(serde_json::json!(stats), formatted)).into_response()
+        }
+        Ok(Err(e)) => {
+            tracing::error!(err = %e, "500 index_stats ERROR /api/stats");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("index_stats error: {e}"),
+            )
+                .into_response()
+        }
+        Err(join_err) => {
+            tracing::error!(err = %join_err, "500 Task Join Error /api/stats");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("task error: {join_err}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// GET /api/summary?top=10&as_user=alice
+///
+/// Whole-dataset totals, age distribution, top users and top folders plus
+/// the DB build time in one response, for landing pages and Grafana JSON
+/// datasources. Non-admins get the same numbers for their own files only;
+/// trees `--acl-file` hides from the caller count nowhere.
+/// With `--tiers-file`, `tiers` sums the usage per storage tier.
+/// `?locale=` or `Accept-Language` adds display strings (see `locale.rs`).
+pub async fn summary_handler(
+    claims: Claims,
+    headers: HeaderMap,
+    Query(q): Query<SummaryQuery>,
+) -> Response {
+    let locale = Locale::resolve(q.locale.as_deref(), &headers);
+    let claims = match impersonate(claims, q.as_user.as_deref(), "/api/summary") {
+        Ok(c) => c,
+        Err(e) => return e.into_response(),
+    };
+    let admin = claims.is_admin;
+    let hidden = crate::hidden_for(&claims);
+    let users = if admin { Vec::new() } else { vec![claims.sub] };
+    let top = q.top.unwrap_or(10).clamp(1, 100);
+    let pool = get_db();
+    let case_insensitive = is_case_insensitive();
+    let summary = move || -> anyhow::Result<_> {
+        let mut summary = dashboard(&pool, &users, top, &hidden, case_insensitive)?;
+        if !admin {
+            summary.source = None;
+        }
+        if let Some(tiers) = get_tiers() {
+            summary.tiers = tiers.totals(&pool, users.first().map(String::as_str), case_insensitive)?;
+        }
+        Ok(summary)
+    };
+    match tokio::task::spawn_blocking(summary).await {
+        Ok(Ok(summary)) => {
+            tracing::info!(count = summary.count, "200 OK /api/summary");
+            let formatted = locale.map(|l| l.dashboard(&summary));
+            Json(with_formatted(serde_json::json!(summary), formatted)).into_response()
+        }
+        Ok(Err(e)) => {
+            tracing::error!(err = %e, "500 dashboard ERROR /api/summary");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("summary error: {e}"),
+            )
+                .into_response()
+        }
+        Err(join_err) => {
+            tracing::error!(err = %join_err, "500 Task Join Error /api/summary");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("task error: {join_err}"),
+            )
+                .into_response()
+        }
+    }
+}
+
 /// POST /api/login
 pub async fn login_handler(Json(payload): Json<AuthPayload>) -> Result<Json<AuthBody>, AuthError> {
     if payload.username.is_empty() || payload.password.is_empty() {
@@ -60,38 +175,103 @@ pub async fn login_handler(Json(payload): Json<AuthPayload>) -> Result<Json<Auth
     let is_admin =
         verified.admin_override || admins.contains(&payload.username.trim().to_ascii_lowercase());
 
-    let claims = Claims {
-        sub: payload.username.to_owned(),
-        is_admin,
-        exp,
-    };
+    let claims = crate::anonymize::claims(Claims::new(payload.username.to_owned(), is_admin, exp));
     tracing::info!(user = %claims.sub, is_admin = claims.is_admin, "login success");
 
-    let token = encode(&Header::default(), &claims, &keys().encoding)
-        .map_err(|_| AuthError::TokenCreation)?;
+    let token = issue_token(&claims)?;
 
     Ok(Json(AuthBody::new(token)))
 }
 
-/// GET /api/users
-pub async fn users_handler(claims: Claims) -> Response {
-    if claims.is_admin {
+/// `?as_user=NAME`: an admin acting as NAME sees exactly what NAME would,
+/// i.e. the claims become NAME's without admin rights or the admin's
+/// groups. Every use is logged on the `audit` target; non-admins get 403.
+pub fn impersonate(claims: Claims, as_user: Option<&str>, route: &str) -> Result<Claims, AuthError> {
+    let Some(as_user) = as_user.map(str::trim).filter(|u| !u.is_empty()) else {
+        return Ok(claims);
+    };
+    if !claims.is_admin {
+        tracing::warn!(
+            target: "audit",
+            user = %claims.sub,
+            as_user = %as_user,
+            route,
+            "403 Forbidden impersonation by non-admin"
+        );
+        return Err(AuthError::Forbidden);
+    }
+    tracing::info!(
+        target: "audit",
+        admin = %claims.sub,
+        as_user = %as_user,
+        route,
+        "impersonation"
+    );
+    Ok(Claims {
+        sub: as_user.to_string(),
+        is_admin: false,
+        groups: Vec::new(),
+        ..claims
+    })
+}
+
+/// GET /api/users[?details=true]
+///
+/// A list of usernames; with `details=true`, objects carrying the
+/// `--users-file` display metadata of each user (just `user` when unknown).
+pub async fn users_handler(claims: Claims, Query(q): Query<UsersQuery>) -> Response {
+    let claims = match impersonate(claims, q.as_user.as_deref(), "/api/users") {
+        Ok(c) => c,
+        Err(e) => return e.into_response(),
+    };
+    let users: Vec<String> = if claims.is_admin {
         let users = get_users();
         tracing::info!(count = users.len(), "200 OK /api/users");
-        Json(users).into_response()
+        users
     } else {
         tracing::info!(user = %claims.sub, "200 OK /api/users (self)");
-        Json([claims.sub]).into_response()
+        vec![claims.sub]
+    };
+    if !q.details.unwrap_or(false) {
+        return Json(users).into_response();
     }
+    let dir = get_user_info();
+    let details: Vec<UserOut> = users
+        .into_iter()
+        .map(|user| {
+            let info = dir.and_then(|d| d.get(&user)).cloned();
+            UserOut { user, info }
+        })
+        .collect();
+    Json(details).into_response()
 }
 
-/// GET /api/folders?path=/some/dir&users=alice,bob&age=1
+#[derive(serde::Serialize)]
+struct UserOut {
+    user: String,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    info: Option<dutopia::userinfo::UserInfo>,
+}
+
+/// GET /api/folders?path=/some/dir&users=alice,bob&age=1&by_device=true&as_user=alice
+///
+/// `compact=true` returns the folders as arrays with a schema (see
+/// `compact.rs`); it does not apply to `group_by=project`.
+///
+/// `group_by=project` returns usage per project (see `--project-rules`) for
+/// the project roots within `path` instead of per child folder.
 pub async fn get_folders_handler(
     claims: Claims,
     Query(q): Query<FolderQuery>,
 ) -> impl IntoResponse {
+    let claims = match impersonate(claims, q.as_user.as_deref(), "/api/folders") {
+        Ok(c) => c,
+        Err(e) => return e.into_response(),
+    };
     let raw_path = q.path.unwrap_or_default();
+    let case_insensitive = is_case_insensitive();
     let path = match crate::query::normalize_path(&raw_path) {
+        Some(p) if case_insensitive => dutopia::query::nfc(&p).into_owned(),
         Some(p) => p,
         None => {
             tracing::warn!(input = %raw_path, "400 Bad Request /api/folders rejected path");
@@ -104,22 +284,51 @@ pub async fn get_folders_handler(
         _ => Vec::new(),
     };
 
-    if !claims.is_admin {
-        if requested.is_empty() || requested.len() != 1 || requested[0] != claims.sub {
-            tracing::warn!(
-                path = %path,
-                requested_users = ?requested,
-                "403 Forbidden /api/folders"
-            );
-            return AuthError::Forbidden.into_response();
+    if !claims.is_admin
+        && (requested.is_empty() || requested.len() != 1 || requested[0] != claims.sub)
+    {
+        tracing::warn!(
+            path = %path,
+            requested_users = ?requested,
+            "403 Forbidden /api/folders"
+        );
+        return AuthError::Forbidden.into_response();
+    }
+    if let Err(e) = crate::ensure_visible(&path, &claims) {
+        tracing::warn!(path = %path, user = %claims.sub, "403 Forbidden /api/folders hidden");
+        return e.into_response();
+    }
+    let hidden = crate::hidden_for(&claims);
+
+    match q.group_by.as_deref().map(str::trim) {
+        None | Some("") | Some("folder") => {}
+        Some("project") => return folders_by_project(path, requested, q.age, hidden).await,
+        Some(other) => {
+            tracing::warn!(group_by = %other, "400 Bad Request /api/folders group_by");
+            return (StatusCode::BAD_REQUEST, "group_by must be 'folder' or 'project'")
+                .into_response();
         }
     }
 
-    let pool = get_db().clone();
+    let cache_path = dutopia::query::canonical_key(&path, case_insensitive);
+    let by_device = q.by_device.unwrap_or(false);
+    let compact = q.compact.unwrap_or(false);
+    let cache_key = crate::cache::key(&cache_path, &requested, q.age, by_device, &hidden);
+    if let Some(hit) = cache_key.as_ref().and_then(crate::cache::get) {
+        tracing::info!(path = %path, items = hit.len(), "200 OK /api/folders (cached)");
+        return folders_json(&hit, compact);
+    }
+
+    let pool = get_db();
     let path_for_task = path.clone();
     let age_filter = q.age;
+    let opts = db::ListOptions {
+        case_insensitive,
+        by_device,
+        hidden,
+    };
     let fut = tokio::task::spawn_blocking(move || {
-        db::list_children(&pool, &path_for_task, &requested, age_filter)
+        db::list_children_with(&pool, &path_for_task, &requested, age_filter, &opts)
     });
 
     let items = match fut.await {
@@ -137,8 +346,23 @@ pub async fn get_folders_handler(
                 tracing::warn!(path = %path, total = v.len(), cap, "/api/folders truncated");
                 v.truncate(cap);
             }
+            if let Some(dir) = get_user_info() {
+                for f in v.iter_mut() {
+                    f.user_info = dir.subset(f.users.keys());
+                }
+            }
+            if let Some(owners) = get_owners() {
+                for f in v.iter_mut() {
+                    f.owner = owners.lookup(&f.path, case_insensitive).cloned();
+                }
+            }
+            if let Some(tiers) = get_tiers() {
+                for f in v.iter_mut() {
+                    f.tier = tiers.lookup(&f.path, case_insensitive).map(str::to_string);
+                }
+            }
             tracing::info!(path = %path, items = v.len(), "200 OK /api/folders");
-            v
+            Arc::new(v)
         }
         Ok(Err(e)) => {
             tracing::error!(path = %path, err = %e, "500 list_children ERROR /api/folders");
@@ -150,11 +374,74 @@ pub async fn get_folders_handler(
         }
     };
 
-    Json(items).into_response()
+    if let Some(k) = cache_key {
+        crate::cache::put(k, items.clone());
+    }
+    folders_json(&items, compact)
+}
+
+pub fn folders_json(items: &[db::FolderOut], compact: bool) -> Response {
+    if compact {
+        Json(crate::compact::folders(items)).into_response()
+    } else {
+        Json(items).into_response()
+    }
+}
+
+async fn folders_by_project(
+    path: String,
+    requested: Vec<String>,
+    age: Option<u8>,
+    hidden: Vec<String>,
+) -> Response {
+    let Some(roots) = get_projects() else {
+        tracing::warn!("400 Bad Request /api/folders group_by=project without rules");
+        return (
+            StatusCode::BAD_REQUEST,
+            "group_by=project needs duapi --project-rules",
+        )
+            .into_response();
+    };
+    let pool = get_db();
+    let dir = path.clone();
+    let ci = is_case_insensitive();
+    let fut = tokio::task::spawn_blocking(move || {
+        dutopia::project::list_projects(&pool, &roots, &dir, &requested, age, &hidden, ci)
+    });
+    match fut.await {
+        Ok(Ok(v)) => {
+            tracing::info!(path = %path, items = v.len(), "200 OK /api/folders (projects)");
+            Json(v).into_response()
+        }
+        Ok(Err(e)) => {
+            tracing::error!(path = %path, err = %e, "500 list_projects ERROR /api/folders");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("list_projects error: {e}"),
+            )
+                .into_response()
+        }
+        Err(join_err) => {
+            tracing::error!(err = %join_err, "500 Task Join Error /api/folders");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("task error: {join_err}"),
+            )
+                .into_response()
+        }
+    }
 }
 
 /// GET /api/files?path=/some/dir&users=alice,bob&age=1
 pub async fn get_files_handler(claims: Claims, Query(q): Query<FilesQuery>) -> impl IntoResponse {
+    let claims = match impersonate(claims, q.as_user.as_deref(), "/api/files") {
+        Ok(c) => c,
+        Err(e) => return e.into_response(),
+    };
+    if crate::anonymize::active().is_some() {
+        tracing::warn!("404 Not Found /api/files in anonymized mode");
+        return (StatusCode::NOT_FOUND, "file listing is off in anonymized mode").into_response();
+    }
     let folder = match q.path.as_deref() {
         None => {
             tracing::warn!("400 Bad Request /api/files missing 'path'");
@@ -182,20 +469,49 @@ pub async fn get_files_handler(claims: Claims, Query(q): Query<FilesQuery>) -> i
         _ => Vec::new(),
     };
 
-    if !claims.is_admin {
-        if requested.is_empty() || requested.len() != 1 || requested[0] != claims.sub {
-            tracing::warn!(
-                path = %folder,
-                requested_users = ?requested,
-                "403 Forbidden /api/files"
-            );
-            return AuthError::Forbidden.into_response();
-        }
+    if !claims.is_admin
+        && (requested.is_empty() || requested.len() != 1 || requested[0] != claims.sub)
+    {
+        tracing::warn!(
+            path = %folder,
+            requested_users = ?requested,
+            "403 Forbidden /api/files"
+        );
+        return AuthError::Forbidden.into_response();
+    }
+    if let Err(e) = crate::ensure_visible(&folder, &claims) {
+        tracing::warn!(path = %folder, user = %claims.sub, "403 Forbidden /api/files hidden");
+        return e.into_response();
     }
 
-    let age = q.age;
+    let sort = match q.sort.as_deref().map(SortKey::parse) {
+        None => SortKey::Path,
+        Some(Some(k)) => k,
+        Some(None) => {
+            tracing::warn!("400 Bad Request /api/files bad 'sort'");
+            return (StatusCode::BAD_REQUEST, "sort must be path, size or mtime").into_response();
+        }
+    };
+    let desc = match q.order.as_deref().map(str::to_ascii_lowercase).as_deref() {
+        None | Some("asc") => false,
+        Some("desc") => true,
+        Some(_) => {
+            tracing::warn!("400 Bad Request /api/files bad 'order'");
+            return (StatusCode::BAD_REQUEST, "order must be asc or desc").into_response();
+        }
+    };
+    let cap = crate::query::max_page_size();
+    let page = FilesPage {
+        dir: folder,
+        users: requested,
+        age: q.age,
+        sort,
+        desc,
+        offset: q.offset.unwrap_or(0),
+        limit: q.limit.unwrap_or(cap).min(cap),
+    };
 
-    let fut = tokio::task::spawn_blocking(move || get_items(folder, &requested, age));
+    let fut = tokio::task::spawn_blocking(move || list_files(page));
 
     match fut.await {
         Err(join_err) => {
@@ -206,6 +522,14 @@ pub async fn get_files_handler(claims: Claims, Query(q): Query<FilesQuery>) -> i
             )
                 .into_response()
         }
+        Ok(Err(e)) if get_file_index().is_some() => {
+            tracing::error!(err = %e, "500 files index ERROR /api/files");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("files index error: {e}"),
+            )
+                .into_response()
+        }
         Ok(Err(e)) => {
             #[cfg(not(unix))]
             {
@@ -218,18 +542,27 @@ pub async fn get_files_handler(claims: Claims, Query(q): Query<FilesQuery>) -> i
                 (StatusCode::BAD_REQUEST, e.to_string()).into_response()
             }
         }
-        Ok(Ok(mut items)) => {
-            let cap = crate::query::max_page_size();
-            if items.len() > cap {
-                tracing::warn!(total = items.len(), cap, "/api/files truncated");
-                items.truncate(cap);
-            }
-            tracing::info!(items = items.len(), "200 OK /api/files");
-            Json(items).into_response()
+        Ok(Ok((total, items))) => {
+            tracing::info!(items = items.len(), total, "200 OK /api/files");
+            ([(TOTAL_COUNT, total.to_string())], Json(items)).into_response()
         }
     }
 }
 
+/// One sorted page of a folder's files and the number matching before
+/// paging: from the `--files-source` scan index when configured, otherwise
+/// from a live directory listing.
+pub fn list_files(page: FilesPage) -> anyhow::Result<(usize, Vec<FsItemOut>)> {
+    if crate::anonymize::active().is_some() {
+        anyhow::bail!("file listing is off in anonymized mode");
+    }
+    if let Some(index) = get_file_index() {
+        return index.list(&page);
+    }
+    let mut items = get_items(&page.dir, &page.users, page.age)?;
+    let total = page_items(&mut items, page.sort, page.desc, page.offset, page.limit);
+    Ok((total, items))
+}
 
 #[cfg(test)]
 #[path = "handler_tests.rs"]
@@ -2,11 +2,14 @@
 use super::*;
 use axum::body::to_bytes;
 use axum::extract::Query;
+use axum::http::header::ACCEPT_LANGUAGE;
+use axum::http::HeaderValue;
 use serial_test::serial;
 #[cfg(unix)]
 use tempfile::tempdir;
 
-use crate::{DB_POOL, TEST_DB, USERS};
+use crate::dataset::{self, Dataset};
+use crate::{test_claims, OWNERS, TEST_DB, USER_INFO};
 use dutopia::db::FolderOut;
 #[cfg(unix)]
 use dutopia::item::FsItemOut;
@@ -14,29 +17,32 @@ use dutopia::item::FsItemOut;
 const TEST_BODY_LIMIT: usize = 2 * 1024 * 1024;
 
 fn init_db_once() {
-    if DB_POOL.get().is_some() {
+    if dataset::current().is_some() {
         return;
     }
     let temp_db = dutopia::db::test_support::build_test_db();
-    let pool = dutopia::db::open_pool(&temp_db.path).expect("open_pool");
-    let users = dutopia::db::list_users(&pool).expect("list_users");
+    let ds = Dataset::open(&temp_db.path, None).expect("open dataset");
+    dataset::configure(temp_db.path.clone(), None);
     // Keep the TempDb alive for the entire test run so the file is not
     // removed while the pool is still using it.
     let _ = TEST_DB.set(temp_db);
-    let _ = DB_POOL.set(pool);
-    let _ = USERS.set(users);
+    dataset::install(ds);
 }
 
 #[tokio::test]
 #[serial]
 async fn test_users_handler_admin_and_user() {
     init_db_once();
-    let admin = Claims {
-        sub: "root".to_string(),
-        is_admin: true,
-        exp: 9_999_999_999usize,
-    };
-    let resp_admin = users_handler(admin).await.into_response();
+    let admin = test_claims("root", true);
+    let resp_admin = users_handler(
+        admin,
+        Query(UsersQuery {
+            details: None,
+            as_user: None,
+        }),
+    )
+    .await
+    .into_response();
     assert_eq!(resp_admin.status(), StatusCode::OK);
     let body = to_bytes(resp_admin.into_body(), TEST_BODY_LIMIT)
         .await
@@ -45,12 +51,16 @@ async fn test_users_handler_admin_and_user() {
     assert!(list.contains(&"alice".to_string()));
     assert!(list.contains(&"bob".to_string()));
 
-    let user = Claims {
-        sub: "alice".to_string(),
-        is_admin: false,
-        exp: 9_999_999_999usize,
-    };
-    let resp_user = users_handler(user).await.into_response();
+    let user = test_claims("alice", false);
+    let resp_user = users_handler(
+        user,
+        Query(UsersQuery {
+            details: None,
+            as_user: None,
+        }),
+    )
+    .await
+    .into_response();
     let body = to_bytes(resp_user.into_body(), TEST_BODY_LIMIT)
         .await
         .unwrap();
@@ -60,18 +70,95 @@ async fn test_users_handler_admin_and_user() {
 
 #[tokio::test]
 #[serial]
-async fn test_get_folders_handler_authz_and_filters() {
+async fn test_users_handler_details_and_folder_user_info() {
     init_db_once();
+    let _ = USER_INFO.set(
+        dutopia::userinfo::UserDirectory::parse(
+            "user,name,department\nalice,Alice Chen,Genomics\n",
+        )
+        .unwrap(),
+    );
+    let alice = test_claims("alice", false);
+    let resp = users_handler(
+        alice.clone(),
+        Query(UsersQuery {
+            details: Some(true),
+            as_user: None,
+        }),
+    )
+    .await
+    .into_response();
+    let body = to_bytes(resp.into_body(), TEST_BODY_LIMIT).await.unwrap();
+    let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(v[0]["user"], "alice");
+    assert_eq!(v[0]["display"], "Alice Chen (Genomics)");
+
+    let q = FolderQuery {
+        path: Some("/".into()),
+        users: Some("alice".into()),
+        age: None,
+        by_device: None,
+        group_by: None,
+        as_user: None,
+        compact: None,
+    };
+    let resp = get_folders_handler(alice, Query(q)).await.into_response();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), TEST_BODY_LIMIT).await.unwrap();
+    let items: Vec<FolderOut> = serde_json::from_slice(&body).unwrap();
+    let info = items[0].user_info.as_ref().unwrap();
+    assert_eq!(info["alice"].name.as_deref(), Some("Alice Chen"));
+}
 
-    let non_admin = Claims {
-        sub: "alice".into(),
-        is_admin: false,
-        exp: 9_999_999_999usize,
+#[tokio::test]
+#[serial]
+async fn test_get_folders_handler_owner_contacts() {
+    init_db_once();
+    let _ = OWNERS.set(
+        dutopia::owners::OwnerDirectory::parse(
+            "prefix,team,email
+/,Storage,storage@example.org
+/nowhere,Other,other@example.org
+",
+        )
+        .unwrap(),
+    );
+    let q = FolderQuery {
+        path: Some("/".into()),
+        users: None,
+        age: None,
+        by_device: None,
+        group_by: None,
+        as_user: None,
+        compact: None,
     };
+    let admin = test_claims("root", true);
+    let resp = get_folders_handler(admin, Query(q)).await.into_response();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), TEST_BODY_LIMIT).await.unwrap();
+    let items: Vec<FolderOut> = serde_json::from_slice(&body).unwrap();
+    assert!(!items.is_empty());
+    for f in &items {
+        let owner = f.owner.as_ref().unwrap();
+        assert_eq!(owner.prefix, "/");
+        assert_eq!(owner.email.as_deref(), Some("storage@example.org"));
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_get_folders_handler_authz_and_filters() {
+    init_db_once();
+
+    let non_admin = test_claims("alice", false);
     let q_all = FolderQuery {
         path: Some("/".into()),
         users: None,
         age: None,
+        by_device: None,
+        group_by: None,
+        as_user: None,
+        compact: None,
     };
     let resp = get_folders_handler(non_admin.clone(), Query(q_all))
         .await
@@ -82,6 +169,10 @@ async fn test_get_folders_handler_authz_and_filters() {
         path: Some("/".into()),
         users: Some("alice".into()),
         age: None,
+        by_device: None,
+        group_by: None,
+        as_user: None,
+        compact: None,
     };
     let resp = get_folders_handler(non_admin, Query(q_self))
         .await
@@ -91,15 +182,15 @@ async fn test_get_folders_handler_authz_and_filters() {
     let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
     assert!(v.is_array());
 
-    let admin = Claims {
-        sub: "root".into(),
-        is_admin: true,
-        exp: 9_999_999_999usize,
-    };
+    let admin = test_claims("root", true);
     let q_admin_all = FolderQuery {
         path: Some("/".into()),
         users: None,
         age: None,
+        by_device: None,
+        group_by: None,
+        as_user: None,
+        compact: None,
     };
     let resp = get_folders_handler(admin, Query(q_admin_all))
         .await
@@ -113,16 +204,46 @@ async fn test_get_folders_handler_authz_and_filters() {
 }
 
 #[tokio::test]
-async fn test_get_files_handler_bad_path() {
-    let claims = Claims {
-        sub: "any".into(),
-        is_admin: true,
-        exp: 9_999_999_999usize,
+#[serial]
+async fn test_get_folders_handler_compact() {
+    init_db_once();
+    let admin = test_claims("root", true);
+    let q = FolderQuery {
+        path: Some("/".into()),
+        users: None,
+        age: None,
+        by_device: None,
+        group_by: None,
+        as_user: None,
+        compact: Some(true),
     };
+    let resp = get_folders_handler(admin, Query(q)).await.into_response();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), TEST_BODY_LIMIT).await.unwrap();
+    let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(v["schema"]["folder"][0], "path");
+    let docs = v["folders"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|f| f[0] == "/docs")
+        .unwrap();
+    let alice = v["users"].as_array().unwrap().iter().position(|u| u == "alice").unwrap();
+    assert!(docs[1].as_array().unwrap().iter().any(|r| r[0] == alice));
+}
+
+#[tokio::test]
+async fn test_get_files_handler_bad_path() {
+    let claims = test_claims("any", true);
     let q = FilesQuery {
         path: None,
         users: None,
         age: None,
+        as_user: None,
+        sort: None,
+        order: None,
+        offset: None,
+        limit: None,
     };
     let resp = get_files_handler(claims, Query(q)).await.into_response();
     assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
@@ -130,31 +251,115 @@ async fn test_get_files_handler_bad_path() {
 
 #[tokio::test]
 async fn test_get_folders_handler_rejects_traversal() {
-    let claims = Claims {
-        sub: "root".into(),
-        is_admin: true,
-        exp: 9_999_999_999usize,
-    };
+    let claims = test_claims("root", true);
     let q = FolderQuery {
         path: Some("/var/../etc/passwd".into()),
         users: None,
         age: None,
+        by_device: None,
+        group_by: None,
+        as_user: None,
+        compact: None,
     };
     let resp = get_folders_handler(claims, Query(q)).await.into_response();
     assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
 }
 
 #[tokio::test]
-async fn test_get_files_handler_rejects_traversal() {
-    let claims = Claims {
-        sub: "root".into(),
-        is_admin: true,
-        exp: 9_999_999_999usize,
+#[serial]
+async fn test_get_folders_handler_group_by_project() {
+    init_db_once();
+    let rules = dutopia::project::ProjectRules::parse("docs = ^/docs$").unwrap();
+    let db_path = &TEST_DB.get().unwrap().path;
+    dataset::install(Dataset::open(db_path, Some(&rules)).unwrap());
+    let admin = test_claims("root", true);
+    let query = |group_by: &str| FolderQuery {
+        path: Some("/".into()),
+        users: None,
+        age: None,
+        by_device: None,
+        group_by: Some(group_by.into()),
+        as_user: None,
+        compact: None,
     };
+
+    let resp = get_folders_handler(admin.clone(), Query(query("project")))
+        .await
+        .into_response();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), TEST_BODY_LIMIT).await.unwrap();
+    let items: Vec<dutopia::project::ProjectOut> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].project, "docs");
+    assert_eq!(items[0].paths, ["/docs"]);
+
+    let resp = get_folders_handler(admin, Query(query("owner")))
+        .await
+        .into_response();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    dataset::install(Dataset::open(db_path, None).unwrap());
+}
+
+#[tokio::test]
+async fn test_named_dataset_delete_and_reload_admin_only() {
+    let db = dutopia::db::test_support::build_test_db();
+    let claims = |is_admin| test_claims("root", is_admin);
+    let name = || axum::extract::Path("dm-test".to_string());
+    let first = dataset::load_named("dm-test", &db.path).unwrap();
+
+    let resp = dataset::reload_named_handler(claims(false), name()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let resp = dataset::reload_named_handler(claims(true), name()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let reloaded = dataset::named("dm-test").unwrap();
+    assert!(!Arc::ptr_eq(&first, &reloaded));
+    assert_eq!(reloaded.path, db.path);
+
+    let resp = dataset::delete_handler(claims(false), name()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let resp = dataset::delete_handler(claims(true), name()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), TEST_BODY_LIMIT).await.unwrap();
+    let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(v["name"], "dm-test");
+    assert!(dataset::named("dm-test").is_none());
+    assert!(db.path.exists());
+
+    let resp = dataset::delete_handler(claims(true), name()).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    let resp = dataset::reload_named_handler(claims(true), name()).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[serial]
+async fn test_reload_handler_admin_only() {
+    init_db_once();
+    let claims = |is_admin| test_claims("root", is_admin);
+    let resp = dataset::reload_handler(claims(false)).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let before = dataset::current().unwrap();
+    let resp = dataset::reload_handler(claims(true)).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), TEST_BODY_LIMIT).await.unwrap();
+    let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(v["users"], before.users.len());
+    assert!(!Arc::ptr_eq(&before, &dataset::current().unwrap()));
+}
+
+#[tokio::test]
+async fn test_get_files_handler_rejects_traversal() {
+    let claims = test_claims("root", true);
     let q = FilesQuery {
         path: Some("/var/../etc/passwd".into()),
         users: None,
         age: None,
+        as_user: None,
+        sort: None,
+        order: None,
+        offset: None,
+        limit: None,
     };
     let resp = get_files_handler(claims, Query(q)).await.into_response();
     assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
@@ -167,15 +372,16 @@ async fn test_get_files_handler_unix_admin_ok() {
     let file_path = dir.path().join("a.txt");
     std::fs::write(&file_path, b"hi").unwrap();
 
-    let claims = Claims {
-        sub: "root".into(),
-        is_admin: true,
-        exp: 9_999_999_999usize,
-    };
+    let claims = test_claims("root", true);
     let q = FilesQuery {
         path: Some(dir.path().to_string_lossy().into()),
         users: None,
         age: None,
+        as_user: None,
+        sort: None,
+        order: None,
+        offset: None,
+        limit: None,
     };
 
     let resp = get_files_handler(claims, Query(q)).await.into_response();
@@ -186,6 +392,41 @@ async fn test_get_files_handler_unix_admin_ok() {
     assert!(items[0].path.ends_with("a.txt"));
 }
 
+#[cfg(unix)]
+#[tokio::test]
+async fn test_get_files_handler_sorts_and_pages() {
+    let dir = tempdir().unwrap();
+    for (name, len) in [("a.txt", 3), ("b.txt", 9), ("c.txt", 1)] {
+        std::fs::write(dir.path().join(name), vec![b'x'; len]).unwrap();
+    }
+    let claims = test_claims("root", true);
+    let query = |sort: &str, offset| FilesQuery {
+        path: Some(dir.path().to_string_lossy().into()),
+        users: None,
+        age: None,
+        as_user: None,
+        sort: Some(sort.into()),
+        order: Some("desc".into()),
+        offset: Some(offset),
+        limit: Some(1),
+    };
+
+    let resp = get_files_handler(claims.clone(), Query(query("size", 1)))
+        .await
+        .into_response();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()["x-total-count"], "3");
+    let body = to_bytes(resp.into_body(), TEST_BODY_LIMIT).await.unwrap();
+    let items: Vec<FsItemOut> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(items.len(), 1);
+    assert!(items[0].path.ends_with("a.txt"));
+
+    let resp = get_files_handler(claims, Query(query("owner", 0)))
+        .await
+        .into_response();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
 #[cfg(unix)]
 #[tokio::test]
 async fn test_get_files_handler_unix_non_admin_forbidden() {
@@ -193,15 +434,16 @@ async fn test_get_files_handler_unix_non_admin_forbidden() {
     let file_path = dir.path().join("b.txt");
     std::fs::write(&file_path, b"hi").unwrap();
 
-    let claims = Claims {
-        sub: "alice".into(),
-        is_admin: false,
-        exp: 9_999_999_999usize,
-    };
+    let claims = test_claims("alice", false);
     let q = FilesQuery {
         path: Some(dir.path().to_string_lossy().into()),
         users: None,
         age: None,
+        as_user: None,
+        sort: None,
+        order: None,
+        offset: None,
+        limit: None,
     };
     let resp = get_files_handler(claims, Query(q)).await.into_response();
     assert_eq!(resp.status(), StatusCode::FORBIDDEN);
@@ -228,13 +470,12 @@ async fn test_login_missing_credentials() {
 #[serial]
 async fn test_list_children_filters_and_ages() {
     init_db_once();
-    let pool = DB_POOL.get().unwrap();
+    let pool = &get_db();
 
     let items = dutopia::db::list_children(pool, "/", &[], None).unwrap();
     assert!(items.iter().any(|it| it.path == "/docs"));
 
-    let items_alice =
-        dutopia::db::list_children(pool, "/", &["alice".to_string()], None).unwrap();
+    let items_alice = dutopia::db::list_children(pool, "/", &["alice".to_string()], None).unwrap();
     assert!(items_alice.iter().any(|it| it.path == "/docs"));
     let docs = items_alice
         .into_iter()
@@ -261,21 +502,96 @@ async fn test_health_handler() {
     assert!(v["smtp_configured"].is_boolean());
 }
 
+#[tokio::test]
+#[serial]
+async fn test_stats_handler_reports_db_size() {
+    init_db_once();
+    let user = test_claims("alice", false);
+    let admin = test_claims("alice", true);
+    let resp = stats_handler(user, HeaderMap::new(), Query(StatsQuery { locale: None }))
+        .await
+        .into_response();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), TEST_BODY_LIMIT).await.unwrap();
+    let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert!(v["db_bytes"].as_u64().unwrap() > 0);
+    assert!(v["rows_malformed"].is_null());
+    assert!(v.get("formatted").is_none());
+    assert!(v.get("source_csv").is_none());
+
+    let resp = stats_handler(admin, HeaderMap::new(), Query(StatsQuery { locale: None }))
+        .await
+        .into_response();
+    let body = to_bytes(resp.into_body(), TEST_BODY_LIMIT).await.unwrap();
+    let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(v["source_csv"], "/data/fs.sum.csv");
+}
+
+#[tokio::test]
+#[serial]
+async fn test_summary_handler_scopes_non_admin() {
+    init_db_once();
+    let claims = |sub: &str, is_admin| test_claims(sub, is_admin);
+    let q = || {
+        Query(SummaryQuery {
+            top: None,
+            as_user: None,
+            locale: None,
+        })
+    };
+    let resp = summary_handler(claims("root", true), HeaderMap::new(), q()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = to_bytes(resp.into_body(), TEST_BODY_LIMIT).await.unwrap();
+    let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(v["count"], 3);
+    assert_eq!(v["top_users"].as_array().unwrap().len(), 2);
+
+    let resp = summary_handler(claims("bob", false), HeaderMap::new(), q()).await;
+    let body = to_bytes(resp.into_body(), TEST_BODY_LIMIT).await.unwrap();
+    let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(v["count"], 1);
+    assert_eq!(v["top_users"][0]["user"], "bob");
+    assert!(v.get("formatted").is_none());
+}
+
+#[tokio::test]
+#[serial]
+async fn test_summary_handler_formats_for_accept_language() {
+    init_db_once();
+    let admin = test_claims("root", true);
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("de-DE,de;q=0.9,en;q=0.5"));
+    let q = Query(SummaryQuery {
+        top: None,
+        as_user: None,
+        locale: None,
+    });
+    let resp = summary_handler(admin, headers, q).await;
+    let body = to_bytes(resp.into_body(), TEST_BODY_LIMIT).await.unwrap();
+    let v: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(v["count"], 3);
+    let f = &v["formatted"];
+    assert_eq!(f["locale"], "de-DE");
+    assert_eq!(f["count"], "3");
+    assert_eq!(f["top_users"][0]["user"], v["top_users"][0]["user"]);
+    assert!(f["size"].as_str().unwrap().ends_with('B'));
+}
+
 #[tokio::test]
 #[serial]
 async fn test_get_folders_handler_clamps_to_max_page_size() {
     init_db_once();
     // SAFETY: we set then restore for test isolation.
     unsafe { std::env::set_var("MAX_PAGE_SIZE", "1") };
-    let admin = Claims {
-        sub: "root".into(),
-        is_admin: true,
-        exp: 9_999_999_999usize,
-    };
+    let admin = test_claims("root", true);
     let q = FolderQuery {
         path: Some("/".into()),
         users: None,
         age: None,
+        by_device: None,
+        group_by: None,
+        as_user: None,
+        compact: None,
     };
     let resp = get_folders_handler(admin, Query(q)).await.into_response();
     assert_eq!(resp.status(), StatusCode::OK);
@@ -284,3 +600,45 @@ async fn test_get_folders_handler_clamps_to_max_page_size() {
     assert!(arr.len() <= 1);
     unsafe { std::env::remove_var("MAX_PAGE_SIZE") };
 }
+
+#[tokio::test]
+#[serial]
+async fn test_hidden_paths_are_refused_even_to_admins() {
+    init_db_once();
+    // The ACL is process-wide: it hides only a tree no other test uses.
+    let acl = dutopia::acl::AccessList::parse("prefix,allow\n/acl-test,carol\n").unwrap();
+    let _ = crate::ACL.set(acl);
+    let folders = |path: &str, users: &str| FolderQuery {
+        path: Some(path.into()),
+        users: Some(users.into()),
+        age: None,
+        by_device: None,
+        group_by: None,
+        as_user: None,
+        compact: None,
+    };
+    let files = FilesQuery {
+        path: Some("/acl-test/sub".into()),
+        users: None,
+        age: None,
+        as_user: None,
+        sort: None,
+        order: None,
+        offset: None,
+        limit: None,
+    };
+
+    let root = test_claims("root", true);
+    let resp = get_folders_handler(root.clone(), Query(folders("/acl-test/sub", "")))
+        .await
+        .into_response();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let resp = get_files_handler(root, Query(files)).await.into_response();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let carol = test_claims("carol", false);
+    let resp = get_folders_handler(carol, Query(folders("/acl-test", "carol")))
+        .await
+        .into_response();
+    assert_eq!(resp.status(), StatusCode::OK);
+}
@@ -0,0 +1,536 @@
+// rs/src/bin/duapi/jobs.rs
+//
+// Background jobs for exports too large to answer inside one request.
+//
+// POST /api/jobs queues a job and returns at once with its id; the job runs
+// on a small worker pool (JOB_WORKERS at a time), so a full-subtree export
+// neither holds a request open for minutes nor trips a proxy timeout.
+// GET /api/jobs/{id} reports its state and progress, and once it is done
+// GET /api/jobs/{id}/result downloads the CSV it wrote. Kinds:
+//
+//   folders  every folder below `path`, one row per (folder, user,
+//            age), walked breadth first; progress counts folders listed
+//            against folders found so far
+//   files    every file directly in `path`, with no page cap
+//
+// Jobs and their results live in memory and temp files only: a restart
+// drops them, and finished jobs are forgotten JOB_TTL_SECS after they end.
+// A job reads the dataset that was loaded when it started, even if a reload
+// swaps it meanwhile. Users filters follow /api/folders: a non-admin must
+// ask for exactly their own files, and sees only their own jobs. Folders
+// `--acl-file` hides from the owner are left out of the export, and an admin
+// sees another user's job only if it hid everything hidden from the admin.
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Path as UrlPath, Request},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tempfile::TempPath;
+use tokio::sync::Semaphore;
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+
+use dutopia::auth::{AuthError, Claims};
+use dutopia::db;
+use dutopia::fileindex::FilesPage;
+use dutopia::item::SortKey;
+
+use crate::dataset::{self, Dataset};
+use crate::query::normalize_path;
+
+const DEFAULT_WORKERS: usize = 2;
+const DEFAULT_TTL_SECS: i64 = 3600;
+const DEFAULT_MAX_JOBS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Folders,
+    Files,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobReq {
+    pub kind: Kind,
+    #[serde(default)]
+    pub path: String,
+    #[serde(default)]
+    pub users: Vec<String>,
+    pub age: Option<u8>,
+}
+
+struct Status {
+    state: State,
+    finished_at: Option<i64>,
+    error: Option<String>,
+    /// Rows written, once done
+    rows: Option<u64>,
+    result: Option<TempPath>,
+}
+
+struct Job {
+    id: u64,
+    owner: String,
+    kind: Kind,
+    path: String,
+    users: Vec<String>,
+    age: Option<u8>,
+    /// `--acl-file` prefixes hidden from the owner.
+    hidden: Vec<String>,
+    created_at: i64,
+    done: AtomicU64,
+    total: AtomicU64,
+    status: Mutex<Status>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobOut {
+    pub id: u64,
+    pub owner: String,
+    pub kind: Kind,
+    pub path: String,
+    pub state: State,
+    pub done: u64,
+    pub total: u64,
+    pub created_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rows: Option<u64>,
+    /// Download link, once done
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_url: Option<String>,
+}
+
+struct Registry {
+    jobs: Mutex<HashMap<u64, Arc<Job>>>,
+    next_id: AtomicU64,
+    slots: Arc<Semaphore>,
+    ttl_secs: i64,
+    max_jobs: usize,
+}
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+/// Worker count, finished-job lifetime and job cap; the defaults apply when
+/// this is never called.
+pub fn configure(workers: usize, ttl_secs: u64, max_jobs: usize) {
+    let _ = REGISTRY.set(Registry::new(workers, ttl_secs as i64, max_jobs));
+}
+
+fn registry() -> &'static Registry {
+    REGISTRY.get_or_init(|| Registry::new(DEFAULT_WORKERS, DEFAULT_TTL_SECS, DEFAULT_MAX_JOBS))
+}
+
+impl Registry {
+    fn new(workers: usize, ttl_secs: i64, max_jobs: usize) -> Self {
+        Self {
+            jobs: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            slots: Arc::new(Semaphore::new(workers.max(1))),
+            ttl_secs,
+            max_jobs: max_jobs.max(1),
+        }
+    }
+
+    /// Forget jobs that finished more than `ttl_secs` ago; their result
+    /// files go with them.
+    fn prune(&self, now: i64) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.retain(|_, j| {
+            let st = j.status.lock().unwrap_or_else(|e| e.into_inner());
+            st.finished_at.is_none_or(|t| now - t < self.ttl_secs)
+        });
+    }
+
+    fn get(&self, id: u64) -> Option<Arc<Job>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner()).get(&id).cloned()
+    }
+}
+
+impl Job {
+    fn out(&self) -> JobOut {
+        let st = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        JobOut {
+            id: self.id,
+            owner: self.owner.clone(),
+            kind: self.kind,
+            path: self.path.clone(),
+            state: st.state,
+            done: self.done.load(Ordering::Relaxed),
+            total: self.total.load(Ordering::Relaxed),
+            created_at: self.created_at,
+            finished_at: st.finished_at,
+            error: st.error.clone(),
+            rows: st.rows,
+            result_url: (st.state == State::Done).then(|| format!("/api/jobs/{}/result", self.id)),
+        }
+    }
+
+    /// The owner, or an admin from whom the job hid at least everything
+    /// `--acl-file` hides from them.
+    fn visible_to(&self, claims: &Claims) -> bool {
+        self.owner == claims.sub
+            || claims.is_admin && crate::hidden_for(claims).iter().all(|h| self.hidden.contains(h))
+    }
+
+    fn finish(&self, res: Result<(u64, TempPath)>) {
+        let mut st = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        st.finished_at = Some(Utc::now().timestamp());
+        match res {
+            Ok((rows, path)) => {
+                st.state = State::Done;
+                st.rows = Some(rows);
+                st.result = Some(path);
+            }
+            Err(e) => {
+                st.state = State::Failed;
+                st.error = Some(format!("{e:#}"));
+            }
+        }
+    }
+}
+
+/// Run `job` against `ds`; returns the rows written and the result file.
+fn run(job: &Job, ds: &Dataset, case_insensitive: bool) -> Result<(u64, TempPath)> {
+    let tmp = tempfile::Builder::new()
+        .prefix(&format!("duapi-job-{}-", job.id))
+        .suffix(".csv")
+        .tempfile()
+        .context("creating result file")?;
+    let (file, path) = tmp.into_parts();
+    let mut w = csv::Writer::from_writer(std::io::BufWriter::new(file));
+    let rows = match job.kind {
+        Kind::Folders => export_folders(job, ds, case_insensitive, &mut w)?,
+        Kind::Files => export_files(job, &mut w)?,
+    };
+    w.into_inner()
+        .map_err(|e| e.into_error())?
+        .flush()
+        .context("writing result file")?;
+    Ok((rows, path))
+}
+
+fn export_folders<W: Write>(
+    job: &Job,
+    ds: &Dataset,
+    case_insensitive: bool,
+    w: &mut csv::Writer<W>,
+) -> Result<u64> {
+    w.write_record([
+        "path", "user", "age", "files", "size", "disk", "linked", "accessed", "modified",
+        "oldest_accessed", "oldest_modified",
+    ])?;
+    let opts = db::ListOptions {
+        case_insensitive,
+        by_device: false,
+        hidden: job.hidden.clone(),
+    };
+    let mut rows = 0u64;
+    let mut queue = VecDeque::from([job.path.clone()]);
+    job.total.store(1, Ordering::Relaxed);
+    while let Some(dir) = queue.pop_front() {
+        for folder in db::list_children_with(&ds.pool, &dir, &job.users, job.age, &opts)? {
+            let mut users: Vec<_> = folder.users.iter().collect();
+            users.sort_by(|a, b| a.0.cmp(b.0));
+            for (user, ages) in users {
+                let mut ages: Vec<_> = ages.iter().collect();
+                ages.sort_by(|a, b| a.0.cmp(b.0));
+                for (age, t) in ages {
+                    w.write_record([
+                        folder.path.as_str(),
+                        user,
+                        age,
+                        &t.count.to_string(),
+                        &t.size.to_string(),
+                        &t.disk.to_string(),
+                        &t.linked.to_string(),
+                        &t.atime.to_string(),
+                        &t.mtime.to_string(),
+                        &t.oldest_atime.to_string(),
+                        &t.oldest_mtime.to_string(),
+                    ])?;
+                    rows += 1;
+                }
+            }
+            queue.push_back(folder.path);
+            job.total.fetch_add(1, Ordering::Relaxed);
+        }
+        job.done.fetch_add(1, Ordering::Relaxed);
+    }
+    Ok(rows)
+}
+
+fn export_files<W: Write>(job: &Job, w: &mut csv::Writer<W>) -> Result<u64> {
+    w.write_record(["path", "owner", "size", "accessed", "modified"])?;
+    job.total.store(1, Ordering::Relaxed);
+    let (_, items) = crate::handler::list_files(FilesPage {
+        dir: job.path.clone(),
+        users: job.users.clone(),
+        age: job.age,
+        sort: SortKey::Path,
+        desc: false,
+        offset: 0,
+        limit: usize::MAX,
+    })?;
+    for it in &items {
+        w.write_record([
+            it.path.as_str(),
+            it.owner.as_str(),
+            &it.size.to_string(),
+            &it.accessed.to_string(),
+            &it.modified.to_string(),
+        ])?;
+    }
+    job.done.store(1, Ordering::Relaxed);
+    Ok(items.len() as u64)
+}
+
+fn not_found(route: &str) -> Response {
+    tracing::warn!("404 Not Found {route}");
+    (StatusCode::NOT_FOUND, "job not found").into_response()
+}
+
+pub async fn create_handler(claims: Claims, Json(req): Json<JobReq>) -> Response {
+    let Some(path) = normalize_path(&req.path) else {
+        tracing::warn!(input = %req.path, "400 Bad Request POST /api/jobs rejected path");
+        return (StatusCode::BAD_REQUEST, "invalid path").into_response();
+    };
+    let case_insensitive = crate::is_case_insensitive();
+    let path = if case_insensitive {
+        dutopia::query::nfc(&path).into_owned()
+    } else {
+        path
+    };
+    if req.kind == Kind::Files {
+        if crate::anonymize::active().is_some() {
+            tracing::warn!("404 Not Found POST /api/jobs files in anonymized mode");
+            return (StatusCode::NOT_FOUND, "file listing is off in anonymized mode").into_response();
+        }
+        if path == "/" || path.is_empty() {
+            tracing::warn!("400 Bad Request POST /api/jobs files without a folder");
+            return (StatusCode::BAD_REQUEST, "files jobs need a folder path").into_response();
+        }
+    }
+    let users: Vec<String> = req
+        .users
+        .iter()
+        .map(|u| u.trim().to_string())
+        .filter(|u| !u.is_empty())
+        .collect();
+    if !claims.is_admin && (users.len() != 1 || users[0] != claims.sub) {
+        tracing::warn!(path = %path, requested_users = ?users, "403 Forbidden POST /api/jobs");
+        return AuthError::Forbidden.into_response();
+    }
+    if let Err(e) = crate::ensure_visible(&path, &claims) {
+        tracing::warn!(path = %path, user = %claims.sub, "403 Forbidden POST /api/jobs hidden");
+        return e.into_response();
+    }
+    let Some(ds) = dataset::current() else {
+        return (StatusCode::SERVICE_UNAVAILABLE, "no dataset loaded").into_response();
+    };
+
+    let reg = registry();
+    let now = Utc::now().timestamp();
+    reg.prune(now);
+    let job = {
+        let mut jobs = reg.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if jobs.len() >= reg.max_jobs {
+            tracing::warn!(jobs = jobs.len(), "429 Too Many Requests POST /api/jobs");
+            return (StatusCode::TOO_MANY_REQUESTS, "too many jobs, try again later").into_response();
+        }
+        let job = Arc::new(Job {
+            id: reg.next_id.fetch_add(1, Ordering::Relaxed),
+            owner: claims.sub.clone(),
+            kind: req.kind,
+            path,
+            users,
+            age: req.age,
+            hidden: crate::hidden_for(&claims),
+            created_at: now,
+            done: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+            status: Mutex::new(Status {
+                state: State::Queued,
+                finished_at: None,
+                error: None,
+                rows: None,
+                result: None,
+            }),
+        });
+        jobs.insert(job.id, job.clone());
+        job
+    };
+
+    let worker = job.clone();
+    let slots = reg.slots.clone();
+    tokio::spawn(async move {
+        let Ok(_permit) = slots.acquire_owned().await else { return };
+        worker.status.lock().unwrap_or_else(|e| e.into_inner()).state = State::Running;
+        let task = worker.clone();
+        let res = tokio::task::spawn_blocking(move || run(&task, &ds, case_insensitive))
+            .await
+            .unwrap_or_else(|e| Err(anyhow::anyhow!("task error: {e}")));
+        match &res {
+            Ok((rows, _)) => tracing::info!(id = worker.id, rows, "job done"),
+            Err(e) => tracing::error!(id = worker.id, err = %format!("{e:#}"), "job failed"),
+        }
+        worker.finish(res);
+    });
+
+    tracing::info!(id = job.id, kind = ?job.kind, path = %job.path, "202 Accepted POST /api/jobs");
+    (StatusCode::ACCEPTED, Json(job.out())).into_response()
+}
+
+pub async fn list_handler(claims: Claims) -> Response {
+    let reg = registry();
+    reg.prune(Utc::now().timestamp());
+    let jobs: Vec<Arc<Job>> = reg
+        .jobs
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .values()
+        .filter(|j| j.visible_to(&claims))
+        .cloned()
+        .collect();
+    let mut out: Vec<JobOut> = jobs.iter().map(|j| j.out()).collect();
+    out.sort_by_key(|j| j.id);
+    tracing::info!(jobs = out.len(), "200 OK GET /api/jobs");
+    Json(out).into_response()
+}
+
+pub async fn get_handler(claims: Claims, UrlPath(id): UrlPath<u64>) -> Response {
+    let reg = registry();
+    reg.prune(Utc::now().timestamp());
+    match reg.get(id) {
+        Some(job) if job.visible_to(&claims) => Json(job.out()).into_response(),
+        _ => not_found("GET /api/jobs/{id}"),
+    }
+}
+
+pub async fn result_handler(claims: Claims, UrlPath(id): UrlPath<u64>, req: Request) -> Response {
+    let Some(job) = registry().get(id).filter(|j| j.visible_to(&claims)) else {
+        return not_found("GET /api/jobs/{id}/result");
+    };
+    let path = {
+        let st = job.status.lock().unwrap_or_else(|e| e.into_inner());
+        match &st.result {
+            Some(p) => p.to_path_buf(),
+            None => {
+                tracing::warn!(id, state = ?st.state, "409 Conflict GET /api/jobs/{{id}}/result");
+                return (StatusCode::CONFLICT, "job has no result yet").into_response();
+            }
+        }
+    };
+    // `job` keeps the temp file alive until the file is open.
+    let mut resp = match ServeFile::new(&path).oneshot(req).await {
+        Ok(r) => r.into_response(),
+        Err(e) => match e {},
+    };
+    drop(job);
+    if let Ok(v) = HeaderValue::from_str(&format!("attachment; filename=\"job-{id}.csv\"")) {
+        resp.headers_mut().insert(header::CONTENT_DISPOSITION, v);
+    }
+    tracing::info!(id, "200 OK GET /api/jobs/{{id}}/result");
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Dataset;
+    use crate::test_claims as claims;
+    use crate::TEST_DB;
+    use axum::body::{to_bytes, Body};
+    use serial_test::serial;
+
+    fn init_db_once() {
+        if dataset::current().is_some() {
+            return;
+        }
+        let temp_db = dutopia::db::test_support::build_test_db();
+        let ds = Dataset::open(&temp_db.path, None).expect("open dataset");
+        dataset::configure(temp_db.path.clone(), None);
+        let _ = TEST_DB.set(temp_db);
+        dataset::install(ds);
+    }
+
+    async fn body_json(resp: Response) -> serde_json::Value {
+        serde_json::from_slice(&to_bytes(resp.into_body(), usize::MAX).await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn folder_export_runs_in_the_background() {
+        init_db_once();
+        let req = JobReq { kind: Kind::Folders, path: "/".into(), users: vec!["alice".into()], age: None };
+        let resp = create_handler(claims("alice", false), Json(req)).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let id = body_json(resp).await["id"].as_u64().unwrap();
+
+        let mut job = serde_json::Value::Null;
+        for _ in 0..200 {
+            job = body_json(get_handler(claims("alice", false), UrlPath(id)).await).await;
+            if job["state"] == "done" || job["state"] == "failed" {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(job["state"], "done", "{job}");
+        assert_eq!(job["done"], job["total"]);
+        assert_eq!(job["result_url"], format!("/api/jobs/{id}/result"));
+
+        // Other users neither see the job nor get its result.
+        let resp = get_handler(claims("bob", false), UrlPath(id)).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let resp = result_handler(claims("bob", false), UrlPath(id), Request::new(Body::empty())).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let resp = result_handler(claims("alice", false), UrlPath(id), Request::new(Body::empty())).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let csv = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        let csv = String::from_utf8(csv.to_vec()).unwrap();
+        let header = "path,user,age,files,size,disk,linked,accessed,modified,\
+                      oldest_accessed,oldest_modified\n";
+        assert!(csv.starts_with(header), "{csv}");
+        assert!(csv.contains("/docs,alice,"), "{csv}");
+        assert!(!csv.contains(",bob,"), "{csv}");
+
+        let listed = body_json(list_handler(claims("root", true)).await).await;
+        assert!(listed.as_array().unwrap().iter().any(|j| j["id"] == id));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn non_admin_must_export_own_files() {
+        init_db_once();
+        let req = JobReq { kind: Kind::Folders, path: "/".into(), users: vec![], age: None };
+        let resp = create_handler(claims("alice", false), Json(req)).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        let req = JobReq { kind: Kind::Folders, path: "../x".into(), users: vec![], age: None };
+        let resp = create_handler(claims("root", true), Json(req)).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let resp = result_handler(claims("root", true), UrlPath(u64::MAX), Request::new(Body::empty())).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,83 @@
+// rs/src/bin/duapi/limit.rs
+//
+// Concurrency cap for the API routes. Requests beyond
+// `MAX_CONCURRENT_REQUESTS` get an immediate 503 with `Retry-After`, so a
+// burst sheds load at the edge instead of queueing behind slow folder
+// queries until every request hits the timeout. The same semaphore tells
+// shutdown how many requests are still in flight.
+use axum::{
+    extract::{Request, State},
+    http::{header::RETRY_AFTER, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+#[derive(Clone)]
+pub struct RequestLimit {
+    permits: Arc<Semaphore>,
+    max: usize,
+}
+
+impl RequestLimit {
+    /// `max == 0` disables the cap (in-flight requests are still counted).
+    pub fn new(max: usize) -> Self {
+        let permits = if max == 0 { Semaphore::MAX_PERMITS } else { max };
+        Self {
+            permits: Arc::new(Semaphore::new(permits)),
+            max: permits,
+        }
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.max - self.permits.available_permits()
+    }
+}
+
+/// `axum::middleware::from_fn_with_state` handler.
+pub async fn limit_requests(
+    State(limit): State<RequestLimit>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Ok(_permit) = limit.permits.clone().try_acquire_owned() else {
+        tracing::warn!(path = %req.uri().path(), "503 Service Unavailable: concurrency limit");
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(RETRY_AFTER, "1")],
+            "server busy, retry shortly",
+        )
+            .into_response();
+    };
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware::from_fn_with_state, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn rejects_beyond_the_limit_and_counts_in_flight() {
+        let limit = RequestLimit::new(1);
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(from_fn_with_state(limit.clone(), limit_requests));
+        let req = || Request::builder().uri("/").body(Body::empty()).unwrap();
+
+        let resp = app.clone().oneshot(req()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let held = limit.permits.clone().try_acquire_owned().unwrap();
+        assert_eq!(limit.in_flight(), 1);
+        let resp = app.clone().oneshot(req()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(resp.headers()[RETRY_AFTER], "1");
+        drop(held);
+        assert_eq!(limit.in_flight(), 0);
+
+        assert_eq!(RequestLimit::new(0).in_flight(), 0);
+    }
+}
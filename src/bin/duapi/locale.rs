@@ -0,0 +1,261 @@
+// rs/src/bin/duapi/locale.rs
+//
+// Preformatted numbers and dates for `/api/summary` and `/api/stats`.
+//
+// The locale comes from `?locale=de-DE`, else from the first supported
+// language in `Accept-Language`. With either hint the response keeps its
+// raw values and gains a `formatted` object holding the same fields as
+// display strings (`"1.234.567"`, `"1,5GB"`, `"16.10.2026 14:03 UTC"`), so
+// the UI shows them as they are instead of carrying formatting tables of
+// its own. Without a hint nothing changes, which keeps scripts and Grafana
+// datasources on the plain payload. Only separators and date order vary by
+// locale: unit names stay as `human_bytes` writes them, and times are UTC.
+// Unknown languages fall back to `en-US`.
+
+use axum::http::{header, HeaderMap};
+use chrono::DateTime;
+use serde_json::{json, Map, Value};
+
+use dutopia::dashboard::Dashboard;
+use dutopia::db::IndexStats;
+use dutopia::util::human_bytes;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DateOrder {
+    Mdy,
+    Dmy,
+    Ymd,
+}
+
+/// (language, region, group separator, decimal mark, date order, date
+/// separator); the entry without a region covers the other regions.
+type Rule = (&'static str, Option<&'static str>, &'static str, char, DateOrder, char);
+
+const RULES: &[Rule] = &[
+    ("en", Some("US"), ",", '.', DateOrder::Mdy, '/'),
+    ("en", None, ",", '.', DateOrder::Dmy, '/'),
+    ("de", Some("CH"), "’", '.', DateOrder::Dmy, '.'),
+    ("de", None, ".", ',', DateOrder::Dmy, '.'),
+    ("nl", None, ".", ',', DateOrder::Dmy, '-'),
+    ("da", None, ".", ',', DateOrder::Dmy, '.'),
+    ("es", None, ".", ',', DateOrder::Dmy, '/'),
+    ("it", None, ".", ',', DateOrder::Dmy, '/'),
+    ("pt", None, ".", ',', DateOrder::Dmy, '/'),
+    ("id", None, ".", ',', DateOrder::Dmy, '/'),
+    ("tr", None, ".", ',', DateOrder::Dmy, '.'),
+    ("fr", None, "\u{202f}", ',', DateOrder::Dmy, '/'),
+    ("ru", None, "\u{a0}", ',', DateOrder::Dmy, '.'),
+    ("uk", None, "\u{a0}", ',', DateOrder::Dmy, '.'),
+    ("pl", None, "\u{a0}", ',', DateOrder::Dmy, '.'),
+    ("cs", None, "\u{a0}", ',', DateOrder::Dmy, '.'),
+    ("fi", None, "\u{a0}", ',', DateOrder::Dmy, '.'),
+    ("nb", None, "\u{a0}", ',', DateOrder::Dmy, '.'),
+    ("sv", None, "\u{a0}", ',', DateOrder::Ymd, '-'),
+    ("ja", None, ",", '.', DateOrder::Ymd, '/'),
+    ("zh", None, ",", '.', DateOrder::Ymd, '/'),
+    ("ko", None, ",", '.', DateOrder::Ymd, '.'),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    /// The tag as asked for, e.g. `de-DE`
+    pub tag: String,
+    group: &'static str,
+    decimal: char,
+    order: DateOrder,
+    date_sep: char,
+}
+
+impl Locale {
+    /// Rules for a BCP 47 tag (`de`, `de-CH`, `pt_BR`); `None` when the
+    /// language is not in the table.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let tag = tag.trim().replace('_', "-");
+        let mut parts = tag.split('-');
+        let lang = parts.next()?.to_ascii_lowercase();
+        let region = parts.find(|p| p.len() == 2).map(str::to_ascii_uppercase);
+        let rule = RULES
+            .iter()
+            .find(|r| r.0 == lang && r.1.is_some() && r.1 == region.as_deref())
+            .or_else(|| RULES.iter().find(|r| r.0 == lang && r.1.is_none()))?;
+        Some(Self {
+            tag,
+            group: rule.2,
+            decimal: rule.3,
+            order: rule.4,
+            date_sep: rule.5,
+        })
+    }
+
+    /// `?locale=` first, then `Accept-Language` by preference; `None` when
+    /// neither names a supported language.
+    pub fn resolve(query: Option<&str>, headers: &HeaderMap) -> Option<Self> {
+        if let Some(l) = query.filter(|q| !q.trim().is_empty()) {
+            return Some(Self::parse(l).unwrap_or_else(Self::fallback));
+        }
+        let accept = headers.get(header::ACCEPT_LANGUAGE)?.to_str().ok()?;
+        accept_languages(accept).iter().find_map(|t| Self::parse(t))
+    }
+
+    fn fallback() -> Self {
+        Self::parse("en-US").expect("en-US is in the table")
+    }
+
+    /// `1234567` -> `1.234.567` (de)
+    pub fn number(&self, n: u64) -> String {
+        let digits = n.to_string();
+        let mut out = String::with_capacity(digits.len() + digits.len() / 3 * self.group.len());
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                out.push_str(self.group);
+            }
+            out.push(c);
+        }
+        out
+    }
+
+    /// `human_bytes` with the local decimal mark: `1,5GB` (de)
+    pub fn bytes(&self, n: u64) -> String {
+        human_bytes(n).replace('.', &self.decimal.to_string())
+    }
+
+    /// Epoch seconds as a UTC date and time in local order.
+    pub fn datetime(&self, epoch: i64) -> Option<String> {
+        let d = DateTime::from_timestamp(epoch, 0)?;
+        let s = self.date_sep;
+        let pattern = match self.order {
+            DateOrder::Mdy => format!("%m{s}%d{s}%Y %H:%M UTC"),
+            DateOrder::Dmy => format!("%d{s}%m{s}%Y %H:%M UTC"),
+            DateOrder::Ymd => format!("%Y{s}%m{s}%d %H:%M UTC"),
+        };
+        Some(d.format(&pattern).to_string())
+    }
+
+    fn totals(&self, count: u64, size: u64, disk: u64) -> Map<String, Value> {
+        let mut m = Map::new();
+        m.insert("count".into(), self.number(count).into());
+        m.insert("size".into(), self.bytes(size).into());
+        m.insert("disk".into(), self.bytes(disk).into());
+        m
+    }
+
+    /// `formatted` object of `/api/summary`.
+    pub fn dashboard(&self, d: &Dashboard) -> Value {
+        let mut m = self.totals(d.count, d.size, d.disk);
+        m.insert("locale".into(), self.tag.clone().into());
+        m.insert("built_at".into(), d.built_at.and_then(|t| self.datetime(t)).into());
+        m.insert("linked".into(), self.bytes(d.linked).into());
+        let ages: Vec<Value> = d
+            .ages
+            .iter()
+            .map(|a| {
+                let mut e = self.totals(a.count, a.size, a.disk);
+                e.insert("age".into(), a.age.into());
+                Value::Object(e)
+            })
+            .collect();
+        m.insert("ages".into(), ages.into());
+        let users: Vec<Value> = d
+            .top_users
+            .iter()
+            .map(|u| {
+                let mut e = self.totals(u.count, u.size, u.disk);
+                e.insert("user".into(), u.user.clone().into());
+                Value::Object(e)
+            })
+            .collect();
+        m.insert("top_users".into(), users.into());
+        let folders: Vec<Value> = d
+            .top_folders
+            .iter()
+            .map(|f| {
+                let mut e = self.totals(f.count, f.size, f.disk);
+                e.insert("path".into(), f.path.clone().into());
+                Value::Object(e)
+            })
+            .collect();
+        m.insert("top_folders".into(), folders.into());
+        Value::Object(m)
+    }
+
+    /// `formatted` object of `/api/stats`.
+    pub fn stats(&self, s: &IndexStats) -> Value {
+        let n = |v: Option<u64>| v.map(|v| self.number(v));
+        json!({
+            "locale": self.tag,
+            "built_at": s.built_at.and_then(|t| self.datetime(t)),
+            "rows": n(s.rows),
+            "rows_malformed": n(s.rows_malformed),
+            "rows_skipped": n(s.rows_skipped),
+            "rows_duplicate": n(s.rows_duplicate),
+            "paths": n(s.paths),
+            "users": n(s.users),
+            "db_bytes": self.bytes(s.db_bytes),
+        })
+    }
+}
+
+/// Language tags of an `Accept-Language` header, most preferred first;
+/// `*` and `q=0` entries are dropped.
+fn accept_languages(header: &str) -> Vec<String> {
+    let mut tags: Vec<(f32, usize, String)> = header
+        .split(',')
+        .enumerate()
+        .filter_map(|(i, part)| {
+            let mut it = part.split(';');
+            let tag = it.next()?.trim();
+            let q = it
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            (!tag.is_empty() && tag != "*" && q > 0.0).then(|| (q, i, tag.to_string()))
+        })
+        .collect();
+    tags.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+    tags.into_iter().map(|t| t.2).collect()
+}
+
+/// `value` with a `formatted` object added when a locale was asked for.
+pub fn with_formatted(mut value: Value, formatted: Option<Value>) -> Value {
+    if let (Some(f), Some(obj)) = (formatted, value.as_object_mut()) {
+        obj.insert("formatted".into(), f);
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn separators_and_date_order_follow_the_locale() {
+        let de = Locale::parse("de-DE").unwrap();
+        assert_eq!(de.number(1_234_567), "1.234.567");
+        assert_eq!(de.bytes(1_610_612_736), "1,5GB");
+        assert_eq!(de.datetime(1_760_623_380).unwrap(), "16.10.2025 14:03 UTC");
+
+        let us = Locale::parse("en_US").unwrap();
+        assert_eq!(us.number(999), "999");
+        assert_eq!(us.number(1000), "1,000");
+        assert_eq!(us.datetime(1_760_623_380).unwrap(), "10/16/2025 14:03 UTC");
+
+        assert_eq!(Locale::parse("de-CH").unwrap().number(12_345), "12’345");
+        assert_eq!(Locale::parse("fr").unwrap().number(12_345), "12\u{202f}345");
+        assert_eq!(Locale::parse("sv-SE").unwrap().datetime(0).unwrap(), "1970-01-01 00:00 UTC");
+        assert!(Locale::parse("xx").is_none());
+    }
+
+    #[test]
+    fn query_wins_over_accept_language() {
+        let mut headers = HeaderMap::new();
+        assert!(Locale::resolve(None, &headers).is_none());
+        headers.insert(
+            header::ACCEPT_LANGUAGE,
+            HeaderValue::from_static("xx;q=1, fr-CA;q=0.5, de;q=0.8, *;q=0.1"),
+        );
+        assert_eq!(Locale::resolve(None, &headers).unwrap().tag, "de");
+        assert_eq!(Locale::resolve(Some("ja"), &headers).unwrap().tag, "ja");
+        // An unknown explicit locale still gets formatting, in en-US.
+        assert_eq!(Locale::resolve(Some("xx"), &headers).unwrap().tag, "en-US");
+    }
+}
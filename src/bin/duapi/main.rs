@@ -1,9 +1,10 @@
 // rs/src/bin/duapi/main.rs
 use anyhow::{Context, Result};
 use axum::{
-    http::{Method, StatusCode},
+    http::{HeaderName, Method, StatusCode},
+    middleware,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post},
     Json, Router,
 };
 use axum_server::tls_rustls::RustlsConfig;
@@ -11,30 +12,62 @@ use clap::{ColorChoice, Parser};
 use colored::Colorize;
 use std::net::{SocketAddr, TcpStream};
 use std::path::PathBuf;
-use std::sync::OnceLock;
+use std::future::IntoFuture;
+use std::sync::{Arc, OnceLock};
 use std::time::Duration;
+use tokio::sync::Notify;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::limit::RequestBodyLimitLayer;
 use tower_http::services::{ServeDir, ServeFile};
 use tower_http::timeout::TimeoutLayer;
 
+use dutopia::acl::AccessList;
+use dutopia::auth::{AuthError, Claims};
 use dutopia::db;
+use dutopia::fileindex::FileIndex;
+use dutopia::project::{ProjectRoot, ProjectRules};
+use dutopia::owners::OwnerDirectory;
+use dutopia::tiers::TierMap;
+use dutopia::userinfo::UserDirectory;
 use dutopia::util::logging::init_tracing;
-use dutopia::util::print_about;
+use dutopia::util::{parse_bytes, parse_duration, print_about};
 
+mod age;
+mod agents;
+mod anonymize;
+mod cache;
 mod cleanup;
+mod compact;
+mod dataset;
 mod email;
+mod federate;
+mod graphql;
 mod handler;
+mod jobs;
+mod limit;
+mod locale;
 mod mcp;
+mod me;
 mod oidc;
 mod query;
 mod shutdown;
+mod subscriptions;
+mod upload;
+mod watch;
 
+use dataset::Dataset;
 use db::DbPool;
-use handler::{get_files_handler, get_folders_handler, health_handler, login_handler, users_handler};
+use handler::{
+    get_files_handler, get_folders_handler, health_handler, login_handler, stats_handler,
+    summary_handler, users_handler,
+};
 
-static DB_POOL: OnceLock<DbPool> = OnceLock::new();
-static USERS: OnceLock<Vec<String>> = OnceLock::new();
+static CASE_INSENSITIVE: OnceLock<bool> = OnceLock::new();
+static USER_INFO: OnceLock<UserDirectory> = OnceLock::new();
+static OWNERS: OnceLock<OwnerDirectory> = OnceLock::new();
+static ACL: OnceLock<AccessList> = OnceLock::new();
+static TIERS: OnceLock<TierMap> = OnceLock::new();
+static FILE_INDEX: OnceLock<FileIndex> = OnceLock::new();
 
 #[cfg(test)]
 static TEST_DB: OnceLock<db::test_support::TempDb> = OnceLock::new();
@@ -47,6 +80,7 @@ static TEST_DB: OnceLock<db::test_support::TempDb> = OnceLock::new();
 )]
 struct Args {
     /// Input SQLite database file path (built by `dudb`). Falls back to DB_PATH env var.
+    /// May not exist yet: duapi then serves 503 until POST /api/admin/reload
     #[arg(env = "DB_PATH")]
     input: Option<PathBuf>,
     /// UI folder (defaults to STATIC_DIR env var or local public directory)
@@ -64,6 +98,76 @@ struct Args {
     /// CORS allowed origin (falls back to CORS_ORIGIN env var)
     #[arg(long, value_name = "URL")]
     cors_origin: Option<String>,
+    /// Number of /api/folders responses kept in an LRU cache (0 disables)
+    #[arg(long, value_name = "N", env = "FOLDERS_CACHE_SIZE", default_value_t = 0)]
+    cache_size: usize,
+    /// Match folder paths ignoring letter case and Unicode form (implied when
+    /// the DB was built with `dudb --case-insensitive`)
+    #[arg(long, env = "CASE_INSENSITIVE")]
+    case_insensitive: bool,
+    /// Rules file mapping path regexes to project names; enables
+    /// /api/folders?group_by=project
+    #[arg(long, value_name = "FILE", env = "PROJECT_RULES")]
+    project_rules: Option<PathBuf>,
+    /// CSV with user,name,department,email,avatar columns; adds display
+    /// names to /api/users?details=true and /api/folders
+    #[arg(long, value_name = "FILE", env = "USERS_FILE")]
+    users_file: Option<PathBuf>,
+    /// CSV with prefix,team,email,name columns; adds the owner contact of
+    /// each folder to /api/folders
+    #[arg(long, value_name = "FILE", env = "OWNERS_FILE")]
+    owners_file: Option<PathBuf>,
+    /// CSV with prefix,allow columns; folders under a prefix are hidden
+    /// from everyone (admins too) not named in its allow list of users and
+    /// groups
+    #[arg(long, value_name = "FILE", env = "ACL_FILE")]
+    acl_file: Option<PathBuf>,
+    /// CSV with prefix,tier columns; labels /api/folders entries with their
+    /// storage tier and sums usage per tier in /api/summary
+    #[arg(long, value_name = "FILE", env = "TIERS_FILE")]
+    tiers_file: Option<PathBuf>,
+    /// Serve /api/files from this duscan output (CSV or .zst) instead of
+    /// live stat; indexed into <scan>.files.db on first use
+    #[arg(long, value_name = "SCAN", env = "FILES_SOURCE")]
+    files_source: Option<PathBuf>,
+    /// JSON file holding folder subscriptions; enables /api/subscriptions
+    /// and weekly email digests
+    #[arg(long, value_name = "FILE", env = "SUBSCRIPTIONS_FILE")]
+    subscriptions_file: Option<PathBuf>,
+    /// TOML file listing duscand agents; enables the admin-only remote
+    /// scans at /api/admin/agents and /api/admin/scans
+    #[arg(long, value_name = "FILE", env = "AGENTS_FILE")]
+    agents: Option<PathBuf>,
+    /// TOML file listing remote duapi sites; /api/folders and /api/summary
+    /// then span them, each under /SITE/...
+    #[arg(long, value_name = "FILE", env = "SITES_FILE")]
+    sites: Option<PathBuf>,
+    /// Serve the newest *.sum.csv summaries dropped in this directory as
+    /// named datasets (?dataset=NAME), indexed with dudb as they arrive
+    #[arg(long, value_name = "DIR", env = "WATCH_DIR")]
+    watch_dir: Option<PathBuf>,
+    /// Summaries --watch-dir serves; older ones are unloaded
+    #[arg(long, value_name = "N", env = "WATCH_KEEP", default_value_t = 7, requires = "watch_dir")]
+    watch_keep: usize,
+    /// Accounts, besides admins, that may upload summaries into --watch-dir
+    /// with POST /api/datasets (comma-separated)
+    #[arg(long, value_name = "USERS", env = "UPLOAD_USERS", value_delimiter = ',', requires = "watch_dir")]
+    upload_users: Vec<String>,
+    /// Serve a GraphQL API over the index at POST /api/graphql
+    #[arg(long, env = "GRAPHQL")]
+    graphql: bool,
+    /// Demo mode: serve usernames and deep path components as stable
+    /// hashes so screenshots expose no real data; turns off /api/files and
+    /// --users-file names
+    #[arg(long, env = "ANONYMIZE")]
+    anonymize: bool,
+    /// Path components down to this depth keep their names (0 hashes all)
+    #[arg(long, value_name = "N", env = "ANONYMIZE_DEPTH", default_value_t = 1, requires = "anonymize")]
+    anonymize_depth: usize,
+    /// Salt for --anonymize hashes (defaults to JWT_SECRET, so names are
+    /// stable across restarts)
+    #[arg(long, value_name = "TEXT", env = "ANONYMIZE_SALT", hide_env_values = true, requires = "anonymize")]
+    anonymize_salt: Option<String>,
 }
 
 #[tokio::main]
@@ -130,24 +234,135 @@ async fn main() -> Result<()> {
         if oidc::is_enabled() { " + oidc" } else { "" }
     );
 
-    println!("Opening database: {}", db_path.display());
-    let pool = db::open_pool(&db_path).with_context(|| {
-        format!(
-            "opening DB at {}. Build it first with `dudb --input <csv> --output {}`",
-            db_path.display(),
-            db_path.display()
-        )
-    })?;
-    let users = db::list_users(&pool).context("loading user list")?;
-    println!("Loaded {} users", users.len());
+    if args.anonymize {
+        let salt = args
+            .anonymize_salt
+            .clone()
+            .or_else(|| std::env::var("JWT_SECRET").ok())
+            .unwrap_or_default();
+        anonymize::configure(&salt, args.anonymize_depth);
+        println!(
+            "Anonymized: users and paths below depth {} are hashed",
+            args.anonymize_depth
+        );
+        for (set, flag) in [
+            (args.users_file.is_some(), "--users-file"),
+            (args.owners_file.is_some(), "--owners-file"),
+            (args.files_source.is_some(), "--files-source"),
+        ] {
+            if set {
+                eprintln!("{}", format!("Warning: {flag} is ignored with --anonymize.").yellow());
+            }
+        }
+    }
 
-    if DB_POOL.set(pool).is_err() {
-        eprintln!("{}", "FATAL: DB_POOL already initialized".red());
-        std::process::exit(1);
+    if let Some(users_path) = args.users_file.as_ref().filter(|_| !args.anonymize) {
+        let dir = UserDirectory::load(users_path)?;
+        println!("User info: {} entries", dir.len());
+        let _ = USER_INFO.set(dir);
     }
-    if USERS.set(users).is_err() {
-        eprintln!("{}", "FATAL: USERS already initialized".red());
-        std::process::exit(1);
+
+    if let Some(owners_path) = args.owners_file.as_ref().filter(|_| !args.anonymize) {
+        let owners = OwnerDirectory::load(owners_path)?;
+        println!("Owner contacts: {} prefixes", owners.len());
+        let _ = OWNERS.set(owners);
+    }
+
+    if let Some(acl_path) = args.acl_file.as_ref() {
+        let acl = AccessList::load(acl_path)?;
+        println!("Access control: {} restricted prefixes", acl.len());
+        let _ = ACL.set(acl);
+    }
+
+    if let Some(tiers_path) = args.tiers_file.as_ref() {
+        let tiers = TierMap::load(tiers_path)?;
+        println!("Storage tiers: {} prefixes", tiers.len());
+        let _ = TIERS.set(tiers);
+    }
+
+    if let Some(scan) = args.files_source.as_ref().filter(|_| !args.anonymize) {
+        let index = FileIndex::open(scan)
+            .with_context(|| format!("indexing files source {}", scan.display()))?;
+        println!(
+            "Files source: {} files in {}",
+            index.file_count()?,
+            index.path().display()
+        );
+        let _ = FILE_INDEX.set(index);
+    }
+
+    if let Some(path) = &args.subscriptions_file {
+        let n = subscriptions::configure(path.clone())
+            .with_context(|| format!("loading subscriptions {}", path.display()))?;
+        println!(
+            "Subscriptions: {n} in {}{}",
+            path.display(),
+            if email::is_configured() { "" } else { " (no SMTP: digests off)" }
+        );
+    }
+
+    if let Some(path) = &args.agents {
+        let cfg = agents::Config::load(path)?;
+        println!(
+            "Scan agents: {} ({}), results in {}",
+            cfg.agents.len(),
+            cfg.agents.iter().map(|a| a.name.as_str()).collect::<Vec<_>>().join(", "),
+            cfg.work_dir.display()
+        );
+        agents::configure(cfg)?;
+    }
+
+    if let Some(path) = &args.sites {
+        let fed = federate::Federation::load(path)?;
+        println!(
+            "Federation: {} sites ({})",
+            fed.sites.len(),
+            fed.sites.iter().map(|s| s.name.as_str()).collect::<Vec<_>>().join(", ")
+        );
+        federate::configure(fed);
+    }
+
+    if args.cache_size > 0 {
+        cache::init(args.cache_size, String::new());
+        println!("Folders cache: {} entries", args.cache_size);
+    }
+
+    let _ = CASE_INSENSITIVE.set(args.case_insensitive);
+    let rules = args
+        .project_rules
+        .as_deref()
+        .map(ProjectRules::load)
+        .transpose()?;
+    let rule_count = rules.as_ref().map(ProjectRules::len);
+    dataset::configure(db_path.clone(), rules);
+
+    // A missing file is not fatal: the pipeline may not have produced it
+    // yet. One that exists but cannot be opened still is.
+    if db_path.exists() {
+        println!("Opening database: {}", db_path.display());
+        let ds = dataset::reload()?;
+        print_dataset(&ds, args.case_insensitive, rule_count);
+    } else {
+        eprintln!(
+            "{}",
+            format!(
+                "Warning: no database at {} yet; data routes answer 503 until POST /api/admin/reload.",
+                db_path.display()
+            )
+            .yellow()
+        );
+    }
+
+    if let Some(dir) = &args.watch_dir {
+        if !dir.is_dir() {
+            anyhow::bail!("--watch-dir {} is not a directory", dir.display());
+        }
+        println!(
+            "Watching: {} (newest {} summaries, as ?dataset=NAME)",
+            dir.display(),
+            args.watch_keep.max(1)
+        );
+        watch::start(dir.clone(), args.watch_keep);
     }
 
     let cors_origin = args
@@ -171,44 +386,119 @@ async fn main() -> Result<()> {
         };
         CorsLayer::new()
             .allow_origin(header)
-            .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+            .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::OPTIONS])
             .allow_headers(Any)
+            .expose_headers([HeaderName::from_static("x-total-count")])
     } else {
         CorsLayer::new()
-            .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+            .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::OPTIONS])
             .allow_headers(Any)
+            .expose_headers([HeaderName::from_static("x-total-count")])
     };
 
-    let api = Router::new()
-        .route("/health", get(health_handler))
+    let timeout_secs = env_secs("REQUEST_TIMEOUT_SECS", 30);
+    let body_limit_bytes = env_bytes("MAX_BODY_BYTES", 64 * 1024) as usize;
+    let upload_limit_bytes = env_bytes("MAX_UPLOAD_BYTES", 16 << 30) as usize;
+    let max_concurrent = env_u64("MAX_CONCURRENT_REQUESTS", 256) as usize;
+    let grace_secs = env_secs("SHUTDOWN_GRACE_SECS", 30);
+    tracing::info!(
+        timeout_secs,
+        body_limit_bytes,
+        upload_limit_bytes,
+        max_concurrent,
+        grace_secs,
+        "request limits configured"
+    );
+    if let Some(dir) = &args.watch_dir {
+        upload::configure(dir.clone(), &args.upload_users, upload_limit_bytes as u64);
+    }
+    let job_workers = env_u64("JOB_WORKERS", 2) as usize;
+    let job_ttl_secs = env_secs("JOB_TTL_SECS", 3600);
+    let max_jobs = env_u64("MAX_JOBS", 100) as usize;
+    jobs::configure(job_workers, job_ttl_secs, max_jobs);
+    tracing::info!(job_workers, job_ttl_secs, max_jobs, "export jobs configured");
+    let request_limit = limit::RequestLimit::new(max_concurrent);
+
+    // /health stays outside the concurrency cap so a busy server is not
+    // taken for a dead one.
+    // Routes above the `require_dataset` layer answer 503 until a dataset
+    // is loaded; login and admin reload work without one.
+    let mut data = Router::new()
+        .route("/users", get(users_handler))
+        .route("/stats", get(stats_handler))
+        .route("/me", get(me::handler))
+        .route("/files", get(get_files_handler))
+        .route("/age-profile", get(age::handler))
+        .route("/jobs", get(jobs::list_handler).post(jobs::create_handler))
+        .route("/jobs/{id}", get(jobs::get_handler))
+        .route("/jobs/{id}/result", get(jobs::result_handler))
+        .route("/mcp", post(mcp::handler));
+    if args.graphql {
+        println!("GraphQL: POST /api/graphql");
+        data = data.route("/graphql", post(graphql::handler));
+    }
+    // Federated folders and summaries come from the sites, with or without
+    // a local dataset.
+    let data = if args.sites.is_some() {
+        data.route_layer(middleware::from_fn(dataset::require_dataset))
+            .route("/summary", get(federate::summary_handler))
+            .route("/folders", get(federate::folders_handler))
+    } else {
+        data.route("/summary", get(summary_handler))
+            .route("/folders", get(get_folders_handler))
+            .route_layer(middleware::from_fn(dataset::require_dataset))
+    };
+    let api = data
         .route("/login", post(login_handler))
         .route("/auth/mode", get(oidc::mode_handler))
         .route("/auth/login", get(oidc::login_handler))
         .route("/auth/callback", get(oidc::callback_handler))
-        .route("/users", get(users_handler))
-        .route("/folders", get(get_folders_handler))
-        .route("/files", get(get_files_handler))
-        .route("/mcp", post(mcp::handler))
         .route("/cleanup/script", post(cleanup::script_handler))
         .route("/cleanup/notify", post(cleanup::notify_handler))
+        .route("/admin/reload", post(dataset::reload_handler))
+        .route("/datasets", get(dataset::list_handler))
+        .route("/datasets/{name}", delete(dataset::delete_handler))
+        .route("/datasets/{name}/reload", post(dataset::reload_named_handler))
+        .route("/admin/agents", get(agents::agents_handler))
+        .route("/admin/scans", get(agents::list_handler).post(agents::create_handler))
+        .route("/admin/scans/{id}", get(agents::get_handler))
+        .route("/admin/scans/{id}/cancel", post(agents::cancel_handler))
+        .route(
+            "/subscriptions",
+            get(subscriptions::list_handler)
+                .post(subscriptions::subscribe_handler)
+                .delete(subscriptions::unsubscribe_handler),
+        )
+        .layer(middleware::from_fn_with_state(
+            request_limit.clone(),
+            limit::limit_requests,
+        ))
+        .route("/health", get(health_handler))
         .fallback(api_not_found);
 
     let frontend = ServeDir::new(&static_dir)
         .not_found_service(ServeFile::new(format!("{}/index.html", static_dir)));
 
-    let timeout_secs = env_u64("REQUEST_TIMEOUT_SECS", 30);
-    let body_limit_bytes = env_u64("MAX_BODY_BYTES", 64 * 1024) as usize;
-    tracing::info!(timeout_secs, body_limit_bytes, "request limits configured");
-
+    // Summary uploads are added after the timeout and body limit, so they
+    // stream for as long as they take under their own size cap.
     let app = Router::new()
         .nest("/api", api)
         .fallback_service(frontend)
-        .layer(cors)
         .layer(TimeoutLayer::with_status_code(
             StatusCode::REQUEST_TIMEOUT,
             Duration::from_secs(timeout_secs),
         ))
-        .layer(RequestBodyLimitLayer::new(body_limit_bytes));
+        .layer(RequestBodyLimitLayer::new(body_limit_bytes))
+        .route(
+            "/api/datasets",
+            post(upload::handler)
+                .layer(RequestBodyLimitLayer::new(upload_limit_bytes))
+                .layer(middleware::from_fn_with_state(
+                    request_limit.clone(),
+                    limit::limit_requests,
+                )),
+        )
+        .layer(cors);
 
     let addr: SocketAddr = ([0, 0, 0, 0], port).into();
 
@@ -242,9 +532,11 @@ async fn main() -> Result<()> {
             println!("Serving on https://{addr}  (static dir: {static_dir})");
             let handle = axum_server::Handle::new();
             let shutdown_handle = handle.clone();
+            let draining = request_limit.clone();
             tokio::spawn(async move {
                 shutdown::shutdown_signal().await;
-                shutdown_handle.graceful_shutdown(Some(Duration::from_secs(30)));
+                tracing::info!(in_flight = draining.in_flight(), grace_secs, "draining");
+                shutdown_handle.graceful_shutdown(Some(Duration::from_secs(grace_secs)));
             });
 
             axum_server::bind_rustls(addr, config)
@@ -255,12 +547,32 @@ async fn main() -> Result<()> {
         (None, None) => {
             println!("Serving on http://{addr}  (static dir: {static_dir})");
             let listener = tokio::net::TcpListener::bind(addr).await?;
-            axum::serve(
+            let signalled = Arc::new(Notify::new());
+            let notify = signalled.clone();
+            let draining = request_limit.clone();
+            let server = axum::serve(
                 listener,
                 app.into_make_service_with_connect_info::<SocketAddr>(),
             )
-            .with_graceful_shutdown(shutdown::shutdown_signal())
-            .await?;
+            .with_graceful_shutdown(async move {
+                shutdown::shutdown_signal().await;
+                tracing::info!(in_flight = draining.in_flight(), grace_secs, "draining");
+                notify.notify_one();
+            });
+            // In-flight requests get the grace period to finish, then the
+            // process exits regardless.
+            tokio::select! {
+                res = server.into_future() => res?,
+                _ = async {
+                    signalled.notified().await;
+                    tokio::time::sleep(Duration::from_secs(grace_secs)).await;
+                } => {
+                    tracing::warn!(
+                        in_flight = request_limit.in_flight(),
+                        "grace period over, dropping remaining requests"
+                    );
+                }
+            }
         }
         _ => {
             eprintln!(
@@ -312,6 +624,22 @@ fn env_u64(key: &str, default: u64) -> u64 {
         .unwrap_or(default)
 }
 
+/// A size such as `64KB` or `1MB`, or a plain byte count.
+fn env_bytes(key: &str, default: u64) -> u64 {
+    std::env::var(key)
+        .ok()
+        .and_then(|s| parse_bytes(&s).ok())
+        .unwrap_or(default)
+}
+
+/// A duration such as `90s` or `2h30m`, or plain seconds; in seconds.
+fn env_secs(key: &str, default: u64) -> u64 {
+    std::env::var(key)
+        .ok()
+        .and_then(|s| parse_duration(&s).ok())
+        .map_or(default, |d| d.as_secs())
+}
+
 pub fn is_port_taken(port: u16) -> bool {
     let addrs = [format!("127.0.0.1:{port}"), format!("[::1]:{port}")];
     for a in addrs {
@@ -333,12 +661,103 @@ fn default_static_dir() -> String {
     static_dir.to_string_lossy().into_owned()
 }
 
-pub fn get_db() -> &'static DbPool {
-    DB_POOL.get().expect("DB pool not initialized")
+fn print_dataset(ds: &Dataset, case_flag: bool, rule_count: Option<usize>) {
+    println!("Loaded {} users", ds.users.len());
+    if case_flag && !ds.case_insensitive {
+        eprintln!(
+            "{}",
+            "Warning: --case-insensitive on a DB not built with `dudb --case-insensitive`; \
+             spellings are not merged and lookups fold ASCII only, without an index."
+                .yellow()
+        );
+    }
+    if case_flag || ds.case_insensitive {
+        println!("Path case: insensitive");
+    }
+    if let (Some(rules), Some(roots)) = (rule_count, &ds.projects) {
+        let projects: std::collections::HashSet<&str> =
+            roots.iter().map(|r| r.project.as_str()).collect();
+        println!(
+            "Projects: {} rules, {} projects, {} root folders",
+            rules,
+            projects.len(),
+            roots.len()
+        );
+    }
+    if let Some(dir) = get_user_info() {
+        let known = ds.users.iter().filter(|u| dir.get(u).is_some()).count();
+        println!("User info: {} of {} DB users", known, ds.users.len());
+    }
+}
+
+pub fn loaded() -> Arc<Dataset> {
+    dataset::current().expect("no dataset loaded")
+}
+
+/// Pool of the served dataset. Only called behind `require_dataset`.
+pub fn get_db() -> DbPool {
+    loaded().pool.clone()
 }
 
-pub fn get_users() -> &'static Vec<String> {
-    USERS.get().expect("User list not initialized")
+pub fn get_users() -> Vec<String> {
+    loaded().users.clone()
+}
+
+pub fn is_case_insensitive() -> bool {
+    CASE_INSENSITIVE.get().copied().unwrap_or(false)
+        || dataset::current().is_some_and(|d| d.case_insensitive)
+}
+
+/// Project roots from `--project-rules`; `None` when no rules were given.
+pub fn get_projects() -> Option<Arc<[ProjectRoot]>> {
+    dataset::current()?.projects.clone()
+}
+
+/// Display metadata from `--users-file`; `None` when no file was given.
+pub fn get_user_info() -> Option<&'static UserDirectory> {
+    USER_INFO.get()
+}
+
+/// Folder contacts from `--owners-file`; `None` when no file was given.
+pub fn get_owners() -> Option<&'static OwnerDirectory> {
+    OWNERS.get()
+}
+
+/// Storage tiers from `--tiers-file`; `None` when no file was given.
+pub fn get_tiers() -> Option<&'static TierMap> {
+    TIERS.get()
+}
+
+/// The prefixes `--acl-file` hides from the caller; empty without a file.
+pub fn hidden_for(claims: &Claims) -> Vec<String> {
+    hidden_from(&claims.sub, &claims.groups)
+}
+
+/// `hidden_for` by name, for work done outside a request (digests).
+pub fn hidden_from(user: &str, groups: &[String]) -> Vec<String> {
+    ACL.get()
+        .map(|acl| acl.hidden_for(user, groups))
+        .unwrap_or_default()
+}
+
+/// 403 when `--acl-file` hides `path` from the caller. Every handler that
+/// takes a path checks it before reading anything under it.
+pub fn ensure_visible(path: &str, claims: &Claims) -> Result<(), AuthError> {
+    if dutopia::acl::is_hidden(path, &hidden_for(claims), is_case_insensitive()) {
+        return Err(AuthError::Forbidden);
+    }
+    Ok(())
+}
+
+/// Claims of a non-expiring token for `sub`, for handler tests.
+#[cfg(test)]
+pub fn test_claims(sub: &str, is_admin: bool) -> Claims {
+    Claims::new(sub.to_string(), is_admin, 9_999_999_999)
+}
+
+/// Scan index from `--files-source`; `None` serves /api/files live.
+pub fn get_file_index() -> Option<&'static FileIndex> {
+    FILE_INDEX.get()
 }
 
 #[cfg(test)]
@@ -378,4 +797,15 @@ mod tests {
         unsafe { std::env::set_var("DUAPI_TEST_PARSE_BAD", "not-a-number") };
         assert_eq!(env_u64("DUAPI_TEST_PARSE_BAD", 30), 30);
     }
+
+    #[test]
+    fn test_env_bytes_and_secs_accept_units() {
+        // SAFETY: env mutation in a unit test, on variables no other test reads.
+        unsafe { std::env::set_var("DUAPI_TEST_BYTES", "1MB") };
+        assert_eq!(env_bytes("DUAPI_TEST_BYTES", 1), 1 << 20);
+        unsafe { std::env::set_var("DUAPI_TEST_SECS", "2m30s") };
+        assert_eq!(env_secs("DUAPI_TEST_SECS", 1), 150);
+        unsafe { std::env::set_var("DUAPI_TEST_SECS_BAD", "soon") };
+        assert_eq!(env_secs("DUAPI_TEST_SECS_BAD", 30), 30);
+    }
 }
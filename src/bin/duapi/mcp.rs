@@ -13,7 +13,8 @@ use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 
 use dutopia::auth::Claims;
-use dutopia::{analytic, db, item};
+use dutopia::fileindex::FilesPage;
+use dutopia::{analytic, db};
 
 use crate::query::{normalize_path, parse_users_csv};
 use crate::{get_db, get_users};
@@ -318,7 +319,7 @@ fn require_admin(claims: &Claims) -> Result<(), String> {
 
 async fn tool_list_users(claims: &Claims) -> Result<Value, String> {
     let users = if claims.is_admin {
-        get_users().clone()
+        get_users()
     } else {
         vec![claims.sub.clone()]
     };
@@ -332,10 +333,16 @@ async fn tool_list_folders(claims: &Claims, args: Value) -> Result<Value, String
     if !claims.is_admin {
         enforce_self_or_admin(claims, &users)?;
     }
-    let pool = get_db().clone();
+    let pool = get_db();
     let users_t = users.clone();
     let path_t = path.clone();
-    let res = tokio::task::spawn_blocking(move || db::list_children(&pool, &path_t, &users_t, age))
+    let opts = db::ListOptions {
+        hidden: crate::hidden_for(claims),
+        ..Default::default()
+    };
+    let res = tokio::task::spawn_blocking(move || {
+        db::list_children_with(&pool, &path_t, &users_t, age, &opts)
+    })
         .await
         .map_err(|e| format!("join: {e}"))?
         .map_err(|e| format!("query: {e}"))?;
@@ -353,15 +360,17 @@ async fn tool_list_files(claims: &Claims, args: Value) -> Result<Value, String>
     if !claims.is_admin {
         enforce_self_or_admin(claims, &users)?;
     }
-    let users_t = users.clone();
-    let path_t = path.clone();
-    let mut items = tokio::task::spawn_blocking(move || item::get_items(path_t, &users_t, age))
+    let page = FilesPage {
+        dir: path,
+        users,
+        age,
+        limit,
+        ..FilesPage::default()
+    };
+    let (_, items) = tokio::task::spawn_blocking(move || crate::handler::list_files(page))
         .await
         .map_err(|e| format!("join: {e}"))?
-        .map_err(|e| format!("get_items: {e}"))?;
-    if items.len() > limit {
-        items.truncate(limit);
-    }
+        .map_err(|e| format!("list_files: {e}"))?;
     serde_json::to_value(items).map_err(|e| format!("serialize: {e}"))
 }
 
@@ -371,7 +380,7 @@ async fn tool_top_consumers(claims: &Claims, args: Value) -> Result<Value, Strin
     require_admin(claims)?;
     let path = parse_path_arg(&args, "path", false)?;
     let limit = parse_limit_arg(&args, 10)?;
-    let pool = get_db().clone();
+    let pool = get_db();
     let res = tokio::task::spawn_blocking(move || analytic::top_consumers(&pool, path.as_deref(), limit))
         .await
         .map_err(|e| format!("join: {e}"))?
@@ -383,7 +392,7 @@ async fn tool_largest_folders(claims: &Claims, args: Value) -> Result<Value, Str
     require_admin(claims)?;
     let path = parse_path_arg(&args, "path", false)?;
     let limit = parse_limit_arg(&args, 10)?;
-    let pool = get_db().clone();
+    let pool = get_db();
     let res = tokio::task::spawn_blocking(move || analytic::largest_folders(&pool, path.as_deref(), limit))
         .await
         .map_err(|e| format!("join: {e}"))?
@@ -395,7 +404,7 @@ async fn tool_cold_data(claims: &Claims, args: Value) -> Result<Value, String> {
     require_admin(claims)?;
     let path = parse_path_arg(&args, "path", false)?;
     let limit = parse_limit_arg(&args, 50)?;
-    let pool = get_db().clone();
+    let pool = get_db();
     let res = tokio::task::spawn_blocking(move || analytic::cold_data(&pool, path.as_deref(), limit))
         .await
         .map_err(|e| format!("join: {e}"))?
@@ -410,7 +419,7 @@ async fn tool_summary(claims: &Claims, args: Value) -> Result<Value, String> {
     if !claims.is_admin {
         enforce_self_or_admin(claims, &users)?;
     }
-    let pool = get_db().clone();
+    let pool = get_db();
     let users_t = users.clone();
     let res = tokio::task::spawn_blocking(move || analytic::summary(&pool, path.as_deref(), &users_t, age))
         .await
@@ -422,26 +431,26 @@ async fn tool_summary(claims: &Claims, args: Value) -> Result<Value, String> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{DB_POOL, TEST_DB, USERS};
+    use crate::dataset::{self, Dataset};
+    use crate::TEST_DB;
     use serial_test::serial;
 
     fn init_db_once() {
-        if DB_POOL.get().is_some() {
+        if dataset::current().is_some() {
             return;
         }
         let temp_db = dutopia::db::test_support::build_test_db();
-        let pool = dutopia::db::open_pool(&temp_db.path).expect("open_pool");
-        let users = dutopia::db::list_users(&pool).expect("list_users");
+        let ds = Dataset::open(&temp_db.path, None).expect("open dataset");
+        dataset::configure(temp_db.path.clone(), None);
         let _ = TEST_DB.set(temp_db);
-        let _ = DB_POOL.set(pool);
-        let _ = USERS.set(users);
+        dataset::install(ds);
     }
 
     fn admin() -> Claims {
-        Claims { sub: "root".into(), is_admin: true, exp: 9_999_999_999usize }
+        crate::test_claims("root", true)
     }
     fn alice() -> Claims {
-        Claims { sub: "alice".into(), is_admin: false, exp: 9_999_999_999usize }
+        crate::test_claims("alice", false)
     }
 
     #[test]
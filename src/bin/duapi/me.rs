@@ -0,0 +1,152 @@
+// rs/src/bin/duapi/me.rs
+//
+// `GET /api/me`: everything a personal landing page shows after login, in
+// one call. The caller's own totals, age buckets and top folders come from
+// the dashboard query (see `dutopia::dashboard`) restricted to their files,
+// for admins too. `change` compares them with the previous dataset: the one
+// the served dataset replaced at the last reload, or the newest named
+// dataset built before it (see `dataset::previous`). Without an earlier
+// dataset, or for DBs that carry no `built_at`, `change` is null.
+use anyhow::Result;
+use axum::{
+    extract::Query,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use dutopia::auth::Claims;
+use dutopia::dashboard::{dashboard, Dashboard};
+
+use crate::dataset::{self, Dataset};
+use crate::handler::impersonate;
+use crate::locale::{with_formatted, Locale};
+use crate::query::SummaryQuery;
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Change {
+    /// `built_at` of the dataset compared with (epoch seconds)
+    pub since: i64,
+    pub count: i64,
+    pub size: i64,
+    pub disk: i64,
+    pub linked: i64,
+}
+
+#[derive(Serialize)]
+pub struct Me {
+    pub user: String,
+    #[serde(flatten)]
+    pub summary: Dashboard,
+    pub change: Option<Change>,
+}
+
+fn delta(now: u64, then: u64) -> i64 {
+    now as i64 - then as i64
+}
+
+fn me(ds: &Dataset, user: String, top: u32, hidden: &[String], ci: bool) -> Result<Me> {
+    let users = [user.clone()];
+    let mut summary = dashboard(&ds.pool, &users, top, hidden, ci)?;
+    summary.top_users.clear();
+    summary.source = None;
+    let change = match dataset::previous(ds) {
+        Some(prev) => {
+            let then = dashboard(&prev.pool, &users, 1, hidden, ci)?;
+            prev.built_secs().map(|since| Change {
+                since,
+                count: delta(summary.count, then.count),
+                size: delta(summary.size, then.size),
+                disk: delta(summary.disk, then.disk),
+                linked: delta(summary.linked, then.linked),
+            })
+        }
+        None => None,
+    };
+    Ok(Me { user, summary, change })
+}
+
+/// GET /api/me?top=10&as_user=alice
+///
+/// The caller's usage, age distribution and top folders, plus the change
+/// since the previous dataset, all without the trees `--acl-file` hides.
+/// Admins may look at another user's page with `as_user`; `?locale=` or
+/// `Accept-Language` adds display strings.
+pub async fn handler(claims: Claims, headers: HeaderMap, Query(q): Query<SummaryQuery>) -> Response {
+    let locale = Locale::resolve(q.locale.as_deref(), &headers);
+    let claims = match impersonate(claims, q.as_user.as_deref(), "/api/me") {
+        Ok(c) => c,
+        Err(e) => return e.into_response(),
+    };
+    let top = q.top.unwrap_or(10).clamp(1, 100);
+    let hidden = crate::hidden_for(&claims);
+    let ci = crate::is_case_insensitive();
+    // Behind `require_dataset`, so a dataset is loaded.
+    let ds = crate::loaded();
+    match tokio::task::spawn_blocking(move || me(&ds, claims.sub, top, &hidden, ci)).await {
+        Ok(Ok(me)) => {
+            tracing::info!(user = %me.user, count = me.summary.count, "200 OK /api/me");
+            let formatted = locale.map(|l| l.dashboard(&me.summary));
+            Json(with_formatted(serde_json::json!(me), formatted)).into_response()
+        }
+        Ok(Err(e)) => {
+            tracing::error!(err = %format!("{e:#}"), "500 dashboard ERROR /api/me");
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("me error: {e:#}")).into_response()
+        }
+        Err(join_err) => {
+            tracing::error!(err = %join_err, "500 Task Join Error /api/me");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("task error: {join_err}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A test DB stamped `built_at`, with alice's `/` rollup scaled by `k`.
+    fn stamped(built_at: i64, k: u64) -> dutopia::db::test_support::TempDb {
+        let db = dutopia::db::test_support::build_test_db();
+        let conn = rusqlite::Connection::open(&db.path).unwrap();
+        conn.execute_batch(&format!(
+            "INSERT INTO metadata(key, value) VALUES('built_at', '{built_at}');
+             UPDATE stats SET file_count = file_count * {k}, file_size = file_size * {k},
+                              disk_bytes = disk_bytes * {k}
+             WHERE user_id = (SELECT id FROM users WHERE name = 'alice')
+               AND path_id = (SELECT id FROM paths WHERE full_path = '/');"
+        ))
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn reports_own_usage_and_change_since_the_previous_dataset() {
+        let (old, new) = (stamped(1_800_000_000, 1), stamped(1_800_086_400, 3));
+        let newest = dataset::load_named("me-new", &new.path).unwrap();
+        let first = me(&newest, "alice".into(), 10, &[], false).unwrap();
+        assert_eq!(first.change, None);
+        assert_eq!(first.summary.count, 6);
+        assert!(first.summary.top_users.is_empty());
+
+        dataset::load_named("me-old", &old.path).unwrap();
+        let v = serde_json::json!(me(&newest, "alice".into(), 10, &[], false).unwrap());
+        assert_eq!(v["user"], "alice");
+        assert_eq!(v["count"], 6);
+        assert_eq!(v["ages"][0]["count"], 6);
+        assert_eq!(
+            v["change"],
+            serde_json::json!({ "since": 1_800_000_000i64, "count": 4, "size": 400, "disk": 200, "linked": 0 })
+        );
+        // The older dataset has nothing before it.
+        let older = dataset::named("me-old").unwrap();
+        assert_eq!(me(&older, "alice".into(), 10, &[], false).unwrap().change, None);
+
+        dataset::unload_named("me-new");
+        dataset::unload_named("me-old");
+    }
+}
@@ -247,10 +247,21 @@ pub async fn verify_bearer(token: String) -> Option<Claims> {
         .and_then(|v| v.as_u64())
         .unwrap_or(0) as usize;
 
+    let groups = data
+        .claims
+        .extra
+        .get("groups")
+        .and_then(|v| v.as_array())
+        .map(|arr| arr.iter().filter_map(|x| x.as_str()).map(str::to_string).collect())
+        .unwrap_or_default();
+
     Some(Claims {
         sub: username,
         is_admin,
         exp,
+        iss: None,
+        aud: None,
+        groups,
     })
 }
 
@@ -408,10 +419,10 @@ async fn get_jwk(kid: &str, cfg: &OidcConfig) -> Result<Jwk> {
     // Fast path: cache hit that's fresh
     {
         let guard = JWKS_CACHE.get().expect("jwks cache").lock().unwrap();
-        if let (Some(k), Some(fetched)) = (guard.keys.get(kid), guard.fetched_at) {
-            if fetched.elapsed() < JWKS_TTL {
-                return Ok(k.clone());
-            }
+        if let (Some(k), Some(fetched)) = (guard.keys.get(kid), guard.fetched_at)
+            && fetched.elapsed() < JWKS_TTL
+        {
+            return Ok(k.clone());
         }
     }
     // Refetch
@@ -507,11 +518,7 @@ fn map_to_internal(id: IdTokenClaims, cfg: &OidcConfig, ttl_secs: u64) -> Result
         .as_secs()
         + ttl_secs;
 
-    Ok(Claims {
-        sub: username,
-        is_admin,
-        exp: exp.try_into().unwrap(),
-    })
+    Ok(Claims::new(username, is_admin, exp.try_into().unwrap()))
 }
 
 // ---- HTTP handlers ----
@@ -525,7 +532,7 @@ use axum::{
 use jsonwebtoken::{encode, Header};
 use serde::Serialize;
 
-use dutopia::auth::keys;
+use dutopia::auth::{issue_token, keys};
 
 const STATE_COOKIE: &str = "duapi_oidc_state";
 const STATE_TTL_SECS: u64 = 10 * 60;
@@ -656,7 +663,7 @@ pub async fn callback_handler(headers: HeaderMap, Query(q): Query<CallbackQuery>
     )
     .await
     {
-        Ok(c) => c,
+        Ok(c) => crate::anonymize::claims(c),
         Err(e) => {
             tracing::warn!(err = %e, "OIDC exchange/verify failed");
             return (StatusCode::UNAUTHORIZED, "oidc verify failed").into_response();
@@ -664,10 +671,10 @@ pub async fn callback_handler(headers: HeaderMap, Query(q): Query<CallbackQuery>
     };
     tracing::info!(user = %claims.sub, is_admin = claims.is_admin, "oidc login success");
 
-    let token = match encode(&Header::default(), &claims, &keys().encoding) {
+    let token = match issue_token(&claims) {
         Ok(t) => t,
         Err(e) => {
-            tracing::error!(err = %e, "mint internal jwt failed");
+            tracing::error!(err = ?e, "mint internal jwt failed");
             return (StatusCode::INTERNAL_SERVER_ERROR, "token creation error").into_response();
         }
     };
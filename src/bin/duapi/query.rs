@@ -8,6 +8,17 @@ pub struct FolderQuery {
     pub path: Option<String>,
     pub users: Option<String>,
     pub age: Option<u8>,
+    pub by_device: Option<bool>,
+    pub group_by: Option<String>,
+    pub as_user: Option<String>,
+    /// Arrays with a schema instead of objects (see `compact`)
+    pub compact: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct UsersQuery {
+    pub details: Option<bool>,
+    pub as_user: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -15,6 +26,37 @@ pub struct FilesQuery {
     pub path: Option<String>,
     pub users: Option<String>,
     pub age: Option<u8>,
+    pub as_user: Option<String>,
+    /// path (default), size or mtime
+    pub sort: Option<String>,
+    /// asc (default) or desc
+    pub order: Option<String>,
+    pub offset: Option<usize>,
+    /// Page size, capped at MAX_PAGE_SIZE
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct AgeProfileQuery {
+    pub path: Option<String>,
+    /// One user's files; all users when absent (admins only)
+    pub user: Option<String>,
+    pub as_user: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SummaryQuery {
+    /// Length of the top-users and top-folders lists (default 10, max 100)
+    pub top: Option<u32>,
+    pub as_user: Option<String>,
+    /// Display locale for `formatted`, e.g. `de-DE` (else `Accept-Language`)
+    pub locale: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct StatsQuery {
+    /// Display locale for `formatted`, e.g. `de-DE` (else `Accept-Language`)
+    pub locale: Option<String>,
 }
 
 pub fn parse_users_csv(s: &str) -> Vec<String> {
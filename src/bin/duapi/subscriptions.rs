@@ -0,0 +1,539 @@
+// rs/src/bin/duapi/subscriptions.rs
+//
+// Folder watch subscriptions and weekly email digests.
+//
+// A user subscribes to folders through /api/subscriptions; the list lives in
+// a JSON file (SUBSCRIPTIONS_FILE) so it survives restarts. Each
+// subscription keeps a baseline: the folder's totals when it was created or
+// when the last digest went out. Whenever a new dataset is installed, users
+// whose last digest is at least a week old get one email listing every
+// folder they watch with its size, file count and old-bucket size against
+// the baseline, and the baselines move forward. Non-admins watch their own
+// files only, matching what /api/folders shows them; an admin's
+// subscriptions cover all users. Folders `--acl-file` hides from the user
+// cannot be subscribed to, and are left out of their list and digests.
+//
+// Digests need SMTP (see email.rs); without it subscriptions are still
+// kept and the digest pass is skipped.
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use dutopia::acl::is_hidden;
+use dutopia::auth::Claims;
+use dutopia::db::{self, DbPool};
+use dutopia::util::{human_bytes, human_count, AtomicFile};
+
+use crate::dataset::{self, Dataset};
+use crate::email;
+
+const DIGEST_INTERVAL_SECS: i64 = 7 * 24 * 3600;
+const MAX_PER_USER: usize = 100;
+/// Age bucket reported as "old" (see dusum's default buckets).
+const OLD_BUCKET: u8 = 2;
+
+/// Totals of a watched folder at one point in time.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Epoch seconds
+    pub taken_at: i64,
+    /// `built_at` of the dataset it was read from
+    pub built_at: String,
+    pub files: u64,
+    pub size: u64,
+    pub disk: u64,
+    /// Size in the old age bucket
+    pub old_size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subscription {
+    pub user: String,
+    pub path: String,
+    /// Covers every user's files (created by an admin)
+    pub all_users: bool,
+    pub created_at: i64,
+    /// Totals at subscribe time or at the last digest; `None` when no
+    /// dataset was loaded then
+    pub baseline: Option<Snapshot>,
+    /// The subscriber's OIDC groups at subscribe time, for `--acl-file`
+    /// when the digest goes out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Store {
+    pub subscriptions: Vec<Subscription>,
+    /// user -> epoch seconds of the last digest sent
+    pub last_digest: BTreeMap<String, i64>,
+}
+
+struct Persisted {
+    path: PathBuf,
+    store: Store,
+}
+
+static STORE: OnceLock<Mutex<Persisted>> = OnceLock::new();
+/// One digest pass at a time, so two quick reloads cannot mail twice.
+static DIGESTING: Mutex<()> = Mutex::new(());
+
+/// Load (or start) the store at `path`; returns the number of subscriptions.
+pub fn configure(path: PathBuf) -> Result<usize> {
+    let store = Store::load(&path)?;
+    let n = store.subscriptions.len();
+    let _ = STORE.set(Mutex::new(Persisted { path, store }));
+    Ok(n)
+}
+
+pub fn is_configured() -> bool {
+    STORE.get().is_some()
+}
+
+impl Store {
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("parsing {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+        }
+    }
+
+    /// Write through an `AtomicFile`, so a crash never leaves half a store
+    /// behind.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let (atomic, mut file) = AtomicFile::create(path)?;
+        file.write_all(&serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("writing {}", atomic.temp_path().display()))?;
+        atomic.commit(file).with_context(|| format!("replacing {}", path.display()))
+    }
+
+    pub fn of_user<'a>(&'a self, user: &'a str) -> impl Iterator<Item = &'a Subscription> {
+        self.subscriptions.iter().filter(move |s| s.user == user)
+    }
+
+    /// Add `sub` unless the user already watches that path; returns the
+    /// stored subscription, or `None` when the user is at the limit.
+    pub fn subscribe(&mut self, sub: Subscription) -> Option<Subscription> {
+        if let Some(existing) = self.of_user(&sub.user).find(|s| s.path == sub.path) {
+            return Some(existing.clone());
+        }
+        if self.of_user(&sub.user).count() >= MAX_PER_USER {
+            return None;
+        }
+        self.subscriptions.push(sub.clone());
+        Some(sub)
+    }
+
+    pub fn unsubscribe(&mut self, user: &str, path: &str) -> bool {
+        let before = self.subscriptions.len();
+        self.subscriptions.retain(|s| !(s.user == user && s.path == path));
+        if !self.subscriptions.iter().any(|s| s.user == user) {
+            self.last_digest.remove(user);
+        }
+        self.subscriptions.len() != before
+    }
+
+    /// Users due a digest at `now`, with their subscriptions.
+    pub fn due(&self, now: i64) -> BTreeMap<String, Vec<Subscription>> {
+        let mut out: BTreeMap<String, Vec<Subscription>> = BTreeMap::new();
+        for s in &self.subscriptions {
+            let last = self.last_digest.get(&s.user).copied();
+            if last.is_none_or(|t| now - t >= DIGEST_INTERVAL_SECS) {
+                out.entry(s.user.clone()).or_default().push(s.clone());
+            }
+        }
+        out
+    }
+
+    /// Record a sent digest: new baselines and the send time.
+    fn digested(&mut self, user: &str, now: i64, current: &[(String, Option<Snapshot>)]) {
+        for s in self.subscriptions.iter_mut().filter(|s| s.user == user) {
+            if let Some((_, snap)) = current.iter().find(|(p, _)| *p == s.path) {
+                s.baseline = snap.clone();
+            }
+        }
+        self.last_digest.insert(user.to_string(), now);
+    }
+}
+
+/// Current totals of the folder `sub` watches; `None` when the folder (or
+/// the user's files in it) is gone.
+pub fn snapshot(
+    pool: &DbPool,
+    built_at: &str,
+    case_insensitive: bool,
+    sub: &Subscription,
+    now: i64,
+) -> Result<Option<Snapshot>> {
+    let user = (!sub.all_users).then_some(sub.user.as_str());
+    let Some(ages) = db::folder_totals(pool, &sub.path, user, case_insensitive)? else {
+        return Ok(None);
+    };
+    let mut snap = Snapshot {
+        taken_at: now,
+        built_at: built_at.to_string(),
+        ..Default::default()
+    };
+    for (age, t) in &ages {
+        snap.files += t.count;
+        snap.size += t.size;
+        snap.disk += t.disk;
+        if *age == OLD_BUCKET {
+            snap.old_size += t.size;
+        }
+    }
+    Ok(Some(snap))
+}
+
+fn signed_bytes(now: u64, before: u64) -> String {
+    if now >= before {
+        format!("+{}", human_bytes(now - before))
+    } else {
+        format!("-{}", human_bytes(before - now))
+    }
+}
+
+fn signed_count(now: u64, before: u64) -> String {
+    if now >= before {
+        format!("+{}", human_count(now - before))
+    } else {
+        format!("-{}", human_count(before - now))
+    }
+}
+
+fn date(epoch: i64) -> String {
+    Utc.timestamp_opt(epoch, 0)
+        .single()
+        .map(|t| t.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+/// Plain-text digest for `user`: one block per watched folder.
+pub fn render_digest(user: &str, built_at: &str, items: &[(&Subscription, Option<&Snapshot>)]) -> String {
+    let mut body = format!("Hello {user},\n\nChanges in the folders you watch");
+    if !built_at.is_empty() {
+        body.push_str(&format!(" (scan of {built_at})"));
+    }
+    body.push_str(":\n\n");
+    for (sub, now) in items {
+        body.push_str(&sub.path);
+        if sub.all_users {
+            body.push_str("  (all users)");
+        }
+        body.push('\n');
+        let Some(now) = now else {
+            body.push_str("  no longer in the scan\n\n");
+            continue;
+        };
+        match &sub.baseline {
+            Some(b) => {
+                let since = date(b.taken_at);
+                body.push_str(&format!(
+                    "  size   {:>10}  ({} since {since})\n",
+                    human_bytes(now.size),
+                    signed_bytes(now.size, b.size)
+                ));
+                body.push_str(&format!(
+                    "  files  {:>10}  ({})\n",
+                    human_count(now.files),
+                    signed_count(now.files, b.files)
+                ));
+                body.push_str(&format!(
+                    "  old    {:>10}  ({})\n\n",
+                    human_bytes(now.old_size),
+                    signed_bytes(now.old_size, b.old_size)
+                ));
+            }
+            None => {
+                body.push_str(&format!(
+                    "  size   {:>10}\n  files  {:>10}\n  old    {:>10}\n\n",
+                    human_bytes(now.size),
+                    human_count(now.files),
+                    human_bytes(now.old_size)
+                ));
+            }
+        }
+    }
+    body.push_str("\"old\" is the oldest age bucket (by default not modified for 600 days).\n");
+    body.push_str("Manage your subscriptions in Dutopia.\n— Dutopia\n");
+    body
+}
+
+/// Hook for a newly installed dataset: mail due digests in the background.
+pub fn on_install(ds: Arc<Dataset>) {
+    if !is_configured() || !email::is_configured() {
+        return;
+    }
+    std::thread::spawn(move || {
+        if let Err(e) = send_digests(&ds, Utc::now().timestamp()) {
+            tracing::error!(err = %format!("{e:#}"), "subscription digests failed");
+        }
+    });
+}
+
+fn send_digests(ds: &Dataset, now: i64) -> Result<()> {
+    let _pass = DIGESTING.lock().unwrap_or_else(|e| e.into_inner());
+    let store = STORE.get().context("subscriptions not configured")?;
+    // Read the work under the lock, mail without it.
+    let due = store.lock().unwrap_or_else(|e| e.into_inner()).store.due(now);
+    let ci = crate::is_case_insensitive();
+    for (user, subs) in due {
+        let subs: Vec<Subscription> = subs
+            .into_iter()
+            .filter(|s| !is_hidden(&s.path, &crate::hidden_from(&s.user, &s.groups), ci))
+            .collect();
+        if subs.is_empty() {
+            continue;
+        }
+        let Some(to) = email::resolve_email(&user) else {
+            tracing::warn!(%user, "no email for subscription digest");
+            continue;
+        };
+        let mut current = Vec::with_capacity(subs.len());
+        for s in &subs {
+            current.push((s.path.clone(), snapshot(&ds.pool, &ds.built_at, ci, s, now)?));
+        }
+        let items: Vec<_> = subs
+            .iter()
+            .zip(&current)
+            .map(|(s, (_, snap))| (s, snap.as_ref()))
+            .collect();
+        let body = render_digest(&user, &ds.built_at, &items);
+        let subject = format!("Dutopia: weekly digest for {} folders", subs.len());
+        if let Err(e) = email::send(&to, &subject, &body) {
+            tracing::error!(%user, err = %e, "subscription digest send failed");
+            continue;
+        }
+        tracing::info!(%user, folders = subs.len(), "subscription digest sent");
+        let mut p = store.lock().unwrap_or_else(|e| e.into_inner());
+        p.store.digested(&user, now, &current);
+        p.store.save(&p.path)?;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct SubscribeReq {
+    pub path: String,
+}
+
+#[derive(Deserialize)]
+pub struct UnsubscribeQuery {
+    pub path: Option<String>,
+}
+
+fn not_configured(route: &str) -> Response {
+    tracing::warn!("501 Not Implemented {route} (SUBSCRIPTIONS_FILE not set)");
+    (StatusCode::NOT_IMPLEMENTED, "subscriptions not configured").into_response()
+}
+
+fn store_error(route: &str, e: anyhow::Error) -> Response {
+    tracing::error!(err = %format!("{e:#}"), "500 store ERROR {route}");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("store error: {e:#}"),
+    )
+        .into_response()
+}
+
+/// Same form /api/folders matches against; the synthetic root is refused.
+fn normalize(raw: &str) -> Option<String> {
+    let ci = crate::is_case_insensitive();
+    match crate::query::normalize_path(raw)? {
+        p if p.is_empty() => None,
+        p if ci => Some(dutopia::query::nfc(&p).into_owned()),
+        p => Some(p),
+    }
+}
+
+/// GET /api/subscriptions
+///
+/// The caller's subscriptions with their baselines, less folders
+/// `--acl-file` hides from them.
+pub async fn list_handler(claims: Claims) -> Response {
+    let Some(store) = STORE.get() else {
+        return not_configured("/api/subscriptions");
+    };
+    let p = store.lock().unwrap_or_else(|e| e.into_inner());
+    let hidden = crate::hidden_for(&claims);
+    let ci = crate::is_case_insensitive();
+    let mine: Vec<&Subscription> = p
+        .store
+        .of_user(&claims.sub)
+        .filter(|s| !is_hidden(&s.path, &hidden, ci))
+        .collect();
+    tracing::info!(user = %claims.sub, items = mine.len(), "200 OK GET /api/subscriptions");
+    Json(mine).into_response()
+}
+
+/// POST /api/subscriptions  {"path": "/data/proj"}
+///
+/// Watch a folder. The baseline is taken from the served dataset, so the
+/// first digest already shows a change; 404 when the folder (for a
+/// non-admin: their files in it) is not in it. Subscribing twice returns
+/// the existing subscription.
+pub async fn subscribe_handler(claims: Claims, Json(req): Json<SubscribeReq>) -> Response {
+    let Some(store) = STORE.get() else {
+        return not_configured("/api/subscriptions");
+    };
+    let Some(path) = normalize(&req.path) else {
+        tracing::warn!(input = %req.path, "400 Bad Request POST /api/subscriptions rejected path");
+        return (StatusCode::BAD_REQUEST, "invalid path").into_response();
+    };
+    if let Err(e) = crate::ensure_visible(&path, &claims) {
+        tracing::warn!(path = %path, user = %claims.sub, "403 POST /api/subscriptions hidden");
+        return e.into_response();
+    }
+    let now = Utc::now().timestamp();
+    let mut sub = Subscription {
+        user: claims.sub.clone(),
+        path,
+        all_users: claims.is_admin,
+        created_at: now,
+        baseline: None,
+        groups: claims.groups.clone(),
+    };
+    if let Some(ds) = dataset::current() {
+        let ci = crate::is_case_insensitive();
+        let probe = sub.clone();
+        let res = tokio::task::spawn_blocking(move || {
+            snapshot(&ds.pool, &ds.built_at, ci, &probe, now)
+        })
+        .await;
+        match res {
+            Ok(Ok(Some(snap))) => sub.baseline = Some(snap),
+            Ok(Ok(None)) => {
+                tracing::warn!(path = %sub.path, "404 POST /api/subscriptions folder not found");
+                return (StatusCode::NOT_FOUND, "folder not found").into_response();
+            }
+            Ok(Err(e)) => return store_error("POST /api/subscriptions", e),
+            Err(join_err) => {
+                tracing::error!(err = %join_err, "500 Task Join Error POST /api/subscriptions");
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("task error: {join_err}"),
+                )
+                    .into_response();
+            }
+        }
+    }
+
+    let mut p = store.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(stored) = p.store.subscribe(sub) else {
+        tracing::warn!(user = %claims.sub, "400 POST /api/subscriptions limit reached");
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("at most {MAX_PER_USER} subscriptions per user"),
+        )
+            .into_response();
+    };
+    if let Err(e) = p.store.save(&p.path) {
+        return store_error("POST /api/subscriptions", e);
+    }
+    tracing::info!(user = %claims.sub, path = %stored.path, "201 Created POST /api/subscriptions");
+    (StatusCode::CREATED, Json(stored)).into_response()
+}
+
+/// DELETE /api/subscriptions?path=/data/proj
+pub async fn unsubscribe_handler(claims: Claims, Query(q): Query<UnsubscribeQuery>) -> Response {
+    let Some(store) = STORE.get() else {
+        return not_configured("/api/subscriptions");
+    };
+    let raw = q.path.unwrap_or_default();
+    let Some(path) = normalize(&raw) else {
+        tracing::warn!(input = %raw, "400 Bad Request DELETE /api/subscriptions rejected path");
+        return (StatusCode::BAD_REQUEST, "invalid path").into_response();
+    };
+    let mut p = store.lock().unwrap_or_else(|e| e.into_inner());
+    if !p.store.unsubscribe(&claims.sub, &path) {
+        return (StatusCode::NOT_FOUND, "not subscribed").into_response();
+    }
+    if let Err(e) = p.store.save(&p.path) {
+        return store_error("DELETE /api/subscriptions", e);
+    }
+    tracing::info!(user = %claims.sub, %path, "204 No Content DELETE /api/subscriptions");
+    StatusCode::NO_CONTENT.into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sub(user: &str, path: &str) -> Subscription {
+        Subscription {
+            user: user.into(),
+            path: path.into(),
+            all_users: false,
+            created_at: 0,
+            baseline: None,
+            groups: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn store_subscribes_once_and_persists() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("subs.json");
+        let mut store = Store::load(&file).unwrap();
+        assert!(store.subscribe(sub("alice", "/docs")).is_some());
+        assert!(store.subscribe(sub("alice", "/docs")).is_some());
+        store.subscribe(sub("bob", "/docs"));
+        assert_eq!(store.subscriptions.len(), 2);
+        store.save(&file).unwrap();
+
+        let mut back = Store::load(&file).unwrap();
+        assert_eq!(back.subscriptions, store.subscriptions);
+        assert!(back.unsubscribe("alice", "/docs"));
+        assert!(!back.unsubscribe("alice", "/docs"));
+        assert_eq!(back.of_user("bob").count(), 1);
+
+        std::fs::write(&file, "[").unwrap();
+        assert!(Store::load(&file).is_err());
+    }
+
+    #[test]
+    fn digests_are_weekly_and_move_the_baseline() {
+        let db = db::test_support::build_test_db();
+        let pool = db::open_pool(&db.path).unwrap();
+        let mut store = Store::default();
+        store.subscribe(sub("alice", "/"));
+        store.subscribe(Subscription {
+            all_users: true,
+            ..sub("root", "/")
+        });
+        assert_eq!(store.due(0).len(), 2);
+
+        let alice = &store.due(0)["alice"][0];
+        let now = snapshot(&pool, "b1", false, alice, 100).unwrap().unwrap();
+        assert_eq!((now.files, now.size, now.old_size), (2, 200, 0));
+        let all = snapshot(&pool, "b1", false, &store.due(0)["root"][0], 100).unwrap();
+        assert_eq!(all.unwrap().size, 250);
+        assert!(snapshot(&pool, "b1", false, &sub("bob", "/docs"), 100).unwrap().is_none());
+
+        store.digested("alice", 100, &[("/".into(), Some(now.clone()))]);
+        assert_eq!(store.subscriptions[0].baseline.as_ref(), Some(&now));
+        assert!(!store.due(100 + DIGEST_INTERVAL_SECS - 1).contains_key("alice"));
+        assert!(store.due(100 + DIGEST_INTERVAL_SECS).contains_key("alice"));
+
+        let grown = Snapshot { size: 150, files: 1, ..now };
+        let body = render_digest("alice", "b2", &[(&store.subscriptions[0], Some(&grown))]);
+        assert!(body.contains("Hello alice"));
+        assert!(body.contains(&format!("-{}", human_bytes(50))));
+        assert!(body.contains("since 1970-01-01"));
+        let gone = render_digest("alice", "", &[(&store.subscriptions[0], None)]);
+        assert!(gone.contains("no longer in the scan"));
+    }
+}
@@ -0,0 +1,231 @@
+// rs/src/bin/duapi/upload.rs
+//
+// `POST /api/datasets?name=NAME`: remote sites push their nightly summaries
+// to a central duapi instead of sharing a filesystem with it. The body is
+// the `.sum.csv` itself, plain or zstd-compressed (told apart by the zstd
+// magic number, so `curl --data-binary @fs.sum.csv.zst` works as is), and
+// is streamed to disk rather than held in memory. Once it has been checked
+// to be a dusum summary (a header with `path` and `user` columns) it is
+// renamed into the `--watch-dir` as `NAME.sum.csv`, replacing any earlier
+// upload of that name; the watcher then indexes and serves it as the
+// dataset NAME within a pass or two (see `watch`). A failed upload leaves
+// nothing behind.
+//
+// Admins may upload, and so may the accounts in `--upload-users`, so a site
+// can push with a token that has no other rights. Uploads have their own
+// size cap (`MAX_UPLOAD_BYTES`) and no request timeout; the cap also bounds
+// what a compressed upload may decompress to.
+use anyhow::{bail, Context, Result};
+use axum::{
+    body::{Body, HttpBody},
+    extract::Query,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::OnceLock;
+use tempfile::NamedTempFile;
+use tokio::io::AsyncWriteExt;
+
+use dutopia::auth::{AuthError, Claims};
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+struct Uploads {
+    dir: PathBuf,
+    /// Lowercased accounts that may upload besides admins
+    users: Vec<String>,
+    /// Most bytes a stored summary may have, after decompression
+    limit: u64,
+}
+
+static UPLOADS: OnceLock<Uploads> = OnceLock::new();
+
+/// Accept uploads of up to `limit` bytes into the watched directory `dir`.
+pub fn configure(dir: PathBuf, users: &[String], limit: u64) {
+    let users = users.iter().map(|u| u.trim().to_lowercase()).collect();
+    let _ = UPLOADS.set(Uploads { dir, users, limit });
+}
+
+#[derive(Deserialize)]
+pub struct UploadQuery {
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Serialize)]
+pub struct UploadOut {
+    name: String,
+    /// Bytes of the stored summary, after decompression
+    bytes: u64,
+}
+
+/// Dataset names double as file names: letters, digits, `.`, `_` and `-`,
+/// not starting with a dot.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 128
+        && !name.starts_with('.')
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'))
+}
+
+fn error(status: StatusCode, msg: String) -> Response {
+    (status, Json(serde_json::json!({ "error": msg }))).into_response()
+}
+
+/// POST /api/datasets?name=NAME
+pub async fn handler(claims: Claims, Query(q): Query<UploadQuery>, body: Body) -> Response {
+    let Some(cfg) = UPLOADS.get() else {
+        tracing::warn!("404 Not Found POST /api/datasets (no --watch-dir)");
+        return error(StatusCode::NOT_FOUND, "uploads need --watch-dir".into());
+    };
+    if !claims.is_admin && !cfg.users.contains(&claims.sub.to_lowercase()) {
+        tracing::warn!(actor = %claims.sub, "403 Forbidden POST /api/datasets (not an uploader)");
+        return AuthError::Forbidden.into_response();
+    }
+    let name = q.name.trim().to_string();
+    if !valid_name(&name) {
+        tracing::warn!(actor = %claims.sub, name = %name, "400 Bad Request POST /api/datasets");
+        return error(
+            StatusCode::BAD_REQUEST,
+            format!("'{name}' is not a dataset name (letters, digits, '.', '_', '-')"),
+        );
+    }
+
+    let raw = match receive(body, &cfg.dir).await {
+        Ok(raw) => raw,
+        Err(e) => {
+            tracing::warn!(actor = %claims.sub, dataset = %name, err = %format!("{e:#}"), "400 upload failed POST /api/datasets");
+            return error(StatusCode::BAD_REQUEST, format!("upload failed: {e:#}"));
+        }
+    };
+    let dir = cfg.dir.clone();
+    let (task_name, limit) = (name.clone(), cfg.limit);
+    match tokio::task::spawn_blocking(move || store(raw.path(), &dir, &task_name, limit)).await {
+        Ok(Ok(bytes)) => {
+            tracing::info!(actor = %claims.sub, dataset = %name, bytes, "202 Accepted POST /api/datasets");
+            (StatusCode::ACCEPTED, Json(UploadOut { name, bytes })).into_response()
+        }
+        Ok(Err(e)) => {
+            tracing::warn!(actor = %claims.sub, dataset = %name, err = %format!("{e:#}"), "400 upload rejected POST /api/datasets");
+            error(StatusCode::BAD_REQUEST, format!("upload rejected: {e:#}"))
+        }
+        Err(join_err) => {
+            tracing::error!(err = %join_err, "500 Task Join Error POST /api/datasets");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("task error: {join_err}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Stream the request body into a temp file in `dir`, deleted on drop.
+async fn receive(mut body: Body, dir: &Path) -> Result<NamedTempFile> {
+    let raw = tempfile::Builder::new()
+        .prefix(".upload-")
+        .suffix(".raw")
+        .tempfile_in(dir)
+        .with_context(|| format!("creating a temp file in {}", dir.display()))?;
+    let mut file = tokio::fs::File::from_std(raw.reopen()?);
+    while let Some(frame) = std::future::poll_fn(|cx| Pin::new(&mut body).poll_frame(cx)).await {
+        if let Ok(data) = frame.context("reading the request body")?.into_data() {
+            file.write_all(&data).await?;
+        }
+    }
+    file.flush().await?;
+    Ok(raw)
+}
+
+/// Check the upload at `raw` and store it, decompressed, as
+/// `dir/NAME.sum.csv`, refusing it past `limit` bytes. Returns its size.
+pub fn store(raw: &Path, dir: &Path, name: &str, limit: u64) -> Result<u64> {
+    let mut input = BufReader::new(std::fs::File::open(raw)?);
+    let compressed = input.fill_buf()?.starts_with(&ZSTD_MAGIC);
+    // One byte over the limit is enough to tell it was exceeded.
+    let mut input: Box<dyn BufRead> = if compressed {
+        let decoder = zstd::Decoder::new(input).context("reading zstd")?;
+        Box::new(BufReader::new(decoder.take(limit + 1)))
+    } else {
+        Box::new(input.take(limit + 1))
+    };
+
+    let mut header = String::new();
+    input.read_line(&mut header).context("reading the header")?;
+    let columns: Vec<String> = header
+        .trim_end()
+        .split(',')
+        .map(|c| c.trim().to_lowercase())
+        .collect();
+    if !["path", "user"].iter().all(|c| columns.iter().any(|h| h == c)) {
+        bail!("not a dusum summary: the header has no 'path' and 'user' columns");
+    }
+
+    let mut out = tempfile::Builder::new()
+        .prefix(".upload-")
+        .suffix(".part")
+        .tempfile_in(dir)?;
+    out.write_all(header.as_bytes())?;
+    let rest = io::copy(&mut input, &mut out).context("copying the summary")?;
+    let bytes = header.len() as u64 + rest;
+    if bytes > limit {
+        bail!("the summary is over {limit} bytes once decompressed");
+    }
+    out.as_file().sync_all()?;
+    let dest = dir.join(format!("{name}.sum.csv"));
+    out.persist(&dest)
+        .with_context(|| format!("storing {}", dest.display()))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uploads_are_checked_and_stored_decompressed() {
+        assert!(valid_name("site-b.2026-10-15"));
+        assert!(!valid_name(".hidden"));
+        assert!(!valid_name("../etc"));
+        assert!(!valid_name(""));
+
+        let tmp = tempfile::tempdir().unwrap();
+        let sum = "path,user,age,files,size,disk\n/a,alice,0,1,10,10\n";
+        let plain = tmp.path().join("plain");
+        std::fs::write(&plain, sum).unwrap();
+        assert_eq!(store(&plain, tmp.path(), "p", 1 << 20).unwrap(), sum.len() as u64);
+        assert_eq!(std::fs::read_to_string(tmp.path().join("p.sum.csv")).unwrap(), sum);
+
+        let zst = tmp.path().join("zst");
+        std::fs::write(&zst, zstd::encode_all(sum.as_bytes(), 1).unwrap()).unwrap();
+        store(&zst, tmp.path(), "p", 1 << 20).unwrap();
+        assert_eq!(std::fs::read_to_string(tmp.path().join("p.sum.csv")).unwrap(), sum);
+
+        // A small upload that inflates past the cap is refused.
+        let bomb = format!("{sum}{}", "/a,alice,0,1,10,10\n".repeat(10_000));
+        std::fs::write(&zst, zstd::encode_all(bomb.as_bytes(), 19).unwrap()).unwrap();
+        assert!(std::fs::metadata(&zst).unwrap().len() < 4096);
+        let err = store(&zst, tmp.path(), "b", 4096).unwrap_err();
+        assert!(err.to_string().contains("over 4096 bytes"), "{err}");
+        assert!(!tmp.path().join("b.sum.csv").exists());
+
+        let scan = tmp.path().join("scan");
+        std::fs::write(&scan, "INODE,ATIME,MTIME,UID,GID,MODE,SIZE,DISK,PATH\n").unwrap();
+        let err = store(&scan, tmp.path(), "s", 1 << 20).unwrap_err();
+        assert!(err.to_string().contains("not a dusum summary"));
+        assert!(!tmp.path().join("s.sum.csv").exists());
+        let left: Vec<_> = std::fs::read_dir(tmp.path())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with(".upload-"))
+            .collect();
+        assert!(left.is_empty());
+    }
+}
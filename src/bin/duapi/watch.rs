@@ -0,0 +1,271 @@
+// rs/src/bin/duapi/watch.rs
+//
+// `--watch-dir DIR`: named datasets from the summaries a pipeline drops in
+// DIR. Every `POLL` the directory is listed, and the newest `--watch-keep`
+// `*.sum.csv` files (by modification time) are served as datasets named
+// after the file (`fs-2026-10-15.sum.csv` -> `fs-2026-10-15`), each indexed
+// by dudb into `<name>.db` beside it. A summary is indexed once its size and
+// mtime held still for a whole pass, so a half-copied file is not loaded; a
+// summary that changes later is indexed again and swapped in. Datasets that
+// fall out of the newest `--watch-keep`, or whose summary is gone, are
+// retired: unloaded and their DB deleted, the summary left alone. A DB newer
+// than its summary is reused, so a restart does not index everything again.
+// A summary dudb fails on is skipped until it changes.
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tokio::process::Command;
+
+use crate::agents::{exe, run_step};
+use crate::dataset;
+
+const POLL: Duration = Duration::from_secs(30);
+const SUFFIX: &str = ".sum.csv";
+
+#[derive(Debug, Clone, PartialEq)]
+struct Summary {
+    name: String,
+    path: PathBuf,
+    size: u64,
+    mtime: SystemTime,
+}
+
+pub struct Watcher {
+    dir: PathBuf,
+    keep: usize,
+    dudb: PathBuf,
+    /// (size, mtime) of summaries seen last pass and not loaded yet
+    pending: HashMap<String, (u64, SystemTime)>,
+    /// mtime of the summary each served dataset was built from
+    loaded: HashMap<String, SystemTime>,
+    /// mtime of summaries dudb failed on
+    failed: HashMap<String, SystemTime>,
+}
+
+/// Watch `dir` in the background for as long as duapi runs.
+pub fn start(dir: PathBuf, keep: usize) {
+    let bin_dir = std::env::current_exe()
+        .ok()
+        .and_then(|e| e.parent().map(Path::to_path_buf))
+        .unwrap_or_else(|| PathBuf::from("."));
+    let mut w = Watcher::new(dir, keep, bin_dir.join(exe("dudb")));
+    tokio::spawn(async move {
+        loop {
+            w.pass().await;
+            tokio::time::sleep(POLL).await;
+        }
+    });
+}
+
+/// `*.sum.csv` files in `dir`, newest first.
+fn summaries(dir: &Path) -> Result<Vec<Summary>> {
+    let mut out = Vec::new();
+    for e in std::fs::read_dir(dir).with_context(|| format!("listing {}", dir.display()))? {
+        let e = e?;
+        let file_name = e.file_name();
+        let Some(name) = file_name.to_str().and_then(|n| n.strip_suffix(SUFFIX)) else {
+            continue;
+        };
+        let meta = e.metadata()?;
+        if name.is_empty() || !meta.is_file() {
+            continue;
+        }
+        out.push(Summary {
+            name: name.to_string(),
+            path: e.path(),
+            size: meta.len(),
+            mtime: meta.modified()?,
+        });
+    }
+    out.sort_by(|a, b| b.mtime.cmp(&a.mtime).then_with(|| a.name.cmp(&b.name)));
+    Ok(out)
+}
+
+fn remove_db(db: &Path) {
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{suffix}", db.display()));
+    }
+}
+
+impl Watcher {
+    pub fn new(dir: PathBuf, keep: usize, dudb: PathBuf) -> Self {
+        Self {
+            dir,
+            keep: keep.max(1),
+            dudb,
+            pending: HashMap::new(),
+            loaded: HashMap::new(),
+            failed: HashMap::new(),
+        }
+    }
+
+    fn db_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.db"))
+    }
+
+    pub async fn pass(&mut self) {
+        let all = match summaries(&self.dir) {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::warn!(dir = %self.dir.display(), err = %format!("{e:#}"), "watch dir unreadable");
+                return;
+            }
+        };
+        let newest: Vec<Summary> = all.into_iter().take(self.keep).collect();
+        for s in &newest {
+            if self.loaded.get(&s.name) == Some(&s.mtime) || self.failed.get(&s.name) == Some(&s.mtime) {
+                continue;
+            }
+            match self.load(s).await {
+                Ok(false) => {}
+                Ok(true) => {
+                    self.pending.remove(&s.name);
+                    self.failed.remove(&s.name);
+                    self.loaded.insert(s.name.clone(), s.mtime);
+                }
+                Err(e) => {
+                    tracing::warn!(dataset = %s.name, err = %format!("{e:#}"), "watched summary not loaded");
+                    self.pending.remove(&s.name);
+                    self.failed.insert(s.name.clone(), s.mtime);
+                }
+            }
+        }
+
+        let keep: HashSet<&str> = newest.iter().map(|s| s.name.as_str()).collect();
+        let retired: Vec<String> = self
+            .loaded
+            .keys()
+            .filter(|n| !keep.contains(n.as_str()))
+            .cloned()
+            .collect();
+        for name in retired {
+            self.loaded.remove(&name);
+            dataset::unload_named(&name);
+            remove_db(&self.db_path(&name));
+            tracing::info!(dataset = %name, "watched dataset retired");
+        }
+        self.pending.retain(|n, _| keep.contains(n.as_str()));
+        self.failed.retain(|n, _| keep.contains(n.as_str()));
+    }
+
+    /// Serve `s`, indexing it first unless its DB is up to date. False
+    /// while the summary is still changing.
+    async fn load(&mut self, s: &Summary) -> Result<bool> {
+        let db = self.db_path(&s.name);
+        let fresh = std::fs::metadata(&db)
+            .and_then(|m| m.modified())
+            .is_ok_and(|t| t >= s.mtime);
+        if !fresh {
+            let seen = self.pending.insert(s.name.clone(), (s.size, s.mtime));
+            if seen != Some((s.size, s.mtime)) {
+                return Ok(false);
+            }
+            let building = PathBuf::from(format!("{}.loading", db.display()));
+            let mut dudb = Command::new(&self.dudb);
+            dudb.arg(&s.path).arg("-o").arg(&building).arg("--rebuild");
+            run_step(dudb, "dudb").await?;
+            std::fs::rename(&building, &db)
+                .with_context(|| format!("replacing {} with {}", db.display(), building.display()))?;
+        }
+        let name = s.name.clone();
+        let ds = tokio::task::spawn_blocking(move || dataset::load_named(&name, &db)).await??;
+        tracing::info!(dataset = %s.name, path = %ds.path.display(), built_at = %ds.built_at, "watched dataset loaded");
+        Ok(true)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+    use std::os::unix::fs::PermissionsExt;
+    use tower::ServiceExt;
+
+    fn touch(path: &Path, body: &str, age_secs: u64) {
+        std::fs::write(path, body).unwrap();
+        let f = std::fs::File::options().write(true).open(path).unwrap();
+        f.set_modified(SystemTime::now() - Duration::from_secs(age_secs)).unwrap();
+    }
+
+    /// What a data route answers with `?dataset=name`: the path it read.
+    async fn picked(name: &str) -> (StatusCode, String) {
+        let app = Router::new()
+            .route("/x", get(|| async { dataset::current().unwrap().path.display().to_string() }))
+            .route_layer(axum::middleware::from_fn(dataset::require_dataset));
+        let req = axum::http::Request::builder()
+            .uri(format!("/x?dataset={name}"))
+            .body(Body::empty())
+            .unwrap();
+        let resp = app.oneshot(req).await.unwrap();
+        let status = resp.status();
+        let body = to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn served(prefix: &str) -> Vec<String> {
+        dataset::named_list()
+            .into_iter()
+            .map(|(n, _)| n)
+            .filter(|n| n.starts_with(prefix))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn serves_the_newest_summaries_and_retires_the_rest() {
+        let tmp = tempfile::tempdir().unwrap();
+        let test_db = dutopia::db::test_support::build_test_db();
+        // dudb stand-in: copies a real index to the path after -o.
+        let dudb = tmp.path().join("dudb");
+        std::fs::write(
+            &dudb,
+            format!(
+                "#!/bin/sh\ncase \"$1\" in *bad*) echo 'Error: bad CSV' >&2; exit 1;; esac\ncp {:?} \"$3\"\n",
+                test_db.path
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&dudb, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let dir = tmp.path().join("sums");
+        std::fs::create_dir(&dir).unwrap();
+        for (name, age) in [("wt-old", 300), ("wt-mid", 200), ("wt-new", 100)] {
+            touch(&dir.join(format!("{name}.sum.csv")), "x", age);
+        }
+        touch(&dir.join("notes.csv"), "x", 0);
+
+        let mut w = Watcher::new(dir.clone(), 2, dudb);
+        w.pass().await;
+        assert!(served("wt-").is_empty(), "loaded before the files held still");
+        w.pass().await;
+        assert_eq!(served("wt-"), ["wt-mid", "wt-new"]);
+        assert!(dir.join("wt-new.db").exists());
+        assert_eq!(picked("wt-new").await, (StatusCode::OK, dir.join("wt-new.db").display().to_string()));
+        assert_eq!(picked("wt-old").await.0, StatusCode::NOT_FOUND);
+        assert!(!dir.join("wt-old.db").exists());
+
+        // A newer summary pushes the oldest served one out.
+        touch(&dir.join("wt-next.sum.csv"), "x", 50);
+        w.pass().await;
+        w.pass().await;
+        assert_eq!(served("wt-"), ["wt-new", "wt-next"]);
+        assert!(!dir.join("wt-mid.db").exists());
+        assert!(dir.join("wt-mid.sum.csv").exists());
+
+        // A fresh DB is reused at once; a summary dudb rejects is skipped.
+        let mut again = Watcher::new(dir.clone(), 3, PathBuf::from("/nonexistent/dudb"));
+        again.pass().await;
+        assert_eq!(served("wt-"), ["wt-new", "wt-next"]);
+        touch(&dir.join("wt-bad.sum.csv"), "x", 0);
+        w.pass().await;
+        w.pass().await;
+        assert!(!served("wt-").contains(&"wt-bad".to_string()));
+        assert_eq!(w.failed.len(), 1);
+
+        for name in served("wt-") {
+            dataset::unload_named(&name);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+// rs/src/bin/duclean/action.rs
+//
+// What is done to a candidate, and the audit log of it.
+//
+// `--delete` removes the path: a file or symlink with `remove_file`, a
+// directory with `remove_dir_all`, which deletes links inside it and never
+// follows them. `--archive-to DIR` first writes the path into
+// `DIR/<path with '/' as '_'>.tar` with the system `tar` (GNU tar, bsdtar
+// or Windows' tar.exe), then removes it the same way; if tar fails the
+// partial archive is deleted and the path left alone.
+//
+// Every path acted on, done or failed, appends one JSON line to the audit
+// log: when, who, on which host, what, and the outcome. The log is opened
+// in append mode and flushed per line, so it survives an interrupted run.
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use dutopia::util::get_hostname;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    DryRun,
+    Archive(PathBuf),
+    Delete,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::DryRun => "dry-run",
+            Action::Archive(_) => "archive",
+            Action::Delete => "delete",
+        }
+    }
+}
+
+/// Apply `action` to `path`; the archive written, for `Archive`.
+pub fn apply(action: &Action, path: &Path) -> Result<Option<PathBuf>> {
+    match action {
+        Action::DryRun => Ok(None),
+        Action::Delete => {
+            remove(path).with_context(|| format!("removing {}", path.display()))?;
+            Ok(None)
+        }
+        Action::Archive(dir) => archive(path, dir).map(Some),
+    }
+}
+
+fn remove(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// `/proj/old/run1` -> `proj_old_run1`; `C:\data\x` -> `C_data_x`.
+fn archive_stem(path: &Path) -> String {
+    path.to_string_lossy()
+        .split(['/', '\\', ':'])
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn archive(path: &Path, dir: &Path) -> Result<PathBuf> {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        bail!("{} has no parent folder", path.display());
+    };
+    let stem = archive_stem(path);
+    let mut dest = dir.join(format!("{stem}.tar"));
+    let mut n = 1;
+    while dest.exists() {
+        dest = dir.join(format!("{stem}.{n}.tar"));
+        n += 1;
+    }
+    let status = Command::new("tar")
+        .arg("-cf")
+        .arg(&dest)
+        .arg("-C")
+        .arg(parent)
+        .arg(name)
+        .status()
+        .context("running tar")?;
+    if !status.success() {
+        let _ = fs::remove_file(&dest);
+        bail!("tar of {} failed ({status})", path.display());
+    }
+    remove(path).with_context(|| {
+        format!("removing {} after archiving it to {}", path.display(), dest.display())
+    })?;
+    Ok(dest)
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditEntry<'a> {
+    pub time: String,
+    pub host: &'a str,
+    pub user: &'a str,
+    pub action: &'static str,
+    pub path: &'a str,
+    pub bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive: Option<String>,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub struct AuditLog {
+    file: File,
+    host: String,
+    user: String,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("opening audit log {}", path.display()))?;
+        let user = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".into());
+        Ok(Self {
+            file,
+            host: get_hostname(),
+            user,
+        })
+    }
+
+    pub fn record(
+        &mut self,
+        action: &Action,
+        path: &str,
+        bytes: u64,
+        result: &Result<Option<PathBuf>>,
+    ) -> Result<()> {
+        let entry = AuditEntry {
+            time: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            host: &self.host,
+            user: &self.user,
+            action: action.name(),
+            path,
+            bytes,
+            archive: result
+                .as_ref()
+                .ok()
+                .and_then(|a| a.as_ref())
+                .map(|a| a.display().to_string()),
+            ok: result.is_ok(),
+            error: result.as_ref().err().map(|e| format!("{e:#}")),
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn delete_and_archive_remove_the_path_and_are_audited() {
+        let tmp = tempdir().unwrap();
+        let old = tmp.path().join("old");
+        fs::create_dir_all(old.join("run1")).unwrap();
+        fs::write(old.join("run1/data.bin"), vec![0u8; 1000]).unwrap();
+        let gone = tmp.path().join("gone.txt");
+        fs::write(&gone, "x").unwrap();
+        let archives = tmp.path().join("archives");
+        fs::create_dir(&archives).unwrap();
+
+        assert_eq!(apply(&Action::DryRun, &gone).unwrap(), None);
+        assert!(gone.exists());
+        assert_eq!(apply(&Action::Delete, &gone).unwrap(), None);
+        assert!(!gone.exists());
+        assert!(apply(&Action::Delete, &gone).is_err());
+
+        let tar = apply(&Action::Archive(archives.clone()), &old).unwrap().unwrap();
+        assert!(!old.exists());
+        assert_eq!(tar.parent(), Some(archives.as_path()));
+        assert!(fs::metadata(&tar).unwrap().len() > 1000);
+        assert!(archive_stem(&old).ends_with("_old"));
+        assert_eq!(archive_stem(Path::new(r"C:\data\x")), "C_data_x");
+
+        let log_path = tmp.path().join("audit.jsonl");
+        let mut log = AuditLog::open(&log_path).unwrap();
+        let archived = Ok(Some(tar));
+        log.record(&Action::Archive(archives), "/tmp/old", 1000, &archived).unwrap();
+        let failed = Err(anyhow::anyhow!("boom"));
+        log.record(&Action::Delete, "/tmp/gone.txt", 1, &failed).unwrap();
+        let lines: Vec<serde_json::Value> = fs::read_to_string(&log_path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["action"], "archive");
+        assert!(lines[0]["archive"].as_str().unwrap().ends_with("_old.tar"));
+        assert_eq!(lines[1]["ok"], false);
+        assert_eq!(lines[1]["error"], "boom");
+    }
+}
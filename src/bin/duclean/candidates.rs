@@ -0,0 +1,174 @@
+// rs/src/bin/duclean/candidates.rs
+//
+// The paths to clean up, read from any CSV with a header row and a `path`
+// column: a dusum summary filtered down to the folders to reclaim, a list
+// exported from the UI, or one written by hand. Bytes come from a `disk`
+// column, else `size`, and are summed over the rows of a path (a summary
+// has one row per user and age). Columns are found by name and the rest are
+// ignored. Input order is kept, so a list ranked by size is worked through
+// largest first.
+use anyhow::{bail, Context, Result};
+use csv::{ReaderBuilder, Trim};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use dutopia::util::{parse_int, replace_path_prefix};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub path: String,
+    pub bytes: u64,
+}
+
+/// Candidates of the CSV at `path`, each path once.
+pub fn load(path: &Path) -> Result<Vec<Candidate>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("reading candidates {}", path.display()))?;
+    parse(&text).with_context(|| format!("in {}", path.display()))
+}
+
+pub fn parse(text: &str) -> Result<Vec<Candidate>> {
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .trim(Trim::All)
+        .comment(Some(b'#'))
+        .from_reader(text.as_bytes());
+    let headers = rdr.headers()?.clone();
+    let col = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let Some(path_col) = col("path") else {
+        bail!("candidates need a 'path' column");
+    };
+    let bytes_col = col("disk").or_else(|| col("size"));
+
+    let mut out: Vec<Candidate> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for rec in rdr.records() {
+        let rec = rec?;
+        let Some(path) = rec.get(path_col).filter(|p| !p.is_empty()) else {
+            continue;
+        };
+        let bytes = bytes_col
+            .map(|c| parse_int::<u64>(rec.get(c).map(str::as_bytes)))
+            .unwrap_or(0);
+        match index.get(path) {
+            Some(&i) => out[i].bytes += bytes,
+            None => {
+                index.insert(path.to_string(), out.len());
+                out.push(Candidate {
+                    path: path.to_string(),
+                    bytes,
+                });
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Drops candidates inside another candidate, since cleaning the outer one
+/// takes them along. Returns the rest and how many were dropped.
+pub fn drop_nested(cands: Vec<Candidate>) -> (Vec<Candidate>, usize) {
+    let covered = |c: &Candidate, all: &[Candidate]| {
+        all.iter().any(|o| {
+            o.path.len() < c.path.len()
+                && replace_path_prefix(c.path.as_bytes(), o.path.as_bytes(), b"").is_some()
+        })
+    };
+    let keep: Vec<bool> = cands.iter().map(|c| !covered(c, &cands)).collect();
+    let before = cands.len();
+    let out: Vec<Candidate> = cands
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(c, k)| k.then_some(c))
+        .collect();
+    let dropped = before - out.len();
+    (out, dropped)
+}
+
+/// Why `path` may not be cleaned up: not absolute, climbing with `..`, or
+/// the root of a filesystem. `None` when it may.
+pub fn refusal(path: &Path) -> Option<&'static str> {
+    if !path.is_absolute() {
+        return Some("not an absolute path");
+    }
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Some("contains '..'");
+    }
+    if path.parent().is_none() || dutopia::util::is_volume_root(path) {
+        return Some("a filesystem root");
+    }
+    None
+}
+
+/// `path` with symlinks resolved as far as it exists, so that candidates
+/// and the files duclean writes compare alike.
+pub fn resolve(path: &Path) -> PathBuf {
+    if let Ok(p) = path.canonicalize() {
+        return p;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if parent.as_os_str().is_empty() => {
+            resolve(Path::new(".")).join(name)
+        }
+        (Some(parent), Some(name)) => resolve(parent).join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Whether cleaning up `path` would take one of `kept` (resolved paths
+/// duclean writes to) along.
+pub fn holds(path: &Path, kept: &[PathBuf]) -> bool {
+    let path = resolve(path);
+    kept.iter().any(|k| k.starts_with(&path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_rows_merge_and_nested_paths_drop() {
+        let cands = parse(
+            "path,user,age,files,size,disk\n\
+             /proj/old,alice,2,10,500,400\n\
+             /proj/old,bob,2,1,100,100\n\
+             /proj/old/run1,alice,2,5,200,200\n\
+             /proj/older,bob,1,1,30,30\n\
+             ,bob,1,1,1,1\n",
+        )
+        .unwrap();
+        assert_eq!(cands.len(), 3);
+        assert_eq!(cands[0], Candidate { path: "/proj/old".into(), bytes: 500 });
+
+        let (kept, dropped) = drop_nested(cands);
+        assert_eq!(dropped, 1);
+        let paths: Vec<_> = kept.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(paths, ["/proj/old", "/proj/older"]);
+
+        let bare = parse("Path\n/tmp/x\n").unwrap();
+        assert_eq!(bare[0].bytes, 0);
+        assert!(parse("folder,size\n/a,1\n").is_err());
+
+        assert_eq!(refusal(Path::new("/")), Some("a filesystem root"));
+        assert_eq!(refusal(Path::new("proj/old")), Some("not an absolute path"));
+        assert_eq!(refusal(Path::new("/proj/../etc")), Some("contains '..'"));
+    }
+
+    #[test]
+    fn archive_folder_and_audit_log_are_held() {
+        let tmp = tempfile::tempdir().unwrap();
+        let proj = tmp.path().join("proj");
+        std::fs::create_dir_all(proj.join("archives")).unwrap();
+        std::fs::create_dir_all(proj.join("old")).unwrap();
+        let kept = [
+            resolve(&proj.join("archives")),
+            resolve(&proj.join("logs/audit.jsonl")),
+        ];
+        assert!(holds(&proj, &kept));
+        assert!(holds(&proj.join("archives"), &kept));
+        assert!(holds(&proj.join("logs"), &kept));
+        assert!(holds(&proj.join("archives/../old/.."), &kept));
+        assert!(!holds(&proj.join("old"), &kept));
+        assert!(!holds(&proj.join("archives.bak"), &kept));
+    }
+}
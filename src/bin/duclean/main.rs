@@ -0,0 +1,203 @@
+// rs/src/bin/duclean/main.rs
+//
+// Acts on a list of cleanup candidates: shows what would go (the default,
+// `--dry-run`), archives each path to a tar file and removes it
+// (`--archive-to DIR`), or deletes it (`--delete`). Archiving and deleting
+// ask for confirmation on the terminal unless `--yes` is given, and every
+// path acted on is written to an audit log. Paths inside another candidate
+// are left to the outer one; relative paths, `..`, filesystem roots and
+// paths holding the archive folder or the audit log are refused.
+use anyhow::{bail, Result};
+use clap::{ColorChoice, Parser};
+use colored::Colorize;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use dutopia::util::exit::{self, ExitArgs, Outcome};
+use dutopia::util::{human_bytes, print_about};
+
+mod action;
+mod candidates;
+
+use action::{apply, Action, AuditLog};
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    color = ColorChoice::Auto,
+    about = "Archive or delete the paths of a cleanup candidate list"
+)]
+struct Args {
+    /// Candidate CSV with a `path` column (and optionally `disk` or `size`),
+    /// e.g. rows picked from a dusum summary
+    input: PathBuf,
+    /// Only list what would be done (the default without --archive-to or
+    /// --delete)
+    #[arg(long)]
+    dry_run: bool,
+    /// Write each path into a tar file in DIR, then remove it
+    #[arg(long, value_name = "DIR", conflicts_with = "delete")]
+    archive_to: Option<PathBuf>,
+    /// Delete each path
+    #[arg(long)]
+    delete: bool,
+    /// Do not ask for confirmation (needed when not on a terminal)
+    #[arg(short, long)]
+    yes: bool,
+    /// Audit log, one JSON line per path acted on (default:
+    /// <input_stem>.audit.jsonl)
+    #[arg(long, value_name = "FILE")]
+    audit_log: Option<PathBuf>,
+
+    #[command(flatten)]
+    exit: ExitArgs,
+}
+
+fn main() -> ExitCode {
+    print_about();
+
+    let args = Args::parse();
+    let result = run(&args);
+    exit::finish("duclean", &args.exit, result)
+}
+
+fn run(args: &Args) -> Result<Outcome> {
+    let start_time = std::time::Instant::now();
+    let action = match (&args.archive_to, args.delete) {
+        _ if args.dry_run => Action::DryRun,
+        (Some(dir), _) => Action::Archive(dir.clone()),
+        (None, true) => Action::Delete,
+        (None, false) => Action::DryRun,
+    };
+    if let Action::Archive(dir) = &action
+        && !dir.is_dir()
+    {
+        bail!("archive folder {} does not exist", dir.display());
+    }
+
+    let audit_path = args.audit_log.clone().unwrap_or_else(|| {
+        let stem = args
+            .input
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("duclean");
+        PathBuf::from(format!("{stem}.audit.jsonl"))
+    });
+    let kept: Vec<PathBuf> = args
+        .archive_to
+        .iter()
+        .chain([&audit_path])
+        .map(|p| candidates::resolve(p))
+        .collect();
+
+    // Refused paths go first, so that `/` cannot take every other candidate
+    // along as nested in it.
+    let mut refused = 0u64;
+    let mut cands = candidates::load(&args.input)?;
+    cands.retain(|c| {
+        let path = Path::new(&c.path);
+        let why = candidates::refusal(path).or_else(|| {
+            candidates::holds(path, &kept).then_some("holds the archive folder or the audit log")
+        });
+        match why {
+            Some(why) => {
+                eprintln!("{}", format!("[refused] {}: {why}", c.path).yellow());
+                refused += 1;
+                false
+            }
+            None => true,
+        }
+    });
+    let (cands, nested) = candidates::drop_nested(cands);
+    let total: u64 = cands.iter().map(|c| c.bytes).sum();
+    println!("Candidates   : {} paths, {}", cands.len(), human_bytes(total));
+    if nested > 0 {
+        println!("Nested       : {nested} paths inside other candidates, left to them");
+    }
+    match &action {
+        Action::DryRun => println!("Action       : dry run, nothing is changed"),
+        Action::Archive(dir) => println!("Action       : archive to {}", dir.display()),
+        Action::Delete => println!("Action       : delete"),
+    }
+
+    let mut audit = None;
+    if action != Action::DryRun {
+        if !args.yes && !confirm(&action, cands.len(), total)? {
+            println!("Aborted, nothing was changed.");
+            return Ok(Outcome::Ok);
+        }
+        println!("Audit log    : {}", audit_path.display());
+        audit = Some(AuditLog::open(&audit_path)?);
+    }
+
+    let (mut done, mut freed, mut missing, mut errors) = (0u64, 0u64, 0u64, refused);
+    for c in &cands {
+        let path = Path::new(&c.path);
+        if std::fs::symlink_metadata(path).is_err() {
+            println!("[missing] {}", c.path);
+            missing += 1;
+            continue;
+        }
+        let result = apply(&action, path);
+        if let Some(log) = audit.as_mut() {
+            log.record(&action, &c.path, c.bytes, &result)?;
+        }
+        match result {
+            Ok(archive) => {
+                done += 1;
+                freed += c.bytes;
+                match archive {
+                    Some(tar) => println!("[archived] {} -> {}", c.path, tar.display()),
+                    None if action == Action::Delete => println!("[deleted] {}", c.path),
+                    None => println!("[dry-run] {} ({})", c.path, human_bytes(c.bytes)),
+                }
+            }
+            Err(e) => {
+                errors += 1;
+                eprintln!("{}", format!("[error] {e:#}").red());
+            }
+        }
+    }
+
+    let verb = match action {
+        Action::DryRun => "To clean up ",
+        Action::Archive(_) => "Archived    ",
+        Action::Delete => "Deleted     ",
+    };
+    println!("{verb} : {done} paths, {}", human_bytes(freed));
+    if missing > 0 {
+        println!("Missing      : {missing} paths already gone");
+    }
+    if refused > 0 {
+        println!("{}", format!("Refused      : {refused} paths").yellow());
+    }
+    if errors > refused {
+        println!("{}", format!("Errors       : {} paths", errors - refused).red());
+    }
+    println!(
+        "Elapsed time : {:.3} sec.",
+        start_time.elapsed().as_secs_f64()
+    );
+    Ok(Outcome::with_errors(errors))
+}
+
+/// Ask on the terminal before archiving or deleting; refuses to guess when
+/// there is none to ask on.
+fn confirm(action: &Action, paths: usize, bytes: u64) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        bail!(
+            "no terminal to confirm on; pass --yes to {} without asking",
+            action.name()
+        );
+    }
+    print!(
+        "{} {paths} paths ({})? Type 'yes' to go on: ",
+        if *action == Action::Delete { "Delete" } else { "Archive and remove" },
+        human_bytes(bytes)
+    );
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().eq_ignore_ascii_case("yes"))
+}
@@ -0,0 +1,126 @@
+// rs/src/bin/ducron/main.rs
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use clap::{ColorChoice, Parser};
+use colored::Colorize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use dutopia::util::print_about;
+
+mod runner;
+mod schedule;
+
+use runner::{append_history, run_pipeline, RunRecord};
+use schedule::Config;
+
+/// Longest sleep between schedule checks, so clock changes are noticed.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    color = ColorChoice::Auto,
+    about = "Run scheduled duscan -> dusum pipelines with overlap protection and retries"
+)]
+struct Args {
+    /// Schedule file (TOML)
+    config: PathBuf,
+    /// Run this pipeline once now and exit (non-zero if it fails)
+    #[arg(long, value_name = "NAME", conflicts_with = "check")]
+    once: Option<String>,
+    /// Validate the schedule, print the next run times and exit
+    #[arg(long)]
+    check: bool,
+}
+
+fn main() -> Result<()> {
+    print_about();
+    let args = Args::parse();
+    let cfg = Config::load(&args.config)?;
+
+    println!("Work dir     : {}", cfg.work_dir.display());
+    println!("Binaries     : {}", cfg.bin_dir.display());
+    println!("History      : {}", cfg.history.display());
+    let now = Local::now();
+    for p in &cfg.pipelines {
+        println!(
+            "Pipeline     : {} next at {} (retries {})",
+            p.name,
+            p.schedule.next_after(now).format("%Y-%m-%d %H:%M"),
+            p.retries
+        );
+    }
+    if args.check {
+        return Ok(());
+    }
+    std::fs::create_dir_all(&cfg.work_dir)
+        .with_context(|| format!("creating {}", cfg.work_dir.display()))?;
+
+    if let Some(name) = &args.once {
+        let Some(p) = cfg.pipelines.iter().find(|p| &p.name == name) else {
+            bail!("no pipeline named '{name}'");
+        };
+        let rec = run_pipeline(&cfg, p);
+        record(&cfg, &rec);
+        if rec.status != "ok" {
+            bail!("pipeline {name} {}", rec.status);
+        }
+        return Ok(());
+    }
+
+    daemon(Arc::new(cfg))
+}
+
+fn daemon(cfg: Arc<Config>) -> Result<()> {
+    let mut next: Vec<_> = cfg
+        .pipelines
+        .iter()
+        .map(|p| p.schedule.next_after(Local::now()))
+        .collect();
+    loop {
+        let now = Local::now();
+        for (i, p) in cfg.pipelines.iter().enumerate() {
+            if next[i] > now {
+                continue;
+            }
+            next[i] = p.schedule.next_after(now);
+            println!("[{}] {} started", now.format("%F %T"), p.name);
+            // A run still holding the pipeline lock turns this one into a
+            // "skipped" record, so overlapping runs never pile up.
+            let cfg = cfg.clone();
+            thread::spawn(move || {
+                let rec = run_pipeline(&cfg, &cfg.pipelines[i]);
+                record(&cfg, &rec);
+            });
+        }
+        let soonest = next.iter().min().copied().unwrap_or(now);
+        let wait = (soonest - Local::now()).to_std().unwrap_or_default();
+        thread::sleep(wait.clamp(Duration::from_millis(100), MAX_SLEEP));
+    }
+}
+
+fn record(cfg: &Config, rec: &RunRecord) {
+    let line = format!(
+        "[{}] {} {} after {:.0}s ({} attempts)",
+        Local::now().format("%F %T"),
+        rec.pipeline,
+        rec.status,
+        rec.elapsed_secs,
+        rec.attempts
+    );
+    match rec.status {
+        "ok" => println!("{}", line.green()),
+        "skipped" => println!("{}", line.yellow()),
+        _ => {
+            let step = rec.failed_step.unwrap_or("-");
+            let err = rec.error.as_deref().unwrap_or("");
+            eprintln!("{} at {step}: {err}", line.red());
+        }
+    }
+    if let Err(e) = append_history(&cfg.history, rec) {
+        eprintln!("{}", format!("Cannot write history: {e:#}").red());
+    }
+}
@@ -0,0 +1,330 @@
+// rs/src/bin/ducron/runner.rs
+use anyhow::{bail, Context, Result};
+use chrono::{Local, SecondsFormat};
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use dutopia::util::exit::Outcome;
+
+use crate::schedule::{Config, Pipeline};
+
+/// One line of the run history (JSON Lines).
+#[derive(Debug, Serialize)]
+pub struct RunRecord {
+    pub pipeline: String,
+    pub started: String,
+    pub finished: String,
+    /// "ok", "failed", or "skipped" when the previous run still holds the lock
+    pub status: &'static str,
+    /// Step executions including retries
+    pub attempts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_step: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub elapsed_secs: f64,
+}
+
+/// Lock file held for the duration of a run; removed on drop.
+struct Lock(PathBuf);
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Take `<work_dir>/<name>.lock`, or `None` while another live process holds
+/// it. A lock left by a dead process is replaced.
+fn acquire_lock(path: &Path) -> Result<Option<Lock>> {
+    for _ in 0..2 {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut f) => {
+                writeln!(f, "{}", std::process::id())?;
+                return Ok(Some(Lock(path.to_path_buf())));
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                let holder = fs::read_to_string(path)
+                    .ok()
+                    .and_then(|s| s.trim().parse::<u32>().ok());
+                if holder.is_some_and(pid_alive) {
+                    return Ok(None);
+                }
+                eprintln!("Removing stale lock {}", path.display());
+                fs::remove_file(path)?;
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("creating lock {}", path.display()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(unix)]
+fn pid_alive(pid: u32) -> bool {
+    let r = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    r == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn pid_alive(_pid: u32) -> bool {
+    // No cheap liveness probe; a leftover lock must be removed by hand.
+    true
+}
+
+pub struct Paths {
+    pub scan: PathBuf,
+    pub sum: PathBuf,
+    pub log: PathBuf,
+    lock: PathBuf,
+}
+
+impl Paths {
+    pub fn new(cfg: &Config, p: &Pipeline) -> Self {
+        Self {
+            scan: cfg.work_dir.join(format!("{}.csv", p.name)),
+            sum: cfg.work_dir.join(format!("{}.sum.csv", p.name)),
+            log: cfg.work_dir.join(format!("{}.log", p.name)),
+            lock: cfg.work_dir.join(format!("{}.lock", p.name)),
+        }
+    }
+}
+
+/// Run every step of `p` in order, retrying each failed step; the first
+/// step that fails for good ends the run.
+pub fn run_pipeline(cfg: &Config, p: &Pipeline) -> RunRecord {
+    let start = std::time::Instant::now();
+    let started = now();
+    let mut rec = RunRecord {
+        pipeline: p.name.clone(),
+        started,
+        finished: String::new(),
+        status: "ok",
+        attempts: 0,
+        failed_step: None,
+        error: None,
+        elapsed_secs: 0.0,
+    };
+    let paths = Paths::new(cfg, p);
+    match acquire_lock(&paths.lock) {
+        Ok(Some(lock)) => {
+            if let Err((step, e)) = run_steps(cfg, p, &paths, &mut rec.attempts) {
+                rec.status = "failed";
+                rec.failed_step = Some(step);
+                rec.error = Some(format!("{e:#}"));
+            }
+            drop(lock);
+        }
+        Ok(None) => {
+            rec.status = "skipped";
+            rec.error = Some("previous run still in progress".to_string());
+        }
+        Err(e) => {
+            rec.status = "failed";
+            rec.failed_step = Some("lock");
+            rec.error = Some(format!("{e:#}"));
+        }
+    }
+    rec.finished = now();
+    rec.elapsed_secs = start.elapsed().as_secs_f64();
+    rec
+}
+
+/// Builds a fresh `Command` for each attempt of a step.
+type StepCommand<'a> = Box<dyn Fn() -> Command + 'a>;
+
+fn run_steps(
+    cfg: &Config,
+    p: &Pipeline,
+    paths: &Paths,
+    attempts: &mut u32,
+) -> std::result::Result<(), (&'static str, anyhow::Error)> {
+    let scan = || {
+        let mut c = Command::new(cfg.bin_dir.join(exe("duscan")));
+        c.args(&p.scan).arg("-o").arg(&paths.scan).arg("-q");
+        c
+    };
+    let sum = || {
+        let mut c = Command::new(cfg.bin_dir.join(exe("dusum")));
+        c.arg(&paths.scan).arg("-o").arg(&paths.sum).args(&p.sum);
+        c
+    };
+    let mut steps: Vec<(&'static str, StepCommand)> =
+        vec![("scan", Box::new(scan)), ("sum", Box::new(sum))];
+    if let Some(cmd) = &p.upload {
+        steps.push(("upload", Box::new(move || shell(&expand(cmd, p, paths)))));
+    }
+    if let Some(cmd) = &p.reload {
+        steps.push(("reload", Box::new(move || shell(&expand(cmd, p, paths)))));
+    }
+
+    for (step, make) in steps {
+        let mut tries = 0;
+        loop {
+            tries += 1;
+            *attempts += 1;
+            // scan and sum follow the batch tools' exit codes; 1 and 3
+            // still leave an output for the next step.
+            let tool = matches!(step, "scan" | "sum");
+            match run_logged(make(), &paths.log, &p.name, step, tool) {
+                Ok(()) => break,
+                Err(e) if tries > p.retries => return Err((step, e)),
+                Err(e) => {
+                    eprintln!(
+                        "[{}] {step} failed (attempt {tries}): {e:#}; retrying in {}s",
+                        p.name,
+                        p.retry_delay.as_secs()
+                    );
+                    std::thread::sleep(p.retry_delay);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run `cmd` with stdout/stderr appended to the pipeline log. For a dutopia
+/// batch `tool` any exit code that left an output counts as done.
+fn run_logged(mut cmd: Command, log: &Path, name: &str, step: &str, tool: bool) -> Result<()> {
+    let mut out = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log)
+        .with_context(|| format!("opening log {}", log.display()))?;
+    writeln!(out, "=== {} {name} {step}: {:?}", now(), cmd)?;
+    let mut note = out.try_clone()?;
+    let err = out.try_clone()?;
+    let status = cmd
+        .stdin(Stdio::null())
+        .stdout(out)
+        .stderr(err)
+        .status()
+        .with_context(|| format!("starting {:?}", cmd.get_program()))?;
+    if status.success() {
+        return Ok(());
+    }
+    match status.code().and_then(Outcome::from_code) {
+        Some(o) if tool && o.wrote_output() => {
+            writeln!(note, "=== {} {name} {step}: finished with exit code {} ({o:?})", now(), o.code())?;
+            Ok(())
+        }
+        _ => bail!("{step} exited with {status} (see {})", log.display()),
+    }
+}
+
+fn expand(cmd: &str, p: &Pipeline, paths: &Paths) -> String {
+    cmd.replace("{name}", &p.name)
+        .replace("{scan}", &paths.scan.display().to_string())
+        .replace("{sum}", &paths.sum.display().to_string())
+}
+
+fn shell(cmd: &str) -> Command {
+    let mut c = if cfg!(windows) {
+        let mut c = Command::new("cmd");
+        c.arg("/C");
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.arg("-c");
+        c
+    };
+    c.arg(cmd);
+    c
+}
+
+fn exe(name: &str) -> String {
+    format!("{name}{}", std::env::consts::EXE_SUFFIX)
+}
+
+fn now() -> String {
+    Local::now().to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+pub fn append_history(path: &Path, rec: &RunRecord) -> Result<()> {
+    let mut f: File = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("opening history {}", path.display()))?;
+    writeln!(f, "{}", serde_json::to_string(rec)?)?;
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn script(dir: &Path, name: &str, body: &str) {
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    fn config(dir: &Path, retries: u32, upload: &str) -> Config {
+        let text = format!(
+            "[defaults]\nwork_dir = {:?}\nbin_dir = {:?}\nretry_delay = \"1s\"\n\
+             [[pipeline]]\nname = \"t\"\nevery = \"1h\"\nscan = [\"/x\"]\n\
+             retries = {retries}\nupload = {upload:?}\n",
+            dir, dir
+        );
+        Config::parse(&text).unwrap()
+    }
+
+    #[test]
+    fn retries_failed_step_then_runs_the_rest() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        // duscan fails on its first call only.
+        script(
+            dir,
+            "duscan",
+            "n=$(cat \"$0.n\" 2>/dev/null || echo 0); echo $((n+1)) > \"$0.n\"; \
+             [ \"$n\" -ge 1 ] || exit 2; touch \"$3\"",
+        );
+        // Done with some rows skipped: not a failure.
+        script(dir, "dusum", "touch \"$3\"; exit 1");
+        let cfg = config(dir, 1, "cp {sum} {sum}.up");
+        let rec = run_pipeline(&cfg, &cfg.pipelines[0]);
+        assert_eq!(rec.status, "ok", "{:?}", rec.error);
+        assert_eq!(rec.attempts, 4);
+        assert!(dir.join("t.sum.csv.up").exists());
+        assert!(!dir.join("t.lock").exists());
+
+        let history = dir.join("h.jsonl");
+        append_history(&history, &rec).unwrap();
+        let line = fs::read_to_string(&history).unwrap();
+        assert!(line.contains("\"status\":\"ok\""));
+    }
+
+    #[test]
+    fn failed_step_stops_the_pipeline() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        script(dir, "duscan", "touch \"$3\"");
+        script(dir, "dusum", "exit 2");
+        let cfg = config(dir, 0, "touch {sum}.up");
+        let rec = run_pipeline(&cfg, &cfg.pipelines[0]);
+        assert_eq!(rec.status, "failed");
+        assert_eq!(rec.failed_step, Some("sum"));
+        assert!(!dir.join("t.sum.csv.up").exists());
+    }
+
+    #[test]
+    fn live_lock_skips_and_stale_lock_is_replaced() {
+        let tmp = tempfile::tempdir().unwrap();
+        let lock = tmp.path().join("x.lock");
+        fs::write(&lock, format!("{}\n", std::process::id())).unwrap();
+        assert!(acquire_lock(&lock).unwrap().is_none());
+
+        fs::write(&lock, "999999999\n").unwrap();
+        let held = acquire_lock(&lock).unwrap();
+        assert!(held.is_some());
+        drop(held);
+        assert!(!lock.exists());
+    }
+}
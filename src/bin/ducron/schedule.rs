@@ -0,0 +1,310 @@
+// rs/src/bin/ducron/schedule.rs
+//
+// ducron schedule file (TOML). Every pipeline scans into `work_dir`, sums
+// the scan, then optionally runs `upload` and `reload` shell commands:
+//
+//   [defaults]
+//   work_dir = "/var/lib/dutopia"
+//   history = "/var/log/dutopia/ducron.jsonl"
+//   retries = 2              # extra attempts per failed step
+//   retry_delay = "10m"
+//
+//   [[pipeline]]
+//   name = "projects"
+//   at = ["02:30"]           # daily local times ...
+//   # every = "6h"           # ... or a fixed interval
+//   scan = ["/proj", "--workers", "32"]
+//   sum = ["--age", "30,365"]
+//   upload = "rsync -a {sum} web1:/data/"
+//   reload = "ssh web1 systemctl restart duapi"
+//
+// `upload`/`reload` run through `sh -c` with {name}, {scan} and {sum}
+// replaced by the pipeline name and output paths.
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Local, NaiveTime, TimeZone};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct FileDefaults {
+    work_dir: Option<PathBuf>,
+    bin_dir: Option<PathBuf>,
+    history: Option<PathBuf>,
+    retries: Option<u32>,
+    retry_delay: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FilePipeline {
+    name: String,
+    #[serde(default)]
+    at: Vec<String>,
+    every: Option<String>,
+    scan: Vec<String>,
+    #[serde(default)]
+    sum: Vec<String>,
+    upload: Option<String>,
+    reload: Option<String>,
+    retries: Option<u32>,
+    retry_delay: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    defaults: FileDefaults,
+    #[serde(default)]
+    pipeline: Vec<FilePipeline>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    /// Daily at these local times, sorted.
+    At(Vec<NaiveTime>),
+    Every(Duration),
+}
+
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    pub name: String,
+    pub schedule: Schedule,
+    pub scan: Vec<String>,
+    pub sum: Vec<String>,
+    pub upload: Option<String>,
+    pub reload: Option<String>,
+    pub retries: u32,
+    pub retry_delay: Duration,
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub work_dir: PathBuf,
+    /// Directory holding duscan and dusum (default: next to ducron)
+    pub bin_dir: PathBuf,
+    pub history: PathBuf,
+    pub pipelines: Vec<Pipeline>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading schedule {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("in {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let file: File = toml::from_str(text)?;
+        let d = file.defaults;
+        let work_dir = d.work_dir.unwrap_or_else(|| PathBuf::from("."));
+        let bin_dir = match d.bin_dir {
+            Some(b) => b,
+            None => std::env::current_exe()
+                .ok()
+                .and_then(|e| e.parent().map(Path::to_path_buf))
+                .unwrap_or_else(|| PathBuf::from(".")),
+        };
+        let history = d
+            .history
+            .unwrap_or_else(|| work_dir.join("ducron-history.jsonl"));
+        let default_delay = parse_interval(d.retry_delay.as_deref().unwrap_or("5m"))?;
+
+        let mut names = HashSet::new();
+        let mut pipelines = Vec::new();
+        for p in file.pipeline {
+            if p.name.is_empty()
+                || !p
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                bail!("pipeline name '{}' must be [A-Za-z0-9_-]+", p.name);
+            }
+            if !names.insert(p.name.clone()) {
+                bail!("pipeline '{}' defined twice", p.name);
+            }
+            if p.scan.is_empty() {
+                bail!("pipeline '{}': scan needs at least one folder", p.name);
+            }
+            let schedule = match (p.at.is_empty(), &p.every) {
+                (false, None) => {
+                    let mut times = p
+                        .at
+                        .iter()
+                        .map(|t| {
+                            NaiveTime::parse_from_str(t, "%H:%M").with_context(|| {
+                                format!("pipeline '{}': at '{t}' is not HH:MM", p.name)
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    times.sort();
+                    times.dedup();
+                    Schedule::At(times)
+                }
+                (true, Some(every)) => Schedule::Every(
+                    parse_interval(every).with_context(|| format!("pipeline '{}'", p.name))?,
+                ),
+                _ => bail!("pipeline '{}': set exactly one of `at` or `every`", p.name),
+            };
+            let retry_delay = match &p.retry_delay {
+                Some(s) => parse_interval(s).with_context(|| format!("pipeline '{}'", p.name))?,
+                None => default_delay,
+            };
+            pipelines.push(Pipeline {
+                name: p.name,
+                schedule,
+                scan: p.scan,
+                sum: p.sum,
+                upload: p.upload,
+                reload: p.reload,
+                retries: p.retries.or(d.retries).unwrap_or(0),
+                retry_delay,
+            });
+        }
+        if pipelines.is_empty() {
+            bail!("no [[pipeline]] entries");
+        }
+        Ok(Self {
+            work_dir,
+            bin_dir,
+            history,
+            pipelines,
+        })
+    }
+}
+
+/// `90s`, `15m`, `6h`, `1d`; a bare number is seconds.
+pub fn parse_interval(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let (num, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let n: u64 = num
+        .parse()
+        .with_context(|| format!("invalid interval '{s}'"))?;
+    let secs = match unit {
+        "" | "s" => n,
+        "m" => n * 60,
+        "h" => n * 3600,
+        "d" => n * 86400,
+        _ => bail!("invalid interval '{s}' (use s, m, h or d)"),
+    };
+    if secs == 0 {
+        bail!("interval '{s}' must be positive");
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+impl Schedule {
+    /// First run strictly after `after`. `Every` counts from `after`, i.e.
+    /// from daemon start and then from each scheduled run.
+    pub fn next_after(&self, after: DateTime<Local>) -> DateTime<Local> {
+        match self {
+            Schedule::Every(d) => after + ChronoDuration::from_std(*d).unwrap_or_default(),
+            Schedule::At(times) => {
+                let today = after.date_naive();
+                for day in 0..=2 {
+                    let date = today + ChronoDuration::days(day);
+                    for t in times {
+                        // Times skipped by a DST jump resolve to None and are
+                        // dropped; repeated ones take the first occurrence.
+                        if let Some(at) = Local.from_local_datetime(&date.and_time(*t)).earliest()
+                            && at > after
+                        {
+                            return at;
+                        }
+                    }
+                }
+                after + ChronoDuration::days(1)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"
+[defaults]
+work_dir = "/tmp/dutopia"
+retries = 2
+
+[[pipeline]]
+name = "projects"
+at = ["14:00", "02:30"]
+scan = ["/proj"]
+upload = "cp {sum} /srv/"
+
+[[pipeline]]
+name = "home"
+every = "6h"
+retries = 0
+retry_delay = "30s"
+scan = ["/home", "-w", "8"]
+"#;
+
+    #[test]
+    fn parse_sample_config() {
+        let c = Config::parse(SAMPLE).unwrap();
+        assert_eq!(c.work_dir, PathBuf::from("/tmp/dutopia"));
+        assert_eq!(c.history, PathBuf::from("/tmp/dutopia/ducron-history.jsonl"));
+        let p = &c.pipelines[0];
+        assert_eq!(p.retries, 2);
+        assert_eq!(p.retry_delay, Duration::from_secs(300));
+        assert_eq!(
+            p.schedule,
+            Schedule::At(vec![
+                NaiveTime::from_hms_opt(2, 30, 0).unwrap(),
+                NaiveTime::from_hms_opt(14, 0, 0).unwrap(),
+            ])
+        );
+        let h = &c.pipelines[1];
+        assert_eq!(h.schedule, Schedule::Every(Duration::from_secs(6 * 3600)));
+        assert_eq!((h.retries, h.retry_delay), (0, Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn reject_invalid_configs() {
+        let one = |body: &str| Config::parse(&format!("[[pipeline]]\n{body}"));
+        assert!(one("name = \"a\"\nscan = [\"/x\"]").is_err());
+        assert!(one("name = \"a\"\nat = [\"2:30pm\"]\nscan = [\"/x\"]").is_err());
+        assert!(one("name = \"a b\"\nevery = \"1h\"\nscan = [\"/x\"]").is_err());
+        assert!(one("name = \"a\"\nevery = \"1h\"\nat = [\"01:00\"]\nscan = [\"/x\"]").is_err());
+        assert!(one("name = \"a\"\nevery = \"1h\"\nscan = []").is_err());
+        assert!(one("name = \"a\"\nevery = \"1h\"\nscan = [\"/x\"]\ntypo = 1").is_err());
+        assert!(Config::parse("").is_err());
+    }
+
+    #[test]
+    fn intervals() {
+        assert_eq!(parse_interval("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_interval("15m").unwrap(), Duration::from_secs(900));
+        assert_eq!(parse_interval("1d").unwrap(), Duration::from_secs(86400));
+        assert!(parse_interval("0h").is_err());
+        assert!(parse_interval("5w").is_err());
+        assert!(parse_interval("h").is_err());
+    }
+
+    #[test]
+    fn next_daily_run() {
+        let at = Schedule::At(vec![
+            NaiveTime::from_hms_opt(2, 30, 0).unwrap(),
+            NaiveTime::from_hms_opt(14, 0, 0).unwrap(),
+        ]);
+        let t = |h, m| {
+            Local
+                .with_ymd_and_hms(2024, 3, 20, h, m, 0)
+                .earliest()
+                .unwrap()
+        };
+        assert_eq!(at.next_after(t(1, 0)), t(2, 30));
+        assert_eq!(at.next_after(t(2, 30)), t(14, 0));
+        let next = at.next_after(t(15, 0));
+        assert_eq!(next.date_naive(), t(0, 0).date_naive().succ_opt().unwrap());
+        let every = Schedule::Every(Duration::from_secs(3600));
+        assert_eq!(every.next_after(t(1, 0)), t(2, 0));
+    }
+}
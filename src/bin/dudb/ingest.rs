@@ -1,19 +1,58 @@
 // rs/src/bin/dudb/ingest.rs
 use anyhow::{Context, Result};
 use csv::ReaderBuilder;
+use dutopia::query::{canonical_key, nfc};
 use dutopia::util::dusum_parent;
-use memchr::memchr_iter;
+
+use crate::schema;
+use crossbeam::channel::{bounded, unbounded};
+use memchr::{memchr2_iter, memchr_iter};
 use rusqlite::{params, Connection};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom};
 use std::path::Path;
+use std::thread;
 
+/// Counts describing how faithfully the CSV made it into the DB. Written to
+/// `metadata` so duapi can report them at `/api/stats`.
 #[derive(Default, Debug)]
 pub struct IngestStats {
     pub rows_inserted: u64,
     pub paths_inserted: u64,
     pub users_inserted: u64,
+    /// Rows the CSV reader could not parse.
+    pub rows_malformed: u64,
+    /// Parsed rows dropped for missing columns, path, or user.
+    pub rows_skipped: u64,
+    /// Rows whose (path, user, age) key was already present: skipped, or
+    /// merged into the existing row in case-insensitive mode.
+    pub rows_duplicate: u64,
+    /// Deepest folder, in path components below the platform root.
+    pub max_depth: u64,
+    /// The CSV carried a `device` column (`dusum --by-device`).
+    pub by_device: bool,
+}
+
+impl IngestStats {
+    pub fn metadata(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("rows_malformed", self.rows_malformed.to_string()),
+            ("rows_skipped", self.rows_skipped.to_string()),
+            ("rows_duplicate", self.rows_duplicate.to_string()),
+            ("path_count", self.paths_inserted.to_string()),
+            ("user_count", self.users_inserted.to_string()),
+            ("max_depth", self.max_depth.to_string()),
+            ("by_device", u8::from(self.by_device).to_string()),
+        ]
+    }
+}
+
+/// Number of non-empty `/` or `\` separated components: `/` is 0,
+/// `/a/b` and `\\srv\shr` are 2.
+fn path_depth(path: &str) -> u64 {
+    path.split(['/', '\\']).filter(|s| !s.is_empty()).count() as u64
 }
 
 /// Synthetic-root sentinel: `dusum` does not emit this path. We insert one
@@ -36,31 +75,333 @@ pub fn count_lines(path: &Path) -> Result<usize> {
         count += memchr_iter(b'\n', &buf[..n]).count();
         last = Some(buf[n - 1]);
     }
-    if let Some(b) = last {
-        if b != b'\n' {
-            count += 1;
-        }
+    if let Some(b) = last
+        && b != b'\n'
+    {
+        count += 1;
     }
     Ok(count)
 }
 
+/// Key of `path` in the ingest path cache. In case-insensitive mode every
+/// spelling of a folder maps to the same key, so they share one `paths` row.
+fn path_key(path: &str, case_insensitive: bool) -> Cow<'_, str> {
+    if case_insensitive {
+        Cow::Owned(canonical_key(path, true))
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// INSERT for a stats-shaped table keyed by `key_cols`. With `merge`, a row
+/// hitting an existing key is added into it instead of failing.
+fn stats_insert_sql(table: &str, key_cols: &str, merge: bool) -> String {
+    let n = key_cols.split(',').count();
+    let placeholders: Vec<String> = (1..=n + 8).map(|i| format!("?{i}")).collect();
+    let mut sql = format!(
+        "INSERT INTO {table}
+          ({key_cols}, file_count, file_size, disk_bytes, linked_size, atime, mtime,
+           oldest_atime, oldest_mtime)
+         VALUES ({})",
+        placeholders.join(", ")
+    );
+    if merge {
+        sql.push_str(&format!(
+            " ON CONFLICT({key_cols}) DO UPDATE SET
+               file_count  = file_count  + excluded.file_count,
+               file_size   = file_size   + excluded.file_size,
+               disk_bytes  = disk_bytes  + excluded.disk_bytes,
+               linked_size = linked_size + excluded.linked_size,
+               atime       = MAX(atime, excluded.atime),
+               mtime       = MAX(mtime, excluded.mtime),
+               oldest_atime = {},
+               oldest_mtime = {}",
+            min_set("oldest_atime"),
+            min_set("oldest_mtime"),
+        ));
+    }
+    sql
+}
+
+/// SQL for the earlier of `col` and `excluded.col`, where 0 means unset.
+fn min_set(col: &str) -> String {
+    format!(
+        "CASE WHEN {col} = 0 THEN excluded.{col}
+              WHEN excluded.{col} = 0 THEN {col}
+              ELSE MIN({col}, excluded.{col}) END"
+    )
+}
+
+/// Bytes of CSV handed to a parsing thread at a time.
+const CHUNK_BYTES: usize = 4 << 20;
+
+/// A folder as a chunk first met it.
+struct ChunkPath {
+    path: String,
+    /// Cache key and `paths.path_key` (case-insensitive mode only)
+    key: Option<String>,
+    parent_key: Option<String>,
+}
+
+impl ChunkPath {
+    fn key(&self) -> &str {
+        self.key.as_deref().unwrap_or(&self.path)
+    }
+}
+
+struct ChunkRow {
+    /// Index into `Chunk::paths`
+    path: u32,
+    /// Index into `Chunk::users`
+    user: u32,
+    age: u8,
+    /// count, size, disk, linked, atime, mtime, oldest atime, oldest mtime
+    values: (u64, u64, u64, u64, i64, i64, i64, i64),
+    device: i64,
+}
+
+enum Item {
+    Row(ChunkRow),
+    Malformed(String),
+    Skipped,
+}
+
+/// One chunk of CSV records, parsed: its records in order and the folders
+/// and users they name, each once.
+#[derive(Default)]
+struct Chunk {
+    paths: Vec<ChunkPath>,
+    users: Vec<String>,
+    items: Vec<Item>,
+}
+
+/// End (past its newline) of the last whole record in `buf`, which starts
+/// at a record boundary. A newline inside a quoted field does not end one.
+fn last_record_end(buf: &[u8]) -> Option<usize> {
+    let mut quoted = false;
+    let mut end = None;
+    for i in memchr2_iter(b'"', b'\n', buf) {
+        if buf[i] == b'"' {
+            quoted = !quoted;
+        } else if !quoted {
+            end = Some(i + 1);
+        }
+    }
+    end
+}
+
+/// Cut `r` into runs of whole records of about `chunk_bytes` and pass them
+/// to `emit` until it returns false.
+fn split_records(mut r: impl Read, chunk_bytes: usize, mut emit: impl FnMut(Vec<u8>) -> bool) -> Result<()> {
+    let mut buf: Vec<u8> = Vec::with_capacity(chunk_bytes);
+    loop {
+        let want = buf.len() + chunk_bytes;
+        let read = r.by_ref().take((want - buf.len()) as u64).read_to_end(&mut buf)?;
+        if read == 0 {
+            if !buf.is_empty() {
+                emit(buf);
+            }
+            return Ok(());
+        }
+        // A record longer than a chunk keeps growing the buffer.
+        let Some(end) = last_record_end(&buf) else { continue };
+        let rest = buf.split_off(end);
+        if !emit(std::mem::replace(&mut buf, rest)) {
+            return Ok(());
+        }
+    }
+}
+
+/// Where each field sits in a record. dusum's optional columns (`device`,
+/// `duplicated_paths`, `oldest_accessed`, ...) come and go with its flags,
+/// so they are found by header name; a header without the base names (a
+/// hand-made CSV) falls back to dusum's fixed order for those.
+#[derive(Clone, Debug)]
+struct Columns {
+    path: usize,
+    user: usize,
+    age: usize,
+    files: usize,
+    size: usize,
+    disk: usize,
+    linked: usize,
+    accessed: usize,
+    modified: usize,
+    device: Option<usize>,
+    oldest_accessed: Option<usize>,
+    oldest_modified: Option<usize>,
+}
+
+impl Columns {
+    fn from_header(header: &csv::StringRecord) -> Self {
+        let col = |name: &str| header.iter().position(|h| h.trim() == name);
+        let base = |name: &str, i: usize| col(name).unwrap_or(i);
+        Columns {
+            path: base("path", 0),
+            user: base("user", 1),
+            age: base("age", 2),
+            files: base("files", 3),
+            size: base("size", 4),
+            disk: base("disk", 5),
+            linked: base("linked", 6),
+            accessed: base("accessed", 7),
+            modified: base("modified", 8),
+            device: col("device"),
+            oldest_accessed: col("oldest_accessed"),
+            oldest_modified: col("oldest_modified"),
+        }
+    }
+
+    /// Fields a record needs before it can be loaded.
+    fn required(&self) -> usize {
+        [
+            self.path, self.user, self.age, self.files, self.size, self.disk, self.linked,
+            self.accessed, self.modified,
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or(0)
+            + 1
+    }
+}
+
+fn parse_chunk(bytes: &[u8], cols: &Columns, case_insensitive: bool) -> Chunk {
+    let mut chunk = Chunk::default();
+    let mut path_idx: HashMap<String, u32> = HashMap::new();
+    let mut user_idx: HashMap<String, u32> = HashMap::new();
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(bytes);
+    for rec in rdr.records() {
+        let rec = match rec {
+            Ok(r) => r,
+            Err(e) => {
+                chunk.items.push(Item::Malformed(e.to_string()));
+                continue;
+            }
+        };
+        if rec.len() < cols.required() {
+            chunk.items.push(Item::Skipped);
+            continue;
+        }
+        // Path is stored byte-for-byte from dusum. No canonicalization
+        // here — util::get_folder_ancestors is the single
+        // source of truth for the on-disk format. The only exception is
+        // NFC in case-insensitive mode.
+        let path = rec.get(cols.path).unwrap_or("");
+        let path: Cow<str> = if case_insensitive { nfc(path) } else { Cow::Borrowed(path) };
+        let path = path.as_ref();
+        let user = rec.get(cols.user).unwrap_or("").trim();
+        if path.is_empty() || user.is_empty() {
+            chunk.items.push(Item::Skipped);
+            continue;
+        }
+        let num = |i: Option<usize>| -> i64 {
+            i.and_then(|i| rec.get(i)).and_then(|s| s.trim().parse().ok()).unwrap_or(0)
+        };
+        let count = |i: usize| -> u64 { rec.get(i).and_then(|s| s.trim().parse().ok()).unwrap_or(0) };
+        let age: u8 = rec.get(cols.age).and_then(|s| s.trim().parse().ok()).unwrap_or(0);
+        let values = (
+            count(cols.files),
+            count(cols.size),
+            count(cols.disk),
+            count(cols.linked),
+            num(Some(cols.accessed)),
+            num(Some(cols.modified)),
+            num(cols.oldest_accessed),
+            num(cols.oldest_modified),
+        );
+        let device = num(cols.device);
+
+        let user = match user_idx.get(user) {
+            Some(&i) => i,
+            None => {
+                let i = chunk.users.len() as u32;
+                chunk.users.push(user.to_string());
+                user_idx.insert(user.to_string(), i);
+                i
+            }
+        };
+        let key = path_key(path, case_insensitive);
+        let path = match path_idx.get(key.as_ref()) {
+            Some(&i) => i,
+            None => {
+                let i = chunk.paths.len() as u32;
+                chunk.paths.push(ChunkPath {
+                    path: path.to_string(),
+                    key: case_insensitive.then(|| key.to_string()),
+                    parent_key: dusum_parent(path).map(|pp| path_key(&pp, case_insensitive).into_owned()),
+                });
+                path_idx.insert(key.into_owned(), i);
+                i
+            }
+        };
+        chunk.items.push(Item::Row(ChunkRow {
+            path,
+            user,
+            age,
+            values,
+            device,
+        }));
+    }
+    chunk
+}
+
+/// With `case_insensitive`, paths are NFC-normalized and folders that differ
+/// only in letter case are merged into the first spelling seen; their stats
+/// rows are summed (file counts and sizes), maxed (atime/mtime) or take the
+/// earliest set value (oldest atime/mtime).
+///
+/// `threads` parse the CSV in chunks of whole records, each chunk with its
+/// own table of the folders and users it names; the chunks are merged into
+/// the DB in file order on the calling thread, so ids, first spellings and
+/// duplicate handling are those of a front-to-back read.
 pub fn ingest_csv<F: FnMut(u64)>(
     conn: &mut Connection,
     csv_path: &Path,
     total_data_lines: usize,
+    case_insensitive: bool,
+    threads: usize,
+    on_progress: F,
+) -> Result<IngestStats> {
+    ingest_chunked(conn, csv_path, total_data_lines, case_insensitive, threads, CHUNK_BYTES, on_progress)
+}
+
+fn ingest_chunked<F: FnMut(u64)>(
+    conn: &mut Connection,
+    csv_path: &Path,
+    total_data_lines: usize,
+    case_insensitive: bool,
+    threads: usize,
+    chunk_bytes: usize,
     mut on_progress: F,
 ) -> Result<IngestStats> {
     let mut user_cache: HashMap<String, i64> = HashMap::new();
     let mut path_cache: HashMap<String, i64> = HashMap::new();
     let mut stats = IngestStats::default();
+    let threads = threads.max(1);
+
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .from_path(csv_path)
+        .with_context(|| format!("opening CSV {}", csv_path.display()))?;
+    // `dusum --by-device` appends a device column; its rows are kept per
+    // device in `device_stats` and summed across devices into `stats`.
+    let cols = Columns::from_header(rdr.headers()?);
+    let by_device = cols.device.is_some();
+    let data_start = rdr.position().byte();
+    drop(rdr);
+    stats.by_device = by_device;
 
     let tx = conn.transaction()?;
     {
         // The synthetic root has no parent and no stats. Every CSV path whose
         // dusum_parent() is None links here.
         let synth_root_id: i64 = tx.query_row(
-            "INSERT INTO paths(full_path, parent_id) VALUES(?1, NULL) RETURNING id",
-            params![SYNTHETIC_ROOT],
+            "INSERT INTO paths(full_path, path_key, parent_id) VALUES(?1, ?2, NULL) RETURNING id",
+            params![SYNTHETIC_ROOT, case_insensitive.then_some(SYNTHETIC_ROOT)],
             |r| r.get(0),
         )?;
         path_cache.insert(SYNTHETIC_ROOT.to_string(), synth_root_id);
@@ -68,112 +409,183 @@ pub fn ingest_csv<F: FnMut(u64)>(
 
         let mut insert_user =
             tx.prepare("INSERT INTO users(name) VALUES(?1) RETURNING id")?;
-        let mut insert_path =
-            tx.prepare("INSERT INTO paths(full_path, parent_id) VALUES(?1, ?2) RETURNING id")?;
-        let mut insert_stat = tx.prepare(
-            "INSERT INTO stats
-              (path_id, user_id, age, file_count, file_size, disk_bytes, linked_size, atime, mtime)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        let mut insert_path = tx.prepare(
+            "INSERT INTO paths(full_path, path_key, parent_id) VALUES(?1, ?2, ?3) RETURNING id",
         )?;
-
-        let mut rdr = ReaderBuilder::new()
-            .has_headers(true)
-            .flexible(true)
-            .from_path(csv_path)
-            .with_context(|| format!("opening CSV {}", csv_path.display()))?;
+        if by_device {
+            schema::create_device_table(&tx)?;
+        }
+        let mut insert_stat = tx.prepare(&stats_insert_sql(
+            "stats",
+            "path_id, user_id, age",
+            case_insensitive || by_device,
+        ))?;
+        let mut insert_dev = by_device
+            .then(|| {
+                tx.prepare(&stats_insert_sql(
+                    "device_stats",
+                    "path_id, device, user_id, age",
+                    case_insensitive,
+                ))
+            })
+            .transpose()?;
 
         let progress_step = if total_data_lines >= 100 {
             total_data_lines / 100
         } else {
             0
         };
-
-        for (lineno, rec) in rdr.records().enumerate() {
-            let rec = match rec {
-                Ok(r) => r,
-                Err(e) => {
-                    eprintln!("warn: skipping malformed CSV row {}: {}", lineno + 2, e);
-                    continue;
-                }
-            };
-            if rec.len() < 9 {
-                continue;
+        let mut lineno = 0usize;
+
+        let mut merge = |chunk: Chunk| -> Result<()> {
+            let mut user_ids = Vec::with_capacity(chunk.users.len());
+            for user in &chunk.users {
+                let id = match user_cache.get(user) {
+                    Some(&id) => id,
+                    None => {
+                        let id: i64 = insert_user.query_row(params![user], |r| r.get(0))?;
+                        user_cache.insert(user.clone(), id);
+                        stats.users_inserted += 1;
+                        id
+                    }
+                };
+                user_ids.push(id);
             }
-            // Path is stored byte-for-byte from dusum. No canonicalization
-            // here — dusum::aggregate::get_folder_ancestors is the single
-            // source of truth for the on-disk format.
-            let path = rec.get(0).unwrap_or("");
-            let user = rec.get(1).unwrap_or("").trim();
-            if path.is_empty() || user.is_empty() {
-                continue;
+            let mut path_ids = Vec::with_capacity(chunk.paths.len());
+            for p in &chunk.paths {
+                let id = match path_cache.get(p.key()) {
+                    Some(&id) => id,
+                    None => {
+                        let parent_id = p
+                            .parent_key
+                            .as_deref()
+                            .and_then(|k| path_cache.get(k).copied())
+                            .unwrap_or(synth_root_id);
+                        let id: i64 = insert_path.query_row(params![p.path, p.key, parent_id], |r| r.get(0))?;
+                        path_cache.insert(p.key().to_string(), id);
+                        stats.paths_inserted += 1;
+                        stats.max_depth = stats.max_depth.max(path_depth(&p.path));
+                        id
+                    }
+                };
+                path_ids.push(id);
             }
-            let age: u8 = rec.get(2).and_then(|s| s.trim().parse().ok()).unwrap_or(0);
-            let file_count: u64 = rec.get(3).and_then(|s| s.trim().parse().ok()).unwrap_or(0);
-            let file_size: u64 = rec.get(4).and_then(|s| s.trim().parse().ok()).unwrap_or(0);
-            let disk_bytes: u64 = rec.get(5).and_then(|s| s.trim().parse().ok()).unwrap_or(0);
-            let linked_size: u64 = rec.get(6).and_then(|s| s.trim().parse().ok()).unwrap_or(0);
-            let atime: i64 = rec.get(7).and_then(|s| s.trim().parse().ok()).unwrap_or(0);
-            let mtime: i64 = rec.get(8).and_then(|s| s.trim().parse().ok()).unwrap_or(0);
-
-            let user_id = match user_cache.get(user) {
-                Some(&id) => id,
-                None => {
-                    let id: i64 = insert_user.query_row(params![user], |r| r.get(0))?;
-                    user_cache.insert(user.to_string(), id);
-                    stats.users_inserted += 1;
-                    id
-                }
-            };
-
-            let path_id = match path_cache.get(path) {
-                Some(&id) => id,
-                None => {
-                    let parent_id = match dusum_parent(path) {
-                        Some(pp) => path_cache.get(&pp).copied().unwrap_or(synth_root_id),
-                        None => synth_root_id,
-                    };
-                    let id: i64 = insert_path.query_row(params![path, parent_id], |r| r.get(0))?;
-                    path_cache.insert(path.to_string(), id);
-                    stats.paths_inserted += 1;
-                    id
+
+            for item in chunk.items {
+                let line = lineno;
+                lineno += 1;
+                if progress_step > 0 && lineno.is_multiple_of(progress_step) {
+                    on_progress(lineno as u64);
                 }
-            };
-
-            let inserted = insert_stat.execute(params![
-                path_id,
-                user_id,
-                age,
-                file_count,
-                file_size,
-                disk_bytes,
-                linked_size,
-                atime,
-                mtime,
-            ]);
-            match inserted {
-                Ok(_) => stats.rows_inserted += 1,
-                Err(e) => {
-                    if let rusqlite::Error::SqliteFailure(err, _) = &e {
-                        if err.code == rusqlite::ErrorCode::ConstraintViolation {
+                let row = match item {
+                    Item::Row(row) => row,
+                    Item::Malformed(e) => {
+                        eprintln!("warn: skipping malformed CSV row {}: {}", line + 2, e);
+                        stats.rows_malformed += 1;
+                        continue;
+                    }
+                    Item::Skipped => {
+                        stats.rows_skipped += 1;
+                        continue;
+                    }
+                };
+                let (path_id, user_id, age) = (path_ids[row.path as usize], user_ids[row.user as usize], row.age);
+                let values = row.values;
+                let inserted = match insert_dev.as_mut() {
+                    Some(dev_stmt) => dev_stmt
+                        .execute(params![
+                            path_id, row.device, user_id, age, values.0, values.1, values.2,
+                            values.3, values.4, values.5, values.6, values.7,
+                        ])
+                        .and_then(|_| {
+                            insert_stat.execute(params![
+                                path_id, user_id, age, values.0, values.1, values.2, values.3,
+                                values.4, values.5, values.6, values.7,
+                            ])
+                        }),
+                    None => insert_stat.execute(params![
+                        path_id, user_id, age, values.0, values.1, values.2, values.3, values.4,
+                        values.5, values.6, values.7,
+                    ]),
+                };
+                match inserted {
+                    Ok(_) => stats.rows_inserted += 1,
+                    Err(e) => {
+                        if let rusqlite::Error::SqliteFailure(err, _) = &e
+                            && err.code == rusqlite::ErrorCode::ConstraintViolation
+                        {
                             eprintln!(
                                 "warn: duplicate stats row at line {} (path_id={}, user={}, age={}); skipped",
-                                lineno + 2, path_id, user, age
+                                line + 2, path_id, chunk.users[row.user as usize], age
                             );
+                            stats.rows_duplicate += 1;
                             continue;
                         }
+                        return Err(e.into());
                     }
-                    return Err(e.into());
                 }
             }
+            Ok(())
+        };
 
-            if progress_step > 0 && (lineno + 1) % progress_step == 0 {
-                on_progress(lineno as u64 + 1);
-            }
+        let mut file =
+            File::open(csv_path).with_context(|| format!("opening CSV {}", csv_path.display()))?;
+        file.seek(SeekFrom::Start(data_start))?;
+        // Chunks read but not merged yet, which bounds the memory held.
+        let in_flight = threads * 2;
+        let (chunk_tx, chunk_rx) = bounded::<(usize, Vec<u8>)>(threads);
+        let (done_tx, done_rx) = unbounded::<(usize, Chunk)>();
+        let (token_tx, token_rx) = bounded::<()>(in_flight);
+        for _ in 0..in_flight {
+            token_tx.send(())?;
         }
+        thread::scope(|s| -> Result<()> {
+            let reader = s.spawn(move || {
+                let mut seq = 0;
+                split_records(file, chunk_bytes, |bytes| {
+                    let sent = token_rx.recv().is_ok() && chunk_tx.send((seq, bytes)).is_ok();
+                    seq += 1;
+                    sent
+                })
+            });
+            for _ in 0..threads {
+                let (chunk_rx, done_tx, cols) = (chunk_rx.clone(), done_tx.clone(), &cols);
+                s.spawn(move || {
+                    for (seq, bytes) in chunk_rx {
+                        if done_tx.send((seq, parse_chunk(&bytes, cols, case_insensitive))).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+            drop((chunk_rx, done_tx));
+
+            let mut pending: BTreeMap<usize, Chunk> = BTreeMap::new();
+            let mut next = 0;
+            for (seq, chunk) in &done_rx {
+                pending.insert(seq, chunk);
+                while let Some(chunk) = pending.remove(&next) {
+                    merge(chunk)?;
+                    next += 1;
+                    let _ = token_tx.send(());
+                }
+            }
+            reader.join().map_err(|_| anyhow::anyhow!("CSV reader thread panicked"))?
+        })?;
     }
     tx.commit()?;
 
-    backfill_missing_parents(conn, &path_cache)?;
+    if case_insensitive {
+        // Upserts report success for merged rows too; the difference to the
+        // stored row count is what was folded into an existing key.
+        let table = if stats.by_device { "device_stats" } else { "stats" };
+        let stored: u64 =
+            conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |r| r.get(0))?;
+        stats.rows_duplicate += stats.rows_inserted.saturating_sub(stored);
+        stats.rows_inserted = stored;
+    }
+
+    backfill_missing_parents(conn, &path_cache, case_insensitive)?;
     Ok(stats)
 }
 
@@ -183,6 +595,7 @@ pub fn ingest_csv<F: FnMut(u64)>(
 fn backfill_missing_parents(
     conn: &mut Connection,
     path_cache: &HashMap<String, i64>,
+    case_insensitive: bool,
 ) -> Result<()> {
     let synth_root_id = match path_cache.get(SYNTHETIC_ROOT) {
         Some(&id) => id,
@@ -203,13 +616,12 @@ fn backfill_missing_parents(
     {
         let mut upd = tx.prepare("UPDATE paths SET parent_id = ?1 WHERE id = ?2")?;
         for (id, full) in &suspects {
-            if let Some(pp) = dusum_parent(full) {
-                if let Some(&pid) = path_cache.get(&pp) {
-                    if pid != synth_root_id {
-                        upd.execute(params![pid, id])?;
-                        rebound += 1;
-                    }
-                }
+            if let Some(pp) = dusum_parent(full)
+                && let Some(&pid) = path_cache.get(path_key(&pp, case_insensitive).as_ref())
+                && pid != synth_root_id
+            {
+                upd.execute(params![pid, id])?;
+                rebound += 1;
             }
         }
     }
@@ -315,7 +727,7 @@ mod tests {
     fn linux_ingest_preserves_paths_verbatim() {
         let f = linux_csv();
         let mut c = fresh_conn();
-        let s = ingest_csv(&mut c, f.path(), 3, |_| {}).unwrap();
+        let s = ingest_csv(&mut c, f.path(), 3, false, 1, |_| {}).unwrap();
         assert_eq!(s.rows_inserted, 3);
         assert_eq!(s.users_inserted, 2);
         // synth root + "/" + "/docs"
@@ -336,7 +748,7 @@ mod tests {
     fn windows_ingest_preserves_native_separators() {
         let f = windows_csv();
         let mut c = fresh_conn();
-        let s = ingest_csv(&mut c, f.path(), 4, |_| {}).unwrap();
+        let s = ingest_csv(&mut c, f.path(), 4, false, 1, |_| {}).unwrap();
         assert_eq!(s.rows_inserted, 4);
         // synth + C:\ + C:\Users + C:\Users\San + D:\
         assert_eq!(s.paths_inserted, 5);
@@ -357,7 +769,7 @@ mod tests {
     fn unc_ingest_preserves_native_form() {
         let f = unc_csv();
         let mut c = fresh_conn();
-        let s = ingest_csv(&mut c, f.path(), 3, |_| {}).unwrap();
+        let s = ingest_csv(&mut c, f.path(), 3, false, 1, |_| {}).unwrap();
         assert_eq!(s.rows_inserted, 3);
         // synth + \\srv + \\srv\shr + \\srv\shr\dir
         assert_eq!(s.paths_inserted, 4);
@@ -379,8 +791,70 @@ mod tests {
         )
         .unwrap();
         let mut c = fresh_conn();
-        let s = ingest_csv(&mut c, f.path(), 3, |_| {}).unwrap();
+        let s = ingest_csv(&mut c, f.path(), 3, false, 1, |_| {}).unwrap();
         assert_eq!(s.rows_inserted, 1);
+        assert_eq!(s.rows_skipped, 2);
+    }
+
+    #[test]
+    fn ingest_counts_duplicates_malformed_and_depth() {
+        let mut f = NamedTempFile::new().unwrap();
+        f.write_all(
+            b"path,user,age,files,size,disk,linked,accessed,modified\n\
+              /a,alice,0,1,100,100,0,1,1\n\
+              /a,alice,0,1,100,100,0,1,1\n\
+              /a/b/c,alice,0,1,100,100,0,1,1\n\
+              /a/\xff,alice,0,1,100,100,0,1,1\n\
+              /a,alice\n",
+        )
+        .unwrap();
+        let mut c = fresh_conn();
+        let s = ingest_csv(&mut c, f.path(), 5, false, 1, |_| {}).unwrap();
+        assert_eq!(s.rows_inserted, 2);
+        assert_eq!(s.rows_duplicate, 1);
+        assert_eq!(s.rows_malformed, 1);
+        assert_eq!(s.rows_skipped, 1);
+        assert_eq!(s.max_depth, 3);
+        assert_eq!(path_depth("/"), 0);
+        assert_eq!(path_depth("\\\\srv\\shr"), 2);
+        assert_eq!(path_depth("C:\\Users"), 2);
+    }
+
+    #[test]
+    fn chunked_parallel_ingest_matches_a_serial_read() {
+        let mut f = NamedTempFile::new().unwrap();
+        writeln!(f, "path,user,age,files,size,disk,linked,accessed,modified").unwrap();
+        for i in 0..200 {
+            writeln!(f, "/D{}/x,u{},{},1,10,10,0,1,{i}", i % 7, i % 2, i % 3).unwrap();
+            writeln!(f, "/d{},u{},{},1,10,10,0,1,{i}", i % 7, i % 2, i % 3).unwrap();
+        }
+        // A quoted newline must not be taken for a record boundary.
+        writeln!(f, "\"/d1/two\nlines\",u1,0,1,10,10,0,1,1\n/d1,u1").unwrap();
+
+        let dump = |threads: usize, chunk_bytes: usize| {
+            let mut c = fresh_conn();
+            let s = ingest_chunked(&mut c, f.path(), 402, true, threads, chunk_bytes, |_| {}).unwrap();
+            let rows: Vec<(String, String, i64, i64, i64)> = c
+                .prepare(
+                    "SELECT p.full_path, u.name, s.age, s.file_count, s.mtime FROM stats s
+                     JOIN paths p ON p.id = s.path_id JOIN users u ON u.id = s.user_id
+                     ORDER BY s.path_id, s.user_id, s.age",
+                )
+                .unwrap()
+                .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)))
+                .unwrap()
+                .map(|r| r.unwrap())
+                .collect();
+            (s.rows_inserted, s.rows_duplicate, s.rows_skipped, s.paths_inserted, rows)
+        };
+        let serial = dump(1, CHUNK_BYTES);
+        // 42 keys for /dN and /dN/x each (7 x 2 users x 3 ages), merged
+        // across case; the two-line path, and a short row skipped.
+        assert_eq!((serial.0, serial.1, serial.2, serial.3), (85, 316, 1, 16));
+        assert!(serial.4.iter().any(|r| r == &("/D0/x".into(), "u0".into(), 0, 5, 168)));
+        assert!(serial.4.iter().any(|r| r.0 == "/d1/two\nlines"));
+        assert_eq!(dump(4, 64), serial);
+        assert_eq!(dump(3, 1), serial);
     }
 
     #[test]
@@ -395,8 +869,107 @@ mod tests {
         )
         .unwrap();
         let mut c = fresh_conn();
-        ingest_csv(&mut c, f.path(), 2, |_| {}).unwrap();
+        ingest_csv(&mut c, f.path(), 2, false, 1, |_| {}).unwrap();
         assert_eq!(parent_of(&c, "/a/b").as_deref(), Some("/a"));
         assert_eq!(parent_of(&c, "/a").as_deref(), Some(""));
     }
+
+    #[test]
+    fn case_insensitive_ingest_merges_spellings() {
+        let mut f = NamedTempFile::new().unwrap();
+        writeln!(
+            f,
+            "path,user,age,files,size,disk,linked,accessed,modified\n\
+             \\\\srv\\Data,San,0,2,200,200,0,10,20\n\
+             \\\\srv\\data,San,0,3,300,300,0,30,5\n\
+             \\\\srv\\DATA\\Cafe\u{301},San,0,1,100,100,0,1,1\n\
+             \\\\srv\\data\\caf\u{e9},San,0,1,100,100,0,1,1"
+        )
+        .unwrap();
+        let mut c = fresh_conn();
+        let s = ingest_csv(&mut c, f.path(), 4, true, 1, |_| {}).unwrap();
+        // synth + \\srv\Data + \\srv\Data\Café
+        assert_eq!(s.paths_inserted, 3);
+        assert_eq!((s.rows_inserted, s.rows_duplicate), (2, 2));
+        assert!(path_exists(&c, "\\\\srv\\Data"));
+        assert!(!path_exists(&c, "\\\\srv\\data"));
+        let key: String = c
+            .query_row(
+                "SELECT path_key FROM paths WHERE full_path = ?1",
+                params!["\\\\srv\\DATA\\Caf\u{e9}"],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(key, "\\\\srv\\data\\caf\u{e9}");
+        assert_eq!(
+            parent_of(&c, "\\\\srv\\DATA\\Caf\u{e9}").as_deref(),
+            Some("\\\\srv\\Data")
+        );
+
+        let (files, size, atime, mtime): (i64, i64, i64, i64) = c
+            .query_row(
+                "SELECT s.file_count, s.file_size, s.atime, s.mtime FROM stats s
+                 JOIN paths p ON p.id = s.path_id WHERE p.full_path = ?1",
+                params!["\\\\srv\\Data"],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!((files, size, atime, mtime), (5, 500, 30, 20));
+    }
+
+    #[test]
+    fn device_column_fills_device_stats_and_totals() {
+        let mut f = NamedTempFile::new().unwrap();
+        writeln!(
+            f,
+            "path,user,age,files,size,disk,linked,accessed,modified,device\n\
+             /a,alice,0,2,200,200,0,10,20,64768\n\
+             /a,alice,0,1,50,50,0,30,5,64769\n\
+             /a,alice,0,1,50,50,0,30,5,64769"
+        )
+        .unwrap();
+        let mut c = fresh_conn();
+        let s = ingest_csv(&mut c, f.path(), 3, false, 1, |_| {}).unwrap();
+        assert!(s.by_device);
+        assert_eq!((s.rows_inserted, s.rows_duplicate), (2, 1));
+        assert_eq!(count(&c, "SELECT COUNT(*) FROM device_stats"), 2);
+        assert_eq!(count(&c, "SELECT file_count FROM stats"), 3);
+        assert_eq!(count(&c, "SELECT atime FROM stats"), 30);
+    }
+
+    #[test]
+    fn columns_are_found_by_header_name() {
+        // dusum's order without --by-device, and with it plus
+        // --extrapolate: the oldest timestamps take index 9 in the first.
+        let mut plain = NamedTempFile::new().unwrap();
+        writeln!(
+            plain,
+            "path,user,age,files,size,disk,linked,accessed,modified,\
+             oldest_accessed,oldest_modified\n\
+             /a,alice,0,2,200,200,0,30,40,10,20"
+        )
+        .unwrap();
+        let mut c = fresh_conn();
+        let s = ingest_csv(&mut c, plain.path(), 1, false, 1, |_| {}).unwrap();
+        assert!(!s.by_device);
+        assert_eq!(count(&c, "SELECT oldest_atime FROM stats"), 10);
+        assert_eq!(count(&c, "SELECT oldest_mtime FROM stats"), 20);
+
+        let mut dev = NamedTempFile::new().unwrap();
+        writeln!(
+            dev,
+            "path,user,age,files,size,disk,linked,accessed,modified,device,\
+             extrapolated,oldest_accessed,oldest_modified\n\
+             /a,alice,0,2,200,200,0,30,40,64768,0,10,20\n\
+             /a,alice,0,1,50,50,0,30,40,64769,0,0,15"
+        )
+        .unwrap();
+        let mut c = fresh_conn();
+        let s = ingest_csv(&mut c, dev.path(), 2, false, 1, |_| {}).unwrap();
+        assert!(s.by_device);
+        assert_eq!(count(&c, "SELECT COUNT(*) FROM device_stats"), 2);
+        // The unset (0) oldest atime of the second device does not win.
+        assert_eq!(count(&c, "SELECT oldest_atime FROM stats"), 10);
+        assert_eq!(count(&c, "SELECT oldest_mtime FROM stats"), 15);
+    }
 }
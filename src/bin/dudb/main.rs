@@ -24,6 +24,14 @@ struct Args {
     /// Overwrite an existing DB instead of failing
     #[arg(long)]
     rebuild: bool,
+    /// Merge folders that differ only in letter case or Unicode form (NFC),
+    /// for datasets scanned from Windows/SMB sources
+    #[arg(long)]
+    case_insensitive: bool,
+    /// Threads parsing the CSV (default: CPU count); rows are still
+    /// written to the DB in file order
+    #[arg(short = 'j', long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    threads: Option<usize>,
 }
 
 fn main() -> Result<()> {
@@ -70,20 +78,31 @@ fn main() -> Result<()> {
     let data_lines = total_lines.saturating_sub(1);
     println!("done");
     println!("Total lines  : {}", total_lines);
+    if args.case_insensitive {
+        println!("Path case    : insensitive (NFC)");
+    }
 
     let mut conn = Connection::open(&output)
         .with_context(|| format!("opening {}", output.display()))?;
     schema::apply_ingest_pragmas(&conn)?;
     schema::create_tables(&conn)?;
 
-    println!("Loading CSV into SQLite...");
-    let stats = ingest::ingest_csv(&mut conn, &args.input, data_lines, |processed| {
-        let pct = ((processed as f64 / data_lines.max(1) as f64) * 100.0).round() as u32;
-        println!("{}%", pct.min(100));
-    })?;
+    let threads = args.threads.unwrap_or_else(num_cpus::get);
+    println!("Loading CSV into SQLite ({threads} parsing threads)...");
+    let stats = ingest::ingest_csv(
+        &mut conn,
+        &args.input,
+        data_lines,
+        args.case_insensitive,
+        threads,
+        |processed| {
+            let pct = ((processed as f64 / data_lines.max(1) as f64) * 100.0).round() as u32;
+            println!("{}%", pct.min(100));
+        },
+    )?;
 
     println!("Building indexes...");
-    schema::create_indexes(&conn)?;
+    schema::create_indexes(&conn, args.case_insensitive)?;
     println!("Running ANALYZE...");
     conn.execute_batch("ANALYZE;")?;
 
@@ -93,11 +112,17 @@ fn main() -> Result<()> {
         .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
         .map(|d| d.as_secs() as i64)
         .unwrap_or(0);
+    let mut metadata = stats.metadata();
+    metadata.push((
+        "path_case",
+        if args.case_insensitive { "insensitive" } else { "sensitive" }.to_string(),
+    ));
     schema::write_metadata(
         &conn,
         &args.input.to_string_lossy(),
         source_mtime,
         stats.rows_inserted,
+        &metadata,
     )?;
 
     let elapsed = started.elapsed();
@@ -105,6 +130,23 @@ fn main() -> Result<()> {
     println!("Stats rows   : {}", stats.rows_inserted);
     println!("Paths        : {}", stats.paths_inserted);
     println!("Users        : {}", stats.users_inserted);
+    println!("Max depth    : {}", stats.max_depth);
+    if stats.by_device {
+        println!("By device    : yes (device_stats)");
+    }
+    if stats.rows_malformed + stats.rows_skipped + stats.rows_duplicate > 0 {
+        println!(
+            "{}",
+            format!(
+                "Row issues   : {} malformed, {} incomplete, {} duplicate{}",
+                stats.rows_malformed,
+                stats.rows_skipped,
+                stats.rows_duplicate,
+                if args.case_insensitive { " (merged)" } else { "" }
+            )
+            .yellow()
+        );
+    }
     println!("Elapsed time : {:.2} seconds", elapsed.as_secs_f64());
     Ok(())
 }
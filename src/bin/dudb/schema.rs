@@ -28,7 +28,8 @@ pub fn create_tables(conn: &Connection) -> Result<()> {
          CREATE TABLE IF NOT EXISTS paths (
             id        INTEGER PRIMARY KEY,
             parent_id INTEGER,
-            full_path TEXT NOT NULL UNIQUE
+            full_path TEXT NOT NULL UNIQUE,
+            path_key  TEXT
          );
          CREATE TABLE IF NOT EXISTS stats (
             path_id     INTEGER NOT NULL,
@@ -40,6 +41,8 @@ pub fn create_tables(conn: &Connection) -> Result<()> {
             linked_size INTEGER NOT NULL,
             atime       INTEGER NOT NULL,
             mtime       INTEGER NOT NULL,
+            oldest_atime INTEGER NOT NULL DEFAULT 0,
+            oldest_mtime INTEGER NOT NULL DEFAULT 0,
             PRIMARY KEY (path_id, user_id, age)
          ) WITHOUT ROWID;
          CREATE TABLE IF NOT EXISTS metadata (
@@ -50,8 +53,40 @@ pub fn create_tables(conn: &Connection) -> Result<()> {
     Ok(())
 }
 
-pub fn create_indexes(conn: &Connection) -> Result<()> {
+/// Per-device breakdown of `stats`, present only for `dusum --by-device`
+/// input. `stats` still holds the totals across devices.
+pub fn create_device_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS device_stats (
+            path_id     INTEGER NOT NULL,
+            device      INTEGER NOT NULL,
+            user_id     INTEGER NOT NULL,
+            age         INTEGER NOT NULL,
+            file_count  INTEGER NOT NULL,
+            file_size   INTEGER NOT NULL,
+            disk_bytes  INTEGER NOT NULL,
+            linked_size INTEGER NOT NULL,
+            atime       INTEGER NOT NULL,
+            mtime       INTEGER NOT NULL,
+            oldest_atime INTEGER NOT NULL DEFAULT 0,
+            oldest_mtime INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (path_id, device, user_id, age)
+         ) WITHOUT ROWID;",
+    )?;
+    Ok(())
+}
+
+/// `case_insensitive` indexes `path_key` (`query::canonical_key` of each
+/// folder, NULL otherwise) so duapi can look folders up in any letter case
+/// without a table scan. SQLite's NOCASE only folds ASCII, so the key is
+/// computed in Rust with the same folding duapi applies to requests.
+pub fn create_indexes(conn: &Connection, case_insensitive: bool) -> Result<()> {
     conn.execute_batch("CREATE INDEX IF NOT EXISTS idx_paths_parent ON paths(parent_id);")?;
+    if case_insensitive {
+        conn.execute_batch(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_paths_key ON paths(path_key);",
+        )?;
+    }
     Ok(())
 }
 
@@ -60,6 +95,7 @@ pub fn write_metadata(
     source_csv: &str,
     source_mtime: i64,
     row_count: u64,
+    extra: &[(&str, String)],
 ) -> Result<()> {
     let loader = format!("dudb {}", env!("CARGO_PKG_VERSION"));
     let now = chrono::Utc::now().timestamp();
@@ -75,7 +111,7 @@ pub fn write_metadata(
         "INSERT INTO metadata(key, value) VALUES(?1, ?2)
          ON CONFLICT(key) DO UPDATE SET value = excluded.value",
     )?;
-    for (k, v) in pairs {
+    for (k, v) in pairs.iter().chain(extra) {
         stmt.execute(params![k, v])?;
     }
     Ok(())
@@ -111,7 +147,7 @@ mod tests {
     #[test]
     fn metadata_round_trips() {
         let c = fresh();
-        write_metadata(&c, "x.csv", 1234, 99).unwrap();
+        write_metadata(&c, "x.csv", 1234, 99, &[("path_case", "insensitive".into())]).unwrap();
         let v: String = c
             .query_row(
                 "SELECT value FROM metadata WHERE key='schema_version'",
@@ -128,19 +164,24 @@ mod tests {
             )
             .unwrap();
         assert_eq!(src, "x.csv");
+        let case: String = c
+            .query_row("SELECT value FROM metadata WHERE key='path_case'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(case, "insensitive");
     }
 
     #[test]
     fn indexes_built() {
         let c = fresh();
-        create_indexes(&c).unwrap();
+        create_indexes(&c, true).unwrap();
         let n: i64 = c
             .query_row(
-                "SELECT COUNT(*) FROM sqlite_master WHERE type='index' AND name='idx_paths_parent'",
+                "SELECT COUNT(*) FROM sqlite_master WHERE type='index'
+                 AND name IN ('idx_paths_parent', 'idx_paths_key')",
                 [],
                 |r| r.get(0),
             )
             .unwrap();
-        assert_eq!(n, 1);
+        assert_eq!(n, 2);
     }
 }
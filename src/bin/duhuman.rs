@@ -187,7 +187,12 @@ fn get_groupname_from_gid(gid: u32) -> String {
     }
 }
 
-#[cfg(not(unix))]
+#[cfg(windows)]
+fn get_username_from_uid(uid: u32) -> String {
+    dutopia::util::account_name_from_rid(uid).unwrap_or_else(|| uid.to_string())
+}
+
+#[cfg(not(any(unix, windows)))]
 fn get_username_from_uid(uid: u32) -> String { 
     uid.to_string() 
 }
@@ -315,7 +320,7 @@ mod tests {
         assert_eq!(bytes[7], b'-');
         // digits elsewhere
         assert!(bytes.iter().enumerate().all(|(i, &c)|
-            (i == 4 || i == 7) || (c >= b'0' && c <= b'9')
+            (i == 4 || i == 7) || c.is_ascii_digit()
         ));
     }
 
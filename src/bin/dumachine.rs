@@ -5,8 +5,12 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
 use chrono::{NaiveDate, TimeZone, Utc};
+use dutopia::util::exit::{self, ExitArgs, Outcome};
 use dutopia::util::print_about;
+use dutopia::util::progress::{Counter, CountingReader, ProgressArgs, Reporter, Unit};
 
 const READ_BUF_SIZE: usize = 8 * 1024 * 1024;
 const WRITE_BUF_SIZE: usize = 8 * 1024 * 1024;
@@ -21,15 +25,28 @@ struct Args {
     /// Output CSV (defaults to <stem>.raw.csv in the current directory)
     #[arg(short, long)]
     output: Option<PathBuf>,
+    /// Do not report progress
+    #[arg(short, long)]
+    quiet: bool,
+    #[command(flatten)]
+    progress: ProgressArgs,
+    #[command(flatten)]
+    exit: ExitArgs,
 }
 
 const OUT_HEADER: &[u8] = b"INODE,ATIME,MTIME,UID,GID,MODE,SIZE,DISK,PATH\n";
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     print_about();
 
-    let start = std::time::Instant::now();
     let args = Args::parse();
+    let exit = args.exit.clone();
+    exit::finish("dumachine", &exit, run(args))
+}
+
+/// The conversion; lines that do not parse are reported and skipped.
+fn run(args: Args) -> Result<Outcome> {
+    let start = std::time::Instant::now();
     let input = &args.input;
 
     let stem = input
@@ -42,7 +59,15 @@ fn main() -> Result<()> {
 
     let file = File::open(input)
         .with_context(|| format!("opening input file {}", input.display()))?;
-    let mut reader = BufReader::with_capacity(READ_BUF_SIZE, file);
+    let input_bytes = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let progress = Arc::new(Counter::with_total(input_bytes));
+    let reporter = Reporter::start(
+        "dumachine",
+        Unit::Bytes,
+        progress.clone(),
+        args.progress.sinks(args.quiet)?,
+    );
+    let mut reader = BufReader::with_capacity(READ_BUF_SIZE, CountingReader::new(file, progress));
 
     let out_file = File::create(&output)
         .with_context(|| format!("creating output csv {}", output.display()))?;
@@ -91,19 +116,16 @@ fn main() -> Result<()> {
                 errors += 1;
             }
         }
-
-        if files % 10_000_000 == 0 {
-            eprintln!("Progress: {}M lines", files / 1_000_000);
-        }
     }
 
     writer.flush()?;
+    reporter.finish();
 
     println!("Output       : {}", output.display());
     println!("Total files  : {}", files);
     println!("Total errors : {}", errors);
     println!("Elapsed time : {:.3} sec.", start.elapsed().as_secs_f64());
-    Ok(())
+    Ok(Outcome::with_errors(errors))
 }
 
 /// Find the CSV portion after ";DIGITS!" or ";-DIGITS!" pattern
@@ -0,0 +1,159 @@
+// rs/src/bin/dureport/main.rs
+use anyhow::{Context, Result};
+use clap::{ColorChoice, Parser};
+use csv::{ReaderBuilder, Trim, WriterBuilder};
+use dutopia::util::{parse_int, print_about};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+mod model;
+
+use model::{rate_of, CostModel, Usage, GIB};
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    color = ColorChoice::Auto,
+    about = "Chargeback report: storage cost per user or group from dusum output"
+)]
+struct Args {
+    /// Summary CSV produced by dusum
+    input: PathBuf,
+    /// Cost model CSV: prefix,tier,rate (rate per GiB-month; prefix * = default)
+    #[arg(short, long, value_name = "FILE")]
+    costs: PathBuf,
+    /// Output CSV file path (defaults to <input_stem>.cost.csv)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Roll users up into groups with a user,group CSV
+    #[arg(short, long, value_name = "FILE")]
+    groups: Option<PathBuf>,
+    /// Bill logical size instead of disk usage
+    #[arg(long)]
+    apparent: bool,
+}
+
+fn main() -> Result<()> {
+    print_about();
+
+    let start_time = std::time::Instant::now();
+    let args = Args::parse();
+
+    let model = CostModel::load(&args.costs)?;
+    for t in model.tiers() {
+        println!("Tier         : {} {} @ {}/GiB-month", t.name, t.prefix, t.rate);
+    }
+    let groups = args.groups.as_deref().map(load_groups).transpose()?;
+
+    let output = args.output.clone().unwrap_or_else(|| {
+        let stem = args
+            .input
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output");
+        PathBuf::from(format!("{}.cost.csv", stem))
+    });
+
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .trim(Trim::All)
+        .from_path(&args.input)
+        .with_context(|| format!("opening {}", args.input.display()))?;
+    // path,user,age,files,size,disk,...
+    let bytes_col = if args.apparent { 4 } else { 5 };
+    let mut usage = Usage::default();
+    let mut rows = 0u64;
+    for rec in rdr.byte_records() {
+        let rec = rec?;
+        let path = String::from_utf8_lossy(rec.get(0).unwrap_or(b""));
+        let user = String::from_utf8_lossy(rec.get(1).unwrap_or(b""));
+        let bytes = parse_int::<u64>(rec.get(bytes_col));
+        usage.add(&model, &path, &user, bytes);
+        rows += 1;
+    }
+
+    // owner -> tier -> bytes
+    let mut owners: BTreeMap<String, BTreeMap<String, u64>> = BTreeMap::new();
+    for (user, tiers) in usage.by_tier(&model) {
+        let owner = match &groups {
+            Some(g) => g.get(&user).cloned().unwrap_or_else(|| "unassigned".into()),
+            None => user,
+        };
+        let slot = owners.entry(owner).or_default();
+        for (tier, bytes) in tiers {
+            *slot.entry(tier).or_default() += bytes;
+        }
+    }
+
+    let mut wtr = WriterBuilder::new()
+        .from_path(&output)
+        .with_context(|| format!("creating {}", output.display()))?;
+    let owner_col = if groups.is_some() { "group" } else { "user" };
+    wtr.write_record([owner_col, "tier", "bytes", "gib", "rate", "cost"])?;
+    let mut total = 0.0;
+    for (owner, tiers) in &owners {
+        for (tier, bytes) in tiers {
+            let gib = *bytes as f64 / GIB;
+            let rate = rate_of(&model, tier);
+            let cost = gib * rate;
+            total += cost;
+            wtr.write_record([
+                owner.as_str(),
+                tier.as_str(),
+                &bytes.to_string(),
+                &format!("{gib:.3}"),
+                &rate.to_string(),
+                &format!("{cost:.2}"),
+            ])?;
+        }
+    }
+    wtr.flush()?;
+
+    println!("Input rows   : {}", rows);
+    println!("Owners       : {} {}s", owners.len(), owner_col);
+    println!("Total cost   : {:.2} per month", total);
+    println!("Output       : {}", output.display());
+    println!(
+        "Elapsed time : {:.3} sec.",
+        start_time.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+/// `user,group` CSV (header required).
+fn load_groups(path: &Path) -> Result<HashMap<String, String>> {
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(Trim::All)
+        .comment(Some(b'#'))
+        .from_path(path)
+        .with_context(|| format!("opening groups file {}", path.display()))?;
+    let mut out = HashMap::new();
+    for rec in rdr.records() {
+        let rec = rec?;
+        if let (Some(u), Some(g)) = (rec.get(0), rec.get(1))
+            && !u.is_empty()
+            && !g.is_empty()
+        {
+            out.insert(u.to_string(), g.to_string());
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn load_groups_skips_incomplete_rows() {
+        let mut f = tempfile::NamedTempFile::new().unwrap();
+        writeln!(f, "user,group\nalice,physics\nbob,\n# carol,chem\ndave,chem").unwrap();
+        let g = load_groups(f.path()).unwrap();
+        assert_eq!(g.len(), 2);
+        assert_eq!(g["alice"], "physics");
+        assert_eq!(g["dave"], "chem");
+    }
+}
@@ -0,0 +1,217 @@
+// rs/src/bin/dureport/model.rs
+//
+// Chargeback cost model: per-GiB-month rates by storage tier, each tier bound
+// to a folder prefix. A folder is billed at the rate of its longest matching
+// prefix; `*` is the default for everything else.
+//
+//   prefix,tier,rate
+//   *,standard,0.02
+//   /proj/fast,ssd,0.10
+//   /archive,cold,0.004
+//
+// dusum rows roll up every descendant, so the bytes of a prefix minus those
+// of the prefixes nested directly inside it are what its rate applies to.
+use anyhow::{bail, Context, Result};
+use csv::{ReaderBuilder, Trim};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use dutopia::util::dusum_parent;
+
+pub const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// Tier name for bytes outside every prefix when the model has no `*` row.
+pub const UNPRICED: &str = "unpriced";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tier {
+    pub prefix: String,
+    pub name: String,
+    pub rate: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct CostModel {
+    tiers: Vec<Tier>,
+    by_prefix: HashMap<String, usize>,
+    default: Option<usize>,
+}
+
+impl CostModel {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading cost model {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("in {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(true)
+            .trim(Trim::All)
+            .comment(Some(b'#'))
+            .from_reader(text.as_bytes());
+        let mut model = Self::default();
+        for (i, rec) in rdr.records().enumerate() {
+            let rec = rec?;
+            let line = i + 2;
+            let (Some(prefix), Some(name), Some(rate)) = (rec.get(0), rec.get(1), rec.get(2))
+            else {
+                bail!("line {line}: expected prefix,tier,rate");
+            };
+            let rate: f64 = rate
+                .parse()
+                .ok()
+                .filter(|r: &f64| r.is_finite() && *r >= 0.0)
+                .with_context(|| format!("line {line}: invalid rate '{rate}'"))?;
+            if name.is_empty() {
+                bail!("line {line}: empty tier name");
+            }
+            let idx = model.tiers.len();
+            let slot = if prefix == "*" || prefix.is_empty() {
+                model.default.replace(idx).is_some()
+            } else {
+                model.by_prefix.insert(prefix.to_string(), idx).is_some()
+            };
+            if slot {
+                bail!("line {line}: prefix '{prefix}' listed twice");
+            }
+            model.tiers.push(Tier {
+                prefix: prefix.to_string(),
+                name: name.to_string(),
+                rate,
+            });
+        }
+        if model.tiers.is_empty() {
+            bail!("cost model has no rates");
+        }
+        Ok(model)
+    }
+
+    pub fn tiers(&self) -> &[Tier] {
+        &self.tiers
+    }
+
+    /// Tier index of `path` when it is exactly one of the model's prefixes.
+    fn prefix_tier(&self, path: &str) -> Option<usize> {
+        self.by_prefix.get(path).copied()
+    }
+
+    /// Tier that owns the bytes of prefix `path` minus its nested prefixes:
+    /// the nearest prefix strictly above it, else the default.
+    fn enclosing(&self, path: &str) -> Option<usize> {
+        std::iter::successors(dusum_parent(path), |p| dusum_parent(p))
+            .find_map(|p| self.prefix_tier(&p))
+            .or(self.default)
+    }
+}
+
+/// Bytes per (tier, user), fed with dusum rows.
+#[derive(Debug, Default)]
+pub struct Usage {
+    /// Rolled-up bytes at each priced prefix (by tier index) and user.
+    at_prefix: HashMap<(usize, String), u64>,
+    /// Bytes at platform roots, i.e. everything, per user.
+    total: HashMap<String, u64>,
+}
+
+impl Usage {
+    pub fn add(&mut self, model: &CostModel, path: &str, user: &str, bytes: u64) {
+        if let Some(t) = model.prefix_tier(path) {
+            *self.at_prefix.entry((t, user.to_string())).or_default() += bytes;
+        }
+        if dusum_parent(path).is_none() {
+            *self.total.entry(user.to_string()).or_default() += bytes;
+        }
+    }
+
+    /// Exclusive bytes per user and tier name; bytes outside every prefix
+    /// land on the default tier, or on `UNPRICED` without one.
+    pub fn by_tier(&self, model: &CostModel) -> BTreeMap<String, BTreeMap<String, u64>> {
+        let mut out: BTreeMap<String, BTreeMap<String, i128>> = BTreeMap::new();
+        let name = |t: Option<usize>| {
+            t.map_or_else(|| UNPRICED.to_string(), |i| model.tiers[i].name.clone())
+        };
+        for (user, bytes) in &self.total {
+            *out.entry(user.clone())
+                .or_default()
+                .entry(name(model.default))
+                .or_default() += *bytes as i128;
+        }
+        for ((t, user), bytes) in &self.at_prefix {
+            let user_tiers = out.entry(user.clone()).or_default();
+            *user_tiers.entry(model.tiers[*t].name.clone()).or_default() += *bytes as i128;
+            let above = model.enclosing(&model.tiers[*t].prefix);
+            *user_tiers.entry(name(above)).or_default() -= *bytes as i128;
+        }
+        out.into_iter()
+            .map(|(user, tiers)| {
+                let tiers = tiers
+                    .into_iter()
+                    .filter(|(_, b)| *b > 0)
+                    .map(|(t, b)| (t, b as u64))
+                    .collect();
+                (user, tiers)
+            })
+            .collect()
+    }
+}
+
+/// Rate of a tier by name; `UNPRICED` costs nothing.
+pub fn rate_of(model: &CostModel, tier: &str) -> f64 {
+    model
+        .tiers()
+        .iter()
+        .find(|t| t.name == tier)
+        .map_or(0.0, |t| t.rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODEL: &str = "prefix,tier,rate\n\
+                         # comment\n\
+                         *,standard,0.02\n\
+                         /proj/fast,ssd,0.10\n\
+                         /proj/fast/scratch,scratch,0\n";
+
+    #[test]
+    fn parse_model_and_reject_bad_rows() {
+        let m = CostModel::parse(MODEL).unwrap();
+        assert_eq!(m.tiers().len(), 3);
+        assert_eq!(rate_of(&m, "ssd"), 0.10);
+        assert!(CostModel::parse("prefix,tier,rate\n/a,x,abc\n").is_err());
+        assert!(CostModel::parse("prefix,tier,rate\n/a,x,-1\n").is_err());
+        assert!(CostModel::parse("prefix,tier,rate\n*,x,1\n*,y,2\n").is_err());
+        assert!(CostModel::parse("prefix,tier,rate\n").is_err());
+    }
+
+    #[test]
+    fn nested_prefixes_are_billed_exclusively() {
+        let m = CostModel::parse(MODEL).unwrap();
+        let mut u = Usage::default();
+        // Rolled-up rows: / holds 100, /proj/fast 40 of it, scratch 10 of that.
+        u.add(&m, "/", "alice", 100);
+        u.add(&m, "/proj", "alice", 60);
+        u.add(&m, "/proj/fast", "alice", 40);
+        u.add(&m, "/proj/fast/scratch", "alice", 10);
+        u.add(&m, "/", "bob", 5);
+        let t = u.by_tier(&m);
+        assert_eq!(t["alice"]["standard"], 60);
+        assert_eq!(t["alice"]["ssd"], 30);
+        assert_eq!(t["alice"]["scratch"], 10);
+        assert_eq!(t["bob"].len(), 1);
+    }
+
+    #[test]
+    fn without_default_outside_bytes_are_unpriced() {
+        let m = CostModel::parse("prefix,tier,rate\n/proj,hot,1\n").unwrap();
+        let mut u = Usage::default();
+        u.add(&m, "/", "alice", 100);
+        u.add(&m, "/proj", "alice", 30);
+        let t = u.by_tier(&m);
+        assert_eq!(t["alice"][UNPRICED], 70);
+        assert_eq!(t["alice"]["hot"], 30);
+        assert_eq!(rate_of(&m, UNPRICED), 0.0);
+    }
+}
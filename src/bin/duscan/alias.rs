@@ -0,0 +1,104 @@
+// rs/src/bin/duscan/alias.rs
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+/// `--alias FROM=TO`: paths under FROM are emitted as if they were under TO,
+/// so outputs carry canonical names regardless of the scan host's mounts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Alias {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+pub fn parse_alias(s: &str) -> Result<Alias, String> {
+    let (from, to) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected FROM=TO, got '{s}'"))?;
+    let (from, to) = (from.trim(), to.trim());
+    if from.is_empty() || to.is_empty() {
+        return Err(format!("expected FROM=TO with both sides non-empty, got '{s}'"));
+    }
+    Ok(Alias {
+        from: PathBuf::from(from),
+        to: PathBuf::from(to),
+    })
+}
+
+impl Alias {
+    /// Canonicalize FROM so it matches the canonicalized scan roots (on
+    /// Windows that includes the `\\?\` verbatim prefix). A FROM that does
+    /// not exist on this host is kept verbatim.
+    pub fn canonicalized(self) -> Self {
+        let from = std::fs::canonicalize(&self.from).unwrap_or(self.from);
+        Alias { from, ..self }
+    }
+
+    /// `--relative`: the root is emitted as `.` and the entries below it as
+    /// `./NAME/...`, whatever the root is mounted as.
+    pub fn relative(root: &Path) -> Self {
+        Alias {
+            from: root.to_path_buf(),
+            to: PathBuf::from("."),
+        }
+    }
+}
+
+/// Rewrite `path` with the first alias whose FROM is a component-wise prefix.
+pub fn apply_aliases<'a>(aliases: &[Alias], path: &'a Path) -> Cow<'a, Path> {
+    for a in aliases {
+        if let Ok(rest) = path.strip_prefix(&a.from) {
+            if rest.as_os_str().is_empty() {
+                return Cow::Owned(a.to.clone());
+            }
+            return Cow::Owned(a.to.join(rest));
+        }
+    }
+    Cow::Borrowed(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_alias_accepts_from_eq_to() {
+        let a = parse_alias("/mnt/nfs/proj=/projects").unwrap();
+        assert_eq!(a.from, PathBuf::from("/mnt/nfs/proj"));
+        assert_eq!(a.to, PathBuf::from("/projects"));
+        assert!(parse_alias("/mnt/nfs/proj").is_err());
+        assert!(parse_alias("=/projects").is_err());
+        assert!(parse_alias("/mnt=").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn apply_aliases_rewrites_whole_components() {
+        let aliases = vec![parse_alias("/mnt/nfs/proj=/projects").unwrap()];
+        let p = Path::new("/mnt/nfs/proj/a/b.txt");
+        assert_eq!(apply_aliases(&aliases, p), Path::new("/projects/a/b.txt"));
+        let root = Path::new("/mnt/nfs/proj");
+        assert_eq!(apply_aliases(&aliases, root), Path::new("/projects"));
+        let other = Path::new("/mnt/nfs/project2/x");
+        assert!(matches!(apply_aliases(&aliases, other), Cow::Borrowed(_)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn first_matching_alias_wins() {
+        let aliases = vec![
+            parse_alias("/mnt/a/b=/deep").unwrap(),
+            parse_alias("/mnt/a=/shallow").unwrap(),
+        ];
+        assert_eq!(apply_aliases(&aliases, Path::new("/mnt/a/b/x")), Path::new("/deep/x"));
+        assert_eq!(apply_aliases(&aliases, Path::new("/mnt/a/c")), Path::new("/shallow/c"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn relative_alias_anchors_paths_at_dot() {
+        let aliases = vec![Alias::relative(Path::new("/mnt/nfs/proj"))];
+        assert_eq!(apply_aliases(&aliases, Path::new("/mnt/nfs/proj")), Path::new("."));
+        let p = apply_aliases(&aliases, Path::new("/mnt/nfs/proj/a/b.txt"));
+        assert_eq!(p.to_str(), Some("./a/b.txt"));
+    }
+}
@@ -0,0 +1,159 @@
+// rs/src/bin/duscan/batch.rs
+//
+// The payload of `Task::Files`: up to FILE_CHUNK entries of one directory.
+// Names are packed back to back in a single byte buffer and each stat result
+// is reduced to a `Row` by the lister, so a chunk costs three allocations
+// instead of an `OsString` plus a full `fs::Metadata` per file — the
+// allocator dominated on directories with millions of small files.
+//
+// `NameBatch` is the payload of `Task::Stat`: names of a huge directory
+// still to be stat'ed, packed the same way.
+use std::ffi::OsStr;
+
+use dutopia::util::Row;
+
+#[derive(Default)]
+pub struct FileBatch {
+    names: Vec<u8>,
+    /// End offset of each name in `names`
+    ends: Vec<u32>,
+    rows: Vec<Row>,
+}
+
+/// Name bytes reserved per entry up front; longer names just grow the buffer.
+const NAME_HINT: usize = 24;
+
+impl FileBatch {
+    pub fn with_capacity(files: usize) -> Self {
+        Self {
+            names: Vec::with_capacity(files * NAME_HINT),
+            ends: Vec::with_capacity(files),
+            rows: Vec::with_capacity(files),
+        }
+    }
+
+    pub fn push(&mut self, name: &OsStr, row: Row) {
+        self.names.extend_from_slice(name.as_encoded_bytes());
+        self.ends.push(self.names.len() as u32);
+        self.rows.push(row);
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&OsStr, &Row)> {
+        let starts = std::iter::once(0).chain(self.ends.iter().copied());
+        self.ends
+            .iter()
+            .zip(starts)
+            .zip(&self.rows)
+            .map(|((&end, start), row)| {
+                let bytes = &self.names[start as usize..end as usize];
+                // SAFETY: every slice is exactly the bytes of one `OsStr` passed to
+                // `push`, so it is valid encoded `OsStr` data.
+                (unsafe { OsStr::from_encoded_bytes_unchecked(bytes) }, row)
+            })
+    }
+}
+
+/// Names of one directory, without their rows yet.
+#[derive(Default)]
+pub struct NameBatch {
+    names: Vec<u8>,
+    /// End offset of each name in `names`
+    ends: Vec<u32>,
+}
+
+impl NameBatch {
+    pub fn with_capacity(files: usize) -> Self {
+        Self {
+            names: Vec::with_capacity(files * NAME_HINT),
+            ends: Vec::with_capacity(files),
+        }
+    }
+
+    pub fn push(&mut self, name: &OsStr) {
+        self.names.extend_from_slice(name.as_encoded_bytes());
+        self.ends.push(self.names.len() as u32);
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &OsStr> {
+        let starts = std::iter::once(0).chain(self.ends.iter().copied());
+        self.ends.iter().zip(starts).map(|(&end, start)| {
+            // SAFETY: as in `FileBatch::iter`.
+            unsafe { OsStr::from_encoded_bytes_unchecked(&self.names[start as usize..end as usize]) }
+        })
+    }
+}
+
+impl std::fmt::Debug for NameBatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl std::fmt::Debug for FileBatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.iter().map(|(name, _)| name))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(size: u64) -> Row {
+        Row {
+            dev: 0,
+            ino: 0,
+            mode: 0o100644,
+            uid: 0,
+            gid: 0,
+            size,
+            blocks: 0,
+            atime: 0,
+            mtime: 0,
+        }
+    }
+
+    #[test]
+    fn names_and_rows_round_trip() {
+        let mut b = FileBatch::with_capacity(2);
+        assert!(b.is_empty());
+        b.push(OsStr::new("a.txt"), row(1));
+        b.push(OsStr::new(""), row(2));
+        b.push(OsStr::new("名前 with spaces"), row(3));
+        assert_eq!(b.len(), 3);
+        let got: Vec<_> = b.iter().map(|(n, r)| (n.to_owned(), r.size)).collect();
+        assert_eq!(
+            got,
+            [
+                ("a.txt".into(), 1),
+                ("".into(), 2),
+                ("名前 with spaces".into(), 3)
+            ]
+        );
+        assert!(format!("{b:?}").contains("a.txt"));
+
+        let mut n = NameBatch::with_capacity(1);
+        n.push(OsStr::new("x"));
+        n.push(OsStr::new("名前"));
+        assert_eq!(n.len(), 2);
+        assert_eq!(n.iter().collect::<Vec<_>>(), [OsStr::new("x"), OsStr::new("名前")]);
+    }
+}
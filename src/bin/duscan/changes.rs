@@ -0,0 +1,208 @@
+// rs/src/bin/duscan/changes.rs
+//
+// `--since SCAN`: the directories that changed since an earlier scan of the
+// same roots, written to `<output>.changes.csv` beside the output. It scopes
+// incremental backups on its own, without diffing the file rows. A
+// directory is
+//
+//   added     walked now, not in SCAN
+//   removed   in SCAN, not walked now
+//   modified  in both, with another mtime: an entry was created, deleted or
+//             renamed in it (files edited in place do not change it)
+//
+// SCAN (CSV or `--bin` .zst) is read first, keeping one entry per
+// directory. Paths are compared as written to the output, aliases applied.
+// A directory this run does not walk (`--skip`, `--exclude`,
+// `--exclude-fstype`, `-x`, `--max-depth`, `--time-limit`) counts as
+// removed, so both scans should use the same roots and options.
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use std::sync::Mutex;
+
+use dutopia::fileindex::for_each_row;
+use dutopia::util::strip_verbatim_prefix;
+
+#[cfg(unix)]
+use crate::csv::csv_push_bytes_smart_quoted;
+#[cfg(windows)]
+use crate::csv::csv_push_str_smart_quoted;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Added,
+    Removed,
+    Modified,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Added => "added",
+            Kind::Removed => "removed",
+            Kind::Modified => "modified",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: Kind,
+    pub path: String,
+    /// Now; `None` when removed
+    pub mtime: Option<i64>,
+    /// In SCAN; `None` when added
+    pub previous: Option<i64>,
+}
+
+pub struct Changes {
+    /// Directory path -> (mtime, index into `seen`)
+    previous: HashMap<String, (i64, usize)>,
+    seen: Vec<AtomicBool>,
+    found: Mutex<Vec<Change>>,
+}
+
+impl Changes {
+    /// The directories of the scan at `scan`.
+    pub fn load(scan: &Path) -> Result<Self> {
+        let mut dirs = Vec::new();
+        for_each_row(scan, |r| {
+            if r.mode & S_IFMT == S_IFDIR {
+                dirs.push((r.path, r.mtime));
+            }
+            Ok(())
+        })
+        .with_context(|| format!("reading --since {}", scan.display()))?;
+        Ok(Self::new(dirs))
+    }
+
+    pub fn new(dirs: impl IntoIterator<Item = (String, i64)>) -> Self {
+        let previous: HashMap<String, (i64, usize)> = dirs
+            .into_iter()
+            .enumerate()
+            .map(|(i, (path, mtime))| (path, (mtime, i)))
+            .collect();
+        let seen = (0..previous.len()).map(|_| AtomicBool::new(false)).collect();
+        Self {
+            previous,
+            seen,
+            found: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Directories in the earlier scan.
+    pub fn previous_dirs(&self) -> usize {
+        self.previous.len()
+    }
+
+    /// Note the directory `path` (as output) walked with `mtime`.
+    pub fn observe(&self, path: &Path, mtime: i64) {
+        let shown = strip_verbatim_prefix(path);
+        let path = shown.to_string_lossy();
+        let change = match self.previous.get(path.as_ref()) {
+            Some(&(before, i)) => {
+                self.seen[i].store(true, Relaxed);
+                if before == mtime {
+                    return;
+                }
+                Change { kind: Kind::Modified, path: path.into_owned(), mtime: Some(mtime), previous: Some(before) }
+            }
+            None => Change { kind: Kind::Added, path: path.into_owned(), mtime: Some(mtime), previous: None },
+        };
+        self.found.lock().unwrap_or_else(|e| e.into_inner()).push(change);
+    }
+
+    /// Every change, removals included, sorted by path.
+    pub fn finish(&self) -> Vec<Change> {
+        let mut out = std::mem::take(&mut *self.found.lock().unwrap_or_else(|e| e.into_inner()));
+        for (path, &(mtime, i)) in &self.previous {
+            if !self.seen[i].load(Relaxed) {
+                out.push(Change { kind: Kind::Removed, path: path.clone(), mtime: None, previous: Some(mtime) });
+            }
+        }
+        out.sort_by(|a, b| a.path.cmp(&b.path).then(a.kind.cmp(&b.kind)));
+        out
+    }
+}
+
+/// `<output>.changes.csv` next to the scan output.
+pub fn changes_path(output: &Path) -> PathBuf {
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(".changes.csv");
+    output.with_file_name(name)
+}
+
+/// Write `changes` as `change,path,mtime,previous_mtime`.
+pub fn write_changes(path: &Path, changes: &[Change]) -> Result<()> {
+    let f = File::create(path).with_context(|| format!("create {}", path.display()))?;
+    let mut w = BufWriter::new(f);
+    writeln!(w, "change,path,mtime,previous_mtime")?;
+    let mut line = Vec::with_capacity(256);
+    for c in changes {
+        line.clear();
+        line.extend_from_slice(c.kind.name().as_bytes());
+        line.push(b',');
+        #[cfg(unix)]
+        csv_push_bytes_smart_quoted(&mut line, c.path.as_bytes());
+        #[cfg(windows)]
+        csv_push_str_smart_quoted(&mut line, &c.path);
+        line.push(b',');
+        if let Some(t) = c.mtime {
+            line.extend_from_slice(t.to_string().as_bytes());
+        }
+        line.push(b',');
+        if let Some(t) = c.previous {
+            line.extend_from_slice(t.to_string().as_bytes());
+        }
+        line.push(b'\n');
+        w.write_all(&line)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directories_are_added_removed_or_modified() {
+        let changes = Changes::new([
+            ("/proj".to_string(), 100),
+            ("/proj/a".to_string(), 200),
+            ("/proj/gone".to_string(), 300),
+        ]);
+        assert_eq!(changes.previous_dirs(), 3);
+        changes.observe(Path::new("/proj"), 100);
+        changes.observe(Path::new("/proj/a"), 250);
+        changes.observe(Path::new("/proj/new, dir"), 400);
+        let found = changes.finish();
+        let kinds: Vec<_> = found.iter().map(|c| (c.kind, c.path.as_str())).collect();
+        assert_eq!(
+            kinds,
+            [
+                (Kind::Modified, "/proj/a"),
+                (Kind::Removed, "/proj/gone"),
+                (Kind::Added, "/proj/new, dir"),
+            ]
+        );
+
+        let tmp = tempfile::tempdir().unwrap();
+        let out = changes_path(&tmp.path().join("scan.csv"));
+        assert!(out.ends_with("scan.csv.changes.csv"));
+        write_changes(&out, &found).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            "change,path,mtime,previous_mtime\n\
+             modified,/proj/a,250,200\n\
+             removed,/proj/gone,,300\n\
+             added,\"/proj/new, dir\",400,\n"
+        );
+    }
+}
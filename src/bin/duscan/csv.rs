@@ -6,6 +6,17 @@ use dutopia::util::{Row, push_u32, push_u64, push_i64};
 use std::os::unix::ffi::OsStrExt;
 
 pub fn write_row_csv(buf: &mut Vec<u8>, path: &Path, r: &Row, no_atime: bool) {
+    write_row_csv_with(buf, path, r, no_atime, &[]);
+}
+
+/// `write_row_csv` plus `--enrich` values appended after PATH.
+pub fn write_row_csv_with(
+    buf: &mut Vec<u8>,
+    path: &Path,
+    r: &Row,
+    no_atime: bool,
+    extra: &[String],
+) {
     buf.reserve(256);
     // INODE as dev-ino
     push_u64(buf, r.dev);
@@ -41,6 +52,10 @@ pub fn write_row_csv(buf: &mut Vec<u8>, path: &Path, r: &Row, no_atime: bool) {
     buf.push(b',');
 
     csv_push_path_smart_quoted(buf, path);
+    for v in extra {
+        buf.push(b',');
+        csv_push_bytes_smart_quoted(buf, v.as_bytes());
+    }
     buf.push(b'\n');
 }
 
@@ -302,6 +317,26 @@ mod tests {
         assert!(result.starts_with("1-2,0,1234567891,1000,1000,755,1024,1024,"));
     }
 
+    #[test]
+    fn test_write_row_csv_with_extra_columns() {
+        let mut buf = Vec::new();
+        let row = Row {
+            dev: 1,
+            ino: 2,
+            mode: 755,
+            uid: 1000,
+            gid: 1000,
+            size: 1024,
+            blocks: 2,
+            atime: 0,
+            mtime: 5,
+        };
+        let extra = ["p1".to_string(), "a,b".to_string(), String::new()];
+        write_row_csv_with(&mut buf, Path::new("/x"), &row, true, &extra);
+        let result = String::from_utf8(buf).unwrap();
+        assert_eq!(result, "1-2,0,5,1000,1000,755,1024,1024,/x,p1,\"a,b\",\n");
+    }
+
     #[test]
     fn test_write_row_bin_with_atime() {
         let mut buf = Vec::new();
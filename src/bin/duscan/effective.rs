@@ -0,0 +1,224 @@
+// rs/src/bin/duscan/effective.rs
+//
+// `--effective-owner RULE`: an `EFFECTIVE_OWNER` column naming who a row is
+// attributed to, for trees where a shared service account owns most files
+// and per-uid reports say nothing. The rules:
+//
+//   parent            owner of the directory holding the entry
+//   acl-user[:PFX]    first named user entry (`user:NAME:...`) of the
+//                     entry's POSIX access ACL, optionally only names
+//                     starting with PFX (Linux)
+//   acl-group[:PFX]   the same for named group entries, e.g.
+//                     `acl-group:proj-` for per-project groups (Linux)
+//
+// When the rule finds nobody the entry's own owner is written, so the column
+// is never empty. Names are resolved once per id and thread; ids without a
+// name are written as numbers. Like the security columns the value comes from
+// the scanned path, before aliases and redaction.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use dutopia::item::owner_of_uid;
+use dutopia::util::Row;
+
+use crate::row::row_for_path;
+use crate::security::xattr;
+
+// `acl_ea_entry` tags (`include/uapi/linux/posix_acl.h`).
+const ACL_USER: u16 = 0x02;
+const ACL_GROUP: u16 = 0x08;
+const ACL_EA_VERSION: u32 = 2;
+
+pub const COLUMN: &str = "EFFECTIVE_OWNER";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OwnerRule {
+    Parent,
+    AclUser(String),
+    AclGroup(String),
+}
+
+/// clap parser for `--effective-owner`.
+pub fn parse_rule(s: &str) -> Result<OwnerRule, String> {
+    let (kind, prefix) = match s.trim().split_once(':') {
+        Some((k, p)) => (k, p.to_string()),
+        None => (s.trim(), String::new()),
+    };
+    match kind {
+        "parent" if prefix.is_empty() => Ok(OwnerRule::Parent),
+        "acl-user" => Ok(OwnerRule::AclUser(prefix)),
+        "acl-group" => Ok(OwnerRule::AclGroup(prefix)),
+        _ => Err(format!(
+            "'{s}' is not an owner rule: parent, acl-user[:PREFIX] or acl-group[:PREFIX]"
+        )),
+    }
+}
+
+impl std::fmt::Display for OwnerRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OwnerRule::Parent => f.write_str("parent"),
+            OwnerRule::AclUser(p) if p.is_empty() => f.write_str("acl-user"),
+            OwnerRule::AclGroup(p) if p.is_empty() => f.write_str("acl-group"),
+            OwnerRule::AclUser(p) => write!(f, "acl-user:{p}"),
+            OwnerRule::AclGroup(p) => write!(f, "acl-group:{p}"),
+        }
+    }
+}
+
+impl OwnerRule {
+    pub fn is_acl(&self) -> bool {
+        !matches!(self, OwnerRule::Parent)
+    }
+
+    /// The owner `path`, with `row`, is attributed to.
+    pub fn value(&self, path: &Path, row: &Row) -> String {
+        let found = match self {
+            OwnerRule::Parent => parent_uid(path).map(user_name),
+            OwnerRule::AclUser(prefix) => named_entry(path, ACL_USER, prefix, user_name),
+            OwnerRule::AclGroup(prefix) => named_entry(path, ACL_GROUP, prefix, group_name),
+        };
+        found.unwrap_or_else(|| user_name(row.uid))
+    }
+}
+
+thread_local! {
+    /// Last directory looked up and its owner; a worker emits the entries
+    /// of one directory in a row.
+    static PARENT: RefCell<Option<(PathBuf, Option<u32>)>> = const { RefCell::new(None) };
+    static NAMES: RefCell<HashMap<(bool, u32), String>> = RefCell::new(HashMap::new());
+}
+
+fn parent_uid(path: &Path) -> Option<u32> {
+    let parent = path.parent()?;
+    PARENT.with_borrow_mut(|last| {
+        if let Some((dir, uid)) = last.as_ref()
+            && dir == parent
+        {
+            return *uid;
+        }
+        let uid = fs::symlink_metadata(parent)
+            .ok()
+            .map(|md| row_for_path(parent, &md).uid);
+        *last = Some((parent.to_path_buf(), uid));
+        uid
+    })
+}
+
+fn named_entry(
+    path: &Path,
+    tag: u16,
+    prefix: &str,
+    name: fn(u32) -> String,
+) -> Option<String> {
+    let raw = xattr(path, c"system.posix_acl_access")?;
+    acl_ids(&raw, tag)
+        .into_iter()
+        .map(name)
+        .find(|n| n.starts_with(prefix))
+}
+
+/// Ids of the entries tagged `tag` in a `system.posix_acl_*` attribute, in
+/// the kernel's order (ascending id).
+pub fn acl_ids(raw: &[u8], tag: u16) -> Vec<u32> {
+    let Some(version) = raw.get(..4) else {
+        return Vec::new();
+    };
+    if u32::from_le_bytes([version[0], version[1], version[2], version[3]]) != ACL_EA_VERSION {
+        return Vec::new();
+    }
+    raw[4..]
+        .chunks_exact(8)
+        .filter(|e| u16::from_le_bytes([e[0], e[1]]) == tag)
+        .map(|e| u32::from_le_bytes([e[4], e[5], e[6], e[7]]))
+        .collect()
+}
+
+fn cached(group: bool, id: u32, resolve: impl FnOnce() -> Option<String>) -> String {
+    NAMES.with_borrow_mut(|names| {
+        names
+            .entry((group, id))
+            .or_insert_with(|| resolve().unwrap_or_else(|| id.to_string()))
+            .clone()
+    })
+}
+
+fn user_name(uid: u32) -> String {
+    cached(false, uid, || Some(owner_of_uid(uid)).filter(|n| n != "UNK"))
+}
+
+#[cfg(unix)]
+fn group_name(gid: u32) -> String {
+    cached(true, gid, || {
+        // SAFETY: getgrgid returns null or a pointer to a static entry whose
+        // name, when set, is NUL-terminated; it is copied before returning.
+        unsafe {
+            let group = libc::getgrgid(gid);
+            if group.is_null() || (*group).gr_name.is_null() {
+                return None;
+            }
+            std::ffi::CStr::from_ptr((*group).gr_name)
+                .to_str()
+                .ok()
+                .map(str::to_string)
+        }
+    })
+}
+
+#[cfg(not(unix))]
+fn group_name(gid: u32) -> String {
+    gid.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn acl(entries: &[(u16, u32)]) -> Vec<u8> {
+        let mut raw = ACL_EA_VERSION.to_le_bytes().to_vec();
+        for &(tag, id) in entries {
+            raw.extend(tag.to_le_bytes());
+            raw.extend(7u16.to_le_bytes());
+            raw.extend(id.to_le_bytes());
+        }
+        raw
+    }
+
+    #[test]
+    fn rules_parse_and_acl_entries_are_found() {
+        assert_eq!(parse_rule("parent"), Ok(OwnerRule::Parent));
+        assert_eq!(parse_rule("acl-group:proj-"), Ok(OwnerRule::AclGroup("proj-".into())));
+        assert_eq!(parse_rule("acl-user"), Ok(OwnerRule::AclUser(String::new())));
+        assert!(parse_rule("parent:x").is_err());
+        assert!(parse_rule("uid").is_err());
+
+        // user_obj, user:1001, user:1005, group_obj, group:2000, mask, other
+        let raw = acl(&[
+            (0x01, u32::MAX),
+            (ACL_USER, 1001),
+            (ACL_USER, 1005),
+            (0x04, u32::MAX),
+            (ACL_GROUP, 2000),
+            (0x10, u32::MAX),
+            (0x20, u32::MAX),
+        ]);
+        assert_eq!(acl_ids(&raw, ACL_USER), [1001, 1005]);
+        assert_eq!(acl_ids(&raw, ACL_GROUP), [2000]);
+        assert!(acl_ids(&raw[..3], ACL_USER).is_empty());
+        assert!(acl_ids(&acl(&[(ACL_USER, 1)])[4..], ACL_USER).is_empty());
+
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("f");
+        fs::write(&file, "x").unwrap();
+        let md = fs::symlink_metadata(&file).unwrap();
+        let row = row_for_path(&file, &md);
+        let dir_row = row_for_path(tmp.path(), &fs::symlink_metadata(tmp.path()).unwrap());
+        assert_eq!(OwnerRule::Parent.value(&file, &row), user_name(dir_row.uid));
+        // No ACL on the file: its own owner.
+        let none = OwnerRule::AclGroup("zz-".into()).value(&file, &row);
+        assert_eq!(none, user_name(row.uid));
+        assert!(!none.is_empty());
+    }
+}
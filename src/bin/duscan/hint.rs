@@ -0,0 +1,115 @@
+// rs/src/bin/duscan/hint.rs
+//
+// Files hint from a previous run: the row count of an earlier duscan output
+// (`--previous`, or the output file this run is about to replace) is a far
+// better estimate of the total than a hand-typed `--files-hint`, because
+// progress counts exactly one row per entry. Counting a large output takes a
+// while, so it runs beside the scan and progress switches to percentages
+// once the count is in.
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use dutopia::util::progress::Counter;
+
+const ZSTD_MAGIC: u32 = 0xFD2F_B528;
+/// Fixed part of a binary record after the path (see `duzip`).
+const BIN_FIXED: u64 = 60;
+
+/// Rows in a duscan CSV (header excluded) or binary .zst output. CSV rows
+/// are counted as lines, so a path with an embedded newline counts twice —
+/// close enough for a progress estimate.
+pub fn count_rows(path: &Path) -> Result<u64> {
+    let mut file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut magic = [0u8; 4];
+    let is_zst = file.read_exact(&mut magic).is_ok() && u32::from_le_bytes(magic) == ZSTD_MAGIC;
+    file.rewind()?;
+    if is_zst {
+        return count_bin_rows(zstd::stream::read::Decoder::new(file)?);
+    }
+
+    let mut r = BufReader::with_capacity(1 << 20, file);
+    let mut lines = 0u64;
+    let mut last = b'\n';
+    loop {
+        let buf = r.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        lines += memchr::memchr_iter(b'\n', buf).count() as u64;
+        last = buf[buf.len() - 1];
+        let n = buf.len();
+        r.consume(n);
+    }
+    if last != b'\n' {
+        lines += 1;
+    }
+    Ok(lines.saturating_sub(1))
+}
+
+fn count_bin_rows(r: impl Read) -> Result<u64> {
+    let mut r = BufReader::with_capacity(1 << 20, r);
+    let mut rows = 0u64;
+    let mut len = [0u8; 4];
+    loop {
+        match r.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(rows),
+            Err(e) => return Err(e.into()),
+        }
+        let skip = u32::from_le_bytes(len) as u64 + BIN_FIXED;
+        if std::io::copy(&mut (&mut r).take(skip), &mut std::io::sink())? != skip {
+            return Err(anyhow!("truncated record after {rows} rows"));
+        }
+        rows += 1;
+    }
+}
+
+/// Count `previous` on a background thread and make it the total of
+/// `counter`; the total stays unknown if it cannot be read.
+pub fn count_in_background(previous: PathBuf, counter: Arc<Counter>) {
+    thread::spawn(move || match count_rows(&previous) {
+        Ok(rows) if rows > 0 => {
+            tracing::info!(rows, previous = %previous.display(), "files hint from previous output");
+            counter.set_total(rows);
+        }
+        Ok(_) => {}
+        Err(e) => {
+            tracing::warn!(previous = %previous.display(), error = format!("{e:#}"), "cannot count previous output");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_csv_and_binary_rows() {
+        let tmp = tempfile::tempdir().unwrap();
+        let csv = tmp.path().join("scan.csv");
+        std::fs::write(
+            &csv,
+            "INODE,ATIME,MTIME,UID,GID,MODE,SIZE,DISK,PATH\n1-1,0,0,0,0,16877,0,0,/a\n1-2,0,0,0,0,33188,1,8,/a/b",
+        )
+        .unwrap();
+        assert_eq!(count_rows(&csv).unwrap(), 2);
+
+        let mut raw = Vec::new();
+        for path in [&b"/a"[..], b"/a/bb"] {
+            raw.extend_from_slice(&(path.len() as u32).to_le_bytes());
+            raw.extend_from_slice(path);
+            raw.extend_from_slice(&[0u8; BIN_FIXED as usize]);
+        }
+        let zst = tmp.path().join("scan.zst");
+        std::fs::write(&zst, zstd::encode_all(raw.as_slice(), 1).unwrap()).unwrap();
+        assert_eq!(count_rows(&zst).unwrap(), 2);
+
+        raw.truncate(raw.len() - 1);
+        std::fs::write(&zst, zstd::encode_all(raw.as_slice(), 1).unwrap()).unwrap();
+        assert!(count_rows(&zst).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+// rs/src/bin/duscan/jsonl.rs
+//
+// `--format jsonl`: one JSON object per entry and per line (NDJSON), for
+// log pipelines such as Vector, Fluentd or Elasticsearch that take it
+// as is:
+//
+//   {"path":"/proj/a.txt","dev":2049,"ino":131,"atime":1700000000,
+//    "mtime":1690000000,"uid":1000,"gid":1000,"mode":33188,"size":12,"disk":4096}
+//
+// The keys hold the CSV fields by name, INODE split in `dev` and `ino`.
+// JSON strings are UTF-8, so bytes of a path that are not are written as
+// U+FFFD. Lines are self-contained, so shards are merged (and sorted with
+// `--no-atime`) the way CSV shards are, with no header.
+use std::path::Path;
+use dutopia::util::{push_i64, push_u32, push_u64, Row};
+
+pub fn write_row_jsonl(buf: &mut Vec<u8>, path: &Path, r: &Row, no_atime: bool) {
+    buf.reserve(192);
+    #[cfg(not(unix))]
+    let path = &dutopia::util::strip_verbatim_prefix(path);
+    buf.extend_from_slice(b"{\"path\":\"");
+    push_json_escaped(buf, &path.to_string_lossy());
+    buf.extend_from_slice(b"\",\"dev\":");
+    push_u64(buf, r.dev);
+    buf.extend_from_slice(b",\"ino\":");
+    push_u64(buf, r.ino);
+    buf.extend_from_slice(b",\"atime\":");
+    push_i64(buf, if no_atime { 0 } else { r.atime });
+    buf.extend_from_slice(b",\"mtime\":");
+    push_i64(buf, r.mtime);
+    buf.extend_from_slice(b",\"uid\":");
+    push_u32(buf, r.uid);
+    buf.extend_from_slice(b",\"gid\":");
+    push_u32(buf, r.gid);
+    buf.extend_from_slice(b",\"mode\":");
+    push_u32(buf, r.mode);
+    buf.extend_from_slice(b",\"size\":");
+    push_u64(buf, r.size);
+    buf.extend_from_slice(b",\"disk\":");
+    push_u64(buf, r.blocks * 512);
+    buf.extend_from_slice(b"}\n");
+}
+
+/// `s` as the inside of a JSON string.
+fn push_json_escaped(buf: &mut Vec<u8>, s: &str) {
+    let bytes = s.as_bytes();
+    if !bytes.iter().any(|&b| b < 0x20 || b == b'"' || b == b'\\') {
+        buf.extend_from_slice(bytes);
+        return;
+    }
+    for &b in bytes {
+        match b {
+            b'"' => buf.extend_from_slice(b"\\\""),
+            b'\\' => buf.extend_from_slice(b"\\\\"),
+            b'\n' => buf.extend_from_slice(b"\\n"),
+            b'\r' => buf.extend_from_slice(b"\\r"),
+            b'\t' => buf.extend_from_slice(b"\\t"),
+            0..0x20 => {
+                const HEX: &[u8; 16] = b"0123456789abcdef";
+                buf.extend_from_slice(b"\\u00");
+                buf.push(HEX[(b >> 4) as usize]);
+                buf.push(HEX[(b & 0xf) as usize]);
+            }
+            _ => buf.push(b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_json_objects_with_escaped_paths() {
+        let row = Row {
+            dev: 2049,
+            ino: 131,
+            atime: 1_700_000_000,
+            mtime: 1_690_000_000,
+            uid: 1000,
+            gid: 100,
+            mode: 0o100644,
+            size: 12,
+            blocks: 8,
+        };
+        let mut buf = Vec::new();
+        write_row_jsonl(&mut buf, Path::new("/proj/a \"b\"\\c\n\x01.txt"), &row, false);
+        write_row_jsonl(&mut buf, Path::new("/proj/plain"), &row, true);
+        let text = String::from_utf8(buf).unwrap();
+        let lines: Vec<serde_json::Value> =
+            text.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["path"], "/proj/a \"b\"\\c\n\x01.txt");
+        assert_eq!(lines[0]["ino"], 131);
+        assert_eq!(lines[0]["atime"], 1_700_000_000);
+        assert_eq!(lines[0]["mode"], 0o100644);
+        assert_eq!(lines[0]["disk"], 4096);
+        assert_eq!(lines[1]["path"], "/proj/plain");
+        assert_eq!(lines[1]["atime"], 0);
+    }
+}
@@ -0,0 +1,210 @@
+// rs/src/bin/duscan/listing.rs
+//
+// How a directory is read. Entries are stat'ed relative to the open
+// directory without following symlinks (`fstatat(dirfd, name,
+// AT_SYMLINK_NOFOLLOW)`, which is what `DirEntry::metadata` does on Linux),
+// so a symlink always reports itself and never its target.
+//
+// Reading a directory updates its atime, the very data the age reports are
+// built from. `--preserve-atime` (Linux) opens each directory with
+// `O_NOATIME` instead of through `fs::read_dir`. The kernel allows that flag
+// only to the directory's owner or a process with CAP_FOWNER; elsewhere the
+// open fails with EPERM and the directory is read the ordinary way, so the
+// flag only needs root to be complete. duscan opens no file contents, so
+// directories are all there is to protect.
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+
+use dutopia::util::Row;
+
+use crate::row::row_from_metadata;
+use crate::types::Kind;
+
+/// One entry of a directory listing.
+pub trait Listed {
+    fn name(&self) -> OsString;
+    fn kind(&self) -> io::Result<Kind>;
+    /// The entry's own metadata, never a symlink target's.
+    fn lstat(&self) -> io::Result<Row>;
+}
+
+impl Listed for fs::DirEntry {
+    fn name(&self) -> OsString {
+        self.file_name()
+    }
+
+    fn kind(&self) -> io::Result<Kind> {
+        self.file_type().map(|ft| Kind::of(&ft))
+    }
+
+    fn lstat(&self) -> io::Result<Row> {
+        let md = self.metadata()?;
+        #[cfg(windows)]
+        return Ok(crate::row::row_for_path(&self.path(), &md));
+        #[cfg(not(windows))]
+        Ok(row_from_metadata(&md))
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub mod noatime {
+    use std::ffi::{CStr, CString, OsString};
+    use std::io;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::Path;
+    use std::ptr::NonNull;
+    use std::rc::Rc;
+
+    use dutopia::util::Row;
+
+    use super::Listed;
+    use crate::types::Kind;
+
+    /// An open directory stream.
+    pub struct Dir(NonNull<libc::DIR>);
+
+    impl Drop for Dir {
+        fn drop(&mut self) {
+            unsafe { libc::closedir(self.0.as_ptr()) };
+        }
+    }
+
+    fn cstring(bytes: &[u8]) -> io::Result<CString> {
+        CString::new(bytes).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))
+    }
+
+    fn open_flags(path: &CStr, flags: libc::c_int) -> io::Result<libc::c_int> {
+        let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC | flags) };
+        if fd < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(fd)
+        }
+    }
+
+    /// Entries of `path`, read through an `O_NOATIME` descriptor when the
+    /// kernel allows it.
+    pub fn read_dir(path: &Path) -> io::Result<Entries> {
+        let c = cstring(path.as_os_str().as_bytes())?;
+        let fd = match open_flags(&c, libc::O_NOATIME) {
+            Err(e) if e.raw_os_error() == Some(libc::EPERM) => open_flags(&c, 0)?,
+            r => r?,
+        };
+        match NonNull::new(unsafe { libc::fdopendir(fd) }) {
+            Some(dirp) => Ok(Entries(Rc::new(Dir(dirp)))),
+            None => {
+                let e = io::Error::last_os_error();
+                unsafe { libc::close(fd) };
+                Err(e)
+            }
+        }
+    }
+
+    pub struct Entries(Rc<Dir>);
+
+    impl Iterator for Entries {
+        type Item = io::Result<Entry>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            // readdir returns NULL both at the end and on error; errno tells.
+            unsafe { *libc::__errno_location() = 0 };
+            let ent = unsafe { libc::readdir((self.0).0.as_ptr()) };
+            if ent.is_null() {
+                let e = io::Error::last_os_error();
+                return (e.raw_os_error() != Some(0)).then_some(Err(e));
+            }
+            let (name, d_type) = unsafe { (CStr::from_ptr((*ent).d_name.as_ptr()), (*ent).d_type) };
+            Some(Ok(Entry {
+                dir: self.0.clone(),
+                name: name.to_owned(),
+                d_type,
+            }))
+        }
+    }
+
+    pub struct Entry {
+        dir: Rc<Dir>,
+        name: CString,
+        d_type: u8,
+    }
+
+    impl Listed for Entry {
+        fn name(&self) -> OsString {
+            OsString::from_vec(self.name.as_bytes().to_vec())
+        }
+
+        fn kind(&self) -> io::Result<Kind> {
+            let mode = match self.d_type {
+                libc::DT_DIR => libc::S_IFDIR,
+                libc::DT_LNK => libc::S_IFLNK,
+                libc::DT_REG => libc::S_IFREG,
+                libc::DT_SOCK => libc::S_IFSOCK,
+                libc::DT_FIFO => libc::S_IFIFO,
+                libc::DT_BLK => libc::S_IFBLK,
+                libc::DT_CHR => libc::S_IFCHR,
+                // DT_UNKNOWN: the filesystem does not fill d_type.
+                _ => self.lstat()?.mode,
+            };
+            Ok(Kind::from_mode(mode))
+        }
+
+        fn lstat(&self) -> io::Result<Row> {
+            let mut st: libc::stat = unsafe { std::mem::zeroed() };
+            let rc = unsafe {
+                libc::fstatat(
+                    libc::dirfd(self.dir.0.as_ptr()),
+                    self.name.as_ptr(),
+                    &mut st,
+                    libc::AT_SYMLINK_NOFOLLOW,
+                )
+            };
+            if rc != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Row {
+                dev: st.st_dev,
+                ino: st.st_ino,
+                mode: st.st_mode,
+                uid: st.st_uid,
+                gid: st.st_gid,
+                size: st.st_size as u64,
+                blocks: st.st_blocks as u64,
+                atime: st.st_atime,
+                mtime: st.st_mtime,
+            })
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noatime_listing_matches_read_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("f"), b"12345").unwrap();
+        fs::create_dir(tmp.path().join("d")).unwrap();
+        std::os::unix::fs::symlink("f", tmp.path().join("l")).unwrap();
+
+        fn seen<E: Listed>(e: E) -> (OsString, Kind, u64, u32, u64, i64) {
+            let r = e.lstat().unwrap();
+            (e.name(), e.kind().unwrap(), r.ino, r.mode, r.size, r.mtime)
+        }
+        let mut ours: Vec<_> = noatime::read_dir(tmp.path())
+            .unwrap()
+            .map(|e| e.unwrap())
+            .filter(|e| e.name() != "." && e.name() != "..")
+            .map(seen)
+            .collect();
+        let mut std: Vec<_> = fs::read_dir(tmp.path()).unwrap().map(|e| seen(e.unwrap())).collect();
+        ours.sort_by(|a, b| a.0.cmp(&b.0));
+        std.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(ours, std);
+        assert_eq!((ours[1].0.as_os_str(), ours[1].4), ("f".as_ref(), 5));
+        assert_eq!(ours[2].1, Kind::from_mode(0o120000));
+        assert!(ours[0].1.is_dir());
+        assert!(noatime::read_dir(&tmp.path().join("f")).is_err());
+    }
+}
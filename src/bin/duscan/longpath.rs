@@ -0,0 +1,139 @@
+// rs/src/bin/duscan/longpath.rs
+//
+// Paths past the platform limit. Linux system calls take at most 4095
+// bytes of path (PATH_MAX with its NUL), so a directory nested deeper than
+// that cannot be listed by name. Windows allows 259 UTF-16 units (MAX_PATH)
+// unless the path carries the `\\?\` verbatim prefix, which raises the
+// limit to 32767; roots are given that prefix, so every path joined to them
+// takes the long form. A directory whose path is over the limit is not
+// entered and a file whose stat fails for its length is not written. Both
+// are counted, with a few examples, and reported at the end of the scan
+// instead of ending up among the generic errors.
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+/// Examples kept for the summary.
+const EXAMPLES: usize = 5;
+
+#[cfg(unix)]
+pub const LIMIT: &str = "4095 bytes";
+#[cfg(windows)]
+pub const LIMIT: &str = "259 characters (32767 with \\\\?\\)";
+#[cfg(not(any(unix, windows)))]
+pub const LIMIT: &str = "the platform limit";
+
+/// Whether `path` is longer than the platform accepts.
+pub fn too_long(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        path.as_os_str().len() >= 4096
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        let units = path.as_os_str().encode_wide().count();
+        let verbatim = path.as_os_str().to_string_lossy().starts_with(r"\\?\");
+        units >= if verbatim { 32767 } else { 260 }
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// Whether `e` is the platform's "name too long" error.
+pub fn is_too_long(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        e.raw_os_error() == Some(libc::ENAMETOOLONG)
+    }
+    #[cfg(windows)]
+    {
+        // ERROR_FILENAME_EXCED_RANGE
+        e.raw_os_error() == Some(206)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = e;
+        false
+    }
+}
+
+/// `path` with the `\\?\` (or `\\?\UNC\`) prefix on Windows; unchanged
+/// elsewhere, or when it is not absolute.
+pub fn verbatim(path: PathBuf) -> PathBuf {
+    #[cfg(windows)]
+    {
+        let s = path.to_string_lossy().into_owned();
+        if s.starts_with(r"\\?\") || !path.is_absolute() {
+            path
+        } else if let Some(unc) = s.strip_prefix(r"\\") {
+            PathBuf::from(format!(r"\\?\UNC\{unc}"))
+        } else {
+            PathBuf::from(format!(r"\\?\{s}"))
+        }
+    }
+    #[cfg(not(windows))]
+    path
+}
+
+/// Paths left out for their length.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct LongPaths {
+    pub count: u64,
+    pub examples: Vec<String>,
+}
+
+impl LongPaths {
+    pub fn record(&mut self, path: &Path) {
+        self.count += 1;
+        if self.examples.len() < EXAMPLES {
+            self.examples.push(dutopia::util::strip_verbatim_prefix(path).display().to_string());
+        }
+    }
+
+    pub fn merge(&mut self, other: LongPaths) {
+        self.count += other.count;
+        let room = EXAMPLES.saturating_sub(self.examples.len());
+        self.examples.extend(other.examples.into_iter().take(room));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_and_counts_long_paths() {
+        assert!(!too_long(Path::new("/a/b")));
+        #[cfg(unix)]
+        {
+            let deep = PathBuf::from(format!("/{}", "d/".repeat(2048)));
+            assert!(too_long(&deep));
+            assert!(is_too_long(&io::Error::from_raw_os_error(libc::ENAMETOOLONG)));
+            assert!(!is_too_long(&io::Error::from_raw_os_error(libc::ENOENT)));
+            // A real lstat of it fails the same way.
+            assert!(is_too_long(&std::fs::symlink_metadata(&deep).unwrap_err()));
+            assert_eq!(verbatim(deep.clone()), deep);
+        }
+
+        let mut a = LongPaths::default();
+        for i in 0..4 {
+            a.record(Path::new(&format!("/x/{i}")));
+        }
+        let mut b = LongPaths::default();
+        b.record(Path::new("/y/0"));
+        b.record(Path::new("/y/1"));
+        a.merge(b);
+        assert_eq!(a.count, 6);
+        assert_eq!(a.examples, ["/x/0", "/x/1", "/x/2", "/x/3", "/y/0"]);
+        assert!(LongPaths::default().is_empty());
+    }
+}
@@ -1,29 +1,69 @@
 // rs/src/bin/duscan/main.rs
 use anyhow::{Context, Result};
 use std::fs::{self, File};
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
 use std::sync::Arc;
-use std::thread::{self, JoinHandle};
-use std::time::{Duration, Instant};
+use std::thread;
+use std::time::Instant;
 
 use chrono::Local;
 use clap::{ColorChoice, Parser};
 use colored::Colorize;
 use crossbeam::channel::unbounded;
 
+use dutopia::enrich::Enrichers;
+use dutopia::manifest::ScanManifest;
+use dutopia::util::exit::{self, ExitArgs, Outcome};
+use dutopia::util::logging::{init_cli_tracing, level_for_verbosity, LogArgs};
+use dutopia::util::progress::{Counter, ProgressArgs, Reporter, Unit};
 use dutopia::util::{
     format_duration, get_hostname, human_bytes, human_count, parse_file_hint, print_about,
-    progress_bar, strip_verbatim_prefix,
+    strip_verbatim_prefix, PathFilter,
 };
 
+mod alias;
+mod batch;
+mod changes;
 mod csv;
+mod effective;
+mod hint;
+mod jsonl;
+mod listing;
+mod longpath;
 mod merge;
+mod mmap;
+mod notify;
+mod overlap;
+mod owner;
+mod parquet;
+mod redact;
+mod report;
+mod sample;
+mod scale;
+mod security;
+mod rotate;
 mod row;
+mod smb;
+#[cfg(target_os = "linux")]
+mod snapshot;
+mod sort;
+mod space;
+mod split;
+mod types;
+mod volumes;
 mod worker;
 
-use merge::{merge_shards, OutputFormat};
-use worker::{worker, Config, Progress, Stats, Task};
+use alias::{apply_aliases, parse_alias, Alias};
+use merge::{merge_shards, remove_shards, MergeOptions, OutputFormat};
+use overlap::RootIds;
+use security::SecurityColumns;
+use space::SpaceGuard;
+use worker::{worker, Config, Stats, Task};
+
+/// Extensions listed in the console summary; `--report` has all of them.
+const TOP_EXTENSIONS: usize = 10;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -33,48 +73,308 @@ use worker::{worker, Config, Progress, Stats, Task};
     about = "Scan filesystem and gather file metadata into CSV or binary output"
 )]
 struct Args {
-    /// Folders to scan (required, one or more)
+    /// Folders to scan (required, one or more, unless --all-volumes)
     folders: Vec<String>,
-    /// Output path (default: folder.csv or folder.zst if --bin)
+    /// Output path (default: folder.csv, folder.zst with --bin, else
+    /// folder.parquet or folder.jsonl as --format says)
     #[arg(short, long, value_name = "PATH")]
     output: Option<PathBuf>,
+    /// Output path with strftime fields for the local start time, e.g.
+    /// /data/scan_%Y%m%d.zst
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "output")]
+    output_template: Option<String>,
+    /// After a successful scan, delete all but the N newest outputs matching
+    /// --output-template (and their sidecars)
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "output_template",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    keep: Option<usize>,
     /// Number of worker (default: 2xCPU, capped to 48)
     #[arg(short, long, value_name = "N")]
     workers: Option<usize>,
+    /// Adaptive pool: the fewest workers kept taking tasks (default: 4, or
+    /// --max-workers when lower)
+    #[arg(long, value_name = "N", conflicts_with = "workers", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    min_workers: Option<usize>,
+    /// Adaptive pool: the most workers started as the queue grows (default:
+    /// 2xCPU, capped to 48)
+    #[arg(long, value_name = "N", conflicts_with = "workers", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    max_workers: Option<usize>,
     /// Skip any folder whose full path contains this substring
     #[arg(short, long, value_name = "SUBSTR")]
     skip: Option<String>,
+    /// Do not walk or emit paths matching this glob, e.g. '**/.git' or
+    /// '*.tmp'; a matching directory is skipped with everything below it
+    /// (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Keep paths matching this glob even when an --exclude matches them,
+    /// like !PATTERN in .gitignore (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+    /// Rewrite emitted path prefix FROM to TO, e.g. /mnt/nfs/proj=/projects (repeatable)
+    #[arg(long, value_name = "FROM=TO", value_parser = parse_alias)]
+    alias: Vec<Alias>,
+    /// Emit paths relative to the root (`.`, `./a/b`) and record the root in
+    /// the manifest, so scans of one tree mounted in different places match
+    #[arg(long, conflicts_with = "alias")]
+    relative: bool,
+    /// Scan a read-only ZFS/Btrfs/LVM snapshot of each root's filesystem
+    /// (Linux, needs root); removed when the scan ends
+    #[arg(long)]
+    snapshot: bool,
+    /// Connect to \\server\share roots as this user (Windows)
+    #[arg(long, value_name = "USER")]
+    smb_user: Option<String>,
+    /// Environment variable holding the --smb-user password
+    #[arg(long, value_name = "VAR", default_value = "SMB_PASSWORD", requires = "smb_user")]
+    smb_pass_env: String,
+    /// Also scan every fixed local volume, including ones mounted in a folder
+    /// (Windows)
+    #[arg(long)]
+    all_volumes: bool,
+    /// With --all-volumes, include removable and optical drives
+    #[arg(long, requires = "all_volumes")]
+    include_removable: bool,
+    /// With --all-volumes, include mapped network drives
+    #[arg(long, requires = "all_volumes")]
+    include_network: bool,
+    /// Add per-file CSV columns: regex:NAME=PATTERN (first capture group on
+    /// the path) or lib:PATH (shared library plugin); repeatable
+    #[arg(long, value_name = "SPEC")]
+    enrich: Vec<String>,
+    /// Emit only these entry types: f (file), d (dir), l (symlink), s (socket),
+    /// p (fifo), b (block device), c (char device); directories are still walked
+    #[arg(long, value_name = "LIST", value_parser = types::parse_types)]
+    types: Option<types::EntryTypes>,
+    /// Do not enter directories on these filesystem types, e.g.
+    /// proc,sysfs,tmpfs,overlay,nfs
+    #[arg(long, value_name = "LIST", value_delimiter = ',')]
+    exclude_fstype: Vec<String>,
+    /// Stay on the filesystems of the roots, like du -x: NFS, bind and
+    /// other mounts below a root are not entered (Unix)
+    #[arg(short = 'x', long)]
+    one_file_system: bool,
+    /// Emit only rows owned by these user ids, e.g. 1000,1001 (Unix);
+    /// directories are still walked
+    #[arg(long, value_name = "LIST", value_delimiter = ',')]
+    uid: Vec<u32>,
+    /// Emit only rows whose group is one of these ids (Unix); with --uid a
+    /// row must match both
+    #[arg(long, value_name = "LIST", value_delimiter = ',')]
+    gid: Vec<u32>,
+    /// Write file names as stable hashes, keeping the extension and the
+    /// directory path
+    #[arg(long)]
+    redact_names: bool,
+    /// Add a `capabilities` CSV column with each file's capabilities
+    /// (security.capability, as getcap prints them; Linux)
+    #[arg(long)]
+    capabilities: bool,
+    /// Add a `selinux` CSV column with each entry's SELinux context (Linux)
+    #[arg(long)]
+    selinux: bool,
+    /// Add an EFFECTIVE_OWNER CSV column attributing each entry by RULE
+    /// instead of its uid: parent (the directory's owner), acl-user[:PREFIX]
+    /// or acl-group[:PREFIX] (first named ACL entry; Linux)
+    #[arg(long, value_name = "RULE", value_parser = effective::parse_rule)]
+    effective_owner: Option<effective::OwnerRule>,
+    /// Salt for --redact-names hashes; keep it secret and the same across
+    /// scans that should compare
+    #[arg(long, value_name = "TEXT", env = "DUSCAN_REDACT_SALT", hide_env_values = true, requires = "redact_names")]
+    redact_salt: Option<String>,
+    /// Walk only this share of the subdirectories (e.g. 5% or 0.05) and
+    /// estimate the full totals
+    #[arg(long, value_name = "RATE", value_parser = sample::parse_rate)]
+    sample: Option<f64>,
+    /// With --sample, sample the directories this many levels below each
+    /// root
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        requires = "sample",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    sample_depth: usize,
+    /// Also write each owner's rows to <output>.users/<UID>.csv (CSV only)
+    #[arg(long)]
+    split_by_user: bool,
     /// Write a binary .zst compressed file instead of .csv
     #[arg(short, long)]
     bin: bool,
+    /// Output format: csv, bin (as --bin), parquet (Apache Parquet, for
+    /// Spark and DuckDB) or jsonl (one JSON object per line)
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with = "bin")]
+    format: Option<OutputFormat>,
     /// Zero the ATIME field in outputs (CSV & BIN) for testing
     #[arg(long = "no-atime")]
     no_atime: bool,
+    /// Read directories with O_NOATIME so the scan leaves their atimes as
+    /// they were (Linux; complete only as root)
+    #[arg(long)]
+    preserve_atime: bool,
+    /// Directory for temporary shard files (default: output directory)
+    #[arg(long = "temp-dir", value_name = "DIR")]
+    temp_dir: Option<PathBuf>,
+    /// zstd-compress CSV shard files while scanning (decompressed at merge)
+    #[arg(long = "compress-shards")]
+    compress_shards: bool,
+    /// Pause the workers while free space in the temp or output directory
+    /// is below SIZE (e.g. 500MB, 2GB; default 0, no check)
+    #[arg(long, value_name = "SIZE", default_value = "0", value_parser = dutopia::util::parse_bytes)]
+    min_free: u64,
+    /// Abort the scan, removing its shards, when space has not come back
+    /// after DURATION of pause (e.g. 30s, 10m; 0 aborts at once)
+    #[arg(long, value_name = "DURATION", default_value = "10m", value_parser = dutopia::util::parse_duration)]
+    min_free_wait: std::time::Duration,
+    /// Stop entering directories after DURATION (e.g. 2h): what was walked
+    /// is written, the manifest counts the directories left and duscan
+    /// exits 3
+    #[arg(long, value_name = "DURATION", value_parser = dutopia::util::parse_duration)]
+    time_limit: Option<std::time::Duration>,
+    /// Walk at most N levels below each root, for quick surveys: entries of
+    /// directories at depth N are listed, their subdirectories not entered
+    /// (0 lists the roots alone)
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+    /// Merge shards through memory maps with large writes (Unix; plain
+    /// shards only)
+    #[arg(long)]
+    mmap_merge: bool,
     /// Total files hint (e.g. 750m, 1.2b). Used for % progress
     #[arg(long = "files-hint", value_name = "N")]
     files_hint: Option<String>,
+    /// Previous output of this scan (CSV or .zst); its row count is the
+    /// files hint. Defaults to the output file when it already exists
+    #[arg(long, value_name = "FILE", conflicts_with = "files_hint")]
+    previous: Option<PathBuf>,
+    /// Earlier output of the same roots (CSV or .zst); writes the
+    /// directories added, removed or modified since it to
+    /// <output>.changes.csv
+    #[arg(long, value_name = "SCAN", conflicts_with = "sample")]
+    since: Option<PathBuf>,
+    /// Write a JSON run report (totals, per-extension and data-age counters) to FILE
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+    /// POST the run summary JSON to URL when the scan ends or fails
+    #[arg(long, value_name = "URL", env = "DUSCAN_NOTIFY_WEBHOOK")]
+    notify_webhook: Option<String>,
+    /// Email the run summary to ADDR when the scan ends or fails (SMTP_* env
+    /// vars); repeatable
+    #[arg(long, value_name = "ADDR")]
+    notify_email: Vec<String>,
     /// Do not report progress
     #[arg(short, long)]
     quiet: bool,
+    #[command(flatten)]
+    progress: ProgressArgs,
     /// Verbose output: print errors (-v) or errors and paths (-vv)
     #[arg(short, long, action = clap::ArgAction::Count)]
     verbose: u8,
+    #[command(flatten)]
+    log: LogArgs,
+    #[command(flatten)]
+    exit: ExitArgs,
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     print_about();
 
     let args = Args::parse();
+    let exit = args.exit.clone();
+    exit::finish("duscan", &exit, scan(args))
+}
+
+/// Runs the scan and notifies about it; returns how it went.
+fn scan(mut args: Args) -> Result<Outcome> {
+    init_cli_tracing("duscan", &args.log, level_for_verbosity(args.verbose))?;
 
+    if args.all_volumes {
+        let filter = volumes::VolumeFilter {
+            removable: args.include_removable,
+            network: args.include_network,
+        };
+        let found = volumes::all_volumes(filter)?;
+        if found.is_empty() {
+            anyhow::bail!("--all-volumes found no volume to scan");
+        }
+        args.folders.extend(found);
+    }
     if args.folders.is_empty() {
         anyhow::bail!("At least one folder must be specified");
     }
 
+    let notifier = notify::Notifier::new(args.notify_webhook.as_deref(), &args.notify_email)?;
+    if notifier.is_empty() {
+        return run(args).map(|s| s.outcome()).inspect_err(log_failure);
+    }
+    let folders = args.folders.clone();
+    let start_time = Instant::now();
+    match run(args) {
+        Ok(summary) => {
+            notifier.send(&summary);
+            Ok(summary.outcome())
+        }
+        Err(e) => {
+            log_failure(&e);
+            let elapsed = start_time.elapsed().as_secs_f64();
+            notifier.send(&report::RunReport::failed(&folders, &e, elapsed));
+            Err(e)
+        }
+    }
+}
+
+fn log_failure(e: &anyhow::Error) {
+    tracing::error!(error = format!("{e:#}"), "scan failed");
+}
+
+/// The whole scan; returns the run summary.
+fn run(args: Args) -> Result<report::RunReport> {
+
     let out_fmt = if args.bin {
         OutputFormat::Bin
     } else {
-        OutputFormat::Csv
+        args.format.unwrap_or_default()
     };
+    let csv = out_fmt == OutputFormat::Csv;
+
+    let enrich = Enrichers::parse(&args.enrich)?;
+    if !enrich.is_empty() && !csv {
+        anyhow::bail!("--enrich adds CSV columns and needs CSV output");
+    }
+    let enrich = (!enrich.is_empty()).then(|| Arc::new(enrich));
+    let security = SecurityColumns::new(args.capabilities, args.selinux).map(Arc::new);
+    if security.is_some() {
+        if !cfg!(target_os = "linux") {
+            anyhow::bail!("--capabilities and --selinux are only supported on Linux");
+        }
+        if !csv {
+            anyhow::bail!("--capabilities and --selinux add CSV columns and need CSV output");
+        }
+    }
+    let effective = args.effective_owner.clone().map(Arc::new);
+    if let Some(rule) = &effective {
+        if rule.is_acl() && !cfg!(target_os = "linux") {
+            anyhow::bail!("--effective-owner acl-user / acl-group are only supported on Linux");
+        }
+        if !csv {
+            anyhow::bail!("--effective-owner adds a CSV column and needs CSV output");
+        }
+    }
+    let mut extra_columns: Vec<String> = enrich
+        .iter()
+        .flat_map(|e| e.columns())
+        .chain(security.iter().flat_map(|s| s.columns()))
+        .cloned()
+        .chain(effective.iter().map(|_| effective::COLUMN.to_string()))
+        .collect();
+    if args.split_by_user && !csv {
+        anyhow::bail!("--split-by-user only splits CSV output");
+    }
 
     if args.no_atime {
         eprintln!(
@@ -82,14 +382,44 @@ fn main() -> Result<()> {
             "ATIME will be written as 0 and lines sorted for reproducible output.".yellow()
         );
     }
+    if args.preserve_atime && !cfg!(target_os = "linux") {
+        eprintln!(
+            "{}",
+            "--preserve-atime needs O_NOATIME (Linux); directories are read as usual.".yellow()
+        );
+    }
+
+    // Shares needing credentials must be connected before they can be
+    // canonicalized. Connections close when main returns.
+    #[cfg(windows)]
+    let _smb_connections = match &args.smb_user {
+        Some(user) => {
+            let password = std::env::var(&args.smb_pass_env).with_context(|| {
+                format!("--smb-user needs the password in ${}", args.smb_pass_env)
+            })?;
+            let folders: Vec<PathBuf> = args.folders.iter().map(PathBuf::from).collect();
+            smb::connect_shares(&folders, user, &password)?
+        }
+        None => Vec::new(),
+    };
+    #[cfg(not(windows))]
+    if args.smb_user.is_some() {
+        anyhow::bail!("--smb-user is only supported on Windows");
+    }
 
     // Canonicalize all root folders
-    let mut roots = Vec::new();
+    let mut canonical = Vec::new();
     for folder in &args.folders {
         let root = fs::canonicalize(folder)
             .with_context(|| format!("Failed to canonicalize folder: {}", folder))?;
-        roots.push(root);
+        canonical.push(longpath::verbatim(root));
     }
+    #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+    let (mut roots, mut overlaps) = overlap::prune_roots(canonical, overlap::dir_id);
+    let root_names: Vec<String> = roots
+        .iter()
+        .map(|r| strip_verbatim_prefix(r).display().to_string())
+        .collect();
 
     // Create a combined name for default output
     let combined_name = if roots.len() == 1 {
@@ -112,8 +442,15 @@ fn main() -> Result<()> {
         format!("stats_{}", roots.len())
     };
 
+    let template = args
+        .output_template
+        .as_deref()
+        .map(rotate::Template::parse)
+        .transpose()?;
+    let now = Local::now();
+
     // Decide default output by out_fmt
-    let final_path: PathBuf = match args.output {
+    let final_path: PathBuf = match args.output.or_else(|| template.as_ref().map(|t| t.render(&now))) {
         Some(p) => {
             if p.is_absolute() {
                 p
@@ -122,11 +459,7 @@ fn main() -> Result<()> {
             }
         }
         None => {
-            let ext = match out_fmt {
-                OutputFormat::Csv => "csv",
-                OutputFormat::Bin => "zst",
-            };
-            std::env::current_dir()?.join(format!("{combined_name}.{ext}"))
+            std::env::current_dir()?.join(format!("{combined_name}.{}", out_fmt.ext()))
         }
     };
 
@@ -136,31 +469,42 @@ fn main() -> Result<()> {
         .map(|p| p.to_path_buf())
         .unwrap_or(std::env::current_dir()?);
 
-    if !out_dir.exists() {
-        anyhow::bail!("Output directory does not exist: {}", out_dir.display());
-    }
-
-    if !out_dir.is_dir() {
-        anyhow::bail!("Output path is not a directory: {}", out_dir.display());
-    }
+    ensure_writable_dir(&out_dir, "Output")?;
 
-    // Check write access by trying to create a temp file
-    let testfile = out_dir.join(".dutopia_write_test");
-    File::create(&testfile)
-        .with_context(|| format!("No write access to directory {}", out_dir.display()))?;
-    let _ = fs::remove_file(&testfile);
+    // Shards may live elsewhere (e.g. local NVMe) than the final output
+    let shard_dir: PathBuf = match &args.temp_dir {
+        Some(dir) => {
+            ensure_writable_dir(dir, "Temp")?;
+            fs::canonicalize(dir)
+                .with_context(|| format!("Failed to canonicalize temp dir: {}", dir.display()))?
+        }
+        None => out_dir.clone(),
+    };
 
-    let workers = args
-        .workers
-        .unwrap_or_else(|| (num_cpus::get() * 2).max(4).min(48));
+    let default_workers = (num_cpus::get() * 2).clamp(4, 48);
+    // Adaptive: all max workers are started (one shard each), min of them active.
+    let scale = match (args.min_workers, args.max_workers) {
+        (None, None) => None,
+        (min, max) => {
+            let max = max.unwrap_or_else(|| default_workers.max(min.unwrap_or(1)));
+            let min = min.unwrap_or(4.min(max));
+            if min > max {
+                anyhow::bail!("--min-workers {min} is above --max-workers {max}");
+            }
+            Some(Arc::new(scale::Scaler::new(min, max)))
+        }
+    };
+    let workers = match &scale {
+        Some(s) => s.max(),
+        None => args.workers.unwrap_or(default_workers),
+    };
     let cmd: Vec<String> = std::env::args().collect();
-    let now = Local::now();
     let hostname = get_hostname();
     let pid = std::process::id();
 
     println!(
         "Local time   : {}",
-        now.format("%Y-%m-%d %H:%M:%S").to_string()
+        now.format("%Y-%m-%d %H:%M:%S")
     );
     println!("Host         : {}", hostname);
     println!("Process ID   : {}", pid);
@@ -170,11 +514,162 @@ fn main() -> Result<()> {
         let root_normalized = strip_verbatim_prefix(root);
         println!("Input {}      : {}", i + 1, root_normalized.display());
     }
+    for o in &overlaps {
+        println!("{}", format!("Overlap      : {}", o.describe()).yellow());
+    }
+
+    let relative_to = if args.relative {
+        if roots.len() > 1 {
+            anyhow::bail!("--relative takes one root; the paths of several would collide");
+        }
+        println!("Relative to  : {}", root_names[0]);
+        Some(root_names[0].clone())
+    } else {
+        None
+    };
+    let aliases: Vec<Alias> = if args.relative {
+        vec![Alias::relative(&roots[0])]
+    } else {
+        args.alias.into_iter().map(Alias::canonicalized).collect()
+    };
+    for a in &aliases {
+        println!(
+            "Alias        : {} -> {}",
+            strip_verbatim_prefix(&a.from).display(),
+            a.to.display()
+        );
+    }
+
+    // Snapshots live until the end of main so they outlast the workers.
+    #[cfg(target_os = "linux")]
+    let mut _snapshots = Vec::new();
+    #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+    let mut snapshot_aliases: Vec<Alias> = Vec::new();
+    if args.snapshot {
+        #[cfg(target_os = "linux")]
+        {
+            let (snaps, redirected) = snapshot::snapshot_roots(&roots, pid)?;
+            for s in &snaps {
+                println!("Snapshot     : {}", s.describe());
+                snapshot_aliases.push(s.alias());
+            }
+            roots = redirected;
+            _snapshots = snaps;
+        }
+        #[cfg(not(target_os = "linux"))]
+        anyhow::bail!("--snapshot is only supported on Linux");
+    }
+
+    let root_ids = RootIds::new(&roots).map(Arc::new);
+
+    let devices = if args.one_file_system {
+        if cfg!(not(unix)) {
+            anyhow::bail!("--one-file-system needs Unix device ids");
+        }
+        let mut devs = Vec::with_capacity(roots.len());
+        for r in &roots {
+            let (dev, _) = overlap::dir_id(r)
+                .with_context(|| format!("reading the device of {}", r.display()))?;
+            if !devs.contains(&dev) {
+                devs.push(dev);
+            }
+        }
+        println!("One fs       : stay on the roots' filesystems");
+        Some(Arc::new(devs))
+    } else {
+        None
+    };
+
+    let dfs = (cfg!(windows) && roots.iter().any(|r| smb::unc_share(r).is_some()))
+        .then(|| Arc::new(smb::DfsMap::default()));
+    if dfs.is_some() && csv {
+        extra_columns.push(smb::COLUMN.to_string());
+    }
+    if let Some(user) = &args.smb_user {
+        println!("SMB user     : {}", user);
+    }
+
+    if let Some(e) = &enrich {
+        println!("Enrich       : {}", e.columns().join(", "));
+    }
+    if let Some(s) = &security {
+        println!("Security     : {}", s.columns().join(", "));
+    }
+    if let Some(rule) = &args.effective_owner {
+        println!("Owner rule   : {rule}");
+    }
+    let filter = PathFilter::new(args.skip.as_deref(), &args.exclude)?.with_include(&args.include)?;
+    for g in &args.exclude {
+        println!("Exclude      : {g}");
+    }
+    for g in &args.include {
+        println!("Include      : {g}");
+    }
+    let types = args.types.unwrap_or_default();
+    if !types.is_all() {
+        println!("Types        : {}", types.letters());
+    }
+    let exclude_fstypes: Vec<String> = args
+        .exclude_fstype
+        .iter()
+        .map(|t| t.trim().to_ascii_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    if !exclude_fstypes.is_empty() {
+        println!("Skip fstypes : {}", exclude_fstypes.join(","));
+    }
+    let owners = owner::OwnerFilter::new(&args.uid, &args.gid);
+    if let Some(o) = &owners {
+        if cfg!(windows) {
+            anyhow::bail!("--uid and --gid need Unix owners; Windows rows have none");
+        }
+        println!("Owners       : {}", o.describe());
+    }
+    let changes = match &args.since {
+        Some(scan) => {
+            let c = changes::Changes::load(scan)?;
+            println!("Since        : {} ({} directories)", scan.display(), c.previous_dirs());
+            Some(Arc::new(c))
+        }
+        None => None,
+    };
+    let redact = args.redact_names.then(|| {
+        println!(
+            "Redact names : yes{}",
+            if args.redact_salt.is_some() { " (salted)" } else { "" }
+        );
+        Arc::new(redact::Redactor::new(args.redact_salt.as_deref().unwrap_or_default()))
+    });
+    let sample = args.sample.map(|rate| {
+        println!(
+            "Sample       : {}% of the dirs {} level(s) below each root",
+            (rate * 1e4).round() / 100.0,
+            args.sample_depth
+        );
+        Arc::new(sample::Sampler::new(rate, args.sample_depth, &roots))
+    });
 
     println!("Output       : {}", &final_path.display());
-    println!("Temp dir     : {}", out_dir.display());
-    println!("Workers      : {}", workers);
+    println!("Temp dir     : {}", shard_dir.display());
+    match &scale {
+        Some(s) => println!("Workers      : {}-{} (adaptive)", s.active(), workers),
+        None => println!("Workers      : {}", workers),
+    }
+    let space = SpaceGuard::new(&[&shard_dir, &out_dir], args.min_free, args.min_free_wait).map(Arc::new);
+    if space.is_some() {
+        println!(
+            "Min free     : {} (abort after {})",
+            human_bytes(args.min_free),
+            format_duration(args.min_free_wait)
+        );
+    }
 
+    if let Some(t) = args.time_limit {
+        println!("Time limit   : {}", format_duration(t));
+    }
+    if let Some(n) = args.max_depth {
+        println!("Max depth    : {n}");
+    }
     if args.verbose > 0 {
         println!("Verbose      : Level {}", args.verbose);
     }
@@ -183,77 +678,54 @@ fn main() -> Result<()> {
     let (tx, rx) = unbounded::<Task>();
     let inflight = Arc::new(AtomicUsize::new(0));
 
-    let progress = Arc::new(Progress::default());
-    let reporting_done = Arc::new(AtomicBool::new(false));
-    let mut reporter_join: Option<JoinHandle<()>> = None;
-
-    if !args.quiet {
-        let hinted_files = args
-            .files_hint
-            .as_deref()
-            .and_then(|s| parse_file_hint(s));
-
-        if let Some(total_files) = hinted_files {
+    let progress = Arc::new(Counter::default());
+    progress.watch_queue(inflight.clone());
+    let previous = match (args.previous.clone(), &template) {
+        (Some(p), _) => Some(p),
+        _ if final_path.is_file() => Some(final_path.clone()),
+        // The newest earlier run of the template.
+        (None, Some(t)) => t.outputs(&out_dir).ok().and_then(|v| v.into_iter().next()),
+        (None, None) => None,
+    };
+    match (args.files_hint.as_deref().and_then(parse_file_hint), previous) {
+        (Some(total_files), _) => {
             println!(
                 "Files hint   : {} (from --files-hint)",
                 human_count(total_files)
             );
+            progress.set_total(total_files);
         }
-
-        let progress_for_reporter = progress.clone();
-        let reporting_done = reporting_done.clone();
-        let start_for_reporter = Instant::now();
-
-        reporter_join = Some(thread::spawn(move || {
-            let mut last_pct = 0.0;
-            loop {
-                if reporting_done.load(Relaxed) {
-                    break;
-                }
-                let f = progress_for_reporter.files.load(Relaxed);
-                let elapsed = start_for_reporter.elapsed().as_secs_f64().max(0.001);
-                let rate_f = human_count((f as f64 / elapsed) as u64);
-
-                if let Some(total) = hinted_files {
-                    let mut pct = ((f as f64 / total as f64) * 100.0).min(100.0);
-                    if pct < last_pct {
-                        pct = last_pct;
-                    }
-                    last_pct = pct;
-                    let bar = progress_bar(pct.into(), 25);
-                    eprint!(
-                        "\r    {} {} {:>3}% | {} files [{} f/s]        \r",
-                        "Progress".bright_cyan(),
-                        bar,
-                        pct as u32,
-                        human_count(f),
-                        rate_f
-                    );
-                } else {
-                    eprint!(
-                        "\r    {} : {} files [{} f/s]        \r",
-                        "Progress".bright_cyan(),
-                        human_count(f),
-                        rate_f
-                    );
-                }
-                thread::sleep(Duration::from_millis(1000));
-            }
-            eprint!("\r{}", " ".repeat(120));
-        }));
+        (None, Some(previous)) if !args.quiet || args.progress.progress_url.is_some() => {
+            println!("Files hint   : rows of {}", previous.display());
+            hint::count_in_background(previous, progress.clone());
+        }
+        _ => {}
     }
+    let sinks = args.progress.sinks(args.quiet)?;
+    let counting = !sinks.is_empty();
+    let reporter = Reporter::start("duscan", Unit::Files, progress.clone(), sinks);
 
     let start_time = Instant::now();
+    tracing::info!(roots = ?root_names, workers, output = %final_path.display(), "scan started");
 
     // seed all root folders
     for root in roots {
         inflight.fetch_add(1, Relaxed);
-        tx.send(Task::Dir(root)).expect("enqueue root");
+        tx.send(Task::Dir(root, 0)).expect("enqueue root");
+    }
+
+    if let Some(space) = &space {
+        space.start(tx.clone(), workers);
+    }
+
+    if let Some(scale) = &scale {
+        scale.start(rx.clone());
     }
 
     // shutdown detection with stronger memory ordering and double-check
     {
         let tx = tx.clone();
+        let scale = scale.clone();
         let inflight = inflight.clone();
         thread::spawn(move || {
             let mut consecutive_zeros = 0;
@@ -262,6 +734,9 @@ fn main() -> Result<()> {
                 if current_inflight == 0 {
                     consecutive_zeros += 1;
                     if consecutive_zeros >= 5 {
+                        if let Some(scale) = &scale {
+                            scale.release();
+                        }
                         for _ in 0..workers {
                             let _ = tx.send(Task::Shutdown);
                         }
@@ -276,12 +751,33 @@ fn main() -> Result<()> {
     }
 
     let cfg = Config {
-        skip: args.skip,
+        filter,
         out_fmt,
         no_atime: args.no_atime,
-        progress: (!args.quiet).then(|| progress.clone()),
+        progress: counting.then(|| progress.clone()),
         pid,
         verbose: args.verbose,
+        compress_shards: args.compress_shards,
+        aliases,
+        snapshots: snapshot_aliases,
+        dfs: dfs.clone(),
+        enrich: enrich.clone(),
+        security,
+        effective,
+        changes: changes.clone(),
+        started_at: now.timestamp(),
+        space: space.clone(),
+        scale: scale.clone(),
+        preserve_atime: args.preserve_atime,
+        deadline: args.time_limit.map(|t| start_time + t),
+        max_depth: args.max_depth,
+        types,
+        exclude_fstypes,
+        devices,
+        owners,
+        root_ids,
+        redact,
+        sample: sample.clone(),
     };
 
     // ---- spawn workers ----
@@ -290,51 +786,242 @@ fn main() -> Result<()> {
         let rx = rx.clone();
         let tx = tx.clone();
         let inflight = inflight.clone();
-        let out_dir = out_dir.clone();
+        let shard_dir = shard_dir.clone();
         let cfg = cfg.clone();
         joins.push(thread::spawn(move || {
-            worker(tid, rx, tx, inflight, out_dir, cfg)
+            worker(tid, rx, tx, inflight, shard_dir, cfg)
         }));
     }
     drop(tx);
 
     // ---- gather stats ----
     let mut total = Stats::default();
-    for j in joins {
+    let mut worker_lines = Vec::new();
+    for (tid, j) in joins.into_iter().enumerate() {
         match j.join() {
             Ok(s) => {
-                total.files += s.files;
-                total.errors += s.errors;
-                total.bytes += s.bytes;
+                if args.verbose > 0 {
+                    worker_lines.push(report::WorkerLine {
+                        tid,
+                        dirs: s.dirs,
+                        files: s.files,
+                        bytes: s.bytes,
+                        errors: s.errors,
+                        write_time: s.write_time,
+                        stat_latency: s.stat_latency.clone(),
+                    });
+                }
+                total.merge(s)
             }
             Err(_) => {
-                eprintln!("{}", "Error: a worker thread panicked".red());
+                tracing::error!(tid, "worker thread panicked");
                 total.errors += 1;
             }
         }
     }
+    stop_space_guard(space.as_deref(), &shard_dir, workers, pid)?;
+
     // measure speed before merging
     let elapsed = start_time.elapsed().as_secs_f64().max(0.001);
     let speed = ((total.files as f64) / elapsed) as u32;
+    let estimate = sample
+        .as_ref()
+        .map(|s| s.estimate(total.files, total.bytes, &total.clusters));
 
     // ---- merge shards ----
-    let sort_csv = args.no_atime && matches!(out_fmt, OutputFormat::Csv);
-    merge_shards(&out_dir, &final_path, workers, out_fmt, sort_csv, pid)?;
+    // The manifest says "incomplete" until the merged output is whole.
+    let mut manifest = ScanManifest {
+        format: out_fmt.ext().to_string(),
+        rows: total.files,
+        complete: false,
+        errors: total.errors,
+        unvisited: 0,
+        max_depth: args.max_depth,
+        roots: root_names.clone(),
+        relative_to,
+        host: hostname.clone(),
+        started_at: now.timestamp(),
+        finished_at: 0,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        sample: sample.as_ref().map(|s| {
+            s.info(|dir| {
+                let live = apply_aliases(&cfg.snapshots, dir);
+                strip_verbatim_prefix(&apply_aliases(&cfg.aliases, &live)).display().to_string()
+            })
+        }),
+    };
+    manifest.write(&final_path)?;
+    let sort_csv = args.no_atime && matches!(out_fmt, OutputFormat::Csv | OutputFormat::Jsonl);
+    let merge_start = Instant::now();
+    let merge_opts = MergeOptions {
+        threads: workers,
+        pid,
+        format: out_fmt,
+        sort_csv,
+        compressed: args.compress_shards,
+        extra_columns: &extra_columns,
+        mmap: args.mmap_merge,
+    };
+    merge_shards(&shard_dir, &final_path, &merge_opts)?;
+    tracing::info!(
+        elapsed_secs = merge_start.elapsed().as_secs_f64(),
+        mmap = args.mmap_merge,
+        "shards merged"
+    );
+    let timed_out = total.unvisited > 0;
+    manifest.complete = true;
+    manifest.unvisited = total.unvisited;
+    manifest.finished_at = Local::now().timestamp();
+    let manifest_path = manifest.write(&final_path)?;
 
-    if let Some(h) = reporter_join.take() {
-        reporting_done.store(true, Relaxed);
-        let _ = h.join();
+    if let Some(dfs) = dfs.filter(|d| d.len() > 0) {
+        let side = smb::dfs_sidecar_path(&final_path);
+        dfs.write_csv(&side)?;
+        println!("\rDFS links    : {} ({})", dfs.len(), side.display());
+    }
+    if let Some(c) = &changes {
+        let found = c.finish();
+        let side = changes::changes_path(&final_path);
+        changes::write_changes(&side, &found)?;
+        let count = |k| found.iter().filter(|c| c.kind == k).count();
+        println!(
+            "\rChanges      : {} added, {} removed, {} modified ({})",
+            count(changes::Kind::Added),
+            count(changes::Kind::Removed),
+            count(changes::Kind::Modified),
+            side.display()
+        );
+        if timed_out {
+            println!("{}", "Changes      : directories not walked are listed as removed".yellow());
+        }
+    }
+    if args.split_by_user {
+        let users = split::split_by_user(&final_path)?;
+        println!(
+            "\rSplit users  : {} ({})",
+            users.len(),
+            split::split_dir(&final_path).display()
+        );
     }
 
+    reporter.finish();
+
     let elapsed_str = format_duration(start_time.elapsed());
 
-    println!("\rTotal files  : {}", total.files);
+    tracing::info!(
+        files = total.files,
+        dirs = total.dirs,
+        long_paths = total.long_paths.count,
+        unvisited = total.unvisited,
+        errors = total.errors,
+        bytes = total.bytes,
+        elapsed_secs = start_time.elapsed().as_secs_f64(),
+        "scan finished"
+    );
+    println!("\rTotal rows   : {}", total.files);
+    println!("Dirs walked  : {}", total.dirs);
     println!("Total errors : {}", total.errors);
+    if timed_out {
+        let msg = format!("Time limit   : reached, {} directories not walked", total.unvisited);
+        println!("{}", msg.yellow());
+    }
+    if !total.long_paths.is_empty() {
+        let msg = format!(
+            "Long paths   : {} left out, over {}",
+            total.long_paths.count,
+            longpath::LIMIT
+        );
+        println!("{}", msg.yellow());
+        for p in &total.long_paths.examples {
+            println!("{}", format!("  {p}").yellow());
+        }
+    }
     println!("Total disk   : {}", human_bytes(total.bytes));
+    if let Some(e) = &estimate {
+        sample::print_estimate(e);
+    }
     println!("Elapsed time : {}", elapsed_str);
     println!("Files/s      : {:.2}", speed);
+    println!("Manifest     : {}", manifest_path.display());
+    // A partial scan does not push complete ones out of the rotation.
+    if let (Some(t), Some(keep), false) = (&template, args.keep, timed_out) {
+        for old in rotate::prune(t, &final_path, keep)? {
+            println!("Pruned       : {}", old.display());
+        }
+    }
+    for o in &total.overlaps {
+        println!("{}", format!("Overlap      : {}", o.describe()).yellow());
+    }
+    overlaps.append(&mut total.overlaps);
+    report::print_kinds(&total.kinds);
+    report::print_top_extensions(&total.exts, TOP_EXTENSIONS);
+    report::print_mtime_profile(&total.mtimes);
+    if !worker_lines.is_empty() {
+        report::print_worker_table(&worker_lines);
+    }
+    let summary = report::RunReport {
+        status: if timed_out { "partial" } else { "ok" },
+        host: hostname,
+        roots: root_names,
+        output: final_path.display().to_string(),
+        files: total.files,
+        dirs: total.dirs,
+        errors: total.errors,
+        bytes: total.bytes,
+        kinds: total.kinds,
+        long_paths: total.long_paths.clone(),
+        unvisited: total.unvisited,
+        elapsed_secs: start_time.elapsed().as_secs_f64(),
+        error: None,
+        extensions: report::ExtensionOut::from_counter(&total.exts),
+        overlaps,
+        sample: estimate,
+        mtime: Some(report::MtimeOut::from_histogram(&total.mtimes)),
+    };
+    if let Some(path) = &args.report {
+        report::write_report_json(path, &summary)?;
+        println!("Report       : {}", path.display());
+    }
     println!("{}", "-".repeat(44).bright_cyan());
     println!("Done.");
+    Ok(summary)
+}
+
+/// Fail early if `dir` is missing, not a directory, or not writable.
+fn ensure_writable_dir(dir: &Path, what: &str) -> Result<()> {
+    if !dir.exists() {
+        anyhow::bail!("{} directory does not exist: {}", what, dir.display());
+    }
+
+    if !dir.is_dir() {
+        anyhow::bail!("{} path is not a directory: {}", what, dir.display());
+    }
+
+    // Check write access by trying to create a temp file
+    let testfile = dir.join(".dutopia_write_test");
+    File::create(&testfile)
+        .with_context(|| format!("No write access to directory {}", dir.display()))?;
+    let _ = fs::remove_file(&testfile);
+    Ok(())
+}
+
+/// End the free-space monitor. A scan it aborted mid-walk is not merged:
+/// its shards are incomplete, so they are removed. A walk that finished is
+/// kept whatever the free space is now.
+fn stop_space_guard(
+    space: Option<&SpaceGuard>,
+    shard_dir: &Path,
+    workers: usize,
+    pid: u32,
+) -> Result<()> {
+    let Some(space) = space else {
+        return Ok(());
+    };
+    space.stop();
+    if let Some(reason) = space.abort_reason() {
+        remove_shards(shard_dir, workers, pid);
+        anyhow::bail!("{reason}; scan aborted and its shards removed");
+    }
     Ok(())
 }
 
@@ -347,18 +1034,92 @@ mod tests {
         let args = Args {
             folders: vec!["folder1".to_string(), "folder2".to_string()],
             output: Some("output.csv".into()),
+            output_template: None,
+            keep: None,
             workers: Some(8),
+            preserve_atime: false,
+            min_workers: None,
+            max_workers: None,
             skip: Some("skip_pattern".to_string()),
+            exclude: vec![],
+            include: vec![],
+            alias: vec![],
+            relative: false,
+            snapshot: false,
+            smb_user: None,
+            smb_pass_env: "SMB_PASSWORD".to_string(),
+            all_volumes: false,
+            include_removable: false,
+            include_network: false,
+            enrich: vec![],
+            types: None,
+            exclude_fstype: vec![],
+            one_file_system: false,
+            uid: vec![],
+            gid: vec![],
+            redact_names: false,
+            capabilities: false,
+            effective_owner: None,
+            selinux: false,
+            redact_salt: None,
+            sample: None,
+            sample_depth: 1,
+            split_by_user: false,
             bin: false,
+            format: None,
             no_atime: true,
+            temp_dir: Some("/scratch".into()),
+            compress_shards: false,
+            min_free: 0,
+            min_free_wait: std::time::Duration::from_secs(600),
+            time_limit: None,
+            max_depth: None,
+            mmap_merge: false,
             files_hint: Some("1000".to_string()),
+            previous: None,
+            since: None,
+            report: None,
+            notify_webhook: None,
+            notify_email: vec![],
             quiet: false,
+            progress: ProgressArgs::default(),
             verbose: 0,
+            log: LogArgs::default(),
+            exit: ExitArgs::default(),
         };
 
         let debug_str = format!("{:?}", args);
         assert!(debug_str.contains("folder1"));
         assert!(debug_str.contains("output.csv"));
         assert!(debug_str.contains("skip_pattern"));
+        assert!(debug_str.contains("/scratch"));
+    }
+
+    #[test]
+    fn test_ensure_writable_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert!(ensure_writable_dir(tmp.path(), "Temp").is_ok());
+
+        let missing = tmp.path().join("missing");
+        let err = ensure_writable_dir(&missing, "Temp").unwrap_err();
+        assert!(err.to_string().starts_with("Temp directory does not exist"));
+
+        let file = tmp.path().join("f");
+        File::create(&file).unwrap();
+        assert!(ensure_writable_dir(&file, "Temp").is_err());
+    }
+
+    #[test]
+    fn test_finished_scan_is_kept_when_space_is_low_at_the_end() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pid = std::process::id();
+        let shard = tmp.path().join(format!("shard_{}_{pid}_0.tmp", get_hostname()));
+        std::fs::write(&shard, b"row\n").unwrap();
+
+        let guard = SpaceGuard::new(&[tmp.path()], u64::MAX, std::time::Duration::ZERO).unwrap();
+        assert!(guard.low().is_some());
+        assert!(stop_space_guard(Some(&guard), tmp.path(), 1, pid).is_ok());
+        assert!(shard.exists());
+        assert!(stop_space_guard(None, tmp.path(), 1, pid).is_ok());
     }
 }
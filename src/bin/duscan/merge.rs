@@ -1,106 +1,181 @@
 // rs/src/bin/duscan/merge.rs
 use std::fs::File;
 use std::io::{self, BufReader, BufWriter, Read, Write};
-use std::path::Path;
-use dutopia::util::get_hostname;
+use std::path::{Path, PathBuf};
+use dutopia::util::{get_hostname, AtomicFile};
+
+use crate::mmap::append_mapped;
+use crate::parquet::{BinRow, ParquetWriter};
+use crate::sort::{RunSorter, RUN_BYTES};
 
 const READ_BUF_SIZE: usize = 2 * 1024 * 1024;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
 pub enum OutputFormat {
+    #[default]
     Csv,
     Bin,
+    Parquet,
+    Jsonl,
 }
 
-pub fn merge_shards(
-    out_dir: &Path,
-    final_path: &Path,
-    threads: usize,
-    out_fmt: OutputFormat,
-    sort_csv: bool,
-    pid: u32,
-) -> io::Result<()> {
-    let mut out = BufWriter::with_capacity(16 * 1024 * 1024, File::create(final_path)?);
+impl OutputFormat {
+    /// Default file extension, also the manifest's `format`.
+    pub fn ext(self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Bin => "zst",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Jsonl => "jsonl",
+        }
+    }
+
+    /// Workers write `--bin` rows to their shards; Parquet is made from
+    /// them at the merge.
+    pub fn bin_shards(self) -> bool {
+        matches!(self, OutputFormat::Bin | OutputFormat::Parquet)
+    }
+}
+
+/// Delete this run's shards, for a scan that will not be merged.
+pub fn remove_shards(shard_dir: &Path, threads: usize, pid: u32) {
+    let hostname = get_hostname();
+    for tid in 0..threads {
+        let _ = std::fs::remove_file(shard_dir.join(format!("shard_{hostname}_{pid}_{tid}.tmp")));
+    }
+}
 
-    match out_fmt {
-        OutputFormat::Csv => merge_shards_csv(out_dir, &mut out, threads, sort_csv, pid),
-        OutputFormat::Bin => merge_shards_bin(out_dir, &mut out, threads, pid),
+/// What `merge_shards` reads and how it writes the output.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MergeOptions<'a> {
+    /// Number of worker shards (`shard_{host}_{pid}_{tid}.tmp`, tid < threads)
+    pub threads: usize,
+    pub pid: u32,
+    pub format: OutputFormat,
+    /// Sort CSV/JSONL rows (`--no-atime` output, for stable diffs)
+    pub sort_csv: bool,
+    /// CSV shards are zstd-compressed (`--compress-shards`)
+    pub compressed: bool,
+    /// Extra column names (`--enrich` and friends) appended to the CSV header
+    pub extra_columns: &'a [String],
+    /// Copy plain shards through a memory map (see `mmap.rs`)
+    pub mmap: bool,
+}
+
+/// Merge this run's shards into `final_path`.
+///
+/// The output goes through `AtomicFile`: a crash mid-merge leaves at most
+/// the hidden temp file, never a truncated file under the final name for a
+/// cron job to pick up.
+pub fn merge_shards(shard_dir: &Path, final_path: &Path, opts: &MergeOptions) -> io::Result<()> {
+    let (atomic, file) = AtomicFile::create(final_path)?;
+    let file = write_merged(shard_dir, file, opts)?;
+    atomic.commit(file)
+}
+
+fn write_merged(shard_dir: &Path, file: File, opts: &MergeOptions) -> io::Result<File> {
+    let mut out = BufWriter::with_capacity(16 * 1024 * 1024, file);
+
+    match opts.format {
+        OutputFormat::Csv => {
+            out.write_all(b"INODE,ATIME,MTIME,UID,GID,MODE,SIZE,DISK,PATH")?;
+            for c in opts.extra_columns {
+                write!(out, ",{c}")?;
+            }
+            out.write_all(b"\n")?;
+            merge_shards_csv(shard_dir, &mut out, opts)
+        }
+        OutputFormat::Jsonl => merge_shards_csv(shard_dir, &mut out, opts),
+        OutputFormat::Bin => {
+            merge_shards_bin(shard_dir, &mut out, opts.threads, opts.pid, opts.mmap)
+        }
+        OutputFormat::Parquet => {
+            merge_shards_parquet(shard_dir, &mut out, opts.threads, opts.pid)
+        }
     }?;
 
-    out.flush()?;
-    Ok(())
+    out.into_inner().map_err(|e| e.into_error())
 }
 
 fn merge_shards_csv(
-    out_dir: &Path,
+    shard_dir: &Path,
     out: &mut BufWriter<File>,
-    threads: usize,
-    sort_csv: bool,
-    pid: u32,
+    opts: &MergeOptions,
 ) -> io::Result<()> {
-    out.write_all(b"INODE,ATIME,MTIME,UID,GID,MODE,SIZE,DISK,PATH\n")?;
     let hostname = get_hostname();
-
+    let MergeOptions {
+        threads,
+        pid,
+        sort_csv,
+        compressed,
+        mmap,
+        ..
+    } = *opts;
+
+    if !sort_csv && mmap && !compressed {
+        return append_shards_mapped(shard_dir, out, threads, pid);
+    }
     if !sort_csv {
         for tid in 0..threads {
-            let shard = out_dir.join(format!("shard_{hostname}_{pid}_{tid}.tmp"));
+            let shard = shard_dir.join(format!("shard_{hostname}_{pid}_{tid}.tmp"));
             if !shard.exists() {
                 continue;
             }
-            let f = File::open(&shard)?;
-            let mut reader = BufReader::with_capacity(READ_BUF_SIZE, f);
-            io::copy(&mut reader, out)?;
+            io::copy(&mut open_shard(&shard, compressed)?, out)?;
             let _ = std::fs::remove_file(shard);
         }
         return Ok(());
     }
 
-    // Sorted mode (only used when --skip-atime and CSV)
-    let mut lines: Vec<String> = Vec::new();
+    // Sorted mode (only used when --no-atime and CSV): shards are cut into
+    // sorted runs of bounded size and k-way merged, so memory stays flat
+    // regardless of output size.
+    let mut sorter = RunSorter::new(
+        shard_dir,
+        &format!("sort_{hostname}_{pid}"),
+        RUN_BYTES,
+        compressed,
+    );
 
     for tid in 0..threads {
-        let shard = out_dir.join(format!("shard_{hostname}_{pid}_{tid}.tmp"));
+        let shard = shard_dir.join(format!("shard_{hostname}_{pid}_{tid}.tmp"));
         if !shard.exists() {
             continue;
         }
 
-        let f = File::open(&shard)?;
-        let mut reader = BufReader::with_capacity(READ_BUF_SIZE, f);
-
-        let mut buf = String::new();
-        reader.read_to_string(&mut buf)?;
-        for line in buf.split_inclusive('\n') {
-            if line.trim().is_empty() {
-                continue;
-            }
-            let ln = line.strip_suffix('\n').unwrap_or(line).to_string();
-            if !ln.is_empty() {
-                lines.push(ln);
-            }
-        }
-
+        let reader = open_shard(&shard, compressed)?;
+        sorter.push_reader(BufReader::with_capacity(READ_BUF_SIZE, reader))?;
         let _ = std::fs::remove_file(shard);
     }
 
-    lines.sort_unstable();
+    sorter.finish(out)
+}
 
-    for ln in lines {
-        out.write_all(ln.as_bytes())?;
-        out.write_all(b"\n")?;
+/// Buffered reader over a CSV shard, transparently decoding zstd when the
+/// workers wrote compressed shards.
+fn open_shard(shard: &Path, compressed: bool) -> io::Result<Box<dyn Read>> {
+    let f = File::open(shard)?;
+    if compressed {
+        let reader = BufReader::with_capacity(READ_BUF_SIZE, f);
+        Ok(Box::new(zstd::stream::read::Decoder::with_buffer(reader)?))
+    } else {
+        Ok(Box::new(BufReader::with_capacity(READ_BUF_SIZE, f)))
     }
-
-    Ok(())
 }
 
 fn merge_shards_bin(
-    out_dir: &Path,
+    shard_dir: &Path,
     out: &mut BufWriter<File>,
     threads: usize,
     pid: u32,
+    mmap: bool,
 ) -> io::Result<()> {
+    if mmap {
+        return append_shards_mapped(shard_dir, out, threads, pid);
+    }
     let hostname = get_hostname();
     for tid in 0..threads {
-        let shard = out_dir.join(format!("shard_{hostname}_{pid}_{tid}.tmp"));
+        let shard = shard_dir.join(format!("shard_{hostname}_{pid}_{tid}.tmp"));
         if !shard.exists() {
             continue;
         }
@@ -113,21 +188,90 @@ fn merge_shards_bin(
     Ok(())
 }
 
+/// Concatenate every shard with `append_mapped`, then remove them.
+fn append_shards_mapped(
+    shard_dir: &Path,
+    out: &mut BufWriter<File>,
+    threads: usize,
+    pid: u32,
+) -> io::Result<()> {
+    let hostname = get_hostname();
+    let shards: Vec<PathBuf> = (0..threads)
+        .map(|tid| shard_dir.join(format!("shard_{hostname}_{pid}_{tid}.tmp")))
+        .filter(|s| s.exists())
+        .collect();
+    append_mapped(&shards, out)?;
+    for shard in shards {
+        let _ = std::fs::remove_file(shard);
+    }
+    Ok(())
+}
+
+/// Decode the binary rows of every shard into one Parquet file.
+fn merge_shards_parquet(
+    shard_dir: &Path,
+    out: &mut BufWriter<File>,
+    threads: usize,
+    pid: u32,
+) -> io::Result<()> {
+    let hostname = get_hostname();
+    let mut writer = ParquetWriter::new(out)?;
+    let mut buf: Vec<u8> = Vec::with_capacity(READ_BUF_SIZE);
+    let mut chunk = vec![0u8; READ_BUF_SIZE];
+    for tid in 0..threads {
+        let shard = shard_dir.join(format!("shard_{hostname}_{pid}_{tid}.tmp"));
+        if !shard.exists() {
+            continue;
+        }
+        let mut reader = open_shard(&shard, true)?;
+        buf.clear();
+        loop {
+            let n = reader.read(&mut chunk)?;
+            buf.extend_from_slice(&chunk[..n]);
+            let mut at = 0;
+            while let Some((row, len)) = BinRow::decode(&buf[at..]) {
+                writer.push(&row)?;
+                at += len;
+            }
+            buf.drain(..at);
+            if n == 0 {
+                break;
+            }
+        }
+        if !buf.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: truncated row at the end", shard.display()),
+            ));
+        }
+        let _ = std::fs::remove_file(shard);
+    }
+    writer.finish()?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::io::Read;
     use tempfile::tempdir;
 
+    /// Whether an `AtomicFile` temp (`.NAME.PID.tmp`) is left in `dir`.
+    fn has_temp(dir: &Path) -> bool {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .any(|e| e.unwrap().file_name().to_string_lossy().starts_with('.'))
+    }
+
     #[test]
     fn test_merge_shards_csv_unsorted_only() -> io::Result<()> {
         let tmp = tempdir()?;
-        let out_dir = tmp.path().to_path_buf();
-        let final_path = out_dir.join("out_unsorted.csv");
+        let shard_dir = tmp.path().to_path_buf();
+        let final_path = shard_dir.join("out_unsorted.csv");
         let pid = 123;
 
-        let shard0 = out_dir.join(format!("shard_{}_{}_0.tmp", get_hostname(), pid));
-        let shard1 = out_dir.join(format!("shard_{}_{}_1.tmp", get_hostname(), pid));
+        let shard0 = shard_dir.join(format!("shard_{}_{}_0.tmp", get_hostname(), pid));
+        let shard1 = shard_dir.join(format!("shard_{}_{}_1.tmp", get_hostname(), pid));
 
         {
             let mut w = File::create(&shard0)?;
@@ -138,7 +282,12 @@ mod tests {
             w.write_all(b"a\n")?;
         }
 
-        merge_shards(&out_dir, &final_path, 2, OutputFormat::Csv, false, pid)?;
+        let opts = MergeOptions {
+            threads: 2,
+            pid,
+            ..Default::default()
+        };
+        merge_shards(&shard_dir, &final_path, &opts)?;
 
         let mut s = String::new();
         File::open(&final_path)?.read_to_string(&mut s)?;
@@ -146,18 +295,42 @@ mod tests {
 
         assert_eq!(lines.remove(0), "INODE,ATIME,MTIME,UID,GID,MODE,SIZE,DISK,PATH");
         assert_eq!(lines, vec!["b", "a"]);
+        assert!(!has_temp(&shard_dir));
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_shards_failed_rename_leaves_no_partial() -> io::Result<()> {
+        let tmp = tempdir()?;
+        let shard_dir = tmp.path().to_path_buf();
+        let pid = 321;
+        std::fs::write(shard_dir.join(format!("shard_{}_{}_0.tmp", get_hostname(), pid)), b"a\n")?;
+        // A directory under the final name makes the rename fail.
+        let final_path = shard_dir.join("out.csv");
+        std::fs::create_dir(&final_path)?;
+        std::fs::write(final_path.join("keep"), b"")?;
+
+        let opts = MergeOptions {
+            threads: 1,
+            pid,
+            ..Default::default()
+        };
+        let res = merge_shards(&shard_dir, &final_path, &opts);
+        assert!(res.is_err());
+        assert!(!has_temp(&shard_dir));
+        assert!(final_path.join("keep").exists());
         Ok(())
     }
 
     #[test]
     fn test_merge_shards_csv_sorted_with_no_atime() -> io::Result<()> {
         let tmp = tempdir()?;
-        let out_dir = tmp.path().to_path_buf();
-        let final_path = out_dir.join("out_sorted.csv");
+        let shard_dir = tmp.path().to_path_buf();
+        let final_path = shard_dir.join("out_sorted.csv");
         let pid = 123;
 
-        let shard0 = out_dir.join(format!("shard_{}_{}_0.tmp", get_hostname(), pid));
-        let shard1 = out_dir.join(format!("shard_{}_{}_1.tmp", get_hostname(), pid));
+        let shard0 = shard_dir.join(format!("shard_{}_{}_0.tmp", get_hostname(), pid));
+        let shard1 = shard_dir.join(format!("shard_{}_{}_1.tmp", get_hostname(), pid));
         {
             let mut w = File::create(&shard0)?;
             w.write_all(b"b\n")?;
@@ -167,7 +340,13 @@ mod tests {
             w.write_all(b"a\n")?;
         }
 
-        merge_shards(&out_dir, &final_path, 2, OutputFormat::Csv, true, pid)?;
+        let opts = MergeOptions {
+            threads: 2,
+            pid,
+            sort_csv: true,
+            ..Default::default()
+        };
+        merge_shards(&shard_dir, &final_path, &opts)?;
 
         let mut s = String::new();
         File::open(&final_path)?.read_to_string(&mut s)?;
@@ -181,12 +360,12 @@ mod tests {
     #[test]
     fn test_merge_shards_bin() -> io::Result<()> {
         let tmp = tempdir()?;
-        let out_dir = tmp.path().to_path_buf();
-        let final_path = out_dir.join("out.bin");
+        let shard_dir = tmp.path().to_path_buf();
+        let final_path = shard_dir.join("out.bin");
         let pid = 123;
 
-        let shard0 = out_dir.join(format!("shard_{}_{}_0.tmp", get_hostname(), pid));
-        let shard1 = out_dir.join(format!("shard_{}_{}_1.tmp", get_hostname(), pid));
+        let shard0 = shard_dir.join(format!("shard_{}_{}_0.tmp", get_hostname(), pid));
+        let shard1 = shard_dir.join(format!("shard_{}_{}_1.tmp", get_hostname(), pid));
 
         {
             let mut w = File::create(&shard0)?;
@@ -197,7 +376,13 @@ mod tests {
             w.write_all(b"binary_data_1")?;
         }
 
-        merge_shards(&out_dir, &final_path, 2, OutputFormat::Bin, false, pid)?;
+        let opts = MergeOptions {
+            threads: 2,
+            pid,
+            format: OutputFormat::Bin,
+            ..Default::default()
+        };
+        merge_shards(&shard_dir, &final_path, &opts)?;
 
         let mut s = Vec::new();
         File::open(&final_path)?.read_to_end(&mut s)?;
@@ -208,17 +393,22 @@ mod tests {
     #[test]
     fn test_merge_shards_with_missing_shards() -> io::Result<()> {
         let tmp = tempdir()?;
-        let out_dir = tmp.path().to_path_buf();
-        let final_path = out_dir.join("out.csv");
+        let shard_dir = tmp.path().to_path_buf();
+        let final_path = shard_dir.join("out.csv");
         let pid = 123;
 
-        let shard1 = out_dir.join(format!("shard_{}_{}_1.tmp", get_hostname(), pid));
+        let shard1 = shard_dir.join(format!("shard_{}_{}_1.tmp", get_hostname(), pid));
         {
             let mut w = File::create(&shard1)?;
             w.write_all(b"data\n")?;
         }
 
-        merge_shards(&out_dir, &final_path, 2, OutputFormat::Csv, false, pid)?;
+        let opts = MergeOptions {
+            threads: 2,
+            pid,
+            ..Default::default()
+        };
+        merge_shards(&shard_dir, &final_path, &opts)?;
 
         let mut s = String::new();
         File::open(&final_path)?.read_to_string(&mut s)?;
@@ -232,17 +422,23 @@ mod tests {
     #[test]
     fn test_merge_shards_csv_with_empty_lines() -> io::Result<()> {
         let tmp = tempdir()?;
-        let out_dir = tmp.path().to_path_buf();
-        let final_path = out_dir.join("out_sorted.csv");
+        let shard_dir = tmp.path().to_path_buf();
+        let final_path = shard_dir.join("out_sorted.csv");
         let pid = 123;
 
-        let shard0 = out_dir.join(format!("shard_{}_{}_0.tmp", get_hostname(), pid));
+        let shard0 = shard_dir.join(format!("shard_{}_{}_0.tmp", get_hostname(), pid));
         {
             let mut w = File::create(&shard0)?;
             w.write_all(b"valid_line\n\n   \n")?;
         }
 
-        merge_shards(&out_dir, &final_path, 1, OutputFormat::Csv, true, pid)?;
+        let opts = MergeOptions {
+            threads: 1,
+            pid,
+            sort_csv: true,
+            ..Default::default()
+        };
+        merge_shards(&shard_dir, &final_path, &opts)?;
 
         let mut s = String::new();
         File::open(&final_path)?.read_to_string(&mut s)?;
@@ -257,13 +453,13 @@ mod tests {
     #[test]
     fn test_merge_shards_many_threads() -> io::Result<()> {
         let tmp = tempdir()?;
-        let out_dir = tmp.path().to_path_buf();
-        let final_path = out_dir.join("out.csv");
+        let shard_dir = tmp.path().to_path_buf();
+        let final_path = shard_dir.join("out.csv");
         let pid = 123;
         let num_threads = 100;
 
-        let shard0 = out_dir.join(format!("shard_{}_{}_0.tmp", get_hostname(), pid));
-        let shard50 = out_dir.join(format!("shard_{}_{}_50.tmp", get_hostname(), pid));
+        let shard0 = shard_dir.join(format!("shard_{}_{}_0.tmp", get_hostname(), pid));
+        let shard50 = shard_dir.join(format!("shard_{}_{}_50.tmp", get_hostname(), pid));
 
         {
             let mut w = File::create(&shard0)?;
@@ -274,7 +470,12 @@ mod tests {
             w.write_all(b"data50\n")?;
         }
 
-        merge_shards(&out_dir, &final_path, num_threads, OutputFormat::Csv, false, pid)?;
+        let opts = MergeOptions {
+            threads: num_threads,
+            pid,
+            ..Default::default()
+        };
+        merge_shards(&shard_dir, &final_path, &opts)?;
 
         let mut s = String::new();
         File::open(&final_path)?.read_to_string(&mut s)?;
@@ -286,6 +487,52 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_merge_shards_csv_compressed() -> io::Result<()> {
+        let tmp = tempdir()?;
+        let shard_dir = tmp.path().to_path_buf();
+        let final_path = shard_dir.join("out.csv");
+        let pid = 123;
+
+        for (tid, data) in [(0, &b"b\n"[..]), (1, &b"a\n"[..])] {
+            let shard = shard_dir.join(format!("shard_{}_{}_{}.tmp", get_hostname(), pid, tid));
+            let mut enc = zstd::stream::write::Encoder::new(File::create(&shard)?, 1)?;
+            enc.write_all(data)?;
+            enc.finish()?;
+        }
+
+        let opts = MergeOptions {
+            threads: 2,
+            pid,
+            compressed: true,
+            ..Default::default()
+        };
+        merge_shards(&shard_dir, &final_path, &opts)?;
+        let s = std::fs::read_to_string(&final_path)?;
+        assert_eq!(s, "INODE,ATIME,MTIME,UID,GID,MODE,SIZE,DISK,PATH\nb\na\n");
+
+        let sorted_path = shard_dir.join("sorted.csv");
+        for (tid, data) in [(0, &b"b\n"[..]), (1, &b"a\n"[..])] {
+            let shard = shard_dir.join(format!("shard_{}_{}_{}.tmp", get_hostname(), pid, tid));
+            let mut enc = zstd::stream::write::Encoder::new(File::create(&shard)?, 1)?;
+            enc.write_all(data)?;
+            enc.finish()?;
+        }
+        let extra = ["project".to_string()];
+        let opts = MergeOptions {
+            threads: 2,
+            pid,
+            sort_csv: true,
+            compressed: true,
+            extra_columns: &extra,
+            ..Default::default()
+        };
+        merge_shards(&shard_dir, &sorted_path, &opts)?;
+        let s = std::fs::read_to_string(&sorted_path)?;
+        assert_eq!(s, "INODE,ATIME,MTIME,UID,GID,MODE,SIZE,DISK,PATH,project\na\nb\n");
+        Ok(())
+    }
+
     #[test]
     fn test_output_format_equality() {
         assert_eq!(OutputFormat::Csv, OutputFormat::Csv);
@@ -0,0 +1,206 @@
+// rs/src/bin/duscan/mmap.rs
+//
+// `--mmap-merge`: shards are appended to the output from read-only memory
+// maps instead of through a read buffer. The maps are cut into pieces of at
+// most `WINDOW` bytes and written with vectored writes, so one syscall
+// carries up to `WINDOW` bytes even when it spans many small shards. The
+// kernel reads ahead on the maps and there is no copy into a user buffer.
+// Written ranges are dropped from the page cache with
+// `posix_fadvise(POSIX_FADV_DONTNEED)` (Linux), so hundreds of GBs of shards
+// do not crowd it. Only plain concatenation uses it (uncompressed CSV
+// without sorting, and --bin); elsewhere, and off Unix, the merge reads and
+// writes as usual.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+/// Most bytes handed to one vectored write.
+#[cfg(unix)]
+const WINDOW: usize = 256 * 1024 * 1024;
+
+/// Most slices per vectored write (the usual IOV_MAX).
+#[cfg(unix)]
+const MAX_SLICES: usize = 1024;
+
+/// Append the whole of every shard, in order, to `out`; returns the bytes
+/// copied.
+#[cfg(unix)]
+pub fn append_mapped(shards: &[PathBuf], out: &mut BufWriter<File>) -> io::Result<u64> {
+    use memmap2::{Advice, Mmap};
+    use std::io::IoSlice;
+
+    out.flush()?;
+    let out = out.get_mut();
+
+    let mut maps: Vec<(File, Mmap)> = Vec::with_capacity(shards.len());
+    for shard in shards {
+        let f = File::open(shard)?;
+        if f.metadata()?.len() == 0 {
+            continue;
+        }
+        // SAFETY: shards are only written by this run's workers, which have
+        // all finished; nothing changes them while they are mapped.
+        let map = unsafe { Mmap::map(&f)? };
+        map.advise(Advice::Sequential)?;
+        maps.push((f, map));
+    }
+
+    // (map, start, end) in output order, each at most WINDOW bytes
+    let pieces: Vec<(usize, usize, usize)> = maps
+        .iter()
+        .enumerate()
+        .flat_map(|(m, (_, map))| {
+            (0..map.len())
+                .step_by(WINDOW)
+                .map(move |start| (m, start, (start + WINDOW).min(map.len())))
+        })
+        .collect();
+
+    let mut total = 0u64;
+    let mut i = 0;
+    while i < pieces.len() {
+        let mut j = i;
+        let mut bytes = 0;
+        while j < pieces.len() && j - i < MAX_SLICES {
+            let len = pieces[j].2 - pieces[j].1;
+            if j > i && bytes + len > WINDOW {
+                break;
+            }
+            bytes += len;
+            j += 1;
+        }
+        let mut slices: Vec<IoSlice> = pieces[i..j]
+            .iter()
+            .map(|&(m, start, end)| IoSlice::new(&maps[m].1[start..end]))
+            .collect();
+        write_all_vectored(out, &mut slices)?;
+        for &(m, start, end) in &pieces[i..j] {
+            drop_cached(&maps[m].0, start, end - start);
+        }
+        total += bytes as u64;
+        i = j;
+    }
+    Ok(total)
+}
+
+/// `Write::write_all` for a vectored write, retrying short writes.
+#[cfg(unix)]
+fn write_all_vectored(out: &mut File, mut slices: &mut [io::IoSlice<'_>]) -> io::Result<()> {
+    while !slices.is_empty() {
+        match out.write_vectored(slices) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => io::IoSlice::advance_slices(&mut slices, n),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Evict a written range of `f` from the page cache. Unmapping alone would
+/// not: `MADV_DONTNEED` on a file map only drops this process's mapping,
+/// the pages stay cached.
+#[cfg(target_os = "linux")]
+fn drop_cached(f: &File, offset: usize, len: usize) {
+    use std::os::fd::AsRawFd;
+    // SAFETY: advisory only, on a descriptor this function borrows.
+    unsafe {
+        libc::posix_fadvise(
+            f.as_raw_fd(),
+            offset as libc::off_t,
+            len as libc::off_t,
+            libc::POSIX_FADV_DONTNEED,
+        );
+    }
+}
+
+/// No `posix_fadvise` on macOS and the BSDs; the pages age out as usual.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn drop_cached(_f: &File, _offset: usize, _len: usize) {}
+
+#[cfg(not(unix))]
+pub fn append_mapped(shards: &[PathBuf], out: &mut BufWriter<File>) -> io::Result<u64> {
+    let mut total = 0;
+    for shard in shards {
+        total += io::copy(&mut File::open(shard)?, out)?;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn appends_whole_shards_after_buffered_bytes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let shard = tmp.path().join("shard.tmp");
+        let data: Vec<u8> = (0..100_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        std::fs::write(&shard, &data).unwrap();
+        let empty = tmp.path().join("empty.tmp");
+        std::fs::write(&empty, b"").unwrap();
+        let small = tmp.path().join("small.tmp");
+        std::fs::write(&small, b"tail\n").unwrap();
+
+        let out_path = tmp.path().join("out");
+        let mut out = BufWriter::new(File::create(&out_path).unwrap());
+        out.write_all(b"HEADER\n").unwrap();
+        let n = append_mapped(&[shard, empty, small], &mut out).unwrap();
+        assert_eq!(n, data.len() as u64 + 5);
+        out.flush().unwrap();
+        drop(out);
+
+        let written = std::fs::read(&out_path).unwrap();
+        assert_eq!(&written[..7], b"HEADER\n");
+        assert_eq!(&written[7..7 + data.len()], &data[..]);
+        assert_eq!(&written[7 + data.len()..], b"tail\n");
+    }
+
+    /// `--mmap-merge` against the plain read/write loop on the same shards:
+    /// `DUSCAN_BENCH_MB=4096 cargo test --release --bin duscan
+    /// bench_mmap_merge -- --ignored --nocapture`. Use a size well above RAM
+    /// for numbers that are not just page-cache copies.
+    #[test]
+    #[ignore]
+    fn bench_mmap_merge() {
+        let mb: usize = std::env::var("DUSCAN_BENCH_MB")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(512);
+        let tmp = tempfile::tempdir().unwrap();
+        let line = b"1-2,1700000000,1700000000,1000,1000,33188,4096,4096,/data/some/file\n";
+        let chunk: Vec<u8> = line.iter().copied().cycle().take(1 << 20).collect();
+        let shards: Vec<PathBuf> = (0..8)
+            .map(|i| {
+                let p = tmp.path().join(format!("shard_{i}.tmp"));
+                let mut f = BufWriter::new(File::create(&p).unwrap());
+                for _ in 0..mb / 8 {
+                    f.write_all(&chunk).unwrap();
+                }
+                p
+            })
+            .collect();
+
+        let run = |name: &str, mapped: bool| {
+            let out_path = tmp.path().join(format!("out_{name}"));
+            let mut out = BufWriter::with_capacity(16 << 20, File::create(&out_path).unwrap());
+            let start = Instant::now();
+            let n = if mapped {
+                append_mapped(&shards, &mut out).unwrap()
+            } else {
+                shards
+                    .iter()
+                    .map(|s| io::copy(&mut File::open(s).unwrap(), &mut out).unwrap())
+                    .sum()
+            };
+            out.into_inner().unwrap().sync_all().unwrap();
+            let secs = start.elapsed().as_secs_f64();
+            let mb = (n >> 20) as f64;
+            println!("{name:>6}: {mb} MB in {secs:.2}s, {:.0} MB/s", mb / secs);
+            std::fs::remove_file(out_path).unwrap();
+        };
+        run("copy", false);
+        run("mmap", true);
+    }
+}
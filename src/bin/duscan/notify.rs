@@ -0,0 +1,241 @@
+// rs/src/bin/duscan/notify.rs
+//
+// End-of-run notifications (`--notify-webhook`, `--notify-email`): the run
+// summary JSON is posted to the webhook and mailed to the recipients on
+// success and on failure alike, so an overnight scan cannot fail silently.
+//
+// Email uses the same SMTP env vars as duapi: SMTP_HOST, SMTP_PORT (587),
+// SMTP_USER + SMTP_PASSWORD (optional here), SMTP_FROM, SMTP_TLS (true).
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use lettre::message::{header::ContentType, Mailbox, Message};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{SmtpTransport, Transport};
+
+use crate::report::RunReport;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+pub struct Notifier {
+    webhook: Option<url::Url>,
+    email: Vec<String>,
+    smtp: Option<SmtpConfig>,
+}
+
+#[derive(Debug, Clone)]
+struct SmtpConfig {
+    host: String,
+    port: u16,
+    credentials: Option<(String, String)>,
+    from: Mailbox,
+    tls: bool,
+}
+
+impl Notifier {
+    /// Checks the settings up front so a typo fails the run before the scan
+    /// rather than after it.
+    pub fn new(webhook: Option<&str>, email: &[String]) -> Result<Self> {
+        let webhook = webhook
+            .map(|u| {
+                let url = url::Url::parse(u).with_context(|| format!("--notify-webhook {u}"))?;
+                if !matches!(url.scheme(), "http" | "https") {
+                    bail!("--notify-webhook must be an http(s) URL, got '{u}'");
+                }
+                Ok(url)
+            })
+            .transpose()?;
+        for to in email {
+            to.parse::<Mailbox>()
+                .with_context(|| format!("--notify-email: invalid address '{to}'"))?;
+        }
+        let smtp = if email.is_empty() {
+            None
+        } else {
+            Some(SmtpConfig::from_env()?)
+        };
+        Ok(Self {
+            webhook,
+            email: email.to_vec(),
+            smtp,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.webhook.is_none() && self.email.is_empty()
+    }
+
+    /// Deliver `report`; failures are printed and otherwise ignored so they
+    /// never change the scan's own outcome.
+    pub fn send(&self, report: &RunReport) {
+        let body = match serde_json::to_string_pretty(report) {
+            Ok(b) => b,
+            Err(e) => {
+                tracing::error!(error = %e, "cannot serialize run summary");
+                return;
+            }
+        };
+        if let Some(url) = &self.webhook {
+            match post_webhook(url, &body) {
+                Ok(()) => println!("Notified     : {url}"),
+                Err(e) => {
+                    tracing::error!(%url, error = format!("{e:#}"), "webhook notification failed")
+                }
+            }
+        }
+        if let Some(smtp) = &self.smtp {
+            let subject = subject(report);
+            for to in &self.email {
+                match send_email(smtp, to, &subject, &body) {
+                    Ok(()) => println!("Notified     : {to}"),
+                    Err(e) => {
+                        tracing::error!(%to, error = format!("{e:#}"), "email notification failed")
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl SmtpConfig {
+    fn from_env() -> Result<Self> {
+        let var = |k: &str| std::env::var(k).ok().filter(|s| !s.trim().is_empty());
+        let host = var("SMTP_HOST").context("--notify-email needs SMTP_HOST")?;
+        let from = var("SMTP_FROM").context("--notify-email needs SMTP_FROM")?;
+        let from: Mailbox = from
+            .parse()
+            .with_context(|| format!("invalid SMTP_FROM '{from}'"))?;
+        let port = var("SMTP_PORT").and_then(|s| s.parse().ok()).unwrap_or(587);
+        let credentials = var("SMTP_USER").zip(var("SMTP_PASSWORD"));
+        let tls = var("SMTP_TLS")
+            .map(|s| matches!(s.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+            .unwrap_or(true);
+        Ok(Self {
+            host,
+            port,
+            credentials,
+            from,
+            tls,
+        })
+    }
+}
+
+fn subject(report: &RunReport) -> String {
+    let roots = report.roots.join(", ");
+    match &report.error {
+        None => format!(
+            "duscan OK on {}: {} ({} files, {} errors)",
+            report.host, roots, report.files, report.errors
+        ),
+        Some(_) => format!("duscan FAILED on {}: {}", report.host, roots),
+    }
+}
+
+fn post_webhook(url: &url::Url, body: &str) -> Result<()> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    rt.block_on(async {
+        let resp = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()?
+            .post(url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await?;
+        let status = resp.status();
+        if !status.is_success() {
+            bail!("HTTP {status}");
+        }
+        Ok(())
+    })
+}
+
+fn send_email(cfg: &SmtpConfig, to: &str, subject: &str, body: &str) -> Result<()> {
+    let email = Message::builder()
+        .from(cfg.from.clone())
+        .to(to.parse()?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(body.to_string())?;
+    let builder = if cfg.tls {
+        SmtpTransport::starttls_relay(&cfg.host)?
+    } else {
+        SmtpTransport::builder_dangerous(&cfg.host)
+    };
+    let mut builder = builder.port(cfg.port);
+    if let Some((user, password)) = &cfg.credentials {
+        builder = builder.credentials(Credentials::new(user.clone(), password.clone()));
+    }
+    builder.build().send(&email)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    fn report(error: Option<&str>) -> RunReport {
+        RunReport {
+            status: if error.is_some() { "failed" } else { "ok" },
+            host: "node1".into(),
+            roots: vec!["/data".into()],
+            error: error.map(str::to_string),
+            files: 3,
+            ..RunReport::default()
+        }
+    }
+
+    #[test]
+    fn rejects_bad_settings() {
+        assert!(Notifier::new(Some("ftp://host/x"), &[]).is_err());
+        assert!(Notifier::new(Some("not a url"), &[]).is_err());
+        assert!(Notifier::new(None, &["not-an-address".into()]).is_err());
+        assert!(Notifier::new(None, &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn subject_reflects_outcome() {
+        assert_eq!(
+            subject(&report(None)),
+            "duscan OK on node1: /data (3 files, 0 errors)"
+        );
+        assert_eq!(
+            subject(&report(Some("disk full"))),
+            "duscan FAILED on node1: /data"
+        );
+    }
+
+    #[test]
+    fn webhook_receives_summary_json() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut req = Vec::new();
+            let mut buf = [0u8; 4096];
+            while !String::from_utf8_lossy(&req).contains("\"extensions\"") {
+                let n = stream.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                req.extend_from_slice(&buf[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\ncontent-length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8_lossy(&req).into_owned()
+        });
+
+        let notifier = Notifier::new(Some(&url), &[]).unwrap();
+        let body = serde_json::to_string_pretty(&report(Some("disk full"))).unwrap();
+        post_webhook(notifier.webhook.as_ref().unwrap(), &body).unwrap();
+        let req = server.join().unwrap();
+        assert!(req.starts_with("POST /hook "));
+        assert!(req.contains("\"status\": \"failed\""));
+    }
+}
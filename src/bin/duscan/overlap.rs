@@ -0,0 +1,179 @@
+// rs/src/bin/duscan/overlap.rs
+//
+// Roots that cover the same directories would be walked, and counted, twice.
+// Before the scan, a root that repeats another, lies inside another, or is
+// the same directory under another name (bind mount, same dev/ino) is
+// dropped. During the scan, a directory of one root that turns out to be
+// another root (`/data` given together with `/mnt/x`, a bind mount of
+// `/data/x`) is left to that root. Every overlap is reported.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use dutopia::util::strip_verbatim_prefix;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlapKind {
+    /// The same path given twice
+    Duplicate,
+    /// Inside another root
+    Nested,
+    /// The same directory as another root under a different path
+    BindMount,
+}
+
+/// A root or directory not scanned because another root covers it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Overlap {
+    pub path: String,
+    pub scanned_as: String,
+    pub kind: OverlapKind,
+}
+
+impl Overlap {
+    fn new(path: &Path, scanned_as: &Path, kind: OverlapKind) -> Self {
+        Self {
+            path: strip_verbatim_prefix(path).display().to_string(),
+            scanned_as: strip_verbatim_prefix(scanned_as).display().to_string(),
+            kind,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let how = match self.kind {
+            OverlapKind::Duplicate => "repeats",
+            OverlapKind::Nested => "is inside",
+            OverlapKind::BindMount => "is the same directory as",
+        };
+        format!("{} {how} {} (scanned once)", self.path, self.scanned_as)
+    }
+}
+
+/// Device and inode of a directory; `None` where they are not available.
+#[cfg(unix)]
+pub fn dir_id(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    let md = std::fs::metadata(path).ok()?;
+    Some((md.dev(), md.ino()))
+}
+
+#[cfg(not(unix))]
+pub fn dir_id(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+/// Drop roots already covered by another root, keeping the rest in the
+/// given order. Shallower roots win, so `/data` covers `/data/x` whichever
+/// is listed first. Roots must be canonical.
+pub fn prune_roots(
+    roots: Vec<PathBuf>,
+    id: impl Fn(&Path) -> Option<(u64, u64)>,
+) -> (Vec<PathBuf>, Vec<Overlap>) {
+    let ids: Vec<_> = roots.iter().map(|r| id(r)).collect();
+    let mut order: Vec<usize> = (0..roots.len()).collect();
+    order.sort_by_key(|&i| roots[i].components().count());
+
+    let mut kept: Vec<usize> = Vec::new();
+    let mut overlaps = Vec::new();
+    for i in order {
+        let covered = kept.iter().find_map(|&k| {
+            if roots[i] == roots[k] {
+                Some((k, OverlapKind::Duplicate))
+            } else if roots[i].starts_with(&roots[k]) {
+                Some((k, OverlapKind::Nested))
+            } else if ids[i].is_some() && ids[i] == ids[k] {
+                Some((k, OverlapKind::BindMount))
+            } else {
+                None
+            }
+        });
+        match covered {
+            Some((k, kind)) => overlaps.push(Overlap::new(&roots[i], &roots[k], kind)),
+            None => kept.push(i),
+        }
+    }
+    kept.sort_unstable();
+    let mut slots: Vec<Option<PathBuf>> = roots.into_iter().map(Some).collect();
+    let roots = kept.into_iter().filter_map(|i| slots[i].take()).collect();
+    (roots, overlaps)
+}
+
+/// Device/inode of every root, to notice a root reached again inside
+/// another one.
+#[derive(Debug, Default)]
+pub struct RootIds(HashMap<(u64, u64), PathBuf>);
+
+impl RootIds {
+    /// `None` when fewer than two roots have an id: nothing can overlap.
+    pub fn new(roots: &[PathBuf]) -> Option<Self> {
+        let map: HashMap<_, _> = roots
+            .iter()
+            .filter_map(|r| dir_id(r).map(|id| (id, r.clone())))
+            .collect();
+        (map.len() > 1).then_some(Self(map))
+    }
+
+    /// The other root that `dir` is the same directory as, if any.
+    pub fn covered_by(&self, dir: &Path) -> Option<Overlap> {
+        let root = self.0.get(&dir_id(dir)?)?;
+        (root != dir).then(|| Overlap::new(dir, root, OverlapKind::BindMount))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prune_drops_duplicate_nested_and_same_id_roots() {
+        let roots: Vec<PathBuf> = ["/data/x", "/srv", "/data", "/mnt/srv", "/srv"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        let id = |p: &Path| match p.to_str() {
+            Some("/srv") | Some("/mnt/srv") => Some((1, 2)),
+            _ => None,
+        };
+        let (kept, overlaps) = prune_roots(roots, id);
+        assert_eq!(kept, [PathBuf::from("/srv"), PathBuf::from("/data")]);
+        let kinds: Vec<_> = overlaps
+            .iter()
+            .map(|o| (o.path.as_str(), o.scanned_as.as_str(), o.kind))
+            .collect();
+        assert_eq!(kinds.len(), 3);
+        assert!(kinds.contains(&("/data/x", "/data", OverlapKind::Nested)));
+        assert!(kinds.contains(&("/srv", "/srv", OverlapKind::Duplicate)));
+        assert!(kinds.contains(&("/mnt/srv", "/srv", OverlapKind::BindMount)));
+        assert!(overlaps[0].describe().contains("scanned once"));
+    }
+
+    #[test]
+    fn prune_keeps_siblings_with_common_prefix() {
+        let roots = vec![PathBuf::from("/data"), PathBuf::from("/data2")];
+        let (kept, overlaps) = prune_roots(roots, |_| None);
+        assert_eq!(kept.len(), 2);
+        assert!(overlaps.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn root_ids_spot_another_root_inside_the_walk() {
+        let tmp = tempfile::tempdir().unwrap();
+        let a = tmp.path().join("a");
+        let b = tmp.path().join("b");
+        std::fs::create_dir_all(a.join("x")).unwrap();
+        std::fs::create_dir(&b).unwrap();
+        // A symlink stands in for a bind mount: same dev/ino, other path.
+        std::os::unix::fs::symlink(&b, a.join("link")).unwrap();
+
+        let ids = RootIds::new(&[a.clone(), b.clone()]).unwrap();
+        assert!(ids.covered_by(&a).is_none());
+        assert!(ids.covered_by(&a.join("x")).is_none());
+        let o = ids.covered_by(&a.join("link")).unwrap();
+        assert_eq!(o.kind, OverlapKind::BindMount);
+        assert_eq!(o.scanned_as, b.display().to_string());
+        assert!(RootIds::new(&[a]).is_none());
+    }
+}
@@ -0,0 +1,283 @@
+// rs/src/bin/duscan/parquet.rs
+//
+// `--format parquet`: the scan as an Apache Parquet file that Spark, DuckDB
+// or pandas load as is. Workers write the binary rows of `--bin` to their
+// shards; the merge decodes them and hands them to the `parquet` crate's
+// file writer here, one row group per `ROW_GROUP_ROWS` rows, so memory
+// stays bounded on any scan size.
+//
+// Schema, every column required:
+//
+//   dev, ino       INT64 (UINT_64)   INODE split in its two parts
+//   atime, mtime   INT64             epoch seconds, as in the CSV
+//   uid, gid, mode INT32 (UINT_32)
+//   size, disk     INT64             bytes
+//   path           BYTE_ARRAY        the raw name bytes, with no UTF8
+//                                    annotation: names need not be UTF-8
+//
+// Pages are zstd-compressed, at most `PAGE_ROWS` values each.
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::data_type::{ByteArray, ByteArrayType, DataType, Int32Type, Int64Type};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{SerializedFileWriter, SerializedRowGroupWriter};
+use parquet::schema::parser::parse_message_type;
+
+pub const ROW_GROUP_ROWS: usize = 1 << 18;
+const PAGE_ROWS: usize = 64 * 1024;
+const ZSTD_LEVEL: i32 = 3;
+
+const SCHEMA: &str = "
+message scan {
+    required int64 dev (INTEGER(64, false));
+    required int64 ino (INTEGER(64, false));
+    required int64 atime;
+    required int64 mtime;
+    required int32 uid (INTEGER(32, false));
+    required int32 gid (INTEGER(32, false));
+    required int32 mode (INTEGER(32, false));
+    required int64 size;
+    required int64 disk;
+    required binary path;
+}";
+
+/// One `--bin` row, as the merge decodes it from a shard.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BinRow {
+    pub path: Vec<u8>,
+    pub dev: u64,
+    pub ino: u64,
+    pub atime: i64,
+    pub mtime: i64,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u32,
+    pub size: u64,
+    pub disk: u64,
+}
+
+const BIN_FIXED: usize = 8 * 4 + 4 * 3 + 8 * 2;
+
+impl BinRow {
+    /// Decode the row at the start of `buf` (see `write_row_bin`); the row
+    /// and its length, or None when `buf` does not hold a whole row yet.
+    pub fn decode(buf: &[u8]) -> Option<(BinRow, usize)> {
+        let len = u32::from_le_bytes(buf.get(..4)?.try_into().ok()?) as usize;
+        let end = 4 + len + BIN_FIXED;
+        let rec = buf.get(..end)?;
+        let mut at = 4 + len;
+        let mut take = |n: usize| {
+            let v = &rec[at..at + n];
+            at += n;
+            v
+        };
+        let u64_ = |b: &[u8]| u64::from_le_bytes(b.try_into().unwrap());
+        let u32_ = |b: &[u8]| u32::from_le_bytes(b.try_into().unwrap());
+        let row = BinRow {
+            path: rec[4..4 + len].to_vec(),
+            dev: u64_(take(8)),
+            ino: u64_(take(8)),
+            atime: u64_(take(8)) as i64,
+            mtime: u64_(take(8)) as i64,
+            uid: u32_(take(4)),
+            gid: u32_(take(4)),
+            mode: u32_(take(4)),
+            size: u64_(take(8)),
+            disk: u64_(take(8)),
+        };
+        Some((row, end))
+    }
+}
+
+/// Rows of the row group being filled, one vector per column. Unsigned
+/// values are stored as their bit patterns, as Parquet wants.
+#[derive(Default)]
+struct Group {
+    dev: Vec<i64>,
+    ino: Vec<i64>,
+    atime: Vec<i64>,
+    mtime: Vec<i64>,
+    uid: Vec<i32>,
+    gid: Vec<i32>,
+    mode: Vec<i32>,
+    size: Vec<i64>,
+    disk: Vec<i64>,
+    path: Vec<ByteArray>,
+}
+
+pub struct ParquetWriter<W: Write + Send> {
+    file: SerializedFileWriter<W>,
+    group: Group,
+}
+
+fn io_err(e: ParquetError) -> io::Error {
+    io::Error::other(e)
+}
+
+/// Write `values` as the next column of `rg`.
+fn column<T: DataType, W: Write + Send>(
+    rg: &mut SerializedRowGroupWriter<'_, W>,
+    values: &[T::T],
+) -> Result<(), ParquetError> {
+    let mut col = rg
+        .next_column()?
+        .ok_or_else(|| ParquetError::General("more columns than the schema".into()))?;
+    col.typed::<T>().write_batch(values, None, None)?;
+    col.close()
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    pub fn new(out: W) -> io::Result<Self> {
+        let schema = parse_message_type(SCHEMA).map_err(io_err)?;
+        let zstd = ZstdLevel::try_new(ZSTD_LEVEL).map_err(io_err)?;
+        let props = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(zstd))
+            .set_data_page_row_count_limit(PAGE_ROWS)
+            .set_max_row_group_size(ROW_GROUP_ROWS)
+            .set_created_by(format!("dutopia duscan version {}", env!("CARGO_PKG_VERSION")))
+            .build();
+        let file =
+            SerializedFileWriter::new(out, Arc::new(schema), Arc::new(props)).map_err(io_err)?;
+        Ok(Self {
+            file,
+            group: Group::default(),
+        })
+    }
+
+    pub fn push(&mut self, r: &BinRow) -> io::Result<()> {
+        let g = &mut self.group;
+        g.dev.push(r.dev as i64);
+        g.ino.push(r.ino as i64);
+        g.atime.push(r.atime);
+        g.mtime.push(r.mtime);
+        g.uid.push(r.uid as i32);
+        g.gid.push(r.gid as i32);
+        g.mode.push(r.mode as i32);
+        g.size.push(r.size as i64);
+        g.disk.push(r.disk as i64);
+        g.path.push(ByteArray::from(r.path.clone()));
+        if g.path.len() == ROW_GROUP_ROWS {
+            self.flush_group()?;
+        }
+        Ok(())
+    }
+
+    fn flush_group(&mut self) -> io::Result<()> {
+        if self.group.path.is_empty() {
+            return Ok(());
+        }
+        let g = std::mem::take(&mut self.group);
+        let write = |rg: &mut SerializedRowGroupWriter<'_, W>| -> Result<(), ParquetError> {
+            column::<Int64Type, W>(rg, &g.dev)?;
+            column::<Int64Type, W>(rg, &g.ino)?;
+            column::<Int64Type, W>(rg, &g.atime)?;
+            column::<Int64Type, W>(rg, &g.mtime)?;
+            column::<Int32Type, W>(rg, &g.uid)?;
+            column::<Int32Type, W>(rg, &g.gid)?;
+            column::<Int32Type, W>(rg, &g.mode)?;
+            column::<Int64Type, W>(rg, &g.size)?;
+            column::<Int64Type, W>(rg, &g.disk)?;
+            column::<ByteArrayType, W>(rg, &g.path)
+        };
+        let mut rg = self.file.next_row_group().map_err(io_err)?;
+        write(&mut rg).map_err(io_err)?;
+        rg.close().map_err(io_err)?;
+        Ok(())
+    }
+
+    /// Write the last row group and the footer; returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush_group()?;
+        self.file.into_inner().map_err(io_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::basic::{ConvertedType, Type as PhysicalType};
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::RowAccessor;
+
+    #[test]
+    fn rows_read_back_with_the_parquet_reader() {
+        let mut rows: Vec<BinRow> = (0..(PAGE_ROWS as u64 + 10))
+            .map(|i| BinRow {
+                path: format!("/data/f{i}").into_bytes(),
+                dev: 64768,
+                ino: i,
+                atime: 1_700_000_000,
+                mtime: 1_600_000_000 + i as i64,
+                uid: 1000,
+                gid: 100,
+                mode: 0o100644,
+                size: i * 10,
+                disk: 4096,
+            })
+            .collect();
+        rows[1].path = b"/data/caf\xe9".to_vec();
+        rows[2].uid = u32::MAX;
+        rows[2].ino = u64::MAX;
+        let mut bin = Vec::new();
+        for r in &rows {
+            bin.extend((r.path.len() as u32).to_le_bytes());
+            bin.extend(&r.path);
+            for v in [r.dev, r.ino, r.atime as u64, r.mtime as u64] {
+                bin.extend(v.to_le_bytes());
+            }
+            for v in [r.uid, r.gid, r.mode] {
+                bin.extend(v.to_le_bytes());
+            }
+            bin.extend(r.size.to_le_bytes());
+            bin.extend(r.disk.to_le_bytes());
+        }
+        let (first, n) = BinRow::decode(&bin).unwrap();
+        assert_eq!(first, rows[0]);
+        assert!(BinRow::decode(&bin[..n - 1]).is_none());
+
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("scan.parquet");
+        let mut w = ParquetWriter::new(std::fs::File::create(&path).unwrap()).unwrap();
+        let mut at = 0;
+        while let Some((r, n)) = BinRow::decode(&bin[at..]) {
+            w.push(&r).unwrap();
+            at += n;
+        }
+        w.finish().unwrap();
+
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let meta = reader.metadata().file_metadata();
+        assert_eq!(meta.num_rows(), rows.len() as i64);
+        let schema = meta.schema_descr();
+        assert_eq!(schema.num_columns(), 10);
+        let path_col = schema.column(9);
+        assert_eq!(path_col.name(), "path");
+        assert_eq!(path_col.physical_type(), PhysicalType::BYTE_ARRAY);
+        assert_eq!(path_col.converted_type(), ConvertedType::NONE);
+        assert_eq!(schema.column(1).converted_type(), ConvertedType::UINT_64);
+
+        let read: Vec<BinRow> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| {
+                let row = row.unwrap();
+                BinRow {
+                    dev: row.get_ulong(0).unwrap(),
+                    ino: row.get_ulong(1).unwrap(),
+                    atime: row.get_long(2).unwrap(),
+                    mtime: row.get_long(3).unwrap(),
+                    uid: row.get_uint(4).unwrap(),
+                    gid: row.get_uint(5).unwrap(),
+                    mode: row.get_uint(6).unwrap(),
+                    size: row.get_long(7).unwrap() as u64,
+                    disk: row.get_long(8).unwrap() as u64,
+                    path: row.get_bytes(9).unwrap().data().to_vec(),
+                }
+            })
+            .collect();
+        assert_eq!(read, rows);
+    }
+}
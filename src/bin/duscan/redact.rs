@@ -0,0 +1,88 @@
+// rs/src/bin/duscan/redact.rs
+//
+// `--redact-names`: file rows are written with the base name replaced by a
+// hash, so home directories can be analysed for capacity without file names
+// leaving the host. Directory rows and the directory part of every path are
+// kept, and so is a short extension (the same rule as the extension report),
+// so per-folder and per-type rollups still work. The hash is SHA-256 of
+// `--redact-salt` and the whole name: the same name gets the same tag in
+// every scan with the same salt, and without a salt common names can be
+// guessed back from a dictionary.
+use sha2::{Digest, Sha256};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+use crate::report::MAX_EXT_LEN;
+
+/// Hex digits kept from the digest.
+const TAG_LEN: usize = 16;
+
+#[derive(Clone, Debug, Default)]
+pub struct Redactor {
+    salt: Vec<u8>,
+}
+
+impl Redactor {
+    pub fn new(salt: &str) -> Self {
+        Self {
+            salt: salt.as_bytes().to_vec(),
+        }
+    }
+
+    /// `name` as written: `<hash>.<ext>`, `<hash>` without a usable
+    /// extension, `.<hash>` for dot files.
+    pub fn name(&self, name: &OsStr) -> OsString {
+        let mut h = Sha256::new();
+        h.update(&self.salt);
+        h.update([0]);
+        h.update(name.as_encoded_bytes());
+        let mut out = OsString::with_capacity(TAG_LEN + 8);
+        if name.as_encoded_bytes().starts_with(b".") {
+            out.push(".");
+        }
+        let tag: String = h
+            .finalize()
+            .iter()
+            .take(TAG_LEN / 2)
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        out.push(tag);
+        if let Some(ext) = Path::new(name).extension().filter(|e| e.len() <= MAX_EXT_LEN) {
+            out.push(".");
+            out.push(ext);
+        }
+        out
+    }
+
+    /// `path` with its last component redacted.
+    pub fn path(&self, path: &Path) -> PathBuf {
+        match path.file_name() {
+            Some(name) => path.with_file_name(self.name(name)),
+            None => path.to_path_buf(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_hash_stably_and_keep_extension_and_dirs() {
+        let r = Redactor::new("s");
+        let a = r.path(Path::new("/home/alice/salary 2026.xlsx"));
+        assert_eq!(a.parent(), Some(Path::new("/home/alice")));
+        let name = a.file_name().unwrap().to_str().unwrap();
+        assert_eq!(name.len(), TAG_LEN + ".xlsx".len());
+        assert!(name.ends_with(".xlsx") && !name.contains("salary"));
+        assert_eq!(r.path(Path::new("/other/salary 2026.xlsx")).file_name(), a.file_name());
+        assert_ne!(Redactor::new("t").path(Path::new("/x/salary 2026.xlsx")).file_name(), a.file_name());
+
+        let dot = r.name(OsStr::new(".bash_history"));
+        assert!(dot.to_str().unwrap().starts_with('.'));
+        assert_eq!(dot.len(), 1 + TAG_LEN);
+        let long = r.name(OsStr::new("notes.a-very-long-extension"));
+        assert_eq!(long.len(), TAG_LEN);
+        assert_eq!(r.path(Path::new("/")), Path::new("/"));
+    }
+}
@@ -0,0 +1,569 @@
+// rs/src/bin/duscan/report.rs
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike};
+use serde::Serialize;
+
+use dutopia::util::exit::Outcome;
+use dutopia::util::human_bytes;
+
+use crate::longpath::LongPaths;
+use crate::overlap::Overlap;
+use crate::sample::SampleEstimate;
+
+/// Extensions longer than this are counted under `NO_EXT`; they are
+/// usually generated names rather than file types.
+pub const MAX_EXT_LEN: usize = 16;
+
+/// Bucket for files without a (usable) extension.
+pub const NO_EXT: &str = "(none)";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ExtStat {
+    pub count: u64,
+    /// Disk usage, like the DISK column
+    pub bytes: u64,
+}
+
+/// Files and bytes per lowercase extension, kept per worker and merged at
+/// the end of the scan.
+#[derive(Debug, Default)]
+pub struct ExtCounter {
+    map: HashMap<Vec<u8>, ExtStat>,
+    key: Vec<u8>,
+}
+
+impl ExtCounter {
+    pub fn add(&mut self, name: &OsStr, bytes: u64) {
+        self.key.clear();
+        match Path::new(name).extension().map(OsStr::as_encoded_bytes) {
+            Some(ext) if !ext.is_empty() && ext.len() <= MAX_EXT_LEN => {
+                self.key.extend(ext.iter().map(u8::to_ascii_lowercase))
+            }
+            _ => self.key.extend_from_slice(NO_EXT.as_bytes()),
+        }
+        if let Some(slot) = self.map.get_mut(self.key.as_slice()) {
+            slot.count += 1;
+            slot.bytes += bytes;
+        } else {
+            self.map.insert(self.key.clone(), ExtStat { count: 1, bytes });
+        }
+    }
+
+    pub fn merge(&mut self, other: ExtCounter) {
+        for (ext, s) in other.map {
+            let slot = self.map.entry(ext).or_default();
+            slot.count += s.count;
+            slot.bytes += s.bytes;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Extensions by bytes, largest first (ties by name).
+    pub fn sorted(&self) -> Vec<(String, ExtStat)> {
+        let mut out: Vec<(String, ExtStat)> = self
+            .map
+            .iter()
+            .map(|(k, v)| (String::from_utf8_lossy(k).into_owned(), *v))
+            .collect();
+        out.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(&b.0)));
+        out
+    }
+}
+
+/// Top `n` extensions for the console summary.
+pub fn print_top_extensions(exts: &ExtCounter, n: usize) {
+    if exts.is_empty() {
+        return;
+    }
+    println!("Top extensions by disk:");
+    for (ext, s) in exts.sorted().into_iter().take(n) {
+        println!("  {:<12} {:>10} {:>12} files", ext, human_bytes(s.bytes), s.count);
+    }
+}
+
+/// Rows and disk bytes per entry kind, kept per worker: `files` alone is
+/// one number for everything, which cannot be held against a filer's
+/// file and directory inode counts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct KindCounts {
+    pub files: ExtStat,
+    pub dirs: ExtStat,
+    pub symlinks: ExtStat,
+    /// Sockets, FIFOs and devices
+    pub special: ExtStat,
+}
+
+impl KindCounts {
+    /// Count a row by the type bits of its `mode`. A zero mode (platforms
+    /// without one) counts as a file.
+    pub fn record(&mut self, mode: u32, bytes: u64) {
+        let slot = match mode & 0o170000 {
+            0o040000 => &mut self.dirs,
+            0o120000 => &mut self.symlinks,
+            0o100000 | 0 => &mut self.files,
+            _ => &mut self.special,
+        };
+        slot.count += 1;
+        slot.bytes += bytes;
+    }
+
+    pub fn merge(&mut self, other: &KindCounts) {
+        for (a, b) in self.slots_mut().into_iter().zip(other.slots()) {
+            a.count += b.1.count;
+            a.bytes += b.1.bytes;
+        }
+    }
+
+    fn slots(&self) -> [(&'static str, ExtStat); 4] {
+        [
+            ("files", self.files),
+            ("dirs", self.dirs),
+            ("symlinks", self.symlinks),
+            ("special", self.special),
+        ]
+    }
+
+    fn slots_mut(&mut self) -> [&mut ExtStat; 4] {
+        [&mut self.files, &mut self.dirs, &mut self.symlinks, &mut self.special]
+    }
+}
+
+/// Rows by kind for the console summary.
+pub fn print_kinds(k: &KindCounts) {
+    println!("Entries by type:");
+    for (label, s) in k.slots() {
+        println!("  {:<12} {:>10} {:>12}", label, human_bytes(s.bytes), s.count);
+    }
+}
+
+/// Age bucket bounds in days, dusum's defaults (`--age 60,600`).
+const AGE_BOUNDS_DAYS: [i64; 2] = [60, 600];
+const AGE_LABELS: [&str; 3] = ["< 60 days", "60-600 days", ">= 600 days"];
+/// Years listed on the console; older ones are summed into one line.
+const CONSOLE_YEARS: usize = 10;
+
+/// Files and disk bytes by modification year and by dusum age bucket, kept
+/// per worker: a data-age profile of the scan without a `dusum` pass.
+#[derive(Debug, Default, Clone)]
+pub struct MtimeHistogram {
+    years: BTreeMap<i32, ExtStat>,
+    ages: [ExtStat; 3],
+}
+
+impl MtimeHistogram {
+    /// Ages are counted from `now`, the scan start. Unset (`<= 0`) mtimes
+    /// are old, as in dusum.
+    pub fn record(&mut self, mtime: i64, bytes: u64, now: i64) {
+        let year = DateTime::from_timestamp(mtime, 0).map_or(1970, |d| d.year());
+        let slot = self.years.entry(year).or_default();
+        slot.count += 1;
+        slot.bytes += bytes;
+        let age = if mtime <= 0 {
+            2
+        } else {
+            let days = now.saturating_sub(mtime) / 86_400;
+            AGE_BOUNDS_DAYS.iter().take_while(|&&b| days >= b).count()
+        };
+        self.ages[age].count += 1;
+        self.ages[age].bytes += bytes;
+    }
+
+    pub fn merge(&mut self, other: &MtimeHistogram) {
+        for (year, s) in &other.years {
+            let slot = self.years.entry(*year).or_default();
+            slot.count += s.count;
+            slot.bytes += s.bytes;
+        }
+        for (a, b) in self.ages.iter_mut().zip(other.ages) {
+            a.count += b.count;
+            a.bytes += b.bytes;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.years.is_empty()
+    }
+}
+
+/// Age buckets, then the most recent years, for the console summary.
+pub fn print_mtime_profile(h: &MtimeHistogram) {
+    if h.is_empty() {
+        return;
+    }
+    println!("Data age (mtime):");
+    for (label, s) in AGE_LABELS.iter().zip(h.ages) {
+        println!("  {:<12} {:>10} {:>12} files", label, human_bytes(s.bytes), s.count);
+    }
+    let mut earlier = ExtStat::default();
+    for (i, (year, s)) in h.years.iter().rev().enumerate() {
+        if i < CONSOLE_YEARS {
+            println!("  {:<12} {:>10} {:>12} files", year, human_bytes(s.bytes), s.count);
+        } else {
+            earlier.count += s.count;
+            earlier.bytes += s.bytes;
+        }
+    }
+    if earlier.count > 0 {
+        println!("  {:<12} {:>10} {:>12} files", "earlier", human_bytes(earlier.bytes), earlier.count);
+    }
+}
+
+/// Upper bounds of the stat latency buckets, in microseconds; the last
+/// bucket holds everything slower.
+const LATENCY_BOUNDS_US: [u64; 5] = [10, 100, 1_000, 10_000, 100_000];
+const LATENCY_LABELS: [&str; 6] = ["<10us", "<100us", "<1ms", "<10ms", "<100ms", ">=100ms"];
+
+/// Decade histogram of `lstat` times, enough to tell a slow NFS server
+/// (everything in the millisecond buckets) from a few slow directories.
+#[derive(Debug, Default, Clone)]
+pub struct LatencyHistogram {
+    buckets: [u64; 6],
+    max: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, d: Duration) {
+        let us = d.as_micros() as u64;
+        let i = LATENCY_BOUNDS_US.iter().take_while(|&&b| us >= b).count();
+        self.buckets[i] += 1;
+        self.max = self.max.max(d);
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (a, b) in self.buckets.iter_mut().zip(other.buckets) {
+            *a += b;
+        }
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Bucket label holding quantile `q` (0.0..=1.0), "-" when empty.
+    pub fn quantile(&self, q: f64) -> &'static str {
+        let n = self.count();
+        if n == 0 {
+            return "-";
+        }
+        let rank = ((n as f64 * q).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, c) in self.buckets.iter().enumerate() {
+            seen += c;
+            if seen >= rank {
+                return LATENCY_LABELS[i];
+            }
+        }
+        LATENCY_LABELS[5]
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+}
+
+/// Per-worker counters for the `-v` table.
+pub struct WorkerLine {
+    pub tid: usize,
+    pub dirs: u64,
+    pub files: u64,
+    pub bytes: u64,
+    pub errors: u64,
+    pub write_time: Duration,
+    pub stat_latency: LatencyHistogram,
+}
+
+/// One line per worker, so stragglers and slow `lstat`s stand out.
+pub fn print_worker_table(lines: &[WorkerLine]) {
+    println!(
+        "{:>6} {:>9} {:>10} {:>10} {:>7} {:>8} {:>8} {:>8} {:>9}",
+        "Worker", "Dirs", "Files", "Disk", "Errors", "Write", "Stat p50", "Stat p99", "Stat max"
+    );
+    for w in lines {
+        println!(
+            "{:>6} {:>9} {:>10} {:>10} {:>7} {:>7.2}s {:>8} {:>8} {:>7.1}ms",
+            w.tid,
+            w.dirs,
+            w.files,
+            human_bytes(w.bytes),
+            w.errors,
+            w.write_time.as_secs_f64(),
+            w.stat_latency.quantile(0.5),
+            w.stat_latency.quantile(0.99),
+            w.stat_latency.max().as_secs_f64() * 1000.0
+        );
+    }
+}
+
+/// Run summary for `--report` and the notifications.
+#[derive(Serialize, Default)]
+pub struct RunReport {
+    /// "ok", "partial" when `--time-limit` cut the walk short, or "failed"
+    /// with `error` set
+    pub status: &'static str,
+    pub host: String,
+    pub roots: Vec<String>,
+    pub output: String,
+    /// Rows written, all kinds
+    pub files: u64,
+    /// Directories walked, whether or not their rows were written
+    pub dirs: u64,
+    pub errors: u64,
+    pub bytes: u64,
+    /// Rows and disk bytes per entry kind
+    pub kinds: KindCounts,
+    /// Paths left out for their length
+    #[serde(skip_serializing_if = "LongPaths::is_empty")]
+    pub long_paths: LongPaths,
+    /// Directories not walked because `--time-limit` had passed
+    #[serde(skip_serializing_if = "is_zero")]
+    pub unvisited: u64,
+    pub elapsed_secs: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub extensions: Vec<ExtensionOut>,
+    /// Roots and directories skipped because another root covers them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub overlaps: Vec<Overlap>,
+    /// Extrapolated totals of a `--sample` run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample: Option<SampleEstimate>,
+    /// Files and bytes by mtime year and age bucket
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<MtimeOut>,
+}
+
+impl RunReport {
+    /// Summary of a run that stopped with `error`.
+    pub fn failed(roots: &[String], error: &anyhow::Error, elapsed_secs: f64) -> Self {
+        Self {
+            status: "failed",
+            host: dutopia::util::get_hostname(),
+            roots: roots.to_vec(),
+            elapsed_secs,
+            error: Some(format!("{error:#}")),
+            ..Self::default()
+        }
+    }
+
+    /// How the run ends for the exit code: partial, or done with or
+    /// without entries left out.
+    pub fn outcome(&self) -> Outcome {
+        if self.status == "partial" {
+            Outcome::Partial
+        } else {
+            Outcome::with_errors(self.errors + self.long_paths.count)
+        }
+    }
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+#[derive(Serialize)]
+pub struct YearOut {
+    pub year: i32,
+    pub count: u64,
+    pub bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct AgeOut {
+    /// dusum's age column: 0 recent, 1 not too old, 2 old
+    pub age: u8,
+    pub label: &'static str,
+    pub count: u64,
+    pub bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct MtimeOut {
+    /// Oldest year first
+    pub years: Vec<YearOut>,
+    pub ages: Vec<AgeOut>,
+}
+
+impl MtimeOut {
+    pub fn from_histogram(h: &MtimeHistogram) -> Self {
+        Self {
+            years: h
+                .years
+                .iter()
+                .map(|(&year, s)| YearOut {
+                    year,
+                    count: s.count,
+                    bytes: s.bytes,
+                })
+                .collect(),
+            ages: (0u8..)
+                .zip(AGE_LABELS)
+                .zip(h.ages)
+                .map(|((age, label), s)| AgeOut {
+                    age,
+                    label,
+                    count: s.count,
+                    bytes: s.bytes,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ExtensionOut {
+    pub ext: String,
+    pub count: u64,
+    pub bytes: u64,
+}
+
+impl ExtensionOut {
+    pub fn from_counter(exts: &ExtCounter) -> Vec<Self> {
+        exts.sorted()
+            .into_iter()
+            .map(|(ext, s)| Self {
+                ext,
+                count: s.count,
+                bytes: s.bytes,
+            })
+            .collect()
+    }
+}
+
+pub fn write_report_json(path: &Path, report: &RunReport) -> Result<()> {
+    let f = File::create(path).with_context(|| format!("creating {}", path.display()))?;
+    serde_json::to_writer_pretty(BufWriter::new(f), report)
+        .with_context(|| format!("writing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_by_lowercase_extension_and_merges() {
+        let mut a = ExtCounter::default();
+        a.add(OsStr::new("x.TXT"), 10);
+        a.add(OsStr::new("y.txt"), 5);
+        a.add(OsStr::new(".bashrc"), 1);
+        a.add(OsStr::new("Makefile"), 1);
+        a.add(OsStr::new("blob.0123456789abcdefXYZ"), 7);
+        let mut b = ExtCounter::default();
+        b.add(OsStr::new("z.tar.gz"), 100);
+        b.add(OsStr::new("w.txt"), 1);
+        a.merge(b);
+
+        let sorted = a.sorted();
+        assert_eq!(sorted[0], ("gz".to_string(), ExtStat { count: 1, bytes: 100 }));
+        assert_eq!(sorted[1], ("txt".to_string(), ExtStat { count: 3, bytes: 16 }));
+        assert_eq!(sorted[2], (NO_EXT.to_string(), ExtStat { count: 3, bytes: 9 }));
+        assert_eq!(sorted.len(), 3);
+    }
+
+    #[test]
+    fn latency_histogram_buckets_and_quantiles() {
+        let mut h = LatencyHistogram::default();
+        assert_eq!(h.quantile(0.5), "-");
+        for _ in 0..98 {
+            h.record(Duration::from_micros(5));
+        }
+        h.record(Duration::from_micros(2_500));
+        let mut other = LatencyHistogram::default();
+        other.record(Duration::from_millis(150));
+        h.merge(&other);
+        assert_eq!(h.count(), 100);
+        assert_eq!(h.quantile(0.5), "<10us");
+        assert_eq!(h.quantile(0.99), "<10ms");
+        assert_eq!(h.quantile(1.0), ">=100ms");
+        assert_eq!(h.max(), Duration::from_millis(150));
+    }
+
+    #[test]
+    fn counts_rows_by_kind() {
+        let mut k = KindCounts::default();
+        k.record(0o100644, 4096);
+        k.record(0, 512);
+        k.record(0o040755, 4096);
+        let mut other = KindCounts::default();
+        other.record(0o120777, 0);
+        other.record(0o010644, 0);
+        other.record(0o140755, 0);
+        other.record(0o040700, 512);
+        k.merge(&other);
+        assert_eq!(k.files, ExtStat { count: 2, bytes: 4608 });
+        assert_eq!(k.dirs, ExtStat { count: 2, bytes: 4608 });
+        assert_eq!(k.symlinks, ExtStat { count: 1, bytes: 0 });
+        assert_eq!(k.special, ExtStat { count: 2, bytes: 0 });
+        assert_eq!(serde_json::json!(k)["dirs"], serde_json::json!({ "count": 2, "bytes": 4608 }));
+    }
+
+    #[test]
+    fn mtime_histogram_by_year_and_age() {
+        let now = 1_767_225_600; // 2026-01-01
+        let day = 86_400;
+        let mut h = MtimeHistogram::default();
+        h.record(now - day, 10, now);
+        h.record(now - 100 * day, 20, now);
+        h.record(now - 700 * day, 40, now);
+        let mut other = MtimeHistogram::default();
+        other.record(0, 80, now);
+        other.record(now + day, 1, now);
+        h.merge(&other);
+
+        let out = MtimeOut::from_histogram(&h);
+        let years: Vec<(i32, u64, u64)> = out.years.iter().map(|y| (y.year, y.count, y.bytes)).collect();
+        assert_eq!(years, vec![(1970, 1, 80), (2024, 1, 40), (2025, 2, 30), (2026, 1, 1)]);
+        let ages: Vec<(u8, u64, u64)> = out.ages.iter().map(|a| (a.age, a.count, a.bytes)).collect();
+        assert_eq!(ages, vec![(0, 2, 11), (1, 1, 20), (2, 2, 120)]);
+    }
+
+    #[test]
+    fn json_report_lists_extensions() {
+        let mut exts = ExtCounter::default();
+        exts.add(OsStr::new("a.rs"), 4096);
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("run.json");
+        let report = RunReport {
+            status: "ok",
+            host: "node1".into(),
+            roots: vec!["/data".to_string()],
+            output: "data.csv".into(),
+            files: 1,
+            dirs: 0,
+            errors: 0,
+            bytes: 4096,
+            kinds: KindCounts::default(),
+            long_paths: LongPaths::default(),
+            unvisited: 0,
+            elapsed_secs: 0.5,
+            error: None,
+            extensions: ExtensionOut::from_counter(&exts),
+            overlaps: vec![],
+            sample: None,
+            mtime: None,
+        };
+        write_report_json(&path, &report).unwrap();
+        let v: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(v["files"], 1);
+        assert_eq!(v["extensions"][0]["ext"], "rs");
+        assert_eq!(v["extensions"][0]["bytes"], 4096);
+        assert!(v.get("unvisited").is_none());
+        assert_eq!(report.outcome(), Outcome::Ok);
+
+        let partial = RunReport { status: "partial", unvisited: 3, ..RunReport::default() };
+        assert_eq!(partial.outcome(), Outcome::Partial);
+        let lossy = RunReport { errors: 2, ..RunReport::default() };
+        assert_eq!(lossy.outcome().code(), 1);
+    }
+}
@@ -0,0 +1,189 @@
+// rs/src/bin/duscan/rotate.rs
+//
+// Dated outputs for unattended scans: `--output-template scan_%Y%m%d.zst`
+// names each run's output with the local start time (strftime), and
+// `--keep N` deletes all but the N newest outputs matching the template in
+// its directory once the new one is complete, together with their manifest,
+// DFS and `--since` sidecars (and `--split-by-user` folder). The newest
+// earlier output is also the default `--previous`, so progress still gets a
+// files hint.
+use anyhow::{bail, Context, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, TimeZone};
+use regex::Regex;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use dutopia::manifest::manifest_path;
+
+use crate::changes::changes_path;
+use crate::smb::dfs_sidecar_path;
+use crate::split::split_dir;
+
+pub struct Template {
+    raw: String,
+    /// Matches file names the template can produce
+    name_re: Regex,
+}
+
+impl Template {
+    pub fn parse(raw: &str) -> Result<Self> {
+        let path = Path::new(raw);
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .with_context(|| format!("--output-template {raw:?} has no file name"))?;
+        if path.parent().is_some_and(|d| d.to_string_lossy().contains('%')) {
+            bail!("--output-template may only use % fields in the file name, not the directory");
+        }
+        if StrftimeItems::new(raw).any(|i| matches!(i, Item::Error)) {
+            bail!("--output-template {raw:?} has an invalid % field");
+        }
+        if !name.contains('%') {
+            bail!("--output-template {raw:?} has no % field, every run would get the same name");
+        }
+        Ok(Self {
+            raw: raw.to_string(),
+            name_re: name_regex(name)?,
+        })
+    }
+
+    /// The output path for a run started at `at`.
+    pub fn render<Tz: TimeZone>(&self, at: &DateTime<Tz>) -> PathBuf
+    where
+        Tz::Offset: Display,
+    {
+        PathBuf::from(at.format(&self.raw).to_string())
+    }
+
+    pub fn matches(&self, file_name: &str) -> bool {
+        self.name_re.is_match(file_name)
+    }
+
+    /// Files in `dir` the template could have produced, newest first.
+    pub fn outputs(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut found: Vec<(SystemTime, PathBuf)> = Vec::new();
+        for entry in fs::read_dir(dir).with_context(|| format!("listing {}", dir.display()))? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else { continue };
+            if !self.matches(name) || !entry.file_type()?.is_file() {
+                continue;
+            }
+            let mtime = entry.metadata()?.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            found.push((mtime, entry.path()));
+        }
+        found.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.cmp(&a.1)));
+        Ok(found.into_iter().map(|(_, p)| p).collect())
+    }
+}
+
+/// Regex for the file names a strftime pattern yields: numeric fields match
+/// digits, name fields letters, anything else one or more characters.
+fn name_regex(name: &str) -> Result<Regex> {
+    let mut re = String::from("^");
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4])));
+            continue;
+        }
+        let mut spec = chars.next().unwrap_or('%');
+        // Padding modifiers: %-d, %_d, %0d
+        if matches!(spec, '-' | '_' | '0') {
+            spec = chars.next().unwrap_or('%');
+        }
+        re.push_str(match spec {
+            '%' => "%",
+            'Y' | 'C' | 'y' | 'm' | 'd' | 'e' | 'H' | 'I' | 'k' | 'l' | 'M' | 'S' | 'j' | 'U'
+            | 'W' | 'V' | 'G' | 'g' | 'u' | 'w' | 's' | 'f' => "[ 0-9]+",
+            'F' => "[0-9]+-[0-9]+-[0-9]+",
+            'T' | 'X' => "[0-9]+:[0-9]+:[0-9]+",
+            'R' => "[0-9]+:[0-9]+",
+            'a' | 'A' | 'b' | 'B' | 'h' | 'p' | 'P' | 'Z' => "[A-Za-z]+",
+            _ => ".+",
+        });
+    }
+    re.push('$');
+    Regex::new(&re).context("compiling --output-template matcher")
+}
+
+/// Delete all but the `keep` newest outputs of `template` beside `current`
+/// (which always stays), with their sidecars and `--split-by-user` files.
+/// Returns the deleted outputs.
+pub fn prune(template: &Template, current: &Path, keep: usize) -> Result<Vec<PathBuf>> {
+    let dir = current.parent().unwrap_or(Path::new("."));
+    let mut kept = 1;
+    let mut removed = Vec::new();
+    for old in template.outputs(dir)? {
+        if old == current {
+            continue;
+        }
+        if kept < keep {
+            kept += 1;
+            continue;
+        }
+        fs::remove_file(&old).with_context(|| format!("removing {}", old.display()))?;
+        for side in [manifest_path(&old), dfs_sidecar_path(&old), changes_path(&old)] {
+            if side.is_file() {
+                fs::remove_file(&side).with_context(|| format!("removing {}", side.display()))?;
+            }
+        }
+        let users = split_dir(&old);
+        if users.is_dir() {
+            fs::remove_dir_all(&users).with_context(|| format!("removing {}", users.display()))?;
+        }
+        removed.push(old);
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Local, TimeZone};
+    use std::time::Duration;
+
+    #[test]
+    fn template_renders_matches_and_validates() {
+        let t = Template::parse("/data/scan_%Y%m%d.zst").unwrap();
+        let at = Local.with_ymd_and_hms(2026, 10, 16, 2, 0, 0).unwrap();
+        assert_eq!(t.render(&at), PathBuf::from("/data/scan_20261016.zst"));
+        assert!(t.matches("scan_20261009.zst"));
+        assert!(!t.matches("scan_20261009.zst.meta.json"));
+        assert!(!t.matches("scan_latest.zst"));
+        assert!(!t.matches("xscan_20261009.zst"));
+
+        assert!(Template::parse("scan.zst").is_err());
+        assert!(Template::parse("/data/%Y/scan_%m.zst").is_err());
+        assert!(Template::parse("scan_%Q.zst").is_err());
+        assert!(Template::parse("scan_%F_%a.csv").unwrap().matches("scan_2026-10-16_Fri.csv"));
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_and_removes_sidecars() {
+        let tmp = tempfile::tempdir().unwrap();
+        let t = Template::parse(tmp.path().join("scan_%Y%m%d.csv").to_str().unwrap()).unwrap();
+        let base = SystemTime::now() - Duration::from_secs(3600);
+        let mut outputs = Vec::new();
+        for (i, day) in ["01", "02", "03", "04"].iter().enumerate() {
+            let p = tmp.path().join(format!("scan_202610{day}.csv"));
+            fs::write(&p, "x").unwrap();
+            let f = fs::File::options().write(true).open(&p).unwrap();
+            f.set_modified(base + Duration::from_secs(i as u64 * 60)).unwrap();
+            fs::write(manifest_path(&p), "{}").unwrap();
+            outputs.push(p);
+        }
+        fs::write(tmp.path().join("other.csv"), "x").unwrap();
+
+        let current = &outputs[3];
+        let removed = prune(&t, current, 2).unwrap();
+        assert_eq!(removed, vec![outputs[1].clone(), outputs[0].clone()]);
+        assert!(!manifest_path(&outputs[0]).exists());
+        assert!(outputs[2].exists() && current.exists());
+        assert!(tmp.path().join("other.csv").exists());
+        assert_eq!(t.outputs(tmp.path()).unwrap(), vec![current.clone(), outputs[2].clone()]);
+    }
+}
@@ -1,4 +1,14 @@
 // rs/src/bin/duscan/row.rs
+//
+// A `Row` from an entry's metadata. On Windows the metadata has no block
+// count, so `blocks` is the size rounded up to 512 bytes; `row_for_path`
+// asks NTFS for the allocation instead (`GetCompressedFileSizeW`), which is
+// smaller than the size for compressed and sparse files. It also fills
+// `dev`/`ino` from the volume serial number and file index of an open handle
+// (`GetFileInformationByHandle`), so hard links share an INODE and dusum
+// counts them once, as on Unix. Without a handle they stay `0-0`. The UID
+// is the last subauthority (RID) of the owner SID, which dusum turns back
+// into an account name; 0 when the owner cannot be read.
 use std::fs;
 use std::path::Path;
 use dutopia::util::Row;
@@ -14,7 +24,7 @@ pub fn row_from_metadata(md: &fs::Metadata) -> Row {
             uid: md.uid(),
             gid: md.gid(),
             size: md.size(),
-            blocks: md.blocks() as u64,
+            blocks: md.blocks(),
             atime: md.atime(),
             mtime: md.mtime(),
         }
@@ -71,9 +81,97 @@ pub fn row_from_metadata(md: &fs::Metadata) -> Row {
     }
 }
 
+/// `row_from_metadata` for the entry at `path`, with the allocation the
+/// filesystem reports on Windows.
+#[cfg_attr(not(windows), allow(unused_variables))]
+pub fn row_for_path(path: &Path, md: &fs::Metadata) -> Row {
+    #[allow(unused_mut)]
+    let mut row = row_from_metadata(md);
+    #[cfg(windows)]
+    {
+        if let Some(bytes) = allocated_bytes(path, md) {
+            row.blocks = bytes.div_ceil(512);
+        }
+        if let Some((dev, ino)) = file_id(path) {
+            row.dev = dev;
+            row.ino = ino;
+        }
+        row.uid = dutopia::util::get_rid(path).unwrap_or(0);
+    }
+    row
+}
+
+#[cfg(windows)]
+fn wide_path(path: &Path) -> Vec<u16> {
+    use std::os::windows::ffi::OsStrExt;
+    path.as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect()
+}
+
+/// Volume serial number and file index of the entry at `path`, the link
+/// itself for a symlink or junction. Opening for no access with
+/// FILE_FLAG_BACKUP_SEMANTICS works on directories and on files opened
+/// exclusively by another process.
+#[cfg(windows)]
+fn file_id(path: &Path) -> Option<(u64, u64)> {
+    use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::Storage::FileSystem::{
+        BY_HANDLE_FILE_INFORMATION, CreateFileW, FILE_FLAG_BACKUP_SEMANTICS,
+        FILE_FLAG_OPEN_REPARSE_POINT, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE,
+        GetFileInformationByHandle, OPEN_EXISTING,
+    };
+
+    let wide = wide_path(path);
+    let handle = unsafe {
+        CreateFileW(
+            wide.as_ptr(),
+            0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            std::ptr::null(),
+            OPEN_EXISTING,
+            FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT,
+            std::ptr::null_mut(),
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        return None;
+    }
+    let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+    let ok = unsafe { GetFileInformationByHandle(handle, &mut info) };
+    unsafe { CloseHandle(handle) };
+    if ok == 0 {
+        return None;
+    }
+    let ino = ((info.nFileIndexHigh as u64) << 32) | info.nFileIndexLow as u64;
+    Some((info.dwVolumeSerialNumber as u64, ino))
+}
+
+/// Bytes NTFS allocates to the file at `path`: the compressed size of a
+/// compressed file, the allocated ranges of a sparse one, the size
+/// otherwise. `None` for directories and links, or if the call fails.
+#[cfg(windows)]
+fn allocated_bytes(path: &Path, md: &fs::Metadata) -> Option<u64> {
+    use windows_sys::Win32::Foundation::{GetLastError, NO_ERROR};
+    use windows_sys::Win32::Storage::FileSystem::{GetCompressedFileSizeW, INVALID_FILE_SIZE};
+
+    if !md.is_file() {
+        return None;
+    }
+    let wide = wide_path(path);
+    let mut high: u32 = 0;
+    let low = unsafe { GetCompressedFileSizeW(wide.as_ptr(), &mut high) };
+    // INVALID_FILE_SIZE is also a valid low word; only the last error tells.
+    if low == INVALID_FILE_SIZE && unsafe { GetLastError() } != NO_ERROR {
+        return None;
+    }
+    Some(((high as u64) << 32) | low as u64)
+}
+
 pub fn stat_row(path: &Path) -> Option<Row> {
     let md = fs::symlink_metadata(path).ok()?;
-    Some(row_from_metadata(&md))
+    Some(row_for_path(path, &md))
 }
 
 #[cfg(test)]
@@ -122,6 +220,21 @@ mod tests {
         assert_eq!(row.unwrap().size, 12);
     }
 
+    #[cfg(windows)]
+    #[test]
+    fn test_hard_links_share_an_inode() {
+        let tmp = tempdir().unwrap();
+        let a = tmp.path().join("a.txt");
+        let b = tmp.path().join("b.txt");
+        fs::write(&a, "test content").unwrap();
+        fs::hard_link(&a, &b).unwrap();
+
+        let (ra, rb) = (stat_row(&a).unwrap(), stat_row(&b).unwrap());
+        assert!(ra.ino != 0);
+        assert_eq!((ra.dev, ra.ino), (rb.dev, rb.ino));
+        assert_ne!(stat_row(tmp.path()).unwrap().ino, ra.ino);
+    }
+
     #[test]
     fn test_stat_row_failure() {
         let nonexistent = Path::new("/nonexistent/path/that/does/not/exist");
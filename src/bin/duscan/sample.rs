@@ -0,0 +1,324 @@
+// rs/src/bin/duscan/sample.rs
+//
+// `--sample 5%`: a quick estimate instead of a full walk. The directories
+// `--sample-depth` levels above the sampling level (by default the roots)
+// are strata. For each of them the walk enters only a fixed share of its
+// subdirectories, ranked by a hash of their path so the same tree always
+// gives the same subset, and at least one. Sampled subdirectories are
+// walked in full; everything above them is scanned as usual.
+//
+// Totals are then estimated per stratum as dirs / sampled times what the
+// sampled subdirectories held, with a 95% normal-approximation interval from
+// the spread between them (stratified cluster sampling without
+// replacement). A stratum where only one subdirectory was sampled adds no
+// spread, so the interval is optimistic when strata are small.
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use dutopia::manifest::{SampleInfo, SampleStratum};
+use dutopia::util::{human_bytes, human_count, should_skip, PathFilter};
+
+/// z for a two-sided 95% interval
+const Z95: f64 = 1.96;
+
+/// `5%` or `0.05`.
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    let rate = match s.strip_suffix('%') {
+        Some(p) => p.trim().parse::<f64>().map(|v| v / 100.0),
+        None => s.parse::<f64>(),
+    }
+    .map_err(|_| format!("'{s}' is not a rate like 5% or 0.05"))?;
+    if !(rate > 0.0 && rate <= 1.0) {
+        return Err(format!("'{s}' must be above 0 and at most 100%"));
+    }
+    Ok(rate)
+}
+
+/// Files and disk bytes below one sampled subdirectory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tally {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default)]
+struct Stratum {
+    dirs: u64,
+    sampled: Vec<OsString>,
+}
+
+pub struct Sampler {
+    rate: f64,
+    depth: usize,
+    roots: Vec<PathBuf>,
+    strata: Mutex<HashMap<PathBuf, Stratum>>,
+}
+
+/// An estimated total and its 95% interval.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct Bound {
+    pub estimate: u64,
+    pub low: u64,
+    pub high: u64,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SampleEstimate {
+    pub rate: f64,
+    pub depth: usize,
+    /// Subdirectories at the sampling level, and how many were walked
+    pub dirs: u64,
+    pub sampled_dirs: u64,
+    pub files: Bound,
+    pub bytes: Bound,
+}
+
+impl Sampler {
+    pub fn new(rate: f64, depth: usize, roots: &[PathBuf]) -> Self {
+        Self {
+            rate,
+            depth: depth.max(1),
+            roots: roots.to_vec(),
+            strata: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Levels `path` lies below its root, and that root.
+    fn level<'a>(&'a self, path: &Path) -> Option<(&'a Path, usize)> {
+        self.roots
+            .iter()
+            .filter_map(|r| Some((r.as_path(), path.strip_prefix(r).ok()?.components().count())))
+            .min_by_key(|(_, n)| *n)
+    }
+
+    /// Whether the walk enters `dir`. A stratum directory has its
+    /// subdirectories ranked here, before any of them is queued.
+    pub fn visit(&self, dir: &Path, filter: &PathFilter) -> bool {
+        let Some((_, level)) = self.level(dir) else {
+            return true;
+        };
+        if level + 1 == self.depth {
+            self.choose(dir, filter);
+            return true;
+        }
+        if level != self.depth {
+            return true;
+        }
+        let (Some(parent), Some(name)) = (dir.parent(), dir.file_name()) else {
+            return true;
+        };
+        let strata = self.strata.lock().unwrap_or_else(|e| e.into_inner());
+        strata
+            .get(parent)
+            .is_none_or(|s| s.sampled.iter().any(|n| n == name))
+    }
+
+    fn choose(&self, dir: &Path, filter: &PathFilter) {
+        let Ok(rd) = fs::read_dir(dir) else { return };
+        let mut ranked: Vec<([u8; 32], OsString)> = rd
+            .filter_map(Result::ok)
+            .filter(|d| d.file_type().is_ok_and(|t| t.is_dir()) && !should_skip(&d.path(), filter))
+            .map(|d| {
+                (
+                    Sha256::digest(d.path().as_os_str().as_encoded_bytes()).into(),
+                    d.file_name(),
+                )
+            })
+            .collect();
+        ranked.sort();
+        let dirs = ranked.len() as u64;
+        let k = ((self.rate * dirs as f64).ceil() as usize).clamp(1, ranked.len().max(1));
+        let sampled = ranked.into_iter().take(k).map(|(_, n)| n).collect();
+        let mut strata = self.strata.lock().unwrap_or_else(|e| e.into_inner());
+        strata.insert(dir.to_path_buf(), Stratum { dirs, sampled });
+    }
+
+    /// The sampled subdirectory `path` lies in, if any.
+    pub fn cluster(&self, path: &Path) -> Option<PathBuf> {
+        let (root, level) = self.level(path)?;
+        if level < self.depth {
+            return None;
+        }
+        let rel: PathBuf = path
+            .strip_prefix(root)
+            .ok()?
+            .components()
+            .take(self.depth)
+            .collect();
+        Some(root.join(rel))
+    }
+
+    /// Estimates from the scanned totals and the per-cluster tallies.
+    pub fn estimate(
+        &self,
+        files: u64,
+        bytes: u64,
+        clusters: &HashMap<PathBuf, Tally>,
+    ) -> SampleEstimate {
+        let strata = self.strata.lock().unwrap_or_else(|e| e.into_inner());
+        let in_clusters = clusters.values().fold(Tally::default(), |a, t| Tally {
+            files: a.files + t.files,
+            bytes: a.bytes + t.bytes,
+        });
+        let mut est = SampleEstimate {
+            rate: self.rate,
+            depth: self.depth,
+            ..Default::default()
+        };
+        let mut f = Extrapolation::new(files.saturating_sub(in_clusters.files));
+        let mut b = Extrapolation::new(bytes.saturating_sub(in_clusters.bytes));
+        for (dir, s) in strata.iter() {
+            est.dirs += s.dirs;
+            est.sampled_dirs += s.sampled.len() as u64;
+            let ys: Vec<Tally> = s
+                .sampled
+                .iter()
+                .map(|n| clusters.get(&dir.join(n)).copied().unwrap_or_default())
+                .collect();
+            f.add(s.dirs, ys.iter().map(|t| t.files));
+            b.add(s.dirs, ys.iter().map(|t| t.bytes));
+        }
+        est.files = f.bound(files);
+        est.bytes = b.bound(bytes);
+        est
+    }
+
+    /// Strata for the manifest, with paths as `shown` writes them.
+    pub fn info(&self, shown: impl Fn(&Path) -> String) -> SampleInfo {
+        let strata = self.strata.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out: Vec<SampleStratum> = strata
+            .iter()
+            .map(|(dir, s)| SampleStratum {
+                path: shown(dir),
+                dirs: s.dirs,
+                sampled: s
+                    .sampled
+                    .iter()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .collect(),
+            })
+            .collect();
+        out.sort_by(|a, b| a.path.cmp(&b.path));
+        SampleInfo {
+            rate: self.rate,
+            depth: self.depth,
+            strata: out,
+        }
+    }
+}
+
+pub fn print_estimate(e: &SampleEstimate) {
+    println!(
+        "Est. files   : {} ({} - {}), {} of {} dirs sampled",
+        human_count(e.files.estimate),
+        human_count(e.files.low),
+        human_count(e.files.high),
+        e.sampled_dirs,
+        e.dirs
+    );
+    println!(
+        "Est. disk    : {} ({} - {})",
+        human_bytes(e.bytes.estimate),
+        human_bytes(e.bytes.low),
+        human_bytes(e.bytes.high)
+    );
+}
+
+/// Stratified estimate of one total.
+struct Extrapolation {
+    total: f64,
+    variance: f64,
+}
+
+impl Extrapolation {
+    fn new(fixed: u64) -> Self {
+        Self {
+            total: fixed as f64,
+            variance: 0.0,
+        }
+    }
+
+    fn add(&mut self, dirs: u64, ys: impl Iterator<Item = u64>) {
+        let ys: Vec<f64> = ys.map(|y| y as f64).collect();
+        let n = ys.len() as f64;
+        if n == 0.0 {
+            return;
+        }
+        let big_n = dirs as f64;
+        let mean = ys.iter().sum::<f64>() / n;
+        self.total += big_n * mean;
+        if ys.len() > 1 {
+            let s2 = ys.iter().map(|y| (y - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            self.variance += big_n * big_n * (1.0 - n / big_n) * s2 / n;
+        }
+    }
+
+    /// The interval never goes below what was actually counted.
+    fn bound(&self, counted: u64) -> Bound {
+        let half = Z95 * self.variance.sqrt();
+        Bound {
+            estimate: self.total.round() as u64,
+            low: ((self.total - half).round().max(0.0) as u64).max(counted),
+            high: (self.total + half).round() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rate_accepts_percent_and_fraction() {
+        assert_eq!(parse_rate("5%"), Ok(0.05));
+        assert_eq!(parse_rate("0.25"), Ok(0.25));
+        assert_eq!(parse_rate("100%"), Ok(1.0));
+        assert!(parse_rate("0%").is_err());
+        assert!(parse_rate("150%").is_err());
+        assert!(parse_rate("lots").is_err());
+    }
+
+    #[test]
+    fn samples_subdirs_and_extrapolates() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().to_path_buf();
+        for i in 0..10 {
+            fs::create_dir(root.join(format!("d{i}"))).unwrap();
+        }
+        let s = Sampler::new(0.2, 1, std::slice::from_ref(&root));
+        assert!(s.visit(&root, &PathFilter::default()));
+        let walked: Vec<PathBuf> = (0..10)
+            .map(|i| root.join(format!("d{i}")))
+            .filter(|d| s.visit(d, &PathFilter::default()))
+            .collect();
+        assert_eq!(walked.len(), 2);
+        // Deterministic: a second sampler picks the same two.
+        let again = Sampler::new(0.2, 1, std::slice::from_ref(&root));
+        again.visit(&root, &PathFilter::default());
+        assert!(walked.iter().all(|d| again.visit(d, &PathFilter::default())));
+        assert!(s.visit(&walked[0].join("deeper"), &PathFilter::default()));
+        assert_eq!(s.cluster(&walked[0].join("a/b")), Some(walked[0].clone()));
+        assert_eq!(s.cluster(&root), None);
+
+        // 3 files at the root, 10 and 30 in the two sampled dirs.
+        let clusters: HashMap<PathBuf, Tally> = [(10, 100), (30, 300)]
+            .iter()
+            .zip(&walked)
+            .map(|(&(files, bytes), d)| (d.clone(), Tally { files, bytes }))
+            .collect();
+        let e = s.estimate(43, 400, &clusters);
+        assert_eq!((e.dirs, e.sampled_dirs), (10, 2));
+        assert_eq!(e.files.estimate, 3 + 200);
+        assert!(e.files.low >= 43 && e.files.high > 203);
+        assert_eq!(e.bytes.estimate, 2000);
+
+        let info = s.info(|p| p.display().to_string());
+        assert_eq!(info.strata[0].weight(), 5.0);
+    }
+}
@@ -0,0 +1,147 @@
+// rs/src/bin/duscan/scale.rs
+//
+// `--min-workers N --max-workers M`: an adaptive pool instead of a fixed
+// `--workers`. All M worker threads are started (each owns a shard file),
+// but only the first `active` take tasks; the others park between tasks.
+// Every `TICK` the controller looks at the queued tasks and at the share of
+// the last tick the active workers spent idle, waiting for a task:
+//   - a queue longer than the pool with workers hardly idle (they are all
+//     blocked on metadata calls) adds a quarter more workers, up to M;
+//   - workers idle for more than half the tick (the filesystem answers
+//     faster than the queue fills) park a quarter of them, down to N.
+// The scan starts with N workers. When the queue drains for good every
+// worker is woken so that all of them see their shutdown task.
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam::channel::Receiver;
+
+use crate::worker::Task;
+
+/// How often the pool size is reconsidered.
+const TICK: Duration = Duration::from_secs(2);
+/// How often a parked worker looks again.
+const PARK_SLEEP: Duration = Duration::from_millis(100);
+/// Idle share below which a long queue grows the pool.
+const GROW_IDLE: f64 = 0.05;
+/// Idle share above which the pool shrinks.
+const SHRINK_IDLE: f64 = 0.5;
+/// `active` after `release`: no worker parks any more.
+const RELEASED: usize = usize::MAX;
+
+#[derive(Debug)]
+pub struct Scaler {
+    min: usize,
+    max: usize,
+    /// Workers taking tasks; `RELEASED` once the scan is ending
+    active: AtomicUsize,
+    /// Time the workers spent waiting on the queue, in microseconds
+    idle_us: AtomicU64,
+}
+
+/// Pool size after a tick with `queued` tasks waiting and the active
+/// workers idle for the `idle` share of it.
+pub fn next_active(active: usize, min: usize, max: usize, queued: usize, idle: f64) -> usize {
+    let step = (active / 4).max(1);
+    if idle < GROW_IDLE && queued > active {
+        (active + step).min(max)
+    } else if idle > SHRINK_IDLE {
+        active.saturating_sub(step).max(min)
+    } else {
+        active
+    }
+}
+
+impl Scaler {
+    pub fn new(min: usize, max: usize) -> Self {
+        Self {
+            min,
+            max,
+            active: AtomicUsize::new(min),
+            idle_us: AtomicU64::new(0),
+        }
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Relaxed)
+    }
+
+    /// Called by worker `tid` between tasks: blocks while it is parked.
+    /// `stop` is polled meanwhile; true from it returns false at once.
+    pub fn wait_turn(&self, tid: usize, stop: impl Fn() -> bool) -> bool {
+        while tid >= self.active() {
+            if stop() {
+                return false;
+            }
+            thread::sleep(PARK_SLEEP);
+        }
+        true
+    }
+
+    /// Time a worker spent waiting for its last task.
+    pub fn add_idle(&self, waited: Duration) {
+        self.idle_us.fetch_add(waited.as_micros() as u64, Relaxed);
+    }
+
+    /// Wake every worker for good: the scan is ending.
+    pub fn release(&self) {
+        self.active.store(RELEASED, Relaxed);
+    }
+
+    /// Resize the pool every `TICK` from the depth of `rx` until `release`.
+    pub fn start(self: &Arc<Self>, rx: Receiver<Task>) {
+        let scaler = self.clone();
+        thread::spawn(move || scaler.control(rx));
+    }
+
+    fn control(&self, rx: Receiver<Task>) {
+        let mut last = Instant::now();
+        loop {
+            thread::sleep(TICK);
+            let active = self.active();
+            if active == RELEASED {
+                return;
+            }
+            let spent = last.elapsed().as_micros() as f64 * active as f64;
+            last = Instant::now();
+            let idle = self.idle_us.swap(0, Relaxed) as f64 / spent.max(1.0);
+            let next = next_active(active, self.min, self.max, rx.len(), idle);
+            if next != active {
+                // Fails when released in between.
+                if self.active.compare_exchange(active, next, Relaxed, Relaxed).is_ok() {
+                    tracing::debug!(from = active, to = next, queued = rx.len(), idle, "worker pool resized");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grows_on_a_busy_queue_and_parks_idle_workers() {
+        // Busy workers and a long queue: a quarter more, capped at max.
+        assert_eq!(next_active(4, 4, 48, 100, 0.0), 5);
+        assert_eq!(next_active(40, 4, 48, 100, 0.01), 48);
+        // A short queue or some idling keeps the pool as is.
+        assert_eq!(next_active(8, 4, 48, 8, 0.0), 8);
+        assert_eq!(next_active(8, 4, 48, 100, 0.2), 8);
+        // Mostly idle: a quarter fewer, not below min.
+        assert_eq!(next_active(16, 4, 48, 0, 0.9), 12);
+        assert_eq!(next_active(5, 4, 48, 0, 0.9), 4);
+
+        let s = Scaler::new(2, 6);
+        assert!(s.wait_turn(1, || unreachable!()));
+        assert!(!s.wait_turn(2, || true));
+        s.release();
+        assert!(s.wait_turn(5, || unreachable!()));
+    }
+}
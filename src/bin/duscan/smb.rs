@@ -0,0 +1,332 @@
+// rs/src/bin/duscan/smb.rs
+//
+// UNC / DFS support for Windows scans. `\\server\share` roots can be opened
+// with explicit credentials (`--smb-user` / `--smb-pass-env`), and when a
+// root is a DFS namespace every link the walk enters is resolved to the
+// server actually holding the data. CSV rows get that server in a
+// `DFS_TARGET` column, and the links are listed next to the output as
+// `<output>.dfs.csv` (`path,target`) for every format.
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+/// Extra CSV column with the `\\server\share` a row's data lives on.
+pub const COLUMN: &str = "DFS_TARGET";
+
+/// `\\server\share` of a UNC path, accepting the `\\?\UNC\` verbatim form
+/// that canonicalize produces. `None` for anything else.
+pub fn unc_share(path: &Path) -> Option<String> {
+    let s = path.to_string_lossy();
+    let rest = s
+        .strip_prefix(r"\\?\UNC\")
+        .or_else(|| s.strip_prefix(r"\\"))
+        .filter(|r| !r.starts_with('?'))?;
+    let mut parts = rest.split('\\').filter(|p| !p.is_empty());
+    let server = parts.next()?;
+    let share = parts.next()?;
+    Some(format!(r"\\{server}\{share}"))
+}
+
+/// DFS link -> active `\\server\share`, collected by the workers.
+#[derive(Default)]
+pub struct DfsMap {
+    links: RwLock<BTreeMap<String, String>>,
+    /// Shares whose lookup failed: plain shares, not DFS namespaces, so
+    /// nothing below them is looked up again.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    plain: Mutex<HashSet<String>>,
+}
+
+impl DfsMap {
+    /// True when `dir` lies under a link that is already resolved. DFS links
+    /// cannot nest, so nothing below a link needs another lookup.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    fn covers(&self, dir: &str) -> bool {
+        let links = self.links.read().unwrap();
+        links.keys().any(|l| {
+            dir.strip_prefix(l.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('\\'))
+                && unc_share(Path::new(l)).as_deref() != Some(l.as_str())
+        })
+    }
+
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn record(&self, entry: String, target: String) {
+        self.links.write().unwrap().entry(entry).or_insert(target);
+    }
+
+    pub fn len(&self) -> usize {
+        self.links.read().unwrap().len()
+    }
+
+    /// Resolve `dir` unless a known link already covers it or its share is
+    /// known not to be DFS. No-op off Windows.
+    pub fn observe(&self, dir: &Path) {
+        #[cfg(windows)]
+        self.observe_with(
+            &dutopia::util::strip_verbatim_prefix(dir).to_string_lossy(),
+            win::resolve_dfs,
+        );
+        #[cfg(not(windows))]
+        let _ = dir;
+    }
+
+    /// `observe` with the lookup passed in. Every directory of a namespace
+    /// resolves to at least its root entry, so a failed lookup marks the
+    /// whole share as plain.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    fn observe_with(&self, dir: &str, resolve: impl Fn(&str) -> Option<(String, String)>) {
+        let Some(share) = unc_share(Path::new(dir)) else {
+            return;
+        };
+        if self.plain.lock().unwrap().contains(&share) || self.covers(dir) {
+            return;
+        }
+        match resolve(dir) {
+            Some((entry, target)) => self.record(entry, target),
+            None => {
+                self.plain.lock().unwrap().insert(share);
+            }
+        }
+    }
+
+    /// Target of the longest resolved entry containing `path`, for the
+    /// `DFS_TARGET` column. Empty outside DFS namespaces.
+    pub fn target(&self, path: &Path) -> String {
+        let path = dutopia::util::strip_verbatim_prefix(path);
+        let path = path.to_string_lossy();
+        let links = self.links.read().unwrap();
+        let mut prefix: &str = &path;
+        loop {
+            if let Some(t) = links.get(prefix) {
+                return t.clone();
+            }
+            match prefix.rfind('\\') {
+                Some(i) if i > 1 => prefix = &prefix[..i],
+                _ => return String::new(),
+            }
+        }
+    }
+
+    pub fn write_csv(&self, path: &Path) -> Result<()> {
+        let f = File::create(path).with_context(|| format!("create {}", path.display()))?;
+        let mut w = BufWriter::new(f);
+        writeln!(w, "path,target")?;
+        for (entry, target) in self.links.read().unwrap().iter() {
+            writeln!(w, "{},{}", csv_field(entry), csv_field(target))?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// `<output>.dfs.csv` next to the scan output.
+pub fn dfs_sidecar_path(output: &Path) -> PathBuf {
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(".dfs.csv");
+    output.with_file_name(name)
+}
+
+/// Open every distinct share among `roots` with the given credentials. The
+/// connections are closed when the returned guards drop.
+#[cfg(windows)]
+pub fn connect_shares(
+    roots: &[PathBuf],
+    user: &str,
+    password: &str,
+) -> Result<Vec<win::SmbConnection>> {
+    let mut shares: Vec<String> = roots.iter().filter_map(|r| unc_share(r)).collect();
+    shares.sort();
+    shares.dedup();
+    if shares.is_empty() {
+        anyhow::bail!("--smb-user given but no root is a \\\\server\\share path");
+    }
+    shares
+        .iter()
+        .map(|s| win::SmbConnection::connect(s, user, password))
+        .collect()
+}
+
+#[cfg(windows)]
+pub mod win {
+    use anyhow::Result;
+    use std::ptr;
+    use windows_sys::Win32::Foundation::{FALSE, NO_ERROR};
+    use windows_sys::Win32::NetworkManagement::NetManagement::NetApiBufferFree;
+    use windows_sys::Win32::NetworkManagement::WNet::{
+        WNetAddConnection2W, WNetCancelConnection2W, NETRESOURCEW, RESOURCETYPE_DISK,
+    };
+    use windows_sys::Win32::Storage::DistributedFileSystem::{
+        NetDfsGetClientInfo, DFS_INFO_3, DFS_STORAGE_INFO, DFS_STORAGE_STATE_ACTIVE,
+    };
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    unsafe fn from_wide(p: *const u16) -> String {
+        if p.is_null() {
+            return String::new();
+        }
+        let mut len = 0;
+        unsafe {
+            while *p.add(len) != 0 {
+                len += 1;
+            }
+            String::from_utf16_lossy(std::slice::from_raw_parts(p, len))
+        }
+    }
+
+    /// An authenticated connection to `\\server\share` without a drive letter.
+    pub struct SmbConnection {
+        remote: Vec<u16>,
+    }
+
+    impl SmbConnection {
+        pub fn connect(share: &str, user: &str, password: &str) -> Result<Self> {
+            let mut remote = wide(share);
+            let user_w = wide(user);
+            let pass_w = wide(password);
+            let res = NETRESOURCEW {
+                dwScope: 0,
+                dwType: RESOURCETYPE_DISK,
+                dwDisplayType: 0,
+                dwUsage: 0,
+                lpLocalName: ptr::null_mut(),
+                lpRemoteName: remote.as_mut_ptr(),
+                lpComment: ptr::null_mut(),
+                lpProvider: ptr::null_mut(),
+            };
+            let rc = unsafe { WNetAddConnection2W(&res, pass_w.as_ptr(), user_w.as_ptr(), 0) };
+            if rc != NO_ERROR {
+                anyhow::bail!("connecting to {share} as {user} failed (error {rc})");
+            }
+            Ok(Self { remote })
+        }
+    }
+
+    impl Drop for SmbConnection {
+        fn drop(&mut self) {
+            unsafe {
+                WNetCancelConnection2W(self.remote.as_ptr(), 0, FALSE);
+            }
+        }
+    }
+
+    /// DFS entry (link or namespace root) serving `path` and the active
+    /// `\\server\share` behind it. `None` outside DFS namespaces.
+    pub fn resolve_dfs(path: &str) -> Option<(String, String)> {
+        let path_w = wide(path);
+        let mut buf: *mut u8 = ptr::null_mut();
+        let rc = unsafe {
+            NetDfsGetClientInfo(path_w.as_ptr(), ptr::null(), ptr::null(), 3, &mut buf)
+        };
+        if rc != NO_ERROR || buf.is_null() {
+            return None;
+        }
+        let out = unsafe {
+            let info = &*(buf as *const DFS_INFO_3);
+            let storages: &[DFS_STORAGE_INFO] =
+                std::slice::from_raw_parts(info.Storage, info.NumberOfStorages as usize);
+            let active = storages
+                .iter()
+                .find(|s| s.State & DFS_STORAGE_STATE_ACTIVE != 0)
+                .or_else(|| storages.first());
+            active.map(|s| {
+                (
+                    from_wide(info.EntryPath),
+                    format!(r"\\{}\{}", from_wide(s.ServerName), from_wide(s.ShareName)),
+                )
+            })
+        };
+        unsafe {
+            NetApiBufferFree(buf as *const _);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unc_share_handles_plain_and_verbatim_forms() {
+        assert_eq!(
+            unc_share(Path::new(r"\\corp\dfs\eng\src")).as_deref(),
+            Some(r"\\corp\dfs")
+        );
+        assert_eq!(
+            unc_share(Path::new(r"\\?\UNC\srv\share")).as_deref(),
+            Some(r"\\srv\share")
+        );
+        assert_eq!(unc_share(Path::new(r"\\srv")), None);
+        assert_eq!(unc_share(Path::new(r"\\?\C:\x")), None);
+        assert_eq!(unc_share(Path::new("/mnt/x")), None);
+    }
+
+    #[test]
+    fn resolved_links_cover_their_subtree_but_roots_do_not() {
+        let m = DfsMap::default();
+        m.record(r"\\corp\dfs".into(), r"\\ns1\dfs".into());
+        m.record(r"\\corp\dfs\eng".into(), r"\\fs7\eng$".into());
+        assert!(m.covers(r"\\corp\dfs\eng"));
+        assert!(m.covers(r"\\corp\dfs\eng\src"));
+        assert!(!m.covers(r"\\corp\dfs\engineering"));
+        // The namespace root never short-circuits: links below it still
+        // need their own lookup.
+        assert!(!m.covers(r"\\corp\dfs\hr"));
+        assert_eq!(m.len(), 2);
+    }
+
+    #[test]
+    fn failed_lookup_marks_the_share_plain_and_rows_get_targets() {
+        use std::cell::Cell;
+        let m = DfsMap::default();
+        let calls = Cell::new(0);
+        let resolve = |dir: &str| {
+            calls.set(calls.get() + 1);
+            dir.starts_with(r"\\corp\dfs")
+                .then(|| (r"\\corp\dfs\eng".to_string(), r"\\fs7\eng$".to_string()))
+        };
+        m.observe_with(r"\\srv\plain", resolve);
+        m.observe_with(r"\\srv\plain\a", resolve);
+        m.observe_with(r"\\srv\plain\a\b", resolve);
+        assert_eq!(calls.get(), 1);
+
+        m.observe_with(r"\\corp\dfs\eng", resolve);
+        m.observe_with(r"\\corp\dfs\eng\src", resolve);
+        assert_eq!(calls.get(), 2);
+        assert_eq!(m.target(Path::new(r"\\corp\dfs\eng\src\main.c")), r"\\fs7\eng$");
+        assert_eq!(m.target(Path::new(r"\\corp\dfs\eng")), r"\\fs7\eng$");
+        assert_eq!(m.target(Path::new(r"\\srv\plain\a")), "");
+    }
+
+    #[test]
+    fn sidecar_lists_links_sorted() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("scan.csv");
+        let side = dfs_sidecar_path(&out);
+        assert_eq!(side, dir.path().join("scan.csv.dfs.csv"));
+        let m = DfsMap::default();
+        m.record(r"\\corp\dfs\b".into(), r"\\fs2\b".into());
+        m.record(r"\\corp\dfs\a,x".into(), r"\\fs1\a".into());
+        m.write_csv(&side).unwrap();
+        let text = std::fs::read_to_string(&side).unwrap();
+        assert_eq!(
+            text,
+            "path,target\n\"\\\\corp\\dfs\\a,x\",\\\\fs1\\a\n\\\\corp\\dfs\\b,\\\\fs2\\b\n"
+        );
+    }
+}
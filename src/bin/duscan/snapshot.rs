@@ -0,0 +1,322 @@
+// rs/src/bin/duscan/snapshot.rs
+//
+// `--snapshot`: scan a read-only, point-in-time snapshot instead of the live
+// filesystem. One snapshot is taken per distinct mount under the scan roots
+// (ZFS dataset, Btrfs subvolume, or LVM logical volume), the roots are
+// redirected into it, and an alias maps snapshot paths back to the live ones
+// so the output looks like a normal scan. Snapshots are removed on drop; if
+// the process is killed the cleanup command printed at creation time must be
+// run by hand.
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::alias::Alias;
+
+/// One line of /proc/self/mountinfo, reduced to what snapshotting needs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MountEntry {
+    pub mount_point: PathBuf,
+    pub fstype: String,
+    pub source: String,
+}
+
+/// Parse a mountinfo line: `id parent maj:min root mount_point opts
+/// [optional...] - fstype source super_opts`.
+pub fn parse_mountinfo_line(line: &str) -> Option<MountEntry> {
+    let (pre, post) = line.split_once(" - ")?;
+    let mount_point = pre.split(' ').nth(4)?;
+    let mut post = post.split(' ');
+    let fstype = post.next()?;
+    let source = post.next()?;
+    Some(MountEntry {
+        mount_point: PathBuf::from(unescape_mount(mount_point)),
+        fstype: fstype.to_string(),
+        source: unescape_mount(source),
+    })
+}
+
+/// The kernel writes space, tab, newline and backslash as `\ooo` octal.
+fn unescape_mount(s: &str) -> String {
+    let b = s.as_bytes();
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        if b[i] == b'\\'
+            && b
+                .get(i + 1..i + 4)
+                .is_some_and(|d| d.iter().all(|c| (b'0'..=b'7').contains(c)))
+        {
+            let v = (b[i + 1] - b'0') * 64 + (b[i + 2] - b'0') * 8 + (b[i + 3] - b'0');
+            out.push(v);
+            i += 4;
+        } else {
+            out.push(b[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The mount holding `path`: the entry with the longest mount point that is a
+/// component-wise prefix of it. Later entries win ties (they are stacked on
+/// top of earlier mounts at the same point).
+pub fn find_mount<'a>(mounts: &'a [MountEntry], path: &Path) -> Option<&'a MountEntry> {
+    mounts
+        .iter()
+        .filter(|m| path.starts_with(&m.mount_point))
+        .fold(None, |best: Option<&MountEntry>, m| match best {
+            Some(b) if b.mount_point.as_os_str().len() > m.mount_point.as_os_str().len() => {
+                Some(b)
+            }
+            _ => Some(m),
+        })
+}
+
+fn read_mounts() -> Result<Vec<MountEntry>> {
+    let text = fs::read_to_string("/proc/self/mountinfo")
+        .context("reading /proc/self/mountinfo")?;
+    Ok(text.lines().filter_map(parse_mountinfo_line).collect())
+}
+
+enum Kind {
+    Zfs { snap: String },
+    Btrfs { snap: PathBuf },
+    Lvm { snap_lv: String, mount_dir: PathBuf },
+}
+
+/// A live snapshot of one mount. Dropping it destroys the snapshot.
+pub struct Snapshot {
+    mount: MountEntry,
+    /// Directory where the snapshot shows the content of `mount.mount_point`.
+    base: PathBuf,
+    kind: Kind,
+}
+
+impl Snapshot {
+    fn create(mount: &MountEntry, name: &str) -> Result<Self> {
+        let mp = &mount.mount_point;
+        let (base, kind) = match mount.fstype.as_str() {
+            "zfs" => {
+                let snap = format!("{}@{}", mount.source, name);
+                run("zfs", &["snapshot", &snap])?;
+                (mp.join(".zfs/snapshot").join(name), Kind::Zfs { snap })
+            }
+            "btrfs" => {
+                let snap = mp.join(format!(".{name}"));
+                run(
+                    "btrfs",
+                    &["subvolume", "snapshot", "-r", &path_str(mp), &path_str(&snap)],
+                )?;
+                (snap.clone(), Kind::Btrfs { snap })
+            }
+            fstype if mount.source.starts_with("/dev/mapper/") || is_lvm(&mount.source) => {
+                let out = run("lvs", &["--noheadings", "-o", "vg_name", &mount.source])?;
+                let vg = out.trim().to_string();
+                let snap_lv = format!("{vg}/{name}");
+                run(
+                    "lvcreate",
+                    &["--snapshot", "--extents", "10%ORIGIN", "--name", name, &mount.source],
+                )?;
+                let mount_dir = std::env::temp_dir().join(name);
+                let mut opts = String::from("ro");
+                if fstype == "xfs" {
+                    opts.push_str(",nouuid");
+                }
+                let mounted = fs::create_dir_all(&mount_dir)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|_| {
+                        let dev = format!("/dev/{snap_lv}");
+                        run("mount", &["-o", &opts, &dev, &path_str(&mount_dir)])
+                    });
+                if let Err(e) = mounted {
+                    let _ = fs::remove_dir(&mount_dir);
+                    let _ = run("lvremove", &["-f", &snap_lv]);
+                    return Err(e);
+                }
+                (mount_dir.clone(), Kind::Lvm { snap_lv, mount_dir })
+            }
+            other => anyhow::bail!(
+                "--snapshot: {} ({}) at {} does not support snapshots (need zfs, btrfs or LVM)",
+                mount.source,
+                other,
+                mp.display()
+            ),
+        };
+        Ok(Self {
+            mount: mount.clone(),
+            base,
+            kind,
+        })
+    }
+
+    /// Where `root` (a path under this mount) appears inside the snapshot.
+    pub fn redirect(&self, root: &Path) -> PathBuf {
+        match root.strip_prefix(&self.mount.mount_point) {
+            Ok(rest) if !rest.as_os_str().is_empty() => self.base.join(rest),
+            _ => self.base.clone(),
+        }
+    }
+
+    /// Maps snapshot paths back to the live mount in the output.
+    pub fn alias(&self) -> Alias {
+        Alias {
+            from: self.base.clone(),
+            to: self.mount.mount_point.clone(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.kind {
+            Kind::Zfs { snap } => format!("zfs {snap} (cleanup: zfs destroy {snap})"),
+            Kind::Btrfs { snap } => format!(
+                "btrfs {} (cleanup: btrfs subvolume delete {})",
+                snap.display(),
+                snap.display()
+            ),
+            Kind::Lvm { snap_lv, mount_dir } => format!(
+                "lvm {snap_lv} on {} (cleanup: umount {}; lvremove -f {snap_lv})",
+                mount_dir.display(),
+                mount_dir.display()
+            ),
+        }
+    }
+
+    fn destroy(&self) -> Result<()> {
+        match &self.kind {
+            Kind::Zfs { snap } => run("zfs", &["destroy", snap]).map(drop),
+            Kind::Btrfs { snap } => {
+                run("btrfs", &["subvolume", "delete", &path_str(snap)]).map(drop)
+            }
+            Kind::Lvm { snap_lv, mount_dir } => {
+                run("umount", &[&path_str(mount_dir)])?;
+                let _ = fs::remove_dir(mount_dir);
+                run("lvremove", &["-f", snap_lv]).map(drop)
+            }
+        }
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        if let Err(e) = self.destroy() {
+            let snapshot = self.describe();
+            tracing::warn!(snapshot, error = format!("{e:#}"), "cannot remove snapshot");
+        }
+    }
+}
+
+/// Snapshot every mount that holds one of `roots`. Returns the snapshots and
+/// the roots redirected into them, in the same order as `roots`.
+pub fn snapshot_roots(roots: &[PathBuf], pid: u32) -> Result<(Vec<Snapshot>, Vec<PathBuf>)> {
+    let mounts = read_mounts()?;
+    let mut snaps: Vec<Snapshot> = Vec::new();
+    let mut redirected = Vec::with_capacity(roots.len());
+    for root in roots {
+        let mount = find_mount(&mounts, root)
+            .with_context(|| format!("--snapshot: no mount found for {}", root.display()))?;
+        let idx = match snaps.iter().position(|s| s.mount == *mount) {
+            Some(i) => i,
+            None => {
+                let name = format!("dutopia-snap-{}-{}", pid, snaps.len());
+                snaps.push(Snapshot::create(mount, &name)?);
+                snaps.len() - 1
+            }
+        };
+        redirected.push(snaps[idx].redirect(root));
+    }
+    Ok((snaps, redirected))
+}
+
+fn is_lvm(source: &str) -> bool {
+    // /dev/<vg>/<lv> — two components below /dev, not a plain disk.
+    source
+        .strip_prefix("/dev/")
+        .is_some_and(|rest| rest.split('/').count() == 2 && !rest.starts_with("disk/"))
+}
+
+fn path_str(p: &Path) -> String {
+    p.to_string_lossy().into_owned()
+}
+
+fn run(cmd: &str, args: &[&str]) -> Result<String> {
+    let out = Command::new(cmd)
+        .args(args)
+        .output()
+        .with_context(|| format!("running {cmd}"))?;
+    if !out.status.success() {
+        anyhow::bail!(
+            "{} {} failed: {}",
+            cmd,
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+22 1 0:21 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+40 22 0:35 / /tank rw shared:20 - zfs tank rw,xattr
+41 40 0:36 / /tank/proj rw shared:21 - zfs tank/proj rw,xattr
+50 22 0:40 /@home /home rw - btrfs /dev/sdb1 rw,subvol=/@home
+60 22 253:2 / /srv/my\\040data rw - xfs /dev/mapper/vg0-data rw";
+
+    fn mounts() -> Vec<MountEntry> {
+        MOUNTINFO.lines().filter_map(parse_mountinfo_line).collect()
+    }
+
+    #[test]
+    fn parses_mountinfo_with_escapes() {
+        let m = mounts();
+        assert_eq!(m.len(), 5);
+        assert_eq!(m[1].fstype, "zfs");
+        assert_eq!(m[1].source, "tank");
+        assert_eq!(m[4].mount_point, PathBuf::from("/srv/my data"));
+        assert_eq!(m[4].source, "/dev/mapper/vg0-data");
+        assert!(parse_mountinfo_line("garbage").is_none());
+    }
+
+    #[test]
+    fn find_mount_picks_longest_component_prefix() {
+        let m = mounts();
+        let f = |p: &str| find_mount(&m, Path::new(p)).unwrap().mount_point.clone();
+        assert_eq!(f("/tank/proj/a"), PathBuf::from("/tank/proj"));
+        assert_eq!(f("/tank/projects"), PathBuf::from("/tank"));
+        assert_eq!(f("/home/alice"), PathBuf::from("/home"));
+        assert_eq!(f("/etc"), PathBuf::from("/"));
+    }
+
+    #[test]
+    fn redirect_and_alias_round_trip() {
+        let snap = Snapshot {
+            mount: mounts()[2].clone(),
+            base: PathBuf::from("/tank/proj/.zfs/snapshot/s"),
+            // Never destroyed: forget() below skips Drop.
+            kind: Kind::Zfs { snap: "tank/proj@s".into() },
+        };
+        assert_eq!(
+            snap.redirect(Path::new("/tank/proj/a/b")),
+            PathBuf::from("/tank/proj/.zfs/snapshot/s/a/b")
+        );
+        assert_eq!(
+            snap.redirect(Path::new("/tank/proj")),
+            PathBuf::from("/tank/proj/.zfs/snapshot/s")
+        );
+        let a = snap.alias();
+        assert_eq!(a.to, PathBuf::from("/tank/proj"));
+        std::mem::forget(snap);
+    }
+
+    #[test]
+    fn lvm_source_detection() {
+        assert!(is_lvm("/dev/vg0/data"));
+        assert!(!is_lvm("/dev/sda1"));
+        assert!(!is_lvm("/dev/disk/by-uuid"));
+    }
+}
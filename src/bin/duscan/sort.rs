@@ -0,0 +1,216 @@
+// rs/src/bin/duscan/sort.rs
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Memory budget for one in-memory sorted run (line bytes + per-line overhead).
+pub const RUN_BYTES: usize = 256 * 1024 * 1024;
+
+const RUN_BUF_SIZE: usize = 1024 * 1024;
+const LINE_OVERHEAD: usize = std::mem::size_of::<Vec<u8>>();
+
+/// Spills sorted runs of lines to `run_dir`, each bounded by `budget` bytes,
+/// so an arbitrarily large input is sorted with bounded memory. Runs are
+/// zstd-compressed when `compressed` is set.
+pub struct RunSorter {
+    run_dir: PathBuf,
+    prefix: String,
+    budget: usize,
+    compressed: bool,
+    lines: Vec<Vec<u8>>,
+    used: usize,
+    runs: Vec<PathBuf>,
+}
+
+impl RunSorter {
+    pub fn new(run_dir: &Path, prefix: &str, budget: usize, compressed: bool) -> Self {
+        Self {
+            run_dir: run_dir.to_path_buf(),
+            prefix: prefix.to_string(),
+            budget: budget.max(1),
+            compressed,
+            lines: Vec::new(),
+            used: 0,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Add every non-blank line of `reader` (newline stripped).
+    pub fn push_reader<R: BufRead>(&mut self, mut reader: R) -> io::Result<()> {
+        let mut line = Vec::with_capacity(256);
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            if line.last() == Some(&b'\n') {
+                line.pop();
+            }
+            if line.iter().all(|b| b.is_ascii_whitespace()) {
+                continue;
+            }
+            self.used += line.len() + LINE_OVERHEAD;
+            self.lines.push(line.clone());
+            if self.used >= self.budget {
+                self.spill()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        if self.lines.is_empty() {
+            return Ok(());
+        }
+        self.lines.sort_unstable();
+        let path = self
+            .run_dir
+            .join(format!("{}_run{}.tmp", self.prefix, self.runs.len()));
+        let file = BufWriter::with_capacity(RUN_BUF_SIZE, File::create(&path)?);
+        self.runs.push(path);
+
+        let mut w: Box<dyn Write> = if self.compressed {
+            Box::new(zstd::stream::write::Encoder::new(file, 1)?.auto_finish())
+        } else {
+            Box::new(file)
+        };
+        for ln in self.lines.drain(..) {
+            w.write_all(&ln)?;
+            w.write_all(b"\n")?;
+        }
+        w.flush()?;
+        self.used = 0;
+        Ok(())
+    }
+
+    /// Spill what is left and k-way merge all runs into `out`. Run files are
+    /// removed when the sorter is dropped.
+    pub fn finish<W: Write>(mut self, out: &mut W) -> io::Result<()> {
+        // A single run that still fits in memory needs no spill at all.
+        if self.runs.is_empty() {
+            self.lines.sort_unstable();
+            for ln in &self.lines {
+                out.write_all(ln)?;
+                out.write_all(b"\n")?;
+            }
+            return Ok(());
+        }
+        self.spill()?;
+        kway_merge(&self.runs, self.compressed, out)
+    }
+}
+
+/// A sorter abandoned by an error (a failed spill, a shard that cannot be
+/// read) still owns its runs; remove them rather than leave `*_runN.tmp`
+/// files behind.
+impl Drop for RunSorter {
+    fn drop(&mut self) {
+        for run in &self.runs {
+            let _ = fs::remove_file(run);
+        }
+    }
+}
+
+/// Merge already-sorted line files into `out` with a min-heap holding one
+/// pending line per run.
+fn kway_merge<W: Write>(runs: &[PathBuf], compressed: bool, out: &mut W) -> io::Result<()> {
+    let mut readers: Vec<Box<dyn BufRead>> = Vec::with_capacity(runs.len());
+    for run in runs {
+        let f = BufReader::with_capacity(RUN_BUF_SIZE, File::open(run)?);
+        if compressed {
+            let dec = zstd::stream::read::Decoder::with_buffer(f)?;
+            readers.push(Box::new(BufReader::with_capacity(RUN_BUF_SIZE, dec)));
+        } else {
+            readers.push(Box::new(f));
+        }
+    }
+
+    let mut heap: BinaryHeap<Reverse<(Vec<u8>, usize)>> = BinaryHeap::with_capacity(runs.len());
+    for (idx, r) in readers.iter_mut().enumerate() {
+        if let Some(line) = next_line(r)? {
+            heap.push(Reverse((line, idx)));
+        }
+    }
+
+    while let Some(Reverse((line, idx))) = heap.pop() {
+        out.write_all(&line)?;
+        out.write_all(b"\n")?;
+        if let Some(next) = next_line(&mut readers[idx])? {
+            heap.push(Reverse((next, idx)));
+        }
+    }
+    Ok(())
+}
+
+fn next_line(r: &mut Box<dyn BufRead>) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    if r.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn sort_all(input: &str, budget: usize, compressed: bool) -> (String, usize) {
+        let tmp = tempdir().unwrap();
+        let mut sorter = RunSorter::new(tmp.path(), "t", budget, compressed);
+        sorter.push_reader(input.as_bytes()).unwrap();
+        let runs = sorter.runs.len();
+        let mut out = Vec::new();
+        sorter.finish(&mut out).unwrap();
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 0);
+        (String::from_utf8(out).unwrap(), runs)
+    }
+
+    #[test]
+    fn sorts_in_memory_when_under_budget() {
+        let (out, runs) = sort_all("c\na\n\n  \nb", RUN_BYTES, false);
+        assert_eq!(out, "a\nb\nc\n");
+        assert_eq!(runs, 0);
+    }
+
+    #[test]
+    fn merges_multiple_spilled_runs() {
+        let input: String = (0..200).rev().map(|i| format!("{:04}\n", i)).collect();
+        let expected: String = (0..200).map(|i| format!("{:04}\n", i)).collect();
+        let (out, runs) = sort_all(&input, 100, false);
+        assert!(runs > 1);
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn merges_compressed_runs() {
+        let (out, runs) = sort_all("z\ny\nx\nw\n", 1, true);
+        assert!(runs >= 3);
+        assert_eq!(out, "w\nx\ny\nz\n");
+    }
+
+    #[test]
+    fn keeps_non_utf8_bytes() {
+        let tmp = tempdir().unwrap();
+        let mut sorter = RunSorter::new(tmp.path(), "t", 1, false);
+        sorter.push_reader(&b"b\xff\na\n"[..]).unwrap();
+        let mut out = Vec::new();
+        sorter.finish(&mut out).unwrap();
+        assert_eq!(out, b"a\nb\xff\n");
+    }
+
+    #[test]
+    fn dropping_unfinished_sorter_removes_runs() {
+        let tmp = tempdir().unwrap();
+        let mut sorter = RunSorter::new(tmp.path(), "t", 1, false);
+        sorter.push_reader(&b"b\na\n"[..]).unwrap();
+        assert!(fs::read_dir(tmp.path()).unwrap().count() > 0);
+        drop(sorter);
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 0);
+    }
+}
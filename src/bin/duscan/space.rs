@@ -0,0 +1,180 @@
+// rs/src/bin/duscan/space.rs
+//
+// `--min-free SIZE`: free space in the shard directory, and in the output
+// directory when it is elsewhere, is checked every `POLL` while the scan
+// runs. Below the threshold the workers pause between tasks, so a filling
+// disk stops the scan instead of every worker hitting write errors and the
+// merge concatenating truncated shards. When space comes back (another job
+// cleaned up, a snapshot was dropped) they resume. When it has not come back
+// after `--min-free-wait`, the scan is aborted: the workers stop, their
+// shards are removed and duscan fails with the directory and the free space
+// in the error. A walk that finished is merged even if space ran low at the
+// very end. The check is off by default (`--min-free 0`).
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering::SeqCst};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+use crossbeam::channel::Sender;
+use dutopia::util::{fs_free_bytes, human_bytes};
+
+use crate::worker::Task;
+
+/// How often free space is read while scanning.
+const POLL: Duration = Duration::from_secs(1);
+/// How often a paused worker looks again.
+const PAUSE_SLEEP: Duration = Duration::from_millis(200);
+
+const RUNNING: u8 = 0;
+const PAUSED: u8 = 1;
+const ABORTED: u8 = 2;
+
+#[derive(Debug)]
+pub struct SpaceGuard {
+    dirs: Vec<PathBuf>,
+    min_free: u64,
+    wait: Duration,
+    state: AtomicU8,
+    done: AtomicBool,
+    reason: Mutex<Option<String>>,
+}
+
+impl SpaceGuard {
+    /// `None` when `min_free` is 0.
+    pub fn new(dirs: &[&Path], min_free: u64, wait: Duration) -> Option<Self> {
+        let mut list: Vec<PathBuf> = Vec::new();
+        for d in dirs {
+            if !list.iter().any(|p| p == d) {
+                list.push(d.to_path_buf());
+            }
+        }
+        (min_free > 0).then_some(Self {
+            dirs: list,
+            min_free,
+            wait,
+            state: AtomicU8::new(RUNNING),
+            done: AtomicBool::new(false),
+            reason: Mutex::new(None),
+        })
+    }
+
+    /// The directory with the least free space, when that is below the
+    /// threshold. Directories whose free space cannot be read are ignored.
+    pub fn low(&self) -> Option<(&Path, u64)> {
+        self.dirs
+            .iter()
+            .filter_map(|d| fs_free_bytes(d).map(|free| (d.as_path(), free)))
+            .filter(|&(_, free)| free < self.min_free)
+            .min_by_key(|&(_, free)| free)
+    }
+
+    /// Error text for `dir` having only `free` bytes left.
+    pub fn message(&self, dir: &Path, free: u64) -> String {
+        format!(
+            "disk nearly full: {} free in {}, below --min-free {}",
+            human_bytes(free),
+            dir.display(),
+            human_bytes(self.min_free)
+        )
+    }
+
+    /// Called by the workers between tasks: blocks while paused, false once
+    /// the scan is aborted.
+    pub fn proceed(&self) -> bool {
+        loop {
+            match self.state.load(SeqCst) {
+                RUNNING => return true,
+                ABORTED => return false,
+                _ => thread::sleep(PAUSE_SLEEP),
+            }
+        }
+    }
+
+    /// True once the scan is aborted.
+    pub fn aborted(&self) -> bool {
+        self.state.load(SeqCst) == ABORTED
+    }
+
+    /// Why the scan was aborted, if it was.
+    pub fn abort_reason(&self) -> Option<String> {
+        self.reason.lock().unwrap().clone()
+    }
+
+    /// End the monitor thread.
+    pub fn stop(&self) {
+        self.done.store(true, SeqCst);
+    }
+
+    /// Watch free space until `stop`. On abort, `workers` shutdown tasks
+    /// are queued so that idle workers wake up and leave too.
+    pub fn start(self: &Arc<Self>, tx: Sender<Task>, workers: usize) {
+        let guard = self.clone();
+        thread::spawn(move || guard.monitor(tx, workers));
+    }
+
+    fn monitor(&self, tx: Sender<Task>, workers: usize) {
+        let mut low_since: Option<Instant> = None;
+        while !self.done.load(SeqCst) {
+            match self.low() {
+                Some((dir, free)) => {
+                    let since = *low_since.get_or_insert_with(|| {
+                        self.state.store(PAUSED, SeqCst);
+                        let msg = self.message(dir, free);
+                        tracing::warn!(dir = %dir.display(), free, "low disk space, workers paused");
+                        eprintln!("\r{}", format!("Paused       : {msg}").yellow());
+                        Instant::now()
+                    });
+                    if since.elapsed() >= self.wait {
+                        let msg = self.message(dir, free);
+                        tracing::error!(dir = %dir.display(), free, "low disk space, scan aborted");
+                        *self.reason.lock().unwrap() = Some(msg);
+                        self.state.store(ABORTED, SeqCst);
+                        for _ in 0..workers {
+                            let _ = tx.send(Task::Shutdown);
+                        }
+                        return;
+                    }
+                }
+                None => {
+                    if low_since.take().is_some() {
+                        self.state.store(RUNNING, SeqCst);
+                        tracing::info!("disk space recovered, workers resumed");
+                        eprintln!("\r{}", "Resumed      : disk space recovered".yellow());
+                    }
+                }
+            }
+            thread::sleep(POLL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam::channel::unbounded;
+
+    #[test]
+    fn aborts_when_space_does_not_come_back() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert!(SpaceGuard::new(&[tmp.path()], 0, Duration::ZERO).is_none());
+
+        let roomy = SpaceGuard::new(&[tmp.path(), tmp.path()], 1, Duration::ZERO).unwrap();
+        assert_eq!(roomy.dirs.len(), 1);
+        assert!(roomy.low().is_none());
+        assert!(roomy.proceed());
+
+        let full = Arc::new(SpaceGuard::new(&[tmp.path()], u64::MAX, Duration::ZERO).unwrap());
+        assert_eq!(full.low().unwrap().0, tmp.path());
+        let (tx, rx) = unbounded();
+        full.start(tx, 2);
+        assert!(matches!(rx.recv_timeout(Duration::from_secs(5)), Ok(Task::Shutdown)));
+        assert!(matches!(rx.recv_timeout(Duration::from_secs(5)), Ok(Task::Shutdown)));
+        assert!(!full.proceed());
+        let reason = full.abort_reason().unwrap();
+        assert!(reason.contains(&tmp.path().display().to_string()), "{reason}");
+        assert!(reason.starts_with("disk nearly full: "), "{reason}");
+        assert!(reason.ends_with(&format!("below --min-free {}", human_bytes(u64::MAX))), "{reason}");
+    }
+}
@@ -0,0 +1,125 @@
+// rs/src/bin/duscan/split.rs
+//
+// `--split-by-user`: after the merge, the CSV output is copied once more into
+// `<output>.users/<UID>.csv`, one file per owner, each with the usual header.
+// The merged file stays as it is for dusum; the split files are what a user
+// can be handed as their own raw listing, or what per-user jobs read without
+// filtering the global file. Rows keep their order, so sorted output stays
+// sorted. Paths may hold quoted newlines, so a record ends at a newline
+// outside quotes rather than at every newline.
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Split files kept open at once; others are flushed and reopened on demand.
+const MAX_OPEN: usize = 256;
+
+/// `scan.csv` -> `scan.csv.users`
+pub fn split_dir(output: &Path) -> PathBuf {
+    let mut name = output.file_name().unwrap_or_default().to_os_string();
+    name.push(".users");
+    output.with_file_name(name)
+}
+
+/// One CSV record, with its trailing newline, into `buf`; false at the end.
+fn read_record<R: BufRead>(r: &mut R, buf: &mut Vec<u8>) -> Result<bool> {
+    buf.clear();
+    loop {
+        if r.read_until(b'\n', buf)? == 0 {
+            return Ok(!buf.is_empty());
+        }
+        if buf.iter().filter(|&&b| b == b'"').count() % 2 == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// UID column (the 4th) of a record.
+fn uid(record: &[u8]) -> Option<u32> {
+    let field = record.split(|&b| b == b',').nth(3)?;
+    std::str::from_utf8(field).ok()?.parse().ok()
+}
+
+/// Write the per-UID files for `csv`; returns rows per UID.
+pub fn split_by_user(csv: &Path) -> Result<BTreeMap<u32, u64>> {
+    let dir = split_dir(csv);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).with_context(|| format!("removing {}", dir.display()))?;
+    }
+    fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+
+    let mut reader = BufReader::with_capacity(
+        2 * 1024 * 1024,
+        File::open(csv).with_context(|| format!("opening {}", csv.display()))?,
+    );
+    let mut header = Vec::new();
+    read_record(&mut reader, &mut header)?;
+
+    let mut open: HashMap<u32, BufWriter<File>> = HashMap::new();
+    let mut rows: BTreeMap<u32, u64> = BTreeMap::new();
+    let mut rec = Vec::new();
+    while read_record(&mut reader, &mut rec)? {
+        let Some(uid) = uid(&rec) else { continue };
+        if !open.contains_key(&uid) {
+            if open.len() >= MAX_OPEN {
+                for (_, mut w) in open.drain() {
+                    w.flush()?;
+                }
+            }
+            let path = dir.join(format!("{uid}.csv"));
+            let new = !rows.contains_key(&uid);
+            let f = File::options()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("opening {}", path.display()))?;
+            let mut w = BufWriter::new(f);
+            if new {
+                w.write_all(&header)?;
+            }
+            open.insert(uid, w);
+        }
+        if let Some(w) = open.get_mut(&uid) {
+            w.write_all(&rec)?;
+        }
+        *rows.entry(uid).or_default() += 1;
+    }
+    for (_, mut w) in open {
+        w.flush()?;
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_go_to_their_owner_file_with_the_header() {
+        let tmp = tempfile::tempdir().unwrap();
+        let csv = tmp.path().join("scan.csv");
+        fs::write(
+            &csv,
+            "INODE,ATIME,MTIME,UID,GID,MODE,SIZE,DISK,PATH\n\
+             1-1,0,0,1000,10,33188,5,8,/a\n\
+             1-2,0,0,0,0,33188,1,8,\"/b\nc\"\n\
+             1-3,0,0,1000,10,33188,7,8,\"/d,e\"\n",
+        )
+        .unwrap();
+        let rows = split_by_user(&csv).unwrap();
+        assert_eq!(rows, BTreeMap::from([(0, 1), (1000, 2)]));
+        let dir = split_dir(&csv);
+        assert_eq!(dir, tmp.path().join("scan.csv.users"));
+        let alice = fs::read_to_string(dir.join("1000.csv")).unwrap();
+        assert_eq!(
+            alice,
+            "INODE,ATIME,MTIME,UID,GID,MODE,SIZE,DISK,PATH\n\
+             1-1,0,0,1000,10,33188,5,8,/a\n\
+             1-3,0,0,1000,10,33188,7,8,\"/d,e\"\n"
+        );
+        let root = fs::read_to_string(dir.join("0.csv")).unwrap();
+        assert!(root.ends_with("\"/b\nc\"\n"));
+    }
+}
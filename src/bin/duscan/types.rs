@@ -0,0 +1,174 @@
+// rs/src/bin/duscan/types.rs
+use std::fs::FileType;
+
+const FILE: u8 = 1 << 0;
+const DIR: u8 = 1 << 1;
+const LINK: u8 = 1 << 2;
+const SOCKET: u8 = 1 << 3;
+const FIFO: u8 = 1 << 4;
+const BLOCK: u8 = 1 << 5;
+const CHAR: u8 = 1 << 6;
+const ALL: u8 = FILE | DIR | LINK | SOCKET | FIFO | BLOCK | CHAR;
+
+/// `--types`: the entry kinds written to the output. Directories are walked
+/// regardless; excluding `d` only drops their own rows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryTypes(u8);
+
+impl Default for EntryTypes {
+    fn default() -> Self {
+        Self(ALL)
+    }
+}
+
+/// Comma-separated `find -type` letters: f, d, l, s, p, b, c.
+pub fn parse_types(s: &str) -> Result<EntryTypes, String> {
+    let mut bits = 0u8;
+    for t in s.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        bits |= match t {
+            "f" => FILE,
+            "d" => DIR,
+            "l" => LINK,
+            "s" => SOCKET,
+            "p" => FIFO,
+            "b" => BLOCK,
+            "c" => CHAR,
+            other => {
+                return Err(format!(
+                    "unknown type '{other}' (expected f, d, l, s, p, b or c)"
+                ))
+            }
+        };
+    }
+    if bits == 0 {
+        return Err("expected at least one of f, d, l, s, p, b, c".to_string());
+    }
+    Ok(EntryTypes(bits))
+}
+
+impl EntryTypes {
+    pub fn is_all(self) -> bool {
+        self.0 == ALL
+    }
+
+    pub fn dirs(self) -> bool {
+        self.0 & DIR != 0
+    }
+
+    /// Whether an entry of this kind is emitted.
+    pub fn allows(self, kind: Kind) -> bool {
+        self.0 & kind.0 != 0
+    }
+
+    /// Letters in canonical order, for the run header.
+    pub fn letters(self) -> String {
+        [
+            (FILE, 'f'),
+            (DIR, 'd'),
+            (LINK, 'l'),
+            (SOCKET, 's'),
+            (FIFO, 'p'),
+            (BLOCK, 'b'),
+            (CHAR, 'c'),
+        ]
+        .iter()
+        .filter(|(b, _)| self.0 & b != 0)
+        .map(|(_, c)| c.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+}
+
+/// The kind of one entry, as one of the type bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Kind(u8);
+
+impl Kind {
+    pub fn of(ft: &FileType) -> Self {
+        Self(kind_bit(ft))
+    }
+
+    /// From the type bits of a `st_mode`, for entries listed without a
+    /// `FileType` (see `listing`).
+    pub fn from_mode(mode: u32) -> Self {
+        Self(match mode & 0o170000 {
+            0o040000 => DIR,
+            0o120000 => LINK,
+            0o140000 => SOCKET,
+            0o010000 => FIFO,
+            0o060000 => BLOCK,
+            0o020000 => CHAR,
+            _ => FILE,
+        })
+    }
+
+    pub fn is_dir(self) -> bool {
+        self.0 == DIR
+    }
+}
+
+fn kind_bit(ft: &FileType) -> u8 {
+    if ft.is_dir() {
+        return DIR;
+    }
+    if ft.is_symlink() {
+        return LINK;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if ft.is_socket() {
+            return SOCKET;
+        }
+        if ft.is_fifo() {
+            return FIFO;
+        }
+        if ft.is_block_device() {
+            return BLOCK;
+        }
+        if ft.is_char_device() {
+            return CHAR;
+        }
+    }
+    FILE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn parse_types_accepts_letters_and_rejects_unknown() {
+        let t = parse_types("d, l").unwrap();
+        assert!(t.dirs());
+        assert!(!t.is_all());
+        assert_eq!(t.letters(), "d,l");
+        assert!(parse_types("f,d,l,s,p,b,c").unwrap().is_all());
+        assert!(parse_types("x").is_err());
+        assert!(parse_types(",").is_err());
+    }
+
+    #[test]
+    fn allows_matches_entry_kind() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("a.txt");
+        fs::write(&file, "x").unwrap();
+        let ft_file = fs::symlink_metadata(&file).unwrap().file_type();
+        let ft_dir = fs::symlink_metadata(tmp.path()).unwrap().file_type();
+
+        let dirs_only = parse_types("d").unwrap();
+        assert!(dirs_only.allows(Kind::of(&ft_dir)));
+        assert!(!dirs_only.allows(Kind::of(&ft_file)));
+        assert!(EntryTypes::default().allows(Kind::of(&ft_file)));
+
+        #[cfg(unix)]
+        {
+            let link = tmp.path().join("l");
+            std::os::unix::fs::symlink(&file, &link).unwrap();
+            let ft_link = fs::symlink_metadata(&link).unwrap().file_type();
+            assert!(parse_types("l").unwrap().allows(Kind::of(&ft_link)));
+            assert!(!parse_types("f").unwrap().allows(Kind::of(&ft_link)));
+        }
+    }
+}
@@ -0,0 +1,202 @@
+// rs/src/bin/duscan/volumes.rs
+//
+// `--all-volumes` (Windows): every mounted local volume becomes a scan root.
+// Volumes are listed with FindFirstVolumeW/FindNextVolumeW, so ones mounted
+// in a folder rather than at a drive letter are found too (the walk treats
+// such mount points like symlinks and would not enter them). Mapped network
+// drives only show up in GetLogicalDrives. Removable and network volumes are
+// skipped unless asked for.
+use anyhow::Result;
+
+#[cfg_attr(not(windows), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriveKind {
+    Fixed,
+    /// USB sticks, card readers, optical drives
+    Removable,
+    Network,
+    /// RAM disks and anything Windows cannot classify
+    Other,
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VolumeFilter {
+    pub removable: bool,
+    pub network: bool,
+}
+
+impl VolumeFilter {
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn allows(&self, kind: DriveKind) -> bool {
+        match kind {
+            DriveKind::Fixed => true,
+            DriveKind::Removable => self.removable,
+            DriveKind::Network => self.network,
+            DriveKind::Other => false,
+        }
+    }
+}
+
+/// Mount paths whose kind passes `filter`, sorted, each listed once
+/// (drive letters compare case-insensitively).
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn select(found: Vec<(String, DriveKind)>, filter: VolumeFilter) -> Vec<String> {
+    let mut out: Vec<String> = found
+        .into_iter()
+        .filter(|(_, kind)| filter.allows(*kind))
+        .map(|(path, _)| path)
+        .collect();
+    out.sort_by_key(|p| p.to_ascii_uppercase());
+    out.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+    out
+}
+
+#[cfg(windows)]
+pub fn all_volumes(filter: VolumeFilter) -> Result<Vec<String>> {
+    Ok(select(win::mounted()?, filter))
+}
+
+#[cfg(not(windows))]
+pub fn all_volumes(_filter: VolumeFilter) -> Result<Vec<String>> {
+    anyhow::bail!("--all-volumes is only supported on Windows")
+}
+
+#[cfg(windows)]
+mod win {
+    use super::DriveKind;
+    use anyhow::{Context, Result};
+    use std::ffi::c_void;
+
+    unsafe extern "system" {
+        fn FindFirstVolumeW(lpszVolumeName: *mut u16, cchBufferLength: u32) -> *mut c_void;
+        fn FindNextVolumeW(
+            hFindVolume: *mut c_void,
+            lpszVolumeName: *mut u16,
+            cchBufferLength: u32,
+        ) -> i32;
+        fn FindVolumeClose(hFindVolume: *mut c_void) -> i32;
+        fn GetVolumePathNamesForVolumeNameW(
+            lpszVolumeName: *const u16,
+            lpszVolumePathNames: *mut u16,
+            cchBufferLength: u32,
+            lpcchReturnLength: *mut u32,
+        ) -> i32;
+        fn GetLogicalDrives() -> u32;
+        fn GetDriveTypeW(lpRootPathName: *const u16) -> u32;
+    }
+
+    const DRIVE_REMOVABLE: u32 = 2;
+    const DRIVE_FIXED: u32 = 3;
+    const DRIVE_REMOTE: u32 = 4;
+    const DRIVE_CDROM: u32 = 5;
+    const INVALID_HANDLE_VALUE: isize = -1;
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    fn drive_kind(root: &str) -> DriveKind {
+        match unsafe { GetDriveTypeW(wide(root).as_ptr()) } {
+            DRIVE_FIXED => DriveKind::Fixed,
+            DRIVE_REMOVABLE | DRIVE_CDROM => DriveKind::Removable,
+            DRIVE_REMOTE => DriveKind::Network,
+            _ => DriveKind::Other,
+        }
+    }
+
+    /// First mount path (`C:\` or `D:\mnt\data\`) of a `\\?\Volume{GUID}\`
+    /// name; `None` for volumes that are not mounted anywhere.
+    fn first_mount_path(volume: &[u16]) -> Option<String> {
+        let mut buf = vec![0u16; 1024];
+        let mut needed = 0u32;
+        for _ in 0..2 {
+            let ok = unsafe {
+                GetVolumePathNamesForVolumeNameW(
+                    volume.as_ptr(),
+                    buf.as_mut_ptr(),
+                    buf.len() as u32,
+                    &mut needed,
+                )
+            };
+            if ok != 0 {
+                let end = buf.iter().position(|&c| c == 0)?;
+                return (end > 0).then(|| String::from_utf16_lossy(&buf[..end]));
+            }
+            if needed as usize <= buf.len() {
+                return None;
+            }
+            buf.resize(needed as usize, 0);
+        }
+        None
+    }
+
+    /// Every mounted local volume plus every mapped network drive.
+    pub fn mounted() -> Result<Vec<(String, DriveKind)>> {
+        let mut out = Vec::new();
+        let mut name = [0u16; 260];
+        let h = unsafe { FindFirstVolumeW(name.as_mut_ptr(), name.len() as u32) };
+        if h as isize == INVALID_HANDLE_VALUE {
+            return Err(std::io::Error::last_os_error()).context("FindFirstVolumeW");
+        }
+        loop {
+            if let Some(path) = first_mount_path(&name) {
+                let kind = drive_kind(&path);
+                out.push((path, kind));
+            }
+            if unsafe { FindNextVolumeW(h, name.as_mut_ptr(), name.len() as u32) } == 0 {
+                break;
+            }
+        }
+        unsafe { FindVolumeClose(h) };
+
+        let mask = unsafe { GetLogicalDrives() };
+        for i in 0..26u8 {
+            if mask & (1 << i) != 0 {
+                let root = format!("{}:\\", (b'A' + i) as char);
+                if drive_kind(&root) == DriveKind::Network {
+                    out.push((root, DriveKind::Network));
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_keeps_fixed_unless_asked() {
+        let found = || {
+            vec![
+                (r"D:\".to_string(), DriveKind::Fixed),
+                (r"C:\".to_string(), DriveKind::Fixed),
+                (r"E:\".to_string(), DriveKind::Removable),
+                (r"C:\mnt\data\".to_string(), DriveKind::Fixed),
+                (r"Z:\".to_string(), DriveKind::Network),
+                (r"R:\".to_string(), DriveKind::Other),
+                (r"c:\".to_string(), DriveKind::Fixed),
+            ]
+        };
+        assert_eq!(
+            select(found(), VolumeFilter::default()),
+            [r"C:\", r"C:\mnt\data\", r"D:\"]
+        );
+        let all = VolumeFilter {
+            removable: true,
+            network: true,
+        };
+        assert_eq!(
+            select(found(), all),
+            [r"C:\", r"C:\mnt\data\", r"D:\", r"E:\", r"Z:\"]
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn all_volumes_is_windows_only() {
+        assert!(all_volumes(VolumeFilter::default()).is_err());
+    }
+}
@@ -1,39 +1,67 @@
 // rs/src/bin/duscan/worker.rs
-use std::ffi::{OsStr, OsString};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::fs::{self, File};
-use std::io::{BufWriter, Write};
+use std::io::{self, BufWriter, Write};
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering::Relaxed};
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crossbeam::channel::{Receiver, Sender};
 use zstd::stream::write::Encoder as ZstdEncoder;
 
-use dutopia::util::{get_hostname, should_skip};
-
-use crate::csv::{write_row_bin, write_row_csv};
+use dutopia::enrich::Enrichers;
+use dutopia::util::progress::Counter;
+use dutopia::util::{fs_type, get_hostname, should_skip, strip_verbatim_prefix, PathFilter, Row};
+
+use crate::alias::{apply_aliases, Alias};
+use crate::batch::{FileBatch, NameBatch};
+use crate::changes::Changes;
+use crate::listing::Listed;
+use crate::longpath::{self, LongPaths};
+use crate::csv::{write_row_bin, write_row_csv, write_row_csv_with};
+use crate::jsonl::write_row_jsonl;
 use crate::merge::OutputFormat;
-use crate::row::{row_from_metadata, stat_row};
+use crate::overlap::{Overlap, RootIds};
+use crate::redact::Redactor;
+use crate::sample::{Sampler, Tally};
+use crate::effective::OwnerRule;
+use crate::security::SecurityColumns;
+use crate::report::{ExtCounter, KindCounts, LatencyHistogram, MtimeHistogram};
+use crate::row::{row_for_path, stat_row};
+use crate::smb::DfsMap;
+use crate::scale::Scaler;
+use crate::space::SpaceGuard;
+use crate::owner::OwnerFilter;
+use crate::types::{EntryTypes, Kind};
 
 const FILE_CHUNK: usize = 2048;
 const FLUSH_BYTES: usize = 4 * 1024 * 1024;
 
-#[derive(Default)]
-pub struct Progress {
-    pub files: AtomicU64,
-}
-
-#[derive(Debug)]
-pub struct FileItem {
-    pub name: OsString,
-    pub md: fs::Metadata,
-}
+/// Entries of one directory stat'ed by the worker listing it. Past this the
+/// lister only reads names and sends them out in `Task::Stat` batches, so
+/// the `lstat`s of a directory with millions of entries (one round trip each
+/// on Lustre or NFS) are shared by every worker instead of serializing on
+/// one. Windows listings already carry the metadata, so there the lister
+/// keeps it.
+#[cfg(unix)]
+const INLINE_STATS: usize = 4 * FILE_CHUNK;
+#[cfg(not(unix))]
+const INLINE_STATS: usize = usize::MAX;
 
 pub enum Task {
-    Dir(PathBuf),
+    /// A directory and its depth below its root (a root is 0)
+    Dir(PathBuf, usize),
     Files {
         base: Arc<PathBuf>,
-        items: Vec<FileItem>,
+        items: FileBatch,
+    },
+    /// Names of a huge directory to stat, then handled as `Files`
+    Stat {
+        base: Arc<PathBuf>,
+        names: NameBatch,
     },
     Shutdown,
 }
@@ -41,12 +69,17 @@ pub enum Task {
 impl std::fmt::Debug for Task {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Task::Dir(p) => f.debug_tuple("Dir").field(p).finish(),
+            Task::Dir(p, depth) => f.debug_tuple("Dir").field(p).field(depth).finish(),
             Task::Files { base, items } => f
                 .debug_struct("Files")
                 .field("base", base)
                 .field("items", items)
                 .finish(),
+            Task::Stat { base, names } => f
+                .debug_struct("Stat")
+                .field("base", base)
+                .field("names", names)
+                .finish(),
             Task::Shutdown => write!(f, "Shutdown"),
         }
     }
@@ -54,19 +87,188 @@ impl std::fmt::Debug for Task {
 
 #[derive(Default)]
 pub struct Stats {
+    pub dirs: u64,
     pub files: u64,
     pub errors: u64,
     pub bytes: u64,
+    /// Rows and disk bytes per entry kind
+    pub kinds: KindCounts,
+    /// Files and disk bytes per extension
+    pub exts: ExtCounter,
+    /// Directories and files left out for their path length
+    pub long_paths: LongPaths,
+    /// Directories not entered because `--time-limit` had passed
+    pub unvisited: u64,
+    /// Directories left to the root they turned out to be
+    pub overlaps: Vec<Overlap>,
+    /// Time spent writing the shard
+    pub write_time: Duration,
+    /// `lstat` time per listed entry
+    pub stat_latency: LatencyHistogram,
+    /// Files and disk bytes by mtime year and age
+    pub mtimes: MtimeHistogram,
+    /// Totals below each sampled subdirectory (`--sample`)
+    pub clusters: HashMap<PathBuf, Tally>,
+}
+
+impl Stats {
+    pub fn merge(&mut self, other: Stats) {
+        self.dirs += other.dirs;
+        self.files += other.files;
+        self.errors += other.errors;
+        self.bytes += other.bytes;
+        self.kinds.merge(&other.kinds);
+        self.exts.merge(other.exts);
+        self.long_paths.merge(other.long_paths);
+        self.unvisited += other.unvisited;
+        self.overlaps.extend(other.overlaps);
+        self.write_time += other.write_time;
+        self.stat_latency.merge(&other.stat_latency);
+        self.mtimes.merge(&other.mtimes);
+        for (dir, t) in other.clusters {
+            let slot = self.clusters.entry(dir).or_default();
+            slot.files += t.files;
+            slot.bytes += t.bytes;
+        }
+    }
+
+    /// Count rows written below `path` towards its sampled subdirectory.
+    fn tally(&mut self, sample: Option<&Sampler>, path: &Path, files: u64, bytes: u64) {
+        if let Some(dir) = sample.and_then(|s| s.cluster(path)) {
+            let slot = self.clusters.entry(dir).or_default();
+            slot.files += files;
+            slot.bytes += bytes;
+        }
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Config {
-    pub skip: Option<String>,
+    /// Paths not walked or emitted (`--skip`, `--exclude`, `--include`)
+    pub filter: PathFilter,
     pub out_fmt: OutputFormat,
     pub no_atime: bool,
-    pub progress: Option<Arc<Progress>>,
+    pub progress: Option<Arc<Counter>>,
     pub pid: u32,
     pub verbose: u8,
+    /// zstd-compress CSV shards too (BIN shards are always compressed)
+    pub compress_shards: bool,
+    /// Output path prefix rewrites (`--alias FROM=TO`)
+    pub aliases: Vec<Alias>,
+    /// Snapshot directory -> live mount (`--snapshot`), applied before `aliases`
+    pub snapshots: Vec<Alias>,
+    /// DFS link resolutions, collected when a root is a UNC path on Windows
+    pub dfs: Option<Arc<DfsMap>>,
+    /// Extra per-file CSV columns (`--enrich`)
+    pub enrich: Option<Arc<Enrichers>>,
+    /// Entry kinds to emit (`--types`); directories are walked regardless
+    pub types: EntryTypes,
+    /// Lowercase filesystem types whose directories are not entered
+    /// (`--exclude-fstype`)
+    pub exclude_fstypes: Vec<String>,
+    /// Devices of the roots (`--one-file-system`); directories on others
+    /// are not entered
+    pub devices: Option<Arc<Vec<u64>>>,
+    /// Owners whose rows are emitted (`--uid`, `--gid`); None emits all
+    pub owners: Option<OwnerFilter>,
+    /// Set when scanning several roots, so a root met again inside another
+    /// is walked once
+    pub root_ids: Option<Arc<RootIds>>,
+    /// Hash file names in the output (`--redact-names`)
+    pub redact: Option<Arc<Redactor>>,
+    /// Walk only a sample of the subdirectories (`--sample`)
+    pub sample: Option<Arc<Sampler>>,
+    /// Capability / SELinux columns (`--capabilities`, `--selinux`)
+    pub security: Option<Arc<SecurityColumns>>,
+    /// `EFFECTIVE_OWNER` column (`--effective-owner`)
+    pub effective: Option<Arc<OwnerRule>>,
+    /// Directories of an earlier scan to compare with (`--since`)
+    pub changes: Option<Arc<Changes>>,
+    /// Scan start (epoch seconds); file ages in the run report count from it
+    pub started_at: i64,
+    /// Free-space watch on the shard and output directories (`--min-free`)
+    pub space: Option<Arc<SpaceGuard>>,
+    /// Read directories through `O_NOATIME` descriptors (`--preserve-atime`)
+    pub preserve_atime: bool,
+    /// Adaptive pool size (`--min-workers`, `--max-workers`)
+    pub scale: Option<Arc<Scaler>>,
+    /// Levels below each root that are walked (`--max-depth`)
+    pub max_depth: Option<usize>,
+    /// No directory is entered after this (`--time-limit`)
+    pub deadline: Option<Instant>,
+}
+
+impl Config {
+    /// True when `dir` lives on a filesystem type excluded by the user.
+    fn excluded_fstype(&self, dir: &Path) -> bool {
+        if self.exclude_fstypes.is_empty() {
+            return false;
+        }
+        fs_type(dir).is_some_and(|t| self.exclude_fstypes.contains(&t))
+    }
+
+    /// True when `--one-file-system` is set and `dir` is on a device none
+    /// of the roots is on, such as an NFS or bind mount below a root.
+    fn other_device(&self, dir: &Path) -> bool {
+        let Some(devices) = &self.devices else {
+            return false;
+        };
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            fs::symlink_metadata(dir).is_ok_and(|md| !devices.contains(&md.dev()))
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (devices, dir);
+            false
+        }
+    }
+
+    /// False for rows of owners not asked for with `--uid` / `--gid`.
+    fn emits(&self, row: &Row) -> bool {
+        self.owners.as_ref().is_none_or(|o| o.keeps(row))
+    }
+}
+
+/// Append the output row for `path` to `buf`; `src` is where the entry was
+/// found, before aliases and redaction.
+fn emit_row(
+    buf: &mut Vec<u8>,
+    src: &Path,
+    path: &Path,
+    row: &Row,
+    cfg: &Config,
+    extra: &mut Vec<String>,
+) {
+    if cfg.out_fmt.bin_shards() {
+        return write_row_bin(buf, path, row, cfg.no_atime);
+    }
+    if cfg.out_fmt == OutputFormat::Jsonl {
+        return write_row_jsonl(buf, path, row, cfg.no_atime);
+    }
+    if cfg.enrich.is_none()
+        && cfg.security.is_none()
+        && cfg.effective.is_none()
+        && cfg.dfs.is_none()
+    {
+        return write_row_csv(buf, path, row, cfg.no_atime);
+    }
+    extra.clear();
+    if let Some(e) = &cfg.enrich {
+        let shown = strip_verbatim_prefix(path);
+        e.values(shown.as_os_str().as_encoded_bytes(), row, extra);
+    }
+    if let Some(s) = &cfg.security {
+        s.values(src, extra);
+    }
+    if let Some(rule) = &cfg.effective {
+        extra.push(rule.value(src, row));
+    }
+    if let Some(dfs) = &cfg.dfs {
+        extra.push(dfs.target(src));
+    }
+    write_row_csv_with(buf, path, row, cfg.no_atime, extra);
 }
 
 pub fn worker(
@@ -77,18 +279,15 @@ pub fn worker(
     out_dir: PathBuf,
     cfg: Config,
 ) -> Stats {
-    let is_bin = cfg.out_fmt == OutputFormat::Bin;
+    let is_bin = cfg.out_fmt.bin_shards();
+    let mut extra: Vec<String> = Vec::new();
     let hostname = get_hostname();
     let pid = cfg.pid;
     let shard_path = out_dir.join(format!("shard_{hostname}_{pid}_{tid}.tmp"));
     let file = match File::create(&shard_path) {
         Ok(f) => f,
         Err(e) => {
-            eprintln!(
-                "FATAL: cannot create shard file {}: {}",
-                shard_path.display(),
-                e
-            );
+            tracing::error!(tid, shard = %shard_path.display(), error = %e, "cannot create shard file");
             let mut stats = Stats::default();
             stats.errors += 1;
             return stats;
@@ -96,14 +295,14 @@ pub fn worker(
     };
     let base = BufWriter::with_capacity(32 * 1024 * 1024, file);
     let has_progress = cfg.progress.is_some();
-    let progress = cfg.progress.unwrap_or_default();
+    let progress = cfg.progress.clone().unwrap_or_default();
     let verbose = cfg.verbose;
 
-    let mut writer: Box<dyn Write + Send> = if is_bin {
+    let mut writer: Box<dyn Write + Send> = if is_bin || cfg.compress_shards {
         let enc = match ZstdEncoder::new(base, 1) {
             Ok(e) => e,
             Err(e) => {
-                eprintln!("FATAL: cannot create zstd encoder: {}", e);
+                tracing::error!(tid, error = %e, "cannot create zstd encoder");
                 let mut stats = Stats::default();
                 stats.errors += 1;
                 return stats;
@@ -117,131 +316,284 @@ pub fn worker(
     let mut buf: Vec<u8> = Vec::with_capacity(32 * 1024 * 1024);
     let mut stats = Stats::default();
 
-    while let Ok(task) = rx.recv() {
+    loop {
+        let aborted = || cfg.space.as_ref().is_some_and(|s| s.aborted());
+        if let Some(scale) = &cfg.scale
+            && !scale.wait_turn(tid, aborted)
+        {
+            break;
+        }
+        let waited = Instant::now();
+        let Ok(task) = rx.recv() else { break };
+        if let Some(scale) = &cfg.scale {
+            scale.add_idle(waited.elapsed());
+        }
+        if let Some(space) = &cfg.space
+            && !space.proceed()
+        {
+            break;
+        }
+        let task = match task {
+            Task::Stat { base, names } => {
+                let (items, errors) =
+                    stat_names(&base, &names, &mut stats.stat_latency, &mut stats.long_paths, verbose);
+                stats.errors += errors;
+                Task::Files { base, items }
+            }
+            task => task,
+        };
         match task {
             Task::Shutdown => break,
+            Task::Stat { .. } => unreachable!("stat batches are turned into file batches above"),
 
-            Task::Dir(dir) => {
+            Task::Dir(dir, depth) => {
                 let mut error_count = 0u64;
-                if should_skip(&dir, cfg.skip.as_deref()) {
+                if cfg.deadline.is_some_and(|d| Instant::now() >= d) {
+                    stats.unvisited += 1;
+                    inflight.fetch_sub(1, Relaxed);
+                    continue;
+                }
+                if should_skip(&dir, &cfg.filter) {
                     let _ = inflight.fetch_sub(1, Relaxed);
                     continue;
                 }
+                if longpath::too_long(&dir) {
+                    if verbose >= 1 {
+                        tracing::warn!(dir = %dir.display(), "path too long, directory not entered");
+                    }
+                    stats.long_paths.record(&dir);
+                    inflight.fetch_sub(1, Relaxed);
+                    continue;
+                }
+                if cfg.excluded_fstype(&dir) {
+                    if verbose >= 1 {
+                        tracing::info!(dir = %dir.display(), "skipping excluded filesystem type");
+                    }
+                    inflight.fetch_sub(1, Relaxed);
+                    continue;
+                }
+                if cfg.other_device(&dir) {
+                    if verbose >= 1 {
+                        tracing::info!(dir = %dir.display(), "skipping, on another filesystem");
+                    }
+                    inflight.fetch_sub(1, Relaxed);
+                    continue;
+                }
+
+                if let Some(o) = cfg.root_ids.as_ref().and_then(|r| r.covered_by(&dir)) {
+                    if verbose >= 1 {
+                        tracing::info!(dir = %o.path, root = %o.scanned_as, "skipping, covered by another root");
+                    }
+                    stats.overlaps.push(o);
+                    inflight.fetch_sub(1, Relaxed);
+                    continue;
+                }
+
+                if let Some(s) = &cfg.sample
+                    && !s.visit(&dir, &cfg.filter)
+                {
+                    inflight.fetch_sub(1, Relaxed);
+                    continue;
+                }
 
                 if verbose >= 2 {
-                    eprintln!("[{:>2}] Processing {}", tid, dir.display());
+                    tracing::debug!(tid, dir = %dir.display(), "processing");
                 }
 
-                if let Some(row) = stat_row(&dir) {
-                    if is_bin {
-                        write_row_bin(&mut buf, &dir, &row, cfg.no_atime);
-                    } else {
-                        write_row_csv(&mut buf, &dir, &row, cfg.no_atime);
-                    }
-                    stats.files += 1;
-                } else {
-                    stats.errors += 1;
-                    error_count += 1;
-                    if verbose >= 1 {
-                        eprintln!("ERROR: Failed to stat directory: {}", dir.display());
+                if let Some(dfs) = &cfg.dfs {
+                    dfs.observe(&dir);
+                }
+
+                if cfg.types.dirs() || cfg.changes.is_some() {
+                    match stat_row(&dir) {
+                        Some(row) => {
+                            let live = apply_aliases(&cfg.snapshots, &dir);
+                            let out_path = apply_aliases(&cfg.aliases, &live);
+                            if let Some(changes) = &cfg.changes {
+                                changes.observe(&out_path, row.mtime);
+                            }
+                            if cfg.types.dirs() && cfg.emits(&row) {
+                                emit_row(&mut buf, &dir, &out_path, &row, &cfg, &mut extra);
+                                stats.files += 1;
+                                stats.kinds.record(row.mode, row.blocks * 512);
+                                stats.tally(cfg.sample.as_deref(), &dir, 1, 0);
+                            }
+                        }
+                        None => {
+                            stats.errors += 1;
+                            error_count += 1;
+                            if verbose >= 1 {
+                                tracing::warn!(dir = %dir.display(), "cannot stat directory");
+                            }
+                        }
                     }
                 }
 
                 if buf.len() >= FLUSH_BYTES {
+                    let t = Instant::now();
                     if let Err(e) = writer.write_all(&buf) {
                         if verbose >= 1 {
-                            eprintln!("ERROR: write failed: {}", e);
+                            tracing::error!(tid, error = %e, "shard write failed");
                         }
                         stats.errors += 1;
                     }
+                    stats.write_time += t.elapsed();
                     buf.clear();
                 }
 
-                error_count += enum_dir(&dir, &tx, &inflight, cfg.skip.as_deref(), verbose);
+                let ctx = ListCtx {
+                    tx: &tx,
+                    inflight: &inflight,
+                    filter: &cfg.filter,
+                    subdirs: cfg.max_depth.is_none_or(|m| depth < m).then_some(depth + 1),
+                    types: cfg.types,
+                    verbose,
+                    preserve_atime: cfg.preserve_atime,
+                };
+                error_count += enum_dir(&dir, &ctx, &mut stats.stat_latency);
                 stats.errors += error_count;
+                stats.dirs += 1;
                 inflight.fetch_sub(1, Relaxed);
                 if has_progress {
-                    progress.files.fetch_add(1, Relaxed);
+                    progress.add(1);
                 }
             }
 
             Task::Files { base, items } => {
-                if should_skip(base.as_ref(), cfg.skip.as_deref()) {
+                if should_skip(base.as_ref(), &cfg.filter) {
                     inflight.fetch_sub(1, Relaxed);
                     continue;
                 }
                 let mut files = 0u64;
+                let mut bytes = 0u64;
+                // One path buffer per chunk: push each name, pop it after.
+                let mut full = PathBuf::with_capacity(base.as_os_str().len() + 64);
+                full.push(base.as_path());
 
-                for FileItem { name, md } in &items {
-                    let full = base.join(name);
+                for (name, row) in items.iter() {
+                    if !cfg.emits(row) {
+                        continue;
+                    }
+                    full.push(name);
 
                     if verbose >= 2 {
-                        eprintln!("[{:>2}] Processing {}", tid, full.display());
+                        tracing::debug!(tid, path = %full.display(), "processing");
                     }
 
-                    let row = row_from_metadata(md);
-                    if is_bin {
-                        write_row_bin(&mut buf, &full, &row, cfg.no_atime);
-                    } else {
-                        write_row_csv(&mut buf, &full, &row, cfg.no_atime);
+                    // Folders past --max-depth come in these batches too;
+                    // they count and keep their names like entered ones.
+                    let is_dir = Kind::from_mode(row.mode).is_dir();
+                    let live = apply_aliases(&cfg.snapshots, &full);
+                    let out_path = apply_aliases(&cfg.aliases, &live);
+                    let out_path = match &cfg.redact {
+                        Some(r) if !is_dir => Cow::Owned(r.path(&out_path)),
+                        _ => out_path,
+                    };
+                    if is_dir && let Some(changes) = &cfg.changes {
+                        changes.observe(&out_path, row.mtime);
                     }
+                    emit_row(&mut buf, &full, &out_path, row, &cfg, &mut extra);
+                    full.pop();
                     stats.files += 1;
-                    stats.bytes += row.blocks * 512;
+                    stats.kinds.record(row.mode, row.blocks * 512);
                     files += 1;
+                    if !is_dir {
+                        stats.bytes += row.blocks * 512;
+                        stats.exts.add(name, row.blocks * 512);
+                        stats.mtimes.record(row.mtime, row.blocks * 512, cfg.started_at);
+                        bytes += row.blocks * 512;
+                    }
                     if buf.len() >= FLUSH_BYTES {
+                        let t = Instant::now();
                         if let Err(e) = writer.write_all(&buf) {
                             if verbose >= 1 {
-                                eprintln!("ERROR: write failed: {}", e);
+                                tracing::error!(tid, error = %e, "shard write failed");
                             }
                             stats.errors += 1;
                         }
+                        stats.write_time += t.elapsed();
                         buf.clear();
                     }
                 }
+                stats.tally(cfg.sample.as_deref(), &base, files, bytes);
                 inflight.fetch_sub(1, Relaxed);
                 if has_progress {
-                    progress.files.fetch_add(files, Relaxed);
+                    progress.add(files);
                 }
             }
         }
     }
 
-    if !buf.is_empty() {
-        if let Err(e) = writer.write_all(&buf) {
-            if verbose >= 1 {
-                eprintln!("ERROR: final write failed: {}", e);
-            }
-            stats.errors += 1;
+    let t = Instant::now();
+    if !buf.is_empty()
+        && let Err(e) = writer.write_all(&buf)
+    {
+        if verbose >= 1 {
+            tracing::error!(tid, error = %e, "final shard write failed");
         }
+        stats.errors += 1;
     }
     if let Err(e) = writer.flush() {
         if verbose >= 1 {
-            eprintln!("ERROR: flush failed: {}", e);
+            tracing::error!(tid, error = %e, "shard flush failed");
         }
         stats.errors += 1;
     }
+    // Dropping the writer finishes the zstd frame.
+    drop(writer);
+    stats.write_time += t.elapsed();
 
     stats
 }
 
-pub fn enum_dir(
-    dir: &Path,
-    tx: &Sender<Task>,
-    inflight: &AtomicUsize,
-    skip: Option<&str>,
-    verbose: u8,
-) -> u64 {
-    let rd = match fs::read_dir(dir) {
-        Ok(it) => it,
-        Err(e) => {
-            if verbose >= 1 {
-                eprintln!("ERROR: {}: {}", dir.display(), e);
-            }
-            return 1;
+/// How a worker lists one directory: where the work found goes and what is
+/// kept of it.
+pub struct ListCtx<'a> {
+    pub tx: &'a Sender<Task>,
+    pub inflight: &'a AtomicUsize,
+    pub filter: &'a PathFilter,
+    /// Depth the subdirectories are queued at; None past `--max-depth`,
+    /// where they are emitted like files but not entered
+    pub subdirs: Option<usize>,
+    pub types: EntryTypes,
+    pub verbose: u8,
+    /// Read through an `O_NOATIME` descriptor (Linux, see `listing`)
+    pub preserve_atime: bool,
+}
+
+/// List `dir`, queueing its subdirectories and its other entries in
+/// batches as `ctx` says; returns the entries that could not be read.
+pub fn enum_dir(dir: &Path, ctx: &ListCtx, stat_latency: &mut LatencyHistogram) -> u64 {
+    let unreadable = |e: io::Error| {
+        if ctx.verbose >= 1 {
+            tracing::warn!(dir = %dir.display(), error = %e, "cannot read directory");
         }
+        1
     };
+    #[cfg(target_os = "linux")]
+    if ctx.preserve_atime {
+        return match crate::listing::noatime::read_dir(dir) {
+            Ok(rd) => list_entries(dir, rd, ctx, stat_latency),
+            Err(e) => unreadable(e),
+        };
+    }
+    match fs::read_dir(dir) {
+        Ok(rd) => list_entries(dir, rd, ctx, stat_latency),
+        Err(e) => unreadable(e),
+    }
+}
+
+fn list_entries<E: Listed>(
+    dir: &Path,
+    rd: impl Iterator<Item = io::Result<E>>,
+    ctx: &ListCtx,
+    stat_latency: &mut LatencyHistogram,
+) -> u64 {
+    let ListCtx { tx, inflight, filter, subdirs, types, verbose, .. } = *ctx;
     let mut error_count: u64 = 0;
-    let mut page: Vec<FileItem> = Vec::with_capacity(FILE_CHUNK);
+    let mut page = FileBatch::with_capacity(FILE_CHUNK);
+    let mut names = NameBatch::default();
+    let mut stated = 0usize;
     let base_arc = Arc::new(dir.to_path_buf());
 
     for dent in rd {
@@ -250,70 +602,90 @@ pub fn enum_dir(
             Err(e) => {
                 error_count += 1;
                 if verbose >= 1 {
-                    eprintln!("ERROR: entry in {}: {}", dir.display(), e);
+                    tracing::warn!(dir = %dir.display(), error = %e, "cannot read entry");
                 }
                 continue;
             }
         };
-        let name = dent.file_name();
+        let name = dent.name();
         if name == OsStr::new(".") || name == OsStr::new("..") {
             continue;
         }
 
-        let ft = match dent.file_type() {
-            Ok(ft) => ft,
+        let kind = match dent.kind() {
+            Ok(k) => k,
             Err(e) => {
                 error_count += 1;
                 if verbose >= 1 {
-                    eprintln!("ERROR: {}: {}", dent.path().display(), e);
+                    tracing::warn!(path = %dir.join(&name).display(), error = %e, "cannot stat");
                 }
                 continue;
             }
         };
 
-        if ft.is_dir() {
-            let p = dent.path();
-            if should_skip(&p, skip) {
+        if kind.is_dir() {
+            let p = dir.join(&name);
+            if should_skip(&p, filter) {
                 continue;
             }
-            inflight.fetch_add(1, Relaxed);
-            let _ = tx.send(Task::Dir(p));
-        } else {
-            let md = if ft.is_symlink() {
-                match fs::symlink_metadata(dent.path()) {
-                    Ok(m) => m,
-                    Err(e) => {
-                        error_count += 1;
-                        if verbose >= 1 {
-                            eprintln!("ERROR: {}: {}", dent.path().display(), e);
-                        }
-                        continue;
-                    }
+            if let Some(depth) = subdirs {
+                inflight.fetch_add(1, Relaxed);
+                let _ = tx.send(Task::Dir(p, depth));
+                continue;
+            }
+        }
+        // entries filtered by --types or --exclude are dropped before
+        // paying for a stat
+        if types.allows(kind) {
+            if !kind.is_dir()
+                && filter.has_globs()
+                && filter.glob_excludes(dir.join(&name).as_os_str().to_string_lossy().as_bytes())
+            {
+                continue;
+            }
+            if stated >= INLINE_STATS {
+                names.push(&name);
+                if names.len() == FILE_CHUNK {
+                    inflight.fetch_add(1, Relaxed);
+                    let _ = tx.send(Task::Stat {
+                        base: base_arc.clone(),
+                        names: std::mem::replace(&mut names, NameBatch::with_capacity(FILE_CHUNK)),
+                    });
                 }
-            } else {
-                match dent.metadata() {
-                    Ok(m) => m,
-                    Err(e) => {
-                        error_count += 1;
-                        if verbose >= 1 {
-                            eprintln!("ERROR: {}: {}", dent.path().display(), e);
-                        }
-                        continue;
+                continue;
+            }
+            stated += 1;
+            let t = Instant::now();
+            let row = match dent.lstat() {
+                Ok(r) => r,
+                Err(e) => {
+                    error_count += 1;
+                    if verbose >= 1 {
+                        tracing::warn!(path = %dir.join(&name).display(), error = %e, "cannot stat");
                     }
+                    continue;
                 }
             };
 
-            page.push(FileItem { name, md });
+            stat_latency.record(t.elapsed());
+            page.push(&name, row);
             if page.len() == FILE_CHUNK {
                 inflight.fetch_add(1, Relaxed);
                 let _ = tx.send(Task::Files {
                     base: base_arc.clone(),
-                    items: std::mem::take(&mut page),
+                    items: std::mem::replace(&mut page, FileBatch::with_capacity(FILE_CHUNK)),
                 });
             }
         }
     }
 
+    if !names.is_empty() {
+        inflight.fetch_add(1, Relaxed);
+        let _ = tx.send(Task::Stat {
+            base: base_arc.clone(),
+            names,
+        });
+    }
     if !page.is_empty() {
         inflight.fetch_add(1, Relaxed);
         let _ = tx.send(Task::Files {
@@ -325,19 +697,59 @@ pub fn enum_dir(
     error_count
 }
 
+/// `lstat` each of `names` in `base`; returns their rows and the number of
+/// entries that could not be stat'ed (gone since the listing, mostly).
+/// Paths too long to stat go to `long_paths` instead.
+fn stat_names(
+    base: &Path,
+    names: &NameBatch,
+    stat_latency: &mut LatencyHistogram,
+    long_paths: &mut LongPaths,
+    verbose: u8,
+) -> (FileBatch, u64) {
+    let mut page = FileBatch::with_capacity(names.len());
+    let mut errors = 0;
+    let mut full = base.to_path_buf();
+    for name in names.iter() {
+        full.push(name);
+        let t = Instant::now();
+        match fs::symlink_metadata(&full) {
+            Ok(md) => {
+                stat_latency.record(t.elapsed());
+                page.push(name, row_for_path(&full, &md));
+            }
+            Err(e) if longpath::is_too_long(&e) => long_paths.record(&full),
+            Err(e) => {
+                errors += 1;
+                if verbose >= 1 {
+                    tracing::warn!(path = %full.display(), error = %e, "cannot stat");
+                }
+            }
+        }
+        full.pop();
+    }
+    (page, errors)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crossbeam::channel::unbounded;
+    use crate::row::row_from_metadata;
     use dutopia::util::Row;
     use tempfile::tempdir;
 
-    #[test]
-    fn test_should_skip() {
-        let p = PathBuf::from("/a/b/c/d");
-        assert!(should_skip(&p, Some("b/c")));
-        assert!(!should_skip(&p, Some("x")));
-        assert!(!should_skip(&p, None));
+    /// Listing at depth 1 with every entry type, quietly.
+    fn ctx<'a>(tx: &'a Sender<Task>, inflight: &'a AtomicUsize, filter: &'a PathFilter) -> ListCtx<'a> {
+        ListCtx {
+            tx,
+            inflight,
+            filter,
+            subdirs: Some(1),
+            types: EntryTypes::default(),
+            verbose: 0,
+            preserve_atime: false,
+        }
     }
 
     #[test]
@@ -353,7 +765,11 @@ mod tests {
         let (tx, rx) = unbounded();
         let inflight = Arc::new(AtomicUsize::new(0));
 
-        let error_count = enum_dir(test_dir, &tx, &inflight, None, 0);
+        let error_count = enum_dir(
+            test_dir,
+            &ctx(&tx, &inflight, &PathFilter::default()),
+            &mut LatencyHistogram::default(),
+        );
 
         assert_eq!(error_count, 0);
 
@@ -363,8 +779,9 @@ mod tests {
         drop(tx);
         while let Ok(task) = rx.recv() {
             match task {
-                Task::Dir(_) => dir_tasks += 1,
+                Task::Dir(..) => dir_tasks += 1,
                 Task::Files { items, .. } => file_tasks += items.len(),
+                Task::Stat { .. } => unreachable!("small directories are stat'ed in place"),
                 Task::Shutdown => break,
             }
         }
@@ -373,6 +790,127 @@ mod tests {
         assert!(file_tasks >= 2);
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_enum_dir_hands_out_stats_of_huge_directories() {
+        let tmp = tempdir().unwrap();
+        let total = INLINE_STATS + FILE_CHUNK + 5;
+        for i in 0..total {
+            File::create(tmp.path().join(format!("f{i}"))).unwrap();
+        }
+
+        let (tx, rx) = unbounded();
+        let inflight = Arc::new(AtomicUsize::new(0));
+        let mut lat = LatencyHistogram::default();
+        assert_eq!(enum_dir(tmp.path(), &ctx(&tx, &inflight, &PathFilter::default()), &mut lat), 0);
+        drop(tx);
+
+        let (mut stated, mut pending, mut batches) = (0, 0, 0);
+        let mut rows = 0;
+        while let Ok(task) = rx.recv() {
+            match task {
+                Task::Files { items, .. } => stated += items.len(),
+                Task::Stat { base, names } => {
+                    pending += names.len();
+                    batches += 1;
+                    let (items, errors) = stat_names(&base, &names, &mut lat, &mut LongPaths::default(), 0);
+                    assert_eq!(errors, 0);
+                    rows += items.len();
+                }
+                _ => unreachable!(),
+            }
+        }
+        assert_eq!(stated, INLINE_STATS);
+        assert_eq!((pending, rows, batches), (FILE_CHUNK + 5, FILE_CHUNK + 5, 2));
+        assert_eq!(inflight.load(Relaxed), total.div_ceil(FILE_CHUNK));
+    }
+
+    #[test]
+    fn test_enum_dir_types_filter_files_but_walk_dirs() {
+        let tmp = tempdir().unwrap();
+        let test_dir = tmp.path();
+        fs::write(test_dir.join("file1.txt"), "content1").unwrap();
+        fs::create_dir(test_dir.join("subdir")).unwrap();
+
+        let (tx, rx) = unbounded();
+        let inflight = Arc::new(AtomicUsize::new(0));
+        let dirs_only = crate::types::parse_types("d").unwrap();
+        let mut lat = LatencyHistogram::default();
+        let filter = PathFilter::default();
+        let list = ListCtx { types: dirs_only, ..ctx(&tx, &inflight, &filter) };
+        assert_eq!(enum_dir(test_dir, &list, &mut lat), 0);
+
+        drop(tx);
+        let tasks: Vec<Task> = rx.iter().collect();
+        assert_eq!(tasks.len(), 1);
+        assert!(matches!(tasks[0], Task::Dir(..)));
+    }
+
+    #[test]
+    fn test_enum_dir_at_max_depth_lists_subdirs_without_entering() {
+        let tmp = tempdir().unwrap();
+        let test_dir = tmp.path();
+        fs::write(test_dir.join("file1.txt"), "content1").unwrap();
+        fs::create_dir(test_dir.join("subdir")).unwrap();
+        let files_only = crate::types::parse_types("f").unwrap();
+        let filter = PathFilter::default();
+        let mut lat = LatencyHistogram::default();
+
+        for (subdirs, types, want_dirs, want_rows) in [
+            (Some(3), EntryTypes::default(), vec![3], vec!["file1.txt"]),
+            (None, EntryTypes::default(), vec![], vec!["file1.txt", "subdir"]),
+            (None, files_only, vec![], vec!["file1.txt"]),
+        ] {
+            let (tx, rx) = unbounded();
+            let inflight = Arc::new(AtomicUsize::new(0));
+            let list = ListCtx { subdirs, types, ..ctx(&tx, &inflight, &filter) };
+            assert_eq!(enum_dir(test_dir, &list, &mut lat), 0);
+            drop(tx);
+            let (mut dirs, mut rows) = (Vec::new(), Vec::new());
+            for task in rx.iter() {
+                match task {
+                    Task::Dir(_, depth) => dirs.push(depth),
+                    Task::Files { items, .. } => {
+                        rows.extend(items.iter().map(|(n, _)| n.to_string_lossy().into_owned()))
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            rows.sort();
+            assert_eq!(dirs, want_dirs);
+            assert_eq!(rows, want_rows);
+        }
+    }
+
+    #[test]
+    fn test_excluded_fstype() {
+        let tmp = tempdir().unwrap();
+        let mut cfg = Config::default();
+        assert!(!cfg.excluded_fstype(tmp.path()));
+        cfg.exclude_fstypes = vec!["no-such-fs".to_string()];
+        assert!(!cfg.excluded_fstype(tmp.path()));
+        if let Some(t) = fs_type(tmp.path()) {
+            cfg.exclude_fstypes.push(t);
+            assert!(cfg.excluded_fstype(tmp.path()));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_other_device() {
+        use std::os::unix::fs::MetadataExt;
+        let tmp = tempdir().unwrap();
+        let dev = fs::metadata(tmp.path()).unwrap().dev();
+        let mut cfg = Config::default();
+        assert!(!cfg.other_device(tmp.path()));
+        cfg.devices = Some(Arc::new(vec![dev]));
+        assert!(!cfg.other_device(tmp.path()));
+        cfg.devices = Some(Arc::new(vec![dev.wrapping_add(1)]));
+        assert!(cfg.other_device(tmp.path()));
+        // unreadable paths are left to the walk to report
+        assert!(!cfg.other_device(&tmp.path().join("missing")));
+    }
+
     #[test]
     fn test_enum_dir_with_skip() {
         let tmp = tempdir().unwrap();
@@ -384,7 +922,11 @@ mod tests {
         let (tx, rx) = unbounded();
         let inflight = Arc::new(AtomicUsize::new(0));
 
-        let error_count = enum_dir(test_dir, &tx, &inflight, Some("skip_me"), 0);
+        let error_count = enum_dir(
+            test_dir,
+            &ctx(&tx, &inflight, &PathFilter::new(Some("skip_me"), &[]).unwrap()),
+            &mut LatencyHistogram::default(),
+        );
         assert_eq!(error_count, 0);
 
         drop(tx);
@@ -392,7 +934,7 @@ mod tests {
         let mut found_keep = false;
 
         while let Ok(task) = rx.recv() {
-            if let Task::Dir(path) = task {
+            if let Task::Dir(path, _) = task {
                 if path.file_name().unwrap() == "skip_me" {
                     found_skip = true;
                 }
@@ -406,13 +948,51 @@ mod tests {
         assert!(found_keep);
     }
 
+    #[test]
+    fn test_enum_dir_with_exclude_globs() {
+        let tmp = tempdir().unwrap();
+        let test_dir = tmp.path();
+        fs::create_dir(test_dir.join(".git")).unwrap();
+        fs::create_dir(test_dir.join("src")).unwrap();
+        for name in ["a.tmp", "b.txt", "keep.tmp"] {
+            fs::write(test_dir.join(name), "x").unwrap();
+        }
+        let filter = PathFilter::new(None, &["**/.git".to_string(), "*.tmp".to_string()])
+            .unwrap()
+            .with_include(&["**/keep.tmp".to_string()])
+            .unwrap();
+
+        let (tx, rx) = unbounded();
+        let inflight = Arc::new(AtomicUsize::new(0));
+        let mut lat = LatencyHistogram::default();
+        assert_eq!(enum_dir(test_dir, &ctx(&tx, &inflight, &filter), &mut lat), 0);
+        drop(tx);
+
+        let mut names = Vec::new();
+        for task in rx.iter() {
+            match task {
+                Task::Dir(p, _) => names.push(p.file_name().unwrap().to_string_lossy().into_owned()),
+                Task::Files { items, .. } => {
+                    names.extend(items.iter().map(|(n, _)| n.to_string_lossy().into_owned()))
+                }
+                _ => {}
+            }
+        }
+        names.sort();
+        assert_eq!(names, ["b.txt", "keep.tmp", "src"]);
+    }
+
     #[test]
     fn test_enum_dir_nonexistent() {
         let nonexistent = Path::new("/nonexistent/directory");
         let (tx, _rx) = unbounded();
         let inflight = Arc::new(AtomicUsize::new(0));
 
-        let error_count = enum_dir(nonexistent, &tx, &inflight, None, 0);
+        let error_count = enum_dir(
+            nonexistent,
+            &ctx(&tx, &inflight, &PathFilter::default()),
+            &mut LatencyHistogram::default(),
+        );
         assert_eq!(error_count, 1);
     }
 
@@ -428,7 +1008,11 @@ mod tests {
         let (tx, rx) = unbounded();
         let inflight = Arc::new(AtomicUsize::new(0));
 
-        let error_count = enum_dir(test_dir, &tx, &inflight, None, 0);
+        let error_count = enum_dir(
+            test_dir,
+            &ctx(&tx, &inflight, &PathFilter::default()),
+            &mut LatencyHistogram::default(),
+        );
         assert_eq!(error_count, 0);
 
         drop(tx);
@@ -449,24 +1033,25 @@ mod tests {
 
     #[test]
     fn test_progress_default() {
-        let progress = Progress::default();
-        assert_eq!(progress.files.load(Relaxed), 0);
+        let progress = Counter::default();
+        assert_eq!(progress.get(), 0);
     }
 
     #[test]
     fn test_config_clone() {
-        let progress = Arc::new(Progress::default());
+        let progress = Arc::new(Counter::default());
         let config = Config {
-            skip: Some("test".to_string()),
+            filter: PathFilter::new(Some("test"), &[]).unwrap(),
             out_fmt: OutputFormat::Csv,
             no_atime: true,
             progress: Some(progress.clone()),
             pid: 123,
             verbose: 0,
+            ..Default::default()
         };
 
         let cloned = config.clone();
-        assert_eq!(cloned.skip, Some("test".to_string()));
+        assert!(cloned.filter.prunes(b"/a/test"));
         assert_eq!(cloned.out_fmt, OutputFormat::Csv);
         assert!(cloned.no_atime);
         assert_eq!(cloned.pid, 123);
@@ -482,24 +1067,22 @@ mod tests {
     }
 
     #[test]
-    fn test_file_item_debug() {
+    fn test_file_batch_debug() {
         let tmp = tempdir().unwrap();
         let test_file = tmp.path().join("test.txt");
         fs::write(&test_file, "content").unwrap();
         let metadata = fs::metadata(&test_file).unwrap();
 
-        let item = FileItem {
-            name: "test.txt".into(),
-            md: metadata,
-        };
+        let mut items = FileBatch::default();
+        items.push(OsStr::new("test.txt"), row_from_metadata(&metadata));
 
-        let debug_str = format!("{:?}", item);
+        let debug_str = format!("{:?}", items);
         assert!(debug_str.contains("test.txt"));
     }
 
     #[test]
     fn test_task_debug() {
-        let dir_task = Task::Dir("/test/path".into());
+        let dir_task = Task::Dir("/test/path".into(), 0);
         let debug_str = format!("{:?}", dir_task);
         assert!(debug_str.contains("Dir"));
         assert!(debug_str.contains("test/path"));
@@ -510,7 +1093,7 @@ mod tests {
 
         let files_task = Task::Files {
             base: Arc::new("/base".into()),
-            items: vec![],
+            items: FileBatch::default(),
         };
         let debug_str = format!("{:?}", files_task);
         assert!(debug_str.contains("Files"));
@@ -531,7 +1114,11 @@ mod tests {
         let (tx, rx) = unbounded();
         let inflight = Arc::new(AtomicUsize::new(0));
 
-        let error_count = enum_dir(test_dir, &tx, &inflight, None, 0);
+        let error_count = enum_dir(
+            test_dir,
+            &ctx(&tx, &inflight, &PathFilter::default()),
+            &mut LatencyHistogram::default(),
+        );
         assert_eq!(error_count, 0);
 
         drop(tx);
@@ -557,7 +1144,11 @@ mod tests {
         let (tx, rx) = unbounded();
         let inflight = Arc::new(AtomicUsize::new(0));
 
-        let error_count = enum_dir(test_dir, &tx, &inflight, None, 0);
+        let error_count = enum_dir(
+            test_dir,
+            &ctx(&tx, &inflight, &PathFilter::default()),
+            &mut LatencyHistogram::default(),
+        );
         assert_eq!(error_count, 0);
 
         drop(tx);
@@ -565,8 +1156,8 @@ mod tests {
         let mut found_files = Vec::new();
         while let Ok(task) = rx.recv() {
             if let Task::Files { items, .. } = task {
-                for item in items {
-                    found_files.push(item.name.to_string_lossy().to_string());
+                for (name, _) in items.iter() {
+                    found_files.push(name.to_string_lossy().to_string());
                 }
             }
         }
@@ -593,7 +1184,11 @@ mod tests {
         let (tx, _rx) = unbounded();
         let inflight = Arc::new(AtomicUsize::new(0));
 
-        let error_count = enum_dir(&test_dir, &tx, &inflight, None, 0);
+        let error_count = enum_dir(
+            &test_dir,
+            &ctx(&tx, &inflight, &PathFilter::default()),
+            &mut LatencyHistogram::default(),
+        );
 
         let mut perms = fs::metadata(&test_dir).unwrap().permissions();
         perms.set_mode(0o755);
@@ -605,12 +1200,13 @@ mod tests {
     #[test]
     fn test_config_variations() {
         let cfg1 = Config {
-            skip: None,
+            filter: PathFilter::default(),
             out_fmt: OutputFormat::Bin,
             no_atime: false,
             progress: None,
             pid: 1,
             verbose: 0,
+            ..Default::default()
         };
 
         let cfg2 = cfg1.clone();
@@ -627,26 +1223,25 @@ mod tests {
 
         let (tx, rx) = unbounded();
         let inflight = Arc::new(AtomicUsize::new(0));
-        let progress = Arc::new(Progress::default());
+        let progress = Arc::new(Counter::default());
 
         let cfg = Config {
-            skip: None,
+            filter: PathFilter::default(),
             out_fmt: OutputFormat::Csv,
             no_atime: false,
             progress: Some(progress.clone()),
             pid: 12345,
             verbose: 0,
+            ..Default::default()
         };
 
         let metadata = fs::metadata(&test_file).unwrap();
-        let file_item = FileItem {
-            name: "test.txt".into(),
-            md: metadata,
-        };
+        let mut file_item = FileBatch::default();
+        file_item.push(OsStr::new("test.txt"), row_from_metadata(&metadata));
 
         tx.send(Task::Files {
             base: Arc::new(tmp.path().to_path_buf()),
-            items: vec![file_item],
+            items: file_item,
         })
         .unwrap();
 
@@ -660,7 +1255,26 @@ mod tests {
 
         assert_eq!(stats.files, 1);
         assert_eq!(stats.errors, 0);
-        assert!(progress.files.load(Relaxed) >= 1);
+        assert!(progress.get() >= 1);
+    }
+
+    #[test]
+    fn test_worker_past_deadline_enters_no_directory() {
+        let tmp = tempdir().unwrap();
+        fs::create_dir(tmp.path().join("sub")).unwrap();
+        let (tx, rx) = unbounded();
+        let inflight = Arc::new(AtomicUsize::new(2));
+        let cfg = Config {
+            deadline: Some(Instant::now()),
+            ..Default::default()
+        };
+        tx.send(Task::Dir(tmp.path().to_path_buf(), 0)).unwrap();
+        tx.send(Task::Dir(tmp.path().join("sub"), 1)).unwrap();
+        tx.send(Task::Shutdown).unwrap();
+        let (dummy_tx, _) = unbounded();
+        let stats = worker(0, rx, dummy_tx, inflight.clone(), tmp.path().to_path_buf(), cfg);
+        assert_eq!((stats.unvisited, stats.dirs, stats.files), (2, 0, 0));
+        assert_eq!(inflight.load(Relaxed), 0);
     }
 
     #[test]
@@ -673,23 +1287,22 @@ mod tests {
         let inflight = Arc::new(AtomicUsize::new(0));
 
         let cfg = Config {
-            skip: None,
+            filter: PathFilter::default(),
             out_fmt: OutputFormat::Bin,
             no_atime: true,
             progress: None,
             pid: 12345,
             verbose: 0,
+            ..Default::default()
         };
 
         let metadata = fs::metadata(&test_file).unwrap();
-        let file_item = FileItem {
-            name: "test.txt".into(),
-            md: metadata,
-        };
+        let mut file_item = FileBatch::default();
+        file_item.push(OsStr::new("test.txt"), row_from_metadata(&metadata));
 
         tx.send(Task::Files {
             base: Arc::new(tmp.path().to_path_buf()),
-            items: vec![file_item],
+            items: file_item,
         })
         .unwrap();
 
@@ -715,15 +1328,16 @@ mod tests {
         let inflight = Arc::new(AtomicUsize::new(0));
 
         let cfg = Config {
-            skip: Some("skip_this".to_string()),
+            filter: PathFilter::new(Some("skip_this"), &[]).unwrap(),
             out_fmt: OutputFormat::Csv,
             no_atime: false,
             progress: None,
             pid: 12345,
             verbose: 0,
+            ..Default::default()
         };
 
-        tx.send(Task::Dir(skip_dir)).unwrap();
+        tx.send(Task::Dir(skip_dir, 0)).unwrap();
         tx.send(Task::Shutdown).unwrap();
         drop(tx);
 
@@ -746,23 +1360,22 @@ mod tests {
         let inflight = Arc::new(AtomicUsize::new(0));
 
         let cfg = Config {
-            skip: Some("skip_this".to_string()),
+            filter: PathFilter::new(Some("skip_this"), &[]).unwrap(),
             out_fmt: OutputFormat::Csv,
             no_atime: false,
             progress: None,
             pid: 12345,
             verbose: 0,
+            ..Default::default()
         };
 
         let metadata = fs::metadata(&test_file).unwrap();
-        let file_item = FileItem {
-            name: "test.txt".into(),
-            md: metadata,
-        };
+        let mut file_item = FileBatch::default();
+        file_item.push(OsStr::new("test.txt"), row_from_metadata(&metadata));
 
         tx.send(Task::Files {
             base: Arc::new(skip_base),
-            items: vec![file_item],
+            items: file_item,
         })
         .unwrap();
 
@@ -784,16 +1397,17 @@ mod tests {
         let inflight = Arc::new(AtomicUsize::new(0));
 
         let cfg = Config {
-            skip: None,
+            filter: PathFilter::default(),
             out_fmt: OutputFormat::Csv,
             no_atime: false,
             progress: None,
             pid: 12345,
             verbose: 0,
+            ..Default::default()
         };
 
         let nonexistent = tmp.path().join("nonexistent");
-        tx.send(Task::Dir(nonexistent)).unwrap();
+        tx.send(Task::Dir(nonexistent, 0)).unwrap();
         tx.send(Task::Shutdown).unwrap();
         drop(tx);
 
@@ -858,15 +1472,16 @@ mod tests {
         ] {
             let (tx, rx) = unbounded();
             let inflight = Arc::new(AtomicUsize::new(0));
-            let progress = Arc::new(Progress::default());
+            let progress = Arc::new(Counter::default());
 
             let cfg = Config {
-                skip: None,
+                filter: PathFilter::default(),
                 out_fmt: output_format,
                 no_atime,
                 progress: Some(progress.clone()),
                 pid: 98765,
                 verbose: 0,
+                ..Default::default()
             };
 
             let files = [
@@ -877,14 +1492,12 @@ mod tests {
             for file_name in &files {
                 let file_path = test_dir.join(file_name);
                 if let Ok(metadata) = fs::metadata(&file_path) {
-                    let file_item = FileItem {
-                        name: (*file_name).into(),
-                        md: metadata,
-                    };
+                    let mut file_item = FileBatch::default();
+                    file_item.push(OsStr::new(*file_name), row_from_metadata(&metadata));
 
                     tx.send(Task::Files {
                         base: Arc::new(test_dir.clone()),
-                        items: vec![file_item],
+                        items: file_item,
                     })
                     .unwrap();
                 }
@@ -900,7 +1513,7 @@ mod tests {
             assert!(stats.files >= 3);
             assert_eq!(stats.errors, 0);
             assert!(stats.bytes > 0);
-            assert!(progress.files.load(Relaxed) >= 3);
+            assert!(progress.get() >= 3);
 
             let shard_path = out_dir.join(format!("shard_{}_{}_0.tmp", get_hostname(), 98765));
             if shard_path.exists() {
@@ -0,0 +1,193 @@
+// rs/src/bin/duscand/main.rs
+//
+// duscand: a long-lived scan agent for storage nodes. It runs duscan on
+// request over a small HTTP API (see scans.rs), so an orchestration layer
+// can start, watch, cancel and collect scans without SSH and a shell
+// wrapper on every node. All routes but `/health` need
+// `Authorization: Bearer <--token>`.
+use anyhow::{bail, Context, Result};
+use axum::{
+    extract::{Request, State as AxState},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use clap::{ColorChoice, Parser};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use dutopia::util::logging::{init_cli_tracing, LogArgs};
+use dutopia::util::{format_duration, get_hostname, parse_duration, print_about};
+
+mod scans;
+
+use scans::Agent;
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    color = ColorChoice::Auto,
+    about = "Scan agent: run duscan on request over a small HTTP API"
+)]
+struct Args {
+    /// Directory for scan outputs, reports, logs and shards
+    #[arg(long, value_name = "DIR", env = "DUSCAND_WORK_DIR", default_value = ".")]
+    work_dir: PathBuf,
+    /// duscan executable (default: next to duscand)
+    #[arg(long, value_name = "FILE")]
+    duscan: Option<PathBuf>,
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1", env = "DUSCAND_ADDR")]
+    addr: IpAddr,
+    /// Port to listen on
+    #[arg(long, default_value_t = 8010, env = "DUSCAND_PORT")]
+    port: u16,
+    /// Bearer token clients must send
+    #[arg(long, env = "DUSCAND_TOKEN", hide_env_values = true)]
+    token: String,
+    /// Scans run at the same time; later ones wait queued
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    max_scans: usize,
+    /// Delete finished scans and their files this long after they end
+    #[arg(long, value_name = "DURATION", default_value = "7d", value_parser = parse_duration)]
+    keep_for: Duration,
+    #[command(flatten)]
+    log: LogArgs,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    print_about();
+    let args = Args::parse();
+    init_cli_tracing("duscand", &args.log, "info")?;
+    if args.token.trim().is_empty() {
+        bail!("--token is empty");
+    }
+    let duscan = match args.duscan {
+        Some(p) => p,
+        None => std::env::current_exe()
+            .ok()
+            .and_then(|e| e.parent().map(Path::to_path_buf))
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join(format!("duscan{}", std::env::consts::EXE_SUFFIX)),
+    };
+    if !duscan.is_file() {
+        bail!("duscan not found at {}; use --duscan", duscan.display());
+    }
+    let agent = Arc::new(Agent::new(
+        args.work_dir.clone(),
+        duscan.clone(),
+        args.max_scans,
+        args.keep_for,
+    )?);
+
+    println!("Work dir     : {}", args.work_dir.display());
+    println!("duscan       : {}", duscan.display());
+    println!("Max scans    : {}", args.max_scans.max(1));
+    println!("Keep for     : {}", format_duration(args.keep_for));
+
+    let app = app(agent, &args.token);
+    let addr = SocketAddr::new(args.addr, args.port);
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("binding {addr}"))?;
+    println!("Serving on http://{addr}");
+    // Running duscan children are killed when the agent exits.
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+    Ok(())
+}
+
+fn app(agent: Arc<Agent>, token: &str) -> Router {
+    let digest: Arc<[u8]> = Sha256::digest(token.as_bytes()).to_vec().into();
+    let scans = scans::router(agent.clone()).layer(middleware::from_fn_with_state(digest, require_token));
+    Router::new()
+        .route("/health", get(health_handler))
+        .with_state(agent)
+        .merge(scans)
+}
+
+/// Compare digests rather than the tokens, so the time taken says nothing
+/// about how much of a guess was right.
+async fn require_token(AxState(digest): AxState<Arc<[u8]>>, req: Request, next: Next) -> Response {
+    let given = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|t| Sha256::digest(t.trim().as_bytes()));
+    if given.is_some_and(|g| g.as_slice() == &digest[..]) {
+        return next.run(req).await;
+    }
+    tracing::warn!("401 Unauthorized {} {}", req.method(), req.uri().path());
+    (StatusCode::UNAUTHORIZED, "missing or wrong bearer token").into_response()
+}
+
+async fn health_handler(AxState(agent): AxState<Arc<Agent>>) -> Response {
+    let (running, queued) = agent.load();
+    Json(json!({
+        "status": "ok",
+        "version": env!("CARGO_PKG_VERSION"),
+        "host": get_hostname(),
+        "running": running,
+        "queued": queued,
+    }))
+    .into_response()
+}
+
+/// Ctrl+C, or SIGTERM on Unix.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut s) => {
+                s.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
+    tracing::info!("shutting down");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn scans_need_the_token_but_health_does_not() {
+        let tmp = tempfile::tempdir().unwrap();
+        let agent = Arc::new(Agent::new(tmp.path().into(), "duscan".into(), 1, Duration::from_secs(60)).unwrap());
+        let app = app(agent, "s3cret");
+        let get = |uri: &str, auth: Option<&str>| {
+            let mut b = axum::http::Request::builder().uri(uri);
+            if let Some(a) = auth {
+                b = b.header(header::AUTHORIZATION, a);
+            }
+            b.body(Body::empty()).unwrap()
+        };
+
+        let resp = app.clone().oneshot(get("/health", None)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = app.clone().oneshot(get("/scans", None)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let resp = app.clone().oneshot(get("/scans", Some("Bearer s3cre"))).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let resp = app.clone().oneshot(get("/scans", Some("Bearer s3cret"))).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,686 @@
+// rs/src/bin/duscand/scans.rs
+//
+// The scan queue of duscand and its HTTP API. Every scan is a `duscan`
+// child process run with `-o <work_dir>/scan-<id>.csv` (`.zst` with
+// `"bin": true`), `--report scan-<id>.report.json` and `--progress-json`.
+// Its JSON progress lines become the `done`/`total`/`percent` of the scan;
+// everything else it prints goes to `scan-<id>.log`.
+//
+//   POST /scans               {"folders": [...], "args": [...], "bin": false}
+//                             -> 202 with the queued scan
+//   GET  /scans               all scans, oldest first
+//   GET  /scans/{id}          one scan; `report` is the run report once done
+//   POST /scans/{id}/cancel   queued: dropped; running: duscan is killed and
+//                             its shards, sorted runs and partial output
+//                             removed
+//   GET  /scans/{id}/result   the output file, once done (409 before)
+//   GET  /scans/{id}/manifest its `.meta.json`, for dusum to check it
+//   GET  /scans/{id}/log      what duscan printed so far
+//
+// `args` are passed to duscan after the folders. Only the flags in
+// `dutopia::scanargs` are taken (workers, filters, depth, limits...);
+// output flags are set by duscand, `--bin` is the `bin` field, and flags
+// that read or load files on this host (`--enrich`, `--since`...) are
+// refused. `--max-scans` run at once; the rest wait queued. Scans are kept in
+// memory: a restart forgets them, and ids continue after the highest log in
+// the work dir so files are never reused.
+// Finished scans and their files are deleted `--keep-for` after they end.
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Path as UrlPath, Request, State as AxState},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::{Notify, Semaphore};
+use tower::ServiceExt;
+use tower_http::services::ServeFile;
+
+use dutopia::util::exit::Outcome;
+use dutopia::manifest::manifest_path;
+use dutopia::util::{get_hostname, temp_path_for};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl State {
+    fn finished(self) -> bool {
+        matches!(self, State::Done | State::Failed | State::Cancelled)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScanReq {
+    pub folders: Vec<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Write a .zst (`duscan --bin`) instead of a CSV
+    #[serde(default)]
+    pub bin: bool,
+}
+
+/// The fields duscand reads from a `--progress-json` line.
+#[derive(Debug, Default, Clone, Deserialize)]
+struct Progress {
+    done: u64,
+    total: Option<u64>,
+    percent: Option<f64>,
+    per_sec: f64,
+    #[serde(default)]
+    eta_secs: Option<f64>,
+}
+
+#[derive(Default)]
+struct Status {
+    state: Option<State>,
+    started_at: Option<i64>,
+    finished_at: Option<i64>,
+    exit_code: Option<i32>,
+    error: Option<String>,
+    progress: Progress,
+    report: Option<Value>,
+}
+
+struct Scan {
+    id: u64,
+    folders: Vec<String>,
+    args: Vec<String>,
+    bin: bool,
+    created_at: i64,
+    status: Mutex<Status>,
+    cancel: Notify,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScanOut {
+    pub id: u64,
+    pub folders: Vec<String>,
+    pub args: Vec<String>,
+    pub state: State,
+    pub created_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<i64>,
+    /// Files scanned so far
+    pub done: u64,
+    /// Expected files, when duscan has a hint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percent: Option<f64>,
+    pub per_sec: f64,
+    /// Seconds left at the recent rate, when duscan has a hint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta_secs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Download link, once done
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_url: Option<String>,
+    /// duscan's run report, once done
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<Value>,
+}
+
+impl Scan {
+    fn state(&self) -> State {
+        self.lock().state.unwrap_or(State::Queued)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Status> {
+        self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn out(&self) -> ScanOut {
+        let st = self.lock();
+        let state = st.state.unwrap_or(State::Queued);
+        ScanOut {
+            id: self.id,
+            folders: self.folders.clone(),
+            args: self.args.clone(),
+            state,
+            created_at: self.created_at,
+            started_at: st.started_at,
+            finished_at: st.finished_at,
+            done: st.progress.done,
+            total: st.progress.total,
+            percent: st.progress.percent,
+            per_sec: st.progress.per_sec,
+            eta_secs: st.progress.eta_secs.filter(|_| state == State::Running),
+            exit_code: st.exit_code,
+            error: st.error.clone(),
+            result_url: (state == State::Done).then(|| format!("/scans/{}/result", self.id)),
+            report: st.report.clone(),
+        }
+    }
+
+    fn finish(&self, state: State, error: Option<String>) {
+        let mut st = self.lock();
+        st.state = Some(state);
+        st.error = error;
+        st.finished_at = Some(Utc::now().timestamp());
+    }
+}
+
+pub struct Agent {
+    work_dir: PathBuf,
+    duscan: PathBuf,
+    scans: Mutex<HashMap<u64, Arc<Scan>>>,
+    next_id: AtomicU64,
+    slots: Arc<Semaphore>,
+    keep_for: Duration,
+}
+
+impl Agent {
+    pub fn new(work_dir: PathBuf, duscan: PathBuf, max_scans: usize, keep_for: Duration) -> Result<Self> {
+        std::fs::create_dir_all(&work_dir)
+            .with_context(|| format!("creating work dir {}", work_dir.display()))?;
+        let last = std::fs::read_dir(&work_dir)
+            .with_context(|| format!("reading work dir {}", work_dir.display()))?
+            .filter_map(|e| {
+                let name = e.ok()?.file_name().into_string().ok()?;
+                name.strip_prefix("scan-")?.strip_suffix(".log")?.parse::<u64>().ok()
+            })
+            .max()
+            .unwrap_or(0);
+        Ok(Self {
+            work_dir,
+            duscan,
+            scans: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(last + 1),
+            slots: Arc::new(Semaphore::new(max_scans.max(1))),
+            keep_for,
+        })
+    }
+
+    fn scans(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Arc<Scan>>> {
+        self.scans.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get(&self, id: u64) -> Option<Arc<Scan>> {
+        self.scans().get(&id).cloned()
+    }
+
+    /// (running, queued) scans.
+    pub fn load(&self) -> (usize, usize) {
+        let scans = self.scans();
+        let count = |s: State| scans.values().filter(|j| j.state() == s).count();
+        (count(State::Running), count(State::Queued))
+    }
+
+    fn output(&self, id: u64, bin: bool) -> PathBuf {
+        self.work_dir
+            .join(format!("scan-{id}.{}", if bin { "zst" } else { "csv" }))
+    }
+
+    fn log(&self, id: u64) -> PathBuf {
+        self.work_dir.join(format!("scan-{id}.log"))
+    }
+
+    fn report(&self, id: u64) -> PathBuf {
+        self.work_dir.join(format!("scan-{id}.report.json"))
+    }
+
+    /// Forget scans that ended more than `keep_for` ago and delete their
+    /// files.
+    fn prune(&self) {
+        let cutoff = Utc::now().timestamp() - self.keep_for.as_secs() as i64;
+        let mut scans = self.scans();
+        scans.retain(|_, s| {
+            let old = s.lock().finished_at.is_some_and(|t| t < cutoff);
+            if old {
+                let out = self.output(s.id, s.bin);
+                let meta = PathBuf::from(format!("{}.meta.json", out.display()));
+                for p in [out, meta, self.log(s.id), self.report(s.id)] {
+                    let _ = std::fs::remove_file(p);
+                }
+            }
+            !old
+        });
+    }
+}
+
+/// Delete what a killed duscan leaves in the work dir: its shards and
+/// sorted runs, the partial output (the merge's `AtomicFile` temp) and the
+/// manifest of the unfinished run.
+fn remove_leftovers(work_dir: &Path, pid: u32, output: &Path) {
+    let host = get_hostname();
+    let tags = [format!("shard_{host}_{pid}_"), format!("sort_{host}_{pid}_run")];
+    if let Ok(entries) = std::fs::read_dir(work_dir) {
+        for e in entries.flatten() {
+            let name = e.file_name();
+            let name = name.to_string_lossy();
+            if tags.iter().any(|t| name.starts_with(t.as_str())) && name.ends_with(".tmp") {
+                let _ = std::fs::remove_file(e.path());
+            }
+        }
+    }
+    let _ = std::fs::remove_file(temp_path_for(output, pid));
+    let _ = std::fs::remove_file(manifest_path(output));
+}
+
+async fn run(agent: Arc<Agent>, scan: Arc<Scan>) {
+    let Ok(_permit) = agent.slots.clone().acquire_owned().await else {
+        return;
+    };
+    {
+        let mut st = scan.lock();
+        if st.state.is_some_and(State::finished) {
+            return;
+        }
+        st.state = Some(State::Running);
+        st.started_at = Some(Utc::now().timestamp());
+    }
+    tracing::info!(id = scan.id, folders = ?scan.folders, "scan started");
+    match execute(&agent, &scan).await {
+        Ok(state) => {
+            tracing::info!(id = scan.id, state = ?state, "scan ended");
+            if state == State::Done {
+                scan.lock().report = std::fs::read(agent.report(scan.id))
+                    .ok()
+                    .and_then(|b| serde_json::from_slice(&b).ok());
+            }
+            let error = scan.lock().error.take();
+            scan.finish(state, error);
+        }
+        Err(e) => {
+            tracing::error!(id = scan.id, err = %format!("{e:#}"), "scan failed to run");
+            scan.finish(State::Failed, Some(format!("{e:#}")));
+        }
+    }
+}
+
+/// Run duscan for `scan` until it exits or is cancelled.
+async fn execute(agent: &Agent, scan: &Scan) -> Result<State> {
+    let output = agent.output(scan.id, scan.bin);
+    let log_path = agent.log(scan.id);
+    let log = std::fs::File::create(&log_path)
+        .with_context(|| format!("creating log {}", log_path.display()))?;
+    let mut cmd = Command::new(&agent.duscan);
+    cmd.args(&scan.folders)
+        .args(&scan.args)
+        .arg("-o")
+        .arg(&output)
+        .arg("--report")
+        .arg(agent.report(scan.id))
+        .arg("--progress-json");
+    if scan.bin {
+        cmd.arg("--bin");
+    }
+    let mut child = cmd
+        .current_dir(&agent.work_dir)
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("starting {}", agent.duscan.display()))?;
+    let pid = child.id().unwrap_or(0);
+
+    // Progress lines update the scan; the rest is appended to the log, and
+    // the last of them is kept as the error should duscan fail.
+    let stderr = child.stderr.take().context("duscan stderr")?;
+    let mut log = tokio::fs::File::from_std(log);
+    let mut lines = BufReader::new(stderr).lines();
+    let reader = async {
+        let mut last = None;
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Ok(p) = serde_json::from_str::<Progress>(&line) {
+                scan.lock().progress = p;
+                continue;
+            }
+            let _ = log.write_all(format!("{line}\n").as_bytes()).await;
+            if !line.trim().is_empty() {
+                last = Some(line);
+            }
+        }
+        last
+    };
+
+    let (status, last) = tokio::select! {
+        (status, last) = async { tokio::join!(child.wait(), reader) } => (status?, last),
+        _ = scan.cancel.notified() => {
+            let _ = child.start_kill();
+            let _ = child.wait().await;
+            remove_leftovers(&agent.work_dir, pid, &output);
+            let _ = std::fs::remove_file(&output);
+            return Ok(State::Cancelled);
+        }
+    };
+    let mut st = scan.lock();
+    st.exit_code = status.code();
+    // Exit 1 (unreadable entries) and 3 (time limit) still wrote the scan;
+    // `exit_code` tells them apart.
+    if status.code().and_then(Outcome::from_code).is_some_and(Outcome::wrote_output) {
+        return Ok(State::Done);
+    }
+    st.error = Some(match last {
+        Some(line) => format!("duscan exited with {status}: {line}"),
+        None => format!("duscan exited with {status}"),
+    });
+    Ok(State::Failed)
+}
+
+fn not_found(route: &str) -> Response {
+    tracing::warn!("404 Not Found {route}");
+    (StatusCode::NOT_FOUND, "scan not found").into_response()
+}
+
+async fn create_handler(AxState(agent): AxState<Arc<Agent>>, Json(req): Json<ScanReq>) -> Response {
+    let folders: Vec<String> = req
+        .folders
+        .iter()
+        .map(|f| f.trim().to_string())
+        .filter(|f| !f.is_empty())
+        .collect();
+    if folders.is_empty() {
+        tracing::warn!("400 Bad Request POST /scans without folders");
+        return (StatusCode::BAD_REQUEST, "folders is empty").into_response();
+    }
+    if let Err(msg) = dutopia::scanargs::check(&folders, &req.args) {
+        tracing::warn!(msg = %msg, "400 Bad Request POST /scans");
+        return (StatusCode::BAD_REQUEST, msg).into_response();
+    }
+    agent.prune();
+    let scan = Arc::new(Scan {
+        id: agent.next_id.fetch_add(1, Ordering::Relaxed),
+        folders,
+        args: req.args,
+        bin: req.bin,
+        created_at: Utc::now().timestamp(),
+        status: Mutex::new(Status::default()),
+        cancel: Notify::new(),
+    });
+    agent.scans().insert(scan.id, scan.clone());
+    tokio::spawn(run(agent.clone(), scan.clone()));
+    tracing::info!(id = scan.id, "202 Accepted POST /scans");
+    (StatusCode::ACCEPTED, Json(scan.out())).into_response()
+}
+
+async fn list_handler(AxState(agent): AxState<Arc<Agent>>) -> Response {
+    agent.prune();
+    let mut out: Vec<ScanOut> = agent.scans().values().map(|s| s.out()).collect();
+    out.sort_by_key(|s| s.id);
+    tracing::info!(scans = out.len(), "200 OK GET /scans");
+    Json(out).into_response()
+}
+
+async fn get_handler(AxState(agent): AxState<Arc<Agent>>, UrlPath(id): UrlPath<u64>) -> Response {
+    match agent.get(id) {
+        Some(scan) => Json(scan.out()).into_response(),
+        None => not_found("GET /scans/{id}"),
+    }
+}
+
+async fn cancel_handler(AxState(agent): AxState<Arc<Agent>>, UrlPath(id): UrlPath<u64>) -> Response {
+    let Some(scan) = agent.get(id) else {
+        return not_found("POST /scans/{id}/cancel");
+    };
+    match scan.state() {
+        State::Queued => scan.finish(State::Cancelled, None),
+        State::Running => scan.cancel.notify_one(),
+        state => {
+            tracing::warn!(id, state = ?state, "409 Conflict POST /scans/{{id}}/cancel");
+            return (StatusCode::CONFLICT, "scan already ended").into_response();
+        }
+    }
+    tracing::info!(id, "202 Accepted POST /scans/{{id}}/cancel");
+    (StatusCode::ACCEPTED, Json(scan.out())).into_response()
+}
+
+async fn result_handler(
+    AxState(agent): AxState<Arc<Agent>>,
+    UrlPath(id): UrlPath<u64>,
+    req: Request,
+) -> Response {
+    let Some(scan) = agent.get(id) else {
+        return not_found("GET /scans/{id}/result");
+    };
+    if scan.state() != State::Done {
+        tracing::warn!(id, "409 Conflict GET /scans/{{id}}/result");
+        return (StatusCode::CONFLICT, "scan has no result yet").into_response();
+    }
+    let path = agent.output(id, scan.bin);
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut resp = match ServeFile::new(&path).oneshot(req).await {
+        Ok(r) => r.into_response(),
+        Err(e) => match e {},
+    };
+    if let Ok(v) = HeaderValue::from_str(&format!("attachment; filename=\"{name}\"")) {
+        resp.headers_mut().insert(header::CONTENT_DISPOSITION, v);
+    }
+    tracing::info!(id, "200 OK GET /scans/{{id}}/result");
+    resp
+}
+
+async fn manifest_handler(
+    AxState(agent): AxState<Arc<Agent>>,
+    UrlPath(id): UrlPath<u64>,
+    req: Request,
+) -> Response {
+    let Some(scan) = agent.get(id) else {
+        return not_found("GET /scans/{id}/manifest");
+    };
+    if scan.state() != State::Done {
+        tracing::warn!(id, "409 Conflict GET /scans/{{id}}/manifest");
+        return (StatusCode::CONFLICT, "scan has no result yet").into_response();
+    }
+    let out = agent.output(id, scan.bin);
+    match ServeFile::new(format!("{}.meta.json", out.display())).oneshot(req).await {
+        Ok(r) => r.into_response(),
+        Err(e) => match e {},
+    }
+}
+
+async fn log_handler(
+    AxState(agent): AxState<Arc<Agent>>,
+    UrlPath(id): UrlPath<u64>,
+    req: Request,
+) -> Response {
+    if agent.get(id).is_none() {
+        return not_found("GET /scans/{id}/log");
+    }
+    match ServeFile::new(agent.log(id)).oneshot(req).await {
+        Ok(r) => r.into_response(),
+        Err(e) => match e {},
+    }
+}
+
+/// Routes under the token check.
+pub fn router(agent: Arc<Agent>) -> Router {
+    Router::new()
+        .route("/scans", get(list_handler).post(create_handler))
+        .route("/scans/{id}", get(get_handler))
+        .route("/scans/{id}/cancel", post(cancel_handler))
+        .route("/scans/{id}/result", get(result_handler))
+        .route("/scans/{id}/manifest", get(manifest_handler))
+        .route("/scans/{id}/log", get(log_handler))
+        .with_state(agent)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use std::os::unix::fs::PermissionsExt;
+
+    /// A stand-in duscan: one progress line, one message, then `body`.
+    fn fake_duscan(dir: &Path, body: &str) -> PathBuf {
+        let path = dir.join("duscan");
+        let script = format!(
+            "#!/bin/sh\n\
+             while [ $# -gt 0 ]; do case \"$1\" in -o) out=$2;; --report) rep=$2;; esac; shift; done\n\
+             echo '{{\"tool\":\"duscan\",\"unit\":\"files\",\"done\":7,\"total\":null,\"percent\":null,\
+             \"elapsed_secs\":1.0,\"per_sec\":7.0,\"finished\":false}}' >&2\n\
+             echo 'Output       : somewhere'\n\
+             {body}\n"
+        );
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    async fn call(app: &Router, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Vec<u8>) {
+        let req = axum::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.map_or_else(Body::empty, |b| Body::from(b.to_string())))
+            .unwrap();
+        let resp = app.clone().oneshot(req).await.unwrap();
+        let status = resp.status();
+        (status, to_bytes(resp.into_body(), usize::MAX).await.unwrap().to_vec())
+    }
+
+    async fn wait_for(app: &Router, id: u64, state: &str) -> Value {
+        let mut scan = Value::Null;
+        for _ in 0..500 {
+            let (_, body) = call(app, "GET", &format!("/scans/{id}"), None).await;
+            scan = serde_json::from_slice(&body).unwrap();
+            if scan["state"] == state {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        scan
+    }
+
+    #[tokio::test]
+    async fn runs_a_scan_and_serves_its_output() {
+        let tmp = tempfile::tempdir().unwrap();
+        let duscan = fake_duscan(
+            tmp.path(),
+            "printf 'INODE\\n1-2\\n' > \"$out\"; echo '{}' > \"$out.meta.json\"; echo '{\"status\":\"ok\",\"files\":1}' > \"$rep\"",
+        );
+        let work = tmp.path().join("work");
+        let agent = Arc::new(Agent::new(work.clone(), duscan, 1, Duration::from_secs(60)).unwrap());
+        let app = router(agent);
+
+        let req = serde_json::json!({"folders": ["/data"], "args": ["--workers", "4"]});
+        let (status, body) = call(&app, "POST", "/scans", Some(req)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let id = serde_json::from_slice::<Value>(&body).unwrap()["id"].as_u64().unwrap();
+
+        let scan = wait_for(&app, id, "done").await;
+        assert_eq!(scan["state"], "done", "{scan}");
+        assert_eq!(scan["done"], 7);
+        assert_eq!(scan["exit_code"], 0);
+        assert_eq!(scan["report"]["files"], 1);
+        assert_eq!(scan["result_url"], format!("/scans/{id}/result"));
+
+        let (status, body) = call(&app, "GET", &format!("/scans/{id}/result"), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"INODE\n1-2\n");
+        let (status, body) = call(&app, "GET", &format!("/scans/{id}/manifest"), None).await;
+        assert_eq!((status, body.as_slice()), (StatusCode::OK, &b"{}\n"[..]));
+        let (_, log) = call(&app, "GET", &format!("/scans/{id}/log"), None).await;
+        assert_eq!(String::from_utf8(log).unwrap(), "Output       : somewhere\n");
+
+        for arg in ["-vq", "--temp-dir=/x", "-b", "--enrich=lib:/tmp/x.so", "--since"] {
+            let bad = serde_json::json!({"folders": ["/data"], "args": [arg, "x"]});
+            let (status, body) = call(&app, "POST", "/scans", Some(bad)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(String::from_utf8(body).unwrap(), format!("{arg} is not allowed in scan args"));
+        }
+        let (status, _) = call(&app, "POST", "/scans", Some(serde_json::json!({"folders": []}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // Ids continue after the logs already in the work dir.
+        let again = Agent::new(work, PathBuf::from("duscan"), 1, Duration::from_secs(60)).unwrap();
+        assert_eq!(again.next_id.load(Ordering::Relaxed), id + 1);
+    }
+
+    #[tokio::test]
+    async fn failures_and_cancels_are_reported() {
+        let tmp = tempfile::tempdir().unwrap();
+        let duscan = fake_duscan(
+            tmp.path(),
+            "case \"$out\" in *scan-1.csv) echo 'Error: disk nearly full' >&2; exit 2;; esac\n\
+             touch \"shard_$(hostname)_$$_0.tmp\"; sleep 30",
+        );
+        let agent = Arc::new(Agent::new(tmp.path().join("work"), duscan, 1, Duration::from_secs(60)).unwrap());
+        let app = router(agent.clone());
+
+        let req = serde_json::json!({"folders": ["/data"]});
+        call(&app, "POST", "/scans", Some(req.clone())).await;
+        let failed = wait_for(&app, 1, "failed").await;
+        assert_eq!(failed["exit_code"], 2, "{failed}");
+        assert_eq!(failed["error"], "duscan exited with exit status: 2: Error: disk nearly full");
+        let (status, _) = call(&app, "GET", "/scans/1/result", None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        // One slot: the second scan runs, the third waits in the queue.
+        call(&app, "POST", "/scans", Some(req.clone())).await;
+        call(&app, "POST", "/scans", Some(req)).await;
+        wait_for(&app, 2, "running").await;
+        assert_eq!(agent.load(), (1, 1));
+        let (status, _) = call(&app, "POST", "/scans/3/cancel", None).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let (status, _) = call(&app, "POST", "/scans/2/cancel", None).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(wait_for(&app, 2, "cancelled").await["state"], "cancelled");
+        assert_eq!(wait_for(&app, 3, "cancelled").await["state"], "cancelled");
+        let shards = std::fs::read_dir(tmp.path().join("work"))
+            .unwrap()
+            .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().starts_with("shard_"))
+            .count();
+        assert_eq!(shards, 0);
+        let (status, _) = call(&app, "POST", "/scans/2/cancel", None).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = call(&app, "GET", "/scans/99", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn cancel_leaves_only_the_log() {
+        let tmp = tempfile::tempdir().unwrap();
+        let duscan = fake_duscan(
+            tmp.path(),
+            "h=$(hostname); touch \"shard_${h}_$$_0.tmp\" \"sort_${h}_$$_run0.tmp\" \
+             \"$(dirname \"$out\")/.$(basename \"$out\").$$.tmp\" \"$out.meta.json\"; sleep 30",
+        );
+        let work = tmp.path().join("work");
+        let agent = Arc::new(Agent::new(work.clone(), duscan, 1, Duration::from_secs(60)).unwrap());
+        let app = router(agent);
+
+        call(&app, "POST", "/scans", Some(serde_json::json!({"folders": ["/data"]}))).await;
+        wait_for(&app, 1, "running").await;
+        // Running is set before the script has made its files.
+        for _ in 0..500 {
+            if work.join("scan-1.csv.meta.json").exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(std::fs::read_dir(&work).unwrap().count() > 1);
+        call(&app, "POST", "/scans/1/cancel", None).await;
+        assert_eq!(wait_for(&app, 1, "cancelled").await["state"], "cancelled");
+        let left: Vec<String> = std::fs::read_dir(&work)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(left, ["scan-1.log"]);
+    }
+}
@@ -1,105 +1,72 @@
 // rs/src/bin/dusum/aggregate.rs
-use std::collections::HashMap;
+use dutopia::util::replace_path_prefix;
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+use crate::stats::DedupeScope;
 
 #[cfg(unix)]
 use std::ffi::CStr;
 
-/// Pick the native separator byte for a raw path. A backslash anywhere in the
-/// path (or a drive-letter prefix) means Windows-native; otherwise Unix.
-fn separator(path: &[u8]) -> u8 {
-    if path.iter().any(|&b| b == b'\\') {
-        return b'\\';
-    }
-    if path.len() >= 2 && path[0].is_ascii_alphabetic() && path[1] == b':' {
-        return b'\\';
+/// Apply `--strip-prefix`/`--add-prefix` to a raw row path.
+pub fn remap_path<'a>(raw: &'a [u8], remap: Option<&(String, String)>) -> Cow<'a, [u8]> {
+    match remap {
+        Some((from, to)) => replace_path_prefix(raw, from.as_bytes(), to.as_bytes())
+            .map(Cow::Owned)
+            .unwrap_or(Cow::Borrowed(raw)),
+        None => Cow::Borrowed(raw),
     }
-    b'/'
 }
 
-/// Trim trailing separator characters, preserving OS-native form.
-///   `C:\Users\Default\` → `C:\Users\Default`
-///   `/var/log/`         → `/var/log`
-///   `C:\`               → `C:\`   (drive root keeps the trailing sep)
-///   `/`                 → `/`     (root keeps the separator)
-pub fn normalize_folder_bytes(path: &[u8]) -> Vec<u8> {
-    let sep = separator(path);
-    let mut out: Vec<u8> = path.to_vec();
-
-    // Protect drive-letter roots: a lone `C:\` or `C:` stays as-is.
-    let is_drive_root = out.len() <= 3
-        && out.get(0).map_or(false, |b| b.is_ascii_alphabetic())
-        && out.get(1) == Some(&b':');
-
-    while out.len() > 1 && !is_drive_root && out.last() == Some(&sep) {
-        out.pop();
-    }
-    out
+/// Device id from duscan's `dev-ino` INODE field (`0` when absent).
+pub fn device_of(inode: &[u8]) -> u64 {
+    let dev = inode.split(|&b| b == b'-').next().unwrap_or(b"");
+    dutopia::util::parse_int::<u64>(Some(dev))
 }
 
-/// Convert a file path into its list of ancestor folder paths, outer→inner,
-/// preserving OS-native separators.
-///   `/a/b/file.txt`         → [`/`, `/a`, `/a/b`]
-///   `C:\Users\San\foo.txt`  → [`C:\`, `C:\Users`, `C:\Users\San`]
-///   `\\srv\shr\dir\f.txt`   → [`\\srv`, `\\srv\shr`, `\\srv\shr\dir`]
-pub fn get_folder_ancestors(path: &[u8]) -> Vec<Vec<u8>> {
-    let sep = separator(path);
-
-    let parent_end = path.iter().rposition(|&b| b == sep);
-
-    let folder = match parent_end {
-        None => return Vec::new(),
-        Some(0) => {
-            // File lives directly under root ("/" on Unix, or "\" which only
-            // arises in malformed Windows input).
-            return vec![vec![sep]];
-        }
-        Some(pos) => &path[..pos],
-    };
+/// Inodes already counted, for hard-link accounting. A `dev-ino` field is
+/// kept as one u128 (dev in the high half, 0 with `DedupeScope::Inode`),
+/// 16 bytes a row instead of a heap-allocated string (24 bytes, the text
+/// and allocator overhead), which is tens of GB on a billion-row scan. A
+/// field that is not two numbers is kept as a 64-bit hash with the high
+/// half all ones, which no real device id reaches.
+pub struct SeenInodes {
+    set: HashSet<u128>,
+    scope: DedupeScope,
+}
 
-    let mut folder = folder.to_vec();
-    while folder.len() > 1 && folder.last() == Some(&sep) {
-        folder.pop();
+impl SeenInodes {
+    pub fn new(scope: DedupeScope) -> Self {
+        Self { set: HashSet::new(), scope }
     }
 
-    let mut ancestors: Vec<Vec<u8>> = Vec::new();
+    /// True the first time `inode` is seen.
+    pub fn insert(&mut self, inode: &[u8]) -> bool {
+        self.set.insert(inode_key(inode, self.scope))
+    }
 
-    // Unix absolute path: "/…" — root `/` is itself an ancestor.
-    // UNC: "\\server\share\…" — server + share are the first two ancestors.
-    // Drive-letter: "C:\…" — the drive root "C:\" is the first ancestor.
-    // Relative: no anchor; first segment is the first ancestor.
-    let (prefix, rest): (Vec<u8>, &[u8]) = if sep == b'/' && folder.starts_with(b"/") {
-        ancestors.push(b"/".to_vec());
-        (b"/".to_vec(), &folder[1..])
-    } else if sep == b'\\' && folder.starts_with(b"\\\\") {
-        (b"\\\\".to_vec(), &folder[2..])
-    } else if sep == b'\\'
-        && folder.len() >= 2
-        && folder[0].is_ascii_alphabetic()
-        && folder[1] == b':'
-    {
-        // Build the drive root `C:\` as the first ancestor.
-        let drive_root = vec![folder[0], b':', b'\\'];
-        ancestors.push(drive_root.clone());
-        let rest = if folder.len() >= 3 && folder[2] == b'\\' {
-            &folder[3..]
-        } else {
-            &folder[2..]
-        };
-        (drive_root, rest)
-    } else {
-        (Vec::new(), &folder[..])
-    };
+    pub fn clear(&mut self) {
+        self.set.clear();
+    }
+}
 
-    let mut current: Vec<u8> = prefix;
-    for segment in rest.split(|&b| b == sep).filter(|s| !s.is_empty()) {
-        if !current.is_empty() && current.last() != Some(&sep) {
-            current.push(sep);
+fn inode_key(inode: &[u8], scope: DedupeScope) -> u128 {
+    let num = |b: &[u8]| std::str::from_utf8(b).ok()?.parse::<u64>().ok();
+    let parsed = inode
+        .iter()
+        .position(|&b| b == b'-')
+        .and_then(|i| Some((num(&inode[..i])?, num(&inode[i + 1..])?)));
+    match parsed {
+        Some((_, ino)) if scope == DedupeScope::Inode => ino as u128,
+        Some((dev, ino)) => ((dev as u128) << 64) | ino as u128,
+        None => {
+            let mut h = DefaultHasher::new();
+            inode.hash(&mut h);
+            ((u64::MAX as u128) << 64) | h.finish() as u128
         }
-        current.extend_from_slice(segment);
-        ancestors.push(current.clone());
     }
-
-    ancestors
 }
 
 /// Safely convert bytes to UTF-8 String (invalid sequences -> U+FFFD)
@@ -134,22 +101,58 @@ pub fn get_username_from_uid(uid: u32) -> String {
     }
 }
 
-#[cfg(not(unix))]
+#[cfg(windows)]
+pub fn get_username_from_uid(uid: u32) -> String {
+    // Windows duscan writes the owner SID's RID as the uid. Scans from before
+    // it did have 0 throughout; use the interactive user as a stand-in for
+    // those so the duapi per-user filter has something that matches the
+    // login identity. Falls back to "UNK" if the environment is missing
+    // USERNAME (e.g. running as a service), or if the RID is unknown.
+    if uid == 0 {
+        return std::env::var("USERNAME")
+            .ok()
+            .filter(|s| !s.trim().is_empty())
+            .unwrap_or_else(|| "UNK".to_string());
+    }
+    dutopia::util::account_name_from_rid(uid).unwrap_or_else(|| "UNK".to_string())
+}
+
+#[cfg(not(any(unix, windows)))]
 pub fn get_username_from_uid(_uid: u32) -> String {
-    // Windows duscan does not record per-file ownership yet (uid is always 0).
-    // Use the interactive user as a stand-in so the duapi per-user filter has
-    // something that matches the login identity. Falls back to "UNK" if the
-    // environment is missing USERNAME (e.g. running as a service).
-    std::env::var("USERNAME")
-        .ok()
-        .filter(|s| !s.trim().is_empty())
-        .unwrap_or_else(|| "UNK".to_string())
+    "UNK".to_string()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn device_of_reads_dev_component() {
+        assert_eq!(device_of(b"2049-1234"), 2049);
+        assert_eq!(device_of(b"0-77"), 0);
+        assert_eq!(device_of(b""), 0);
+        assert_eq!(device_of(b"junk"), 0);
+    }
+
+    #[test]
+    fn seen_inodes_key_on_dev_ino_or_inode_alone() {
+        let mut all = SeenInodes::new(DedupeScope::All);
+        assert!(all.insert(b"2049-1234"));
+        assert!(!all.insert(b"2049-1234"));
+        assert!(all.insert(b"2050-1234"));
+        assert!(all.insert(b"1234-2049"));
+        assert!(all.insert(b"junk"));
+        assert!(!all.insert(b"junk"));
+        assert!(all.insert(b"2049-1234x"));
+
+        let mut inode = SeenInodes::new(DedupeScope::Inode);
+        assert!(inode.insert(b"2049-1234"));
+        assert!(!inode.insert(b"2050-1234"));
+        inode.clear();
+        assert!(inode.insert(b"2050-1234"));
+        assert_eq!(inode_key(b"18446744073709551615-7", DedupeScope::All), (u64::MAX as u128) << 64 | 7);
+    }
+
     #[test]
     fn bytes_to_safe_string_handles_invalid_utf8() {
         let bad = [0xFFu8, b'a', 0xFE, b'b'];
@@ -166,117 +169,4 @@ mod tests {
         assert!(s.contains('�'));
         assert!(s.contains("/"));
     }
-
-    #[test]
-    fn ancestors_from_non_utf8_bytes() {
-        let raw = [
-            b'/', 0xFFu8, b'a', b'/', b'b', b'/', b'c', b'/', b'f', b'.', b't', b'x', b't',
-        ];
-        let ancestors = get_folder_ancestors(&raw);
-        assert_eq!(ancestors[0], b"/".to_vec());
-        assert!(ancestors.contains(&vec![b'/', 0xFFu8, b'a']));
-        assert!(ancestors.contains(&vec![b'/', 0xFFu8, b'a', b'/', b'b']));
-    }
-
-    #[test]
-    fn ancestors_trailing_slashes_and_multi_seps() {
-        let res = get_folder_ancestors(b"/a//b///c//file.txt");
-        assert_eq!(
-            res,
-            vec![
-                b"/".to_vec(),
-                b"/a".to_vec(),
-                b"/a/b".to_vec(),
-                b"/a/b/c".to_vec()
-            ]
-        );
-    }
-
-    #[test]
-    fn ancestors_windows_native_backslashes() {
-        let res = get_folder_ancestors(b"C:\\a\\b\\file.txt");
-        assert_eq!(
-            res,
-            vec![
-                b"C:\\".to_vec(),
-                b"C:\\a".to_vec(),
-                b"C:\\a\\b".to_vec()
-            ]
-        );
-    }
-
-    #[test]
-    fn ancestors_unc_native() {
-        let res = get_folder_ancestors(b"\\\\server\\share\\dir\\file.txt");
-        assert_eq!(
-            res,
-            vec![
-                b"\\\\server".to_vec(),
-                b"\\\\server\\share".to_vec(),
-                b"\\\\server\\share\\dir".to_vec(),
-            ]
-        );
-    }
-
-    #[test]
-    fn ancestors_handles_root_only() {
-        assert_eq!(get_folder_ancestors(b"/file.txt"), vec![b"/".to_vec()]);
-        assert_eq!(get_folder_ancestors(b"/"), vec![b"/".to_vec()]);
-    }
-
-    #[test]
-    fn ancestors_handles_relative_paths() {
-        // No path separator at all: no ancestor folders.
-        let res = get_folder_ancestors(b"file.txt");
-        assert!(res.is_empty());
-    }
-
-    #[test]
-    fn ancestors_simple_nested_path() {
-        let res = get_folder_ancestors(b"/a/b/c/file.txt");
-        assert_eq!(
-            res,
-            vec![
-                b"/".to_vec(),
-                b"/a".to_vec(),
-                b"/a/b".to_vec(),
-                b"/a/b/c".to_vec()
-            ]
-        );
-    }
-
-    #[test]
-    fn ancestors_single_level_path() {
-        let res = get_folder_ancestors(b"/a/file.txt");
-        assert_eq!(res, vec![b"/".to_vec(), b"/a".to_vec()]);
-    }
-
-    #[test]
-    fn ancestors_drive_root_file() {
-        let res = get_folder_ancestors(b"C:\\foo.txt");
-        assert_eq!(res, vec![b"C:\\".to_vec()]);
-    }
-
-    #[test]
-    fn normalize_folder_bytes_basic() {
-        assert_eq!(normalize_folder_bytes(b"/a/b"), b"/a/b".to_vec());
-        assert_eq!(normalize_folder_bytes(b"/a/b/"), b"/a/b".to_vec());
-        assert_eq!(normalize_folder_bytes(b"a/b"), b"a/b".to_vec());
-        assert_eq!(normalize_folder_bytes(b"/"), b"/".to_vec());
-        assert_eq!(normalize_folder_bytes(b""), Vec::<u8>::new());
-    }
-
-    #[test]
-    fn normalize_folder_bytes_windows_native() {
-        assert_eq!(
-            normalize_folder_bytes(b"C:\\Users\\Default"),
-            b"C:\\Users\\Default".to_vec()
-        );
-        assert_eq!(
-            normalize_folder_bytes(b"C:\\Users\\Default\\"),
-            b"C:\\Users\\Default".to_vec()
-        );
-        // Drive root keeps its trailing backslash.
-        assert_eq!(normalize_folder_bytes(b"C:\\"), b"C:\\".to_vec());
-    }
 }
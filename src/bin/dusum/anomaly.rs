@@ -0,0 +1,184 @@
+// rs/src/bin/dusum/anomaly.rs
+//
+// Data-quality checks on input rows. Vendor dumps and scans of broken
+// filesystems carry values no real file has; dusum keeps such rows but
+// counts them, prints the counts, and with `--anomalies FILE` lists every
+// one as `row,kind,field,value,path`:
+//
+//   future     ATIME or MTIME more than a day ahead of now; summarized as 0
+//              (unknown), as before
+//   negative   a negative SIZE, DISK, UID, GID, ATIME or MTIME; counts read
+//              as 0, timestamps are kept (before 1970 ages as old)
+//   oversize   SIZE or DISK above `--max-file-size` (default 1PB, more than
+//              any filesystem holds); the row is counted without bytes
+//
+// Rows are never dropped, so file counts match the input.
+use anyhow::{Context, Result};
+use csv::{ByteRecord, Writer};
+use std::fs::File;
+use std::path::Path;
+
+use crate::stats::sanitize_mtime;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Future,
+    Negative,
+    Oversize,
+}
+
+impl Kind {
+    const ALL: [Kind; 3] = [Kind::Future, Kind::Negative, Kind::Oversize];
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Future => "future",
+            Kind::Negative => "negative",
+            Kind::Oversize => "oversize",
+        }
+    }
+}
+
+/// (column index, name) of the numeric input columns.
+const FIELDS: [(usize, &str); 6] = [
+    (1, "ATIME"),
+    (2, "MTIME"),
+    (3, "UID"),
+    (4, "GID"),
+    (6, "SIZE"),
+    (7, "DISK"),
+];
+
+pub struct Anomalies {
+    now: i64,
+    max_size: u64,
+    counts: [u64; 3],
+    rows: u64,
+    report: Option<Writer<File>>,
+}
+
+impl Anomalies {
+    pub fn new(now: i64, max_size: u64, report: Option<&Path>) -> Result<Self> {
+        let report = match report {
+            Some(p) => {
+                let mut w = Writer::from_path(p)
+                    .with_context(|| format!("creating anomalies report {}", p.display()))?;
+                w.write_record(["row", "kind", "field", "value", "path"])?;
+                Some(w)
+            }
+            None => None,
+        };
+        Ok(Self {
+            now,
+            max_size,
+            counts: [0; 3],
+            rows: 0,
+            report,
+        })
+    }
+
+    /// Check row number `row` (1-based, header not counted); true when its
+    /// SIZE or DISK is over the limit and its bytes should not be added.
+    pub fn check(&mut self, row: usize, rec: &ByteRecord, path: &[u8]) -> Result<bool> {
+        let mut found: Vec<(Kind, &str, &[u8])> = Vec::new();
+        for (i, name) in FIELDS {
+            let raw = rec.get(i).unwrap_or(b"").trim_ascii();
+            if raw.first() == Some(&b'-') {
+                found.push((Kind::Negative, name, raw));
+                continue;
+            }
+            let Some(v) = std::str::from_utf8(raw).ok().and_then(|s| s.parse::<u64>().ok()) else {
+                continue;
+            };
+            match name {
+                "ATIME" | "MTIME" => {
+                    let t = i64::try_from(v).unwrap_or(i64::MAX);
+                    if sanitize_mtime(self.now, t) != t {
+                        found.push((Kind::Future, name, raw));
+                    }
+                }
+                "SIZE" | "DISK" if v > self.max_size => found.push((Kind::Oversize, name, raw)),
+                _ => {}
+            }
+        }
+        if found.is_empty() {
+            return Ok(false);
+        }
+        self.rows += 1;
+        let oversize = found.iter().any(|f| f.0 == Kind::Oversize);
+        for (kind, field, value) in found {
+            self.counts[kind as usize] += 1;
+            if let Some(w) = &mut self.report {
+                w.write_record([
+                    row.to_string().as_bytes(),
+                    kind.name().as_bytes(),
+                    field.as_bytes(),
+                    value,
+                    path,
+                ])?;
+            }
+        }
+        Ok(oversize)
+    }
+
+    /// Rows with at least one anomaly.
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// `future 3, oversize 1`: the kinds seen, with their counts.
+    pub fn describe(&self) -> String {
+        Kind::ALL
+            .iter()
+            .filter(|k| self.counts[**k as usize] > 0)
+            .map(|k| format!("{} {}", k.name(), self.counts[*k as usize]))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some(w) = &mut self.report {
+            w.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rec(fields: &[&str]) -> ByteRecord {
+        ByteRecord::from(fields.to_vec())
+    }
+
+    #[test]
+    fn counts_and_reports_bad_values() {
+        let tmp = tempfile::tempdir().unwrap();
+        let report = tmp.path().join("anomalies.csv");
+        let now = 1_700_000_000;
+        let mut a = Anomalies::new(now, 1 << 50, Some(&report)).unwrap();
+
+        let ok = rec(&["1-2", "1600000000", "1600000000", "1000", "1000", "33188", "10", "4096", "/a"]);
+        assert!(!a.check(1, &ok, b"/a").unwrap());
+        let future = rec(&["1-3", "1600000000", "4102444800", "1000", "1000", "33188", "10", "4096", "/b"]);
+        assert!(!a.check(2, &future, b"/b").unwrap());
+        let bad = rec(&["1-4", "-5", "1600000000", "-1", "1000", "33188", "9999999999999999999", "4096", "/c,d"]);
+        assert!(a.check(3, &bad, b"/c,d").unwrap());
+
+        assert_eq!(a.rows(), 2);
+        assert_eq!(a.describe(), "future 1, negative 2, oversize 1");
+        a.finish().unwrap();
+        drop(a);
+        let text = std::fs::read_to_string(&report).unwrap();
+        assert_eq!(
+            text,
+            "row,kind,field,value,path\n\
+             2,future,MTIME,4102444800,/b\n\
+             3,negative,ATIME,-5,\"/c,d\"\n\
+             3,negative,UID,-1,\"/c,d\"\n\
+             3,oversize,SIZE,9999999999999999999,\"/c,d\"\n"
+        );
+        assert_eq!(Anomalies::new(now, 1, None).unwrap().describe(), "");
+    }
+}
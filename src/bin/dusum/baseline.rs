@@ -0,0 +1,96 @@
+// rs/src/bin/dusum/baseline.rs
+//
+// `--baseline old.sum.csv`: an earlier summary to compare against. Each
+// output row gets `files_delta` and `disk_delta`, its files and disk minus
+// those of the same (path, user, age[, device]) row in the baseline, so a
+// growth report is one sort away without a separate diff. A row missing from
+// the baseline counts as grown from zero. Rows only in the baseline are not
+// written. Columns are found by header name, so baselines written with other
+// options (devices, duplicates, extrapolation) still load; without a
+// `device` column every baseline row is device 0.
+use anyhow::{Context, Result};
+use csv::ReaderBuilder;
+use std::collections::HashMap;
+use std::path::Path;
+
+type Key = (String, String, u8, u64);
+
+#[derive(Debug, Default)]
+pub struct Baseline {
+    /// (files, disk) per row
+    rows: HashMap<Key, (u64, u64)>,
+}
+
+impl Baseline {
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(true)
+            .from_path(path)
+            .with_context(|| format!("opening baseline {}", path.display()))?;
+        let header = rdr.headers()?.clone();
+        let col = |name: &str| header.iter().position(|h| h == name);
+        let need = |name: &str| {
+            col(name).with_context(|| format!("baseline {} has no '{name}' column", path.display()))
+        };
+        let (p, u, a, f, d) = (need("path")?, need("user")?, need("age")?, need("files")?, need("disk")?);
+        let dev = col("device");
+
+        let mut rows = HashMap::new();
+        for rec in rdr.records() {
+            let rec = rec.with_context(|| format!("reading baseline {}", path.display()))?;
+            let field = |i: usize| rec.get(i).unwrap_or("");
+            let num = |i: usize| field(i).trim().parse::<u64>().unwrap_or(0);
+            let key = (
+                field(p).to_string(),
+                field(u).to_string(),
+                field(a).trim().parse().unwrap_or(0),
+                dev.map_or(0, num),
+            );
+            let slot: &mut (u64, u64) = rows.entry(key).or_default();
+            slot.0 += num(f);
+            slot.1 += num(d);
+        }
+        Ok(Self { rows })
+    }
+
+    /// Rows loaded.
+    pub fn rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// (files, disk) change of a row since the baseline.
+    pub fn delta(&self, path: &str, user: &str, age: u8, device: u64, files: u64, disk: u64) -> (i64, i64) {
+        let (f0, d0) = self
+            .rows
+            .get(&(path.to_string(), user.to_string(), age, device))
+            .copied()
+            .unwrap_or_default();
+        (files as i64 - f0 as i64, disk as i64 - d0 as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deltas_against_rows_by_header_name() {
+        let tmp = tempfile::tempdir().unwrap();
+        let old = tmp.path().join("old.sum.csv");
+        std::fs::write(
+            &old,
+            "path,user,age,files,size,disk,linked,accessed,modified,device\n\
+             /data,alice,1,10,100,4096,0,0,0,7\n\
+             \"/a,b\",bob,2,3,30,8192,0,0,0,7\n",
+        )
+        .unwrap();
+        let b = Baseline::load(&old).unwrap();
+        assert_eq!(b.rows(), 2);
+        assert_eq!(b.delta("/data", "alice", 1, 7, 15, 5000), (5, 904));
+        assert_eq!(b.delta("/a,b", "bob", 2, 7, 1, 0), (-2, -8192));
+        assert_eq!(b.delta("/data", "alice", 1, 0, 15, 5000), (15, 5000));
+
+        std::fs::write(&old, "path,user\n/x,y\n").unwrap();
+        assert!(Baseline::load(&old).unwrap_err().to_string().contains("'age'"));
+    }
+}
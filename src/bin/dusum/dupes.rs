@@ -0,0 +1,134 @@
+// rs/src/bin/dusum/dupes.rs
+//
+// `--collapse-duplicates`: directories cannot be hard-linked, so two
+// directory rows with the same dev-ino are one directory reached through
+// two paths — a bind mount, a snapshot mounted next to the live tree, or
+// overlapping scan roots. The subtree is counted once, under the path with
+// the fewest components (then the smallest bytes); rows below the other
+// paths are dropped, and the kept folder reports how many other paths it
+// was found at in a `duplicated_paths` column.
+use anyhow::Result;
+use csv::{ReaderBuilder, Trim};
+use dutopia::util::{get_folder_ancestors, normalize_folder_bytes, parse_int, PathFilter};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::aggregate::remap_path;
+use crate::stats::{entry_class, EntryClass};
+
+#[derive(Debug, Default)]
+pub struct Duplicates {
+    /// Folder paths whose rows are counted under another path
+    aliases: HashSet<Vec<u8>>,
+    /// Kept folder -> number of other paths it was found at
+    counts: HashMap<Vec<u8>, u64>,
+    /// (dropped path, kept path) for the top of each dropped subtree, sorted
+    pub pairs: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Duplicates {
+    /// First pass over the duscan CSVs: group directory rows by dev-ino,
+    /// across all inputs.
+    pub fn scan(
+        inputs: &[PathBuf],
+        remap: Option<&(String, String)>,
+        filter: &PathFilter,
+    ) -> Result<Self> {
+        let mut dirs = Vec::new();
+        for input in inputs {
+            let mut reader = ReaderBuilder::new()
+                .has_headers(true)
+                .flexible(true)
+                .trim(Trim::None)
+                .from_path(input)?;
+            for record in reader.byte_records() {
+                let Ok(record) = record else { continue };
+                if entry_class(parse_int::<u32>(record.get(5))) != EntryClass::Dir {
+                    continue;
+                }
+                let path = remap_path(record.get(8).unwrap_or(b""), remap);
+                if path.is_empty() || filter.excludes(&path) {
+                    continue;
+                }
+                dirs.push((record.get(0).unwrap_or(b"").to_vec(), path.into_owned()));
+            }
+        }
+        Ok(Self::from_dirs(dirs))
+    }
+
+    /// Group `(dev-ino, path)` directory rows; rows without inode info
+    /// (`0-0`) never match.
+    pub fn from_dirs(dirs: Vec<(Vec<u8>, Vec<u8>)>) -> Self {
+        let mut by_inode: HashMap<Vec<u8>, Vec<Vec<u8>>> = HashMap::new();
+        for (inode, path) in dirs {
+            if inode.is_empty() || inode == b"0-0" {
+                continue;
+            }
+            let paths = by_inode.entry(inode).or_default();
+            let path = normalize_folder_bytes(&path);
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+
+        let mut out = Self::default();
+        for mut paths in by_inode.into_values().filter(|p| p.len() > 1) {
+            paths.sort_by_key(|p| (depth(p), p.clone()));
+            let kept = paths.remove(0);
+            out.counts.insert(kept.clone(), paths.len() as u64);
+            for p in paths {
+                out.aliases.insert(p.clone());
+                out.pairs.push((p, kept.clone()));
+            }
+        }
+        let aliases = &out.aliases;
+        out.pairs
+            .retain(|(p, _)| !get_folder_ancestors(p).iter().any(|a| aliases.contains(a)));
+        out.pairs.sort();
+        out
+    }
+
+    /// True when a row with these folders lies in a dropped subtree.
+    pub fn covers(&self, folders: &[Vec<u8>]) -> bool {
+        !self.aliases.is_empty() && folders.iter().any(|f| self.aliases.contains(f))
+    }
+
+    /// Other paths `folder` was found at (0 for most folders).
+    pub fn count(&self, folder: &[u8]) -> u64 {
+        self.counts.get(folder).copied().unwrap_or(0)
+    }
+}
+
+fn depth(path: &[u8]) -> usize {
+    path.iter().filter(|&&b| b == b'/' || b == b'\\').count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(inode: &str, path: &str) -> (Vec<u8>, Vec<u8>) {
+        (inode.as_bytes().to_vec(), path.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn same_inode_directories_keep_the_shallowest_path() {
+        let d = Duplicates::from_dirs(vec![
+            dir("1-10", "/data"),
+            dir("1-11", "/data/x"),
+            dir("1-11", "/mnt/bind/x/"),
+            dir("1-12", "/data/x/y"),
+            dir("1-12", "/mnt/bind/x/y"),
+            dir("0-0", "/c"),
+            dir("0-0", "/d"),
+        ]);
+        assert_eq!(d.pairs.len(), 1);
+        assert_eq!(d.count(b"/data/x"), 1);
+        assert_eq!(d.count(b"/data"), 0);
+        assert_eq!(d.pairs[0], (b"/mnt/bind/x".to_vec(), b"/data/x".to_vec()));
+        assert_eq!(d.count(b"/data/x/y"), 1);
+        assert!(d.covers(&[b"/".to_vec(), b"/mnt".to_vec(), b"/mnt/bind/x".to_vec()]));
+        assert!(!d.covers(&[b"/".to_vec(), b"/data".to_vec(), b"/data/x".to_vec()]));
+        assert!(!d.covers(&[b"/c".to_vec()]));
+    }
+}
@@ -0,0 +1,99 @@
+// rs/src/bin/dusum/history.rs
+//
+// `--history FILE`: append this run's rollups to the history DB (see
+// `dutopia::history`). Rows are stored per (path, user, age); with
+// `--by-device` the devices of a folder are summed first.
+use anyhow::Result;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+
+use dutopia::history::{History, HistoryRow, ScanRecord};
+use dutopia::manifest::ScanManifest;
+
+use crate::aggregate::bytes_to_safe_string;
+use crate::output::AggKey;
+use crate::stats::UserStats;
+
+/// When the scan was taken: the manifest's start time, else the input's
+/// modification time.
+pub fn scan_time(input: &Path, manifest: Option<&ScanManifest>) -> i64 {
+    if let Some(t) = manifest.map(|m| m.started_at).filter(|&t| t > 0) {
+        return t;
+    }
+    std::fs::metadata(input)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+pub fn record_history(
+    db: &Path,
+    input: &Path,
+    scanned_at: i64,
+    data: &HashMap<AggKey, UserStats>,
+    by_device: bool,
+) -> Result<ScanRecord> {
+    let source = std::fs::canonicalize(input).unwrap_or_else(|_| input.to_path_buf());
+    let mut history = History::open(db)?;
+
+    let folded: HashMap<(&[u8], &str, u8), UserStats>;
+    let rows: Box<dyn Iterator<Item = (&[u8], &str, u8, &UserStats)>> = if by_device {
+        let mut f: HashMap<(&[u8], &str, u8), UserStats> = HashMap::new();
+        for ((path, user, age, _), s) in data {
+            f.entry((path, user, *age)).or_default().absorb(s);
+        }
+        folded = f;
+        Box::new(folded.iter().map(|((p, u, a), s)| (*p, *u, *a, s)))
+    } else {
+        Box::new(data.iter().map(|((p, u, a, _), s)| (p.as_slice(), u.as_str(), *a, s)))
+    };
+
+    history.record(
+        &source.display().to_string(),
+        scanned_at,
+        rows.map(|(path, user, age, s)| HistoryRow {
+            path: Cow::Owned(bytes_to_safe_string(path)),
+            user: Cow::Borrowed(user),
+            age,
+            files: s.file_count,
+            size: s.file_size,
+            disk: s.disk_size,
+            linked: s.linked_size,
+            atime: s.latest_atime,
+            mtime: s.latest_mtime,
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn devices_are_summed_per_folder() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db = tmp.path().join("h.db");
+        let input = tmp.path().join("scan.csv");
+        std::fs::write(&input, "").unwrap();
+        let mut data: HashMap<AggKey, UserStats> = HashMap::new();
+        for dev in [1, 2] {
+            let mut s = UserStats::default();
+            s.update(10, 8, 0, 0, 100 * dev as i64);
+            data.insert((b"/a".to_vec(), "alice".into(), 0, dev), s);
+        }
+        let rec = record_history(&db, &input, 42, &data, true).unwrap();
+        assert_eq!(rec.rows, 1);
+        let h = History::open(&db).unwrap();
+        let points = h.series("/a", None).unwrap();
+        assert_eq!((points[0].scanned_at, points[0].files, points[0].size), (42, 2, 20));
+
+        let m = ScanManifest {
+            started_at: 7,
+            ..Default::default()
+        };
+        assert_eq!(scan_time(&input, Some(&m)), 7);
+        assert!(scan_time(&input, None) > 0);
+    }
+}
@@ -3,28 +3,38 @@ use anyhow::Result;
 use chrono::Utc;
 use clap::{ColorChoice, Parser};
 use csv::{ReaderBuilder, Trim};
-use dutopia::util::{parse_int, print_about};
+use dutopia::manifest::ScanManifest;
+use dutopia::util::exit::{self, ExitArgs, Outcome};
+use dutopia::util::logging::{init_cli_tracing, LogArgs};
+use dutopia::util::progress::{Counter, ProgressArgs, Reporter, Unit};
+use dutopia::util::{
+    get_folder_ancestors, normalize_folder_bytes, parse_int, print_about, PathFilter,
+};
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
 
 mod aggregate;
+mod anomaly;
+mod baseline;
+mod dupes;
+mod history;
 mod output;
+mod projects;
+mod sample;
 mod stats;
 
-use aggregate::{get_folder_ancestors, normalize_folder_bytes, resolve_user};
-use output::{count_lines, write_results, write_unknown_uids};
-use stats::{age_bucket, parse_age_pair, sanitize_mtime, AgeCfg, UserStats};
-
-// POSIX-style type masks as encoded by dutopia in MODE
-#[cfg(unix)]
-const S_IFMT: u32 = 0o170000;
-#[cfg(unix)]
-const S_IFDIR: u32 = 0o040000;
-
-#[cfg(not(unix))]
-const S_IFMT: u32 = 0o170000;
-#[cfg(not(unix))]
-const S_IFDIR: u32 = 0o040000;
+use aggregate::{device_of, remap_path, resolve_user, SeenInodes};
+use anomaly::Anomalies;
+use baseline::Baseline;
+use dupes::Duplicates;
+use output::{check_output, count_lines, write_results, write_unknown_uids, AggKey, WriteMode};
+use projects::{projects_path, write_projects, Projects};
+use stats::{
+    age_bucket, entry_class, parse_age_pair, sanitize_mtime, AgeCfg, DedupeScope, EntryClass,
+    EntryPolicy, UserStats,
+};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -33,21 +43,111 @@ const S_IFDIR: u32 = 0o040000;
     about = "Compute summary statistics from CSV input"
 )]
 struct Args {
-    /// Input CSV file path
-    input: PathBuf,
-    /// Output CSV file path (defaults to <input_stem>.sum.csv)
+    /// Input CSV file path; several inputs (hosts, partitions) are summed
+    /// into one summary
+    #[arg(required = true, value_name = "INPUT")]
+    inputs: Vec<PathBuf>,
+    /// Output CSV file path (defaults to <first_input_stem>.sum.csv)
     #[arg(short, long)]
     output: Option<PathBuf>,
     /// Age buckets in days as YOUNG,OLD  (defaults to 60,600)
     #[arg(long, value_parser = parse_age_pair, value_name = "YOUNG,OLD")]
     age: Option<(i64, i64)>,
+    /// Skip any row whose path contains this substring
+    #[arg(short, long, value_name = "SUBSTR")]
+    skip: Option<String>,
+    /// Skip rows whose path, or any parent folder, matches this glob (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// Remove this leading path prefix from every row (e.g. a scan-host mount point)
+    #[arg(long, value_name = "PREFIX")]
+    strip_prefix: Option<String>,
+    /// Prepend this prefix, after --strip-prefix, to put rows in the canonical namespace
+    #[arg(long, value_name = "PREFIX")]
+    add_prefix: Option<String>,
+    /// Break rollups down by filesystem (the dev part of INODE); adds a device column
+    #[arg(long)]
+    by_device: bool,
+    /// Deprecated and ignored: an existing output is replaced by default
+    #[arg(long, hide = true, conflicts_with_all = ["append", "no_clobber"])]
+    force: bool,
+    /// Refuse to replace an existing output file
+    #[arg(long, conflicts_with = "append")]
+    no_clobber: bool,
+    /// Merge the rows into an existing output written with the same flags
+    /// instead of replacing it
+    #[arg(long)]
+    append: bool,
+    /// Symlink accounting: all (count + size), count (no bytes), or skip
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = EntryPolicy::All)]
+    symlinks: EntryPolicy,
+    /// Socket, FIFO and device node accounting: all, count, or skip
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = EntryPolicy::All)]
+    special: EntryPolicy,
+    /// Count subtrees reached through several paths (bind mounts, snapshots;
+    /// same dev-ino directories) once; adds a duplicated_paths column
+    #[arg(long)]
+    collapse_duplicates: bool,
+    /// Hard-link accounting across inputs: input (per file), all (dev-ino
+    /// across files) or inode (inode number alone across files)
+    #[arg(long, value_enum, value_name = "SCOPE", default_value_t = DedupeScope::All)]
+    dedupe_scope: DedupeScope,
+    /// Summarize even when the scan's manifest (<input>.meta.json) says the
+    /// scan is incomplete or lists a different row count; warn instead
+    #[arg(long)]
+    allow_incomplete: bool,
+    /// Also append this run's rollups, stamped with the scan time, to this
+    /// SQLite history DB (created if missing)
+    #[arg(long, value_name = "FILE")]
+    history: Option<PathBuf>,
+    /// For a sampled scan (duscan --sample), write the measured rows only
+    /// instead of scaling them up to estimates
+    #[arg(long)]
+    no_extrapolate: bool,
+    /// Earlier summary (.sum.csv) to compare with; adds files_delta and
+    /// disk_delta columns
+    #[arg(long, value_name = "FILE")]
+    baseline: Option<PathBuf>,
+    /// SIZE or DISK above this is an anomaly, counted without bytes
+    #[arg(long, value_name = "SIZE", default_value = "1PB", value_parser = dutopia::util::parse_bytes)]
+    max_file_size: u64,
+    /// List rows with future timestamps, negative or oversized values in
+    /// this CSV file
+    #[arg(long, value_name = "FILE")]
+    anomalies: Option<PathBuf>,
+    /// Also sum usage per project, the folders N components below the root
+    /// (2: /proj/alpha), into a compact <output_stem>.projects.csv
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    project_depth: Option<u16>,
+    /// Do not report progress
+    #[arg(short, long)]
+    quiet: bool,
+    #[command(flatten)]
+    progress: ProgressArgs,
+    #[command(flatten)]
+    log: LogArgs,
+    #[command(flatten)]
+    exit: ExitArgs,
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     print_about();
 
-    let start_time = std::time::Instant::now();
     let args = Args::parse();
+    let exit = args.exit.clone();
+    exit::finish("dusum", &exit, run(args))
+}
+
+/// The summary; returns partial when an input was summarized despite its
+/// manifest, errors when malformed rows were skipped.
+fn run(args: Args) -> Result<Outcome> {
+    let start_time = std::time::Instant::now();
+    init_cli_tracing("dusum", &args.log, "warn")?;
+    let first = &args.inputs[0];
+    tracing::info!(inputs = args.inputs.len(), input = %first.display(), "summary started");
+    if args.force {
+        tracing::warn!("--force is deprecated and does nothing; outputs are replaced by default");
+    }
 
     let age_cfg = AgeCfg::from_args(&args.age);
     println!(
@@ -56,17 +156,106 @@ fn main() -> Result<()> {
     );
 
     let output_path = args.output.clone().unwrap_or_else(|| {
-        let stem = args
-            .input
+        let stem = first
             .file_stem()
             .and_then(|s| s.to_str())
             .unwrap_or("output");
         PathBuf::from(format!("{}.sum.csv", stem))
     });
+    let write_mode = WriteMode::from_flags(args.no_clobber, args.append);
+    let baseline = args.baseline.as_deref().map(Baseline::load).transpose()?;
+    check_output(&output_path, write_mode)?;
+    let mut projects = args.project_depth.map(|d| Projects::new(d as usize));
+    let projects_out = projects_path(&output_path);
+    if projects.is_some() {
+        check_output(&projects_out, write_mode)?;
+    }
+
+    let manifests = args
+        .inputs
+        .iter()
+        .map(|i| ScanManifest::read(i))
+        .collect::<Result<Vec<_>>>()?;
+    for m in manifests.iter().flatten() {
+        println!(
+            "Manifest     : {} rows, {} errors, {}",
+            m.rows,
+            m.errors,
+            if m.complete { "complete" } else { "INCOMPLETE" }
+        );
+        if m.errors > 0 {
+            tracing::warn!(errors = m.errors, "the scan could not read some entries; their rows are missing");
+        }
+    }
+    let manifest = manifests[0].as_ref();
+    if args.inputs.len() > 1 {
+        if manifests.iter().flatten().any(|m| m.sample.is_some()) {
+            anyhow::bail!("sampled scans (duscan --sample) are summarized one input at a time");
+        }
+        println!(
+            "Inputs       : {} (hard links deduplicated per {})",
+            args.inputs.len(),
+            match args.dedupe_scope {
+                DedupeScope::Input => "input",
+                DedupeScope::All => "dev-ino across inputs",
+                DedupeScope::Inode => "inode across inputs",
+            }
+        );
+    }
+
+    let filter = PathFilter::new(args.skip.as_deref(), &args.exclude)?;
+    let mut excluded_rows = 0u64;
+
+    let remap = (args.strip_prefix.is_some() || args.add_prefix.is_some()).then(|| {
+        (
+            args.strip_prefix.clone().unwrap_or_default(),
+            args.add_prefix.clone().unwrap_or_default(),
+        )
+    });
+    if let Some((from, to)) = &remap {
+        println!("Remap        : '{}' -> '{}'", from, to);
+    }
+    let sample_info = manifest.and_then(|m| m.sample.as_ref());
+    if let Some(info) = sample_info {
+        println!(
+            "Sampled scan : {} strata{}",
+            info.strata.len(),
+            if args.no_extrapolate { ", not extrapolated" } else { ", rows scaled up" }
+        );
+    }
+    let mut extrapolation = sample_info
+        .filter(|_| !args.no_extrapolate)
+        .map(|info| sample::Extrapolation::new(info, remap.as_ref()));
+    if args.by_device {
+        println!("By device    : yes");
+    }
+    if (args.symlinks, args.special) != (EntryPolicy::All, EntryPolicy::All) {
+        println!(
+            "Accounting   : symlinks {:?}, special files {:?}",
+            args.symlinks, args.special
+        );
+    }
+    let mut skipped_entries = 0u64;
+    let mut malformed_rows = 0u64;
+    let mut partial = false;
+
+    let duplicates = if args.collapse_duplicates {
+        let d = Duplicates::scan(&args.inputs, remap.as_ref(), &filter)?;
+        for (dropped, kept) in &d.pairs {
+            println!(
+                "Duplicate    : {} = {}",
+                String::from_utf8_lossy(dropped),
+                String::from_utf8_lossy(kept)
+            );
+        }
+        Some(d)
+    } else {
+        None
+    };
+    let mut duplicate_rows = 0u64;
 
     let unk_path = {
-        let stem = args
-            .input
+        let stem = first
             .file_stem()
             .and_then(|s| s.to_str())
             .unwrap_or("output");
@@ -76,104 +265,202 @@ fn main() -> Result<()> {
     let mut user_cache: HashMap<u32, String> = HashMap::new();
     let mut unk_uids: HashSet<u32> = HashSet::new();
 
-    let total_lines = count_lines(&args.input)?;
-    let data_lines = total_lines.saturating_sub(1);
+    let mut total_lines = 0;
+    let mut data_lines = 0;
+    for input in &args.inputs {
+        let lines = count_lines(input)?;
+        total_lines += lines;
+        data_lines += lines.saturating_sub(1);
+    }
     println!("Total lines  : {}", total_lines);
 
-    let mut reader = ReaderBuilder::new()
-        .has_headers(true)
-        .flexible(true)
-        .trim(Trim::None)
-        .from_path(&args.input)?;
-
-    let mut aggregated_data: HashMap<(Vec<u8>, String, u8), UserStats> = HashMap::new();
-    let progress_interval = if data_lines >= 10 {
-        data_lines / 10
-    } else {
-        0
-    };
+    let mut aggregated_data: HashMap<AggKey, UserStats> = HashMap::new();
 
     let now_ts = Utc::now().timestamp();
-    let mut seen_inodes: HashSet<Vec<u8>> = HashSet::new();
+    let mut seen_inodes = SeenInodes::new(args.dedupe_scope);
+    let mut anomalies = Anomalies::new(now_ts, args.max_file_size, args.anomalies.as_deref())?;
 
-    for (index, record_result) in reader.byte_records().enumerate() {
-        let record = match record_result {
-            Ok(r) => r,
-            Err(e) => {
-                eprintln!("Warning: Skipping malformed row {}: {}", index + 1, e);
-                continue;
-            }
-        };
-
-        let inode_bytes = record.get(0).unwrap_or(b"").to_vec();
-        // Sentinel "0-0" means the scanner had no inode info (Windows).
-        // Treat every such row as a distinct file so the hardlink-dedup below
-        // does not collapse all but the first row into linked_size.
-        let has_inode = inode_bytes.as_slice() != b"0-0" && !inode_bytes.is_empty();
-        let mode = parse_int::<u32>(record.get(5));
-        let is_dir = (mode & S_IFMT) == S_IFDIR;
-        let raw_atime = parse_int::<i64>(record.get(1));
-        let raw_mtime = parse_int::<i64>(record.get(2));
-        let sanitized_atime = if is_dir {
-            0
-        } else {
-            sanitize_mtime(now_ts, raw_atime)
-        };
-        let sanitized_mtime = sanitize_mtime(now_ts, raw_mtime);
-        let uid = parse_int::<u32>(record.get(3));
-        let user = resolve_user(uid, &mut user_cache);
-        if user == "UNK" {
-            unk_uids.insert(uid);
+    let progress = Arc::new(Counter::with_total(data_lines as u64));
+    let reporter = Reporter::start(
+        "dusum",
+        Unit::Rows,
+        progress.clone(),
+        args.progress.sinks(args.quiet)?,
+    );
+    for (input, manifest) in args.inputs.iter().zip(&manifests) {
+        let mut reader = ReaderBuilder::new()
+            .has_headers(true)
+            .flexible(true)
+            .trim(Trim::None)
+            .from_path(input)?;
+        if args.dedupe_scope == DedupeScope::Input {
+            seen_inodes.clear();
         }
-        let file_size = parse_int::<u64>(record.get(6));
-        let raw_disk = parse_int::<u64>(record.get(7));
+        let mut rows = 0u64;
+        for (index, record_result) in reader.byte_records().enumerate() {
+            progress.add(1);
+            rows += 1;
+            let record = match record_result {
+                Ok(r) => r,
+                Err(e) => {
+                    tracing::warn!(row = index + 1, error = %e, "skipping malformed row");
+                    malformed_rows += 1;
+                    continue;
+                }
+            };
 
-        let (disk_size, linked_size) = if !has_inode {
-            (raw_disk, 0)
-        } else if seen_inodes.insert(inode_bytes) {
-            (raw_disk, 0)
-        } else {
-            (0, raw_disk)
-        };
+            let path_bytes = remap_path(record.get(8).unwrap_or(b""), remap.as_ref());
+            let path_bytes: &[u8] = &path_bytes;
+            if filter.excludes(path_bytes) {
+                excluded_rows += 1;
+                continue;
+            }
 
-        let path_bytes = record.get(8).unwrap_or(b"");
+            let inode_bytes = record.get(0).unwrap_or(b"");
+            let device = if args.by_device {
+                device_of(inode_bytes)
+            } else {
+                0
+            };
+            // Sentinel "0-0" means the scanner had no inode info (a Windows
+            // entry it could not open, or a scan from before it read them).
+            // Treat every such row as a distinct file so the hardlink-dedup below
+            // does not collapse all but the first row into linked_size.
+            let has_inode = inode_bytes != b"0-0" && !inode_bytes.is_empty();
+            let mode = parse_int::<u32>(record.get(5));
+            let class = entry_class(mode);
+            let policy = match class {
+                EntryClass::Symlink => args.symlinks,
+                EntryClass::Special => args.special,
+                EntryClass::Regular | EntryClass::Dir => EntryPolicy::All,
+            };
+            if policy == EntryPolicy::Skip {
+                skipped_entries += 1;
+                continue;
+            }
+            let oversize = anomalies.check(index + 1, &record, path_bytes)?;
+            let is_dir = class == EntryClass::Dir;
+            let raw_atime = parse_int::<i64>(record.get(1));
+            let raw_mtime = parse_int::<i64>(record.get(2));
+            let sanitized_atime = if is_dir {
+                0
+            } else {
+                sanitize_mtime(now_ts, raw_atime)
+            };
+            let sanitized_mtime = sanitize_mtime(now_ts, raw_mtime);
+            let uid = parse_int::<u32>(record.get(3));
+            let user = resolve_user(uid, &mut user_cache);
+            if user == "UNK" {
+                unk_uids.insert(uid);
+            }
+            let (file_size, raw_disk) = if policy == EntryPolicy::Count || oversize {
+                (0, 0)
+            } else {
+                (parse_int::<u64>(record.get(6)), parse_int::<u64>(record.get(7)))
+            };
 
-        if user.is_empty() || path_bytes.is_empty() {
-            continue;
-        }
+            if user.is_empty() || path_bytes.is_empty() {
+                continue;
+            }
 
-        let bucket = age_bucket(now_ts, sanitized_mtime, age_cfg);
+            let mut folder_paths = get_folder_ancestors(path_bytes);
+            if is_dir {
+                let self_path = normalize_folder_bytes(path_bytes);
+                if !self_path.is_empty() && !folder_paths.iter().any(|p| p == &self_path) {
+                    folder_paths.push(self_path);
+                }
+            }
+            if duplicates.as_ref().is_some_and(|d| d.covers(&folder_paths)) {
+                duplicate_rows += 1;
+                continue;
+            }
 
-        let mut folder_paths = get_folder_ancestors(path_bytes);
-        if is_dir {
-            let self_path = normalize_folder_bytes(path_bytes);
-            if !self_path.is_empty() && !folder_paths.iter().any(|p| p == &self_path) {
-                folder_paths.push(self_path);
+            if let Some(p) = projects.as_mut() {
+                p.note(&folder_paths);
             }
-        }
 
-        for folder_path in folder_paths {
-            let key = (folder_path, user.clone(), bucket);
-            aggregated_data.entry(key).or_default().update(
-                file_size,
-                disk_size,
-                linked_size,
-                sanitized_atime,
-                sanitized_mtime,
-            );
-        }
+            let (disk_size, linked_size) = if !has_inode || seen_inodes.insert(inode_bytes) {
+                (raw_disk, 0)
+            } else {
+                (0, raw_disk)
+            };
+
+            let bucket = age_bucket(now_ts, sanitized_mtime, age_cfg);
+            let weight = extrapolation.as_ref().and_then(|e| e.weight(&folder_paths));
 
-        if progress_interval > 0 && (index + 1) % progress_interval == 0 {
-            let percent = ((index + 1) as f64 * 100.0 / data_lines.max(1) as f64).ceil() as u32;
-            println!("{}%", percent.min(100));
+            for folder_path in folder_paths {
+                let key = (folder_path, user.clone(), bucket, device);
+                if let (Some(e), Some(w)) = (extrapolation.as_mut(), weight) {
+                    e.add(key.clone(), w, file_size, disk_size, linked_size);
+                }
+                aggregated_data.entry(key).or_default().update(
+                    file_size,
+                    disk_size,
+                    linked_size,
+                    sanitized_atime,
+                    sanitized_mtime,
+                );
+            }
+        }
+        if let Some(m) = manifest {
+            partial |= check_manifest(input, m.check(rows), args.allow_incomplete)?;
         }
     }
+    reporter.finish();
+    anomalies.finish()?;
 
-    write_results(&output_path, &aggregated_data)?;
+    let extrapolated = extrapolation.map(|e| e.apply(&mut aggregated_data));
+
+    write_results(
+        &output_path,
+        &aggregated_data,
+        write_mode,
+        args.by_device,
+        duplicates.as_ref(),
+        extrapolated.as_ref(),
+        baseline.as_ref(),
+    )?;
     write_unknown_uids(&unk_path, &unk_uids)?;
+    let project_rows = projects.as_ref().map(|p| p.rows(&aggregated_data));
+    if let Some(rows) = &project_rows {
+        write_projects(&projects_out, rows, write_mode)?;
+    }
+    if let Some(db) = &args.history {
+        let scanned_at = history::scan_time(first, manifest);
+        let rec = history::record_history(db, first, scanned_at, &aggregated_data, args.by_device)?;
+        println!("History      : {} ({} rows, scan #{})", db.display(), rec.rows, rec.id);
+    }
 
     let duration = start_time.elapsed();
     println!("Output       : {}", output_path.display());
+    if !filter.is_empty() {
+        println!("Excluded     : {} rows", excluded_rows);
+    }
+    if duplicates.is_some() {
+        println!("Duplicates   : {} rows in repeated subtrees", duplicate_rows);
+    }
+    if skipped_entries > 0 {
+        println!("Skipped      : {} symlink/special rows", skipped_entries);
+    }
+    if malformed_rows > 0 {
+        println!("Malformed    : {} rows skipped", malformed_rows);
+    }
+    if anomalies.rows() > 0 {
+        tracing::warn!(rows = anomalies.rows(), kinds = %anomalies.describe(), "rows with anomalous values");
+        println!("Anomalies    : {} rows ({})", anomalies.rows(), anomalies.describe());
+    }
+    if let Some(path) = &args.anomalies {
+        println!("Anomaly list : {}", path.display());
+    }
+    if let Some(x) = &extrapolated {
+        println!("Extrapolated : {} rollups", x.len());
+    }
+    if let Some(rows) = &project_rows {
+        println!("Projects     : {} ({} projects)", projects_out.display(), rows.len());
+    }
+    if let (Some(path), Some(b)) = (&args.baseline, &baseline) {
+        println!("Baseline     : {} ({} rows)", path.display(), b.rows());
+    }
     println!(
         "Unknown UIDs : {} (total: {})",
         unk_path.display(),
@@ -189,8 +476,38 @@ fn main() -> Result<()> {
         aggregated_data.len(),
         percent_unique
     );
+    tracing::info!(
+        output = %output_path.display(),
+        rows = aggregated_data.len(),
+        elapsed_secs = duration.as_secs_f64(),
+        "summary finished"
+    );
     println!("Elapsed time : {:.2} seconds", duration.as_secs_f64());
-    Ok(())
+    Ok(if partial {
+        Outcome::Partial
+    } else {
+        Outcome::with_errors(malformed_rows)
+    })
+}
+
+/// Refuse to summarize a scan its manifest does not vouch for, unless
+/// `allow` turns the problems into warnings. True when it was allowed: the
+/// summary then covers only part of the tree.
+fn check_manifest(input: &Path, problems: Vec<String>, allow: bool) -> Result<bool> {
+    if problems.is_empty() {
+        return Ok(false);
+    }
+    if allow {
+        for p in &problems {
+            tracing::warn!(input = %input.display(), "{p}");
+        }
+        return Ok(true);
+    }
+    anyhow::bail!(
+        "{}: {} (use --allow-incomplete to summarize anyway)",
+        input.display(),
+        problems.join("; ")
+    )
 }
 
 #[cfg(test)]
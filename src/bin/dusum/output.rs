@@ -1,13 +1,19 @@
 // rs/src/bin/dusum/output.rs
-use anyhow::Result;
-use csv::WriterBuilder;
+use anyhow::{Context, Result};
+use csv::{ReaderBuilder, WriterBuilder};
 use memchr::memchr_iter;
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::Read;
+use std::io::{BufWriter, Read};
 use std::path::Path;
 
+use dutopia::db::earliest;
+use dutopia::util::AtomicFile;
+
 use crate::aggregate::bytes_to_safe_string;
+use crate::baseline::Baseline;
+use crate::dupes::Duplicates;
 use crate::stats::UserStats;
 
 pub fn count_lines(path: &Path) -> Result<usize> {
@@ -25,76 +31,271 @@ pub fn count_lines(path: &Path) -> Result<usize> {
         last = Some(buf[n - 1]);
     }
 
-    if let Some(b) = last {
-        if b != b'\n' {
-            count += 1;
-        }
+    if let Some(b) = last
+        && b != b'\n'
+    {
+        count += 1;
     }
     Ok(count)
 }
 
+/// What to do when the output file already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Refuse to touch an existing file (`--no-clobber`).
+    Create,
+    /// Replace the existing file (the default).
+    Overwrite,
+    /// Merge the new rows into the existing ones (`--append`).
+    Append,
+}
+
+impl WriteMode {
+    pub fn from_flags(no_clobber: bool, append: bool) -> Self {
+        if append {
+            WriteMode::Append
+        } else if no_clobber {
+            WriteMode::Create
+        } else {
+            WriteMode::Overwrite
+        }
+    }
+}
+
+/// Fail early, before any aggregation work, if `path` exists and `mode`
+/// does not allow touching it.
+pub fn check_output(path: &Path, mode: WriteMode) -> Result<()> {
+    if mode == WriteMode::Create && path.exists() {
+        anyhow::bail!(
+            "Output file already exists: {} (drop --no-clobber to overwrite or use --append to add rows)",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Write `path` through `AtomicFile`, so readers never observe a
+/// half-written file.
+fn write_atomic<F>(path: &Path, mode: WriteMode, body: F) -> Result<()>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<()>,
+{
+    check_output(path, mode)?;
+    let (atomic, file) = AtomicFile::create(path)?;
+    let mut out = BufWriter::new(file);
+    body(&mut out)?;
+    let file = out.into_inner().map_err(|e| e.into_error())?;
+    atomic.commit(file)?;
+    Ok(())
+}
+
+/// Write a CSV of `header` and `rows` with `write_atomic`. In append mode
+/// the existing file must have the same header (the same flags wrote it);
+/// its rows and the new ones are merged on the `key` columns, see
+/// `merge_row`, and written sorted by `order`.
+pub fn write_csv<F>(
+    path: &Path,
+    mode: WriteMode,
+    header: &[&str],
+    key: &[usize],
+    mut rows: Vec<Vec<String>>,
+    order: F,
+) -> Result<()>
+where
+    F: FnMut(&Vec<String>, &Vec<String>) -> Ordering,
+{
+    if mode == WriteMode::Append && path.exists() {
+        rows = merge_existing(path, header, key, rows)?;
+        rows.sort_by(order);
+    }
+    write_atomic(path, mode, |out| {
+        let mut writer = WriterBuilder::new().has_headers(false).from_writer(out);
+        writer.write_record(header)?;
+        for row in &rows {
+            writer.write_record(row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    })
+}
+
+/// The rows of the existing `path` with `rows` merged in: a row whose `key`
+/// columns match an earlier one is folded into it, so no key is written
+/// twice. Fails, leaving the file alone, when its header is not `header`.
+fn merge_existing(
+    path: &Path,
+    header: &[&str],
+    key: &[usize],
+    rows: Vec<Vec<String>>,
+) -> Result<Vec<Vec<String>>> {
+    let mut rdr = ReaderBuilder::new()
+        .from_path(path)
+        .with_context(|| format!("reading {} to append to it", path.display()))?;
+    let existing = rdr.headers()?.clone();
+    if existing.is_empty() {
+        return Ok(rows);
+    }
+    if !existing.iter().eq(header.iter().copied()) {
+        anyhow::bail!(
+            "{} has the columns {}, this run writes {}; --append needs the same flags \
+             that wrote it",
+            path.display(),
+            existing.iter().collect::<Vec<_>>().join(","),
+            header.join(",")
+        );
+    }
+    let mut merged: Vec<Vec<String>> = Vec::new();
+    let mut index: HashMap<Vec<String>, usize> = HashMap::new();
+    let old = rdr.records().map(|r| {
+        r.map(|r| r.iter().map(str::to_string).collect::<Vec<_>>())
+            .with_context(|| format!("reading {}", path.display()))
+    });
+    for row in old.chain(rows.into_iter().map(Ok)) {
+        let row = row?;
+        let k: Vec<String> = key.iter().map(|&i| row[i].clone()).collect();
+        match index.get(&k) {
+            Some(&i) => merge_row(header, &mut merged[i], &row),
+            None => {
+                index.insert(k, merged.len());
+                merged.push(row);
+            }
+        }
+    }
+    Ok(merged)
+}
+
+/// Fold `new` into `old`, two rows for the same key: counts, sizes and
+/// deltas add up, latest times and flags take the larger value, oldest
+/// times the earliest known (0 is unknown). `users` keeps the larger count
+/// (the user sets are not in the file) and `top_user` goes with the larger
+/// `disk`.
+fn merge_row(header: &[&str], old: &mut [String], new: &[String]) {
+    let num = |s: &str| s.trim().parse::<i64>().unwrap_or(0);
+    let new_bigger = header
+        .iter()
+        .position(|h| *h == "disk")
+        .is_some_and(|i| num(&new[i]) > num(&old[i]));
+    for (i, name) in header.iter().enumerate() {
+        let (a, b) = (num(&old[i]), num(&new[i]));
+        let v = match *name {
+            "files" | "size" | "disk" | "linked" | "files_delta" | "disk_delta" => a + b,
+            "accessed" | "modified" | "users" | "duplicated_paths" | "extrapolated" => a.max(b),
+            "oldest_accessed" | "oldest_modified" => earliest(a, b),
+            "top_user" if new_bigger => {
+                old[i] = new[i].clone();
+                continue;
+            }
+            _ => continue,
+        };
+        old[i] = v.to_string();
+    }
+}
+
+/// Aggregation key: (folder, user, age bucket, device). The device is 0
+/// unless dusum runs with `--by-device`.
+pub type AggKey = (Vec<u8>, String, u8, u64);
+
+/// With `by_device`, a trailing `device` column is written and a folder gets
+/// one row per (user, age, device). With `duplicates`
+/// (`--collapse-duplicates`), a `duplicated_paths` column follows. For a
+/// sampled scan, a last `extrapolated` column is 1 for the `extrapolated`
+/// rollups and 0 for measured ones. With a `baseline` (`--baseline`),
+/// `files_delta` and `disk_delta` follow. `oldest_accessed` and
+/// `oldest_modified` always close the row (0 when unset), so past the first
+/// nine columns readers must go by header name, as dudb does.
 pub fn write_results(
     output_path: &Path,
-    aggregated_data: &HashMap<(Vec<u8>, String, u8), UserStats>,
+    aggregated_data: &HashMap<AggKey, UserStats>,
+    mode: WriteMode,
+    by_device: bool,
+    duplicates: Option<&Duplicates>,
+    extrapolated: Option<&HashSet<AggKey>>,
+    baseline: Option<&Baseline>,
 ) -> Result<()> {
     let mut sorted_entries: Vec<_> = aggregated_data.iter().collect();
-    sorted_entries.sort_by(|a, b| {
-        let (path_a, user_a, age_a) = &a.0;
-        let (path_b, user_b, age_b) = &b.0;
-        path_a
-            .cmp(path_b)
-            .then_with(|| user_a.cmp(user_b))
-            .then_with(|| age_a.cmp(age_b))
-    });
+    sorted_entries.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut header = vec![
+        "path", "user", "age", "files", "size", "disk", "linked", "accessed", "modified",
+    ];
+    let mut key_cols = vec![0, 1, 2];
+    if by_device {
+        key_cols.push(header.len());
+        header.push("device");
+    }
+    if duplicates.is_some() {
+        header.push("duplicated_paths");
+    }
+    if extrapolated.is_some() {
+        header.push("extrapolated");
+    }
+    if baseline.is_some() {
+        header.extend(["files_delta", "disk_delta"]);
+    }
+    header.extend(["oldest_accessed", "oldest_modified"]);
 
-    let mut writer = WriterBuilder::new()
-        .has_headers(true)
-        .from_path(output_path)?;
-
-    writer.write_record(&[
-        "path",
-        "user",
-        "age",
-        "files",
-        "size",
-        "disk",
-        "linked",
-        "accessed",
-        "modified",
-    ])?;
-
-    for ((path_bytes, user, age), stats) in sorted_entries {
+    let mut rows = Vec::with_capacity(sorted_entries.len());
+    for (key, stats) in sorted_entries {
+        let (path_bytes, user, age, device) = key;
         let path_str = bytes_to_safe_string(path_bytes);
-        writer.write_record(&[
-            &path_str,
-            user,
-            &age.to_string(),
-            &stats.file_count.to_string(),
-            &stats.file_size.to_string(),
-            &stats.disk_size.to_string(),
-            &stats.linked_size.to_string(),
-            &stats.latest_atime.to_string(),
-            &stats.latest_mtime.to_string(),
-        ])?;
+        let delta = baseline.map(|b| {
+            b.delta(&path_str, user, *age, *device, stats.file_count, stats.disk_size)
+        });
+        let mut record = vec![
+            path_str,
+            user.clone(),
+            age.to_string(),
+            stats.file_count.to_string(),
+            stats.file_size.to_string(),
+            stats.disk_size.to_string(),
+            stats.linked_size.to_string(),
+            stats.latest_atime.to_string(),
+            stats.latest_mtime.to_string(),
+        ];
+        if by_device {
+            record.push(device.to_string());
+        }
+        if let Some(d) = duplicates {
+            record.push(d.count(path_bytes).to_string());
+        }
+        if let Some(x) = extrapolated {
+            record.push(u8::from(x.contains(key)).to_string());
+        }
+        if let Some((files, disk)) = delta {
+            record.push(files.to_string());
+            record.push(disk.to_string());
+        }
+        record.push(stats.oldest_atime.to_string());
+        record.push(stats.oldest_mtime.to_string());
+        rows.push(record);
     }
 
-    writer.flush()?;
-    Ok(())
+    // Merged rows are re-sorted as the aggregation sorts its keys: path,
+    // user, then the numeric age and device.
+    let num = |r: &[String], i: Option<usize>| i.and_then(|i| r[i].parse::<u64>().ok());
+    let device = key_cols.get(3).copied();
+    write_csv(output_path, mode, &header, &key_cols, rows, |a, b| {
+        a[0].cmp(&b[0])
+            .then_with(|| a[1].cmp(&b[1]))
+            .then_with(|| num(a, Some(2)).cmp(&num(b, Some(2))))
+            .then_with(|| num(a, device).cmp(&num(b, device)))
+    })
 }
 
+/// The unknown-UID list is a derived sidecar, so it is always replaced
+/// (atomically) regardless of the policy used for the main output.
 pub fn write_unknown_uids(unk_path: &Path, unk_uids: &HashSet<u32>) -> Result<()> {
     let mut list: Vec<u32> = unk_uids.iter().copied().collect();
     list.sort_unstable();
 
-    let mut wtr = WriterBuilder::new()
-        .has_headers(false)
-        .from_path(unk_path)?;
-
-    for uid in list {
-        wtr.write_record(&[uid.to_string()])?;
-    }
-    wtr.flush()?;
-    Ok(())
+    write_atomic(unk_path, WriteMode::Overwrite, |out| {
+        let mut wtr = WriterBuilder::new().has_headers(false).from_writer(out);
+        for uid in list {
+            wtr.write_record(&[uid.to_string()])?;
+        }
+        wtr.flush()?;
+        Ok(())
+    })
 }
 
 #[cfg(test)]
@@ -126,15 +327,15 @@ mod tests {
 
     #[test]
     fn write_results_emits_utf8_paths() {
-        let mut map: HashMap<(Vec<u8>, String, u8), UserStats> = HashMap::new();
-        let key = (vec![b'/', 0xFFu8, b'a'], "user".to_string(), 0u8);
+        let mut map: HashMap<AggKey, UserStats> = HashMap::new();
+        let key = (vec![b'/', 0xFFu8, b'a'], "user".to_string(), 0u8, 0);
         let mut s = UserStats::default();
         s.update(512, 512, 0, 1_700_000_000, 1_700_000_000);
         map.insert(key, s);
 
         let tmp = std::env::temp_dir().join(format!("sum_out_{}.csv", std::process::id()));
         let _ = fs::remove_file(&tmp);
-        write_results(&tmp, &map).unwrap();
+        write_results(&tmp, &map, WriteMode::Create, false, None, None, None).unwrap();
 
         let contents = fs::read_to_string(&tmp).unwrap();
         fs::remove_file(&tmp).ok();
@@ -150,10 +351,10 @@ mod tests {
 
     #[test]
     fn write_results_is_sorted_by_path_user_age() {
-        let mut map: HashMap<(Vec<u8>, String, u8), UserStats> = HashMap::new();
+        let mut map: HashMap<AggKey, UserStats> = HashMap::new();
 
         map.insert(
-            (b"/a/b".to_vec(), "user2".to_string(), 1),
+            (b"/a/b".to_vec(), "user2".to_string(), 1, 0),
             UserStats {
                 file_count: 2,
                 file_size: 200,
@@ -161,10 +362,11 @@ mod tests {
                 linked_size: 0,
                 latest_atime: 20,
                 latest_mtime: 20,
+                ..Default::default()
             },
         );
         map.insert(
-            (b"/a".to_vec(), "user1".to_string(), 0),
+            (b"/a".to_vec(), "user1".to_string(), 0, 0),
             UserStats {
                 file_count: 1,
                 file_size: 100,
@@ -172,10 +374,11 @@ mod tests {
                 linked_size: 0,
                 latest_atime: 20,
                 latest_mtime: 10,
+                ..Default::default()
             },
         );
         map.insert(
-            (b"/a".to_vec(), "user0".to_string(), 2),
+            (b"/a".to_vec(), "user0".to_string(), 2, 0),
             UserStats {
                 file_count: 3,
                 file_size: 300,
@@ -183,17 +386,18 @@ mod tests {
                 linked_size: 0,
                 latest_atime: 20,
                 latest_mtime: 30,
+                ..Default::default()
             },
         );
 
         let tmp = NamedTempFile::new().unwrap();
-        write_results(tmp.path(), &map).unwrap();
+        write_results(tmp.path(), &map, WriteMode::Overwrite, false, None, None, None).unwrap();
 
         let contents = fs::read_to_string(tmp.path()).unwrap();
         let mut lines = contents.lines();
         assert_eq!(
             lines.next().unwrap(),
-            "path,user,age,files,size,disk,linked,accessed,modified"
+            "path,user,age,files,size,disk,linked,accessed,modified,oldest_accessed,oldest_modified"
         );
 
         let row1 = lines.next().unwrap().to_string();
@@ -209,9 +413,9 @@ mod tests {
 
     #[test]
     fn write_results_includes_all_fields() {
-        let mut map: HashMap<(Vec<u8>, String, u8), UserStats> = HashMap::new();
+        let mut map: HashMap<AggKey, UserStats> = HashMap::new();
         map.insert(
-            (b"/test".to_vec(), "testuser".to_string(), 1),
+            (b"/test".to_vec(), "testuser".to_string(), 1, 0),
             UserStats {
                 file_count: 5,
                 file_size: 1000,
@@ -219,11 +423,13 @@ mod tests {
                 linked_size: 200,
                 latest_atime: 1234567890,
                 latest_mtime: 1234567900,
+                oldest_atime: 1234500000,
+                oldest_mtime: 1234400000,
             },
         );
 
         let tmp = NamedTempFile::new().unwrap();
-        write_results(tmp.path(), &map).unwrap();
+        write_results(tmp.path(), &map, WriteMode::Overwrite, false, None, None, None).unwrap();
 
         let contents = fs::read_to_string(tmp.path()).unwrap();
         let mut lines = contents.lines();
@@ -232,10 +438,28 @@ mod tests {
         let data_line = lines.next().unwrap();
         assert_eq!(
             data_line,
-            "/test,testuser,1,5,1000,800,200,1234567890,1234567900"
+            "/test,testuser,1,5,1000,800,200,1234567890,1234567900,1234500000,1234400000"
         );
     }
 
+    #[test]
+    fn write_results_by_device_adds_column() {
+        let mut map: HashMap<AggKey, UserStats> = HashMap::new();
+        map.insert((b"/m".to_vec(), "u".to_string(), 0, 2049), UserStats::default());
+        map.insert((b"/m".to_vec(), "u".to_string(), 0, 64768), UserStats::default());
+
+        let tmp = NamedTempFile::new().unwrap();
+        write_results(tmp.path(), &map, WriteMode::Overwrite, true, None, None, None).unwrap();
+        let s = fs::read_to_string(tmp.path()).unwrap();
+        let lines: Vec<&str> = s.lines().collect();
+        assert_eq!(
+            lines[0],
+            "path,user,age,files,size,disk,linked,accessed,modified,device,oldest_accessed,oldest_modified"
+        );
+        assert_eq!(lines[1], "/m,u,0,0,0,0,0,0,0,2049,0,0");
+        assert_eq!(lines[2], "/m,u,0,0,0,0,0,0,0,64768,0,0");
+    }
+
     #[test]
     fn write_unknown_uids_is_sorted() {
         let tmp = NamedTempFile::new().unwrap();
@@ -259,4 +483,88 @@ mod tests {
         let s = fs::read_to_string(tmp.path()).unwrap();
         assert_eq!(s, "");
     }
+
+    #[test]
+    fn write_results_create_refuses_existing_file() {
+        let tmp = NamedTempFile::new().unwrap();
+        let map: HashMap<AggKey, UserStats> = HashMap::new();
+        let err = write_results(tmp.path(), &map, WriteMode::Create, false, None, None, None).unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+    }
+
+    #[test]
+    fn write_results_append_keeps_rows_and_single_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("x.sum.csv");
+
+        let mut map: HashMap<AggKey, UserStats> = HashMap::new();
+        map.insert((b"/a".to_vec(), "u1".to_string(), 0, 0), UserStats::default());
+        write_results(&out, &map, WriteMode::Create, false, None, None, None).unwrap();
+
+        let mut map2: HashMap<AggKey, UserStats> = HashMap::new();
+        map2.insert((b"/b".to_vec(), "u2".to_string(), 1, 0), UserStats::default());
+        write_results(&out, &map2, WriteMode::Append, false, None, None, None).unwrap();
+
+        let s = fs::read_to_string(&out).unwrap();
+        let lines: Vec<&str> = s.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("path,user,age"));
+        assert!(lines[1].starts_with("/a,u1,0,"));
+        assert!(lines[2].starts_with("/b,u2,1,"));
+
+        // Only the final file remains; the temp was renamed away.
+        let names: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(names.len(), 1);
+    }
+
+    #[test]
+    fn write_results_append_merges_rows_with_the_same_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("x.sum.csv");
+        let stats = |files, disk, mtime, oldest_mtime| UserStats {
+            file_count: files,
+            disk_size: disk,
+            latest_mtime: mtime,
+            oldest_mtime,
+            ..Default::default()
+        };
+
+        let mut map: HashMap<AggKey, UserStats> = HashMap::new();
+        map.insert((b"/".to_vec(), "u1".to_string(), 0, 0), stats(2, 200, 50, 10));
+        map.insert((b"/b".to_vec(), "u1".to_string(), 0, 0), stats(1, 100, 50, 10));
+        write_results(&out, &map, WriteMode::Create, false, None, None, None).unwrap();
+
+        let mut map2: HashMap<AggKey, UserStats> = HashMap::new();
+        map2.insert((b"/".to_vec(), "u1".to_string(), 0, 0), stats(3, 300, 40, 5));
+        map2.insert((b"/a".to_vec(), "u1".to_string(), 0, 0), stats(3, 300, 40, 5));
+        write_results(&out, &map2, WriteMode::Append, false, None, None, None).unwrap();
+
+        let s = fs::read_to_string(&out).unwrap();
+        let lines: Vec<&str> = s.lines().collect();
+        assert_eq!(
+            lines[1..],
+            ["/,u1,0,5,0,500,0,0,50,0,5", "/a,u1,0,3,0,300,0,0,40,0,5", "/b,u1,0,1,0,100,0,0,50,0,10"]
+        );
+    }
+
+    #[test]
+    fn write_results_append_refuses_other_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("x.sum.csv");
+        let mut map: HashMap<AggKey, UserStats> = HashMap::new();
+        map.insert((b"/a".to_vec(), "u1".to_string(), 0, 0), UserStats::default());
+        write_results(&out, &map, WriteMode::Create, false, None, None, None).unwrap();
+        let before = fs::read_to_string(&out).unwrap();
+
+        let err = write_results(&out, &map, WriteMode::Append, true, None, None, None).unwrap_err();
+        assert!(err.to_string().contains("same flags"), "{err}");
+        assert_eq!(fs::read_to_string(&out).unwrap(), before);
+    }
+
+    #[test]
+    fn write_mode_from_flags() {
+        assert_eq!(WriteMode::from_flags(false, false), WriteMode::Overwrite);
+        assert_eq!(WriteMode::from_flags(true, false), WriteMode::Create);
+        assert_eq!(WriteMode::from_flags(false, true), WriteMode::Append);
+    }
 }
@@ -0,0 +1,223 @@
+// rs/src/bin/dusum/projects.rs
+//
+// `--project-depth N`: a compact per-project summary next to the full one,
+// a project being a folder N components below the root (`/proj/alpha` is at
+// depth 2, `C:\Data\alpha` too). `<output>.projects.csv` has one row per
+// project, largest disk first:
+//
+//   project,users,top_user,files,size,disk,linked,modified,oldest_modified
+//
+// The totals are the project folder's own rollups summed over users, ages
+// and devices, so they follow hard-link dedupe, `--collapse-duplicates` and
+// sampling exactly as the full summary does. `top_user` holds the most disk.
+// Rows above the project depth belong to no project.
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::aggregate::bytes_to_safe_string;
+use crate::output::{write_csv, AggKey, WriteMode};
+use crate::stats::UserStats;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProjectRow {
+    pub project: String,
+    pub users: u64,
+    pub top_user: String,
+    pub files: u64,
+    pub size: u64,
+    pub disk: u64,
+    pub linked: u64,
+    pub modified: i64,
+    pub oldest_modified: i64,
+}
+
+#[derive(Debug)]
+pub struct Projects {
+    depth: usize,
+    paths: HashSet<Vec<u8>>,
+}
+
+impl Projects {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            paths: HashSet::new(),
+        }
+    }
+
+    /// Note the project of a row, given its folders outer to inner with the
+    /// root first.
+    pub fn note(&mut self, folder_paths: &[Vec<u8>]) {
+        if let Some(p) = folder_paths.get(self.depth)
+            && !self.paths.contains(p)
+        {
+            self.paths.insert(p.clone());
+        }
+    }
+
+    pub fn rows(&self, aggregated: &HashMap<AggKey, UserStats>) -> Vec<ProjectRow> {
+        let mut per_user: HashMap<&[u8], HashMap<&str, UserStats>> = HashMap::new();
+        for ((path, user, _, _), s) in aggregated {
+            if !self.paths.contains(path) {
+                continue;
+            }
+            per_user
+                .entry(path.as_slice())
+                .or_default()
+                .entry(user.as_str())
+                .or_default()
+                .absorb(s);
+        }
+
+        let mut out: Vec<ProjectRow> = per_user
+            .into_iter()
+            .map(|(path, users)| {
+                let mut total = UserStats::default();
+                let mut top: Option<(&str, u64)> = None;
+                for (user, s) in &users {
+                    total.absorb(s);
+                    if top.is_none_or(|(u, d)| s.disk_size > d || (s.disk_size == d && *user < u)) {
+                        top = Some((user, s.disk_size));
+                    }
+                }
+                ProjectRow {
+                    project: bytes_to_safe_string(path),
+                    users: users.len() as u64,
+                    top_user: top.map(|(u, _)| u.to_string()).unwrap_or_default(),
+                    files: total.file_count,
+                    size: total.file_size,
+                    disk: total.disk_size,
+                    linked: total.linked_size,
+                    modified: total.latest_mtime,
+                    oldest_modified: total.oldest_mtime,
+                }
+            })
+            .collect();
+        out.sort_by(|a, b| b.disk.cmp(&a.disk).then_with(|| a.project.cmp(&b.project)));
+        out
+    }
+}
+
+/// `x.sum.csv` -> `x.projects.csv`; any other name gets `.projects.csv` in
+/// place of its extension.
+pub fn projects_path(output: &Path) -> PathBuf {
+    let name = output
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stem = name
+        .strip_suffix(".sum.csv")
+        .map(str::to_string)
+        .unwrap_or_else(|| {
+            Path::new(&name)
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "output".to_string())
+        });
+    output.with_file_name(format!("{stem}.projects.csv"))
+}
+
+pub fn write_projects(path: &Path, rows: &[ProjectRow], mode: WriteMode) -> Result<()> {
+    let header = [
+        "project",
+        "users",
+        "top_user",
+        "files",
+        "size",
+        "disk",
+        "linked",
+        "modified",
+        "oldest_modified",
+    ];
+    let rows = rows
+        .iter()
+        .map(|r| {
+            vec![
+                r.project.clone(),
+                r.users.to_string(),
+                r.top_user.clone(),
+                r.files.to_string(),
+                r.size.to_string(),
+                r.disk.to_string(),
+                r.linked.to_string(),
+                r.modified.to_string(),
+                r.oldest_modified.to_string(),
+            ]
+        })
+        .collect();
+    // Largest disk first, as `Projects::rows` orders them.
+    let disk = |r: &[String]| r[5].parse::<u64>().unwrap_or(0);
+    write_csv(path, mode, &header, &[0], rows, |a, b| {
+        disk(b).cmp(&disk(a)).then_with(|| a[0].cmp(&b[0]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dutopia::util::get_folder_ancestors;
+
+    #[test]
+    fn projects_sum_their_folder_rollups() {
+        let mut projects = Projects::new(2);
+        let mut agg: HashMap<AggKey, UserStats> = HashMap::new();
+        let rows: [(&[u8], &str, u8, u64, i64); 4] = [
+            (b"/proj/alpha/a.bin", "alice", 0, 100, 50),
+            (b"/proj/alpha/deep/b.bin", "bob", 2, 300, 20),
+            (b"/proj/beta/c.bin", "alice", 1, 50, 30),
+            (b"/proj/top.txt", "carol", 0, 999, 40),
+        ];
+        for (path, user, age, disk, mtime) in rows {
+            let folders = get_folder_ancestors(path);
+            projects.note(&folders);
+            for f in folders {
+                agg.entry((f, user.to_string(), age, 0))
+                    .or_default()
+                    .update(disk, disk, 0, 0, mtime);
+            }
+        }
+
+        let out = projects.rows(&agg);
+        assert_eq!(out.len(), 2);
+        assert_eq!(
+            out[0],
+            ProjectRow {
+                project: "/proj/alpha".into(),
+                users: 2,
+                top_user: "bob".into(),
+                files: 2,
+                size: 400,
+                disk: 400,
+                linked: 0,
+                modified: 50,
+                oldest_modified: 20,
+            }
+        );
+        assert_eq!(out[1].project, "/proj/beta");
+
+        assert_eq!(projects_path(Path::new("out/scan.sum.csv")), Path::new("out/scan.projects.csv"));
+        assert_eq!(projects_path(Path::new("report.csv")), Path::new("report.projects.csv"));
+
+        let tmp = tempfile::tempdir().unwrap();
+        let csv = tmp.path().join("p.csv");
+        write_projects(&csv, &out, WriteMode::Create).unwrap();
+        let text = std::fs::read_to_string(&csv).unwrap();
+        assert!(text.starts_with("project,users,top_user,"));
+        assert!(text.contains("/proj/alpha,2,bob,2,400,400,0,50,20\n"));
+
+        // Appending a run that saw more of alpha merges it into one row.
+        let more = ProjectRow {
+            users: 1,
+            top_user: "carol".into(),
+            disk: 900,
+            oldest_modified: 5,
+            ..out[0].clone()
+        };
+        write_projects(&csv, &[more], WriteMode::Append).unwrap();
+        let text = std::fs::read_to_string(&csv).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3, "{text}");
+        assert_eq!(lines[1], "/proj/alpha,2,carol,4,800,1300,0,50,5");
+    }
+}
@@ -0,0 +1,135 @@
+// rs/src/bin/dusum/sample.rs
+//
+// Summaries of sampled scans (`duscan --sample`). The manifest lists, per
+// stratum, how many subdirectories it has and which were scanned; every row
+// below a scanned one stands for dirs / sampled rows of its stratum. Those
+// rows are summed as usual and, separately, the extra (weight - 1) share of
+// their counts and sizes, which is added to the rollups at the end. Rollups
+// that received any extra are marked in the `extrapolated` column, so a
+// sampled pipeline gives totals comparable to a full scan and readers can
+// still tell measured from estimated numbers. Latest access and modification
+// times are not scaled.
+use std::collections::{HashMap, HashSet};
+
+use dutopia::manifest::SampleInfo;
+use dutopia::util::{normalize_folder_bytes, separator};
+
+use crate::aggregate::remap_path;
+use crate::output::AggKey;
+use crate::stats::UserStats;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Extra {
+    files: f64,
+    size: f64,
+    disk: f64,
+    linked: f64,
+}
+
+pub struct Extrapolation {
+    /// Sampled subdirectory -> weight
+    weights: HashMap<Vec<u8>, f64>,
+    extra: HashMap<AggKey, Extra>,
+}
+
+impl Extrapolation {
+    /// Weights for the sampled subdirectories of `info`, with paths remapped
+    /// like the rows.
+    pub fn new(info: &SampleInfo, remap: Option<&(String, String)>) -> Self {
+        let mut weights = HashMap::new();
+        for s in &info.strata {
+            let w = s.weight();
+            let parent = normalize_folder_bytes(&remap_path(s.path.as_bytes(), remap));
+            let sep = separator(&parent);
+            for name in &s.sampled {
+                let mut dir = parent.clone();
+                if dir.last() != Some(&sep) {
+                    dir.push(sep);
+                }
+                dir.extend_from_slice(name.as_bytes());
+                weights.insert(dir, w);
+            }
+        }
+        Self {
+            weights,
+            extra: HashMap::new(),
+        }
+    }
+
+    /// Weight of a row whose folders (outer to inner, the row itself last
+    /// for directories) are `folders`; `None` outside sampled subtrees.
+    pub fn weight(&self, folders: &[Vec<u8>]) -> Option<f64> {
+        folders
+            .iter()
+            .find_map(|f| self.weights.get(f).copied())
+            .filter(|&w| w != 1.0)
+    }
+
+    /// Record the scaled-up share of one row in the rollup `key`.
+    pub fn add(&mut self, key: AggKey, weight: f64, size: u64, disk: u64, linked: u64) {
+        let e = self.extra.entry(key).or_default();
+        let k = weight - 1.0;
+        e.files += k;
+        e.size += k * size as f64;
+        e.disk += k * disk as f64;
+        e.linked += k * linked as f64;
+    }
+
+    /// Add the extra shares to `data`; returns the rollups that changed.
+    pub fn apply(self, data: &mut HashMap<AggKey, UserStats>) -> HashSet<AggKey> {
+        let mut marked = HashSet::with_capacity(self.extra.len());
+        for (key, e) in self.extra {
+            if let Some(s) = data.get_mut(&key) {
+                s.file_count = s.file_count.saturating_add(e.files.round() as u64);
+                s.file_size = s.file_size.saturating_add(e.size.round() as u64);
+                s.disk_size = s.disk_size.saturating_add(e.disk.round() as u64);
+                s.linked_size = s.linked_size.saturating_add(e.linked.round() as u64);
+                marked.insert(key);
+            }
+        }
+        marked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dutopia::util::get_folder_ancestors;
+    use dutopia::manifest::SampleStratum;
+
+    #[test]
+    fn rows_below_sampled_dirs_are_scaled_and_marked() {
+        let info = SampleInfo {
+            rate: 0.5,
+            depth: 1,
+            strata: vec![SampleStratum {
+                path: "/scan/home".into(),
+                dirs: 4,
+                sampled: vec!["alice".into()],
+            }],
+        };
+        let remap = ("/scan".to_string(), "".to_string());
+        let mut ex = Extrapolation::new(&info, Some(&remap));
+        let mut data: HashMap<AggKey, UserStats> = HashMap::new();
+        for (path, size) in [("/home/alice/a", 10u64), ("/home/top", 5)] {
+            let folders = get_folder_ancestors(path.as_bytes());
+            let w = ex.weight(&folders);
+            for f in folders {
+                let key: AggKey = (f, "u".into(), 0, 0);
+                if let Some(w) = w {
+                    ex.add(key.clone(), w, size, size, 0);
+                }
+                data.entry(key).or_default().update(size, size, 0, 0, 0);
+            }
+        }
+        let marked = ex.apply(&mut data);
+        let home = &data[&(b"/home".to_vec(), "u".into(), 0, 0)];
+        assert_eq!((home.file_count, home.file_size), (5, 45));
+        let alice = &data[&(b"/home/alice".to_vec(), "u".into(), 0, 0)];
+        assert_eq!((alice.file_count, alice.disk_size), (4, 40));
+        assert_eq!(marked.len(), 3);
+
+        let none = Extrapolation::new(&SampleInfo::default(), None);
+        assert_eq!(none.weight(&get_folder_ancestors(b"/home/alice/a")), None);
+    }
+}
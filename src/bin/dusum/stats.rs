@@ -51,6 +51,21 @@ pub struct UserStats {
     pub linked_size: u64,
     pub latest_atime: i64,
     pub latest_mtime: i64,
+    /// Oldest set times; 0 while none was seen. Unset times (directory
+    /// atimes, mtimes sanitized away) are left out, so that one directory
+    /// row does not make every folder's oldest access 1970.
+    pub oldest_atime: i64,
+    pub oldest_mtime: i64,
+}
+
+/// The earlier of two times, ignoring unset (`<= 0`) ones.
+fn older(a: i64, b: i64) -> i64 {
+    match (a > 0, b > 0) {
+        (true, true) => a.min(b),
+        (true, false) => a,
+        (false, true) => b,
+        (false, false) => 0,
+    }
 }
 
 impl UserStats {
@@ -72,6 +87,20 @@ impl UserStats {
         if mtime_secs > self.latest_mtime {
             self.latest_mtime = mtime_secs;
         }
+        self.oldest_atime = older(self.oldest_atime, atime_secs);
+        self.oldest_mtime = older(self.oldest_mtime, mtime_secs);
+    }
+
+    /// Add another set of totals (e.g. the same folder on another device).
+    pub fn absorb(&mut self, o: &UserStats) {
+        self.file_count = self.file_count.saturating_add(o.file_count);
+        self.file_size = self.file_size.saturating_add(o.file_size);
+        self.disk_size = self.disk_size.saturating_add(o.disk_size);
+        self.linked_size = self.linked_size.saturating_add(o.linked_size);
+        self.latest_atime = self.latest_atime.max(o.latest_atime);
+        self.latest_mtime = self.latest_mtime.max(o.latest_mtime);
+        self.oldest_atime = older(self.oldest_atime, o.oldest_atime);
+        self.oldest_mtime = older(self.oldest_mtime, o.oldest_mtime);
     }
 }
 
@@ -85,6 +114,55 @@ pub fn sanitize_mtime(now_ts: i64, mtime_ts: i64) -> i64 {
     }
 }
 
+/// How symlinks or special files (sockets, FIFOs, device nodes) are
+/// accounted (`--symlinks`, `--special`).
+#[derive(clap::ValueEnum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum EntryPolicy {
+    /// Count the entry and add its size
+    #[default]
+    All,
+    /// Count the entry but add no bytes, as inode quotas do
+    Count,
+    /// Leave the entry out entirely
+    Skip,
+}
+
+/// Which rows share one set of seen inodes for hard-link accounting
+/// (`--dedupe-scope`): the first row of an inode gets the disk bytes, later
+/// ones are `linked`.
+#[derive(clap::ValueEnum, Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DedupeScope {
+    /// One set per input file
+    Input,
+    /// One set across all inputs, keyed on dev-ino
+    #[default]
+    All,
+    /// One set across all inputs, keyed on the inode number alone: scans of
+    /// one shared filesystem from hosts that give it different device ids
+    Inode,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EntryClass {
+    Regular,
+    Dir,
+    Symlink,
+    /// Socket, FIFO, block or char device
+    Special,
+}
+
+/// Entry class from the MODE column (POSIX type bits as written by duscan;
+/// a zero type counts as a regular file).
+pub fn entry_class(mode: u32) -> EntryClass {
+    const S_IFMT: u32 = 0o170000;
+    match mode & S_IFMT {
+        0o040000 => EntryClass::Dir,
+        0o120000 => EntryClass::Symlink,
+        0o140000 | 0o010000 | 0o060000 | 0o020000 => EntryClass::Special,
+        _ => EntryClass::Regular,
+    }
+}
+
 /// Bucket age in days using configurable thresholds:
 /// 0: recent (< young)
 /// 1: not too old (>= young and < old)
@@ -133,6 +211,22 @@ mod tests {
         assert_eq!(stats.latest_mtime, 8000);
     }
 
+    #[test]
+    fn userstats_keep_oldest_set_times() {
+        let mut stats = UserStats::default();
+        stats.update(1, 1, 0, 0, 6000);
+        stats.update(1, 1, 0, 5000, 0);
+        stats.update(1, 1, 0, 7000, 4000);
+        assert_eq!((stats.oldest_atime, stats.oldest_mtime), (5000, 4000));
+
+        let mut other = UserStats::default();
+        other.update(1, 1, 0, 3000, 9000);
+        stats.absorb(&other);
+        stats.absorb(&UserStats::default());
+        assert_eq!((stats.oldest_atime, stats.oldest_mtime), (3000, 4000));
+        assert_eq!((stats.latest_atime, stats.latest_mtime), (7000, 9000));
+    }
+
     #[test]
     fn age_bucket_categorizes_correctly() {
         let cfg = AgeCfg { young: 60, old: 600 };
@@ -173,6 +267,17 @@ mod tests {
         assert_eq!(sanitize_mtime(now, now + 365 * 86_400), 0);
     }
 
+    #[test]
+    fn entry_class_from_mode() {
+        assert_eq!(entry_class(0o100644), EntryClass::Regular);
+        assert_eq!(entry_class(0), EntryClass::Regular);
+        assert_eq!(entry_class(0o040755), EntryClass::Dir);
+        assert_eq!(entry_class(0o120777), EntryClass::Symlink);
+        for special in [0o140755, 0o010644, 0o060660, 0o020666] {
+            assert_eq!(entry_class(special), EntryClass::Special);
+        }
+    }
+
     #[test]
     fn age_cfg_default_values() {
         let cfg = AgeCfg::default();
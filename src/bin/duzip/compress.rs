@@ -3,26 +3,33 @@ use anyhow::Result;
 use std::fs::File;
 use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::path::PathBuf;
+use std::sync::Arc;
 
+use dutopia::util::progress::{Counter, CountingReader};
+
+use crate::output::create_output;
 use crate::record::{parse_csv_record_bytes, BinaryRecord};
+use crate::verify::{verify_zst, Checksum};
 
 pub const READ_BUF_SIZE: usize = 2 * 1024 * 1024;
 pub const WRITE_BUF_SIZE: usize = 8 * 1024 * 1024;
 
-pub fn csv_to_zst(input: &PathBuf, output: Option<&PathBuf>) -> Result<()> {
+pub fn csv_to_zst(
+    input: &PathBuf,
+    output: Option<&PathBuf>,
+    force: bool,
+    verify: bool,
+    progress: Arc<Counter>,
+) -> Result<()> {
     let start = std::time::Instant::now();
-    let input_file = File::open(input)?;
+    let input_file = CountingReader::new(File::open(input)?, progress);
     let mut reader = BufReader::with_capacity(READ_BUF_SIZE, input_file);
 
     let out_path = output
         .cloned()
         .unwrap_or_else(|| input.with_extension("zst"));
 
-    if out_path.exists() {
-        anyhow::bail!("Output file already exists: {}", out_path.display());
-    }
-
-    let out_file = File::create(&out_path)?;
+    let (atomic, out_file) = create_output(&out_path, force)?;
     let encoder = zstd::stream::write::Encoder::new(out_file, 1)?;
     let mut writer = BufWriter::with_capacity(WRITE_BUF_SIZE, encoder);
 
@@ -40,6 +47,8 @@ pub fn csv_to_zst(input: &PathBuf, output: Option<&PathBuf>) -> Result<()> {
     println!("Creating .zst file...");
 
     let mut line_buf = Vec::new();
+    let mut record_buf = Vec::with_capacity(512);
+    let mut checksum = verify.then(Checksum::default);
 
     loop {
         line_buf.clear();
@@ -60,13 +69,24 @@ pub fn csv_to_zst(input: &PathBuf, output: Option<&PathBuf>) -> Result<()> {
         }
 
         let record = parse_csv_record_bytes(&line_buf)?;
-        write_binary_record(&mut writer, &record)?;
+        record_buf.clear();
+        write_binary_record(&mut record_buf, &record)?;
+        writer.write_all(&record_buf)?;
+        if let Some(sum) = checksum.as_mut() {
+            sum.add(&record_buf);
+        }
     }
 
     let encoder = writer
         .into_inner()
         .map_err(|_| anyhow::anyhow!("failed to flush buffered zstd encoder"))?;
-    encoder.finish()?;
+    let out_file = encoder.finish()?;
+    if let Some(sum) = checksum {
+        // Read back before the rename: a bad archive never replaces anything.
+        let (records, digest) = verify_zst(atomic.temp_path(), sum)?;
+        println!("Verified     : {records} records, sha256 {digest}");
+    }
+    atomic.commit(out_file)?;
 
     println!("Output       : {}", out_path.display());
     println!("Elapsed time : {:.3} sec.", start.elapsed().as_secs_f64());
@@ -3,44 +3,35 @@ use anyhow::Result;
 use dutopia::util::{push_i64, push_u32, push_u64};
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Read, Seek, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crate::compress::{READ_BUF_SIZE, WRITE_BUF_SIZE};
+use dutopia::util::progress::{Counter, CountingReader};
 
-pub fn zst_to_csv(input: &PathBuf, output: Option<&PathBuf>) -> Result<()> {
+use crate::compress::{READ_BUF_SIZE, WRITE_BUF_SIZE};
+use crate::output::create_output;
+use crate::select::{header, Column};
+
+pub fn zst_to_csv(
+    input: &Path,
+    output: Option<&PathBuf>,
+    force: bool,
+    columns: &[Column],
+    progress: Arc<Counter>,
+) -> Result<()> {
     let start = std::time::Instant::now();
-    let mut f = File::open(input)?;
-
-    let mut magic_buf = [0u8; 4];
-    f.read_exact(&mut magic_buf)?;
-    f.rewind()?;
-    let magic = u32::from_le_bytes(magic_buf);
-
-    if magic != 0xFD2FB528 {
-        eprintln!("Invalid format.");
-        std::process::exit(1);
-    }
-
-    let reader: Box<dyn Read> = Box::new(zstd::stream::read::Decoder::new(f)?);
-    let mut r = BufReader::with_capacity(READ_BUF_SIZE, reader);
+    let mut r = open_zst(input, progress)?;
 
     let out_path = output
         .cloned()
         .unwrap_or_else(|| input.with_extension("csv"));
 
-    if out_path.exists() {
-        anyhow::bail!(format!(
-            "Output file already exists: {}",
-            out_path.display()
-        ));
-    }
-
-    let out_file = File::create(&out_path)?;
+    let (atomic, out_file) = create_output(&out_path, force)?;
     let mut w = BufWriter::with_capacity(WRITE_BUF_SIZE, out_file);
 
     println!("Creating .csv file...");
 
-    w.write_all(b"INODE,ATIME,MTIME,UID,GID,MODE,SIZE,DISK,PATH\n")?;
+    w.write_all(&header(columns))?;
 
     let mut line = Vec::<u8>::with_capacity(256);
     let mut path_buf = Vec::<u8>::with_capacity(512);
@@ -65,38 +56,60 @@ pub fn zst_to_csv(input: &PathBuf, output: Option<&PathBuf>) -> Result<()> {
         let disk = read_u64_le_exact(&mut r)?;
 
         line.clear();
-
-        push_u64(&mut line, dev);
-        line.push(b'-');
-        push_u64(&mut line, ino);
-        line.push(b',');
-        push_i64(&mut line, atime);
-        line.push(b',');
-        push_i64(&mut line, mtime);
-        line.push(b',');
-        push_u32(&mut line, uid);
-        line.push(b',');
-        push_u32(&mut line, gid);
-        line.push(b',');
-        push_u32(&mut line, mode);
-        line.push(b',');
-        push_u64(&mut line, size);
-        line.push(b',');
-        push_u64(&mut line, disk);
-        line.push(b',');
-        csv_push_path(&mut line, &path_buf);
+        for (i, col) in columns.iter().enumerate() {
+            if i > 0 {
+                line.push(b',');
+            }
+            match col {
+                Column::Inode => {
+                    push_u64(&mut line, dev);
+                    line.push(b'-');
+                    push_u64(&mut line, ino);
+                }
+                Column::Atime => push_i64(&mut line, atime),
+                Column::Mtime => push_i64(&mut line, mtime),
+                Column::Uid => push_u32(&mut line, uid),
+                Column::Gid => push_u32(&mut line, gid),
+                Column::Mode => push_u32(&mut line, mode),
+                Column::Size => push_u64(&mut line, size),
+                Column::Disk => push_u64(&mut line, disk),
+                Column::Path => csv_push_path(&mut line, &path_buf),
+            }
+        }
         line.push(b'\n');
 
         w.write_all(&line)?;
     }
 
-    w.flush()?;
+    let out_file = w
+        .into_inner()
+        .map_err(|_| anyhow::anyhow!("failed to flush csv writer"))?;
+    atomic.commit(out_file)?;
     println!("Output       : {}", out_path.display());
     println!("Elapsed time : {:.3} sec.", start.elapsed().as_secs_f64());
 
     Ok(())
 }
 
+/// Buffered reader over the records of a .zst scan. Progress follows the
+/// compressed bytes: their total is the file size.
+pub fn open_zst(input: &Path, progress: Arc<Counter>) -> Result<BufReader<Box<dyn Read>>> {
+    let mut f = File::open(input)?;
+
+    let mut magic_buf = [0u8; 4];
+    f.read_exact(&mut magic_buf)?;
+    f.rewind()?;
+    let magic = u32::from_le_bytes(magic_buf);
+
+    if magic != 0xFD2FB528 {
+        anyhow::bail!("Invalid format: {} is not zstd-compressed", input.display());
+    }
+
+    let f = CountingReader::new(f, progress);
+    let reader: Box<dyn Read> = Box::new(zstd::stream::read::Decoder::new(f)?);
+    Ok(BufReader::with_capacity(READ_BUF_SIZE, reader))
+}
+
 #[cfg(unix)]
 fn csv_push_path(out: &mut Vec<u8>, path_bytes: &[u8]) {
     let needs_quoting = path_bytes
@@ -194,10 +207,8 @@ fn read_u32_le_opt<R: Read>(r: &mut R) -> Result<Option<u32>> {
     }
 }
 
-#[cfg(test)]
 use crate::record::BinaryRecord;
 
-#[cfg(test)]
 pub fn read_binary_record<R: Read>(r: &mut R) -> Result<Option<BinaryRecord>> {
     let path_len = match read_u32_le_opt(r)? {
         None => return Ok(None),
@@ -251,4 +262,25 @@ mod tests {
         let err = read_binary_record(&mut cursor).unwrap_err();
         assert!(format!("{}", err).contains("path_len"));
     }
+
+    #[test]
+    fn test_zst_to_csv_selected_columns() {
+        use crate::compress::csv_to_zst;
+        use crate::select::Column;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let csv = tmp.path().join("scan.csv");
+        std::fs::write(
+            &csv,
+            "INODE,ATIME,MTIME,UID,GID,MODE,SIZE,DISK,PATH\n1-2,10,20,1000,100,33188,5,8,\"/a,b\"\n",
+        )
+        .unwrap();
+        let zst = tmp.path().join("scan.zst");
+        csv_to_zst(&csv, Some(&zst), false, false, Arc::default()).unwrap();
+        let out = tmp.path().join("cols.csv");
+        let cols = [Column::Size, Column::Mtime, Column::Path];
+        zst_to_csv(&zst, Some(&out), false, &cols, Arc::default()).unwrap();
+        let text = std::fs::read_to_string(&out).unwrap();
+        assert_eq!(text, "SIZE,MTIME,PATH\n5,20,\"/a,b\"\n");
+    }
 }
@@ -2,15 +2,27 @@
 use anyhow::Result;
 use clap::{ColorChoice, Parser};
 use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
 
-use dutopia::util::print_about;
+use dutopia::util::exit::{self, ExitArgs, Outcome};
+use dutopia::util::logging::{init_cli_tracing, LogArgs};
+use dutopia::util::progress::{Counter, ProgressArgs, Reporter, Unit};
+use dutopia::util::{parse_bytes, print_about};
 
 mod compress;
 mod decompress;
+mod merge;
+mod output;
 mod record;
+mod select;
+mod sort;
+mod verify;
 
 use compress::csv_to_zst;
 use decompress::zst_to_csv;
+use merge::merge_zst;
+use sort::sort_zst;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -19,32 +31,177 @@ use decompress::zst_to_csv;
     about = "Convert between CSV and compressed binary (.zst) formats"
 )]
 struct Args {
-    /// Input file (.zst or .csv)
-    input: PathBuf,
+    /// Input file (.zst or .csv); several .zst files with --merge
+    #[arg(required = true, value_name = "INPUT")]
+    inputs: Vec<PathBuf>,
 
     /// Output file path (default: auto-determined based on operation)
     #[arg(short, long)]
     output: Option<PathBuf>,
+
+    /// Overwrite the output file if it already exists
+    #[arg(long)]
+    force: bool,
+
+    /// After compressing, read the archive back and check its record count
+    /// and checksum against the input before writing it in place
+    #[arg(long)]
+    verify: bool,
+
+    /// Decompress only these columns, in this order (e.g. size,mtime,path)
+    #[arg(long, value_name = "LIST", value_delimiter = ',', value_parser = select::parse_column)]
+    select: Vec<select::Column>,
+
+    /// Merge the .zst inputs into one .zst file (needs -o)
+    #[arg(long, requires = "output", conflicts_with_all = ["verify", "select"])]
+    merge: bool,
+
+    /// With --merge, drop records whose dev, ino and path repeat an earlier one
+    #[arg(long, requires = "merge")]
+    dedupe: bool,
+
+    /// Rewrite a .zst input sorted by path (default output: <stem>.sorted.zst)
+    #[arg(long, conflicts_with_all = ["merge", "verify", "select"])]
+    sort: bool,
+
+    /// With --sort, memory for records before a sorted run is spilled to disk
+    #[arg(long, value_name = "SIZE", default_value = "512M", value_parser = parse_bytes, requires = "sort")]
+    sort_mem: u64,
+
+    /// With --sort, directory for spilled runs (default: output directory)
+    #[arg(long, value_name = "DIR", requires = "sort")]
+    temp_dir: Option<PathBuf>,
+
+    /// Do not report progress
+    #[arg(short, long)]
+    quiet: bool,
+
+    #[command(flatten)]
+    progress: ProgressArgs,
+
+    #[command(flatten)]
+    log: LogArgs,
+
+    #[command(flatten)]
+    exit: ExitArgs,
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     print_about();
 
     let args = Args::parse();
+    // Every duzip failure is fatal: there are no rows it skips and goes on.
+    let result = run(&args).map(|()| Outcome::Ok);
+    exit::finish("duzip", &args.exit, result)
+}
 
-    let ext = args
-        .input
-        .extension()
-        .and_then(|s| s.to_str())
-        .map(|s| s.to_ascii_lowercase())
-        .unwrap_or_default();
+fn run(args: &Args) -> Result<()> {
+    init_cli_tracing("duzip", &args.log, "warn")?;
+    let start = std::time::Instant::now();
+
+    if args.merge {
+        return merge(args, start);
+    }
+    let [input] = args.inputs.as_slice() else {
+        anyhow::bail!("{} inputs given; use --merge to combine .zst files", args.inputs.len());
+    };
+    let ext = extension(input);
 
-    match ext.as_str() {
-        "csv" => csv_to_zst(&args.input, args.output.as_ref()),
-        "zst" => zst_to_csv(&args.input, args.output.as_ref()),
+    if args.verify && ext != "csv" {
+        anyhow::bail!("--verify applies to .csv input (compression) only");
+    }
+    if args.sort && ext != "zst" {
+        anyhow::bail!("--sort applies to .zst input only");
+    }
+    if !args.select.is_empty() && ext != "zst" {
+        anyhow::bail!("--select applies to .zst input (decompression) only");
+    }
+
+    let input_bytes = std::fs::metadata(input).map(|m| m.len()).unwrap_or(0);
+    let progress = Arc::new(Counter::with_total(input_bytes));
+    let sinks = args.progress.sinks(args.quiet)?;
+    let reporter = Reporter::start("duzip", Unit::Bytes, progress.clone(), sinks);
+
+    let result = match ext.as_str() {
+        "zst" if args.sort => sort_zst(
+            input,
+            args.output.as_ref(),
+            args.force,
+            args.sort_mem,
+            args.temp_dir.as_deref(),
+            progress,
+        )
+        .map(|_| ()),
+        "csv" => csv_to_zst(
+            input,
+            args.output.as_ref(),
+            args.force,
+            args.verify,
+            progress,
+        ),
+        "zst" => zst_to_csv(
+            input,
+            args.output.as_ref(),
+            args.force,
+            if args.select.is_empty() { &select::ALL } else { &args.select },
+            progress,
+        ),
         other => anyhow::bail!(
             "Unsupported input extension: '{}' (expected .csv, .bin, or .zst)",
             other
         ),
+    };
+    reporter.finish();
+    match &result {
+        Ok(()) => tracing::info!(
+            input = %input.display(),
+            elapsed_secs = start.elapsed().as_secs_f64(),
+            "conversion finished"
+        ),
+        Err(e) => tracing::error!(
+            input = %input.display(),
+            error = format!("{e:#}"),
+            "conversion failed"
+        ),
+    }
+    result
+}
+
+fn extension(path: &std::path::Path) -> String {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .map(|s| s.to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+fn merge(args: &Args, start: std::time::Instant) -> Result<()> {
+    if let Some(other) = args.inputs.iter().find(|i| extension(i) != "zst") {
+        anyhow::bail!("--merge takes .zst inputs only, got {}", other.display());
+    }
+    let output = args.output.as_ref().expect("clap requires -o with --merge");
+    let input_bytes = args
+        .inputs
+        .iter()
+        .map(|i| std::fs::metadata(i).map(|m| m.len()).unwrap_or(0))
+        .sum();
+    let progress = Arc::new(Counter::with_total(input_bytes));
+    let sinks = args.progress.sinks(args.quiet)?;
+    let reporter = Reporter::start("duzip", Unit::Bytes, progress.clone(), sinks);
+    let result = merge_zst(&args.inputs, output, args.force, args.dedupe, progress);
+    reporter.finish();
+    match &result {
+        Ok(stats) => tracing::info!(
+            inputs = args.inputs.len(),
+            records = stats.records,
+            duplicates = stats.duplicates,
+            elapsed_secs = start.elapsed().as_secs_f64(),
+            "merge finished"
+        ),
+        Err(e) => tracing::error!(
+            inputs = args.inputs.len(),
+            error = format!("{e:#}"),
+            "merge failed"
+        ),
     }
+    result.map(|_| ())
 }
@@ -0,0 +1,126 @@
+// rs/src/bin/duzip/merge.rs
+//
+// `--merge`: several .zst scans (per host, per partition) into one, record
+// by record, without expanding them to CSV. Records keep their input order,
+// inputs one after the other. With `--dedupe`, a record whose dev, ino and
+// path all match an earlier one is dropped, so overlapping scans of the same
+// tree do not count it twice; hard links (same dev-ino, other path) stay.
+// The set of seen records holds a 64-bit hash of each path, about 24 bytes
+// per record.
+use anyhow::{Context, Result};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use dutopia::util::progress::Counter;
+
+use crate::compress::{write_binary_record, WRITE_BUF_SIZE};
+use crate::decompress::{open_zst, read_binary_record};
+use crate::output::create_output;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct MergeStats {
+    pub records: u64,
+    pub duplicates: u64,
+}
+
+pub fn merge_zst(
+    inputs: &[PathBuf],
+    output: &Path,
+    force: bool,
+    dedupe: bool,
+    progress: Arc<Counter>,
+) -> Result<MergeStats> {
+    let start = std::time::Instant::now();
+    if inputs.iter().any(|i| i == output) {
+        anyhow::bail!("--merge output {} is also an input", output.display());
+    }
+    let (atomic, out_file) = create_output(output, force)?;
+    let encoder = zstd::stream::write::Encoder::new(out_file, 1)?;
+    let mut writer = BufWriter::with_capacity(WRITE_BUF_SIZE, encoder);
+
+    println!("Merging {} .zst files...", inputs.len());
+
+    let mut seen: HashSet<(u64, u64, u64)> = HashSet::new();
+    let mut stats = MergeStats::default();
+    let mut record_buf = Vec::with_capacity(512);
+    for input in inputs {
+        let mut r = open_zst(input, progress.clone()).with_context(|| format!("reading {}", input.display()))?;
+        let mut records = 0u64;
+        while let Some(rec) = read_binary_record(&mut r).with_context(|| format!("reading {}", input.display()))? {
+            if dedupe {
+                let mut h = DefaultHasher::new();
+                rec.path.hash(&mut h);
+                if !seen.insert((rec.dev, rec.ino, h.finish())) {
+                    stats.duplicates += 1;
+                    continue;
+                }
+            }
+            record_buf.clear();
+            write_binary_record(&mut record_buf, &rec)?;
+            writer.write_all(&record_buf)?;
+            records += 1;
+        }
+        println!("Input        : {} ({records} records)", input.display());
+        stats.records += records;
+    }
+
+    let encoder = writer
+        .into_inner()
+        .map_err(|_| anyhow::anyhow!("failed to flush buffered zstd encoder"))?;
+    let out_file = encoder.finish()?;
+    atomic.commit(out_file)?;
+
+    if dedupe {
+        println!("Duplicates   : {} dropped", stats.duplicates);
+    }
+    println!("Records      : {}", stats.records);
+    println!("Output       : {}", output.display());
+    println!("Elapsed time : {:.3} sec.", start.elapsed().as_secs_f64());
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::csv_to_zst;
+    use crate::decompress::zst_to_csv;
+    use crate::select::ALL;
+
+    const HEADER: &str = "INODE,ATIME,MTIME,UID,GID,MODE,SIZE,DISK,PATH\n";
+
+    fn zst(dir: &Path, name: &str, rows: &str) -> PathBuf {
+        let csv = dir.join(format!("{name}.csv"));
+        std::fs::write(&csv, format!("{HEADER}{rows}")).unwrap();
+        let out = dir.join(format!("{name}.zst"));
+        csv_to_zst(&csv, Some(&out), false, false, Arc::default()).unwrap();
+        out
+    }
+
+    #[test]
+    fn merges_in_order_and_drops_repeated_records() {
+        let tmp = tempfile::tempdir().unwrap();
+        let a = zst(tmp.path(), "a", "1-2,1,1,0,0,33188,5,8,/x\n1-3,1,1,0,0,33188,6,8,\"/y,z\"\n");
+        let b = zst(tmp.path(), "b", "1-3,1,1,0,0,33188,6,8,\"/y,z\"\n1-3,1,1,0,0,33188,6,8,/link\n");
+        let merged = tmp.path().join("merged.zst");
+
+        let stats = merge_zst(&[a.clone(), b.clone()], &merged, false, true, Arc::default()).unwrap();
+        assert_eq!(stats, MergeStats { records: 3, duplicates: 1 });
+        let out = tmp.path().join("merged.csv");
+        zst_to_csv(&merged, Some(&out), false, &ALL, Arc::default()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            format!("{HEADER}1-2,1,1,0,0,33188,5,8,/x\n1-3,1,1,0,0,33188,6,8,\"/y,z\"\n1-3,1,1,0,0,33188,6,8,/link\n")
+        );
+
+        let all = merge_zst(&[a.clone(), b], &merged, true, false, Arc::default()).unwrap();
+        assert_eq!(all, MergeStats { records: 4, duplicates: 0 });
+        assert!(merge_zst(std::slice::from_ref(&a), &a, true, false, Arc::default()).is_err());
+        let csv = tmp.path().join("a.csv");
+        let err = merge_zst(&[csv], &merged, true, false, Arc::default()).unwrap_err();
+        assert!(format!("{err:#}").contains("not zstd-compressed"), "{err:#}");
+    }
+}
@@ -0,0 +1,38 @@
+// rs/src/bin/duzip/output.rs
+use anyhow::Result;
+use dutopia::util::AtomicFile;
+use std::fs::File;
+use std::path::Path;
+
+/// Start writing `dest` through `AtomicFile`, refusing an existing file
+/// unless `force` is set. Dropping the guard uncommitted (early return,
+/// error, panic) removes the temp file.
+pub fn create_output(dest: &Path, force: bool) -> Result<(AtomicFile, File)> {
+    if dest.exists() && !force {
+        anyhow::bail!(
+            "Output file already exists: {} (use --force to overwrite)",
+            dest.display()
+        );
+    }
+    Ok(AtomicFile::create(dest)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+
+    #[test]
+    fn existing_output_requires_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("out.csv");
+        fs::write(&dest, "old").unwrap();
+        assert!(create_output(&dest, false).is_err());
+
+        let (out, mut f) = create_output(&dest, true).unwrap();
+        f.write_all(b"new").unwrap();
+        out.commit(f).unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "new");
+    }
+}
@@ -0,0 +1,225 @@
+// rs/src/bin/duzip/sort.rs
+//
+// `--sort`: rewrite a .zst scan ordered by path (raw bytes), for archives
+// that compress better (neighbouring paths share prefixes) and can be cut by
+// prefix without reading the whole file. It is an external sort: records are
+// collected until about `--sort-mem` bytes, sorted and spilled as a zstd run
+// file in a scratch directory, and the runs are then k-way merged into the
+// output. A scan that fits in memory is never spilled. Records with equal
+// paths keep their input order. The scratch directory (under `--temp-dir`,
+// else beside the output) is removed when the sort ends, even on error.
+use anyhow::{Context, Result};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use dutopia::util::progress::Counter;
+
+use crate::compress::{write_binary_record, READ_BUF_SIZE, WRITE_BUF_SIZE};
+use crate::decompress::{open_zst, read_binary_record};
+use crate::output::create_output;
+use crate::record::BinaryRecord;
+
+/// Rough heap cost of a record besides its path bytes.
+const RECORD_OVERHEAD: usize = std::mem::size_of::<BinaryRecord>() + 16;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SortStats {
+    pub records: u64,
+    pub runs: usize,
+}
+
+/// Next record of one run, ordered so the heap pops the smallest path and,
+/// for equal paths, the earliest run.
+struct Head {
+    rec: BinaryRecord,
+    run: usize,
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.rec.path.cmp(&other.rec.path).then(self.run.cmp(&other.run))
+    }
+}
+
+fn write_records<W: Write>(w: &mut W, recs: &[BinaryRecord], buf: &mut Vec<u8>) -> Result<()> {
+    for rec in recs {
+        buf.clear();
+        write_binary_record(buf, rec)?;
+        w.write_all(buf)?;
+    }
+    Ok(())
+}
+
+fn spill(dir: &Path, n: usize, recs: &mut Vec<BinaryRecord>, buf: &mut Vec<u8>) -> Result<PathBuf> {
+    recs.sort_by(|a, b| a.path.cmp(&b.path));
+    let path = dir.join(format!("run-{n}.zst"));
+    let file = File::create(&path).with_context(|| format!("create {}", path.display()))?;
+    let mut w = BufWriter::with_capacity(WRITE_BUF_SIZE, zstd::stream::write::Encoder::new(file, 1)?);
+    write_records(&mut w, recs, buf)?;
+    w.into_inner()
+        .map_err(|_| anyhow::anyhow!("failed to flush sort run {}", path.display()))?
+        .finish()?;
+    recs.clear();
+    Ok(path)
+}
+
+pub fn sort_zst(
+    input: &Path,
+    output: Option<&PathBuf>,
+    force: bool,
+    mem: u64,
+    temp_dir: Option<&Path>,
+    progress: Arc<Counter>,
+) -> Result<SortStats> {
+    let start = std::time::Instant::now();
+    let out_path = output
+        .cloned()
+        .unwrap_or_else(|| input.with_extension("sorted.zst"));
+    if out_path == input {
+        anyhow::bail!("--sort output {} is the input", out_path.display());
+    }
+    let scratch_in = match temp_dir {
+        Some(d) => d.to_path_buf(),
+        None => match out_path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => PathBuf::from("."),
+        },
+    };
+    let (atomic, out_file) = create_output(&out_path, force)?;
+    let scratch = tempfile::Builder::new()
+        .prefix(".duzip-sort-")
+        .tempdir_in(&scratch_in)
+        .with_context(|| format!("creating a scratch directory in {}", scratch_in.display()))?;
+
+    println!("Sorting .zst file by path...");
+
+    let mem = usize::try_from(mem).unwrap_or(usize::MAX).max(1);
+    let mut r = open_zst(input, progress)?;
+    let mut recs: Vec<BinaryRecord> = Vec::new();
+    let mut held = 0usize;
+    let mut runs: Vec<PathBuf> = Vec::new();
+    let mut buf = Vec::with_capacity(512);
+    let mut stats = SortStats::default();
+    while let Some(rec) = read_binary_record(&mut r)? {
+        held += rec.path.len() + RECORD_OVERHEAD;
+        recs.push(rec);
+        stats.records += 1;
+        if held >= mem {
+            runs.push(spill(scratch.path(), runs.len(), &mut recs, &mut buf)?);
+            held = 0;
+        }
+    }
+
+    let encoder = zstd::stream::write::Encoder::new(out_file, 1)?;
+    let mut w = BufWriter::with_capacity(WRITE_BUF_SIZE, encoder);
+    if runs.is_empty() {
+        recs.sort_by(|a, b| a.path.cmp(&b.path));
+        write_records(&mut w, &recs, &mut buf)?;
+    } else {
+        if !recs.is_empty() {
+            runs.push(spill(scratch.path(), runs.len(), &mut recs, &mut buf)?);
+        }
+        drop(recs);
+        let mut readers = Vec::with_capacity(runs.len());
+        for path in &runs {
+            let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+            let dec = zstd::stream::read::Decoder::new(file)?;
+            readers.push(BufReader::with_capacity(READ_BUF_SIZE / 4, dec));
+        }
+        let mut heap = BinaryHeap::with_capacity(readers.len());
+        for (run, rd) in readers.iter_mut().enumerate() {
+            if let Some(rec) = read_binary_record(rd)? {
+                heap.push(Reverse(Head { rec, run }));
+            }
+        }
+        while let Some(Reverse(Head { rec, run })) = heap.pop() {
+            write_records(&mut w, std::slice::from_ref(&rec), &mut buf)?;
+            if let Some(next) = read_binary_record(&mut readers[run])? {
+                heap.push(Reverse(Head { rec: next, run }));
+            }
+        }
+    }
+    stats.runs = runs.len();
+
+    let encoder = w
+        .into_inner()
+        .map_err(|_| anyhow::anyhow!("failed to flush buffered zstd encoder"))?;
+    let out_file = encoder.finish()?;
+    atomic.commit(out_file)?;
+
+    println!("Records      : {}", stats.records);
+    if stats.runs > 0 {
+        println!("Sort runs    : {}", stats.runs);
+    }
+    println!("Output       : {}", out_path.display());
+    println!("Elapsed time : {:.3} sec.", start.elapsed().as_secs_f64());
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::csv_to_zst;
+    use crate::decompress::zst_to_csv;
+    use crate::select::ALL;
+
+    const HEADER: &str = "INODE,ATIME,MTIME,UID,GID,MODE,SIZE,DISK,PATH\n";
+
+    #[test]
+    fn sorts_in_memory_and_through_spilled_runs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let csv = tmp.path().join("scan.csv");
+        let mut body = String::from(HEADER);
+        let mut expected: Vec<String> = Vec::new();
+        for i in 0..50u32 {
+            // Scrambled order, and one path twice (kept in input order).
+            let n = (i * 37) % 50;
+            let row = format!("1-{i},1,1,0,0,33188,{n},8,/d/f{n:03}\n");
+            body.push_str(&row);
+            expected.push(row);
+        }
+        let dup = "1-99,1,1,0,0,33188,7,8,/d/f007\n";
+        body.push_str(dup);
+        expected.push(dup.to_string());
+        expected.sort_by_key(|r| r.rsplit(',').next().unwrap().to_string());
+        std::fs::write(&csv, body).unwrap();
+        let zst = tmp.path().join("scan.zst");
+        csv_to_zst(&csv, Some(&zst), false, false, Arc::default()).unwrap();
+
+        for (mem, runs) in [(1 << 20, 0), (400, 13)] {
+            let sorted = tmp.path().join(format!("sorted-{mem}.zst"));
+            let stats = sort_zst(&zst, Some(&sorted), false, mem, None, Arc::default()).unwrap();
+            assert_eq!(stats, SortStats { records: 51, runs });
+            let out = tmp.path().join(format!("sorted-{mem}.csv"));
+            zst_to_csv(&sorted, Some(&out), false, &ALL, Arc::default()).unwrap();
+            assert_eq!(std::fs::read_to_string(&out).unwrap(), format!("{HEADER}{}", expected.concat()));
+        }
+        // Default name, and the scratch directory is gone.
+        sort_zst(&zst, None, false, 400, None, Arc::default()).unwrap();
+        assert!(tmp.path().join("scan.sorted.zst").exists());
+        let left: Vec<_> = std::fs::read_dir(tmp.path())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with('.'))
+            .collect();
+        assert!(left.is_empty(), "{left:?}");
+    }
+}
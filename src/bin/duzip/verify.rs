@@ -0,0 +1,118 @@
+// rs/src/bin/duzip/verify.rs
+//
+// `--verify`: while compressing, every encoded record is counted and fed to a
+// SHA-256; afterwards the written archive is decompressed from disk, walked
+// record by record and hashed the same way. Only when both counts and both
+// digests match is the archive renamed into place, so a retention script
+// may delete the CSV as soon as duzip exits 0.
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+use std::path::Path;
+
+use crate::compress::READ_BUF_SIZE;
+
+/// Fixed part of a binary record after the path.
+const FIXED: usize = 60;
+
+#[derive(Default)]
+pub struct Checksum {
+    pub records: u64,
+    hasher: Sha256,
+}
+
+impl Checksum {
+    /// One encoded record: path length, path and the fixed fields.
+    pub fn add(&mut self, record: &[u8]) {
+        self.records += 1;
+        self.hasher.update(record);
+    }
+
+    pub fn hex(self) -> String {
+        self.hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+}
+
+/// Checksum of the records in a duzip .zst archive.
+pub fn checksum_zst(path: &Path) -> Result<Checksum> {
+    let f = File::open(path).with_context(|| format!("reopening {}", path.display()))?;
+    let mut r = BufReader::with_capacity(READ_BUF_SIZE, zstd::stream::read::Decoder::new(f)?);
+    let mut sum = Checksum::default();
+    let mut rec = Vec::with_capacity(512);
+    loop {
+        let mut len = [0u8; 4];
+        match r.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e).context("reading archive"),
+        }
+        let body = u32::from_le_bytes(len) as usize + FIXED;
+        rec.clear();
+        rec.extend_from_slice(&len);
+        rec.resize(4 + body, 0);
+        r.read_exact(&mut rec[4..])
+            .with_context(|| format!("truncated record after {} records", sum.records))?;
+        sum.add(&rec);
+    }
+    Ok(sum)
+}
+
+/// Compare the archive at `path` with what was written to it.
+pub fn verify_zst(path: &Path, written: Checksum) -> Result<(u64, String)> {
+    let read = checksum_zst(path)?;
+    if read.records != written.records {
+        bail!(
+            "verification failed: wrote {} records, archive holds {}",
+            written.records,
+            read.records
+        );
+    }
+    let (records, want, got) = (written.records, written.hex(), read.hex());
+    if want != got {
+        bail!("verification failed: checksum {got} does not match written {want}");
+    }
+    Ok((records, got))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(path: &[u8]) -> Vec<u8> {
+        let mut r = (path.len() as u32).to_le_bytes().to_vec();
+        r.extend_from_slice(path);
+        r.extend_from_slice(&[7u8; FIXED]);
+        r
+    }
+
+    #[test]
+    fn verify_accepts_a_faithful_archive_and_rejects_others() {
+        let tmp = tempfile::tempdir().unwrap();
+        let zst = tmp.path().join("a.zst");
+        let recs = [record(b"/a"), record(b"/a/b")];
+        std::fs::write(&zst, zstd::encode_all(recs.concat().as_slice(), 1).unwrap()).unwrap();
+
+        let written = |recs: &[Vec<u8>]| {
+            let mut sum = Checksum::default();
+            recs.iter().for_each(|r| sum.add(r));
+            sum
+        };
+        let (n, hex) = verify_zst(&zst, written(&recs)).unwrap();
+        assert_eq!((n, hex.len()), (2, 64));
+
+        let err = verify_zst(&zst, written(&recs[..1])).unwrap_err();
+        assert!(err.to_string().contains("wrote 1 records"));
+        let other = [record(b"/a"), record(b"/a/c")];
+        assert!(verify_zst(&zst, written(&other)).is_err());
+
+        let mut cut = recs.concat();
+        cut.pop();
+        std::fs::write(&zst, zstd::encode_all(cut.as_slice(), 1).unwrap()).unwrap();
+        assert!(verify_zst(&zst, written(&recs)).is_err());
+    }
+}
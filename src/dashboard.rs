@@ -0,0 +1,336 @@
+// rs/src/dashboard.rs
+//
+// Whole-dataset numbers for landing pages and Grafana JSON datasources,
+// served by duapi at `/api/summary` in a single call. Totals come from the
+// platform-root rows (`/`, `C:\`, `\\srv`), which dusum has already rolled
+// up; "top folders" are the folders one level below the platform roots
+// (`/home`, `/proj`, `C:\Users`), the level a storage overview starts at.
+// Trees `--acl-file` hides from the caller are subtracted from the rollups
+// that include them before anything is summed or ranked.
+use crate::analytic::{FolderTotal, UserTotal};
+use crate::db::{read_metadata, DbPool};
+use crate::tiers::TierTotal;
+use anyhow::{Context, Result};
+use rusqlite::{params_from_iter, ToSql};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Totals of one age bucket (0 recent, 1 not too old, 2 old).
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AgeTotal {
+    pub age: u8,
+    pub count: u64,
+    pub size: u64,
+    pub disk: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Dashboard {
+    /// When dudb built the DB (epoch seconds)
+    pub built_at: Option<i64>,
+    /// dusum CSV the DB was loaded from; left out for non-admins
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    pub count: u64,
+    pub size: u64,
+    pub disk: u64,
+    pub linked: u64,
+    /// One entry per age bucket present in the data
+    pub ages: Vec<AgeTotal>,
+    /// Users by disk usage, largest first
+    pub top_users: Vec<UserTotal>,
+    /// Folders below the platform roots by disk usage, largest first
+    pub top_folders: Vec<FolderTotal>,
+    /// Usage per storage tier, filled by duapi from its `--tiers-file`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiers: Vec<TierTotal>,
+}
+
+const PLATFORM_ROOTS: &str = "(SELECT id FROM paths WHERE full_path = '')";
+
+/// ` AND u.name IN (?, ...)` for a non-empty `users`, binding the names.
+fn user_filter(users: &[String], params: &mut Vec<Box<dyn ToSql>>) -> String {
+    if users.is_empty() {
+        return String::new();
+    }
+    params.extend(users.iter().map(|u| Box::new(u.clone()) as Box<dyn ToSql>));
+    format!(" AND u.name IN ({})", vec!["?"; users.len()].join(","))
+}
+
+/// Numbers of one stats row, signed so that hidden trees can be taken back
+/// out of the rollups that include them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Usage {
+    count: i64,
+    size: i64,
+    disk: i64,
+    linked: i64,
+}
+
+impl Usage {
+    fn add(&mut self, u: &Usage, sign: i64) {
+        self.count += sign * u.count;
+        self.size += sign * u.size;
+        self.disk += sign * u.disk;
+        self.linked += sign * u.linked;
+    }
+
+    fn row(r: &rusqlite::Row, first: usize) -> rusqlite::Result<Usage> {
+        Ok(Usage {
+            count: r.get(first)?,
+            size: r.get(first + 1)?,
+            disk: r.get(first + 2)?,
+            linked: r.get(first + 3)?,
+        })
+    }
+}
+
+/// A (folder, user, age) stats row.
+struct StatsRow {
+    path: String,
+    user: String,
+    age: u8,
+    usage: Usage,
+}
+
+/// Stats rows matching `filter` (over aliases `p`, `s` and `u`) and `users`,
+/// binding `first` ahead of the user names.
+fn stats_rows(
+    conn: &rusqlite::Connection,
+    filter: &str,
+    first: Option<i64>,
+    users: &[String],
+) -> Result<Vec<StatsRow>> {
+    let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+    if let Some(v) = first {
+        params.push(Box::new(v));
+    }
+    let users_sql = user_filter(users, &mut params);
+    let sql = format!(
+        "SELECT p.full_path, u.name, s.age,
+                s.file_count, s.file_size, s.disk_bytes, s.linked_size
+         FROM   stats s
+         JOIN   paths p ON p.id = s.path_id
+         JOIN   users u ON u.id = s.user_id
+         WHERE  {filter}{users_sql}"
+    );
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let refs: Vec<&dyn ToSql> = params.iter().map(|b| b.as_ref()).collect();
+    let rows = stmt
+        .query_map(params_from_iter(refs), |r| {
+            Ok(StatsRow {
+                path: r.get(0)?,
+                user: r.get(1)?,
+                age: r.get::<_, i64>(2)? as u8,
+                usage: Usage::row(r, 3)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// The topmost folders `hidden` covers inside the visible platform roots.
+/// Only folders leading down to a hidden prefix are walked.
+fn hidden_tops(
+    conn: &rusqlite::Connection,
+    hidden: &[String],
+    case_insensitive: bool,
+) -> Result<Vec<i64>> {
+    let is_hidden = |p: &str| crate::acl::is_hidden(p, hidden, case_insensitive);
+    let leads_to_hidden = |p: &str| {
+        let p = [p.to_string()];
+        hidden.iter().any(|h| crate::acl::is_hidden(h, &p, case_insensitive))
+    };
+    let sql = format!("SELECT id, full_path FROM paths WHERE parent_id = {PLATFORM_ROOTS}");
+    let mut roots = conn.prepare(&sql)?;
+    let mut queue: Vec<(i64, String)> = roots
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    queue.retain(|(_, p)| !is_hidden(p));
+    let mut children = conn.prepare("SELECT id, full_path FROM paths WHERE parent_id = ?1")?;
+    let mut tops = Vec::new();
+    while let Some((id, _)) = queue.pop() {
+        let rows: Vec<(i64, String)> = children
+            .query_map([id], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        for (child, path) in rows {
+            if is_hidden(&path) {
+                tops.push(child);
+            } else if leads_to_hidden(&path) {
+                queue.push((child, path));
+            }
+        }
+    }
+    Ok(tops)
+}
+
+/// Dataset summary, restricted to `users` when non-empty. `limit` caps the
+/// top-users and top-folders lists. Trees under the `hidden` prefixes (see
+/// `acl::is_hidden`) are taken out of every number before the lists are
+/// ranked, so they leave no trace in totals, users or folders.
+pub fn dashboard(
+    pool: &DbPool,
+    users: &[String],
+    limit: u32,
+    hidden: &[String],
+    case_insensitive: bool,
+) -> Result<Dashboard> {
+    let mut out = Dashboard {
+        built_at: read_metadata(pool, "built_at")?.and_then(|v| v.parse().ok()),
+        source: read_metadata(pool, "source_csv")?,
+        ..Dashboard::default()
+    };
+    let conn = pool.get().context("acquiring connection")?;
+    let is_hidden = |p: &str| crate::acl::is_hidden(p, hidden, case_insensitive);
+
+    // The platform roots hold the rollups of everything; hidden roots are
+    // left out and the hidden trees inside the others subtracted.
+    let roots: Vec<StatsRow> =
+        stats_rows(&conn, &format!("p.parent_id = {PLATFORM_ROOTS}"), None, users)?
+            .into_iter()
+            .filter(|r| !is_hidden(&r.path))
+            .collect();
+    let mut hidden_rows = Vec::new();
+    if !hidden.is_empty() {
+        for id in hidden_tops(&conn, hidden, case_insensitive)? {
+            hidden_rows.extend(stats_rows(&conn, "s.path_id = ?", Some(id), users)?);
+        }
+    }
+
+    let mut ages: BTreeMap<u8, Usage> = BTreeMap::new();
+    let mut by_user: HashMap<String, Usage> = HashMap::new();
+    let signed = roots.iter().map(|r| (r, 1)).chain(hidden_rows.iter().map(|r| (r, -1)));
+    for (r, sign) in signed {
+        ages.entry(r.age).or_default().add(&r.usage, sign);
+        by_user.entry(r.user.clone()).or_default().add(&r.usage, sign);
+    }
+    let unsigned = |v: i64| v.max(0) as u64;
+    for (age, u) in ages.into_iter().filter(|(_, u)| *u != Usage::default()) {
+        out.count += unsigned(u.count);
+        out.size += unsigned(u.size);
+        out.disk += unsigned(u.disk);
+        out.linked += unsigned(u.linked);
+        out.ages.push(AgeTotal {
+            age,
+            count: unsigned(u.count),
+            size: unsigned(u.size),
+            disk: unsigned(u.disk),
+        });
+    }
+
+    out.top_users = by_user
+        .into_iter()
+        .filter(|(_, u)| *u != Usage::default())
+        .map(|(user, u)| UserTotal {
+            user,
+            disk: unsigned(u.disk),
+            size: unsigned(u.size),
+            count: unsigned(u.count),
+        })
+        .collect();
+    out.top_users.sort_by(|a, b| b.disk.cmp(&a.disk).then_with(|| a.user.cmp(&b.user)));
+    out.top_users.truncate(limit as usize);
+
+    // Folders one level down, less the hidden trees below them.
+    let mut folders: HashMap<String, Usage> = HashMap::new();
+    let level = format!("p.parent_id IN (SELECT id FROM paths WHERE parent_id = {PLATFORM_ROOTS})");
+    for r in stats_rows(&conn, &level, None, users)? {
+        if !is_hidden(&r.path) {
+            folders.entry(r.path).or_default().add(&r.usage, 1);
+        }
+    }
+    for r in &hidden_rows {
+        let below = folders.iter_mut().find(|(f, _)| {
+            crate::acl::is_hidden(&r.path, std::slice::from_ref(*f), case_insensitive)
+        });
+        if let Some((_, u)) = below {
+            u.add(&r.usage, -1);
+        }
+    }
+    out.top_folders = folders
+        .into_iter()
+        .filter(|(_, u)| *u != Usage::default())
+        .map(|(path, u)| FolderTotal {
+            path,
+            disk: unsigned(u.disk),
+            size: unsigned(u.size),
+            count: unsigned(u.count),
+        })
+        .collect();
+    out.top_folders.sort_by(|a, b| b.disk.cmp(&a.disk).then_with(|| a.path.cmp(&b.path)));
+    out.top_folders.truncate(limit as usize);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{open_pool, test_support::build_test_db};
+
+    #[test]
+    fn dashboard_totals_ages_and_tops() {
+        let t = build_test_db();
+        let p = open_pool(&t.path).unwrap();
+        let d = dashboard(&p, &[], 10, &[], false).unwrap();
+        // Platform root `/`: alice age 0 (2 files, disk 100), bob age 1 (1, 50).
+        assert_eq!((d.count, d.size, d.disk), (3, 250, 150));
+        assert_eq!(d.ages.len(), 2);
+        assert_eq!(d.ages[1], AgeTotal { age: 1, count: 1, size: 50, disk: 50 });
+        let users: Vec<_> = d.top_users.iter().map(|u| u.user.as_str()).collect();
+        assert_eq!(users, ["alice", "bob"]);
+        assert_eq!(d.top_folders.len(), 1);
+        assert_eq!(d.top_folders[0].path, "/docs");
+    }
+
+    #[test]
+    fn dashboard_user_filter_and_limit() {
+        let t = build_test_db();
+        let p = open_pool(&t.path).unwrap();
+        let d = dashboard(&p, &["bob".into()], 10, &[], false).unwrap();
+        assert_eq!((d.count, d.disk), (1, 50));
+        assert_eq!(d.top_users.len(), 1);
+        assert!(d.top_folders.is_empty());
+
+        let d = dashboard(&p, &[], 1, &[], false).unwrap();
+        assert_eq!(d.top_users.len(), 1);
+        assert_eq!(d.top_users[0].user, "alice");
+    }
+
+    #[test]
+    fn hidden_trees_are_left_out_before_ranking() {
+        let t = build_test_db();
+        // /hr, the largest folder, holds alice's and all of carol's files;
+        // the root rollup includes them.
+        let conn = rusqlite::Connection::open(&t.path).unwrap();
+        conn.execute_batch(
+            "INSERT INTO users(name) VALUES('carol');
+             INSERT INTO paths(full_path, parent_id)
+               SELECT '/hr', id FROM paths WHERE full_path = '/';
+             INSERT INTO stats SELECT p.id, u.id, 0, 5, 5000, 4000, 0, 1, 1
+               FROM paths p, users u WHERE p.full_path = '/hr' AND u.name = 'alice';
+             INSERT INTO stats SELECT p.id, u.id, 2, 1, 90000, 90000, 0, 1, 1
+               FROM paths p, users u WHERE p.full_path IN ('/', '/hr') AND u.name = 'carol';
+             UPDATE stats SET file_count = file_count + 5, file_size = file_size + 5000,
+                              disk_bytes = disk_bytes + 4000
+               WHERE age = 0 AND path_id = (SELECT id FROM paths WHERE full_path = '/');",
+        )
+        .unwrap();
+        drop(conn);
+        let p = open_pool(&t.path).unwrap();
+
+        let all = dashboard(&p, &[], 1, &[], false).unwrap();
+        assert_eq!(all.top_folders[0].path, "/hr");
+        assert_eq!(all.top_users[0].user, "carol");
+
+        let d = dashboard(&p, &[], 1, &["/HR".into()], true).unwrap();
+        assert_eq!((d.count, d.size, d.disk), (3, 250, 150));
+        assert_eq!(d.ages.iter().map(|a| a.age).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(d.top_users.len(), 1);
+        assert_eq!(d.top_users[0].user, "alice");
+        assert_eq!(d.top_folders.len(), 1);
+        assert_eq!(d.top_folders[0].path, "/docs");
+
+        let d = dashboard(&p, &[], 10, &["/".into()], false).unwrap();
+        assert_eq!((d.count, d.ages.len(), d.top_users.len(), d.top_folders.len()), (0, 0, 0, 0));
+    }
+}
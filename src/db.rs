@@ -6,11 +6,14 @@ use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 
+use crate::owners::OwnerContact;
+use crate::userinfo::UserInfo;
+
 pub type DbPool = r2d2::Pool<SqliteConnectionManager>;
 
 pub const SUPPORTED_SCHEMA_VERSION: &str = "2";
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Age {
     pub count: u64,
     pub size: u64,
@@ -18,12 +21,68 @@ pub struct Age {
     pub linked: u64,
     pub atime: i64,
     pub mtime: i64,
+    /// Earliest atime/mtime under the row, 0 when unknown (DBs loaded from
+    /// CSVs without `oldest_accessed`/`oldest_modified`).
+    #[serde(default)]
+    pub oldest_atime: i64,
+    #[serde(default)]
+    pub oldest_mtime: i64,
+}
+
+impl Age {
+    /// Adds `a` into `self`: counts and sizes summed, latest times maxed,
+    /// oldest times the earliest known.
+    pub fn add(&mut self, a: &Age) {
+        self.count += a.count;
+        self.size += a.size;
+        self.disk += a.disk;
+        self.linked += a.linked;
+        self.atime = self.atime.max(a.atime);
+        self.mtime = self.mtime.max(a.mtime);
+        self.oldest_atime = earliest(self.oldest_atime, a.oldest_atime);
+        self.oldest_mtime = earliest(self.oldest_mtime, a.oldest_mtime);
+    }
+}
+
+/// The earlier of two timestamps where 0 means unknown.
+pub fn earliest(a: i64, b: i64) -> i64 {
+    match (a, b) {
+        (0, t) | (t, 0) => t,
+        _ => a.min(b),
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FolderOut {
     pub path: String,
     pub users: HashMap<String, HashMap<String, Age>>,
+    /// Totals per device id, only for `by_device` queries on DBs loaded from
+    /// `dusum --by-device` output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub devices: Option<BTreeMap<String, Age>>,
+    /// Display names of the users above, filled by duapi from its
+    /// `--users-file`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_info: Option<BTreeMap<String, UserInfo>>,
+    /// Contact for the folder, filled by duapi from its `--owners-file`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<OwnerContact>,
+    /// Storage tier of the folder, filled by duapi from its `--tiers-file`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tier: Option<String>,
+}
+
+/// Knobs for `list_children_with`.
+#[derive(Debug, Default, Clone)]
+pub struct ListOptions {
+    /// Match `dir_path` case-insensitively (DBs built with
+    /// `dudb --case-insensitive`).
+    pub case_insensitive: bool,
+    /// Fill `FolderOut::devices` from the `device_stats` table, if present.
+    pub by_device: bool,
+    /// Path prefixes the caller may not see (`acl::AccessList::hidden_for`):
+    /// children at or below them are left out.
+    pub hidden: Vec<String>,
 }
 
 /// Open a read-only connection pool against the given DB and validate schema.
@@ -73,6 +132,68 @@ pub fn open_pool(db: &Path) -> Result<DbPool> {
     Ok(pool)
 }
 
+/// Value of a `metadata` key written by dudb, if present.
+pub fn read_metadata(pool: &DbPool, key: &str) -> Result<Option<String>> {
+    let conn = pool.get().context("acquiring connection")?;
+    let mut stmt = conn.prepare("SELECT value FROM metadata WHERE key = ?1")?;
+    let mut rows = stmt.query([key])?;
+    match rows.next()? {
+        Some(r) => Ok(Some(r.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+/// Load-quality summary of a dudb-built DB, served at `/api/stats`. Counters
+/// are `None` for DBs built before dudb recorded them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct IndexStats {
+    /// Server-side path of the dusum CSV; left out for non-admins
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_csv: Option<String>,
+    pub built_at: Option<i64>,
+    pub loader_version: Option<String>,
+    pub path_case: Option<String>,
+    pub rows: Option<u64>,
+    pub rows_malformed: Option<u64>,
+    pub rows_skipped: Option<u64>,
+    pub rows_duplicate: Option<u64>,
+    pub paths: Option<u64>,
+    pub users: Option<u64>,
+    pub max_depth: Option<u64>,
+    /// Size of the DB (page_count * page_size); what duapi can map into memory.
+    pub db_bytes: u64,
+}
+
+pub fn index_stats(pool: &DbPool) -> Result<IndexStats> {
+    let conn = pool.get().context("acquiring connection")?;
+    let mut meta: HashMap<String, String> = HashMap::new();
+    {
+        let mut stmt = conn.prepare("SELECT key, value FROM metadata")?;
+        let rows = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))?;
+        for r in rows {
+            let (k, v) = r?;
+            meta.insert(k, v);
+        }
+    }
+    let num = |k: &str| meta.get(k).and_then(|v| v.parse::<u64>().ok());
+    let page_count: u64 = conn.query_row("PRAGMA page_count", [], |r| r.get(0))?;
+    let page_size: u64 = conn.query_row("PRAGMA page_size", [], |r| r.get(0))?;
+    Ok(IndexStats {
+        source_csv: meta.get("source_csv").cloned(),
+        built_at: meta.get("built_at").and_then(|v| v.parse().ok()),
+        loader_version: meta.get("loader_version").cloned(),
+        path_case: meta.get("path_case").cloned(),
+        rows: num("row_count"),
+        rows_malformed: num("rows_malformed"),
+        rows_skipped: num("rows_skipped"),
+        rows_duplicate: num("rows_duplicate"),
+        paths: num("path_count"),
+        users: num("user_count"),
+        max_depth: num("max_depth"),
+        db_bytes: page_count * page_size,
+    })
+}
+
 /// Return all usernames sorted ascending.
 pub fn list_users(pool: &DbPool) -> Result<Vec<String>> {
     let conn = pool.get().context("acquiring connection")?;
@@ -90,7 +211,7 @@ pub fn list_users(pool: &DbPool) -> Result<Vec<String>> {
 /// `age_filter` of `None` means all three age buckets.
 ///
 /// `dir_path` is matched verbatim against `paths.full_path`, which stores the
-/// exact OS-native form `dusum::aggregate::get_folder_ancestors` produced.
+/// exact OS-native form `util::get_folder_ancestors` produced.
 /// `query::normalize_path` is responsible for putting the request into that
 /// form. The empty string maps to the synthetic root above all platform roots
 /// (so a Linux DB returns `/`; a Windows DB returns `C:\`, `D:\`, `\\srv`).
@@ -103,43 +224,44 @@ pub fn list_children(
     user_filter: &[String],
     age_filter: Option<u8>,
 ) -> Result<Vec<FolderOut>> {
+    list_children_with(pool, dir_path, user_filter, age_filter, &ListOptions::default())
+}
+
+/// `list_children` with extra options: a case-insensitive match on `dir_path`
+/// for DBs built with `dudb --case-insensitive` (which stores one spelling per
+/// folder and indexes its `canonical_key` as `path_key`), and a per-device breakdown
+/// for DBs loaded from `dusum --by-device` output. Folders under
+/// `opts.hidden` are not listed, and neither is anything inside them, so the
+/// caller cannot tell they exist.
+pub fn list_children_with(
+    pool: &DbPool,
+    dir_path: &str,
+    user_filter: &[String],
+    age_filter: Option<u8>,
+    opts: &ListOptions,
+) -> Result<Vec<FolderOut>> {
+    let hidden = |p: &str| crate::acl::is_hidden(p, &opts.hidden, opts.case_insensitive);
+    if hidden(dir_path) {
+        return Ok(Vec::new());
+    }
     let conn = pool.get().context("acquiring connection")?;
 
-    let mut sql = String::from(
+    let (filter, params) =
+        child_filter(&conn, dir_path, user_filter, age_filter, opts.case_insensitive)?;
+    let param_refs: Vec<&dyn ToSql> = params.iter().map(|b| b.as_ref()).collect();
+
+    let oldest = oldest_cols(&conn, "stats", false)?;
+    let sql = format!(
         "SELECT p.full_path, u.name, s.age,
                 s.file_count, s.file_size, s.disk_bytes, s.linked_size,
-                s.atime, s.mtime
+                s.atime, s.mtime, {oldest}
          FROM   paths parent
          JOIN   paths p ON p.parent_id = parent.id
          JOIN   stats s ON s.path_id   = p.id
          JOIN   users u ON u.id        = s.user_id
-         WHERE  parent.full_path = ?1",
+         {filter} ORDER BY p.full_path"
     );
-
-    // Boxed params so we can grow the list dynamically.
-    let mut params: Vec<Box<dyn ToSql>> = vec![Box::new(dir_path.to_string())];
-
-    if let Some(a) = age_filter {
-        sql.push_str(&format!(" AND s.age = ?{}", params.len() + 1));
-        params.push(Box::new(a as i64));
-    }
-
-    if !user_filter.is_empty() {
-        sql.push_str(" AND u.name IN (");
-        for (i, u) in user_filter.iter().enumerate() {
-            if i > 0 {
-                sql.push(',');
-            }
-            sql.push_str(&format!("?{}", params.len() + 1));
-            params.push(Box::new(u.clone()));
-        }
-        sql.push(')');
-    }
-
-    sql.push_str(" ORDER BY p.full_path");
-
     let mut stmt = conn.prepare(&sql)?;
-    let param_refs: Vec<&dyn ToSql> = params.iter().map(|b| b.as_ref()).collect();
 
     let rows = stmt.query_map(param_refs.as_slice(), |r| {
         Ok((
@@ -152,6 +274,7 @@ pub fn list_children(
             r.get::<_, u64>(6)?,
             r.get::<_, i64>(7)?,
             r.get::<_, i64>(8)?,
+            (r.get::<_, i64>(9)?, r.get::<_, i64>(10)?),
         ))
     })?;
 
@@ -159,7 +282,10 @@ pub fn list_children(
     // sorted to match the legacy implementation's `items.sort_by(path)`.
     let mut grouped: BTreeMap<String, HashMap<String, HashMap<String, Age>>> = BTreeMap::new();
     for row in rows {
-        let (path, user, age, count, size, disk, linked, atime, mtime) = row?;
+        let (path, user, age, count, size, disk, linked, atime, mtime, oldest) = row?;
+        if hidden(&path) {
+            continue;
+        }
         let users_map = grouped.entry(path).or_default();
         let ages_map = users_map.entry(user).or_default();
         ages_map.insert(
@@ -171,16 +297,210 @@ pub fn list_children(
                 linked,
                 atime,
                 mtime,
+                oldest_atime: oldest.0,
+                oldest_mtime: oldest.1,
             },
         );
     }
 
+    let mut devices = if opts.by_device && has_table(&conn, "device_stats")? {
+        let oldest = oldest_cols(&conn, "device_stats", true)?;
+        let sql = format!(
+            "SELECT p.full_path, s.device,
+                    SUM(s.file_count), SUM(s.file_size), SUM(s.disk_bytes),
+                    SUM(s.linked_size), MAX(s.atime), MAX(s.mtime), {oldest}
+             FROM   paths parent
+             JOIN   paths p ON p.parent_id = parent.id
+             JOIN   device_stats s ON s.path_id = p.id
+             JOIN   users u ON u.id = s.user_id
+             {filter} GROUP BY p.full_path, s.device"
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(param_refs.as_slice(), |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, i64>(1)?,
+                Age {
+                    count: r.get(2)?,
+                    size: r.get(3)?,
+                    disk: r.get(4)?,
+                    linked: r.get(5)?,
+                    atime: r.get(6)?,
+                    mtime: r.get(7)?,
+                    oldest_atime: r.get(8)?,
+                    oldest_mtime: r.get(9)?,
+                },
+            ))
+        })?;
+        let mut by_path: HashMap<String, BTreeMap<String, Age>> = HashMap::new();
+        for row in rows {
+            let (path, device, age) = row?;
+            by_path.entry(path).or_default().insert(device.to_string(), age);
+        }
+        Some(by_path)
+    } else {
+        None
+    };
+
     Ok(grouped
         .into_iter()
-        .map(|(path, users)| FolderOut { path, users })
+        .map(|(path, users)| {
+            let devs = devices
+                .as_mut()
+                .map(|d| d.remove(&path).unwrap_or_default());
+            FolderOut {
+                path,
+                users,
+                devices: devs,
+                user_info: None,
+                owner: None,
+                tier: None,
+            }
+        })
         .collect())
 }
 
+/// Totals of the folder `dir_path` itself per age bucket, for `user` or for
+/// all users summed. `None` when the folder has no such rows.
+pub fn folder_totals(
+    pool: &DbPool,
+    dir_path: &str,
+    user: Option<&str>,
+    case_insensitive: bool,
+) -> Result<Option<BTreeMap<u8, Age>>> {
+    let conn = pool.get().context("acquiring connection")?;
+    let (path_sql, dir_path) = path_match(&conn, "p", dir_path, case_insensitive)?;
+    let user_filter = if user.is_some() { " AND u.name = ?2" } else { "" };
+    let oldest = oldest_cols(&conn, "stats", true)?;
+    let sql = format!(
+        "SELECT s.age, SUM(s.file_count), SUM(s.file_size), SUM(s.disk_bytes),
+                SUM(s.linked_size), MAX(s.atime), MAX(s.mtime), {oldest}
+         FROM   paths p
+         JOIN   stats s ON s.path_id = p.id
+         JOIN   users u ON u.id      = s.user_id
+         WHERE  {path_sql}{user_filter}
+         GROUP BY s.age"
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let mut params: Vec<&dyn ToSql> = vec![&dir_path];
+    if let Some(u) = &user {
+        params.push(u);
+    }
+    let rows = stmt.query_map(params.as_slice(), |r| {
+        Ok((
+            r.get::<_, u8>(0)?,
+            Age {
+                count: r.get(1)?,
+                size: r.get(2)?,
+                disk: r.get(3)?,
+                linked: r.get(4)?,
+                atime: r.get(5)?,
+                mtime: r.get(6)?,
+                oldest_atime: r.get(7)?,
+                oldest_mtime: r.get(8)?,
+            },
+        ))
+    })?;
+    let mut out = BTreeMap::new();
+    for row in rows {
+        let (age, totals) = row?;
+        out.insert(age, totals);
+    }
+    Ok((!out.is_empty()).then_some(out))
+}
+
+/// WHERE clause (and its params) selecting the children of `dir_path`,
+/// restricted to the requested age bucket and users. Expects the aliases
+/// `parent`, `s` (a stats-shaped table) and `u` (users).
+fn child_filter(
+    conn: &rusqlite::Connection,
+    dir_path: &str,
+    user_filter: &[String],
+    age_filter: Option<u8>,
+    case_insensitive: bool,
+) -> Result<(String, Vec<Box<dyn ToSql>>)> {
+    let (path_sql, dir_path) = path_match(conn, "parent", dir_path, case_insensitive)?;
+    let mut sql = format!("WHERE {path_sql}");
+
+    // Boxed params so we can grow the list dynamically.
+    let mut params: Vec<Box<dyn ToSql>> = vec![Box::new(dir_path)];
+
+    if let Some(a) = age_filter {
+        sql.push_str(&format!(" AND s.age = ?{}", params.len() + 1));
+        params.push(Box::new(a as i64));
+    }
+
+    if !user_filter.is_empty() {
+        sql.push_str(" AND u.name IN (");
+        for (i, u) in user_filter.iter().enumerate() {
+            if i > 0 {
+                sql.push(',');
+            }
+            sql.push_str(&format!("?{}", params.len() + 1));
+            params.push(Box::new(u.clone()));
+        }
+        sql.push(')');
+    }
+    Ok((sql, params))
+}
+
+/// SQL comparing the `paths` row `alias` with `?1`, and the value to bind.
+/// Case-insensitive lookups use the `path_key` that `dudb --case-insensitive`
+/// computed with `canonical_key`, so they fold exactly like duapi does. DBs
+/// without keys (built case-sensitive, served with `--case-insensitive`)
+/// fall back to `COLLATE NOCASE`, which only folds ASCII and is unindexed.
+fn path_match(
+    conn: &rusqlite::Connection,
+    alias: &str,
+    dir_path: &str,
+    case_insensitive: bool,
+) -> Result<(String, String)> {
+    if !case_insensitive {
+        return Ok((format!("{alias}.full_path = ?1"), dir_path.to_string()));
+    }
+    let keyed: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info('paths') WHERE name = 'path_key'",
+        [],
+        |r| r.get(0),
+    )?;
+    Ok(if keyed > 0 {
+        (format!("{alias}.path_key = ?1"), crate::query::canonical_key(dir_path, true))
+    } else {
+        (format!("{alias}.full_path = ?1 COLLATE NOCASE"), dir_path.to_string())
+    })
+}
+
+/// Select list for the oldest atime/mtime of the stats-shaped table `s`,
+/// the earliest known value across rows with `aggregate`. DBs loaded before
+/// dudb stored them have no such columns and report 0 (unknown).
+pub(crate) fn oldest_cols(
+    conn: &rusqlite::Connection,
+    table: &str,
+    aggregate: bool,
+) -> Result<&'static str> {
+    let n: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = 'oldest_atime'",
+        [table],
+        |r| r.get(0),
+    )?;
+    Ok(match (n > 0, aggregate) {
+        (false, _) => "0, 0",
+        (true, false) => "s.oldest_atime, s.oldest_mtime",
+        (true, true) => {
+            "COALESCE(MIN(NULLIF(s.oldest_atime, 0)), 0), COALESCE(MIN(NULLIF(s.oldest_mtime, 0)), 0)"
+        }
+    })
+}
+
+fn has_table(conn: &rusqlite::Connection, name: &str) -> Result<bool> {
+    let n: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [name],
+        |r| r.get(0),
+    )?;
+    Ok(n > 0)
+}
+
 pub mod test_support {
     //! Shared helpers for building a temp SQLite DB so that handler tests and
     //! db tests don't duplicate fixture code.
@@ -240,6 +560,7 @@ pub mod test_support {
              CREATE TABLE metadata (key TEXT PRIMARY KEY, value TEXT NOT NULL);
              CREATE INDEX idx_paths_parent ON paths(parent_id);
              INSERT INTO metadata(key,value) VALUES('schema_version','2');
+             INSERT INTO metadata(key,value) VALUES('source_csv','/data/fs.sum.csv');
              INSERT INTO users(name) VALUES('alice'),('bob');
              INSERT INTO paths(full_path, parent_id) VALUES('', NULL);
              INSERT INTO paths(full_path, parent_id) VALUES(
@@ -271,6 +592,63 @@ mod tests {
         (db, pool)
     }
 
+    #[test]
+    fn index_stats_reads_metadata_and_size() {
+        let (_db, pool) = build_pool();
+        let s = index_stats(&pool).unwrap();
+        assert!(s.db_bytes > 0);
+        // The fixture predates the load counters.
+        assert_eq!(s.rows_malformed, None);
+        assert_eq!(s.source_csv.as_deref(), Some("/data/fs.sum.csv"));
+    }
+
+    #[test]
+    fn folder_totals_sums_users_per_age() {
+        let (_db, pool) = build_pool();
+        let root = folder_totals(&pool, "/", None, false).unwrap().unwrap();
+        assert_eq!(root.keys().copied().collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!((root[&0].count, root[&0].size), (2, 200));
+        assert_eq!((root[&1].count, root[&1].size), (1, 50));
+        let bob = folder_totals(&pool, "/", Some("bob"), false).unwrap().unwrap();
+        assert_eq!(bob.keys().copied().collect::<Vec<_>>(), vec![1]);
+        assert!(folder_totals(&pool, "/docs", Some("bob"), false).unwrap().is_none());
+        assert!(folder_totals(&pool, "/DOCS", None, false).unwrap().is_none());
+        assert_eq!(folder_totals(&pool, "/DOCS", None, true).unwrap().unwrap()[&2].size, 600);
+    }
+
+    #[test]
+    fn oldest_times_are_served_when_stored() {
+        let (db, pool) = build_pool();
+        // The fixture predates the columns: unknown, 0.
+        let docs = list_children(&pool, "/", &[], None).unwrap();
+        assert_eq!(docs[0].users["alice"]["2"].oldest_atime, 0);
+
+        let conn = rusqlite::Connection::open(&db.path).unwrap();
+        conn.execute_batch(
+            "ALTER TABLE stats ADD COLUMN oldest_atime INTEGER NOT NULL DEFAULT 0;
+             ALTER TABLE stats ADD COLUMN oldest_mtime INTEGER NOT NULL DEFAULT 0;
+             INSERT INTO users(name) VALUES('carol');
+             INSERT INTO stats
+               SELECT p.id, u.id, 0, 1, 10, 10, 0, 1700000000, 1700000000, 1650000000, 0
+               FROM paths p, users u WHERE p.full_path = '/' AND u.name = 'carol';
+             UPDATE stats SET oldest_atime = 1690000000, oldest_mtime = 1695000000
+               WHERE age = 0 AND user_id = (SELECT id FROM users WHERE name = 'alice');
+             UPDATE stats SET oldest_atime = 1400000000, oldest_mtime = 1450000000
+               WHERE age = 2;",
+        )
+        .unwrap();
+        drop(conn);
+        let pool = open_pool(&db.path).unwrap();
+
+        let docs = list_children(&pool, "/", &[], None).unwrap();
+        let a = &docs[0].users["alice"]["2"];
+        assert_eq!((a.oldest_atime, a.oldest_mtime), (1400000000, 1450000000));
+        // Summed over users, the earliest known time; carol's unset mtime
+        // does not win.
+        let root = folder_totals(&pool, "/", None, false).unwrap().unwrap();
+        assert_eq!((root[&0].oldest_atime, root[&0].oldest_mtime), (1650000000, 1695000000));
+    }
+
     #[test]
     fn list_users_returns_sorted() {
         let (_db, pool) = build_pool();
@@ -278,6 +656,16 @@ mod tests {
         assert_eq!(users, vec!["alice".to_string(), "bob".to_string()]);
     }
 
+    #[test]
+    fn read_metadata_returns_known_and_missing_keys() {
+        let (_db, pool) = build_pool();
+        assert_eq!(
+            read_metadata(&pool, "schema_version").unwrap().as_deref(),
+            Some("2")
+        );
+        assert_eq!(read_metadata(&pool, "no_such_key").unwrap(), None);
+    }
+
     #[test]
     fn list_children_under_unix_root_returns_docs() {
         let (_db, pool) = build_pool();
@@ -341,7 +729,7 @@ mod tests {
         let conn = Connection::open(&path).unwrap();
         conn.execute_batch(
             "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
-             CREATE TABLE paths (id INTEGER PRIMARY KEY, parent_id INTEGER, full_path TEXT NOT NULL UNIQUE);
+             CREATE TABLE paths (id INTEGER PRIMARY KEY, parent_id INTEGER, full_path TEXT NOT NULL UNIQUE, path_key TEXT);
              CREATE TABLE stats (
                 path_id INTEGER NOT NULL, user_id INTEGER NOT NULL, age INTEGER NOT NULL,
                 file_count INTEGER NOT NULL, file_size INTEGER NOT NULL,
@@ -358,6 +746,8 @@ mod tests {
                  (SELECT id FROM paths WHERE full_path=''));
              INSERT INTO paths(full_path, parent_id) VALUES('C:\\Users',
                  (SELECT id FROM paths WHERE full_path='C:\\'));
+             INSERT INTO paths(full_path, parent_id) VALUES('C:\\Users\\Änne',
+                 (SELECT id FROM paths WHERE full_path='C:\\Users'));
              INSERT INTO paths(full_path, parent_id) VALUES('\\\\srv',
                  (SELECT id FROM paths WHERE full_path=''));
              INSERT INTO stats VALUES
@@ -369,9 +759,28 @@ mod tests {
                 0, 5, 500, 500, 0, 1, 1),
                ((SELECT id FROM paths WHERE full_path='\\\\srv'),
                 (SELECT id FROM users WHERE name='San'),
-                0, 2, 200, 200, 0, 1, 1);",
+                0, 2, 200, 200, 0, 1, 1),
+               ((SELECT id FROM paths WHERE full_path='C:\\Users\\Änne'),
+                (SELECT id FROM users WHERE name='San'),
+                0, 1, 100, 100, 0, 1, 1);",
         )
         .unwrap();
+        // What `dudb --case-insensitive` stores next to each spelling.
+        let paths: Vec<(i64, String)> = conn
+            .prepare("SELECT id, full_path FROM paths")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        for (id, full) in paths {
+            let key = crate::query::canonical_key(&full, true);
+            conn.execute(
+                "UPDATE paths SET path_key = ?1 WHERE id = ?2",
+                rusqlite::params![key, id],
+            )
+            .unwrap();
+        }
         drop(conn);
 
         let pool = open_pool(&path).unwrap();
@@ -388,9 +797,87 @@ mod tests {
         assert_eq!(drive_children.len(), 1);
         assert_eq!(drive_children[0].path, "C:\\Users");
 
+        // Different case only matches in case-insensitive mode; the stored
+        // spelling is what comes back.
+        assert!(list_children(&pool, "c:\\", &[], None).unwrap().is_empty());
+        let ci = list_children_with(
+            &pool,
+            "c:\\",
+            &[],
+            None,
+            &ListOptions {
+                case_insensitive: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(ci.len(), 1);
+        assert_eq!(ci[0].path, "C:\\Users");
+
+        // Non-ASCII letters fold too, which `COLLATE NOCASE` would not do.
+        assert!(folder_totals(&pool, "c:\\users\\ÄNNE", None, false).unwrap().is_none());
+        assert!(folder_totals(&pool, "c:\\users\\ÄNNE", None, true).unwrap().is_some());
+
         drop(cleanup);
     }
 
+    #[test]
+    fn list_children_by_device_sums_users_per_device() {
+        let db = test_support::build_test_db();
+        let conn = rusqlite::Connection::open(&db.path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE device_stats (
+                path_id INTEGER NOT NULL, device INTEGER NOT NULL,
+                user_id INTEGER NOT NULL, age INTEGER NOT NULL,
+                file_count INTEGER NOT NULL, file_size INTEGER NOT NULL,
+                disk_bytes INTEGER NOT NULL, linked_size INTEGER NOT NULL,
+                atime INTEGER NOT NULL, mtime INTEGER NOT NULL,
+                PRIMARY KEY (path_id, device, user_id, age)
+             ) WITHOUT ROWID;
+             INSERT INTO device_stats
+               SELECT p.id, 7, u.id, 2, 1, 100, 100, 0, 5, 6
+               FROM paths p, users u WHERE p.full_path = '/docs';
+             INSERT INTO device_stats
+               SELECT p.id, 9, u.id, 2, 2, 200, 200, 0, 1, 1
+               FROM paths p, users u WHERE p.full_path = '/docs' AND u.name = 'bob';",
+        )
+        .unwrap();
+        drop(conn);
+        let pool = open_pool(&db.path).unwrap();
+
+        let plain = list_children(&pool, "/", &[], None).unwrap();
+        assert!(plain.iter().all(|f| f.devices.is_none()));
+
+        let opts = ListOptions {
+            by_device: true,
+            ..Default::default()
+        };
+        let items = list_children_with(&pool, "/", &[], None, &opts).unwrap();
+        let devs = items.iter().find(|i| i.path == "/docs").unwrap().devices.as_ref().unwrap();
+        assert_eq!((devs["7"].count, devs["7"].size, devs["7"].atime), (2, 200, 5));
+        assert_eq!(devs["9"].count, 2);
+
+        let alice = list_children_with(&pool, "/", &["alice".into()], None, &opts).unwrap();
+        let devs = alice.iter().find(|i| i.path == "/docs").unwrap().devices.as_ref().unwrap();
+        assert_eq!(devs.len(), 1);
+        assert_eq!(devs["7"].count, 1);
+    }
+
+    #[test]
+    fn list_children_leaves_out_hidden_trees() {
+        let db = test_support::build_test_db();
+        let pool = open_pool(&db.path).unwrap();
+        let opts = ListOptions {
+            hidden: vec!["/docs".into()],
+            ..Default::default()
+        };
+        let all = list_children(&pool, "/", &[], None).unwrap();
+        assert!(all.iter().any(|f| f.path == "/docs"));
+        let items = list_children_with(&pool, "/", &[], None, &opts).unwrap();
+        assert!(items.iter().all(|f| f.path != "/docs"));
+        assert!(list_children_with(&pool, "/docs", &[], None, &opts).unwrap().is_empty());
+    }
+
     #[test]
     fn open_pool_rejects_missing_metadata() {
         let path = std::env::temp_dir().join(format!(
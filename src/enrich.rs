@@ -0,0 +1,287 @@
+// rs/src/enrich.rs
+//
+// Extra per-file columns computed during a scan (`duscan --enrich SPEC`).
+// An enricher declares its column names once and fills one value per column
+// for every emitted row; values are appended after PATH in the CSV output.
+//
+// Built-in specs:
+//   regex:NAME=PATTERN   first capture group of PATTERN on the path (the
+//                        whole match without groups), empty when no match
+//   lib:/path/libx.so    shared library exporting the C ABI below (Unix)
+//
+// Shared library ABI (all calls may come from several threads at once):
+//
+//   const char *dutopia_enrich_columns(void);   // "col1,col2", static storage
+//   size_t dutopia_enrich(const uint8_t *path, size_t path_len,
+//                         const Row *row, uint8_t *out, size_t cap);
+//
+// `dutopia_enrich` writes the values separated by 0x1F (unit separator) into
+// `out` and returns the number of bytes needed. When that exceeds `cap` the
+// call is repeated with a larger buffer.
+use anyhow::{bail, Context, Result};
+use regex::bytes::Regex;
+
+use crate::util::Row;
+
+/// Values separator in the shared-library ABI.
+pub const UNIT_SEPARATOR: u8 = 0x1f;
+
+pub trait RowEnricher: Send + Sync {
+    /// Column names, in the order `enrich` appends values.
+    fn columns(&self) -> &[String];
+
+    /// Append exactly one value per column to `out`.
+    fn enrich(&self, path: &[u8], row: &Row, out: &mut Vec<String>);
+}
+
+/// `regex:NAME=PATTERN`.
+pub struct RegexEnricher {
+    columns: Vec<String>,
+    re: Regex,
+}
+
+impl RegexEnricher {
+    pub fn new(name: &str, pattern: &str) -> Result<Self> {
+        let re = Regex::new(pattern).with_context(|| format!("invalid regex: {pattern}"))?;
+        Ok(Self {
+            columns: vec![name.to_string()],
+            re,
+        })
+    }
+}
+
+impl RowEnricher for RegexEnricher {
+    fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    fn enrich(&self, path: &[u8], _row: &Row, out: &mut Vec<String>) {
+        let value = self
+            .re
+            .captures(path)
+            .and_then(|c| c.get(1).or_else(|| c.get(0)))
+            .map(|m| String::from_utf8_lossy(m.as_bytes()).into_owned())
+            .unwrap_or_default();
+        out.push(value);
+    }
+}
+
+/// Every `--enrich` in command-line order, seen as one enricher.
+#[derive(Default)]
+pub struct Enrichers {
+    list: Vec<Box<dyn RowEnricher>>,
+    columns: Vec<String>,
+}
+
+impl Enrichers {
+    pub fn parse(specs: &[String]) -> Result<Self> {
+        let mut e = Self::default();
+        for spec in specs {
+            e.push(parse_enricher(spec)?);
+        }
+        Ok(e)
+    }
+
+    pub fn push(&mut self, enricher: Box<dyn RowEnricher>) {
+        self.columns.extend(enricher.columns().iter().cloned());
+        self.list.push(enricher);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// Values for every column; an enricher returning too few or too many
+    /// values is padded or cut so the CSV stays rectangular.
+    pub fn values(&self, path: &[u8], row: &Row, out: &mut Vec<String>) {
+        out.clear();
+        for e in &self.list {
+            let want = out.len() + e.columns().len();
+            e.enrich(path, row, out);
+            out.resize(want, String::new());
+        }
+    }
+}
+
+pub fn parse_enricher(spec: &str) -> Result<Box<dyn RowEnricher>> {
+    let (kind, arg) = spec
+        .split_once(':')
+        .with_context(|| format!("--enrich expects KIND:ARG, got '{spec}'"))?;
+    match kind {
+        "regex" => {
+            let (name, pattern) = arg
+                .split_once('=')
+                .filter(|(n, p)| !n.is_empty() && !p.is_empty())
+                .with_context(|| format!("--enrich regex: expects NAME=PATTERN, got '{arg}'"))?;
+            Ok(Box::new(RegexEnricher::new(name, pattern)?))
+        }
+        #[cfg(unix)]
+        "lib" => Ok(Box::new(dylib::DylibEnricher::open(arg)?)),
+        #[cfg(not(unix))]
+        "lib" => bail!("--enrich lib: is only supported on Unix"),
+        "wasm" => bail!("--enrich wasm: is not supported by this build; use lib: or regex:"),
+        other => bail!("unknown --enrich kind '{other}' (expected regex: or lib:)"),
+    }
+}
+
+#[cfg(unix)]
+mod dylib {
+    use super::{RowEnricher, UNIT_SEPARATOR};
+    use crate::util::Row;
+    use anyhow::{bail, Result};
+    use std::cell::RefCell;
+    use std::ffi::{c_char, c_void, CStr, CString};
+
+    type ColumnsFn = unsafe extern "C" fn() -> *const c_char;
+    type EnrichFn = unsafe extern "C" fn(*const u8, usize, *const Row, *mut u8, usize) -> usize;
+
+    pub struct DylibEnricher {
+        handle: *mut c_void,
+        enrich: EnrichFn,
+        columns: Vec<String>,
+    }
+
+    thread_local! {
+        /// Output buffer for `dutopia_enrich`, kept per scan worker and only
+        /// ever grown, so rows do not allocate one each.
+        static OUT_BUF: RefCell<Vec<u8>> = RefCell::new(vec![0u8; 256]);
+    }
+
+    // The ABI requires `dutopia_enrich` to be callable from any thread.
+    unsafe impl Send for DylibEnricher {}
+    unsafe impl Sync for DylibEnricher {}
+
+    impl DylibEnricher {
+        pub fn open(path: &str) -> Result<Self> {
+            let c_path = CString::new(path)?;
+            let handle =
+                unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+            if handle.is_null() {
+                bail!("--enrich lib: cannot load {path}: {}", dl_error());
+            }
+            let sym = |name: &CStr| unsafe { libc::dlsym(handle, name.as_ptr()) };
+            let (columns_sym, enrich_sym) =
+                (sym(c"dutopia_enrich_columns"), sym(c"dutopia_enrich"));
+            if columns_sym.is_null() || enrich_sym.is_null() {
+                unsafe { libc::dlclose(handle) };
+                bail!("--enrich lib: {path} does not export dutopia_enrich_columns/dutopia_enrich");
+            }
+            let (columns_fn, enrich) = unsafe {
+                (
+                    std::mem::transmute::<*mut c_void, ColumnsFn>(columns_sym),
+                    std::mem::transmute::<*mut c_void, EnrichFn>(enrich_sym),
+                )
+            };
+            let names = unsafe { columns_fn() };
+            let columns = if names.is_null() {
+                Vec::new()
+            } else {
+                unsafe { CStr::from_ptr(names) }
+                    .to_string_lossy()
+                    .split(',')
+                    .map(|c| c.trim().to_string())
+                    .filter(|c| !c.is_empty())
+                    .collect()
+            };
+            Ok(Self {
+                handle,
+                enrich,
+                columns,
+            })
+        }
+    }
+
+    impl RowEnricher for DylibEnricher {
+        fn columns(&self) -> &[String] {
+            &self.columns
+        }
+
+        fn enrich(&self, path: &[u8], row: &Row, out: &mut Vec<String>) {
+            OUT_BUF.with_borrow_mut(|buf| {
+                let mut n = unsafe {
+                    (self.enrich)(path.as_ptr(), path.len(), row, buf.as_mut_ptr(), buf.len())
+                };
+                if n > buf.len() {
+                    buf.resize(n, 0);
+                    n = unsafe {
+                        (self.enrich)(path.as_ptr(), path.len(), row, buf.as_mut_ptr(), buf.len())
+                    };
+                }
+                out.extend(
+                    buf[..n.min(buf.len())]
+                        .split(|&b| b == UNIT_SEPARATOR)
+                        .map(|v| String::from_utf8_lossy(v).into_owned()),
+                );
+            });
+        }
+    }
+
+    impl Drop for DylibEnricher {
+        fn drop(&mut self) {
+            unsafe { libc::dlclose(self.handle) };
+        }
+    }
+
+    fn dl_error() -> String {
+        let e = unsafe { libc::dlerror() };
+        if e.is_null() {
+            "unknown error".to_string()
+        } else {
+            unsafe { CStr::from_ptr(e) }.to_string_lossy().into_owned()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row() -> Row {
+        Row {
+            dev: 1,
+            ino: 2,
+            mode: 0o100644,
+            uid: 1000,
+            gid: 1000,
+            size: 10,
+            blocks: 1,
+            atime: 0,
+            mtime: 0,
+        }
+    }
+
+    #[test]
+    fn regex_enricher_uses_first_group_or_whole_match() {
+        let e = Enrichers::parse(&[
+            "regex:project=^/proj/([^/]+)".to_string(),
+            "regex:ext=\\.[a-z]+$".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(e.columns(), ["project", "ext"]);
+        let mut out = Vec::new();
+        e.values(b"/proj/abc/src/main.rs", &row(), &mut out);
+        assert_eq!(out, ["abc", ".rs"]);
+        e.values(b"/home/x/README", &row(), &mut out);
+        assert_eq!(out, ["", ""]);
+    }
+
+    #[test]
+    fn bad_specs_are_rejected() {
+        assert!(parse_enricher("project").is_err());
+        assert!(parse_enricher("regex:project").is_err());
+        assert!(parse_enricher("regex:p=(").is_err());
+        assert!(parse_enricher("wasm:plugin.wasm").is_err());
+        assert!(parse_enricher("python:x.py").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn missing_library_is_an_error() {
+        let err = parse_enricher("lib:/nonexistent/libnope.so").err().unwrap();
+        assert!(err.to_string().contains("cannot load"));
+    }
+}
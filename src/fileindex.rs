@@ -0,0 +1,351 @@
+// rs/src/fileindex.rs
+//
+// Per-file listings served from a raw duscan output (CSV or `--bin` .zst)
+// instead of a live `read_dir` + stat (duapi `--files-source`). The scan is
+// indexed once into a SQLite sidecar `<scan>.files.db` holding one row per
+// regular file, keyed by parent folder; the sidecar is rebuilt whenever the
+// scan is newer than it. Listings therefore match the scan the summary was
+// built from and work on hosts that cannot see the scanned filesystem.
+use anyhow::{anyhow, Context, Result};
+use csv::{ReaderBuilder, Trim};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, OpenFlags, ToSql};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Seek};
+use std::path::{Path, PathBuf};
+
+use crate::db::DbPool;
+use crate::item::{age_cutoffs, owner_of_uid, FsItemOut, SortKey};
+use crate::util::{dusum_parent, parse_int, AtomicFile};
+
+const ZSTD_MAGIC: u32 = 0xFD2F_B528;
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+
+/// One `/api/files` request against the index.
+#[derive(Debug, Clone, Default)]
+pub struct FilesPage {
+    /// Parent folder, in the scan's native form (`/a/b`, `C:\a`)
+    pub dir: String,
+    /// Owners to keep; empty keeps all
+    pub users: Vec<String>,
+    /// Age bucket 0/1/2 as in dusum
+    pub age: Option<u8>,
+    pub sort: SortKey,
+    pub desc: bool,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+pub struct FileIndex {
+    pool: DbPool,
+    path: PathBuf,
+}
+
+/// `<scan>.files.db` next to the scan.
+pub fn sidecar_path(scan: &Path) -> PathBuf {
+    let mut name = scan.file_name().unwrap_or_default().to_os_string();
+    name.push(".files.db");
+    scan.with_file_name(name)
+}
+
+impl FileIndex {
+    /// Open the index for `scan`, (re)building the sidecar first when it is
+    /// missing or older than the scan.
+    pub fn open(scan: &Path) -> Result<Self> {
+        let db = sidecar_path(scan);
+        let scan_mtime = std::fs::metadata(scan)
+            .and_then(|m| m.modified())
+            .with_context(|| format!("reading {}", scan.display()))?;
+        let fresh = std::fs::metadata(&db)
+            .and_then(|m| m.modified())
+            .is_ok_and(|t| t >= scan_mtime);
+        if !fresh {
+            build(scan, &db)?;
+        }
+        let manager = SqliteConnectionManager::file(&db)
+            .with_flags(OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+            .with_init(|c| c.execute_batch("PRAGMA query_only = ON;"));
+        let pool = r2d2::Pool::builder()
+            .max_size(std::cmp::max(num_cpus::get(), 4) as u32)
+            .build(manager)
+            .with_context(|| format!("opening {}", db.display()))?;
+        Ok(Self { pool, path: db })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of indexed files.
+    pub fn file_count(&self) -> Result<u64> {
+        let conn = self.pool.get().context("acquiring connection")?;
+        Ok(conn.query_row("SELECT COUNT(*) FROM files", [], |r| r.get::<_, i64>(0))? as u64)
+    }
+
+    /// One page of `q.dir`'s files plus the number matching before paging.
+    pub fn list(&self, q: &FilesPage) -> Result<(usize, Vec<FsItemOut>)> {
+        let mut filter = String::from(" FROM files WHERE dir = ?");
+        let mut args: Vec<Box<dyn ToSql>> = vec![Box::new(q.dir.clone())];
+        if !q.users.is_empty() {
+            filter.push_str(" AND owner IN (");
+            filter.push_str(&vec!["?"; q.users.len()].join(","));
+            filter.push(')');
+            args.extend(q.users.iter().map(|u| Box::new(u.clone()) as Box<dyn ToSql>));
+        }
+        if let Some(age) = q.age {
+            let (recent, old) = age_cutoffs();
+            match age {
+                0 => {
+                    filter.push_str(" AND mtime >= ?");
+                    args.push(Box::new(recent));
+                }
+                1 => {
+                    filter.push_str(" AND mtime < ? AND mtime >= ?");
+                    args.push(Box::new(recent));
+                    args.push(Box::new(old));
+                }
+                _ => {
+                    filter.push_str(" AND mtime < ?");
+                    args.push(Box::new(old));
+                }
+            }
+        }
+        let conn = self.pool.get().context("acquiring connection")?;
+        let params: Vec<&dyn ToSql> = args.iter().map(|b| b.as_ref()).collect();
+        let total: i64 = conn.query_row(
+            &format!("SELECT COUNT(*){filter}"),
+            params.as_slice(),
+            |r| r.get(0),
+        )?;
+
+        let column = match q.sort {
+            SortKey::Path => "path",
+            SortKey::Size => "size",
+            SortKey::Modified => "mtime",
+        };
+        let order = if q.desc { "DESC" } else { "ASC" };
+        let sql = format!(
+            "SELECT path, owner, size, atime, mtime{filter} \
+             ORDER BY {column} {order}, path {order} LIMIT {} OFFSET {}",
+            q.limit, q.offset
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let items = stmt
+            .query_map(params.as_slice(), |r| {
+                Ok(FsItemOut {
+                    path: r.get(0)?,
+                    owner: r.get(1)?,
+                    size: r.get::<_, i64>(2)? as u64,
+                    accessed: r.get(3)?,
+                    modified: r.get(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok((total as usize, items))
+    }
+}
+
+/// Index the regular files of `scan` into `db`, via an `AtomicFile` renamed
+/// into place. Returns the number of files indexed.
+pub fn build(scan: &Path, db: &Path) -> Result<u64> {
+    let (atomic, file) = AtomicFile::create(db)?;
+    let tmp = atomic.temp_path();
+    let mut conn = rusqlite::Connection::open(tmp)
+        .with_context(|| format!("creating {}", tmp.display()))?;
+    conn.execute_batch(
+        "PRAGMA journal_mode = OFF;
+         PRAGMA synchronous = OFF;
+         CREATE TABLE files (
+             dir   TEXT NOT NULL,
+             path  TEXT NOT NULL,
+             owner TEXT NOT NULL,
+             size  INTEGER NOT NULL,
+             atime INTEGER NOT NULL,
+             mtime INTEGER NOT NULL
+         );",
+    )?;
+
+    let mut owners: HashMap<u32, String> = HashMap::new();
+    let mut count = 0u64;
+    let tx = conn.transaction()?;
+    {
+        let mut insert = tx.prepare("INSERT INTO files VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+        for_each_row(scan, |r| {
+            if r.mode & S_IFMT != S_IFREG && r.mode & S_IFMT != 0 {
+                return Ok(());
+            }
+            let Some(dir) = dusum_parent(&r.path) else {
+                return Ok(());
+            };
+            let owner = owners
+                .entry(r.uid)
+                .or_insert_with(|| owner_of_uid(r.uid))
+                .clone();
+            insert.execute(params![dir, r.path, owner, r.size as i64, r.atime, r.mtime])?;
+            count += 1;
+            Ok(())
+        })?;
+    }
+    tx.execute_batch("CREATE INDEX files_dir ON files(dir, owner);")?;
+    tx.commit()?;
+    drop(conn);
+    atomic.commit(file)?;
+    Ok(count)
+}
+
+/// The fields of a scan row read back by `for_each_row`.
+pub struct ScanRow {
+    pub path: String,
+    pub atime: i64,
+    pub mtime: i64,
+    pub uid: u32,
+    pub mode: u32,
+    pub size: u64,
+}
+
+/// Call `f` for every row of a duscan CSV or binary (.zst) output.
+pub fn for_each_row(scan: &Path, mut f: impl FnMut(ScanRow) -> Result<()>) -> Result<()> {
+    let mut file = File::open(scan).with_context(|| format!("opening {}", scan.display()))?;
+    let mut magic = [0u8; 4];
+    let is_zst = file.read_exact(&mut magic).is_ok() && u32::from_le_bytes(magic) == ZSTD_MAGIC;
+    file.rewind()?;
+
+    if is_zst {
+        let mut r = BufReader::new(zstd::stream::read::Decoder::new(file)?);
+        while let Some(row) = read_bin_row(&mut r)? {
+            f(row)?;
+        }
+        return Ok(());
+    }
+
+    let mut rdr = ReaderBuilder::new()
+        .has_headers(true)
+        .flexible(true)
+        .trim(Trim::None)
+        .from_reader(BufReader::new(file));
+    for rec in rdr.byte_records() {
+        let Ok(rec) = rec else { continue };
+        f(ScanRow {
+            path: String::from_utf8_lossy(rec.get(8).unwrap_or(b"")).into_owned(),
+            atime: parse_int::<i64>(rec.get(1)),
+            mtime: parse_int::<i64>(rec.get(2)),
+            uid: parse_int::<u32>(rec.get(3)),
+            mode: parse_int::<u32>(rec.get(5)),
+            size: parse_int::<u64>(rec.get(6)),
+        })?;
+    }
+    Ok(())
+}
+
+/// One record of duscan's binary format (see `duzip`); `None` at a clean EOF.
+fn read_bin_row(r: &mut impl Read) -> Result<Option<ScanRow>> {
+    let mut len = [0u8; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let mut path = vec![0u8; u32::from_le_bytes(len) as usize];
+    r.read_exact(&mut path)?;
+    // dev, ino, atime, mtime (8 each), uid, gid, mode (4 each), size, disk (8 each)
+    let mut fixed = [0u8; 60];
+    r.read_exact(&mut fixed)
+        .map_err(|e| anyhow!("truncated record: {e}"))?;
+    let u64_at = |o: usize| u64::from_le_bytes(fixed[o..o + 8].try_into().unwrap());
+    let u32_at = |o: usize| u32::from_le_bytes(fixed[o..o + 4].try_into().unwrap());
+    Ok(Some(ScanRow {
+        path: String::from_utf8_lossy(&path).into_owned(),
+        atime: u64_at(16) as i64,
+        mtime: u64_at(24) as i64,
+        uid: u32_at(32),
+        mode: u32_at(40),
+        size: u64_at(44),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCAN: &str = "INODE,ATIME,MTIME,UID,GID,MODE,SIZE,DISK,PATH\n\
+        1-1,0,0,0,0,16877,0,0,/data\n\
+        1-2,10,100,0,0,33188,300,512,/data/a.txt\n\
+        1-3,20,200,0,0,33188,100,512,/data/b.txt\n\
+        1-4,30,300,0,0,41471,9,0,/data/link\n\
+        1-5,40,400,0,0,33188,200,512,\"/data/c,d.txt\"\n\
+        1-6,50,500,0,0,33188,50,512,/data/sub/e.txt\n";
+
+    fn page(dir: &str) -> FilesPage {
+        FilesPage {
+            dir: dir.into(),
+            limit: 100,
+            ..FilesPage::default()
+        }
+    }
+
+    #[test]
+    fn indexes_csv_and_pages_sorted() {
+        let tmp = tempfile::tempdir().unwrap();
+        let scan = tmp.path().join("scan.csv");
+        std::fs::write(&scan, SCAN).unwrap();
+        let idx = FileIndex::open(&scan).unwrap();
+        assert_eq!(idx.path(), tmp.path().join("scan.csv.files.db"));
+        assert_eq!(idx.file_count().unwrap(), 4);
+
+        let (total, items) = idx.list(&page("/data")).unwrap();
+        assert_eq!(total, 3);
+        let paths: Vec<_> = items.iter().map(|i| i.path.as_str()).collect();
+        assert_eq!(paths, ["/data/a.txt", "/data/b.txt", "/data/c,d.txt"]);
+
+        let q = FilesPage {
+            sort: SortKey::Size,
+            desc: true,
+            offset: 1,
+            limit: 1,
+            ..page("/data")
+        };
+        let (total, items) = idx.list(&q).unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].path, "/data/c,d.txt");
+        assert_eq!((items[0].accessed, items[0].modified), (40, 400));
+
+        // Every file is far older than 600 days.
+        let old = FilesPage { age: Some(2), ..page("/data") };
+        assert_eq!(idx.list(&old).unwrap().0, 3);
+        let recent = FilesPage { age: Some(0), ..page("/data") };
+        assert_eq!(idx.list(&recent).unwrap().0, 0);
+        let nobody = FilesPage {
+            users: vec!["nobody-such".into()],
+            ..page("/data")
+        };
+        assert_eq!(idx.list(&nobody).unwrap().0, 0);
+    }
+
+    #[test]
+    fn reads_binary_scans() {
+        let tmp = tempfile::tempdir().unwrap();
+        let scan = tmp.path().join("scan.zst");
+        let mut raw = Vec::new();
+        let path = b"/x/y.bin";
+        raw.extend_from_slice(&(path.len() as u32).to_le_bytes());
+        raw.extend_from_slice(path);
+        for v in [1u64, 2, 5, 7] {
+            raw.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in [0u32, 0, 0o100644] {
+            raw.extend_from_slice(&v.to_le_bytes());
+        }
+        for v in [42u64, 512] {
+            raw.extend_from_slice(&v.to_le_bytes());
+        }
+        std::fs::write(&scan, zstd::encode_all(raw.as_slice(), 1).unwrap()).unwrap();
+
+        let idx = FileIndex::open(&scan).unwrap();
+        let (total, items) = idx.list(&page("/x")).unwrap();
+        assert_eq!(total, 1);
+        assert_eq!((items[0].size, items[0].modified), (42, 7));
+    }
+}
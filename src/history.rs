@@ -0,0 +1,264 @@
+// rs/src/history.rs
+//
+// History database: `dusum --history FILE` appends each run's rollups
+// (path, user, age bucket) to a SQLite file under a scan timestamp, so usage
+// can be followed over time instead of each run replacing the last view.
+// Paths and users are interned once; `stats` grows by one set of rows per
+// scan. Recording the same scan (source and time) again replaces it.
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// One rollup row of a scan.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryRow<'a> {
+    pub path: Cow<'a, str>,
+    pub user: Cow<'a, str>,
+    pub age: u8,
+    pub files: u64,
+    pub size: u64,
+    pub disk: u64,
+    pub linked: u64,
+    pub atime: i64,
+    pub mtime: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ScanRecord {
+    pub id: i64,
+    /// Epoch seconds the scan started
+    pub scanned_at: i64,
+    /// The dusum input it came from
+    pub source: String,
+    pub rows: u64,
+}
+
+/// Totals of a path (all ages) in one scan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Point {
+    pub scanned_at: i64,
+    pub files: u64,
+    pub size: u64,
+    pub disk: u64,
+}
+
+pub struct History {
+    conn: Connection,
+}
+
+impl History {
+    /// Open `path`, creating the file and its tables if needed.
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("opening history DB {}", path.display()))?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA synchronous  = NORMAL;
+             CREATE TABLE IF NOT EXISTS scans (
+                id         INTEGER PRIMARY KEY,
+                scanned_at INTEGER NOT NULL,
+                source     TEXT    NOT NULL,
+                rows       INTEGER NOT NULL,
+                UNIQUE (source, scanned_at)
+             );
+             CREATE TABLE IF NOT EXISTS paths (
+                id        INTEGER PRIMARY KEY,
+                full_path TEXT NOT NULL UNIQUE
+             );
+             CREATE TABLE IF NOT EXISTS users (
+                id   INTEGER PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE
+             );
+             CREATE TABLE IF NOT EXISTS stats (
+                path_id     INTEGER NOT NULL,
+                scan_id     INTEGER NOT NULL,
+                user_id     INTEGER NOT NULL,
+                age         INTEGER NOT NULL,
+                file_count  INTEGER NOT NULL,
+                file_size   INTEGER NOT NULL,
+                disk_bytes  INTEGER NOT NULL,
+                linked_size INTEGER NOT NULL,
+                atime       INTEGER NOT NULL,
+                mtime       INTEGER NOT NULL,
+                PRIMARY KEY (path_id, scan_id, user_id, age)
+             ) WITHOUT ROWID;
+             CREATE INDEX IF NOT EXISTS idx_stats_scan ON stats(scan_id);",
+        )
+        .context("creating history schema")?;
+        Ok(Self { conn })
+    }
+
+    /// Store `rows` as the scan of `source` taken at `scanned_at`, in one
+    /// transaction.
+    pub fn record<'a>(
+        &mut self,
+        source: &str,
+        scanned_at: i64,
+        rows: impl IntoIterator<Item = HistoryRow<'a>>,
+    ) -> Result<ScanRecord> {
+        let tx = self.conn.transaction()?;
+        let old: Option<i64> = tx
+            .query_row(
+                "SELECT id FROM scans WHERE source = ?1 AND scanned_at = ?2",
+                params![source, scanned_at],
+                |r| r.get(0),
+            )
+            .optional()?;
+        if let Some(id) = old {
+            tx.execute("DELETE FROM stats WHERE scan_id = ?1", [id])?;
+            tx.execute("DELETE FROM scans WHERE id = ?1", [id])?;
+        }
+        tx.execute(
+            "INSERT INTO scans (scanned_at, source, rows) VALUES (?1, ?2, 0)",
+            params![scanned_at, source],
+        )?;
+        let scan_id = tx.last_insert_rowid();
+        let mut count = 0u64;
+        {
+            let mut path_ids: HashMap<String, i64> = HashMap::new();
+            let mut user_ids: HashMap<String, i64> = HashMap::new();
+            let mut ins_path = tx.prepare(
+                "INSERT INTO paths (full_path) VALUES (?1)
+                 ON CONFLICT (full_path) DO UPDATE SET full_path = excluded.full_path
+                 RETURNING id",
+            )?;
+            let mut ins_user = tx.prepare(
+                "INSERT INTO users (name) VALUES (?1)
+                 ON CONFLICT (name) DO UPDATE SET name = excluded.name
+                 RETURNING id",
+            )?;
+            let mut ins_stat = tx.prepare(
+                "INSERT INTO stats VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            )?;
+            for row in rows {
+                let path_id = match path_ids.get(row.path.as_ref()) {
+                    Some(&id) => id,
+                    None => {
+                        let id: i64 = ins_path.query_row([&row.path], |r| r.get(0))?;
+                        path_ids.insert(row.path.into_owned(), id);
+                        id
+                    }
+                };
+                let user_id = match user_ids.get(row.user.as_ref()) {
+                    Some(&id) => id,
+                    None => {
+                        let id: i64 = ins_user.query_row([&row.user], |r| r.get(0))?;
+                        user_ids.insert(row.user.into_owned(), id);
+                        id
+                    }
+                };
+                ins_stat.execute(params![
+                    path_id,
+                    scan_id,
+                    user_id,
+                    row.age,
+                    row.files as i64,
+                    row.size as i64,
+                    row.disk as i64,
+                    row.linked as i64,
+                    row.atime,
+                    row.mtime,
+                ])?;
+                count += 1;
+            }
+        }
+        tx.execute("UPDATE scans SET rows = ?1 WHERE id = ?2", params![count as i64, scan_id])?;
+        tx.commit()?;
+        Ok(ScanRecord {
+            id: scan_id,
+            scanned_at,
+            source: source.to_string(),
+            rows: count,
+        })
+    }
+
+    /// Recorded scans, oldest first.
+    pub fn scans(&self) -> Result<Vec<ScanRecord>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, scanned_at, source, rows FROM scans ORDER BY scanned_at, id")?;
+        let rows = stmt.query_map([], |r| {
+            Ok(ScanRecord {
+                id: r.get(0)?,
+                scanned_at: r.get(1)?,
+                source: r.get(2)?,
+                rows: r.get(3)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<_>>().map_err(Into::into)
+    }
+
+    /// Totals of `path` in every scan that has it, oldest first; only
+    /// `user`'s files when given.
+    pub fn series(&self, path: &str, user: Option<&str>) -> Result<Vec<Point>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT sc.scanned_at, SUM(s.file_count), SUM(s.file_size), SUM(s.disk_bytes)
+             FROM   stats s
+             JOIN   paths p  ON p.id  = s.path_id
+             JOIN   users u  ON u.id  = s.user_id
+             JOIN   scans sc ON sc.id = s.scan_id
+             WHERE  p.full_path = ?1 AND (?2 IS NULL OR u.name = ?2)
+             GROUP BY sc.id
+             ORDER BY sc.scanned_at, sc.id",
+        )?;
+        let rows = stmt.query_map(params![path, user], |r| {
+            Ok(Point {
+                scanned_at: r.get(0)?,
+                files: r.get(1)?,
+                size: r.get(2)?,
+                disk: r.get(3)?,
+            })
+        })?;
+        rows.collect::<rusqlite::Result<_>>().map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row<'a>(path: &'a str, user: &'a str, age: u8, size: u64) -> HistoryRow<'a> {
+        HistoryRow {
+            path: path.into(),
+            user: user.into(),
+            age,
+            files: 1,
+            size,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn records_scans_and_answers_trends() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("history.db");
+        let mut h = History::open(&file).unwrap();
+        h.record("a.csv", 100, [row("/", "alice", 0, 10), row("/", "bob", 2, 5)])
+            .unwrap();
+        let second = h
+            .record("a.csv", 200, [row("/", "alice", 0, 30), row("/x", "alice", 1, 7)])
+            .unwrap();
+        assert_eq!(second.rows, 2);
+
+        let all = h.series("/", None).unwrap();
+        assert_eq!(
+            all.iter().map(|p| (p.scanned_at, p.size)).collect::<Vec<_>>(),
+            vec![(100, 15), (200, 30)]
+        );
+        assert_eq!(h.series("/", Some("bob")).unwrap().len(), 1);
+        assert_eq!(h.series("/x", None).unwrap()[0].files, 1);
+
+        // Same scan again: replaced, not doubled. Reopening keeps the data.
+        drop(h);
+        let mut h = History::open(&file).unwrap();
+        h.record("a.csv", 200, [row("/", "alice", 0, 40)]).unwrap();
+        let scans = h.scans().unwrap();
+        assert_eq!(scans.len(), 2);
+        assert_eq!(scans[1].rows, 1);
+        assert_eq!(h.series("/", None).unwrap()[1].size, 40);
+        assert!(h.series("/x", None).unwrap().is_empty());
+    }
+}
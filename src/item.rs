@@ -17,6 +17,83 @@ pub struct FsItemOut {
     pub modified: i64,
 }
 
+/// Column `/api/files` sorts by (`?sort=path|size|mtime`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Path,
+    Size,
+    Modified,
+}
+
+impl SortKey {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "path" | "name" => Some(Self::Path),
+            "size" => Some(Self::Size),
+            "mtime" | "modified" => Some(Self::Modified),
+            _ => None,
+        }
+    }
+}
+
+/// Sort `items` and keep the page `offset..offset + limit`; returns the
+/// number of items before paging. Ties are broken by path.
+pub fn page_items(
+    items: &mut Vec<FsItemOut>,
+    sort: SortKey,
+    desc: bool,
+    offset: usize,
+    limit: usize,
+) -> usize {
+    items.sort_by(|a, b| {
+        let ord = match sort {
+            SortKey::Path => a.path.cmp(&b.path),
+            SortKey::Size => a.size.cmp(&b.size).then_with(|| a.path.cmp(&b.path)),
+            SortKey::Modified => a.modified.cmp(&b.modified).then_with(|| a.path.cmp(&b.path)),
+        };
+        if desc { ord.reverse() } else { ord }
+    });
+    let total = items.len();
+    items.drain(..offset.min(total));
+    items.truncate(limit);
+    total
+}
+
+/// mtime cutoffs `(recent, old)` of the age buckets: bucket 0 is newer than
+/// `recent`, 2 older than `old`. Must match the AgeCfg defaults used by dusum
+/// (young=60, old=600) — otherwise /api/files?age=2 returns a different set
+/// of files than the aggregated bucket-2 stats shown on /api/folders.
+pub fn age_cutoffs() -> (i64, i64) {
+    use chrono::{Duration, Utc};
+    let now = Utc::now();
+    (
+        (now - Duration::days(60)).timestamp(),
+        (now - Duration::days(600)).timestamp(),
+    )
+}
+
+/// Username for a scan UID, as dusum resolves it: the local passwd entry on
+/// Unix (`UNK` if missing), the account of the owner RID on Windows (the
+/// interactive user for scans without one).
+#[cfg(unix)]
+pub fn owner_of_uid(uid: u32) -> String {
+    username_from_uid(uid)
+}
+
+#[cfg(windows)]
+pub fn owner_of_uid(uid: u32) -> String {
+    if uid == 0 {
+        return windows_owner();
+    }
+    crate::util::account_name_from_rid(uid).unwrap_or_else(|| "UNK".to_string())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn owner_of_uid(_uid: u32) -> String {
+    "UNK".to_string()
+}
+
 #[cfg(unix)]
 fn username_from_uid(uid: u32) -> String {
     unsafe {
@@ -51,7 +128,6 @@ pub fn get_items<P: AsRef<std::path::Path>>(
     usernames: &[String],
     age_filter: Option<u8>,
 ) -> Result<Vec<FsItemOut>> {
-    use chrono::{Duration, Utc};
     use std::collections::HashSet;
     use std::fs;
     use std::os::unix::fs::MetadataExt;
@@ -62,12 +138,7 @@ pub fn get_items<P: AsRef<std::path::Path>>(
         Some(usernames.iter().cloned().collect())
     };
 
-    let now = Utc::now();
-    // Must match the AgeCfg defaults used by dusum (young=60, old=600) —
-    // otherwise /api/files?age=2 returns a different set of files than the
-    // aggregated bucket-2 stats shown on /api/folders for the same folder.
-    let cutoff_recent = (now - Duration::days(60)).timestamp();
-    let cutoff_old = (now - Duration::days(600)).timestamp();
+    let (cutoff_recent, cutoff_old) = age_cutoffs();
 
     let mut out = Vec::new();
     let mut uid_cache: HashMap<u32, String> = HashMap::new();
@@ -91,10 +162,10 @@ pub fn get_items<P: AsRef<std::path::Path>>(
         }
 
         let owner = cached_username(md.uid(), &mut uid_cache);
-        if let Some(ref allow) = filter {
-            if !allow.contains(&owner) {
-                continue;
-            }
+        if let Some(ref allow) = filter
+            && !allow.contains(&owner)
+        {
+            continue;
         }
 
         let atime = md.atime();
@@ -140,7 +211,6 @@ pub fn get_items<P: AsRef<std::path::Path>>(
     usernames: &[String],
     age_filter: Option<u8>,
 ) -> Result<Vec<FsItemOut>> {
-    use chrono::{Duration, Utc};
     use std::collections::HashSet;
     use std::fs;
     use std::time::SystemTime;
@@ -151,12 +221,7 @@ pub fn get_items<P: AsRef<std::path::Path>>(
         Some(usernames.iter().cloned().collect())
     };
 
-    let now = Utc::now();
-    // Must match the AgeCfg defaults used by dusum (young=60, old=600) —
-    // otherwise /api/files?age=2 returns a different set of files than the
-    // aggregated bucket-2 stats shown on /api/folders for the same folder.
-    let cutoff_recent = (now - Duration::days(60)).timestamp();
-    let cutoff_old = (now - Duration::days(600)).timestamp();
+    let (cutoff_recent, cutoff_old) = age_cutoffs();
 
     let to_unix = |t: SystemTime| -> i64 {
         t.duration_since(SystemTime::UNIX_EPOCH)
@@ -267,6 +332,36 @@ mod tests {
         assert!(items[0].path.ends_with("file.txt"));
     }
 
+    fn item(path: &str, size: u64, modified: i64) -> FsItemOut {
+        FsItemOut {
+            path: path.into(),
+            owner: "u".into(),
+            size,
+            accessed: 0,
+            modified,
+        }
+    }
+
+    #[test]
+    fn test_page_items_sorts_and_slices() {
+        let all = vec![item("/a", 30, 3), item("/b", 10, 1), item("/c", 20, 2)];
+        let paths = |v: &[FsItemOut]| v.iter().map(|i| i.path.clone()).collect::<Vec<_>>();
+
+        let mut v = all.clone();
+        assert_eq!(page_items(&mut v, SortKey::Size, true, 0, 2), 3);
+        assert_eq!(paths(&v), ["/a", "/c"]);
+
+        let mut v = all.clone();
+        assert_eq!(page_items(&mut v, SortKey::Modified, false, 1, 10), 3);
+        assert_eq!(paths(&v), ["/c", "/a"]);
+
+        let mut v = all;
+        page_items(&mut v, SortKey::Path, false, 5, 10);
+        assert!(v.is_empty());
+        assert_eq!(SortKey::parse("MTIME"), Some(SortKey::Modified));
+        assert_eq!(SortKey::parse("owner"), None);
+    }
+
     #[cfg(windows)]
     #[test]
     fn test_get_items_windows_lists_files_and_skips_dirs() {
@@ -4,5 +4,15 @@ pub mod auth;
 pub mod storage;
 pub mod db;
 pub mod item;
+pub mod fileindex;
+pub mod history;
+pub mod manifest;
 pub mod query;
-pub mod analytic;
\ No newline at end of file
+pub mod analytic;
+pub mod dashboard;
+pub mod enrich;
+pub mod project;
+pub mod userinfo;
+pub mod owners;
+pub mod acl;
+pub mod tiers;pub mod scanargs;
@@ -0,0 +1,165 @@
+// rs/src/manifest.rs
+//
+// `<scan>.meta.json`: what duscan knows about an output it wrote. It is
+// written with `complete: false` before the merge starts replacing the
+// output and rewritten with `complete: true` once the output is whole, so a
+// crash or a full disk mid-merge leaves a manifest that says so. dusum
+// checks the row count and the flag before summarizing, so a truncated scan
+// never turns into a report that the filesystem shrank.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScanManifest {
+    /// `csv` or `zst`
+    pub format: String,
+    /// Data rows in the output (header excluded)
+    pub rows: u64,
+    /// The output was fully written
+    pub complete: bool,
+    /// Entries that could not be read; their rows are missing
+    pub errors: u64,
+    /// Directories not walked because the scan hit its `--time-limit`; the
+    /// output is whole but the tree is not
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub unvisited: u64,
+    /// Set when the walk stopped this many levels below the roots (`duscan
+    /// --max-depth`): deeper directories are not in the output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+    pub roots: Vec<String>,
+    /// Set when the PATH column is relative to this root (`duscan
+    /// --relative`): `.` is the root, `./a` an entry below it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative_to: Option<String>,
+    pub host: String,
+    /// Epoch seconds
+    pub started_at: i64,
+    pub finished_at: i64,
+    pub version: String,
+    /// Set when only a sample of the tree was walked (`duscan --sample`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sample: Option<SampleInfo>,
+}
+
+/// Which directories a sampled scan walked. Below each stratum directory
+/// only the `sampled` subdirectories of its `dirs` were scanned (in full);
+/// everything above the strata was scanned as usual.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SampleInfo {
+    /// Requested fraction, 0 < rate <= 1
+    pub rate: f64,
+    /// Levels below each root where directories were sampled
+    pub depth: usize,
+    pub strata: Vec<SampleStratum>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SampleStratum {
+    /// As written in the scan's PATH column
+    pub path: String,
+    /// Subdirectories it has
+    pub dirs: u64,
+    /// Names of the subdirectories that were scanned
+    pub sampled: Vec<String>,
+}
+
+impl SampleStratum {
+    /// Factor scaling a sampled subdirectory's totals up to the stratum.
+    pub fn weight(&self) -> f64 {
+        if self.sampled.is_empty() {
+            1.0
+        } else {
+            self.dirs as f64 / self.sampled.len() as f64
+        }
+    }
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+/// `<scan>.meta.json` next to the scan.
+pub fn manifest_path(scan: &Path) -> PathBuf {
+    let mut name = scan.file_name().unwrap_or_default().to_os_string();
+    name.push(".meta.json");
+    scan.with_file_name(name)
+}
+
+impl ScanManifest {
+    pub fn write(&self, scan: &Path) -> Result<PathBuf> {
+        let path = manifest_path(scan);
+        let json = serde_json::to_vec_pretty(self)?;
+        std::fs::write(&path, json).with_context(|| format!("writing {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// The manifest of `scan`; `None` when there is none (older duscan,
+    /// hand-made input).
+    pub fn read(scan: &Path) -> Result<Option<Self>> {
+        let path = manifest_path(scan);
+        let bytes = match std::fs::read(&path) {
+            Ok(b) => b,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        };
+        serde_json::from_slice(&bytes)
+            .map(Some)
+            .with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Problems that make `rows_read` data rows of the scan untrustworthy.
+    pub fn check(&self, rows_read: u64) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !self.complete {
+            problems.push("the scan did not finish writing its output".to_string());
+        }
+        if self.unvisited > 0 {
+            problems.push(format!(
+                "the scan stopped at its time limit with {} directories not walked",
+                self.unvisited
+            ));
+        }
+        if self.rows != rows_read {
+            problems.push(format!(
+                "the manifest lists {} rows but the input has {}",
+                self.rows, rows_read
+            ));
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_checks_rows() {
+        let tmp = tempfile::tempdir().unwrap();
+        let scan = tmp.path().join("a.csv");
+        assert_eq!(ScanManifest::read(&scan).unwrap(), None);
+
+        let m = ScanManifest {
+            format: "csv".into(),
+            rows: 3,
+            complete: true,
+            ..Default::default()
+        };
+        let written = m.write(&scan).unwrap();
+        assert_eq!(written, tmp.path().join("a.csv.meta.json"));
+        let back = ScanManifest::read(&scan).unwrap().unwrap();
+        assert_eq!(back, m);
+        assert!(back.check(3).is_empty());
+        assert_eq!(back.check(2).len(), 1);
+
+        let partial = ScanManifest { complete: false, ..m };
+        assert_eq!(partial.check(2).len(), 2);
+        let cut = ScanManifest { unvisited: 4, ..partial };
+        assert!(cut.check(3)[1].contains("4 directories not walked"));
+
+        std::fs::write(manifest_path(&scan), "{").unwrap();
+        assert!(ScanManifest::read(&scan).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+// rs/src/owners.rs
+//
+// Who to contact about a folder, by path prefix, loaded from a CSV with a
+// header row. `prefix` is required; the other columns are optional and any
+// unknown column is ignored:
+//
+//   prefix,team,email,name
+//   /projects/genomics,Genomics,genomics-it@example.org,
+//   /projects/genomics/archive,,archive@example.org,Dana Ruiz
+//
+// A folder gets the contact of the longest prefix covering it, matched on
+// whole path components (`/projects/gen` does not cover `/projects/genomics`),
+// so a subtree can name its own owner under a team-wide default. Like the
+// users file, it is expected to be exported by a periodic job; duapi reads
+// it once at startup.
+use anyhow::{bail, Context, Result};
+use csv::{ReaderBuilder, Trim};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::query::canonical_key;
+use crate::util::replace_path_prefix;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct OwnerContact {
+    /// The configured prefix this contact comes from.
+    pub prefix: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct OwnerDirectory {
+    /// Longest prefix first.
+    entries: Vec<OwnerContact>,
+}
+
+impl OwnerDirectory {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading owners file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("in {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(true)
+            .flexible(true)
+            .trim(Trim::All)
+            .comment(Some(b'#'))
+            .from_reader(text.as_bytes());
+        let headers = rdr.headers()?.clone();
+        let col = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+        let Some(prefix_col) = col("prefix") else {
+            bail!("owners file needs a 'prefix' column");
+        };
+        let (team_col, email_col, name_col) = (col("team"), col("email"), col("name"));
+
+        let mut entries = Vec::new();
+        for rec in rdr.records() {
+            let rec = rec?;
+            let field = |c: Option<usize>| {
+                c.and_then(|c| rec.get(c))
+                    .filter(|v| !v.is_empty())
+                    .map(str::to_string)
+            };
+            let Some(prefix) = field(Some(prefix_col)) else {
+                continue;
+            };
+            let contact = OwnerContact {
+                prefix,
+                team: field(team_col),
+                email: field(email_col),
+                name: field(name_col),
+            };
+            if contact.team.is_some() || contact.email.is_some() || contact.name.is_some() {
+                entries.push(contact);
+            }
+        }
+        entries.sort_by_key(|e| std::cmp::Reverse(e.prefix.len()));
+        Ok(Self { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Contact for the folder at `path`; `None` when no prefix covers it.
+    pub fn lookup(&self, path: &str, case_insensitive: bool) -> Option<&OwnerContact> {
+        let path = canonical_key(path, case_insensitive);
+        self.entries.iter().find(|e| {
+            let prefix = canonical_key(&e.prefix, case_insensitive);
+            replace_path_prefix(path.as_bytes(), prefix.as_bytes(), b"").is_some()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_whole_component_prefix_wins() {
+        let dir = OwnerDirectory::parse(
+            "Prefix,Team,Email,Name,Extra\n\
+             # comment\n\
+             /projects/genomics,Genomics,genomics-it@example.org,,x\n\
+             /projects/genomics/archive,,archive@example.org,Dana Ruiz\n\
+             /projects/empty,,,\n\
+             ,Nobody,n@example.org\n",
+        )
+        .unwrap();
+        assert_eq!(dir.len(), 2);
+
+        let top = dir.lookup("/projects/genomics/runs", false).unwrap();
+        assert_eq!(top.team.as_deref(), Some("Genomics"));
+        assert_eq!(top.name, None);
+        let sub = dir.lookup("/projects/genomics/archive/2019", false).unwrap();
+        assert_eq!(sub.email.as_deref(), Some("archive@example.org"));
+        assert_eq!(dir.lookup("/projects/genomics", false).unwrap().prefix, "/projects/genomics");
+        assert!(dir.lookup("/projects/genomics2", false).is_none());
+        assert!(dir.lookup("/projects", false).is_none());
+
+        assert!(dir.lookup("/Projects/Genomics/x", false).is_none());
+        assert!(dir.lookup("/Projects/Genomics/x", true).is_some());
+        assert!(OwnerDirectory::parse("team,email\nA,B\n").is_err());
+    }
+}
@@ -0,0 +1,265 @@
+// rs/src/project.rs
+//
+// Business-level "project" grouping derived from folder paths. A rules file
+// maps path regexes to project names, one rule per line:
+//
+//   # PROJECT = REGEX          (first matching rule wins)
+//   apollo    = ^/proj/apollo(/|$)
+//   $1        = ^/data/groups/([a-z]+)-\d{4}(/|$)
+//
+// The name may reference capture groups (`$1`, `${name}`). Every folder in
+// the DB is tested; a matching folder whose ancestors do not match is a
+// project root, and since dusum rows already roll up all descendants, summing
+// the roots of a project gives its usage without double counting.
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use crate::acl::is_hidden;
+use crate::db::{Age, DbPool};
+use crate::query::canonical_key;
+use crate::util::dusum_parent;
+
+#[derive(Debug, Default)]
+pub struct ProjectRules {
+    rules: Vec<(Regex, String)>,
+}
+
+impl ProjectRules {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rules = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((name, pattern)) = line.split_once('=') else {
+                bail!("line {}: expected PROJECT = REGEX, got '{line}'", i + 1);
+            };
+            let (name, pattern) = (name.trim(), pattern.trim());
+            if name.is_empty() || pattern.is_empty() {
+                bail!("line {}: expected PROJECT = REGEX, got '{line}'", i + 1);
+            }
+            let re = Regex::new(pattern)
+                .with_context(|| format!("line {}: invalid regex '{pattern}'", i + 1))?;
+            rules.push((re, name.to_string()));
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading project rules {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("in {}", path.display()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Project of `path` by the first matching rule, with capture groups
+    /// expanded into the name. An expansion that comes out empty is no match.
+    pub fn project_of(&self, path: &str) -> Option<String> {
+        self.rules.iter().find_map(|(re, name)| {
+            let caps = re.captures(path)?;
+            let mut out = String::new();
+            caps.expand(name, &mut out);
+            (!out.is_empty()).then_some(out)
+        })
+    }
+}
+
+/// A topmost folder assigned to a project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectRoot {
+    pub path_id: i64,
+    pub path: String,
+    pub project: String,
+}
+
+/// Every project root in the DB, ordered by path.
+pub fn project_roots(pool: &DbPool, rules: &ProjectRules) -> Result<Vec<ProjectRoot>> {
+    let conn = pool.get().context("acquiring connection")?;
+    let mut stmt = conn.prepare("SELECT id, full_path FROM paths")?;
+    let rows = stmt.query_map([], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)))?;
+    let mut matched: Vec<ProjectRoot> = Vec::new();
+    for row in rows {
+        let (path_id, path) = row?;
+        if let Some(project) = rules.project_of(&path) {
+            matched.push(ProjectRoot {
+                path_id,
+                path,
+                project,
+            });
+        }
+    }
+    let matched_paths: HashSet<&str> = matched.iter().map(|m| m.path.as_str()).collect();
+    let nested: HashSet<String> = matched
+        .iter()
+        .filter(|m| ancestors(&m.path).any(|a| matched_paths.contains(a.as_str())))
+        .map(|m| m.path.clone())
+        .collect();
+    matched.retain(|m| !nested.contains(&m.path));
+    matched.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(matched)
+}
+
+fn ancestors(path: &str) -> impl Iterator<Item = String> {
+    std::iter::successors(dusum_parent(path), |p| dusum_parent(p))
+}
+
+/// True when `path` is `dir` or lies below it. The empty `dir` (synthetic
+/// root) contains everything.
+fn is_within(path: &str, dir: &str, case_insensitive: bool) -> bool {
+    if dir.is_empty() {
+        return true;
+    }
+    let dir = canonical_key(dir, case_insensitive);
+    std::iter::once(path.to_string())
+        .chain(ancestors(path))
+        .any(|p| canonical_key(&p, case_insensitive) == dir)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectOut {
+    pub project: String,
+    /// Project roots summed into this entry.
+    pub paths: Vec<String>,
+    pub users: HashMap<String, HashMap<String, Age>>,
+}
+
+/// Usage per project for the roots within `dir`, with the same user and age
+/// filters as `db::list_children`. Counts and sizes are summed across roots;
+/// atime/mtime take the maximum, oldest atime/mtime the earliest known. Roots at or below a `hidden` prefix (see
+/// `acl::is_hidden`) are skipped. Projects with no matching rows are omitted.
+pub fn list_projects(
+    pool: &DbPool,
+    roots: &[ProjectRoot],
+    dir: &str,
+    user_filter: &[String],
+    age_filter: Option<u8>,
+    hidden: &[String],
+    case_insensitive: bool,
+) -> Result<Vec<ProjectOut>> {
+    let conn = pool.get().context("acquiring connection")?;
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT u.name, s.age, s.file_count, s.file_size, s.disk_bytes, s.linked_size,
+                s.atime, s.mtime, {}
+         FROM   stats s JOIN users u ON u.id = s.user_id
+         WHERE  s.path_id = ?1",
+        crate::db::oldest_cols(&conn, "stats", false)?
+    ))?;
+    let users: HashSet<&str> = user_filter.iter().map(String::as_str).collect();
+
+    let mut grouped: BTreeMap<String, ProjectOut> = BTreeMap::new();
+    let visible = |r: &&ProjectRoot| {
+        is_within(&r.path, dir, case_insensitive) && !is_hidden(&r.path, hidden, case_insensitive)
+    };
+    for root in roots.iter().filter(visible) {
+        let rows = stmt.query_map([root.path_id], |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, u8>(1)?,
+                Age {
+                    count: r.get(2)?,
+                    size: r.get(3)?,
+                    disk: r.get(4)?,
+                    linked: r.get(5)?,
+                    atime: r.get(6)?,
+                    mtime: r.get(7)?,
+                    oldest_atime: r.get(8)?,
+                    oldest_mtime: r.get(9)?,
+                },
+            ))
+        })?;
+        for row in rows {
+            let (user, age, a) = row?;
+            if (!users.is_empty() && !users.contains(user.as_str()))
+                || age_filter.is_some_and(|f| f != age)
+            {
+                continue;
+            }
+            let out = grouped
+                .entry(root.project.clone())
+                .or_insert_with(|| ProjectOut {
+                    project: root.project.clone(),
+                    paths: Vec::new(),
+                    users: HashMap::new(),
+                });
+            if out.paths.last() != Some(&root.path) {
+                out.paths.push(root.path.clone());
+            }
+            let slot = out
+                .users
+                .entry(user)
+                .or_default()
+                .entry(age.to_string())
+                .or_default();
+            slot.add(&a);
+        }
+    }
+    Ok(grouped.into_values().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{open_pool, test_support};
+
+    #[test]
+    fn parse_rules_and_expand_captures() {
+        let rules = ProjectRules::parse(
+            "# comment\n\
+             apollo = ^/proj/apollo(/|$)\n\
+             \n\
+             $1 = ^/data/groups/([a-z]+)-\\d{4}(/|$)\n",
+        )
+        .unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules.project_of("/proj/apollo/src").as_deref(), Some("apollo"));
+        assert_eq!(rules.project_of("/data/groups/gemini-2020").as_deref(), Some("gemini"));
+        assert_eq!(rules.project_of("/proj/apollo2"), None);
+        assert!(ProjectRules::parse("no equals sign").is_err());
+        assert!(ProjectRules::parse("x = (").is_err());
+    }
+
+    #[test]
+    fn roots_are_topmost_matches_and_totals_follow_filters() {
+        // Fixture: `/` and `/docs`; both match, only `/` is a root.
+        let db = test_support::build_test_db();
+        let pool = open_pool(&db.path).unwrap();
+        let rules = ProjectRules::parse("everything = ^/").unwrap();
+        let roots = project_roots(&pool, &rules).unwrap();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(roots[0].path, "/");
+
+        let rules = ProjectRules::parse("docs = ^/docs$").unwrap();
+        let roots = project_roots(&pool, &rules).unwrap();
+        let all = list_projects(&pool, &roots, "", &[], None, &[], false).unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].project, "docs");
+        assert_eq!(all[0].paths, ["/docs"]);
+
+        let under_root = list_projects(&pool, &roots, "/", &[], None, &[], false).unwrap();
+        assert_eq!(under_root.len(), 1);
+        let under_docs = list_projects(&pool, &roots, "/docs", &[], None, &[], false).unwrap();
+        assert_eq!(under_docs.len(), 1);
+        let elsewhere = list_projects(&pool, &roots, "/var", &[], None, &[], false).unwrap();
+        assert!(elsewhere.is_empty());
+        let hidden = ["/docs".to_string()];
+        let acl = list_projects(&pool, &roots, "", &[], None, &hidden, false).unwrap();
+        assert!(acl.is_empty());
+
+        let alice_old =
+            list_projects(&pool, &roots, "", &["alice".into()], Some(2), &[], false).unwrap();
+        let users = &alice_old[0].users;
+        assert!(users.contains_key("alice") && !users.contains_key("bob"));
+        assert!(users["alice"].keys().all(|k| k == "2"));
+    }
+}
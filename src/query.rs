@@ -5,11 +5,38 @@
 // native OS form the SQLite index was built with (trailing backslash on
 // drive roots, no trailing separator elsewhere, reject `..` traversal).
 
+use std::borrow::Cow;
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+use crate::util::{separator, split_anchor, Anchor};
+
+/// Unicode NFC form of `path`. macOS and some SMB servers hand out decomposed
+/// (NFD) names, so the same folder can arrive as two different byte strings.
+pub fn nfc(path: &str) -> Cow<'_, str> {
+    match is_nfc_quick(path.chars()) {
+        IsNormalized::Yes => Cow::Borrowed(path),
+        _ => Cow::Owned(path.nfc().collect()),
+    }
+}
+
+/// Identity of a path in a case-insensitive index: NFC, and lower-cased when
+/// `case_insensitive` is set. Two paths with the same key are the same folder
+/// (`C:\Data` and `c:\data` on a Windows/SMB source).
+pub fn canonical_key(path: &str, case_insensitive: bool) -> String {
+    let p = nfc(path);
+    if case_insensitive {
+        p.to_lowercase()
+    } else {
+        p.into_owned()
+    }
+}
+
 /// Normalize a user-supplied path to the native OS form used as the index key.
 ///
 /// The path form is preserved: `/var/log` (Unix), `C:\Users\San` (Windows),
 /// `\\server\share\dir` (UNC). The separator detected in the input is the
-/// separator used in the output.
+/// separator used in the output, and the path is split the way the index
+/// was built (see `util::split_anchor`).
 ///
 /// Returns `None` if the input contains a NUL byte or a literal `..` segment.
 pub fn normalize_path(input: &str) -> Option<String> {
@@ -20,68 +47,24 @@ pub fn normalize_path(input: &str) -> Option<String> {
     if trimmed.is_empty() {
         return Some(String::new());
     }
-    let bytes = trimmed.as_bytes();
-
-    let is_unc = trimmed.starts_with(r"\\");
-    let has_drive_prefix = bytes.len() >= 2
-        && bytes[0].is_ascii_alphabetic()
-        && bytes[1] == b':';
-    let use_backslash = is_unc || has_drive_prefix || trimmed.contains('\\');
-    let sep: char = if use_backslash { '\\' } else { '/' };
-    let is_unix_absolute = !use_backslash && trimmed.starts_with('/');
-
-    if trimmed == "/" {
-        return Some("/".to_string());
-    }
-    if has_drive_prefix && bytes.len() <= 3 {
-        return Some(format!("{}:\\", bytes[0] as char));
-    }
-
-    let body: &str = if is_unc {
-        &trimmed[2..]
-    } else if is_unix_absolute {
-        &trimmed[1..]
-    } else if has_drive_prefix {
-        let start = if bytes.len() >= 3 && bytes[2] == b'\\' { 3 } else { 2 };
-        &trimmed[start..]
-    } else {
-        trimmed
-    };
+    let sep = separator(trimmed.as_bytes()) as char;
+    let (anchor, rest) = split_anchor(trimmed.as_bytes());
+    let rest = &trimmed[trimmed.len() - rest.len()..];
 
     let mut out: Vec<&str> = Vec::new();
-    for seg in body.split(sep) {
+    for (i, seg) in rest.split(sep).enumerate() {
         match seg {
+            // `./a` is how `duscan --relative` roots its paths
+            "." if i == 0 && anchor == Anchor::Relative => out.push(seg),
             "" | "." => continue,
             ".." => return None,
             s => out.push(s),
         }
     }
 
-    Some(match (is_unc, has_drive_prefix, is_unix_absolute) {
-        (true, _, _) => {
-            if out.is_empty() {
-                r"\\".to_string()
-            } else {
-                format!("\\\\{}", out.join("\\"))
-            }
-        }
-        (_, true, _) => {
-            let drive = format!("{}:\\", bytes[0] as char);
-            if out.is_empty() {
-                drive
-            } else {
-                format!("{}{}", drive, out.join("\\"))
-            }
-        }
-        (_, _, true) => {
-            if out.is_empty() {
-                "/".to_string()
-            } else {
-                format!("/{}", out.join("/"))
-            }
-        }
-        _ => out.join(&sep.to_string()),
-    })
+    let mut path: String = anchor.prefix().into_iter().map(char::from).collect();
+    path.push_str(&out.join(&sep.to_string()));
+    Some(path)
 }
 
 #[cfg(test)]
@@ -117,6 +100,8 @@ mod tests {
         assert_eq!(normalize_path("/var//log/").as_deref(), Some("/var/log"));
         assert_eq!(normalize_path("/var/./log").as_deref(), Some("/var/log"));
         assert_eq!(normalize_path("  /var/log  ").as_deref(), Some("/var/log"));
+        assert_eq!(normalize_path("./a//b/").as_deref(), Some("./a/b"));
+        assert_eq!(normalize_path("a/./b").as_deref(), Some("a/b"));
     }
 
     #[test]
@@ -125,4 +110,16 @@ mod tests {
         assert!(normalize_path("..").is_none());
         assert!(normalize_path("/var/log\0/etc").is_none());
     }
+
+    #[test]
+    fn canonical_key_folds_case_and_composes() {
+        // "e" + combining acute (NFD) vs precomposed "é" (NFC).
+        let nfd = "/Cafe\u{301}";
+        assert_eq!(nfc(nfd), "/Caf\u{e9}");
+        assert!(matches!(nfc("/plain"), Cow::Borrowed(_)));
+        assert_eq!(canonical_key(nfd, false), "/Caf\u{e9}");
+        assert_eq!(canonical_key(nfd, true), "/caf\u{e9}");
+        assert_eq!(canonical_key("C:\\Data", true), canonical_key("c:\\DATA", true));
+        assert_ne!(canonical_key("/Data", false), canonical_key("/data", false));
+    }
 }
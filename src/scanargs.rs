@@ -0,0 +1,118 @@
+// rs/src/scanargs.rs
+//
+// The duscan flags a remote scan request may carry (duscand `POST /scans`,
+// duapi `POST /api/admin/scans`). It is an allowlist: anything not listed
+// is refused, since other flags either move or silence the output, which
+// duscand sets itself, or make duscan read, load or run something on the
+// agent host (`--enrich lib:`, `--since`, `--previous`, `--snapshot`).
+
+/// Long name, short letter and whether the flag takes a value.
+const ALLOWED: &[(&str, Option<char>, bool)] = &[
+    ("workers", Some('w'), true),
+    ("min-workers", None, true),
+    ("max-workers", None, true),
+    ("skip", Some('s'), true),
+    ("exclude", None, true),
+    ("include", None, true),
+    ("alias", None, true),
+    ("relative", None, false),
+    ("types", None, true),
+    ("exclude-fstype", None, true),
+    ("one-file-system", Some('x'), false),
+    ("uid", None, true),
+    ("gid", None, true),
+    ("sample", None, true),
+    ("sample-depth", None, true),
+    ("no-atime", None, false),
+    ("compress-shards", None, false),
+    ("min-free", None, true),
+    ("min-free-wait", None, true),
+    ("time-limit", None, true),
+    ("max-depth", None, true),
+    ("mmap-merge", None, false),
+    ("files-hint", None, true),
+    ("verbose", Some('v'), false),
+    ("log-level", None, true),
+];
+
+/// Why a scan request with these folders and args is refused, if it is:
+/// a folder that duscan would take for a flag, or an argument `refused`
+/// names.
+pub fn check(folders: &[String], args: &[String]) -> Result<(), String> {
+    if let Some(f) = folders.iter().find(|f| f.starts_with('-')) {
+        return Err(format!("folder {f} starts with '-'"));
+    }
+    match refused(args) {
+        Some(arg) => Err(format!("{arg} is not allowed in scan args")),
+        None => Ok(()),
+    }
+}
+
+/// First argument of `args` that is not an allowed flag (or a value of
+/// one), if any. Values go as `--flag=V`, `--flag V`, `-wV` or `-w V`;
+/// short flags without values can be grouped (`-xv`). Bare words are
+/// refused too: folders go in their own field.
+pub fn refused(args: &[String]) -> Option<&str> {
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.split_once('=') {
+                Some((n, _)) => (n, true),
+                None => (long, false),
+            };
+            let Some(&(_, _, takes_value)) = ALLOWED.iter().find(|(n, _, _)| *n == name) else {
+                return Some(arg);
+            };
+            if takes_value && !inline && it.next().is_none() || !takes_value && inline {
+                return Some(arg);
+            }
+        } else if let Some(short) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+            for (i, c) in short.char_indices() {
+                let Some(&(_, _, takes_value)) = ALLOWED.iter().find(|(_, s, _)| *s == Some(c))
+                else {
+                    return Some(arg);
+                };
+                if takes_value {
+                    if i + c.len_utf8() == short.len() && it.next().is_none() {
+                        return Some(arg);
+                    }
+                    break;
+                }
+            }
+        } else {
+            return Some(arg);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(args: &[&str]) -> Option<String> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        refused(&args).map(str::to_string)
+    }
+
+    #[test]
+    fn allows_listed_flags_and_refuses_the_rest() {
+        assert_eq!(check(&["--workers", "8", "-x", "--exclude=*.tmp", "-vv", "-w16"]), None);
+        assert_eq!(check(&["--max-depth", "2", "-xw", "4", "--uid", "1000"]), None);
+        for bad in [
+            &["--enrich", "lib:/tmp/x.so"][..],
+            &["--since", "/etc/shadow"],
+            &["--snapshot"],
+            &["-o", "/tmp/out.csv"],
+            &["-qv"],
+            &["--output=/tmp/x"],
+            &["--workers"],
+            &["--relative=yes"],
+            &["/other/folder"],
+        ] {
+            assert_eq!(check(bad).as_deref(), Some(bad[0]), "{bad:?}");
+        }
+        let dash = super::check(&["-o".to_string()], &[]).unwrap_err();
+        assert_eq!(dash, "folder -o starts with '-'");
+    }
+}
@@ -0,0 +1,185 @@
+// rs/src/tiers.rs
+//
+// Storage tier labels by path prefix, loaded from a CSV with a header row
+// and `prefix` and `tier` columns; any other column is ignored:
+//
+//   prefix,tier
+//   /scratch,nvme
+//   /projects,ssd
+//   /projects/archive,archive
+//
+// A folder is on the tier of the longest prefix covering it, matched on
+// whole path components as for owner contacts, so a subtree can move to
+// another tier under a default. duapi labels /api/folders entries with it
+// and sums usage per tier for /api/summary. Like the other path files it is
+// read once at startup.
+use anyhow::{bail, Context, Result};
+use csv::{ReaderBuilder, Trim};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::db::{folder_totals, DbPool};
+use crate::query::canonical_key;
+use crate::util::replace_path_prefix;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TierRule {
+    pub prefix: String,
+    pub tier: String,
+}
+
+/// Usage on one tier.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TierTotal {
+    pub tier: String,
+    pub count: u64,
+    pub size: u64,
+    pub disk: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct TierMap {
+    /// Longest prefix first.
+    rules: Vec<TierRule>,
+}
+
+fn covers(path: &str, prefix: &str, case_insensitive: bool) -> bool {
+    let path = canonical_key(path, case_insensitive);
+    let prefix = canonical_key(prefix, case_insensitive);
+    replace_path_prefix(path.as_bytes(), prefix.as_bytes(), b"").is_some()
+}
+
+impl TierMap {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading tiers file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("in {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(true)
+            .flexible(true)
+            .trim(Trim::All)
+            .comment(Some(b'#'))
+            .from_reader(text.as_bytes());
+        let headers = rdr.headers()?.clone();
+        let col = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+        let (Some(prefix_col), Some(tier_col)) = (col("prefix"), col("tier")) else {
+            bail!("tiers file needs 'prefix' and 'tier' columns");
+        };
+
+        let mut rules = Vec::new();
+        for rec in rdr.records() {
+            let rec = rec?;
+            let field = |c: usize| rec.get(c).filter(|v| !v.is_empty()).map(str::to_string);
+            if let (Some(prefix), Some(tier)) = (field(prefix_col), field(tier_col)) {
+                rules.push(TierRule { prefix, tier });
+            }
+        }
+        rules.sort_by_key(|r| std::cmp::Reverse(r.prefix.len()));
+        Ok(Self { rules })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Tier of the folder at `path`; `None` when no prefix covers it.
+    pub fn lookup(&self, path: &str, case_insensitive: bool) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|r| covers(path, &r.prefix, case_insensitive))
+            .map(|r| r.tier.as_str())
+    }
+
+    /// Usage per tier, for `user` or for all users, largest disk first.
+    /// Each prefix contributes its folder's totals minus those of the
+    /// prefixes directly below it, which count for their own tier. Usage
+    /// no prefix covers is in no tier; tiers with nothing on them are left
+    /// out.
+    pub fn totals(
+        &self,
+        pool: &DbPool,
+        user: Option<&str>,
+        case_insensitive: bool,
+    ) -> Result<Vec<TierTotal>> {
+        let mut own = Vec::with_capacity(self.rules.len());
+        for r in &self.rules {
+            let ages = folder_totals(pool, &r.prefix, user, case_insensitive)?.unwrap_or_default();
+            own.push(ages.values().fold((0u64, 0u64, 0u64), |t, a| {
+                (t.0 + a.count, t.1 + a.size, t.2 + a.disk)
+            }));
+        }
+        // Longest first, so the first other rule covering a prefix is its
+        // nearest enclosing one.
+        let mut net = own.clone();
+        for (i, r) in self.rules.iter().enumerate() {
+            let parent = self.rules.iter().enumerate().skip(i + 1).find(|(_, p)| {
+                p.prefix.len() < r.prefix.len() && covers(&r.prefix, &p.prefix, case_insensitive)
+            });
+            if let Some((j, _)) = parent {
+                let n = &mut net[j];
+                *n = (
+                    n.0.saturating_sub(own[i].0),
+                    n.1.saturating_sub(own[i].1),
+                    n.2.saturating_sub(own[i].2),
+                );
+            }
+        }
+
+        let mut by_tier: BTreeMap<&str, TierTotal> = BTreeMap::new();
+        for (r, (count, size, disk)) in self.rules.iter().zip(net) {
+            let t = by_tier.entry(&r.tier).or_insert_with(|| TierTotal {
+                tier: r.tier.clone(),
+                ..TierTotal::default()
+            });
+            t.count += count;
+            t.size += size;
+            t.disk += disk;
+        }
+        let mut out: Vec<TierTotal> = by_tier
+            .into_values()
+            .filter(|t| t.count > 0 || t.disk > 0)
+            .collect();
+        out.sort_by(|a, b| b.disk.cmp(&a.disk).then_with(|| a.tier.cmp(&b.tier)));
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{open_pool, test_support::build_test_db};
+
+    #[test]
+    fn tiers_label_folders_and_sum_without_double_counting() {
+        let map = TierMap::parse(
+            "Prefix,Tier\n\
+             # comment\n\
+             /,ssd\n\
+             /docs,archive\n\
+             /missing,nvme\n\
+             /nothing,\n",
+        )
+        .unwrap();
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.lookup("/docs/2019", false), Some("archive"));
+        assert_eq!(map.lookup("/docsx", false), Some("ssd"));
+        assert_eq!(map.lookup("/DOCS", true), Some("archive"));
+        assert!(TierMap::parse("prefix,team\n/a,b\n").is_err());
+
+        let t = build_test_db();
+        let pool = open_pool(&t.path).unwrap();
+        let all = map.totals(&pool, None, false).unwrap();
+        assert_eq!(all[0], TierTotal { tier: "archive".into(), count: 3, size: 600, disk: 300 });
+        assert_eq!(all.len(), 1);
+        let bob = map.totals(&pool, Some("bob"), false).unwrap();
+        assert_eq!(bob, [TierTotal { tier: "ssd".into(), count: 1, size: 50, disk: 50 }]);
+    }
+}
@@ -0,0 +1,150 @@
+// rs/src/userinfo.rs
+//
+// Display metadata for the usernames found in scans, loaded from a CSV with
+// a header row. `user` is required; the other columns are optional and any
+// unknown column is ignored:
+//
+//   user,name,department,email,avatar
+//   achen2,Alice Chen,Genomics,achen@example.org,https://intra/avatars/achen2.png
+//
+// Directory services (LDAP, AD) are expected to be exported to this format
+// by a periodic job; duapi reads the file once at startup.
+use anyhow::{bail, Context, Result};
+use csv::{ReaderBuilder, Trim};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserInfo {
+    /// "Full Name (Department)", or whichever part is known.
+    pub display: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub department: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// Avatar image URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct UserDirectory {
+    by_user: HashMap<String, UserInfo>,
+}
+
+impl UserDirectory {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading users file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("in {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut rdr = ReaderBuilder::new()
+            .has_headers(true)
+            .flexible(true)
+            .trim(Trim::All)
+            .comment(Some(b'#'))
+            .from_reader(text.as_bytes());
+        let headers = rdr.headers()?.clone();
+        let col = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+        let Some(user_col) = col("user") else {
+            bail!("users file needs a 'user' column");
+        };
+        let (name_col, dept_col, email_col, avatar_col) =
+            (col("name"), col("department"), col("email"), col("avatar"));
+
+        let mut by_user = HashMap::new();
+        for rec in rdr.records() {
+            let rec = rec?;
+            let field = |c: Option<usize>| {
+                c.and_then(|c| rec.get(c))
+                    .filter(|v| !v.is_empty())
+                    .map(str::to_string)
+            };
+            let Some(user) = field(Some(user_col)) else {
+                continue;
+            };
+            let (name, department) = (field(name_col), field(dept_col));
+            let display = match (&name, &department) {
+                (Some(n), Some(d)) => format!("{n} ({d})"),
+                (Some(n), None) => n.clone(),
+                (None, Some(d)) => format!("{user} ({d})"),
+                (None, None) => user.clone(),
+            };
+            by_user.insert(
+                user,
+                UserInfo {
+                    display,
+                    name,
+                    department,
+                    email: field(email_col),
+                    avatar: field(avatar_col),
+                },
+            );
+        }
+        Ok(Self { by_user })
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_user.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_user.is_empty()
+    }
+
+    pub fn get(&self, user: &str) -> Option<&UserInfo> {
+        self.by_user.get(user)
+    }
+
+    /// Entries for the given users that are in the directory; `None` when
+    /// none are.
+    pub fn subset<'a>(
+        &self,
+        users: impl IntoIterator<Item = &'a String>,
+    ) -> Option<BTreeMap<String, UserInfo>> {
+        let out: BTreeMap<String, UserInfo> = users
+            .into_iter()
+            .filter_map(|u| self.get(u).map(|i| (u.clone(), i.clone())))
+            .collect();
+        (!out.is_empty()).then_some(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_builds_display_names() {
+        let dir = UserDirectory::parse(
+            "User,Name,Department,Extra\n\
+             # comment\n\
+             achen2,Alice Chen,Genomics,x\n\
+             bob,Bob Stone,,\n\
+             carol,,Physics\n\
+             ,Nobody,None\n",
+        )
+        .unwrap();
+        assert_eq!(dir.len(), 3);
+        assert_eq!(dir.get("achen2").unwrap().display, "Alice Chen (Genomics)");
+        assert_eq!(dir.get("bob").unwrap().display, "Bob Stone");
+        assert_eq!(dir.get("bob").unwrap().department, None);
+        assert_eq!(dir.get("carol").unwrap().display, "carol (Physics)");
+        assert!(UserDirectory::parse("name,department\nA,B\n").is_err());
+    }
+
+    #[test]
+    fn subset_keeps_known_users_only() {
+        let dir = UserDirectory::parse("user,name\nalice,Alice\n").unwrap();
+        let users = ["alice".to_string(), "bob".to_string()];
+        let sub = dir.subset(&users).unwrap();
+        assert_eq!(sub.len(), 1);
+        assert_eq!(sub["alice"].display, "Alice");
+        assert!(dir.subset(&users[1..]).is_none());
+    }
+}
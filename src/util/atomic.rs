@@ -0,0 +1,114 @@
+// rs/src/util/atomic.rs
+//
+// Outputs other tools pick up (scans, summaries, archives, indexes, stores)
+// are written under a temp name beside the destination and renamed into
+// place once whole, so a crash or a full disk never leaves a truncated file
+// under the real name. The temp file is fsynced before the rename and the
+// directory after it, and removed if the output is dropped uncommitted.
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub struct AtomicFile {
+    dest: PathBuf,
+    tmp: PathBuf,
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Create the temp file for `dest`. An existing `dest` is left alone
+    /// until `commit` replaces it.
+    pub fn create(dest: &Path) -> io::Result<(Self, File)> {
+        let tmp = temp_path_for(dest, std::process::id());
+        let file = File::create(&tmp)
+            .map_err(|e| io::Error::new(e.kind(), format!("create {}: {e}", tmp.display())))?;
+        let out = Self {
+            dest: dest.to_path_buf(),
+            tmp,
+            committed: false,
+        };
+        Ok((out, file))
+    }
+
+    /// Where the output is written until `commit`.
+    pub fn temp_path(&self) -> &Path {
+        &self.tmp
+    }
+
+    /// Fsync `file` (the one `create` returned), move it over the
+    /// destination and make the rename durable.
+    pub fn commit(mut self, file: File) -> io::Result<()> {
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&self.tmp, &self.dest).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("rename {} -> {}: {e}", self.tmp.display(), self.dest.display()),
+            )
+        })?;
+        self.committed = true;
+        sync_parent(&self.dest)
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+/// `dir/.NAME.PID.tmp` for `dir/NAME`: hidden, on the same filesystem so
+/// the rename is atomic, and distinct per process. Public so that whoever
+/// kills process `pid` can remove what it left.
+pub fn temp_path_for(dest: &Path, pid: u32) -> PathBuf {
+    let name = dest
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "output".to_string());
+    dest.with_file_name(format!(".{name}.{pid}.tmp"))
+}
+
+/// Make a rename in the directory of `path` durable.
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        Some(dir) => File::open(dir)?.sync_all(),
+        None => File::open(".")?.sync_all(),
+    }
+}
+
+/// Windows cannot open a directory as a file; NTFS journals the rename.
+#[cfg(not(unix))]
+fn sync_parent(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn commit_replaces_and_drop_cleans_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("out.csv");
+        fs::write(&dest, "old").unwrap();
+
+        {
+            let (out, mut f) = AtomicFile::create(&dest).unwrap();
+            let name = out.temp_path().file_name().unwrap().to_string_lossy();
+            assert!(name.starts_with(".out.csv."));
+            f.write_all(b"partial").unwrap();
+        }
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "old");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let (out, mut f) = AtomicFile::create(&dest).unwrap();
+        f.write_all(b"new").unwrap();
+        out.commit(f).unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "new");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
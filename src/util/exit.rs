@@ -0,0 +1,158 @@
+// rs/src/util/exit.rs
+//
+// The exit codes of the batch tools (duscan, dusum, duzip, dumachine), the
+// same for all of them so a scheduler can branch on the outcome without
+// reading their output:
+//   0  done
+//   1  done, but some input could not be read; the summary counts it
+//   2  fatal: the run stopped and its output, if any, is not to be used
+//   3  partial: an output was written but covers only part of the input
+// clap exits 2 on a bad command line, which fits: nothing was done.
+//
+// With `--error-json` a fatal error is printed on stderr as one JSON object
+// (`Failure`) instead of anyhow's text, so the caller gets the message, its
+// causes and the OS error kind without parsing.
+use std::process::ExitCode;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Ok,
+    Errors,
+    Fatal,
+    Partial,
+}
+
+impl Outcome {
+    pub fn code(self) -> u8 {
+        match self {
+            Outcome::Ok => 0,
+            Outcome::Errors => 1,
+            Outcome::Fatal => 2,
+            Outcome::Partial => 3,
+        }
+    }
+
+    /// What a batch tool's exit code stands for; None for codes outside
+    /// the contract (a panic exits 101, a signal has no code).
+    pub fn from_code(code: i32) -> Option<Self> {
+        match code {
+            0 => Some(Outcome::Ok),
+            1 => Some(Outcome::Errors),
+            2 => Some(Outcome::Fatal),
+            3 => Some(Outcome::Partial),
+            _ => None,
+        }
+    }
+
+    /// Whether the run left an output to go on with, whole or not.
+    pub fn wrote_output(self) -> bool {
+        self != Outcome::Fatal
+    }
+
+    /// `Errors` when `errors` entries could not be read, `Ok` otherwise.
+    pub fn with_errors(errors: u64) -> Self {
+        if errors > 0 { Outcome::Errors } else { Outcome::Ok }
+    }
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(o: Outcome) -> Self {
+        ExitCode::from(o.code())
+    }
+}
+
+/// Exit flags shared by the batch tools; `#[command(flatten)]` them into
+/// the tool's arguments.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct ExitArgs {
+    /// On a fatal error print a JSON description of it on stderr instead
+    /// of text (exit codes: 0 ok, 1 errors, 2 fatal, 3 partial)
+    #[arg(long)]
+    pub error_json: bool,
+}
+
+/// What `--error-json` prints.
+#[derive(Debug, Serialize)]
+pub struct Failure {
+    pub tool: String,
+    pub outcome: Outcome,
+    pub exit_code: u8,
+    /// The outermost message
+    pub error: String,
+    /// The messages below it, outermost first
+    pub causes: Vec<String>,
+    /// `std::io::ErrorKind` of the first I/O error in the chain (e.g.
+    /// "NotFound", "PermissionDenied")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub io_kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub os_error: Option<i32>,
+}
+
+impl Failure {
+    pub fn new(tool: &str, e: &anyhow::Error) -> Self {
+        let io = e.chain().find_map(|c| c.downcast_ref::<std::io::Error>());
+        Self {
+            tool: tool.to_string(),
+            outcome: Outcome::Fatal,
+            exit_code: Outcome::Fatal.code(),
+            error: e.to_string(),
+            causes: e.chain().skip(1).map(|c| c.to_string()).collect(),
+            io_kind: io.map(|io| format!("{:?}", io.kind())),
+            os_error: io.and_then(|io| io.raw_os_error()),
+        }
+    }
+}
+
+/// The process exit code for a tool's result, printing a fatal error the
+/// way `args` asks for.
+pub fn finish(tool: &str, args: &ExitArgs, result: anyhow::Result<Outcome>) -> ExitCode {
+    match result {
+        Ok(outcome) => outcome.into(),
+        Err(e) => {
+            if args.error_json {
+                let json = serde_json::to_string(&Failure::new(tool, &e)).unwrap_or_default();
+                eprintln!("{json}");
+            } else {
+                eprintln!("Error: {e:?}");
+            }
+            Outcome::Fatal.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn failure_carries_causes_and_io_kind() {
+        let e = std::fs::File::open("/nonexistent/dutopia")
+            .context("opening input")
+            .unwrap_err();
+        let v = serde_json::json!(Failure::new("dusum", &e));
+        assert_eq!(v["tool"], "dusum");
+        assert_eq!(v["outcome"], "fatal");
+        assert_eq!(v["exit_code"], 2);
+        assert_eq!(v["error"], "opening input");
+        assert_eq!(v["causes"].as_array().unwrap().len(), 1);
+        assert_eq!(v["io_kind"], "NotFound");
+
+        let plain = serde_json::json!(Failure::new("duzip", &anyhow::anyhow!("bad header")));
+        assert!(plain.get("io_kind").is_none());
+        assert_eq!(plain["causes"], serde_json::json!([]));
+
+        assert_eq!(Outcome::with_errors(0), Outcome::Ok);
+        assert_eq!(Outcome::with_errors(3).code(), 1);
+        assert_eq!(Outcome::Partial.code(), 3);
+        for o in [Outcome::Ok, Outcome::Errors, Outcome::Fatal, Outcome::Partial] {
+            assert_eq!(Outcome::from_code(o.code() as i32), Some(o));
+        }
+        assert_eq!(Outcome::from_code(101), None);
+        assert!(Outcome::Partial.wrote_output() && !Outcome::Fatal.wrote_output());
+    }
+}
@@ -0,0 +1,163 @@
+// rs/src/util/filter.rs
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use memchr::memmem;
+
+/// Path exclusion shared by the tools: a plain substring (`--skip`) and any
+/// number of glob patterns (`--exclude`). A glob excludes a path when it
+/// matches the path itself or any of its ancestor folders, so
+/// `--exclude '**/.snapshot'` drops the whole snapshot tree, the same way
+/// pruning a directory during a scan would. `--include` globs keep a path
+/// they match even when an `--exclude` glob drops it, like `!PATTERN` in a
+/// .gitignore; as there, nothing below a pruned directory comes back.
+#[derive(Debug, Default, Clone)]
+pub struct PathFilter {
+    skip: Option<String>,
+    exclude: Option<GlobSet>,
+    include: Option<GlobSet>,
+}
+
+impl PathFilter {
+    pub fn new(skip: Option<&str>, exclude: &[String]) -> Result<Self> {
+        Ok(Self {
+            skip: skip.filter(|s| !s.is_empty()).map(str::to_string),
+            exclude: build_globset(exclude)?,
+            include: None,
+        })
+    }
+
+    /// Keep paths matching `include` that the `--exclude` globs would drop.
+    pub fn with_include(self, include: &[String]) -> Result<Self> {
+        Ok(Self {
+            include: build_globset(include)?,
+            ..self
+        })
+    }
+
+    /// True when there are `--exclude` globs.
+    pub fn has_globs(&self) -> bool {
+        self.exclude.is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.skip.is_none() && self.exclude.is_none()
+    }
+
+    /// True when `path` (raw bytes, `/` or `\` separated) should be dropped.
+    pub fn excludes(&self, path: &[u8]) -> bool {
+        if let Some(s) = &self.skip
+            && memmem::find(path, s.as_bytes()).is_some()
+        {
+            return true;
+        }
+        match &self.exclude {
+            Some(set) => glob_matches_path_or_ancestor(set, path) && !self.included(path),
+            None => false,
+        }
+    }
+
+    /// Like `excludes`, for walks, which prune excluded directories on the
+    /// way down: `path` is matched alone, not its ancestors.
+    pub fn prunes(&self, path: &[u8]) -> bool {
+        if let Some(s) = &self.skip
+            && memmem::find(path, s.as_bytes()).is_some()
+        {
+            return true;
+        }
+        self.glob_excludes(path)
+    }
+
+    /// The globs alone against `path` alone; a walk checks its files with
+    /// this, `--skip` naming directories.
+    pub fn glob_excludes(&self, path: &[u8]) -> bool {
+        match &self.exclude {
+            Some(set) => set.is_match(String::from_utf8_lossy(path).as_ref()) && !self.included(path),
+            None => false,
+        }
+    }
+
+    fn included(&self, path: &[u8]) -> bool {
+        self.include
+            .as_ref()
+            .is_some_and(|set| set.is_match(String::from_utf8_lossy(path).as_ref()))
+    }
+}
+
+/// Compile `patterns` into one set; `None` when there are no patterns.
+pub fn build_globset(patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut b = GlobSetBuilder::new();
+    for p in patterns {
+        b.add(Glob::new(p).with_context(|| format!("invalid glob pattern: {p}"))?);
+    }
+    Ok(Some(b.build()?))
+}
+
+/// Match the full path, then every ancestor prefix ending before a separator.
+pub fn glob_matches_path_or_ancestor(set: &GlobSet, path: &[u8]) -> bool {
+    let s = String::from_utf8_lossy(path);
+    if set.is_match(s.as_ref()) {
+        return true;
+    }
+    s.char_indices()
+        .filter(|&(i, c)| (c == '/' || c == '\\') && i > 0)
+        .any(|(i, _)| set.is_match(&s[..i]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_filter_excludes_nothing() {
+        let f = PathFilter::new(None, &[]).unwrap();
+        assert!(f.is_empty());
+        assert!(!f.excludes(b"/a/b"));
+    }
+
+    #[test]
+    fn skip_is_substring_match() {
+        let f = PathFilter::new(Some(".snapshot"), &[]).unwrap();
+        assert!(f.excludes(b"/vol/.snapshot/hourly.0/f"));
+        assert!(!f.excludes(b"/vol/data/f"));
+    }
+
+    #[test]
+    fn glob_matches_ancestor_folders() {
+        let f = PathFilter::new(None, &["**/.cache".to_string()]).unwrap();
+        assert!(f.excludes(b"/home/alice/.cache"));
+        assert!(f.excludes(b"/home/alice/.cache/pip/x.whl"));
+        assert!(!f.excludes(b"/home/alice/.cachex/y"));
+    }
+
+    #[test]
+    fn glob_matches_file_names_and_windows_paths() {
+        let f = PathFilter::new(None, &["*.tmp".to_string(), "C:\\\\Temp*".to_string()]).unwrap();
+        assert!(f.excludes(b"/data/x.tmp"));
+        assert!(f.excludes(b"C:\\Temp\\a.txt"));
+        assert!(!f.excludes(b"/data/x.txt"));
+    }
+
+    #[test]
+    fn include_keeps_what_exclude_drops() {
+        let f = PathFilter::new(None, &["*.tmp".to_string(), "**/.git/**".to_string()])
+            .unwrap()
+            .with_include(&["**/keep/*.tmp".to_string()])
+            .unwrap();
+        assert!(f.has_globs());
+        assert!(f.excludes(b"/src/x.tmp"));
+        assert!(!f.excludes(b"/src/keep/x.tmp"));
+        assert!(f.prunes(b"/src/.git/objects"));
+        assert!(!f.prunes(b"/src/.git"));
+        assert!(f.glob_excludes(b"/src/.git/HEAD"));
+        assert!(!f.glob_excludes(b"/src/main.rs"));
+        assert!(PathFilter::default().with_include(&["a[".to_string()]).is_err());
+    }
+
+    #[test]
+    fn invalid_glob_is_an_error() {
+        assert!(PathFilter::new(None, &["a[".to_string()]).is_err());
+    }
+}
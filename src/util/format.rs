@@ -18,7 +18,7 @@ pub fn print_about() {
     println!("{}", "-".repeat(44).bright_cyan());
     println!(
         "{}",
-        format!("Dutopia      : Superfast filesystem analyzer").bright_cyan()
+        "Dutopia      : Superfast filesystem analyzer".to_string().bright_cyan()
     );
     println!(
         "{}",
@@ -137,6 +137,62 @@ pub fn parse_file_hint(s: &str) -> Option<u64> {
     Some((val * mul) as u64)
 }
 
+/// `1.5TB`, `64KB`, `512k`, `2GiB` or a bare byte count. Units are
+/// binary, as `human_bytes` prints them: K, KB and KiB all mean 1024.
+pub fn parse_bytes(s: &str) -> Result<u64, String> {
+    let t = s.trim();
+    let split = t.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(t.len());
+    let (num, unit) = t.split_at(split);
+    let val: f64 = num.parse().map_err(|_| format!("invalid size '{s}'"))?;
+    let exp = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 1,
+        "m" | "mb" | "mib" => 2,
+        "g" | "gb" | "gib" => 3,
+        "t" | "tb" | "tib" => 4,
+        "p" | "pb" | "pib" => 5,
+        _ => return Err(format!("invalid size '{s}' (use B, KB, MB, GB, TB or PB)")),
+    };
+    let bytes = val * 1024f64.powi(exp);
+    if !bytes.is_finite() || bytes >= u64::MAX as f64 {
+        return Err(format!("size '{s}' is too large"));
+    }
+    Ok(bytes.round() as u64)
+}
+
+/// `2h30m`, `90s`, `1.5d`, `500ms` or a bare number of seconds; units
+/// are ms, s, m, h, d and w, and may be combined largest first.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let t = s.trim();
+    if let Ok(secs) = t.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+    let invalid = || format!("invalid duration '{s}' (e.g. 90s, 15m, 2h30m, 1d)");
+    if t.is_empty() {
+        return Err(invalid());
+    }
+    let mut rest = t;
+    let mut total = 0f64;
+    while !rest.is_empty() {
+        let n = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+        let val: f64 = rest[..n].parse().map_err(|_| invalid())?;
+        rest = &rest[n..];
+        let u = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+        let secs = match rest[..u].to_ascii_lowercase().as_str() {
+            "ms" => 0.001,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "d" => 86_400.0,
+            "w" => 604_800.0,
+            _ => return Err(invalid()),
+        };
+        rest = &rest[u..];
+        total += val * secs;
+    }
+    Duration::try_from_secs_f64(total).map_err(|_| invalid())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -339,6 +395,35 @@ mod tests {
         assert_eq!(parse_file_hint("10t"), None);
     }
 
+    #[test]
+    fn test_parse_bytes() {
+        assert_eq!(parse_bytes("100"), Ok(100));
+        assert_eq!(parse_bytes("64KB"), Ok(64 * 1024));
+        assert_eq!(parse_bytes("512k"), Ok(512 * 1024));
+        assert_eq!(parse_bytes("1.5TB"), Ok(3 * (1u64 << 39)));
+        assert_eq!(parse_bytes(" 2 GiB "), Ok(2 << 30));
+        assert_eq!(parse_bytes("10B"), Ok(10));
+        assert!(parse_bytes("10x").is_err());
+        assert!(parse_bytes("GB").is_err());
+        assert!(parse_bytes("").is_err());
+        assert!(parse_bytes("99999999PB").is_err());
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2h30m"), Ok(Duration::from_secs(9000)));
+        assert_eq!(parse_duration("1.5d"), Ok(Duration::from_secs(129_600)));
+        assert_eq!(parse_duration("1w"), Ok(Duration::from_secs(604_800)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1M"), Ok(Duration::from_secs(60)));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("5y").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("2h 30m").is_err());
+    }
+
     #[test]
     fn test_print_about() {
         print_about();
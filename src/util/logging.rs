@@ -18,3 +18,65 @@ pub fn init_tracing(app: &str) {
         tracing::info!(app, "logging initialized");
     }
 }
+
+/// Logging flags shared by the batch tools (duscan, dusum, duzip);
+/// `#[command(flatten)]` them into the tool's arguments.
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct LogArgs {
+    /// Diagnostics level on stderr: error, warn, info, debug or trace
+    /// (default: warn, info with -v, debug with -vv; RUST_LOG also works)
+    #[arg(long, value_name = "LEVEL", env = "DUTOPIA_LOG_LEVEL")]
+    pub log_level: Option<String>,
+    /// Write diagnostics as JSON lines, for log shippers
+    #[arg(long)]
+    pub log_json: bool,
+}
+
+/// Level used when neither `--log-level` nor `RUST_LOG` is set.
+pub fn level_for_verbosity(verbose: u8) -> &'static str {
+    match verbose {
+        0 => "warn",
+        1 => "info",
+        _ => "debug",
+    }
+}
+
+/// Timestamped diagnostics on stderr for a batch tool. Console output meant
+/// for people (banners, totals, progress) stays on stdout.
+pub fn init_cli_tracing(app: &str, args: &LogArgs, default_level: &str) -> anyhow::Result<()> {
+    let filter = match &args.log_level {
+        Some(level) => EnvFilter::try_new(level)
+            .map_err(|e| anyhow::anyhow!("invalid --log-level '{level}': {e}"))?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level)),
+    };
+    let builder = fmt()
+        .with_env_filter(filter)
+        .with_target(false)
+        .with_writer(std::io::stderr);
+    let result = if args.log_json {
+        builder.json().try_init()
+    } else {
+        builder.try_init()
+    };
+    if result.is_ok() {
+        tracing::debug!(app, "logging initialized");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbosity_maps_to_levels_and_bad_level_is_rejected() {
+        assert_eq!(level_for_verbosity(0), "warn");
+        assert_eq!(level_for_verbosity(1), "info");
+        assert_eq!(level_for_verbosity(5), "debug");
+        let bad = LogArgs {
+            log_level: Some("loud[".into()),
+            log_json: false,
+        };
+        assert!(init_cli_tracing("test", &bad, "warn").is_err());
+    }
+}
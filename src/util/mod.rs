@@ -1,21 +1,31 @@
 // rs/src/util/mod.rs
 
+mod atomic;
 mod csv;
+pub mod exit;
+mod filter;
 mod format;
 pub mod logging;
 mod path;
 mod platform;
+pub mod progress;
 mod row;
 
 // Re-export everything for backward compatibility
+pub use atomic::{temp_path_for, AtomicFile};
 pub use csv::{parse_int, push_i64, push_u32, push_u64, trim_ascii};
+pub use filter::{build_globset, glob_matches_path_or_ancestor, PathFilter};
 pub use format::{
-    format_duration, get_hostname, human_bytes, human_count, parse_file_hint, print_about,
-    progress_bar, spinner,
+    format_duration, get_hostname, human_bytes, human_count, parse_bytes, parse_duration,
+    parse_file_hint, print_about, progress_bar, spinner,
 };
-pub use path::{dusum_parent, is_volume_root, should_skip, strip_verbatim_prefix};
-pub use platform::fs_used_bytes;
+pub use path::{
+    components, dusum_parent, folder_parent, get_folder_ancestors, is_volume_root,
+    normalize_folder_bytes, replace_path_prefix, separator, should_skip, split_anchor,
+    strip_verbatim_prefix, Anchor,
+};
+pub use platform::{fs_free_bytes, fs_type, fs_used_bytes};
 pub use row::Row;
 
 #[cfg(windows)]
-pub use platform::get_rid;
+pub use platform::{account_name_from_rid, get_rid};
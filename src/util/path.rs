@@ -1,8 +1,155 @@
 // rs/src/util/path.rs
+//
+// Raw-byte path helpers shared by the tools that write and read path
+// columns: dusum builds its folder rows with `get_folder_ancestors`, and
+// dudb, duapi and dureport walk back up with `dusum_parent`. Both split a
+// path the same way (`separator`, `split_anchor`, `components`), so a
+// folder row one tool writes is always a folder the others can parse.
 use std::path::{Path, PathBuf};
 
+use super::filter::PathFilter;
+
+/// Pick the native separator byte for a raw path. A backslash anywhere in the
+/// path (or a drive-letter prefix) means Windows-native; otherwise Unix.
+pub fn separator(path: &[u8]) -> u8 {
+    if path.contains(&b'\\') {
+        return b'\\';
+    }
+    if path.len() >= 2 && path[0].is_ascii_alphabetic() && path[1] == b':' {
+        return b'\\';
+    }
+    b'/'
+}
+
+/// What a raw path hangs from, in `separator`'s reading of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    /// `/…`; `/` is a folder of its own
+    Unix,
+    /// `C:\…` (the byte is the drive letter, case kept); `C:\` is a folder
+    Drive(u8),
+    /// `\\server\share\…`; server and share are its first components
+    Unc,
+    /// No anchor, e.g. `./a` from `duscan --relative`
+    Relative,
+}
+
+impl Anchor {
+    /// The anchor as written before the first component.
+    pub fn prefix(self) -> Vec<u8> {
+        match self {
+            Anchor::Unix => b"/".to_vec(),
+            Anchor::Drive(d) => vec![d, b':', b'\\'],
+            Anchor::Unc => b"\\\\".to_vec(),
+            Anchor::Relative => Vec::new(),
+        }
+    }
+
+    /// True when the anchor is itself a folder (`/`, `C:\`).
+    pub fn is_folder(self) -> bool {
+        matches!(self, Anchor::Unix | Anchor::Drive(_))
+    }
+}
+
+/// Split `path` into its anchor and the rest.
+///   `/var/log`        → (`Unix`, `var/log`)
+///   `C:\Users`        → (`Drive(b'C')`, `Users`)
+///   `\\srv\shr\dir`   → (`Unc`, `srv\shr\dir`)
+///   `./a`             → (`Relative`, `./a`)
+pub fn split_anchor(path: &[u8]) -> (Anchor, &[u8]) {
+    let sep = separator(path);
+    if sep == b'/' && path.starts_with(b"/") {
+        (Anchor::Unix, &path[1..])
+    } else if sep == b'\\' && path.starts_with(b"\\\\") {
+        (Anchor::Unc, &path[2..])
+    } else if sep == b'\\' && path.len() >= 2 && path[0].is_ascii_alphabetic() && path[1] == b':' {
+        (Anchor::Drive(path[0]), &path[2..])
+    } else {
+        (Anchor::Relative, path)
+    }
+}
+
+/// The components of `path` after its anchor; empty ones (repeated or
+/// trailing separators) are dropped.
+pub fn components(path: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let sep = separator(path);
+    let (_, rest) = split_anchor(path);
+    rest.split(move |&b| b == sep).filter(|c| !c.is_empty())
+}
+
+/// The folders from the anchor down to `folder` itself, outer→inner, in
+/// the form `get_folder_ancestors` emits them.
+fn folder_chain(folder: &[u8]) -> Vec<Vec<u8>> {
+    let sep = separator(folder);
+    let (anchor, _) = split_anchor(folder);
+    let mut chain: Vec<Vec<u8>> = Vec::new();
+    let mut current = anchor.prefix();
+    if anchor.is_folder() {
+        chain.push(current.clone());
+    }
+    for segment in components(folder) {
+        if !current.is_empty() && current.last() != Some(&sep) {
+            current.push(sep);
+        }
+        current.extend_from_slice(segment);
+        chain.push(current.clone());
+    }
+    chain
+}
+
+/// Trim trailing separator characters, preserving OS-native form.
+///   `C:\Users\Default\` → `C:\Users\Default`
+///   `/var/log/`         → `/var/log`
+///   `C:\`               → `C:\`   (drive root keeps the trailing sep)
+///   `/`                 → `/`     (root keeps the separator)
+pub fn normalize_folder_bytes(path: &[u8]) -> Vec<u8> {
+    let sep = separator(path);
+    let mut out: Vec<u8> = path.to_vec();
+
+    // Protect drive-letter roots: a lone `C:\` or `C:` stays as-is.
+    let is_drive_root = out.len() <= 3
+        && out.first().is_some_and(|b| b.is_ascii_alphabetic())
+        && out.get(1) == Some(&b':');
+
+    while out.len() > 1 && !is_drive_root && out.last() == Some(&sep) {
+        out.pop();
+    }
+    out
+}
+
+/// Convert a file path into its list of ancestor folder paths, outer→inner,
+/// preserving OS-native separators.
+///   `/a/b/file.txt`         → [`/`, `/a`, `/a/b`]
+///   `C:\Users\San\foo.txt`  → [`C:\`, `C:\Users`, `C:\Users\San`]
+///   `\\srv\shr\dir\f.txt`   → [`\\srv`, `\\srv\shr`, `\\srv\shr\dir`]
+pub fn get_folder_ancestors(path: &[u8]) -> Vec<Vec<u8>> {
+    let sep = separator(path);
+
+    let parent_end = path.iter().rposition(|&b| b == sep);
+
+    let folder = match parent_end {
+        None => return Vec::new(),
+        Some(0) => {
+            // File lives directly under root ("/" on Unix, or "\" which only
+            // arises in malformed Windows input).
+            return vec![vec![sep]];
+        }
+        Some(pos) => &path[..pos],
+    };
+    folder_chain(folder)
+}
+
+/// The parent of the folder `folder`, the folder before it in
+/// `get_folder_ancestors`; `None` at the top of its hierarchy (`/`, `C:\`,
+/// `\\srv`, a single relative component) and for the empty path.
+pub fn folder_parent(folder: &[u8]) -> Option<Vec<u8>> {
+    let mut chain = folder_chain(folder);
+    chain.pop()?;
+    chain.pop()
+}
+
 /// Compute the parent of a `dusum`-stored path string, in the same OS-native
-/// form `dusum::aggregate::get_folder_ancestors` produces. Returns `None`
+/// form `get_folder_ancestors` produces. Returns `None`
 /// when the path is at the top of its native hierarchy (Unix `/`, Windows
 /// drive root `C:\`, UNC bare server `\\srv`) — callers maintaining a
 /// synthetic root above all platform roots should treat `None` as
@@ -18,44 +165,10 @@ use std::path::{Path, PathBuf};
 ///   `\\srv\shr`           -> `Some("\\\\srv")`
 ///   `\\srv`               -> `None`
 ///   `""`                  -> `None`  (the synthetic root has no parent)
+///
+/// Trailing and repeated separators are ignored, as in the ancestors.
 pub fn dusum_parent(p: &str) -> Option<String> {
-    if p.is_empty() || p == "/" {
-        return None;
-    }
-    let bytes = p.as_bytes();
-
-    // Drive root: "C:\"
-    if bytes.len() == 3
-        && bytes[0].is_ascii_alphabetic()
-        && bytes[1] == b':'
-        && bytes[2] == b'\\'
-    {
-        return None;
-    }
-
-    // UNC bare server: "\\srv" — no further `\` after the leading "\\".
-    if p.starts_with(r"\\") && !p[2..].contains('\\') {
-        return None;
-    }
-
-    // Find the rightmost separator (either kind, whichever appears last).
-    let last = match (p.rfind('\\'), p.rfind('/')) {
-        (Some(a), Some(b)) => a.max(b),
-        (Some(a), None) => a,
-        (None, Some(b)) => b,
-        (None, None) => return None,
-    };
-
-    // Subdir of Unix root: "/var" → "/"
-    if last == 0 {
-        return Some("/".to_string());
-    }
-    // Subdir of Windows drive root: "C:\Users" → "C:\"
-    if last == 2 && bytes.len() > 3 && bytes[1] == b':' {
-        return Some(format!("{}:\\", bytes[0] as char));
-    }
-
-    Some(p[..last].to_string())
+    folder_parent(p.as_bytes()).map(|b| String::from_utf8_lossy(&b).into_owned())
 }
 
 #[cfg(windows)]
@@ -79,13 +192,55 @@ pub fn strip_verbatim_prefix(p: &Path) -> PathBuf {
     p.to_path_buf()
 }
 
+/// Rewrite a leading `from` prefix of a raw path to `to`, matching on whole
+/// components only (`/mnt/a` covers `/mnt/a` and `/mnt/a/x`, not `/mnt/ab`).
+/// Trailing separators on either prefix are ignored, so `from = "/"` or an
+/// empty `from` prepends `to` to every absolute path. Returns `None` when
+/// `path` is not under `from`.
+///
+///   (`/mnt/scan1/p/f`, `/mnt/scan1`, `/projects`) -> `/projects/p/f`
+///   (`/mnt/scan1`,     `/mnt/scan1`, `/projects`) -> `/projects`
+///   (`/mnt/scan1/p`,   `/mnt/scan1`, ``)          -> `/p`
+///   (`/mnt/scan10`,    `/mnt/scan1`, `/projects`) -> `None`
+pub fn replace_path_prefix(path: &[u8], from: &[u8], to: &[u8]) -> Option<Vec<u8>> {
+    let from = trim_trailing_seps(from);
+    let to = trim_trailing_seps(to);
+
+    let rest = path.strip_prefix(from)?;
+    if let Some(&first) = rest.first()
+        && !is_path_sep(first)
+    {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(to.len() + rest.len());
+    out.extend_from_slice(to);
+    out.extend_from_slice(rest);
+    if out.is_empty() {
+        out.push(b'/');
+    }
+    Some(out)
+}
+
 #[inline]
-pub fn should_skip(path: &Path, skip: Option<&str>) -> bool {
-    if let Some(s) = skip {
-        path.as_os_str().to_string_lossy().contains(s)
-    } else {
-        false
+fn is_path_sep(b: u8) -> bool {
+    b == b'/' || b == b'\\'
+}
+
+fn trim_trailing_seps(mut p: &[u8]) -> &[u8] {
+    while let Some((&last, head)) = p.split_last()
+        && is_path_sep(last)
+    {
+        p = head;
     }
+    p
+}
+
+/// True when a walk should not enter the directory `path`: it contains the
+/// `--skip` substring or matches an `--exclude` glob (and no `--include`).
+#[inline]
+pub fn should_skip(path: &Path, filter: &PathFilter) -> bool {
+    !filter.is_empty() && filter.prunes(path.as_os_str().to_string_lossy().as_bytes())
 }
 
 pub fn is_volume_root(path: &Path) -> bool {
@@ -97,10 +252,10 @@ pub fn is_volume_root(path: &Path) -> bool {
             return true;
         }
 
-        if let (Ok(meta), Some(parent)) = (std::fs::metadata(path), path.parent()) {
-            if let Ok(pmeta) = std::fs::metadata(parent) {
-                return meta.dev() != pmeta.dev();
-            }
+        if let (Ok(meta), Some(parent)) = (std::fs::metadata(path), path.parent())
+            && let Ok(pmeta) = std::fs::metadata(parent)
+        {
+            return meta.dev() != pmeta.dev();
         }
         false
     }
@@ -146,28 +301,52 @@ pub fn is_volume_root(path: &Path) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_replace_path_prefix() {
+        let r = |p: &str, f: &str, t: &str| {
+            replace_path_prefix(p.as_bytes(), f.as_bytes(), t.as_bytes())
+                .map(|v| String::from_utf8(v).unwrap())
+        };
+        assert_eq!(r("/mnt/scan1/p/f", "/mnt/scan1", "/projects").as_deref(), Some("/projects/p/f"));
+        assert_eq!(r("/mnt/scan1", "/mnt/scan1/", "/projects/").as_deref(), Some("/projects"));
+        assert_eq!(r("/mnt/scan1/p", "/mnt/scan1", "").as_deref(), Some("/p"));
+        assert_eq!(r("/mnt/scan1", "/mnt/scan1", "").as_deref(), Some("/"));
+        assert_eq!(r("/a/b", "/", "/projects").as_deref(), Some("/projects/a/b"));
+        assert_eq!(r("/a/b", "", "/projects").as_deref(), Some("/projects/a/b"));
+        assert_eq!(r(r"D:\scan\x", r"D:\scan", r"\\srv\proj").as_deref(), Some(r"\\srv\proj\x"));
+        assert_eq!(r("/mnt/scan10/x", "/mnt/scan1", "/projects"), None);
+        assert_eq!(r("/other", "/mnt/scan1", "/projects"), None);
+    }
+
     #[test]
     fn test_should_skip() {
+        let skip = |s: &str| PathFilter::new(Some(s), &[]).unwrap();
         let p = PathBuf::from("/a/b/c/d");
-        assert!(should_skip(&p, Some("b/c")));
-        assert!(!should_skip(&p, Some("x")));
-        assert!(!should_skip(&p, None));
+        assert!(should_skip(&p, &skip("b/c")));
+        assert!(!should_skip(&p, &skip("x")));
+        assert!(!should_skip(&p, &PathFilter::default()));
 
         let p2 = PathBuf::from("C:\\Users\\test");
-        assert!(should_skip(&p2, Some("Users")));
-        assert!(!should_skip(&p2, Some("Documents")));
+        assert!(should_skip(&p2, &skip("Users")));
+        assert!(!should_skip(&p2, &skip("Documents")));
+
+        let globs = PathFilter::new(None, &["**/.git".to_string()]).unwrap();
+        assert!(should_skip(Path::new("/src/.git"), &globs));
+        assert!(!should_skip(Path::new("/src/.github"), &globs));
     }
 
     #[test]
     fn test_should_skip_edge_cases() {
+        let skip = |s: &str| PathFilter::new(Some(s), &[]).unwrap();
         let p = PathBuf::from("");
-        assert!(!should_skip(&p, Some("test")));
-        assert!(!should_skip(&p, None));
+        assert!(!should_skip(&p, &skip("test")));
+        assert!(!should_skip(&p, &PathFilter::default()));
 
+        // an empty --skip skips nothing
         let p2 = PathBuf::from("test");
-        assert!(should_skip(&p2, Some("")));
-        assert!(should_skip(&p2, Some("test")));
-        assert!(should_skip(&p2, Some("te")));
+        assert!(!should_skip(&p2, &skip("")));
+        assert!(should_skip(&p2, &skip("test")));
+        assert!(should_skip(&p2, &skip("te")));
     }
 
     #[cfg(not(windows))]
@@ -266,43 +445,185 @@ mod tests {
         assert_eq!(dusum_parent(""), None);
     }
 
-    /// Cross-check: for every path `dusum::get_folder_ancestors` would emit,
+    #[test]
+    fn dusum_parent_ignores_extra_separators() {
+        assert_eq!(dusum_parent("/var/log/"), Some("/var".to_string()));
+        assert_eq!(dusum_parent("/a//b///c"), Some("/a/b".to_string()));
+        assert_eq!(dusum_parent("C:\\Users\\"), Some("C:\\".to_string()));
+    }
+
+    #[test]
+    fn dusum_parent_relative() {
+        assert_eq!(dusum_parent("./a/b"), Some("./a".to_string()));
+        assert_eq!(dusum_parent("./a"), Some(".".to_string()));
+        assert_eq!(dusum_parent("."), None);
+    }
+
+    #[test]
+    fn anchors_and_components() {
+        assert_eq!(split_anchor(b"/var/log"), (Anchor::Unix, &b"var/log"[..]));
+        assert_eq!(split_anchor(b"c:\\x"), (Anchor::Drive(b'c'), &b"\\x"[..]));
+        assert_eq!(split_anchor(b"\\\\srv\\shr"), (Anchor::Unc, &b"srv\\shr"[..]));
+        assert_eq!(split_anchor(b"./a"), (Anchor::Relative, &b"./a"[..]));
+        let parts: Vec<&[u8]> = components(b"C:\\Users\\\\San\\").collect();
+        assert_eq!(parts, [&b"Users"[..], &b"San"[..]]);
+        assert_eq!(components(b"/").count(), 0);
+    }
+
+    /// Cross-check: for every path `get_folder_ancestors` emits,
     /// `dusum_parent` of `ancestors[i+1]` must equal `ancestors[i]`. Pinning
     /// this guarantees parent_id chains rebuilt by `dudb` match dusum's
     /// notion of the tree byte-for-byte.
     #[test]
     fn dusum_parent_inverts_get_folder_ancestors() {
-        let cases: &[&[&str]] = &[
-            &["/", "/var", "/var/log"],
-            &["C:\\", "C:\\Users", "C:\\Users\\San"],
-            &[r"\\srv", r"\\srv\shr", r"\\srv\shr\dir"],
-        ];
-        for chain in cases {
+        for file in [
+            "/var/log/syslog",
+            "C:\\Users\\San\\f.txt",
+            r"\\srv\shr\dir\f.txt",
+            "./a/b/f",
+            "/a//b/c/f",
+        ] {
+            let chain: Vec<String> = get_folder_ancestors(file.as_bytes())
+                .into_iter()
+                .map(|a| String::from_utf8(a).unwrap())
+                .collect();
+            assert_eq!(dusum_parent(&chain[0]), None, "{:?} should be top-level", chain[0]);
             for win in chain.windows(2) {
-                let parent = dusum_parent(win[1]).unwrap_or_default();
+                let parent = dusum_parent(&win[1]).unwrap_or_default();
                 assert_eq!(
                     parent, win[0],
                     "parent of {:?} should be {:?}, got {:?}",
                     win[1], win[0], parent
                 );
             }
-            // The first entry in each chain has no parent (top of its hierarchy).
-            assert_eq!(dusum_parent(chain[0]), None, "{:?} should be top-level", chain[0]);
         }
     }
 
+    #[test]
+    fn ancestors_from_non_utf8_bytes() {
+        let raw = [
+            b'/', 0xFFu8, b'a', b'/', b'b', b'/', b'c', b'/', b'f', b'.', b't', b'x', b't',
+        ];
+        let ancestors = get_folder_ancestors(&raw);
+        assert_eq!(ancestors[0], b"/".to_vec());
+        assert!(ancestors.contains(&vec![b'/', 0xFFu8, b'a']));
+        assert!(ancestors.contains(&vec![b'/', 0xFFu8, b'a', b'/', b'b']));
+    }
+
+    #[test]
+    fn ancestors_trailing_slashes_and_multi_seps() {
+        let res = get_folder_ancestors(b"/a//b///c//file.txt");
+        assert_eq!(
+            res,
+            vec![
+                b"/".to_vec(),
+                b"/a".to_vec(),
+                b"/a/b".to_vec(),
+                b"/a/b/c".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn ancestors_windows_native_backslashes() {
+        let res = get_folder_ancestors(b"C:\\a\\b\\file.txt");
+        assert_eq!(
+            res,
+            vec![
+                b"C:\\".to_vec(),
+                b"C:\\a".to_vec(),
+                b"C:\\a\\b".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn ancestors_unc_native() {
+        let res = get_folder_ancestors(b"\\\\server\\share\\dir\\file.txt");
+        assert_eq!(
+            res,
+            vec![
+                b"\\\\server".to_vec(),
+                b"\\\\server\\share".to_vec(),
+                b"\\\\server\\share\\dir".to_vec(),
+            ]
+        );
+    }
+
+    #[test]
+    fn ancestors_handles_root_only() {
+        assert_eq!(get_folder_ancestors(b"/file.txt"), vec![b"/".to_vec()]);
+        assert_eq!(get_folder_ancestors(b"/"), vec![b"/".to_vec()]);
+    }
+
+    #[test]
+    fn ancestors_handles_relative_paths() {
+        // No path separator at all: no ancestor folders.
+        let res = get_folder_ancestors(b"file.txt");
+        assert!(res.is_empty());
+    }
+
+    #[test]
+    fn ancestors_simple_nested_path() {
+        let res = get_folder_ancestors(b"/a/b/c/file.txt");
+        assert_eq!(
+            res,
+            vec![
+                b"/".to_vec(),
+                b"/a".to_vec(),
+                b"/a/b".to_vec(),
+                b"/a/b/c".to_vec()
+            ]
+        );
+    }
+
+    #[test]
+    fn ancestors_single_level_path() {
+        let res = get_folder_ancestors(b"/a/file.txt");
+        assert_eq!(res, vec![b"/".to_vec(), b"/a".to_vec()]);
+    }
+
+    #[test]
+    fn ancestors_drive_root_file() {
+        let res = get_folder_ancestors(b"C:\\foo.txt");
+        assert_eq!(res, vec![b"C:\\".to_vec()]);
+    }
+
+    #[test]
+    fn normalize_folder_bytes_basic() {
+        assert_eq!(normalize_folder_bytes(b"/a/b"), b"/a/b".to_vec());
+        assert_eq!(normalize_folder_bytes(b"/a/b/"), b"/a/b".to_vec());
+        assert_eq!(normalize_folder_bytes(b"a/b"), b"a/b".to_vec());
+        assert_eq!(normalize_folder_bytes(b"/"), b"/".to_vec());
+        assert_eq!(normalize_folder_bytes(b""), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn normalize_folder_bytes_windows_native() {
+        assert_eq!(
+            normalize_folder_bytes(b"C:\\Users\\Default"),
+            b"C:\\Users\\Default".to_vec()
+        );
+        assert_eq!(
+            normalize_folder_bytes(b"C:\\Users\\Default\\"),
+            b"C:\\Users\\Default".to_vec()
+        );
+        // Drive root keeps its trailing backslash.
+        assert_eq!(normalize_folder_bytes(b"C:\\"), b"C:\\".to_vec());
+    }
+
     #[test]
     fn test_path_processing_integration() {
         let test_paths = ["/usr/local/bin", "/home/user/documents", "/var/log/system.log"];
 
         for path_str in &test_paths {
             let path = Path::new(path_str);
-            let stripped = strip_verbatim_prefix(&path);
+            let stripped = strip_verbatim_prefix(path);
 
-            assert!(!should_skip(&stripped, Some("nonexistent")));
+            assert!(!should_skip(&stripped, &PathFilter::new(Some("nonexistent"), &[]).unwrap()));
 
             if path_str.contains("user") {
-                assert!(should_skip(&stripped, Some("user")));
+                assert!(should_skip(&stripped, &PathFilter::new(Some("user"), &[]).unwrap()));
             }
         }
     }
@@ -73,6 +73,96 @@ pub fn get_rid(path: &Path) -> std::io::Result<u32> {
     Ok(rid)
 }
 
+/// Account name for an owner RID from `get_rid`, via `LookupAccountSidW`.
+/// The RID is looked up in the domain of the account running this process
+/// (or the machine's, for a local account), then among the BUILTIN groups
+/// (S-1-5-32-RID, e.g. 544 Administrators). `None` if neither knows it.
+#[cfg(windows)]
+pub fn account_name_from_rid(rid: u32) -> Option<String> {
+    use std::sync::OnceLock;
+    static DOMAIN_SID: OnceLock<Option<Vec<u8>>> = OnceLock::new();
+
+    if let Some(domain) = DOMAIN_SID.get_or_init(process_domain_sid) {
+        let mut sid = domain.clone();
+        let last = sid.len() - 4;
+        sid[last..].copy_from_slice(&rid.to_ne_bytes());
+        if let Some(name) = lookup_account_sid(&mut sid) {
+            return Some(name);
+        }
+    }
+    // Revision 1, two subauthorities, authority 5 (NT), 32 (BUILTIN), RID.
+    let mut builtin = vec![1u8, 2, 0, 0, 0, 0, 0, 5];
+    builtin.extend_from_slice(&32u32.to_ne_bytes());
+    builtin.extend_from_slice(&rid.to_ne_bytes());
+    lookup_account_sid(&mut builtin)
+}
+
+/// The SID of the user running this process when it is a domain or local
+/// account (S-1-5-21-...), whose last subauthority is the one to replace.
+#[cfg(windows)]
+fn process_domain_sid() -> Option<Vec<u8>> {
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE};
+    use windows_sys::Win32::Security::{
+        GetLengthSid, GetTokenInformation, TOKEN_QUERY, TOKEN_USER, TokenUser,
+    };
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    let mut token: HANDLE = std::ptr::null_mut();
+    if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) } == 0 {
+        return None;
+    }
+    // TOKEN_USER plus the SID it points into; u64s keep it aligned.
+    let mut buf = [0u64; 64];
+    let mut len = 0u32;
+    let ok = unsafe {
+        GetTokenInformation(
+            token,
+            TokenUser,
+            buf.as_mut_ptr().cast(),
+            std::mem::size_of_val(&buf) as u32,
+            &mut len,
+        )
+    };
+    unsafe { CloseHandle(token) };
+    if ok == 0 {
+        return None;
+    }
+    let user = unsafe { &*(buf.as_ptr() as *const TOKEN_USER) };
+    let sid = user.User.Sid;
+    let n = unsafe { GetLengthSid(sid) } as usize;
+    let bytes = unsafe { std::slice::from_raw_parts(sid as *const u8, n) }.to_vec();
+    let domain = bytes.len() >= 12
+        && bytes[1] >= 2
+        && bytes[2..8] == [0, 0, 0, 0, 0, 5]
+        && bytes[8..12] == 21u32.to_ne_bytes();
+    domain.then_some(bytes)
+}
+
+#[cfg(windows)]
+fn lookup_account_sid(sid: &mut [u8]) -> Option<String> {
+    use windows_sys::Win32::Security::{LookupAccountSidW, SID_NAME_USE};
+
+    let mut name = [0u16; 256];
+    let mut domain = [0u16; 256];
+    let (mut name_len, mut domain_len) = (name.len() as u32, domain.len() as u32);
+    let mut kind: SID_NAME_USE = 0;
+    let ok = unsafe {
+        LookupAccountSidW(
+            std::ptr::null(),
+            sid.as_mut_ptr().cast(),
+            name.as_mut_ptr(),
+            &mut name_len,
+            domain.as_mut_ptr(),
+            &mut domain_len,
+            &mut kind,
+        )
+    };
+    if ok == 0 || name_len == 0 {
+        return None;
+    }
+    Some(String::from_utf16_lossy(&name[..name_len as usize]))
+}
+
 pub fn fs_used_bytes(path: &Path) -> Option<u64> {
     #[cfg(unix)]
     {
@@ -130,6 +220,175 @@ pub fn fs_used_bytes(path: &Path) -> Option<u64> {
     None
 }
 
+/// Bytes an unprivileged process can still write on the filesystem holding
+/// `path` (`f_bavail`; the caller's quota on Windows). `None` if it cannot
+/// be read.
+pub fn fs_free_bytes(path: &Path) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let p = CString::new(path.as_os_str().as_bytes()).ok()?;
+        let mut s: libc::statvfs = unsafe { std::mem::zeroed() };
+        let rc = unsafe { libc::statvfs(p.as_ptr(), &mut s) };
+        if rc != 0 {
+            return None;
+        }
+        let bsize = if s.f_frsize != 0 { s.f_frsize } else { s.f_bsize } as u64;
+        return Some((s.f_bavail as u64).saturating_mul(bsize));
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+        let wide: Vec<u16> = path
+            .as_os_str()
+            .encode_wide()
+            .chain(std::iter::once(0))
+            .collect();
+        let mut free_avail: u64 = 0;
+        let ok = unsafe {
+            GetDiskFreeSpaceExW(
+                wide.as_ptr(),
+                &mut free_avail as *mut u64,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return None;
+        }
+        return Some(free_avail);
+    }
+
+    #[allow(unreachable_code)]
+    None
+}
+
+/// Lowercase filesystem type of the filesystem holding `path`: `ext4`,
+/// `xfs`, `nfs`, `proc`, `overlay` on Linux (ext2/3 report `ext4`, nfs4
+/// `nfs`; unknown magics as `0x<hex>`), the mount's type name on macOS, the
+/// volume's (`ntfs`, `refs`, `exfat`) on Windows. `None` if it cannot be read.
+pub fn fs_type(path: &Path) -> Option<String> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let p = CString::new(path.as_os_str().as_bytes()).ok()?;
+        let mut s: libc::statfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statfs(p.as_ptr(), &mut s) } != 0 {
+            return None;
+        }
+        let magic = s.f_type as u32 as u64;
+        return Some(match linux_fs_name(magic) {
+            Some(name) => name.to_string(),
+            None => format!("0x{magic:x}"),
+        });
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        use std::ffi::{CStr, CString};
+        use std::os::unix::ffi::OsStrExt;
+
+        let p = CString::new(path.as_os_str().as_bytes()).ok()?;
+        let mut s: libc::statfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statfs(p.as_ptr(), &mut s) } != 0 {
+            return None;
+        }
+        let name = unsafe { CStr::from_ptr(s.f_fstypename.as_ptr()) };
+        return Some(name.to_string_lossy().to_ascii_lowercase());
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        use windows_sys::Win32::Storage::FileSystem::{GetVolumeInformationW, GetVolumePathNameW};
+
+        let wide: Vec<u16> = path
+            .as_os_str()
+            .encode_wide()
+            .chain(std::iter::once(0))
+            .collect();
+        let mut root = [0u16; 260];
+        if unsafe { GetVolumePathNameW(wide.as_ptr(), root.as_mut_ptr(), root.len() as u32) } == 0 {
+            return None;
+        }
+        let mut name = [0u16; 64];
+        let ok = unsafe {
+            GetVolumeInformationW(
+                root.as_ptr(),
+                std::ptr::null_mut(),
+                0,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                name.as_mut_ptr(),
+                name.len() as u32,
+            )
+        };
+        if ok == 0 {
+            return None;
+        }
+        let nul = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        return Some(String::from_utf16_lossy(&name[..nul]).to_ascii_lowercase());
+    }
+
+    #[allow(unreachable_code)]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// `statfs.f_type` magic numbers from linux/magic.h and the usual
+/// out-of-tree filesystems.
+#[cfg(target_os = "linux")]
+fn linux_fs_name(magic: u64) -> Option<&'static str> {
+    Some(match magic {
+        0xef53 => "ext4",
+        0x58465342 => "xfs",
+        0x9123683e => "btrfs",
+        0x2fc12fc1 => "zfs",
+        0x01021994 => "tmpfs",
+        0x858458f6 => "ramfs",
+        0x794c7630 => "overlay",
+        0x6969 => "nfs",
+        0xff534d42 => "cifs",
+        0xfe534d42 => "smb2",
+        0x65735546 => "fuse",
+        0x0bd00bd0 => "lustre",
+        0x47504653 => "gpfs",
+        0x00c36400 => "ceph",
+        0x73717368 => "squashfs",
+        0x9660 => "iso9660",
+        0x4d44 => "vfat",
+        0x5346544e => "ntfs",
+        0x0187 => "autofs",
+        0x9fa0 => "proc",
+        0x62656572 => "sysfs",
+        0x1cd1 => "devpts",
+        0x27e0eb => "cgroup",
+        0x63677270 => "cgroup2",
+        0x64626720 => "debugfs",
+        0x74726163 => "tracefs",
+        0x73636673 => "securityfs",
+        0x6165676c => "pstore",
+        0xcafe4a11 => "bpf",
+        0x19800202 => "mqueue",
+        0x958458f6 => "hugetlbfs",
+        0x6e736673 => "nsfs",
+        0x62656570 => "configfs",
+        0xde5e81e4 => "efivarfs",
+        0x42494e4d => "binfmt_misc",
+        _ => return None,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -142,6 +401,17 @@ mod tests {
 
         let result = fs_used_bytes(Path::new("/non/existent/path"));
         assert!(result.is_none());
+
+        assert!(fs_free_bytes(Path::new("/")).is_some());
+        assert!(fs_free_bytes(Path::new("/non/existent/path")).is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_fs_type_linux() {
+        assert_eq!(fs_type(Path::new("/proc/self")).as_deref(), Some("proc"));
+        assert!(fs_type(Path::new("/")).is_some());
+        assert!(fs_type(Path::new("/non/existent/path")).is_none());
     }
 
     #[cfg(windows)]
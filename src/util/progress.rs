@@ -0,0 +1,501 @@
+// rs/src/util/progress.rs
+//
+// Progress reporting shared by the batch tools. The work loop bumps a
+// `Counter`; a `Reporter` thread samples it once a second and hands the
+// sample to its sinks: a terminal progress bar (the default), JSON lines
+// on stderr (`--progress-json`, for wrappers and log collectors) and an
+// HTTP POST (`--progress-url`, for dashboards watching nightly runs).
+//
+// Besides the cumulative rate, each sample carries a smoothed one (an
+// exponentially weighted moving average with a `RATE_WINDOW` time constant)
+// and, once the total is known, the ETA it gives. A tool with a work queue
+// can hand its depth to the counter (`Counter::watch_queue`): a queue that
+// keeps growing while the rate holds means the workers are the bottleneck
+// (IO-bound), one that stays near empty means the walk is.
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde::Serialize;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering::Relaxed};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::format::{format_duration, human_bytes, human_count, progress_bar};
+
+const TICK: Duration = Duration::from_secs(1);
+/// Minimum time between two POSTs of the HTTP sink.
+const HTTP_INTERVAL: Duration = Duration::from_secs(10);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time constant of the smoothed rate.
+const RATE_WINDOW: f64 = 15.0;
+
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct ProgressArgs {
+    /// Report progress as JSON lines on stderr instead of a progress bar
+    #[arg(long)]
+    pub progress_json: bool,
+    /// Also POST progress JSON to URL every 10 seconds and when done
+    #[arg(long, value_name = "URL", env = "DUTOPIA_PROGRESS_URL")]
+    pub progress_url: Option<String>,
+}
+
+impl ProgressArgs {
+    /// Sinks for these flags; `quiet` drops the terminal/JSON one, not the URL.
+    pub fn sinks(&self, quiet: bool) -> Result<Vec<Box<dyn Sink>>> {
+        let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+        if self.progress_json {
+            sinks.push(Box::new(JsonLines(std::io::stderr())));
+        } else if !quiet {
+            sinks.push(Box::new(Terminal::default()));
+        }
+        if let Some(url) = &self.progress_url {
+            sinks.push(Box::new(Http::new(url)?));
+        }
+        Ok(sinks)
+    }
+}
+
+/// Work done so far, and the total once known.
+#[derive(Debug, Default)]
+pub struct Counter {
+    done: AtomicU64,
+    total: OnceLock<u64>,
+    queue: OnceLock<Arc<AtomicUsize>>,
+}
+
+impl Counter {
+    pub fn with_total(total: u64) -> Self {
+        let c = Self::default();
+        c.set_total(total);
+        c
+    }
+
+    #[inline]
+    pub fn add(&self, n: u64) {
+        self.done.fetch_add(n, Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.done.load(Relaxed)
+    }
+
+    /// First call wins; later totals are ignored.
+    pub fn set_total(&self, total: u64) {
+        let _ = self.total.set(total);
+    }
+
+    pub fn total(&self) -> Option<u64> {
+        self.total.get().copied()
+    }
+
+    /// Report `queue` (pending work items) with every sample. First call
+    /// wins.
+    pub fn watch_queue(&self, queue: Arc<AtomicUsize>) {
+        let _ = self.queue.set(queue);
+    }
+
+    pub fn queue(&self) -> Option<u64> {
+        self.queue.get().map(|q| q.load(Relaxed) as u64)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    Files,
+    Rows,
+    Bytes,
+}
+
+impl Unit {
+    fn format(self, n: u64) -> String {
+        match self {
+            Unit::Bytes => human_bytes(n),
+            _ => format!("{} {}", human_count(n), self.name()),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Unit::Files => "files",
+            Unit::Rows => "rows",
+            Unit::Bytes => "bytes",
+        }
+    }
+}
+
+/// One reading of a `Counter`; the JSON body of the JSON and HTTP sinks.
+#[derive(Debug, Clone, Serialize)]
+pub struct Sample {
+    pub tool: &'static str,
+    pub unit: Unit,
+    pub done: u64,
+    pub total: Option<u64>,
+    pub percent: Option<f64>,
+    pub elapsed_secs: f64,
+    /// Average since the start
+    pub per_sec: f64,
+    /// Smoothed recent rate (EWMA)
+    pub rate: f64,
+    /// Seconds left at `rate`; None without a total, once past it, or when
+    /// finished
+    pub eta_secs: Option<f64>,
+    /// Pending work items, for tools that report a queue
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue: Option<u64>,
+    pub finished: bool,
+}
+
+/// Turns counter readings into samples, keeping the smoothed rate.
+struct Sampler {
+    tool: &'static str,
+    unit: Unit,
+    start: Instant,
+    last: (Instant, u64),
+    rate: Option<f64>,
+}
+
+impl Sampler {
+    fn new(tool: &'static str, unit: Unit, start: Instant) -> Self {
+        Self {
+            tool,
+            unit,
+            start,
+            last: (start, 0),
+            rate: None,
+        }
+    }
+
+    fn take(&mut self, counter: &Counter, now: Instant, finished: bool) -> Sample {
+        let done = counter.get();
+        let total = counter.total();
+        let elapsed = now.duration_since(self.start).as_secs_f64().max(0.001);
+        let per_sec = done as f64 / elapsed;
+
+        let dt = now.duration_since(self.last.0).as_secs_f64();
+        if dt > 0.0 {
+            let recent = done.saturating_sub(self.last.1) as f64 / dt;
+            let alpha = 1.0 - (-dt / RATE_WINDOW).exp();
+            self.rate = Some(match self.rate {
+                Some(r) => r + alpha * (recent - r),
+                None => recent,
+            });
+            self.last = (now, done);
+        }
+        let rate = self.rate.unwrap_or(per_sec);
+        let eta_secs = total
+            .filter(|&t| t > done && rate > 0.0 && !finished)
+            .map(|t| (t - done) as f64 / rate);
+        Sample {
+            tool: self.tool,
+            unit: self.unit,
+            done,
+            total,
+            percent: total
+                .filter(|&t| t > 0)
+                .map(|t| (done as f64 * 100.0 / t as f64).min(100.0)),
+            elapsed_secs: elapsed,
+            per_sec,
+            rate,
+            eta_secs,
+            queue: counter.queue(),
+            finished,
+        }
+    }
+}
+
+pub trait Sink: Send {
+    fn update(&mut self, s: &Sample);
+    /// Last sample, once the work is done.
+    fn finish(&mut self, s: &Sample) {
+        self.update(s);
+    }
+}
+
+/// `\r`-redrawn line on stderr; a bar once the total is known.
+#[derive(Default)]
+pub struct Terminal {
+    last_pct: f64,
+}
+
+impl Sink for Terminal {
+    fn update(&mut self, s: &Sample) {
+        let mut rate = format!("{}/s", s.unit.format(s.rate as u64));
+        if let Some(eta) = s.eta_secs {
+            rate.push_str(&format!(", ETA {}", format_duration(Duration::from_secs_f64(eta))));
+        }
+        if let Some(q) = s.queue {
+            rate.push_str(&format!(", queue {}", human_count(q)));
+        }
+        match s.percent {
+            Some(pct) => {
+                // Never move backwards when the total was an underestimate.
+                let pct = pct.max(self.last_pct);
+                self.last_pct = pct;
+                eprint!(
+                    "\r    {} {} {:>3}% | {} [{}]        \r",
+                    "Progress".bright_cyan(),
+                    progress_bar(pct, 25),
+                    pct as u32,
+                    s.unit.format(s.done),
+                    rate
+                );
+            }
+            None => eprint!(
+                "\r    {} : {} [{}]        \r",
+                "Progress".bright_cyan(),
+                s.unit.format(s.done),
+                rate
+            ),
+        }
+    }
+
+    fn finish(&mut self, _s: &Sample) {
+        eprint!("\r{}\r", " ".repeat(120));
+    }
+}
+
+pub struct JsonLines<W: Write + Send>(pub W);
+
+impl<W: Write + Send> Sink for JsonLines<W> {
+    fn update(&mut self, s: &Sample) {
+        if let Ok(line) = serde_json::to_string(s) {
+            let _ = writeln!(self.0, "{line}");
+        }
+    }
+}
+
+/// POSTs samples to a URL, at most every `HTTP_INTERVAL`. A failing
+/// endpoint is logged once and never slows the work down.
+pub struct Http {
+    url: url::Url,
+    rt: tokio::runtime::Runtime,
+    client: reqwest::Client,
+    last: Option<Instant>,
+    warned: bool,
+}
+
+impl Http {
+    pub fn new(url: &str) -> Result<Self> {
+        let url = url::Url::parse(url).with_context(|| format!("invalid progress URL {url:?}"))?;
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let client = reqwest::Client::builder().timeout(HTTP_TIMEOUT).build()?;
+        Ok(Self {
+            url,
+            rt,
+            client,
+            last: None,
+            warned: false,
+        })
+    }
+
+    fn post(&mut self, s: &Sample) {
+        self.last = Some(Instant::now());
+        let req = self.client.post(self.url.clone()).json(s).send();
+        let res = self
+            .rt
+            .block_on(req)
+            .and_then(reqwest::Response::error_for_status);
+        if let Err(e) = res {
+            if !self.warned {
+                tracing::warn!(url = %self.url, error = %e, "progress POST failed");
+                self.warned = true;
+            } else {
+                tracing::debug!(url = %self.url, error = %e, "progress POST failed");
+            }
+        }
+    }
+}
+
+impl Sink for Http {
+    fn update(&mut self, s: &Sample) {
+        if self.last.is_none_or(|t| t.elapsed() >= HTTP_INTERVAL) {
+            self.post(s);
+        }
+    }
+
+    fn finish(&mut self, s: &Sample) {
+        self.post(s);
+    }
+}
+
+/// Counts the bytes read through it, for tools whose natural total is the
+/// input file size.
+pub struct CountingReader<R> {
+    inner: R,
+    counter: Arc<Counter>,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub fn new(inner: R, counter: Arc<Counter>) -> Self {
+        Self { inner, counter }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.counter.add(n as u64);
+        Ok(n)
+    }
+}
+
+/// Samples a `Counter` on a background thread until `finish`.
+pub struct Reporter {
+    stop: Arc<AtomicBool>,
+    join: Option<JoinHandle<()>>,
+}
+
+impl Reporter {
+    /// No thread is started when `sinks` is empty.
+    pub fn start(
+        tool: &'static str,
+        unit: Unit,
+        counter: Arc<Counter>,
+        mut sinks: Vec<Box<dyn Sink>>,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        if sinks.is_empty() {
+            return Self { stop, join: None };
+        }
+        let stopped = stop.clone();
+        let join = thread::spawn(move || {
+            let start = Instant::now();
+            let mut sampler = Sampler::new(tool, unit, start);
+            let mut next = start + TICK;
+            while !stopped.load(Relaxed) {
+                let now = Instant::now();
+                if now >= next {
+                    let s = sampler.take(&counter, now, false);
+                    sinks.iter_mut().for_each(|k| k.update(&s));
+                    next += TICK;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+            let s = sampler.take(&counter, Instant::now(), true);
+            sinks.iter_mut().for_each(|k| k.finish(&s));
+        });
+        Self {
+            stop,
+            join: Some(join),
+        }
+    }
+
+    /// Send the final sample and wait for the sinks.
+    pub fn finish(mut self) {
+        self.stop_and_join();
+    }
+
+    fn stop_and_join(&mut self) {
+        self.stop.store(true, Relaxed);
+        if let Some(j) = self.join.take() {
+            let _ = j.join();
+        }
+    }
+}
+
+impl Drop for Reporter {
+    fn drop(&mut self) {
+        self.stop_and_join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct Collect(Arc<Mutex<Vec<Sample>>>);
+
+    impl Sink for Collect {
+        fn update(&mut self, s: &Sample) {
+            self.0.lock().unwrap().push(s.clone());
+        }
+    }
+
+    #[test]
+    fn reporter_sends_a_final_sample_with_percent() {
+        let counter = Arc::new(Counter::default());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let r = Reporter::start(
+            "test",
+            Unit::Rows,
+            counter.clone(),
+            vec![Box::new(Collect(seen.clone()))],
+        );
+        counter.add(3);
+        counter.set_total(4);
+        counter.set_total(10);
+        r.finish();
+
+        let seen = seen.lock().unwrap();
+        let last = seen.last().unwrap();
+        assert!(last.finished);
+        assert_eq!((last.done, last.total, last.percent), (3, Some(4), Some(75.0)));
+
+        let mut out = Vec::new();
+        JsonLines(&mut out).update(last);
+        let v: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(v["unit"], "rows");
+        assert_eq!(v["tool"], "test");
+        assert!(out.ends_with(b"\n"));
+    }
+
+    #[test]
+    fn sampler_smooths_the_rate_and_estimates_time_left() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let counter = Counter::with_total(1000);
+        let mut sampler = Sampler::new("test", Unit::Files, start);
+
+        counter.add(100);
+        let s = sampler.take(&counter, at(1), false);
+        assert_eq!((s.rate, s.eta_secs, s.queue), (100.0, Some(9.0), None));
+        // A stall pulls the smoothed rate down gradually, not to zero.
+        let s = sampler.take(&counter, at(2), false);
+        assert!(s.rate > 90.0 && s.rate < 100.0, "{}", s.rate);
+        assert!(s.eta_secs.unwrap() > 9.0);
+        assert_eq!(s.per_sec, 50.0);
+
+        let queue = Arc::new(AtomicUsize::new(7));
+        counter.watch_queue(queue.clone());
+        counter.add(900);
+        let s = sampler.take(&counter, at(3), false);
+        assert_eq!((s.eta_secs, s.queue), (None, Some(7)));
+        let s = sampler.take(&counter, at(4), true);
+        assert_eq!(s.eta_secs, None);
+        let v = serde_json::to_value(&s).unwrap();
+        assert_eq!(v["queue"], 7);
+        assert!(v["rate"].is_number());
+    }
+
+    #[test]
+    fn sinks_follow_the_flags() {
+        let args = ProgressArgs::default();
+        assert_eq!(args.sinks(false).unwrap().len(), 1);
+        assert!(args.sinks(true).unwrap().is_empty());
+        let args = ProgressArgs {
+            progress_json: true,
+            progress_url: Some("http://127.0.0.1:9/progress".into()),
+        };
+        assert_eq!(args.sinks(true).unwrap().len(), 2);
+        let bad = ProgressArgs {
+            progress_url: Some("not a url".into()),
+            ..Default::default()
+        };
+        assert!(bad.sinks(false).is_err());
+        assert_eq!(Unit::Bytes.format(2048), human_bytes(2048));
+    }
+
+    #[test]
+    fn counting_reader_counts_bytes() {
+        let counter = Arc::new(Counter::default());
+        let mut r = CountingReader::new(&b"hello world"[..], counter.clone());
+        let mut out = String::new();
+        r.read_to_string(&mut out).unwrap();
+        assert_eq!(counter.get(), 11);
+    }
+}
@@ -1,5 +1,7 @@
 // rs/src/util/row.rs
 
+/// `repr(C)`: passed as-is to `--enrich lib:` plugins (see `enrich`).
+#[repr(C)]
 pub struct Row {
     pub dev: u64,
     pub ino: u64,